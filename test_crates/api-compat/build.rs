@@ -0,0 +1,45 @@
+use std::{fs, path::Path};
+
+/// 清单中以 `prefix` 开头的第一行里 `version` 的值
+fn version_in(manifest: &Path, prefix: &str) -> String {
+    println!("cargo::rerun-if-changed={}", manifest.display());
+    let text = fs::read_to_string(manifest).expect("read manifest");
+    text.lines()
+        .filter(|line| line.starts_with(prefix))
+        .find_map(|line| {
+            let (_, rest) = line.split_once("version")?;
+            let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+            Some(rest.strip_prefix('"')?.split('"').next()?.to_string())
+        })
+        .unwrap_or_else(|| panic!("no version for `{prefix}` in {}", manifest.display()))
+}
+
+/// semver 的主版本号：0.x 以次版本号为主版本
+fn major(version: &str) -> (u64, u64) {
+    let mut parts = version
+        .split('.')
+        .map(|part| part.parse::<u64>().expect("numeric version"));
+    let major = parts.next().unwrap();
+    let minor = parts.next().unwrap_or(0);
+    if major == 0 { (0, minor) } else { (major, 0) }
+}
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(check_current)");
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let baseline = version_in(&dir.join("Cargo.toml"), "usb-if-released");
+    let current = version_in(&dir.join("../../usb-if/Cargo.toml"), "version");
+
+    // 版本号声明兼容时，当前源码必须能编译基线；已升级主版本号的破坏性变更不再检查，
+    // 发布后以新版本重建基线
+    let baseline = baseline.trim_start_matches('=');
+    if major(baseline) == major(&current) {
+        println!("cargo::rustc-cfg=check_current");
+    } else {
+        println!(
+            "cargo::warning=usb-if {current} is a new major version of baseline {baseline}, \
+             rebuild the baseline once it is released"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+// 按 usb-if 0.7.0 的公开 API 编写的下游代码，由 lib.rs 分别针对当前源码与发布版本展开。
+// 只允许使用 0.7.0 中存在的条目，枚举匹配一律穷尽。
+
+use alloc::{vec, vec::Vec};
+
+use usb_if::{
+    Speed,
+    descriptor::{ConfigurationDescriptor, DescriptorType, DeviceDescriptor, EndpointType},
+    endpoint::{
+        EndpointAddress, EndpointInfo, IsoPacketResult, RequestId, TransferCompletion,
+        TransferKind, TransferRequest, TransferStatus,
+    },
+    err::{TransferError, USBError},
+    host::ControlSetup,
+    transfer::{BmRequestType, Direction, Recipient, Request, RequestType},
+};
+
+/// 类请求 IN 到接口的 bmRequestType
+pub fn bm_request_type_in_class_interface() -> u8 {
+    BmRequestType::new(Direction::In, RequestType::Class, Recipient::Interface).into()
+}
+
+/// 请求码经 [`Request`] 往返转换
+pub fn request_code(code: u8) -> u8 {
+    Request::from(code).into()
+}
+
+/// 标准请求的名字
+pub fn standard_request_name(code: u8) -> Option<&'static str> {
+    match Request::from(code) {
+        Request::GetStatus => Some("GET_STATUS"),
+        Request::ClearFeature => Some("CLEAR_FEATURE"),
+        Request::SetFeature => Some("SET_FEATURE"),
+        Request::SetAddress => Some("SET_ADDRESS"),
+        Request::GetDescriptor => Some("GET_DESCRIPTOR"),
+        Request::SetDescriptor => Some("SET_DESCRIPTOR"),
+        Request::GetConfiguration => Some("GET_CONFIGURATION"),
+        Request::SetConfiguration => Some("SET_CONFIGURATION"),
+        Request::GetInterface => Some("GET_INTERFACE"),
+        Request::SetInterface => Some("SET_INTERFACE"),
+        Request::SynchFrame => Some("SYNCH_FRAME"),
+        Request::SetEncryption
+        | Request::GetEncryption
+        | Request::SetHandshake
+        | Request::GetHandshake
+        | Request::SetConnection
+        | Request::SetSecurityData
+        | Request::GetSecurityData
+        | Request::SetWusbData
+        | Request::LoopbackDataWrite
+        | Request::LoopbackDataRead
+        | Request::SetInterfaceDs
+        | Request::GetFwStatus
+        | Request::SetFwStatus
+        | Request::SetSel
+        | Request::SetIsochDelay => Some("OTHER_STANDARD"),
+        Request::Other(_) => None,
+    }
+}
+
+/// 读取配置描述符的控制请求
+pub fn get_configuration_request(buffer: &mut [u8]) -> TransferRequest {
+    let setup = ControlSetup {
+        request_type: RequestType::Standard,
+        recipient: Recipient::Device,
+        request: Request::GetDescriptor,
+        value: (DescriptorType::CONFIGURATION.0 as u16) << 8,
+        index: 0,
+    };
+    TransferRequest::control_in(setup, buffer)
+}
+
+/// 配置中所有批量 IN 端点的地址
+pub fn bulk_in_endpoints(config: &[u8]) -> Vec<u8> {
+    let Some(config) = ConfigurationDescriptor::parse(config) else {
+        return Vec::new();
+    };
+    config
+        .interfaces
+        .iter()
+        .flat_map(|iface| iface.alt_settings.iter())
+        .flat_map(|alt| alt.endpoints.iter())
+        .filter(|ep| {
+            matches!(ep.transfer_type, EndpointType::Bulk) && matches!(ep.direction, Direction::In)
+        })
+        .map(|ep| ep.address)
+        .collect()
+}
+
+/// 设备描述符中的 VID/PID
+pub fn device_ids(data: &[u8]) -> Option<(u16, u16)> {
+    DeviceDescriptor::parse(data).map(|desc| (desc.vendor_id, desc.product_id))
+}
+
+/// 端点地址是否为 IN 方向
+pub fn endpoint_in(address: u8) -> bool {
+    matches!(EndpointAddress::new(address).direction(), Direction::In)
+}
+
+/// 由端点描述符字段构造端点信息
+pub fn endpoint_info(
+    address: u8,
+    transfer_type: EndpointType,
+    max_packet_size: u16,
+) -> EndpointInfo {
+    let address = EndpointAddress::from(address);
+    EndpointInfo {
+        address,
+        transfer_type,
+        direction: address.direction(),
+        max_packet_size,
+        packets_per_microframe: 1,
+        interval: 1,
+    }
+}
+
+/// 传输请求的类型与方向
+pub fn describe_request(request: &TransferRequest) -> (&'static str, bool) {
+    let kind = match request {
+        TransferRequest::Control { .. } => "control",
+        TransferRequest::Bulk { .. } => "bulk",
+        TransferRequest::Interrupt { .. } => "interrupt",
+        TransferRequest::Isochronous { .. } => "isochronous",
+    };
+    (kind, matches!(request.direction(), Direction::In))
+}
+
+/// 各类传输请求的构造方式
+pub fn build_requests(buffer: &mut [u8]) -> Vec<(&'static str, bool)> {
+    let iso = TransferRequest::iso_in(buffer, &[1, 1]);
+    debug_assert_eq!(iso.iso_packets().len(), 2);
+    vec![
+        describe_request(&get_configuration_request(buffer)),
+        describe_request(&TransferRequest::bulk_in(buffer)),
+        describe_request(&TransferRequest::bulk_out(buffer)),
+        describe_request(&TransferRequest::interrupt_in(buffer)),
+        describe_request(&iso),
+    ]
+}
+
+/// 传输类型的名字
+pub fn transfer_kind_name(kind: &TransferKind) -> &'static str {
+    match kind {
+        TransferKind::Control(_) => "control",
+        TransferKind::Bulk => "bulk",
+        TransferKind::Interrupt => "interrupt",
+        TransferKind::Isochronous { .. } => "isochronous",
+    }
+}
+
+/// 等时传输中成功完成的字节数，`packets` 为每个包的（实际长度，是否成功）
+pub fn completed_iso_bytes(packets: &[(usize, bool)]) -> usize {
+    let completion = TransferCompletion {
+        request_id: RequestId::new(1),
+        status: TransferStatus::Completed,
+        actual_length: packets.iter().map(|&(len, _)| len).sum(),
+        iso_packets: packets
+            .iter()
+            .map(|&(len, ok)| IsoPacketResult {
+                requested_length: len,
+                actual_length: len,
+                status: if ok {
+                    TransferStatus::Completed
+                } else {
+                    TransferStatus::Error
+                },
+            })
+            .collect(),
+    };
+    completion
+        .iso_packets
+        .iter()
+        .filter(|packet| {
+            match packet.status {
+                TransferStatus::Completed => true,
+                TransferStatus::Stalled | TransferStatus::Cancelled | TransferStatus::Error => false,
+            }
+        })
+        .map(|packet| packet.actual_length)
+        .sum()
+}
+
+/// 传输错误的分类
+pub fn transfer_error_kind(err: &TransferError) -> &'static str {
+    match err {
+        TransferError::Stall => "stall",
+        TransferError::QueueFull => "busy",
+        TransferError::InvalidEndpoint | TransferError::NotSupported => "usage",
+        TransferError::NoDevice => "gone",
+        TransferError::Timeout | TransferError::Cancelled => "aborted",
+        TransferError::Other(_) => "other",
+    }
+}
+
+/// 主机错误的分类
+pub fn usb_error_kind(err: &USBError) -> &'static str {
+    match err {
+        USBError::TransferError(err) => transfer_error_kind(err),
+        USBError::Timeout => "aborted",
+        USBError::NoMemory | USBError::SlotLimitReached => "resource",
+        USBError::NotInitialized | USBError::ConfigurationNotSet => "state",
+        USBError::NotFound => "gone",
+        USBError::InvalidParameter | USBError::NotSupported => "usage",
+        USBError::Other(_) => "other",
+    }
+}
+
+/// 端点停止应答是否以错误的形式报告
+pub fn is_stall_message(stalled: bool) -> bool {
+    let err: USBError = if stalled {
+        TransferError::Stall.into()
+    } else {
+        USBError::Timeout
+    };
+    usb_error_kind(&err) == "stall"
+}
+
+/// 链路速率的名字
+pub fn speed_name(speed: Speed) -> &'static str {
+    match speed {
+        Speed::Low => "low",
+        Speed::Full => "full",
+        Speed::High => "high",
+        Speed::Wireless => "wireless",
+        Speed::SuperSpeed => "super",
+        Speed::SuperSpeedPlus => "super+",
+    }
+}
+
+/// 端点类型的名字
+pub fn endpoint_type_name(ty: EndpointType) -> &'static str {
+    match ty {
+        EndpointType::Control => "control",
+        EndpointType::Isochronous => "isochronous",
+        EndpointType::Bulk => "bulk",
+        EndpointType::Interrupt => "interrupt",
+    }
+}
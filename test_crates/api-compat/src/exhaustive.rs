@@ -0,0 +1,715 @@
+// 对 usb-if 0.7.0 中每个公开结构体做结构体字面量构造、对每个公开枚举做穷尽匹配。
+// 新增字段或变体都会使按当前源码展开的副本无法编译，由 build.rs 决定是否需要检查。
+// 结构体中含私有字段的（`HubDescriptor`、`SuperSpeedHubDescriptorTail` 与解析器类型）
+// 下游本就无法构造，不在此列。
+
+use alloc::{string::String, vec, vec::Vec};
+use core::{num::NonZero, ptr::NonNull};
+
+use usb_if::{
+    DrMode,
+    descriptor::{ApplicationType, AudioVideoType, Class, ConfigurationDescriptor, DebugProtocol, DescriptorType, DeviceDescriptor, DeviceDescriptorBase, DfxProtocol, DiagnosticType, DvbInterface, EndpointDescriptor, EndpointType, HubSpeed, InterfaceDescriptor, InterfaceDescriptors, LanguageId, MctpType, MctpVersion, MiscellaneousType, RndisType, StepType, TestMeasurementType, TraceProtocol, VisionInterface, WireAdapterInterface, WirelessType},
+    endpoint::{EndpointAddress, EndpointInfo, IsoPacketRequest, IsoPacketResult, RequestId, TransferBuffer, TransferCompletion, TransferKind, TransferRequest, TransferStatus},
+    err::{TransferError, USBError},
+    host::{ControlSetup},
+    host::hub::{HighSpeedHubDescriptorTail, HubCharacteristics, HubDescriptorVariant, HubRequest, MemoryBarrierType, OverCurrentMode, PortFeature, PortStatus, PortStatusChange, PowerSwitchingMode, RegWidth, Speed, TtInfo},
+    transfer::{BmRequestType, Direction, Recipient, Request, RequestType},
+};
+
+/// 所有公开的结构体，按字面量逐字段构造
+pub fn structs() -> (ConfigurationDescriptor, DeviceDescriptor, TransferCompletion) {
+    let base = DeviceDescriptorBase {
+        length: 18,
+        descriptor_type: DescriptorType::DEVICE.0,
+        usb_version: 0x0200,
+        class: 0,
+        subclass: 0,
+        protocol: 0,
+        max_packet_size_0: 64,
+    };
+    let device = DeviceDescriptor {
+        usb_version: base.usb_version,
+        class: base.class,
+        subclass: base.subclass,
+        protocol: base.protocol,
+        max_packet_size_0: base.max_packet_size_0,
+        vendor_id: 0x1234,
+        product_id: 0x5678,
+        device_version: 0x0100,
+        manufacturer_string_index: NonZero::new(1),
+        product_string_index: NonZero::new(2),
+        serial_number_string_index: None,
+        num_configurations: 1,
+    };
+    let endpoint = EndpointDescriptor {
+        address: 0x81,
+        max_packet_size: 512,
+        transfer_type: EndpointType::Bulk,
+        direction: Direction::In,
+        packets_per_microframe: 1,
+        interval: 0,
+    };
+    let interface = InterfaceDescriptor {
+        interface_number: 0,
+        alternate_setting: 0,
+        class: 0xff,
+        subclass: 0,
+        protocol: 0,
+        string_index: None,
+        string: None::<String>,
+        num_endpoints: 1,
+        endpoints: vec![endpoint],
+    };
+    let config = ConfigurationDescriptor {
+        num_interfaces: 1,
+        configuration_value: 1,
+        attributes: 0x80,
+        max_power: 50,
+        string_index: None,
+        string: None,
+        interfaces: vec![InterfaceDescriptors {
+            interface_number: 0,
+            alt_settings: vec![interface],
+        }],
+        raw: Vec::new(),
+    };
+
+    let address = EndpointAddress::new(0x81);
+    let _info = EndpointInfo {
+        address,
+        transfer_type: EndpointType::Bulk,
+        direction: address.direction(),
+        max_packet_size: 512,
+        packets_per_microframe: 1,
+        interval: 0,
+    };
+    let _buffer = TransferBuffer {
+        ptr: NonNull::dangling(),
+        len: 0,
+    };
+    let _packet = IsoPacketRequest { length: 0 };
+    let _setup = ControlSetup {
+        request_type: RequestType::Standard,
+        recipient: Recipient::Device,
+        request: Request::GetStatus,
+        value: 0,
+        index: 0,
+    };
+    let _request_type = BmRequestType {
+        direction: Direction::In,
+        request_type: RequestType::Standard,
+        recipient: Recipient::Device,
+    };
+    let _tail = HubDescriptorVariant {
+        hs: HighSpeedHubDescriptorTail {
+            device_removable: [0; _],
+            port_pwr_ctrl_mask: [0; _],
+        },
+    };
+    let _tt = TtInfo {
+        think_time: 0,
+        multi_tt: false,
+        num_ports: 4,
+    };
+    let _characteristics = HubCharacteristics {
+        power_switching: PowerSwitchingMode::Individual,
+        compound_device: false,
+        over_current_mode: OverCurrentMode::Global,
+        port_indicators: false,
+    };
+    let _status = PortStatus {
+        connected: true,
+        enabled: true,
+        suspended: false,
+        over_current: false,
+        resetting: false,
+        powered: true,
+        low_speed: false,
+        high_speed: true,
+        speed: Speed::High,
+        change: PortStatusChange {
+            connection_changed: true,
+            enabled_changed: false,
+            reset_complete: false,
+            suspend_changed: false,
+            over_current_changed: false,
+        },
+    };
+    let completion = TransferCompletion {
+        request_id: RequestId::new(1),
+        status: TransferStatus::Completed,
+        actual_length: 0,
+        iso_packets: vec![IsoPacketResult {
+            requested_length: 0,
+            actual_length: 0,
+            status: TransferStatus::Completed,
+        }],
+    };
+    (config, device, completion)
+}
+
+/// `DrMode` 变体的序号
+pub fn dr_mode_variant(value: &DrMode) -> usize {
+    match value {
+        DrMode::Host => 0,
+        DrMode::Peripheral => 1,
+        DrMode::Otg => 2,
+    }
+}
+
+/// `Class` 变体的序号
+pub fn class_variant(value: &Class) -> usize {
+    match value {
+        Class::ClassInInterface => 0,
+        Class::Audio => 1,
+        Class::Communication => 2,
+        Class::Hid => 3,
+        Class::Physical => 4,
+        Class::StillImaging => 5,
+        Class::Printer => 6,
+        Class::MassStorage => 7,
+        Class::Hub(..) => 8,
+        Class::CdcData => 9,
+        Class::SmartCard => 10,
+        Class::ContentSecurity => 11,
+        Class::Video => 12,
+        Class::PersonalHealthcare => 13,
+        Class::AudioVideo(..) => 14,
+        Class::Billboard => 15,
+        Class::TypeCBridge => 16,
+        Class::BulkDisplayProtocol => 17,
+        Class::MctpOverUsb(..) => 18,
+        Class::I3c => 19,
+        Class::Diagnostic(..) => 20,
+        Class::Wireless(..) => 21,
+        Class::Miscellaneous(..) => 22,
+        Class::Application(..) => 23,
+        Class::Vendor => 24,
+        Class::Unknown { .. } => 25,
+    }
+}
+
+/// `HubSpeed` 变体的序号
+pub fn hub_speed_variant(value: &HubSpeed) -> usize {
+    match value {
+        HubSpeed::Full => 0,
+        HubSpeed::HiSpeedSignalTT => 1,
+        HubSpeed::HiSpeedMultipleTTs => 2,
+        HubSpeed::Unknown => 3,
+    }
+}
+
+/// `AudioVideoType` 变体的序号
+pub fn audio_video_type_variant(value: &AudioVideoType) -> usize {
+    match value {
+        AudioVideoType::AvControl => 0,
+        AudioVideoType::AvDataVideoStreaming => 1,
+        AudioVideoType::AvDataAudioStreaming => 2,
+    }
+}
+
+/// `MctpType` 变体的序号
+pub fn mctp_type_variant(value: &MctpType) -> usize {
+    match value {
+        MctpType::ManagementControllerEndpoint(..) => 0,
+        MctpType::HostInterfaceEndpoint(..) => 1,
+    }
+}
+
+/// `MctpVersion` 变体的序号
+pub fn mctp_version_variant(value: &MctpVersion) -> usize {
+    match value {
+        MctpVersion::V1x => 0,
+        MctpVersion::V2x => 1,
+    }
+}
+
+/// `DiagnosticType` 变体的序号
+pub fn diagnostic_type_variant(value: &DiagnosticType) -> usize {
+    match value {
+        DiagnosticType::Usb2Compliance => 0,
+        DiagnosticType::DebugTarget(..) => 1,
+        DiagnosticType::Trace(..) => 2,
+        DiagnosticType::Dfx(..) => 3,
+        DiagnosticType::Unknown(..) => 4,
+    }
+}
+
+/// `DebugProtocol` 变体的序号
+pub fn debug_protocol_variant(value: &DebugProtocol) -> usize {
+    match value {
+        DebugProtocol::VendorDefined => 0,
+        DebugProtocol::GnuRemoteDebug => 1,
+    }
+}
+
+/// `TraceProtocol` 变体的序号
+pub fn trace_protocol_variant(value: &TraceProtocol) -> usize {
+    match value {
+        TraceProtocol::VendorDefined => 0,
+    }
+}
+
+/// `DfxProtocol` 变体的序号
+pub fn dfx_protocol_variant(value: &DfxProtocol) -> usize {
+    match value {
+        DfxProtocol::VendorDefined => 0,
+    }
+}
+
+/// `WirelessType` 变体的序号
+pub fn wireless_type_variant(value: &WirelessType) -> usize {
+    match value {
+        WirelessType::BluetoothProgramming => 0,
+        WirelessType::UwbRadioControl => 1,
+        WirelessType::RemoteNdis => 2,
+        WirelessType::BluetoothAmp => 3,
+        WirelessType::HostWireAdapter(..) => 4,
+        WirelessType::DeviceWireAdapter(..) => 5,
+    }
+}
+
+/// `WireAdapterInterface` 变体的序号
+pub fn wire_adapter_interface_variant(value: &WireAdapterInterface) -> usize {
+    match value {
+        WireAdapterInterface::ControlData => 0,
+        WireAdapterInterface::Isochronous => 1,
+    }
+}
+
+/// `MiscellaneousType` 变体的序号
+pub fn miscellaneous_type_variant(value: &MiscellaneousType) -> usize {
+    match value {
+        MiscellaneousType::ActiveSync => 0,
+        MiscellaneousType::PalmSync => 1,
+        MiscellaneousType::InterfaceAssociation => 2,
+        MiscellaneousType::WireAdapterMultifunction => 3,
+        MiscellaneousType::CableBasedAssociation => 4,
+        MiscellaneousType::Rndis(..) => 5,
+        MiscellaneousType::Usb3Vision(..) => 6,
+        MiscellaneousType::Step(..) => 7,
+        MiscellaneousType::DvbCi(..) => 8,
+    }
+}
+
+/// `RndisType` 变体的序号
+pub fn rndis_type_variant(value: &RndisType) -> usize {
+    match value {
+        RndisType::Ethernet => 0,
+        RndisType::Wifi => 1,
+        RndisType::Wimax => 2,
+        RndisType::Wwan => 3,
+        RndisType::RawIpv4 => 4,
+        RndisType::RawIpv6 => 5,
+        RndisType::Gprs => 6,
+    }
+}
+
+/// `VisionInterface` 变体的序号
+pub fn vision_interface_variant(value: &VisionInterface) -> usize {
+    match value {
+        VisionInterface::Control => 0,
+        VisionInterface::Event => 1,
+        VisionInterface::Streaming => 2,
+    }
+}
+
+/// `StepType` 变体的序号
+pub fn step_type_variant(value: &StepType) -> usize {
+    match value {
+        StepType::Step => 0,
+        StepType::StepRaw => 1,
+    }
+}
+
+/// `DvbInterface` 变体的序号
+pub fn dvb_interface_variant(value: &DvbInterface) -> usize {
+    match value {
+        DvbInterface::CommandInIad => 0,
+        DvbInterface::CommandInInterface => 1,
+        DvbInterface::MediaInInterface => 2,
+    }
+}
+
+/// `ApplicationType` 变体的序号
+pub fn application_type_variant(value: &ApplicationType) -> usize {
+    match value {
+        ApplicationType::DeviceFirmwareUpgrade => 0,
+        ApplicationType::IrdaBridge => 1,
+        ApplicationType::TestMeasurement(..) => 2,
+    }
+}
+
+/// `TestMeasurementType` 变体的序号
+pub fn test_measurement_type_variant(value: &TestMeasurementType) -> usize {
+    match value {
+        TestMeasurementType::Standard => 0,
+        TestMeasurementType::Usb488Subclass => 1,
+    }
+}
+
+/// `LanguageId` 变体的序号
+pub fn language_id_variant(value: &LanguageId) -> usize {
+    match value {
+        LanguageId::Afrikaans => 0,
+        LanguageId::Albanian => 1,
+        LanguageId::ArabicSaudiArabia => 2,
+        LanguageId::ArabicIraq => 3,
+        LanguageId::ArabicEgypt => 4,
+        LanguageId::ArabicLibya => 5,
+        LanguageId::ArabicAlgeria => 6,
+        LanguageId::ArabicMorocco => 7,
+        LanguageId::ArabicTunisia => 8,
+        LanguageId::ArabicOman => 9,
+        LanguageId::ArabicYemen => 10,
+        LanguageId::ArabicSyria => 11,
+        LanguageId::ArabicJordan => 12,
+        LanguageId::ArabicLebanon => 13,
+        LanguageId::ArabicKuwait => 14,
+        LanguageId::ArabicUAE => 15,
+        LanguageId::ArabicBahrain => 16,
+        LanguageId::ArabicQatar => 17,
+        LanguageId::Armenian => 18,
+        LanguageId::Assamese => 19,
+        LanguageId::AzeriLatin => 20,
+        LanguageId::AzeriCyrillic => 21,
+        LanguageId::Basque => 22,
+        LanguageId::Belarussian => 23,
+        LanguageId::Bengali => 24,
+        LanguageId::Bulgarian => 25,
+        LanguageId::Burmese => 26,
+        LanguageId::Catalan => 27,
+        LanguageId::ChineseTaiwan => 28,
+        LanguageId::ChinesePRC => 29,
+        LanguageId::ChineseHongKong => 30,
+        LanguageId::ChineseSingapore => 31,
+        LanguageId::ChineseMacau => 32,
+        LanguageId::Croatian => 33,
+        LanguageId::Czech => 34,
+        LanguageId::Danish => 35,
+        LanguageId::DutchNetherlands => 36,
+        LanguageId::DutchBelgium => 37,
+        LanguageId::EnglishUnitedStates => 38,
+        LanguageId::EnglishUnitedKingdom => 39,
+        LanguageId::EnglishAustralian => 40,
+        LanguageId::EnglishCanadian => 41,
+        LanguageId::EnglishNewZealand => 42,
+        LanguageId::EnglishIreland => 43,
+        LanguageId::EnglishSouthAfrica => 44,
+        LanguageId::EnglishJamaica => 45,
+        LanguageId::EnglishCaribbean => 46,
+        LanguageId::EnglishBelize => 47,
+        LanguageId::EnglishTrinidad => 48,
+        LanguageId::EnglishZimbabwe => 49,
+        LanguageId::EnglishPhilippines => 50,
+        LanguageId::Estonian => 51,
+        LanguageId::Faeroese => 52,
+        LanguageId::Farsi => 53,
+        LanguageId::Finnish => 54,
+        LanguageId::FrenchStandard => 55,
+        LanguageId::FrenchBelgian => 56,
+        LanguageId::FrenchCanadian => 57,
+        LanguageId::FrenchSwitzerland => 58,
+        LanguageId::FrenchLuxembourg => 59,
+        LanguageId::FrenchMonaco => 60,
+        LanguageId::Georgian => 61,
+        LanguageId::GermanStandard => 62,
+        LanguageId::GermanSwitzerland => 63,
+        LanguageId::GermanAustria => 64,
+        LanguageId::GermanLuxembourg => 65,
+        LanguageId::GermanLiechtenstein => 66,
+        LanguageId::Greek => 67,
+        LanguageId::Gujarati => 68,
+        LanguageId::Hebrew => 69,
+        LanguageId::Hindi => 70,
+        LanguageId::Hungarian => 71,
+        LanguageId::Icelandic => 72,
+        LanguageId::Indonesian => 73,
+        LanguageId::ItalianStandard => 74,
+        LanguageId::ItalianSwitzerland => 75,
+        LanguageId::Japanese => 76,
+        LanguageId::Kannada => 77,
+        LanguageId::KashmiriIndia => 78,
+        LanguageId::Kazakh => 79,
+        LanguageId::Konkani => 80,
+        LanguageId::Korean => 81,
+        LanguageId::KoreanJohab => 82,
+        LanguageId::Latvian => 83,
+        LanguageId::Lithuanian => 84,
+        LanguageId::LithuanianClassic => 85,
+        LanguageId::Macedonian => 86,
+        LanguageId::MalayMalaysian => 87,
+        LanguageId::MalayBrunei => 88,
+        LanguageId::Malayalam => 89,
+        LanguageId::Manipuri => 90,
+        LanguageId::Marathi => 91,
+        LanguageId::NepaliIndia => 92,
+        LanguageId::NorwegianBokmal => 93,
+        LanguageId::NorwegianNynorsk => 94,
+        LanguageId::Oriya => 95,
+        LanguageId::Polish => 96,
+        LanguageId::PortugueseBrazil => 97,
+        LanguageId::PortugueseStandard => 98,
+        LanguageId::Punjabi => 99,
+        LanguageId::Romanian => 100,
+        LanguageId::Russian => 101,
+        LanguageId::Sanskrit => 102,
+        LanguageId::SerbianCyrillic => 103,
+        LanguageId::SerbianLatin => 104,
+        LanguageId::Sindhi => 105,
+        LanguageId::Slovak => 106,
+        LanguageId::Slovenian => 107,
+        LanguageId::SpanishTraditionalSort => 108,
+        LanguageId::SpanishMexican => 109,
+        LanguageId::SpanishModernSort => 110,
+        LanguageId::SpanishGuatemala => 111,
+        LanguageId::SpanishCostaRica => 112,
+        LanguageId::SpanishPanama => 113,
+        LanguageId::SpanishDominicanRepublic => 114,
+        LanguageId::SpanishVenezuela => 115,
+        LanguageId::SpanishColombia => 116,
+        LanguageId::SpanishPeru => 117,
+        LanguageId::SpanishArgentina => 118,
+        LanguageId::SpanishEcuador => 119,
+        LanguageId::SpanishChile => 120,
+        LanguageId::SpanishUruguay => 121,
+        LanguageId::SpanishParaguay => 122,
+        LanguageId::SpanishBolivia => 123,
+        LanguageId::SpanishElSalvador => 124,
+        LanguageId::SpanishHonduras => 125,
+        LanguageId::SpanishNicaragua => 126,
+        LanguageId::SpanishPuertoRico => 127,
+        LanguageId::Sutu => 128,
+        LanguageId::SwahiliKenya => 129,
+        LanguageId::Swedish => 130,
+        LanguageId::SwedishFinland => 131,
+        LanguageId::Tamil => 132,
+        LanguageId::TatarTatarstan => 133,
+        LanguageId::Telugu => 134,
+        LanguageId::Thai => 135,
+        LanguageId::Turkish => 136,
+        LanguageId::Ukrainian => 137,
+        LanguageId::UrduPakistan => 138,
+        LanguageId::UrduIndia => 139,
+        LanguageId::UzbekLatin => 140,
+        LanguageId::UzbekCyrillic => 141,
+        LanguageId::Vietnamese => 142,
+        LanguageId::HidUsageDataDescriptor => 143,
+        LanguageId::HidVendorDefined1 => 144,
+        LanguageId::HidVendorDefined2 => 145,
+        LanguageId::HidVendorDefined3 => 146,
+        LanguageId::HidVendorDefined4 => 147,
+        LanguageId::Other(..) => 148,
+    }
+}
+
+/// `EndpointType` 变体的序号
+pub fn endpoint_type_variant(value: &EndpointType) -> usize {
+    match value {
+        EndpointType::Control => 0,
+        EndpointType::Isochronous => 1,
+        EndpointType::Bulk => 2,
+        EndpointType::Interrupt => 3,
+    }
+}
+
+/// `TransferKind` 变体的序号
+pub fn transfer_kind_variant(value: &TransferKind) -> usize {
+    match value {
+        TransferKind::Control(..) => 0,
+        TransferKind::Bulk => 1,
+        TransferKind::Interrupt => 2,
+        TransferKind::Isochronous { .. } => 3,
+    }
+}
+
+/// `TransferRequest` 变体的序号
+pub fn transfer_request_variant(value: &TransferRequest) -> usize {
+    match value {
+        TransferRequest::Control { .. } => 0,
+        TransferRequest::Bulk { .. } => 1,
+        TransferRequest::Interrupt { .. } => 2,
+        TransferRequest::Isochronous { .. } => 3,
+    }
+}
+
+/// `TransferStatus` 变体的序号
+pub fn transfer_status_variant(value: &TransferStatus) -> usize {
+    match value {
+        TransferStatus::Completed => 0,
+        TransferStatus::Stalled => 1,
+        TransferStatus::Cancelled => 2,
+        TransferStatus::Error => 3,
+    }
+}
+
+/// `TransferError` 变体的序号
+pub fn transfer_error_variant(value: &TransferError) -> usize {
+    match value {
+        TransferError::Stall => 0,
+        TransferError::QueueFull => 1,
+        TransferError::InvalidEndpoint => 2,
+        TransferError::NoDevice => 3,
+        TransferError::NotSupported => 4,
+        TransferError::Timeout => 5,
+        TransferError::Cancelled => 6,
+        TransferError::Other(..) => 7,
+    }
+}
+
+/// `USBError` 变体的序号
+pub fn u_s_b_error_variant(value: &USBError) -> usize {
+    match value {
+        USBError::Timeout => 0,
+        USBError::NoMemory => 1,
+        USBError::TransferError(..) => 2,
+        USBError::NotInitialized => 3,
+        USBError::NotFound => 4,
+        USBError::InvalidParameter => 5,
+        USBError::SlotLimitReached => 6,
+        USBError::ConfigurationNotSet => 7,
+        USBError::NotSupported => 8,
+        USBError::Other(..) => 9,
+    }
+}
+
+/// `RegWidth` 变体的序号
+pub fn reg_width_variant(value: &RegWidth) -> usize {
+    match value {
+        RegWidth::U8 => 0,
+        RegWidth::U16 => 1,
+        RegWidth::U32 => 2,
+        RegWidth::U64 => 3,
+    }
+}
+
+/// `MemoryBarrierType` 变体的序号
+pub fn memory_barrier_type_variant(value: &MemoryBarrierType) -> usize {
+    match value {
+        MemoryBarrierType::Read => 0,
+        MemoryBarrierType::Write => 1,
+        MemoryBarrierType::Full => 2,
+    }
+}
+
+/// `HubRequest` 变体的序号
+pub fn hub_request_variant(value: &HubRequest) -> usize {
+    match value {
+        HubRequest::GetHubDescriptor => 0,
+        HubRequest::GetHubStatus => 1,
+        HubRequest::SetHubFeature => 2,
+        HubRequest::ClearHubFeature => 3,
+        HubRequest::GetPortStatus => 4,
+        HubRequest::SetPortFeature => 5,
+        HubRequest::ClearPortFeature => 6,
+        HubRequest::GetHubDescriptor16 => 7,
+    }
+}
+
+/// `PortFeature` 变体的序号
+pub fn port_feature_variant(value: &PortFeature) -> usize {
+    match value {
+        PortFeature::Connection => 0,
+        PortFeature::Enable => 1,
+        PortFeature::Suspend => 2,
+        PortFeature::OverCurrent => 3,
+        PortFeature::Reset => 4,
+        PortFeature::Power => 5,
+        PortFeature::LowSpeed => 6,
+        PortFeature::CConnection => 7,
+        PortFeature::CEnable => 8,
+        PortFeature::CSuspend => 9,
+        PortFeature::COverCurrent => 10,
+        PortFeature::CReset => 11,
+    }
+}
+
+/// `PowerSwitchingMode` 变体的序号
+pub fn power_switching_mode_variant(value: &PowerSwitchingMode) -> usize {
+    match value {
+        PowerSwitchingMode::Ganged => 0,
+        PowerSwitchingMode::Individual => 1,
+        PowerSwitchingMode::AlwaysPower => 2,
+    }
+}
+
+/// `OverCurrentMode` 变体的序号
+pub fn over_current_mode_variant(value: &OverCurrentMode) -> usize {
+    match value {
+        OverCurrentMode::Global => 0,
+        OverCurrentMode::Individual => 1,
+    }
+}
+
+/// `Speed` 变体的序号
+pub fn speed_variant(value: &Speed) -> usize {
+    match value {
+        Speed::Low => 0,
+        Speed::Full => 1,
+        Speed::High => 2,
+        Speed::Wireless => 3,
+        Speed::SuperSpeed => 4,
+        Speed::SuperSpeedPlus => 5,
+    }
+}
+
+/// `Direction` 变体的序号
+pub fn direction_variant(value: &Direction) -> usize {
+    match value {
+        Direction::Out => 0,
+        Direction::In => 1,
+    }
+}
+
+/// `RequestType` 变体的序号
+pub fn request_type_variant(value: &RequestType) -> usize {
+    match value {
+        RequestType::Standard => 0,
+        RequestType::Class => 1,
+        RequestType::Vendor => 2,
+        RequestType::Reserved => 3,
+    }
+}
+
+/// `Recipient` 变体的序号
+pub fn recipient_variant(value: &Recipient) -> usize {
+    match value {
+        Recipient::Device => 0,
+        Recipient::Interface => 1,
+        Recipient::Endpoint => 2,
+        Recipient::Other => 3,
+    }
+}
+
+/// `Request` 变体的序号
+pub fn request_variant(value: &Request) -> usize {
+    match value {
+        Request::GetStatus => 0,
+        Request::ClearFeature => 1,
+        Request::SetFeature => 2,
+        Request::SetAddress => 3,
+        Request::GetDescriptor => 4,
+        Request::SetDescriptor => 5,
+        Request::GetConfiguration => 6,
+        Request::SetConfiguration => 7,
+        Request::GetInterface => 8,
+        Request::SetInterface => 9,
+        Request::SynchFrame => 10,
+        Request::SetEncryption => 11,
+        Request::GetEncryption => 12,
+        Request::SetHandshake => 13,
+        Request::GetHandshake => 14,
+        Request::SetConnection => 15,
+        Request::SetSecurityData => 16,
+        Request::GetSecurityData => 17,
+        Request::SetWusbData => 18,
+        Request::LoopbackDataWrite => 19,
+        Request::LoopbackDataRead => 20,
+        Request::SetInterfaceDs => 21,
+        Request::GetFwStatus => 22,
+        Request::SetFwStatus => 23,
+        Request::SetSel => 24,
+        Request::SetIsochDelay => 25,
+        Request::Other(..) => 26,
+    }
+}
@@ -0,0 +1,105 @@
+//! usb-if 公开 API 兼容性检查
+//!
+//! `baseline.rs` 是按上一个发布版本（usb-if 0.7.0）的公开 API 编写的下游代码，
+//! `exhaustive.rs` 以结构体字面量构造每个公开结构体、穷尽匹配每个公开枚举。两者经
+//! `crab_usb::usb_if` 按当前源码编译：新增字段、新增变体或删改条目都会使其无法编译。
+//!
+//! 只有当前源码的版本号与基线属于同一主版本时才做这项检查（见 `build.rs`），
+//! 因此破坏性变更只能通过升级 usb-if 的主版本号让 CI 通过；发布新的主版本后以其
+//! 重建基线。
+//!
+//! 启用 `released` 特性时基线还会按 crates.io 上的发布版本编译一次，保证基线只用到了
+//! 发布版本中确实存在的 API。
+
+#![no_std]
+
+extern crate alloc;
+
+macro_rules! baseline {
+    () => {
+        include!("baseline.rs");
+
+        pub mod exhaustive {
+            use super::usb_if;
+
+            include!("exhaustive.rs");
+        }
+    };
+}
+
+/// 按当前源码编译的基线
+#[cfg(check_current)]
+pub mod current {
+    use crab_usb::usb_if;
+
+    baseline!();
+}
+
+/// 按已发布的 usb-if 编译的基线
+#[cfg(feature = "released")]
+pub mod released {
+    use usb_if_released as usb_if;
+
+    baseline!();
+}
+
+#[cfg(all(test, check_current))]
+mod tests {
+    use super::current as api;
+
+    /// 一个配置、一个接口、一个批量 IN 端点
+    const CONFIG: [u8; 25] = [
+        9, 2, 25, 0, 1, 1, 0, 0x80, 50, //
+        9, 4, 0, 0, 1, 0xff, 0, 0, 0, //
+        7, 5, 0x81, 2, 0x00, 0x02, 0,
+    ];
+
+    #[test]
+    fn baseline_behaviour() {
+        assert_eq!(api::bm_request_type_in_class_interface(), 0xa1);
+        assert_eq!(api::request_code(6), 6);
+        assert_eq!(api::standard_request_name(6), Some("GET_DESCRIPTOR"));
+        assert_eq!(api::bulk_in_endpoints(&CONFIG), [0x81]);
+        assert!(api::is_stall_message(true));
+        assert_eq!(api::completed_iso_bytes(&[(10, true), (4, false)]), 10);
+        assert!(api::endpoint_in(0x81));
+
+        let (config, device, completion) = api::exhaustive::structs();
+        assert_eq!(
+            config.interfaces[0].alt_settings[0].endpoints[0].address,
+            0x81
+        );
+        assert_eq!(device.vendor_id, 0x1234);
+        assert_eq!(completion.iso_packets.len(), 1);
+
+        let mut buffer = [0u8; 8];
+        assert_eq!(
+            api::build_requests(&mut buffer),
+            [
+                ("control", true),
+                ("bulk", true),
+                ("bulk", false),
+                ("interrupt", true),
+                ("isochronous", true),
+            ]
+        );
+    }
+
+    #[cfg(feature = "released")]
+    #[test]
+    fn released_agrees() {
+        use super::released;
+
+        assert_eq!(
+            released::bm_request_type_in_class_interface(),
+            api::bm_request_type_in_class_interface()
+        );
+        assert_eq!(
+            released::bulk_in_endpoints(&CONFIG),
+            api::bulk_in_endpoints(&CONFIG)
+        );
+        for code in 0..=u8::MAX {
+            assert_eq!(released::request_code(code), api::request_code(code));
+        }
+    }
+}
@@ -0,0 +1,6 @@
+fn main() {
+    // 主机上运行 libusb 用例时不使用 bare-test 的链接脚本
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("none") {
+        bare_test_macros::build_test_setup!();
+    }
+}
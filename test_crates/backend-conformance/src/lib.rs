@@ -0,0 +1,502 @@
+#![no_std]
+
+//! 后端一致性测试
+//!
+//! 通过公开的 [`USBHost`] 接口对任意后端执行同一组用例：描述符读取、控制传输、
+//! STALL 恢复、批量回环、请求取消与断开连接。被测设备由设备模拟器提供：
+//!
+//! - libusb：Linux `dummy_hcd` + `g_zero`（见 `tests/libusb.rs`）
+//! - QEMU xHCI：`usb-kbd`（见 `tests/qemu.rs`）
+//! - 模拟后端：带回环端点的 `MockDevice`，随 `cargo test`
+//!   运行（见 `tests/mock.rs`）
+//!
+//! 新后端只需提供一个能枚举到模拟设备的 `USBHost` 即可复用这些用例。
+//! 用例本身不设超时，由运行方负责在超时后判定失败。
+
+extern crate alloc;
+
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+use core::fmt::{self, Display};
+
+use crab_usb::{
+    Device, DeviceInfo, Endpoint, USBHost,
+    err::USBError,
+    usb_if::{
+        descriptor::{DescriptorType, EndpointType},
+        endpoint::TransferRequest,
+        host::ControlSetup,
+        transfer::{Direction, Recipient, Request, RequestType},
+    },
+};
+use log::{info, warn};
+
+/// 被测的模拟设备
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// 设备把批量 OUT 收到的数据原样从批量 IN 返回
+    pub loopback: bool,
+}
+
+impl Target {
+    /// Linux `g_zero`，需以 `loopdefault=1` 加载使回环配置成为默认配置
+    pub const GADGET_ZERO: Target = Target {
+        vendor_id: 0x1a0a,
+        product_id: 0xbadd,
+        loopback: true,
+    };
+
+    /// QEMU `usb-kbd`
+    pub const QEMU_KBD: Target = Target {
+        vendor_id: 0x0627,
+        product_id: 0x0001,
+        loopback: false,
+    };
+}
+
+/// 断开被测设备，例如写 `dummy_hcd` 的 sysfs 节点、禁用根端口或拔出模拟设备
+pub type DisconnectFn<'a> = Box<dyn FnMut(&mut USBHost) -> Result<(), USBError> + 'a>;
+
+/// 单个用例的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Skipped(&'static str),
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub name: &'static str,
+    pub outcome: Outcome,
+}
+
+/// 一次运行的全部结果
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub cases: Vec<Case>,
+}
+
+impl Report {
+    /// 没有失败的用例
+    pub fn is_success(&self) -> bool {
+        !self
+            .cases
+            .iter()
+            .any(|c| matches!(c.outcome, Outcome::Failed(_)))
+    }
+
+    fn record(&mut self, name: &'static str, res: Result<Outcome, String>) {
+        let outcome = res.unwrap_or_else(Outcome::Failed);
+        match &outcome {
+            Outcome::Passed => info!("[PASS] {name}"),
+            Outcome::Skipped(reason) => info!("[SKIP] {name}: {reason}"),
+            Outcome::Failed(msg) => warn!("[FAIL] {name}: {msg}"),
+        }
+        self.cases.push(Case { name, outcome });
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for case in &self.cases {
+            match &case.outcome {
+                Outcome::Passed => writeln!(f, "[PASS] {}", case.name)?,
+                Outcome::Skipped(reason) => writeln!(f, "[SKIP] {}: {reason}", case.name)?,
+                Outcome::Failed(msg) => writeln!(f, "[FAIL] {}: {msg}", case.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+macro_rules! ensure {
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            return Err(format!($($arg)+));
+        }
+    };
+}
+
+pub struct Suite<'a> {
+    host: &'a mut USBHost,
+    target: Target,
+    disconnect: Option<DisconnectFn<'a>>,
+}
+
+impl<'a> Suite<'a> {
+    /// `host` 需已完成 `init`
+    pub fn new(host: &'a mut USBHost, target: Target) -> Self {
+        Self {
+            host,
+            target,
+            disconnect: None,
+        }
+    }
+
+    /// 提供断开设备的方法后执行断开连接用例，该用例总是最后执行
+    pub fn with_disconnect(
+        mut self,
+        disconnect: impl FnMut(&mut USBHost) -> Result<(), USBError> + 'a,
+    ) -> Self {
+        self.disconnect = Some(Box::new(disconnect));
+        self
+    }
+
+    pub async fn run(mut self) -> Report {
+        let mut report = Report::default();
+
+        let info = match self.find_target().await {
+            Ok(info) => {
+                report.record("enumerate", Ok(Outcome::Passed));
+                info
+            }
+            Err(e) => {
+                report.record("enumerate", Err(e));
+                return report;
+            }
+        };
+        let mut device = match self.host.open_device(&info).await {
+            Ok(device) => device,
+            Err(e) => {
+                report.record("open", Err(format!("{e:?}")));
+                return report;
+            }
+        };
+
+        let target = self.target;
+        report.record(
+            "device_descriptor",
+            device_descriptor(&mut device, target).await,
+        );
+        report.record(
+            "configuration_descriptor",
+            configuration_descriptor(&mut device).await,
+        );
+        report.record("control_status", control_status(&mut device).await);
+        report.record("control_stall", control_stall(&mut device).await);
+
+        match claim_test_interface(&mut device, target).await {
+            Ok(mut eps) => {
+                report.record("bulk_loopback", bulk_loopback(&mut eps).await);
+                report.record("cancel", cancel(&mut eps).await);
+            }
+            Err(e) => {
+                report.record("bulk_loopback", Err(e.clone()));
+                report.record("cancel", Err(e));
+            }
+        }
+
+        let res = match self.disconnect.take() {
+            Some(disconnect) => disconnected(self.host, &mut device, disconnect).await,
+            None => Ok(Outcome::Skipped("no disconnect hook")),
+        };
+        report.record("disconnect", res);
+
+        report
+    }
+
+    async fn find_target(&mut self) -> Result<DeviceInfo, String> {
+        let devices = self
+            .host
+            .probe_devices()
+            .await
+            .map_err(|e| format!("probe: {e:?}"))?;
+        devices
+            .into_iter()
+            .filter_map(|d| d.into_device_info())
+            .find(|d| {
+                d.vendor_id() == self.target.vendor_id && d.product_id() == self.target.product_id
+            })
+            .ok_or_else(|| {
+                format!(
+                    "device {:04x}:{:04x} not found",
+                    self.target.vendor_id, self.target.product_id
+                )
+            })
+    }
+}
+
+fn setup(request: Request, value: u16, index: u16) -> ControlSetup {
+    ControlSetup {
+        request_type: RequestType::Standard,
+        recipient: Recipient::Device,
+        request,
+        value,
+        index,
+    }
+}
+
+fn get_descriptor(ty: DescriptorType, index: u8) -> ControlSetup {
+    setup(
+        Request::GetDescriptor,
+        ((ty.0 as u16) << 8) | index as u16,
+        0,
+    )
+}
+
+async fn device_descriptor(device: &mut Device, target: Target) -> Result<Outcome, String> {
+    let desc = device.descriptor().clone();
+    ensure!(
+        desc.vendor_id == target.vendor_id && desc.product_id == target.product_id,
+        "cached descriptor {:04x}:{:04x}",
+        desc.vendor_id,
+        desc.product_id
+    );
+
+    let mut buf = [0u8; 18];
+    let n = device
+        .control_in(get_descriptor(DescriptorType::DEVICE, 0), &mut buf)
+        .await
+        .map_err(|e| format!("GET_DESCRIPTOR(DEVICE): {e:?}"))?;
+    ensure!(n == 18, "device descriptor length {n}");
+    ensure!(
+        buf[0] == 18 && buf[1] == 0x01,
+        "bad header {:x?}",
+        &buf[..2]
+    );
+    ensure!(
+        u16::from_le_bytes([buf[8], buf[9]]) == target.vendor_id,
+        "idVendor mismatch"
+    );
+    ensure!(
+        buf[17] as usize == device.configurations().len(),
+        "bNumConfigurations {} but {} parsed",
+        buf[17],
+        device.configurations().len()
+    );
+    Ok(Outcome::Passed)
+}
+
+async fn configuration_descriptor(device: &mut Device) -> Result<Outcome, String> {
+    let expected = device
+        .configurations()
+        .first()
+        .ok_or("no configuration parsed")?
+        .configuration_value;
+
+    // 先读头部取得 wTotalLength，再读完整描述符
+    let mut head = [0u8; 9];
+    let n = device
+        .control_in(get_descriptor(DescriptorType::CONFIGURATION, 0), &mut head)
+        .await
+        .map_err(|e| format!("GET_DESCRIPTOR(CONFIGURATION) header: {e:?}"))?;
+    ensure!(n == 9 && head[1] == 0x02, "bad header {:x?}", &head[..n]);
+    let total = u16::from_le_bytes([head[2], head[3]]) as usize;
+
+    let mut full = vec![0u8; total];
+    let n = device
+        .control_in(get_descriptor(DescriptorType::CONFIGURATION, 0), &mut full)
+        .await
+        .map_err(|e| format!("GET_DESCRIPTOR(CONFIGURATION): {e:?}"))?;
+    ensure!(n == total, "read {n} of wTotalLength {total}");
+    ensure!(
+        full[5] == expected,
+        "bConfigurationValue {} but parsed {expected}",
+        full[5]
+    );
+    Ok(Outcome::Passed)
+}
+
+async fn control_status(device: &mut Device) -> Result<Outcome, String> {
+    let mut status = [0u8; 2];
+    let n = device
+        .control_in(setup(Request::GetStatus, 0, 0), &mut status)
+        .await
+        .map_err(|e| format!("GET_STATUS: {e:?}"))?;
+    ensure!(n == 2, "GET_STATUS returned {n} bytes");
+
+    let mut value = [0u8; 1];
+    let n = device
+        .control_in(setup(Request::GetConfiguration, 0, 0), &mut value)
+        .await
+        .map_err(|e| format!("GET_CONFIGURATION: {e:?}"))?;
+    ensure!(n == 1, "GET_CONFIGURATION returned {n} bytes");
+    ensure!(
+        device
+            .configurations()
+            .iter()
+            .any(|c| c.configuration_value == value[0]),
+        "device reports unknown configuration {}",
+        value[0]
+    );
+    Ok(Outcome::Passed)
+}
+
+/// 不支持的请求应以 STALL 结束，且端点 0 随后可以继续使用
+async fn control_stall(device: &mut Device) -> Result<Outcome, String> {
+    let mut buf = [0u8; 64];
+    match device
+        .control_in(get_descriptor(DescriptorType(0xEE), 0), &mut buf)
+        .await
+    {
+        Err(e) if e.is_stall() => {}
+        other => return Err(format!("expected Stall, got {other:?}")),
+    }
+
+    let mut status = [0u8; 2];
+    device
+        .control_in(setup(Request::GetStatus, 0, 0), &mut status)
+        .await
+        .map_err(|e| format!("EP0 not usable after stall: {e:?}"))?;
+    Ok(Outcome::Passed)
+}
+
+struct TestEndpoints {
+    /// 没有数据时保持挂起的 IN 端点，回环设备上为批量 IN
+    input: Endpoint,
+    /// 回环设备的批量 OUT 端点
+    output: Option<Endpoint>,
+}
+
+/// 声明第一个带 IN 端点的接口；回环设备要求同时有批量 IN 与批量 OUT
+async fn claim_test_interface(
+    device: &mut Device,
+    target: Target,
+) -> Result<TestEndpoints, String> {
+    let config = device
+        .current_configuration_descriptor()
+        .await
+        .map_err(|e| format!("current configuration: {e:?}"))?;
+
+    let found = config.interfaces.iter().find_map(|iface| {
+        let alt = iface.first_alt_setting();
+        let find = |ty: EndpointType, dir: Direction| {
+            alt.endpoints
+                .iter()
+                .find(|ep| ep.transfer_type == ty && ep.direction == dir)
+                .map(|ep| ep.address)
+        };
+        if target.loopback {
+            let input = find(EndpointType::Bulk, Direction::In)?;
+            let output = find(EndpointType::Bulk, Direction::Out)?;
+            Some((
+                alt.interface_number,
+                alt.alternate_setting,
+                input,
+                Some(output),
+            ))
+        } else {
+            let input = find(EndpointType::Interrupt, Direction::In)
+                .or_else(|| find(EndpointType::Bulk, Direction::In))?;
+            Some((alt.interface_number, alt.alternate_setting, input, None))
+        }
+    });
+    let (interface, alternate, input, output) =
+        found.ok_or("no interface with the required endpoints")?;
+
+    device
+        .claim_interface(interface, alternate)
+        .await
+        .map_err(|e| format!("claim interface {interface}: {e:?}"))?;
+    let input = device
+        .endpoint(input)
+        .map_err(|e| format!("endpoint {input:#x}: {e:?}"))?;
+    let output = match output {
+        Some(addr) => Some(
+            device
+                .endpoint(addr)
+                .map_err(|e| format!("endpoint {addr:#x}: {e:?}"))?,
+        ),
+        None => None,
+    };
+    Ok(TestEndpoints { input, output })
+}
+
+async fn loopback(eps: &mut TestEndpoints, len: usize) -> Result<(), String> {
+    let output = eps.output.as_mut().ok_or("no OUT endpoint")?;
+    let data: Vec<u8> = (0..len).map(|i| (i * 7 + len) as u8).collect();
+    let n = output
+        .wait(TransferRequest::bulk_out(&data))
+        .await
+        .map_err(|e| format!("bulk OUT {len} bytes: {e:?}"))?
+        .actual_length;
+    ensure!(n == len, "bulk OUT wrote {n} of {len}");
+
+    // 按最大包长向上取整，避免设备返回整包时溢出
+    let mps = eps.input.info().max_packet_size as usize;
+    let mut buf = vec![0u8; len.div_ceil(mps).max(1) * mps];
+    let n = eps
+        .input
+        .wait(TransferRequest::bulk_in(&mut buf))
+        .await
+        .map_err(|e| format!("bulk IN {len} bytes: {e:?}"))?
+        .actual_length;
+    ensure!(n == len, "bulk IN read {n} of {len}");
+    ensure!(
+        buf[..n] == data[..],
+        "loopback data mismatch at {len} bytes"
+    );
+    Ok(())
+}
+
+async fn bulk_loopback(eps: &mut TestEndpoints) -> Result<Outcome, String> {
+    if eps.output.is_none() {
+        return Ok(Outcome::Skipped("target has no loopback"));
+    }
+    let mps = eps.input.info().max_packet_size as usize;
+    // 短包、整包、跨包与多包
+    for len in [1, mps - 1, mps, mps + 1, 4096] {
+        loopback(eps, len).await?;
+    }
+    Ok(Outcome::Passed)
+}
+
+/// 取消挂起的 IN 请求应交还缓冲区且不影响端点后续使用
+async fn cancel(eps: &mut TestEndpoints) -> Result<Outcome, String> {
+    let info = eps.input.info();
+    let mut buf = vec![0u8; info.max_packet_size as usize];
+    for round in 0..2 {
+        eps.input.ready().await;
+        let request = match info.transfer_type {
+            EndpointType::Interrupt => TransferRequest::interrupt_in(&mut buf),
+            _ => TransferRequest::bulk_in(&mut buf),
+        };
+        let id = eps
+            .input
+            .submit(request)
+            .map_err(|e| format!("submit (round {round}): {e:?}"))?;
+        let res = eps
+            .input
+            .cancel(id)
+            .await
+            .map_err(|e| format!("cancel (round {round}): {e:?}"))?;
+        if let Some(completion) = res {
+            info!(
+                "request completed before cancel: {} bytes",
+                completion.actual_length
+            );
+        }
+    }
+
+    if eps.output.is_some() {
+        loopback(eps, 64)
+            .await
+            .map_err(|e| format!("after cancel: {e}"))?;
+    }
+    Ok(Outcome::Passed)
+}
+
+/// 断开后的传输应返回错误而不是挂起
+async fn disconnected(
+    host: &mut USBHost,
+    device: &mut Device,
+    mut disconnect: DisconnectFn<'_>,
+) -> Result<Outcome, String> {
+    disconnect(host).map_err(|e| format!("disconnect hook: {e:?}"))?;
+
+    let mut status = [0u8; 2];
+    match device
+        .control_in(setup(Request::GetStatus, 0, 0), &mut status)
+        .await
+    {
+        Ok(n) => Err(format!(
+            "GET_STATUS succeeded with {n} bytes after disconnect"
+        )),
+        Err(e) => {
+            info!("transfer after disconnect: {e}");
+            Ok(Outcome::Passed)
+        }
+    }
+}
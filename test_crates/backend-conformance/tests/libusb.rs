@@ -0,0 +1,33 @@
+#![cfg(not(target_os = "none"))]
+
+//! libusb 后端一致性测试
+//!
+//! 需要 Linux 上的 `dummy_hcd` 与 `g_zero` 模拟设备，默认忽略：
+//!
+//! ```sh
+//! sudo modprobe dummy_hcd
+//! sudo modprobe g_zero loopdefault=1
+//! cargo test -p backend-conformance --test libusb -- --ignored
+//! ```
+
+use std::time::Duration;
+
+use backend_conformance::{Suite, Target};
+use crab_usb::USBHost;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test]
+#[ignore = "requires dummy_hcd and g_zero"]
+async fn libusb_conformance() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let mut host = USBHost::new_libusb().unwrap();
+    host.init().await.unwrap();
+
+    let report = tokio::time::timeout(TIMEOUT, Suite::new(&mut host, Target::GADGET_ZERO).run())
+        .await
+        .expect("conformance suite hung");
+    println!("{report}");
+    assert!(report.is_success(), "conformance suite failed");
+}
@@ -0,0 +1,51 @@
+#![cfg(not(target_os = "none"))]
+
+//! 模拟后端一致性测试，不需要硬件或模拟器
+
+use std::time::Duration;
+
+use backend_conformance::{Suite, Target};
+use crab_usb::{MockDevice, USBHost};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 与 `g_zero` 回环配置相同的 ID 与端点布局
+fn gadget_zero() -> MockDevice {
+    let mut device = [
+        18, 1, 0x00, 0x02, 0xff, 0, 0, 64, 0x0a, 0x1a, 0xdd, 0xba, 0, 1, 0, 0, 0, 1,
+    ];
+    device[8..10].copy_from_slice(&Target::GADGET_ZERO.vendor_id.to_le_bytes());
+    device[10..12].copy_from_slice(&Target::GADGET_ZERO.product_id.to_le_bytes());
+    let mut config = vec![9, 2, 32, 0, 1, 1, 0, 0x80, 50, 9, 4, 0, 0, 2, 0xff, 0, 0, 0];
+    config.extend_from_slice(&[7, 5, 0x81, 2, 0x00, 0x02, 0]);
+    config.extend_from_slice(&[7, 5, 0x01, 2, 0x00, 0x02, 0]);
+    MockDevice::new(device, vec![config]).with_loopback(0x81, 0x01)
+}
+
+#[tokio::test]
+async fn mock_conformance() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let (mut host, bus) = USBHost::new_mock();
+    host.init().await.unwrap();
+    bus.attach(gadget_zero());
+
+    let hook = bus.clone();
+    let suite = Suite::new(&mut host, Target::GADGET_ZERO).with_disconnect(move |_| {
+        hook.detach_all();
+        Ok(())
+    });
+    let report = tokio::time::timeout(TIMEOUT, suite.run())
+        .await
+        .expect("conformance suite hung");
+    println!("{report}");
+    assert!(report.is_success(), "conformance suite failed");
+    assert!(
+        report
+            .cases
+            .iter()
+            .all(|c| c.outcome == backend_conformance::Outcome::Passed),
+        "mock backend skipped cases"
+    );
+    assert_eq!(bus.in_flight(), 0, "requests leaked");
+}
@@ -0,0 +1,43 @@
+#![cfg_attr(target_os = "none", no_std, no_main, feature(used_with_arg))]
+
+//! QEMU xHCI 后端一致性测试
+//!
+//! 被测设备为 QEMU `usb-kbd`，没有回环端点，批量回环用例会跳过。
+//! 断开连接用例通过故障注入禁用根端口。
+
+#[cfg(target_os = "none")]
+extern crate alloc;
+
+/// 主机上构建 `--tests` 时没有可运行的内容
+#[cfg(not(target_os = "none"))]
+fn main() {}
+
+#[cfg(target_os = "none")]
+#[bare_test::tests]
+mod tests {
+    use backend_conformance::{Suite, Target};
+    use bare_test::println;
+    use core::time::Duration;
+    use ktest_helper::*;
+    use log::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    const SUITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn test_conformance() {
+        let mut host = get_usb_host().usb;
+        let handler = host.create_event_handler();
+
+        poll_with_events(&handler, host.init(), TIMEOUT)
+            .expect("init hung")
+            .unwrap();
+        info!("usb host init ok");
+
+        let suite = Suite::new(&mut host, Target::QEMU_KBD).with_disconnect(disable_all_ports);
+        let report =
+            poll_with_events(&handler, suite.run(), SUITE_TIMEOUT).expect("conformance suite hung");
+        println!("{report}");
+        assert!(report.is_success(), "conformance suite failed");
+    }
+}
@@ -10,14 +10,8 @@ extern crate alloc;
 mod tests {
     use alloc::{boxed::Box, vec::Vec};
     use bare_test::{
-        GetIrqConfig,
         async_std::time::sleep,
-        fdt_parser::{PciSpace, Status},
-        globals::{PlatformInfoKind, global_val},
         irq::{IrqHandleResult, IrqInfo, IrqParam},
-        mem::iomap,
-        platform::fdt::GetPciIrqConfig,
-        println,
     };
     use core::{
         sync::atomic::{AtomicBool, Ordering},
@@ -31,9 +25,6 @@ mod tests {
 
     use log::info;
     use log::*;
-    use pcie::*;
-
-    use super::*;
 
     static PROT_CHANGED: AtomicBool = AtomicBool::new(false);
 
@@ -50,6 +41,9 @@ mod tests {
             host.init().await.unwrap();
             info!("usb host init ok");
             info!("usb cmd test");
+            let report = host.self_test(16).await.unwrap();
+            info!("self test: {report:?}");
+            assert!(report.interrupters > 0, "no interrupter checked");
 
             for _ in 0..10 {
                 if PROT_CHANGED.load(Ordering::Acquire) {
@@ -104,6 +98,8 @@ mod tests {
 
                 info!("open device ok: {device:?}");
 
+                bench_control_latency(&mut device).await;
+
                 device
                     .set_configuration(config_desc.configuration_value)
                     .await
@@ -150,174 +146,42 @@ mod tests {
                     }
                 }
 
+                // 设备上下文由 Device 持有，需在释放前弹出
+                host.eject(info.id(), false).await.unwrap();
+                assert!(info.is_stale());
+                info!("eject device {} ok", info.id());
+
                 drop(device);
             }
         });
     }
 
-    struct XhciInfo {
-        usb: USBHost,
-        irq: Option<IrqInfo>,
-    }
-
-    fn get_usb_host_pcie() -> Option<XhciInfo> {
-        let PlatformInfoKind::DeviceTree(fdt) = &global_val().platform_info;
-
-        let fdt = fdt.get();
+    /// 记录 GET_STATUS 往返延迟，只输出日志，不作为功能测试的判定条件
+    async fn bench_control_latency(device: &mut Device) {
+        const ROUNDS: u32 = 64;
 
-        let pcie = fdt
-            .find_compatible(&["pci-host-ecam-generic", "brcm,bcm2711-pcie"])
-            .next()?
-            .into_pci()
+        // 预热一次，排除首次请求的缓存与分配开销
+        device
+            .get_status(usb_if::transfer::Recipient::Device, 0)
+            .await
             .unwrap();
 
-        let mut pcie_regs = alloc::vec![];
-
-        println!("pcie: {}", pcie.node.name);
-
-        for reg in pcie.node.reg().unwrap() {
-            println!(
-                "pcie reg: {:#x}, bus: {:#x}",
-                reg.address, reg.child_bus_address
-            );
-            let size = reg.size.unwrap_or_default().align_up(0x1000);
-
-            pcie_regs.push(iomap((reg.address as usize).into(), size));
+        let mut min = Duration::MAX;
+        let mut max = Duration::ZERO;
+        let mut total = Duration::ZERO;
+        for _ in 0..ROUNDS {
+            let start = KernelImpl.now();
+            device
+                .get_status(usb_if::transfer::Recipient::Device, 0)
+                .await
+                .unwrap();
+            let latency = KernelImpl.now().saturating_sub(start);
+            min = min.min(latency);
+            max = max.max(latency);
+            total += latency;
         }
-
-        let mut bar_alloc = SimpleBarAllocator::default();
-
-        for range in pcie.ranges().unwrap() {
-            info!("pcie range: {range:?}");
-
-            match range.space {
-                PciSpace::Memory32 => bar_alloc.set_mem32(range.cpu_address as _, range.size as _),
-                PciSpace::Memory64 => bar_alloc.set_mem64(range.cpu_address, range.size),
-                _ => {}
-            }
-        }
-
-        let base_vaddr = pcie_regs[0];
-
-        info!("Init PCIE @{base_vaddr:?}");
-
-        let mut root = RootComplexGeneric::new(base_vaddr);
-
-        // for elem in root.enumerate_keep_bar(None) {
-        for elem in root.enumerate(None, Some(bar_alloc)) {
-            debug!("PCI {elem}");
-
-            if let Header::Endpoint(mut ep) = elem.header {
-                ep.update_command(elem.root, |mut cmd| {
-                    cmd.remove(CommandRegister::INTERRUPT_DISABLE);
-                    cmd | CommandRegister::IO_ENABLE
-                        | CommandRegister::MEMORY_ENABLE
-                        | CommandRegister::BUS_MASTER_ENABLE
-                });
-
-                for cap in &mut ep.capabilities {
-                    match cap {
-                        PciCapability::Msi(msi_capability) => {
-                            msi_capability.set_enabled(false, &mut *elem.root);
-                        }
-                        PciCapability::MsiX(msix_capability) => {
-                            msix_capability.set_enabled(false, &mut *elem.root);
-                        }
-                        _ => {}
-                    }
-                }
-
-                println!("irq_pin {:?}, {:?}", ep.interrupt_pin, ep.interrupt_line);
-
-                if matches!(ep.device_type(), DeviceType::UsbController) {
-                    let bar_addr;
-                    let mut bar_size;
-                    match ep.bar {
-                        pcie::BarVec::Memory32(bar_vec_t) => {
-                            let bar0 = bar_vec_t[0].as_ref().unwrap();
-                            bar_addr = bar0.address as usize;
-                            bar_size = bar0.size as usize;
-                        }
-                        pcie::BarVec::Memory64(bar_vec_t) => {
-                            let bar0 = bar_vec_t[0].as_ref().unwrap();
-                            bar_addr = bar0.address as usize;
-                            bar_size = bar0.size as usize;
-                        }
-                        pcie::BarVec::Io(_bar_vec_t) => todo!(),
-                    };
-
-                    println!("bar0: {:#x}", bar_addr);
-                    println!("bar0 size: {:#x}", bar_size);
-                    bar_size = bar_size.align_up(0x1000);
-                    println!("bar0 size algin: {:#x}", bar_size);
-
-                    let addr = iomap(bar_addr.into(), bar_size);
-                    trace!("pin {:?}", ep.interrupt_pin);
-
-                    let irq = pcie.child_irq_info(
-                        ep.address.bus(),
-                        ep.address.device(),
-                        ep.address.function(),
-                        ep.interrupt_pin,
-                    );
-
-                    println!("irq: {irq:?}");
-
-                    return Some(XhciInfo {
-                        usb: USBHost::new_xhci(addr, &KernelImpl).unwrap(),
-                        irq,
-                    });
-                }
-            }
-        }
-        None
-    }
-
-    fn get_usb_host() -> XhciInfo {
-        if let Some(info) = get_usb_host_pcie() {
-            return info;
-        }
-
-        let PlatformInfoKind::DeviceTree(fdt) = &global_val().platform_info;
-
-        let fdt = fdt.get();
-        for node in fdt.all_nodes() {
-            if matches!(node.status(), Some(Status::Disabled)) {
-                continue;
-            }
-
-            if node
-                .compatibles()
-                .any(|c| c.contains("xhci") | c.contains("snps,dwc3"))
-            {
-                // 只选择明确为 host 模式的控制器，避免误用 OTG 端口
-                if let Some(prop) = node.find_property("dr_mode") {
-                    let mode = prop.str();
-                    if mode != "host" {
-                        debug!("skip {} because dr_mode={}", node.name(), mode);
-                        continue;
-                    }
-                }
-
-                println!("usb node: {}", node.name);
-                let regs = node.reg().unwrap().collect::<Vec<_>>();
-                println!("usb regs: {:?}", regs);
-
-                let addr = iomap(
-                    (regs[0].address as usize).into(),
-                    regs[0].size.unwrap_or(0x1000),
-                );
-
-                let irq = node.irq_info();
-
-                return XhciInfo {
-                    usb: USBHost::new_xhci(addr, &KernelImpl).unwrap(),
-                    irq,
-                };
-            }
-        }
-
-        panic!("no xhci found");
+        let avg = total / ROUNDS;
+        info!("GET_STATUS x{ROUNDS}: min {min:?}, avg {avg:?}, max {max:?}");
     }
 
     fn register_irq(irq: IrqInfo, host: &mut USBHost) {
@@ -342,17 +206,3 @@ mod tests {
         }
     }
 }
-
-trait Align {
-    fn align_up(&self, align: usize) -> usize;
-}
-
-impl Align for usize {
-    fn align_up(&self, align: usize) -> usize {
-        if (*self).is_multiple_of(align) {
-            *self
-        } else {
-            *self + align - *self % align
-        }
-    }
-}
@@ -0,0 +1,179 @@
+#![no_std]
+#![no_main]
+#![feature(used_with_arg)]
+#![cfg(target_os = "none")]
+
+//! 意外拔出模拟测试
+//!
+//! 在枚举中、控制传输中与等时流传输中禁用根端口，检查相关 Future 都能在超时内结束，
+//! 且设备释放后没有遗留的 DMA 映射。使用事件环轮询，不依赖中断。
+//! 等时用例需要等时设备，QEMU 下用 `cargo test-removal` 接入 `usb-audio`。
+
+extern crate alloc;
+
+#[bare_test::tests]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use core::{future::poll_fn, time::Duration};
+    use crab_usb::{
+        usb_if::{
+            descriptor::EndpointType,
+            endpoint::TransferRequest,
+            host::ControlSetup,
+            transfer::{Recipient, Request, RequestType},
+        },
+        *,
+    };
+    use ktest_helper::*;
+    use log::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    /// 拔出时在端点上排队的等时请求数与每个请求的包数
+    const ISO_REQUESTS: usize = 4;
+    const ISO_PACKETS: usize = 8;
+
+    #[test]
+    fn test_removal_during_enumeration() {
+        run(RemovalPoint::Enumeration);
+    }
+
+    #[test]
+    fn test_removal_mid_control_transfer() {
+        run(RemovalPoint::ControlTransfer);
+    }
+
+    #[test]
+    fn test_removal_mid_iso_stream() {
+        run(RemovalPoint::IsoStream);
+    }
+
+    fn run(point: RemovalPoint) {
+        info!("=== removal at {point:?} ===");
+        let mut host = get_usb_host().usb;
+        let handler = host.create_event_handler();
+
+        poll_with_events(&handler, host.init(), TIMEOUT)
+            .expect("init hung")
+            .unwrap();
+        let baseline = dma_usage();
+
+        match point {
+            RemovalPoint::Enumeration => {
+                disable_all_ports(&mut host).unwrap();
+                let res = poll_with_events(&handler, host.probe_devices(), TIMEOUT)
+                    .expect("enumeration hung after removal");
+                info!("probe after removal: {:?}", res.map(|ls| ls.len()));
+            }
+            RemovalPoint::ControlTransfer => {
+                let ls = poll_with_events(&handler, host.probe_devices(), TIMEOUT)
+                    .expect("enumeration hung")
+                    .unwrap();
+                let Some(info) = ls.into_iter().find_map(|d| d.into_device_info()) else {
+                    warn!("no device attached, skip");
+                    return;
+                };
+                let mut device = poll_with_events(&handler, host.open_device(&info), TIMEOUT)
+                    .expect("open device hung")
+                    .unwrap();
+
+                let mut buff = alloc::vec![0u8; 18];
+                let ep = device.ctrl_ep_mut();
+                let id = ep
+                    .submit(TransferRequest::control_in(
+                        ControlSetup {
+                            request_type: RequestType::Standard,
+                            recipient: Recipient::Device,
+                            request: Request::GetDescriptor,
+                            value: 0x0100,
+                            index: 0,
+                        },
+                        &mut buff,
+                    ))
+                    .unwrap();
+
+                disable_all_ports(&mut host).unwrap();
+
+                let res =
+                    poll_with_events(&handler, poll_fn(|cx| ep.poll_request(id, cx)), TIMEOUT)
+                        .expect("control transfer never completed after removal");
+                info!("control transfer after removal: {res:?}");
+                drop(device);
+            }
+            RemovalPoint::IsoStream => {
+                let ls = poll_with_events(&handler, host.probe_devices(), TIMEOUT)
+                    .expect("enumeration hung")
+                    .unwrap();
+                let Some((info, (interface, alternate, address))) = ls
+                    .into_iter()
+                    .filter_map(|d| d.into_device_info())
+                    .find_map(|info| find_iso_endpoint(&info).map(|ep| (info, ep)))
+                else {
+                    warn!("no isochronous device attached, skip");
+                    return;
+                };
+                let mut device = poll_with_events(&handler, host.open_device(&info), TIMEOUT)
+                    .expect("open device hung")
+                    .unwrap();
+                poll_with_events(
+                    &handler,
+                    device.claim_interface(interface, alternate),
+                    TIMEOUT,
+                )
+                .expect("claim interface hung")
+                .unwrap();
+                let mut ep = device.endpoint(address).unwrap();
+
+                // 排满多个等时请求后拔出，每个请求都应结束并交还缓冲区
+                let lengths = [ep.iso_packet_size(); ISO_PACKETS];
+                let mut buffers = vec![vec![0u8; lengths.iter().sum::<usize>()]; ISO_REQUESTS];
+                let ids = buffers
+                    .iter_mut()
+                    .map(|buf| {
+                        let request = if address & 0x80 != 0 {
+                            TransferRequest::iso_in(buf, &lengths)
+                        } else {
+                            TransferRequest::iso_out(buf, &lengths)
+                        };
+                        ep.submit(request).unwrap()
+                    })
+                    .collect::<Vec<_>>();
+
+                disable_all_ports(&mut host).unwrap();
+
+                for id in ids {
+                    let res =
+                        poll_with_events(&handler, poll_fn(|cx| ep.poll_request(id, cx)), TIMEOUT)
+                            .expect("iso request never completed after removal");
+                    info!(
+                        "iso request after removal: {:?}",
+                        res.map(|c| c.iso_failed_count())
+                    );
+                }
+                drop(ep);
+                drop(device);
+            }
+        }
+
+        let usage = dma_usage();
+        info!("dma usage: baseline {baseline:?}, after {usage:?}");
+        assert!(
+            usage.mapped <= baseline.mapped,
+            "transfer buffers still mapped after removal"
+        );
+    }
+
+    /// 第一个带等时端点的接口设置：(接口号, 备用设置, 端点地址)
+    fn find_iso_endpoint(info: &DeviceInfo) -> Option<(u8, u8, u8)> {
+        info.configurations()
+            .first()?
+            .interfaces
+            .iter()
+            .flat_map(|iface| &iface.alt_settings)
+            .find_map(|alt| {
+                alt.endpoints
+                    .iter()
+                    .find(|ep| ep.transfer_type == EndpointType::Isochronous)
+                    .map(|ep| (alt.interface_number, alt.alternate_setting, ep.address))
+            })
+    }
+}
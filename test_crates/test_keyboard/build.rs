@@ -0,0 +1,3 @@
+fn main() {
+    bare_test_macros::build_test_setup!();
+}
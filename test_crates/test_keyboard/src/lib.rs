@@ -0,0 +1,4 @@
+#![no_std]
+
+extern crate alloc;
+extern crate crab_usb;
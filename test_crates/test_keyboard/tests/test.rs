@@ -0,0 +1,91 @@
+#![no_std]
+#![no_main]
+#![feature(used_with_arg)]
+#![cfg(target_os = "none")]
+
+//! HID 键盘端到端测试
+//!
+//! 在 kmod 后端上枚举键盘，循环提交中断 IN 传输并把按键事件打印到串口。
+//! 使用事件环轮询，不依赖中断；一段时间内没有按键时正常结束。
+
+extern crate alloc;
+
+#[bare_test::tests]
+mod tests {
+    use alloc::vec::Vec;
+    use bare_test::println;
+    use core::time::Duration;
+    use ktest_helper::*;
+    use log::*;
+    use usb_keyboard::{KeyBoard, KeyEvent};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    /// 等待按键的总时长，QEMU 中可通过 monitor 的 `sendkey` 产生按键
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+    const MAX_REPORTS: usize = 32;
+
+    #[test]
+    fn test_keyboard() {
+        let mut host = get_usb_host().usb;
+        let handler = host.create_event_handler();
+
+        poll_with_events(&handler, host.init(), TIMEOUT)
+            .expect("init hung")
+            .unwrap();
+        info!("usb host init ok");
+
+        let mut devices = Vec::new();
+        for _ in 0..20 {
+            let ls = poll_with_events(&handler, host.probe_devices(), TIMEOUT)
+                .expect("enumeration hung")
+                .unwrap();
+            if !ls.is_empty() {
+                devices = ls
+                    .into_iter()
+                    .filter_map(|d| d.into_device_info())
+                    .collect();
+                break;
+            }
+            bare_test::time::spin_delay(Duration::from_millis(100));
+        }
+
+        let info = devices
+            .iter()
+            .find(|info| KeyBoard::check(info))
+            .expect("no HID keyboard found");
+        info!("found keyboard: {info:?}");
+
+        let device = poll_with_events(&handler, host.open_device(info), TIMEOUT)
+            .expect("open device hung")
+            .unwrap();
+        let mut keyboard = poll_with_events(&handler, KeyBoard::new(device), TIMEOUT)
+            .expect("keyboard init hung")
+            .unwrap();
+        info!("keyboard ready, press keys");
+
+        // 每份报告完成后立即重新提交下一次中断 IN 传输
+        for _ in 0..MAX_REPORTS {
+            let Some(res) = poll_with_events(&handler, keyboard.recv_events(), IDLE_TIMEOUT) else {
+                info!("no key event within {IDLE_TIMEOUT:?}, stop");
+                break;
+            };
+            match res {
+                Ok(events) => {
+                    for event in events {
+                        match event {
+                            KeyEvent::KeyDown { key, modifiers } => {
+                                println!("key down: {key:?} {modifiers:?}")
+                            }
+                            KeyEvent::KeyUp { key, modifiers } => {
+                                println!("key up: {key:?} {modifiers:?}")
+                            }
+                        }
+                    }
+                }
+                Err(e) => warn!("recv events: {e:?}"),
+            }
+        }
+
+        drop(keyboard);
+    }
+}
@@ -0,0 +1,30 @@
+#![cfg(not(target_os = "none"))]
+
+//! 只枚举设备，不需要特定硬件，CI 在 Windows 上运行
+//!
+//! 没有 USB 子系统的环境（如容器中没有 usbfs）无法创建 libusb 上下文，此时跳过。
+//! Windows 上以及设置了 `CI` 环境变量时必须能创建上下文，否则失败。
+
+use crab_usb::USBHost;
+
+#[tokio::test]
+async fn enumerate() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let mut host = match USBHost::new_libusb() {
+        Ok(host) => host,
+        Err(e) if cfg!(windows) || std::env::var_os("CI").is_some() => {
+            panic!("libusb context creation failed: {e}")
+        }
+        Err(e) => {
+            println!("libusb unavailable, skipping: {e}");
+            return;
+        }
+    };
+    host.init().await.unwrap();
+
+    let devices = host.probe_devices().await.unwrap();
+    for probed in devices {
+        println!("{probed:?}");
+    }
+}
@@ -45,6 +45,10 @@ async fn test() {
     if let Some(s) = device.manufacturer() {
         info!("Manufacturer: {s}");
     }
+    info!("Languages: {:?}", device.languages().await);
+    if let Some(s) = device.product().await {
+        info!("Product: {s}");
+    }
 
     let config = device.current_configuration_descriptor().await.unwrap();
 
@@ -4,9 +4,9 @@
 
 use crab_usb::USBHost;
 use crab_uvc::{UvcDevice, VideoControlEvent};
-use log::{debug, error, info, warn};
+use log::{error, info, warn};
 use std::{sync::Arc, time::Duration};
-use uvc_frame_parser::Parser;
+use uvc_frame_parser::{DiskSink, Parser};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -109,61 +109,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let start_time = std::time::Instant::now();
 
-    // 捕获视频帧 (运行30秒)
-    info!("Capturing video frames for 30 seconds...");
+    // 捕获视频帧，逐帧写入磁盘
+    info!("Capturing video frames for 6 seconds...");
     let capture_duration = Duration::from_secs(6);
-    let frame_count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
-    let frame_count_clone = frame_count.clone();
-
-    let running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
-    let running_clone = running.clone();
-
+    let mut sink = DiskSink::new(parser.clone());
     let mut last_err = String::new();
 
-    let saved_frames = Arc::new(std::sync::Mutex::new(Vec::new()));
-    let saved_frames_clone = saved_frames.clone();
-    let parser_clone = parser.clone();
-
-    let handle = tokio::spawn(async move {
-        // 处理设备事件
-        while running_clone.load(std::sync::atomic::Ordering::Relaxed) {
-            let data = stream.recv().await;
-            match data {
-                Ok(frames) => {
-                    for frame in frames {
-                        let frame_number =
-                            frame_count_clone.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                        debug!("Received frame data of {} bytes", frame.data.len());
-
-                        // 保存帧数据
-                        if let Err(e) = parser_clone
-                            .save_frame_to_file(&frame, frame_number as u32)
-                            .await
-                        {
-                            warn!("Failed to save frame {}: {:?}", frame_number, e);
-                        } else {
-                            saved_frames_clone.lock().unwrap().push(frame_number as u32);
-                        }
-                    }
-                }
-                Err(e) => {
-                    if e.to_string() != last_err {
-                        warn!("Error receiving frame: {:?}", e);
-                        last_err = e.to_string();
-                    }
-                }
-            }
-            ()
+    while start_time.elapsed() < capture_duration {
+        if let Err(e) = stream.pump(&mut sink).await
+            && e.to_string() != last_err
+        {
+            warn!("Error receiving frame: {:?}", e);
+            last_err = e.to_string();
         }
-    });
-
-    tokio::time::sleep(capture_duration).await;
-
-    running.store(false, std::sync::atomic::Ordering::Relaxed);
-    handle.await.unwrap();
+    }
 
-    let frame_count = frame_count.load(std::sync::atomic::Ordering::Acquire);
-    let saved_frame_numbers = saved_frames.lock().unwrap().clone();
+    let saved_frame_numbers = sink.saved_frames().to_vec();
+    let frame_count = saved_frame_numbers.len();
 
     let avg_fps = frame_count as f32 / start_time.elapsed().as_secs_f32();
     info!(
@@ -0,0 +1 @@
+//! 通过 VFIO 在 Linux 用户态驱动物理 xHCI 控制器的测试，见 `tests/test.rs`
@@ -0,0 +1,56 @@
+#![cfg(all(target_os = "linux", feature = "vfio"))]
+
+//! 需要一个已绑定到 `vfio-pci` 的 xHCI 控制器：
+//!
+//! ```bash
+//! CRAB_USB_VFIO_BDF=0000:03:00.0 cargo test-vfio
+//! ```
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use crab_usb::{USBHost, VfioConfig};
+use log::info;
+
+#[tokio::test]
+#[ignore = "requires an xHCI controller bound to vfio-pci"]
+async fn test() {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Debug)
+        .is_test(true)
+        .init();
+
+    let bdf = std::env::var("CRAB_USB_VFIO_BDF").expect("CRAB_USB_VFIO_BDF not set");
+    let (mut host, irq) = USBHost::new_vfio(&bdf, VfioConfig::default()).unwrap();
+
+    // 中断线程：等待 eventfd 后处理事件环，超时也处理一次以防丢失中断
+    let handler = host.create_event_handler();
+    let running = Arc::new(AtomicBool::new(true));
+    let irq_thread = std::thread::spawn({
+        let running = running.clone();
+        move || {
+            while running.load(Ordering::Acquire) {
+                irq.wait(Some(Duration::from_millis(10))).unwrap();
+                handler.handle_event();
+                irq.ack().unwrap();
+            }
+        }
+    });
+
+    host.init().await.unwrap();
+    info!("usb host init ok");
+
+    for probed in host.probe_devices().await.unwrap() {
+        info!("{probed:?}");
+    }
+
+    info!("{:?}", host.perf_counters());
+
+    running.store(false, Ordering::Release);
+    irq_thread.join().unwrap();
+}
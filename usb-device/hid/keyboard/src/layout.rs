@@ -0,0 +1,241 @@
+//! 键盘布局：扫描码到字符的映射
+//!
+//! 布局只描述字符键，Enter、方向键等功能键与布局无关。自定义布局使用文本格式，
+//! 在美式布局的基础上覆盖指定的键，每行一个键：
+//!
+//! ```text
+//! # 扫描码  基本  Shift  [AltGr]  [AltGr+Shift]
+//! 0x1C      z     Z
+//! 0x1D      y     Y
+//! 0x14      q     Q      @
+//! ```
+//!
+//! 字符列为单个字符，`space` 表示空格，`none` 表示该层没有字符。
+
+use alloc::collections::BTreeMap;
+
+use anyhow::{anyhow, bail};
+use keyboard_types::Modifiers;
+
+/// 一个字符键在各修饰层产生的字符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LayoutEntry {
+    pub base: char,
+    pub shift: Option<char>,
+    pub altgr: Option<char>,
+    pub altgr_shift: Option<char>,
+}
+
+impl LayoutEntry {
+    pub const fn new(base: char, shift: char) -> Self {
+        Self {
+            base,
+            shift: Some(shift),
+            altgr: None,
+            altgr_shift: None,
+        }
+    }
+
+    /// Shift 层是基本字符的大写形式，此时 CapsLock 生效
+    fn has_case(&self) -> bool {
+        self.shift
+            .is_some_and(|s| s != self.base && self.base.to_uppercase().eq([s]))
+    }
+}
+
+/// 键盘布局
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    entries: BTreeMap<u8, LayoutEntry>,
+}
+
+const US: &[(u8, char, char)] = &[
+    (0x1E, '1', '!'),
+    (0x1F, '2', '@'),
+    (0x20, '3', '#'),
+    (0x21, '4', '$'),
+    (0x22, '5', '%'),
+    (0x23, '6', '^'),
+    (0x24, '7', '&'),
+    (0x25, '8', '*'),
+    (0x26, '9', '('),
+    (0x27, '0', ')'),
+    (0x2C, ' ', ' '),
+    (0x2D, '-', '_'),
+    (0x2E, '=', '+'),
+    (0x2F, '[', '{'),
+    (0x30, ']', '}'),
+    (0x31, '\\', '|'),
+    // Non-US # 与 ~，ISO 键盘 Enter 左侧
+    (0x32, '#', '~'),
+    (0x33, ';', ':'),
+    (0x34, '\'', '"'),
+    (0x35, '`', '~'),
+    (0x36, ',', '<'),
+    (0x37, '.', '>'),
+    (0x38, '/', '?'),
+    // Non-US \ 与 |，ISO 键盘左 Shift 右侧
+    (0x64, '\\', '|'),
+];
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::us()
+    }
+}
+
+impl Layout {
+    /// 美式布局
+    pub fn us() -> Self {
+        let mut entries = BTreeMap::new();
+        for (i, c) in ('a'..='z').enumerate() {
+            entries.insert(0x04 + i as u8, LayoutEntry::new(c, c.to_ascii_uppercase()));
+        }
+        for &(scancode, base, shift) in US {
+            entries.insert(scancode, LayoutEntry::new(base, shift));
+        }
+        Self { entries }
+    }
+
+    /// 解析文本格式的布局，未列出的键沿用美式布局
+    pub fn parse(text: &str) -> Result<Self, anyhow::Error> {
+        let mut layout = Self::us();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let entry = parse_line(line).map_err(|e| anyhow!("layout line {}: {e}", i + 1))?;
+            layout.set(entry.0, entry.1);
+        }
+        Ok(layout)
+    }
+
+    pub fn set(&mut self, scancode: u8, entry: LayoutEntry) {
+        self.entries.insert(scancode, entry);
+    }
+
+    pub fn get(&self, scancode: u8) -> Option<&LayoutEntry> {
+        self.entries.get(&scancode)
+    }
+
+    /// 布局是否使用 AltGr 层；使用时右 Alt 报告为 `ALT_GRAPH` 而非 `ALT`
+    pub fn has_altgr(&self) -> bool {
+        self.entries
+            .values()
+            .any(|e| e.altgr.is_some() || e.altgr_shift.is_some())
+    }
+
+    /// 按修饰键与 CapsLock 状态查找字符，非字符键返回 `None`
+    ///
+    /// CapsLock 只作用于有大小写之分的字母键，AltGr 层没有字符时退回到普通层。
+    pub fn lookup(&self, scancode: u8, modifiers: Modifiers, caps_lock: bool) -> Option<char> {
+        let entry = self.entries.get(&scancode)?;
+        let shift = modifiers.contains(Modifiers::SHIFT) ^ (caps_lock && entry.has_case());
+        let altgr = if modifiers.contains(Modifiers::ALT_GRAPH) {
+            if shift {
+                entry.altgr_shift.or(entry.altgr)
+            } else {
+                entry.altgr
+            }
+        } else {
+            None
+        };
+        altgr.or(if shift { entry.shift } else { Some(entry.base) })
+    }
+}
+
+fn parse_line(line: &str) -> Result<(u8, LayoutEntry), anyhow::Error> {
+    let mut fields = line.split_whitespace();
+    let scancode = fields.next().ok_or(anyhow!("missing scancode"))?;
+    let scancode = match scancode.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => scancode.parse(),
+    }
+    .map_err(|_| anyhow!("invalid scancode `{scancode}`"))?;
+
+    let mut chars = [None; 4];
+    for slot in chars.iter_mut() {
+        let Some(field) = fields.next() else {
+            break;
+        };
+        *slot = parse_char(field)?;
+    }
+    if fields.next().is_some() {
+        bail!("too many columns");
+    }
+    let base = chars[0].ok_or(anyhow!("missing base character"))?;
+
+    Ok((
+        scancode,
+        LayoutEntry {
+            base,
+            shift: chars[1],
+            altgr: chars[2],
+            altgr_shift: chars[3],
+        },
+    ))
+}
+
+fn parse_char(field: &str) -> Result<Option<char>, anyhow::Error> {
+    match field {
+        "none" => Ok(None),
+        "space" => Ok(Some(' ')),
+        _ => {
+            let mut chars = field.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(Some(c)),
+                _ => bail!("expected a single character, got `{field}`"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QWERTZ: &str = "
+        # 德语布局片段
+        0x1C z Z
+        0x1D y Y
+        0x14 q Q @
+        0x24 7 / {
+        0x2D ß ? \\
+    ";
+
+    #[test]
+    fn us_shift_and_caps() {
+        let us = Layout::us();
+        assert_eq!(us.lookup(0x04, Modifiers::empty(), false), Some('a'));
+        assert_eq!(us.lookup(0x04, Modifiers::SHIFT, false), Some('A'));
+        assert_eq!(us.lookup(0x04, Modifiers::SHIFT, true), Some('a'));
+        assert_eq!(us.lookup(0x1E, Modifiers::empty(), true), Some('1'));
+        assert_eq!(us.lookup(0x1E, Modifiers::SHIFT, false), Some('!'));
+        assert_eq!(us.lookup(0x28, Modifiers::empty(), false), None);
+        assert!(!us.has_altgr());
+    }
+
+    #[test]
+    fn qwertz_overlay() {
+        let de = Layout::parse(QWERTZ).unwrap();
+        assert!(de.has_altgr());
+        assert_eq!(de.lookup(0x1C, Modifiers::empty(), false), Some('z'));
+        assert_eq!(de.lookup(0x1D, Modifiers::SHIFT, false), Some('Y'));
+        assert_eq!(de.lookup(0x14, Modifiers::ALT_GRAPH, false), Some('@'));
+        assert_eq!(de.lookup(0x24, Modifiers::ALT_GRAPH, false), Some('{'));
+        assert_eq!(de.lookup(0x2D, Modifiers::empty(), true), Some('ß'));
+        // AltGr 层没有字符时退回普通层
+        assert_eq!(de.lookup(0x1C, Modifiers::ALT_GRAPH, false), Some('z'));
+        // 未覆盖的键沿用美式布局
+        assert_eq!(de.lookup(0x04, Modifiers::empty(), false), Some('a'));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Layout::parse("0x14").is_err());
+        assert!(Layout::parse("0x14 ab").is_err());
+        assert!(Layout::parse("zz a A").is_err());
+        assert!(Layout::parse("0x14 a A b c d").is_err());
+    }
+}
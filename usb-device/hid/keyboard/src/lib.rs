@@ -1,22 +1,40 @@
 #![no_std]
 
 extern crate alloc;
-use alloc::{string::ToString, vec::Vec};
+use alloc::{boxed::Box, string::ToString, vec::Vec};
+use core::{
+    future::poll_fn,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
 
-use anyhow::bail;
+use anyhow::anyhow;
 use crab_usb::{
     Endpoint,
     device::{Device, DeviceInfo},
     err::USBError,
 };
 use keyboard_types::{Key, Modifiers, NamedKey};
-use log::debug;
+use log::{debug, warn};
 use usb_if::{
     descriptor::{Class, EndpointType},
-    endpoint::TransferRequest,
-    transfer::Direction,
+    endpoint::{RequestId, TransferRequest},
+    host::ControlSetup,
+    transfer::{Direction, Recipient, Request, RequestType},
 };
 
+mod layout;
+mod repeat;
+mod set;
+pub use layout::{Layout, LayoutEntry};
+pub use repeat::{KeyRepeat, RepeatConfig};
+pub use set::{KeyboardSet, MemberError, TimedKeyEvent};
+
+/// HID 类请求 SET_IDLE
+const HID_SET_IDLE: u8 = 0x0A;
+/// CapsLock 扫描码
+const CAPS_LOCK: u8 = 0x39;
+
 /// 键盘事件类型
 #[derive(Debug, Clone, PartialEq)]
 pub enum KeyEvent {
@@ -26,65 +44,14 @@ pub enum KeyEvent {
     KeyUp { key: Key, modifiers: Modifiers },
 }
 
-/// USB HID 键盘扫描码到 Key 的映射
+/// USB HID 键盘扫描码到功能键的映射，字符键由 [`Layout`] 决定
 fn scancode_to_key(scancode: u8) -> Option<Key> {
     match scancode {
-        0x04 => Some(Key::Character("a".to_string())),
-        0x05 => Some(Key::Character("b".to_string())),
-        0x06 => Some(Key::Character("c".to_string())),
-        0x07 => Some(Key::Character("d".to_string())),
-        0x08 => Some(Key::Character("e".to_string())),
-        0x09 => Some(Key::Character("f".to_string())),
-        0x0A => Some(Key::Character("g".to_string())),
-        0x0B => Some(Key::Character("h".to_string())),
-        0x0C => Some(Key::Character("i".to_string())),
-        0x0D => Some(Key::Character("j".to_string())),
-        0x0E => Some(Key::Character("k".to_string())),
-        0x0F => Some(Key::Character("l".to_string())),
-        0x10 => Some(Key::Character("m".to_string())),
-        0x11 => Some(Key::Character("n".to_string())),
-        0x12 => Some(Key::Character("o".to_string())),
-        0x13 => Some(Key::Character("p".to_string())),
-        0x14 => Some(Key::Character("q".to_string())),
-        0x15 => Some(Key::Character("r".to_string())),
-        0x16 => Some(Key::Character("s".to_string())),
-        0x17 => Some(Key::Character("t".to_string())),
-        0x18 => Some(Key::Character("u".to_string())),
-        0x19 => Some(Key::Character("v".to_string())),
-        0x1A => Some(Key::Character("w".to_string())),
-        0x1B => Some(Key::Character("x".to_string())),
-        0x1C => Some(Key::Character("y".to_string())),
-        0x1D => Some(Key::Character("z".to_string())),
-
-        // 数字键
-        0x1E => Some(Key::Character("1".to_string())),
-        0x1F => Some(Key::Character("2".to_string())),
-        0x20 => Some(Key::Character("3".to_string())),
-        0x21 => Some(Key::Character("4".to_string())),
-        0x22 => Some(Key::Character("5".to_string())),
-        0x23 => Some(Key::Character("6".to_string())),
-        0x24 => Some(Key::Character("7".to_string())),
-        0x25 => Some(Key::Character("8".to_string())),
-        0x26 => Some(Key::Character("9".to_string())),
-        0x27 => Some(Key::Character("0".to_string())),
-
         // 特殊键
         0x28 => Some(Key::Named(NamedKey::Enter)),
         0x29 => Some(Key::Named(NamedKey::Escape)),
         0x2A => Some(Key::Named(NamedKey::Backspace)),
         0x2B => Some(Key::Named(NamedKey::Tab)),
-        0x2C => Some(Key::Character(" ".to_string())), // Space
-        0x2D => Some(Key::Character("-".to_string())),
-        0x2E => Some(Key::Character("=".to_string())),
-        0x2F => Some(Key::Character("[".to_string())),
-        0x30 => Some(Key::Character("]".to_string())),
-        0x31 => Some(Key::Character("\\".to_string())),
-        0x33 => Some(Key::Character(";".to_string())),
-        0x34 => Some(Key::Character("'".to_string())),
-        0x35 => Some(Key::Character("`".to_string())),
-        0x36 => Some(Key::Character(",".to_string())),
-        0x37 => Some(Key::Character(".".to_string())),
-        0x38 => Some(Key::Character("/".to_string())),
 
         // 功能键
         0x3A => Some(Key::Named(NamedKey::F1)),
@@ -123,10 +90,26 @@ fn scancode_to_key(scancode: u8) -> Option<Key> {
 }
 
 pub struct KeyBoard {
-    _device: Device,
+    device: Device,
     endpoint: Endpoint,
+    /// 在途请求的报告缓冲区，需在 `endpoint` 之后释放
+    report: Box<[u8; 8]>,
+    pending: Option<RequestId>,
+    interface_number: u8,
     /// 上一次按键状态，用于检测按键变化
     previous_state: [u8; 8],
+    /// 按下时产生的键，释放时报告同一个键
+    pressed: Vec<(u8, Key)>,
+    layout: Layout,
+    /// 布局使用 AltGr 层，右 Alt 报告为 `ALT_GRAPH`
+    altgr: bool,
+    caps_lock: bool,
+    repeat: Option<Repeat>,
+}
+
+struct Repeat {
+    state: KeyRepeat,
+    clock: Box<dyn Fn() -> Duration + Send>,
 }
 
 impl KeyBoard {
@@ -182,32 +165,142 @@ impl KeyBoard {
         let endpoint = device.endpoint(endpoint_address)?;
 
         Ok(Self {
-            _device: device,
+            device,
             endpoint,
+            report: Box::new([0; 8]),
+            pending: None,
+            interface_number,
             previous_state: [0; 8],
+            pressed: Vec::new(),
+            layout: Layout::us(),
+            altgr: false,
+            caps_lock: false,
+            repeat: None,
         })
     }
 
     /// 接收并解析键盘事件
     pub async fn recv_events(&mut self) -> Result<Vec<KeyEvent>, anyhow::Error> {
-        let mut buf = [0u8; 8];
-        let n = self
-            .endpoint
-            .wait(TransferRequest::interrupt_in(&mut buf))
-            .await?
-            .actual_length;
-
-        if n == 0 {
-            bail!("No data received from keyboard");
+        poll_fn(|cx| self.poll_events(cx)).await
+    }
+
+    /// 轮询键盘事件，报告尚未到达时注册唤醒
+    ///
+    /// 请求在返回 `Pending` 后保持在途，丢弃等待的 future 不会丢失报告，
+    /// 因此可以与其他键盘或定时器一起轮询。
+    pub fn poll_events(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Vec<KeyEvent>, anyhow::Error>> {
+        let id = match self.pending {
+            Some(id) => id,
+            None => {
+                let id = self
+                    .endpoint
+                    .submit(TransferRequest::interrupt_in(&mut self.report[..]))?;
+                self.pending = Some(id);
+                id
+            }
+        };
+        let completion = ready!(self.endpoint.poll_request(id, cx));
+        self.pending = None;
+
+        if completion?.actual_length == 0 {
+            return Poll::Ready(Err(anyhow!("No data received from keyboard")));
         }
 
-        let events = self.parse_keyboard_report(&buf);
+        let buf = *self.report;
+        let mut events = self.parse_keyboard_report(&buf);
+        self.update_repeat(&buf, &mut events);
         self.previous_state = buf;
-        Ok(events)
+        Poll::Ready(Ok(events))
+    }
+
+    /// 开启按键重复合成，`clock` 为单调时钟（如 `KernelOp::now`）
+    ///
+    /// 通过 SET_IDLE 让键盘在按住期间按 `config.interval` 周期重发报告，
+    /// 使 [`recv_events`](Self::recv_events) 能及时返回重复的 `KeyDown` 事件。
+    /// 设备不支持 SET_IDLE 时仍可由调用方定时调用 [`poll_repeat`](Self::poll_repeat)。
+    pub async fn enable_key_repeat(
+        &mut self,
+        config: RepeatConfig,
+        clock: impl Fn() -> Duration + Send + 'static,
+    ) {
+        // SET_IDLE 以 4ms 为单位，0 表示仅在状态变化时报告
+        let idle = (config.interval.as_millis() / 4).clamp(1, 255) as u8;
+        if let Err(e) = self.set_idle(idle).await {
+            warn!("SET_IDLE failed, key repeat relies on poll_repeat: {e:?}");
+        }
+        self.repeat = Some(Repeat {
+            state: KeyRepeat::new(config),
+            clock: Box::new(clock),
+        });
+    }
+
+    /// 关闭按键重复合成，并恢复为仅在状态变化时报告
+    pub async fn disable_key_repeat(&mut self) {
+        self.repeat = None;
+        if let Err(e) = self.set_idle(0).await {
+            debug!("SET_IDLE(0) failed: {e:?}");
+        }
+    }
+
+    /// 若按住的键已到重复时间，返回一个重复的 `KeyDown` 事件
+    pub fn poll_repeat(&mut self) -> Option<KeyEvent> {
+        let repeat = self.repeat.as_mut()?;
+        repeat.state.poll((repeat.clock)())
+    }
+
+    /// 下一次重复的时间，供调用方安排定时器
+    pub fn next_repeat_deadline(&self) -> Option<Duration> {
+        self.repeat.as_ref()?.state.next_deadline()
+    }
+
+    /// 设置空闲报告周期（HID 7.2.4），单位 4ms，0 表示无限
+    pub async fn set_idle(&mut self, duration: u8) -> Result<(), USBError> {
+        self.device
+            .control_out(
+                ControlSetup {
+                    request_type: RequestType::Class,
+                    recipient: Recipient::Interface,
+                    request: Request::Class(HID_SET_IDLE),
+                    value: (duration as u16) << 8,
+                    index: self.interface_number as u16,
+                },
+                &[],
+            )
+            .await?;
+        Ok(())
+    }
+
+    fn update_repeat(&mut self, report: &[u8; 8], events: &mut Vec<KeyEvent>) {
+        let modifiers = self.parse_modifiers(report[0]);
+        let Some(repeat) = self.repeat.as_mut() else {
+            return;
+        };
+        let now = (repeat.clock)();
+        let previous = &self.previous_state[2..8];
+        let current = &report[2..8];
+
+        for &scancode in previous {
+            if scancode != 0 && !current.contains(&scancode) {
+                repeat.state.release(scancode);
+            }
+        }
+        for &scancode in current {
+            if scancode != 0
+                && !previous.contains(&scancode)
+                && let Some((_, key)) = self.pressed.iter().find(|(s, _)| *s == scancode)
+            {
+                repeat.state.press(scancode, key.clone(), modifiers, now);
+            }
+        }
+        repeat.state.set_modifiers(modifiers);
+        events.extend(repeat.state.poll(now));
     }
 
     /// 解析 USB HID 键盘报告
-    fn parse_keyboard_report(&self, report: &[u8; 8]) -> Vec<KeyEvent> {
+    fn parse_keyboard_report(&mut self, report: &[u8; 8]) -> Vec<KeyEvent> {
         let mut events = Vec::new();
 
         if report.len() < 8 {
@@ -242,9 +335,13 @@ impl KeyBoard {
 
         // 检测新按下的键
         for &scancode in &current_keys {
+            if scancode == CAPS_LOCK && !previous_keys.contains(&scancode) {
+                self.caps_lock = !self.caps_lock;
+            }
             if !previous_keys.contains(&scancode)
-                && let Some(key) = scancode_to_key(scancode)
+                && let Some(key) = self.translate(scancode, current_modifiers)
             {
+                self.pressed.push((scancode, key.clone()));
                 events.push(KeyEvent::KeyDown {
                     key,
                     modifiers: current_modifiers,
@@ -255,8 +352,9 @@ impl KeyBoard {
         // 检测释放的键
         for &scancode in &previous_keys {
             if !current_keys.contains(&scancode)
-                && let Some(key) = scancode_to_key(scancode)
+                && let Some(i) = self.pressed.iter().position(|(s, _)| *s == scancode)
             {
+                let (_, key) = self.pressed.remove(i);
                 events.push(KeyEvent::KeyUp {
                     key,
                     modifiers: previous_modifiers,
@@ -296,8 +394,12 @@ impl KeyBoard {
             modifiers |= Modifiers::SHIFT;
         }
         if modifier_byte & 0x40 != 0 {
-            // Right Alt
-            modifiers |= Modifiers::ALT;
+            // Right Alt，布局使用 AltGr 层时作为 AltGr
+            if self.altgr {
+                modifiers |= Modifiers::ALT_GRAPH;
+            } else {
+                modifiers |= Modifiers::ALT;
+            }
         }
         if modifier_byte & 0x80 != 0 {
             // Right GUI (Windows/Cmd)
@@ -309,15 +411,30 @@ impl KeyBoard {
 
     /// 获取当前按下的所有键
     pub fn get_pressed_keys(&self) -> Vec<Key> {
-        let mut keys = Vec::new();
-        for &scancode in &self.previous_state[2..8] {
-            if scancode != 0
-                && let Some(key) = scancode_to_key(scancode)
-            {
-                keys.push(key);
-            }
+        self.pressed.iter().map(|(_, key)| key.clone()).collect()
+    }
+
+    /// 设置键盘布局，默认为美式布局
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.altgr = layout.has_altgr();
+        self.layout = layout;
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// CapsLock 是否处于开启状态
+    pub fn caps_lock(&self) -> bool {
+        self.caps_lock
+    }
+
+    /// 按当前布局与 CapsLock 状态将扫描码转换为键
+    fn translate(&self, scancode: u8, modifiers: Modifiers) -> Option<Key> {
+        match self.layout.lookup(scancode, modifiers, self.caps_lock) {
+            Some(c) => Some(Key::Character(c.to_string())),
+            None => scancode_to_key(scancode),
         }
-        keys
     }
 
     /// 获取当前修饰键状态
@@ -0,0 +1,183 @@
+use core::time::Duration;
+
+use keyboard_types::{Key, Modifiers, NamedKey};
+
+use crate::KeyEvent;
+
+/// 按键重复参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatConfig {
+    /// 按下后开始重复前的延迟
+    pub delay: Duration,
+    /// 重复间隔
+    pub interval: Duration,
+}
+
+impl Default for RepeatConfig {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(500),
+            interval: Duration::from_millis(33),
+        }
+    }
+}
+
+struct Held {
+    scancode: u8,
+    key: Key,
+    modifiers: Modifiers,
+    next: Duration,
+}
+
+/// 按键重复合成器
+///
+/// USB 键盘只报告按键状态，不会自动重复。合成器跟踪最后按下的键，
+/// 按 [`RepeatConfig`] 在到期时生成额外的 `KeyDown` 事件。时间由调用方传入，
+/// 通常来自 `KernelOp::now` 等单调时钟。
+pub struct KeyRepeat {
+    config: RepeatConfig,
+    held: Option<Held>,
+}
+
+impl KeyRepeat {
+    pub fn new(config: RepeatConfig) -> Self {
+        Self { config, held: None }
+    }
+
+    pub fn config(&self) -> RepeatConfig {
+        self.config
+    }
+
+    /// 按下新键，取代之前正在重复的键
+    pub fn press(&mut self, scancode: u8, key: Key, modifiers: Modifiers, now: Duration) {
+        if !repeatable(&key) {
+            return;
+        }
+        self.held = Some(Held {
+            scancode,
+            key,
+            modifiers,
+            next: now + self.config.delay,
+        });
+    }
+
+    /// 释放按键，若正是重复中的键则停止重复
+    pub fn release(&mut self, scancode: u8) {
+        if self.held.as_ref().is_some_and(|h| h.scancode == scancode) {
+            self.held = None;
+        }
+    }
+
+    /// 修饰键变化后，后续重复使用新的修饰键
+    pub fn set_modifiers(&mut self, modifiers: Modifiers) {
+        if let Some(held) = self.held.as_mut() {
+            held.modifiers = modifiers;
+        }
+    }
+
+    /// 下一次重复的时间，没有按住的键时返回 `None`
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.held.as_ref().map(|h| h.next)
+    }
+
+    /// 到期时返回一个重复的 `KeyDown` 事件
+    ///
+    /// 调用不及时时只补发一次，不会积压成一串重复。
+    pub fn poll(&mut self, now: Duration) -> Option<KeyEvent> {
+        let held = self.held.as_mut()?;
+        if now < held.next {
+            return None;
+        }
+        held.next += self.config.interval;
+        if held.next <= now {
+            held.next = now + self.config.interval;
+        }
+        Some(KeyEvent::KeyDown {
+            key: held.key.clone(),
+            modifiers: held.modifiers,
+        })
+    }
+}
+
+/// 锁定键按住时不重复，否则会反复切换状态
+fn repeatable(key: &Key) -> bool {
+    !matches!(
+        key,
+        Key::Named(NamedKey::CapsLock | NamedKey::ScrollLock | NamedKey::NumLock)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn repeat() -> KeyRepeat {
+        KeyRepeat::new(RepeatConfig {
+            delay: ms(500),
+            interval: ms(100),
+        })
+    }
+
+    #[test]
+    fn repeats_after_delay() {
+        let mut r = repeat();
+        r.press(
+            0x04,
+            Key::Character("a".to_string()),
+            Modifiers::empty(),
+            ms(0),
+        );
+        assert!(r.poll(ms(499)).is_none());
+        assert!(r.poll(ms(500)).is_some());
+        assert!(r.poll(ms(550)).is_none());
+        assert!(r.poll(ms(600)).is_some());
+        assert_eq!(r.next_deadline(), Some(ms(700)));
+    }
+
+    #[test]
+    fn late_poll_does_not_burst() {
+        let mut r = repeat();
+        r.press(
+            0x04,
+            Key::Character("a".to_string()),
+            Modifiers::empty(),
+            ms(0),
+        );
+        assert!(r.poll(ms(2000)).is_some());
+        assert!(r.poll(ms(2000)).is_none());
+        assert_eq!(r.next_deadline(), Some(ms(2100)));
+    }
+
+    #[test]
+    fn release_stops_only_held_key() {
+        let mut r = repeat();
+        r.press(
+            0x04,
+            Key::Character("a".to_string()),
+            Modifiers::empty(),
+            ms(0),
+        );
+        r.release(0x05);
+        assert!(r.next_deadline().is_some());
+        r.release(0x04);
+        assert!(r.poll(ms(1000)).is_none());
+    }
+
+    #[test]
+    fn lock_keys_do_not_repeat() {
+        let mut r = repeat();
+        r.press(
+            0x39,
+            Key::Named(NamedKey::CapsLock),
+            Modifiers::empty(),
+            ms(0),
+        );
+        assert!(r.next_deadline().is_none());
+    }
+}
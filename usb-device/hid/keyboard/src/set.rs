@@ -0,0 +1,180 @@
+//! 多键盘聚合
+//!
+//! 主键盘加数字小键盘，或 KVM 切换器同时提供两个 HID 键盘接口时，把各键盘的事件
+//! 合并为一个带时间戳的事件流，成员随主机的热插拔事件增减。
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{future::poll_fn, mem, task::Poll, time::Duration};
+
+use crab_usb::{HotplugEvent, USBHost, err::USBError};
+use log::{debug, info};
+
+use crate::{KeyBoard, KeyEvent};
+
+/// 带来源与时间戳的键盘事件
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedKeyEvent {
+    /// 产生事件的键盘，即加入集合时的设备 ID
+    pub source: usize,
+    /// 事件被取出时 `clock` 的读数
+    pub timestamp: Duration,
+    pub event: KeyEvent,
+}
+
+/// 某个成员接收失败，成员仍保留在集合中
+#[derive(Debug)]
+pub struct MemberError {
+    pub id: usize,
+    pub error: anyhow::Error,
+}
+
+/// 多个键盘合并成一个事件流
+///
+/// ```ignore
+/// let mut set = KeyboardSet::new(move || kernel.now());
+/// for probed in host.probe_devices().await? {
+///     set.handle_hotplug(&mut host, &HotplugEvent::Attached(probed)).await?;
+/// }
+/// loop {
+///     select! {
+///         ev = host.next_hotplug_event() => { set.handle_hotplug(&mut host, &ev?).await?; }
+///         events = set.recv_events() => { /* 处理合并后的事件 */ }
+///     }
+/// }
+/// ```
+pub struct KeyboardSet {
+    members: Vec<(usize, KeyBoard)>,
+    clock: Box<dyn Fn() -> Duration + Send>,
+    /// 下一次从哪个成员开始轮询，避免总是优先处理同一个键盘
+    next: usize,
+    /// 前一次轮询因其他成员出错而未能返回的事件
+    backlog: Vec<TimedKeyEvent>,
+}
+
+impl KeyboardSet {
+    /// `clock` 为单调时钟（如 `KernelOp::now`），用于事件时间戳
+    pub fn new(clock: impl Fn() -> Duration + Send + 'static) -> Self {
+        Self {
+            members: Vec::new(),
+            clock: Box::new(clock),
+            next: 0,
+            backlog: Vec::new(),
+        }
+    }
+
+    /// 加入一个键盘，`id` 已存在时替换原有成员并返回它
+    pub fn insert(&mut self, id: usize, keyboard: KeyBoard) -> Option<KeyBoard> {
+        match self.members.iter_mut().find(|(i, _)| *i == id) {
+            Some((_, old)) => Some(mem::replace(old, keyboard)),
+            None => {
+                self.members.push((id, keyboard));
+                None
+            }
+        }
+    }
+
+    /// 移除一个键盘
+    pub fn remove(&mut self, id: usize) -> Option<KeyBoard> {
+        let i = self.members.iter().position(|(i, _)| *i == id)?;
+        self.backlog.retain(|ev| ev.source != id);
+        Some(self.members.remove(i).1)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut KeyBoard> {
+        self.members
+            .iter_mut()
+            .find_map(|(i, kb)| (*i == id).then_some(kb))
+    }
+
+    /// 当前成员的设备 ID
+    pub fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.members.iter().map(|(id, _)| *id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// 根据热插拔事件增减成员，返回成员是否发生变化
+    ///
+    /// 插入的设备是 HID 键盘时打开并加入集合，拔出的设备是成员时移除。
+    pub async fn handle_hotplug(
+        &mut self,
+        host: &mut USBHost,
+        event: &HotplugEvent,
+    ) -> Result<bool, USBError> {
+        match event {
+            HotplugEvent::Attached(probed) => {
+                let Some(info) = probed.as_device_info() else {
+                    return Ok(false);
+                };
+                if !KeyBoard::check(info) {
+                    return Ok(false);
+                }
+                let device = host.open_device(info).await?;
+                let keyboard = KeyBoard::new(device).await?;
+                info!("Keyboard {} joined set", info.id());
+                self.insert(info.id(), keyboard);
+                Ok(true)
+            }
+            HotplugEvent::Detached { id } => {
+                let removed = self.remove(*id).is_some();
+                if removed {
+                    info!("Keyboard {id} left set");
+                }
+                Ok(removed)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// 等待任一成员产生事件，返回同一时刻已就绪的全部事件
+    ///
+    /// 集合为空时一直挂起，适合与热插拔事件一起 `select`。取消等待不会丢失报告。
+    pub async fn recv_events(&mut self) -> Result<Vec<TimedKeyEvent>, MemberError> {
+        poll_fn(|cx| {
+            if !self.backlog.is_empty() {
+                return Poll::Ready(Ok(mem::take(&mut self.backlog)));
+            }
+            let count = self.members.len();
+            let mut events = Vec::new();
+            for k in 0..count {
+                let i = (self.next + k) % count;
+                let (id, keyboard) = &mut self.members[i];
+                // 报告可能不产生事件，继续轮询直到重新提交的请求挂起并注册唤醒
+                loop {
+                    match keyboard.poll_events(cx) {
+                        Poll::Ready(Ok(batch)) => {
+                            let timestamp = (self.clock)();
+                            events.extend(batch.into_iter().map(|event| TimedKeyEvent {
+                                source: *id,
+                                timestamp,
+                                event,
+                            }));
+                        }
+                        Poll::Ready(Err(error)) => {
+                            debug!("Keyboard {id} receive failed: {error:?}");
+                            self.next = (i + 1) % count;
+                            self.backlog = events;
+                            return Poll::Ready(Err(MemberError { id: *id, error }));
+                        }
+                        Poll::Pending => break,
+                    }
+                }
+            }
+            if count > 0 {
+                self.next = (self.next + 1) % count;
+            }
+            if events.is_empty() {
+                Poll::Pending
+            } else {
+                Poll::Ready(Ok(events))
+            }
+        })
+        .await
+    }
+}
@@ -0,0 +1,48 @@
+use crab_usb::{DeviceInfo, USBHost};
+use log::info;
+use usb_mouse::Mouse;
+
+#[tokio::main]
+async fn main() {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Debug)
+        .init();
+
+    let mut host = USBHost::new_libusb().unwrap();
+    let ls = host.probe_devices().await.unwrap();
+
+    let mut info: Option<DeviceInfo> = None;
+
+    for probed in ls {
+        println!("{probed}");
+        let Some(device) = probed.into_device_info() else {
+            continue;
+        };
+
+        if Mouse::check(&device) {
+            info!("Found mouse: {device}");
+            info = Some(device);
+            break;
+        }
+    }
+
+    let info = info.expect("No device found with HID mouse interface");
+
+    let device = host.open_device(&info).await.unwrap();
+    info!("Opened device: {device}");
+
+    let mut mouse = Mouse::new(device).await.unwrap();
+
+    loop {
+        match mouse.recv_events().await {
+            Ok(events) => {
+                for event in events {
+                    info!("{event:?}");
+                }
+            }
+            Err(e) => {
+                info!("Error receiving report: {e:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+#![no_std]
+
+extern crate alloc;
+use alloc::vec::Vec;
+
+use anyhow::bail;
+use crab_usb::{
+    Endpoint,
+    device::{Device, DeviceInfo},
+    err::USBError,
+};
+use log::{debug, warn};
+use usb_if::{
+    descriptor::{Class, EndpointType},
+    endpoint::TransferRequest,
+    host::ControlSetup,
+    transfer::{Direction, Recipient, Request, RequestType},
+};
+
+mod report;
+pub use report::{MouseButton, MouseEvent, MouseReport};
+
+/// HID 类请求 SET_PROTOCOL
+const HID_SET_PROTOCOL: u8 = 0x0B;
+/// SET_PROTOCOL 的引导协议取值
+const BOOT_PROTOCOL: u16 = 0;
+
+pub struct Mouse {
+    device: Device,
+    endpoint: Endpoint,
+    interface_number: u8,
+    /// 上一次报告，用于检测按键变化
+    previous: MouseReport,
+}
+
+impl Mouse {
+    /// 检查设备是否为引导协议 HID 鼠标
+    pub fn check(info: &DeviceInfo) -> bool {
+        for config in info.configurations() {
+            for interface in &config.interfaces {
+                let alt = interface.first_alt_setting();
+                if matches!(alt.class(), Class::Hid) && alt.subclass == 1 && alt.protocol == 2 {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// 创建新的鼠标设备实例，并切换到引导协议
+    pub async fn new(mut device: Device) -> Result<Self, USBError> {
+        // 查找 HID 鼠标接口
+        let config = &device.configurations()[0];
+        let (interface_number, alternate_setting, endpoint_address) = config
+            .interfaces
+            .iter()
+            .find_map(|iface| {
+                let alt = iface.first_alt_setting();
+                if matches!(alt.class(), Class::Hid) && alt.subclass == 1 && alt.protocol == 2 {
+                    // 查找中断 IN 端点
+                    for ep in &alt.endpoints {
+                        if matches!(ep.transfer_type, EndpointType::Interrupt)
+                            && matches!(ep.direction, Direction::In)
+                        {
+                            return Some((alt.interface_number, alt.alternate_setting, ep.address));
+                        }
+                    }
+                }
+                None
+            })
+            .ok_or(USBError::NotFound)?;
+
+        debug!(
+            "Using interface: {interface_number}, alt: {alternate_setting}, endpoint: {endpoint_address:#x}"
+        );
+
+        device
+            .claim_interface(interface_number, alternate_setting)
+            .await?;
+
+        let endpoint = device.endpoint(endpoint_address)?;
+
+        let mut mouse = Self {
+            device,
+            endpoint,
+            interface_number,
+            previous: MouseReport::default(),
+        };
+
+        // 报告协议的格式由报告描述符决定，切换到引导协议后固定为按键、X、Y（及滚轮）
+        if let Err(e) = mouse.set_boot_protocol().await {
+            warn!("SET_PROTOCOL(boot) failed, assuming boot report layout: {e:?}");
+        }
+
+        Ok(mouse)
+    }
+
+    /// 接收并解析鼠标事件
+    pub async fn recv_events(&mut self) -> Result<Vec<MouseEvent>, anyhow::Error> {
+        let mut buf = [0u8; 8];
+        let n = self
+            .endpoint
+            .wait(TransferRequest::interrupt_in(&mut buf))
+            .await?
+            .actual_length;
+
+        let Some(report) = MouseReport::parse(&buf[..n]) else {
+            bail!("Short report from mouse: {n} bytes");
+        };
+
+        let events = report.events_since(&self.previous).collect();
+        self.previous = report;
+        Ok(events)
+    }
+
+    /// 当前是否按住 `button`
+    pub fn is_pressed(&self, button: MouseButton) -> bool {
+        self.previous.is_pressed(button)
+    }
+
+    /// 切换到引导协议（HID 7.2.6）
+    pub async fn set_boot_protocol(&mut self) -> Result<(), USBError> {
+        self.device
+            .control_out(
+                ControlSetup {
+                    request_type: RequestType::Class,
+                    recipient: Recipient::Interface,
+                    request: Request::Class(HID_SET_PROTOCOL),
+                    value: BOOT_PROTOCOL,
+                    index: self.interface_number as u16,
+                },
+                &[],
+            )
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+/// 鼠标按键
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    /// 侧键（后退）
+    Back,
+    /// 侧键（前进）
+    Forward,
+}
+
+impl MouseButton {
+    /// 按报告字节 0 的位序排列
+    const ALL: [MouseButton; 5] = [
+        MouseButton::Left,
+        MouseButton::Right,
+        MouseButton::Middle,
+        MouseButton::Back,
+        MouseButton::Forward,
+    ];
+
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// 鼠标事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseEvent {
+    /// 按键按下事件
+    ButtonDown(MouseButton),
+    /// 按键释放事件
+    ButtonUp(MouseButton),
+    /// 相对移动，`dy` 向下为正
+    Move { dx: i8, dy: i8 },
+    /// 滚轮，向上滚动为正
+    Wheel { delta: i8 },
+}
+
+/// 引导协议鼠标报告
+///
+/// ```text
+/// Byte 0: 按键位图（bit0 左键、bit1 右键、bit2 中键）
+/// Byte 1: X 位移（i8）
+/// Byte 2: Y 位移（i8）
+/// Byte 3: 滚轮（可选，多数鼠标在引导协议下仍会发送）
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseReport {
+    pub buttons: u8,
+    pub dx: i8,
+    pub dy: i8,
+    pub wheel: i8,
+}
+
+impl MouseReport {
+    /// 解析报告，不足 3 字节时返回 `None`
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 3 {
+            return None;
+        }
+        Some(Self {
+            buttons: data[0],
+            dx: data[1] as i8,
+            dy: data[2] as i8,
+            wheel: data.get(3).map_or(0, |&w| w as i8),
+        })
+    }
+
+    pub fn is_pressed(&self, button: MouseButton) -> bool {
+        self.buttons & button.mask() != 0
+    }
+
+    /// 与上一份报告比较，按键变化在前，随后是移动与滚轮
+    pub fn events_since(&self, previous: &MouseReport) -> impl Iterator<Item = MouseEvent> {
+        let (current, previous) = (*self, *previous);
+        let buttons = MouseButton::ALL.into_iter().filter_map(move |button| {
+            match (previous.is_pressed(button), current.is_pressed(button)) {
+                (false, true) => Some(MouseEvent::ButtonDown(button)),
+                (true, false) => Some(MouseEvent::ButtonUp(button)),
+                _ => None,
+            }
+        });
+        let motion = (current.dx != 0 || current.dy != 0).then_some(MouseEvent::Move {
+            dx: current.dx,
+            dy: current.dy,
+        });
+        let wheel = (current.wheel != 0).then_some(MouseEvent::Wheel {
+            delta: current.wheel,
+        });
+        buttons.chain(motion).chain(wheel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn parse_boot_and_wheel_reports() {
+        assert_eq!(MouseReport::parse(&[0x01, 0x05]), None);
+        let boot = MouseReport::parse(&[0x01, 0xFB, 0x03]).unwrap();
+        assert_eq!((boot.dx, boot.dy, boot.wheel), (-5, 3, 0));
+        assert!(boot.is_pressed(MouseButton::Left));
+        let wheel = MouseReport::parse(&[0x00, 0, 0, 0xFF]).unwrap();
+        assert_eq!(wheel.wheel, -1);
+    }
+
+    #[test]
+    fn events_track_button_changes() {
+        let idle = MouseReport::default();
+        let press = MouseReport::parse(&[0x05, 2, 0xFE, 1]).unwrap();
+        let events: Vec<_> = press.events_since(&idle).collect();
+        assert_eq!(
+            events,
+            [
+                MouseEvent::ButtonDown(MouseButton::Left),
+                MouseEvent::ButtonDown(MouseButton::Middle),
+                MouseEvent::Move { dx: 2, dy: -2 },
+                MouseEvent::Wheel { delta: 1 },
+            ]
+        );
+
+        let release = MouseReport::parse(&[0x04, 0, 0]).unwrap();
+        let events: Vec<_> = release.events_since(&press).collect();
+        assert_eq!(events, [MouseEvent::ButtonUp(MouseButton::Left)]);
+    }
+}
@@ -0,0 +1,481 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::{
+    ParseError,
+    item::{ItemType, items},
+};
+
+/// 报告类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReportKind {
+    Input,
+    Output,
+    Feature,
+}
+
+/// Input/Output/Feature 条目的数据标志（HID 6.2.2.5）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MainFlags(pub u32);
+
+impl MainFlags {
+    /// 常量字段，通常是填充位
+    pub fn is_constant(&self) -> bool {
+        self.0 & 0x01 != 0
+    }
+
+    /// 每个元素对应一个用途；否则为数组，元素值是用途表的下标
+    pub fn is_variable(&self) -> bool {
+        self.0 & 0x02 != 0
+    }
+
+    /// 相对值，如鼠标位移
+    pub fn is_relative(&self) -> bool {
+        self.0 & 0x04 != 0
+    }
+}
+
+/// 由 Input/Output/Feature 条目定义的一组字段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportField {
+    pub kind: ReportKind,
+    pub report_id: Option<u8>,
+    /// 在报告中的位偏移，不含报告 ID 字节
+    pub bit_offset: u32,
+    /// 每个元素的位数
+    pub bit_size: u32,
+    /// 元素个数
+    pub count: u32,
+    pub flags: MainFlags,
+    pub logical_min: i32,
+    pub logical_max: i32,
+    /// 扩展用途（高 16 位为用途页）。变量字段按元素对应，数组字段为下标查找表
+    pub usages: Vec<u32>,
+    /// 所在应用集合的用途，如键盘、鼠标、游戏手柄
+    pub application: u32,
+}
+
+impl ReportField {
+    /// 读取第 `index` 个元素，`data` 不含报告 ID 字节
+    ///
+    /// 逻辑最小值为负时按有符号数扩展。
+    pub fn value(&self, data: &[u8], index: u32) -> Option<i32> {
+        if index >= self.count || self.bit_size == 0 || self.bit_size > 32 {
+            return None;
+        }
+        let start = index
+            .checked_mul(self.bit_size)?
+            .checked_add(self.bit_offset)? as usize;
+        let end = start + self.bit_size as usize;
+        if end > data.len().saturating_mul(8) {
+            return None;
+        }
+        let mut raw = 0u32;
+        for bit in (start..end).rev() {
+            raw = (raw << 1) | ((data[bit / 8] >> (bit % 8)) & 1) as u32;
+        }
+        let shift = 32 - self.bit_size;
+        Some(if self.logical_min < 0 {
+            ((raw << shift) as i32) >> shift
+        } else {
+            raw as i32
+        })
+    }
+
+    /// 变量字段第 `index` 个元素的用途，用途不足时沿用最后一个
+    pub fn usage(&self, index: u32) -> Option<u32> {
+        self.usages
+            .get(index as usize)
+            .or(self.usages.last())
+            .copied()
+    }
+}
+
+/// 解码得到的一个用途及其取值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsageValue {
+    pub usage: u32,
+    pub value: i32,
+}
+
+/// 解析后的报告描述符
+#[derive(Debug, Clone, Default)]
+pub struct ReportDescriptor {
+    fields: Vec<ReportField>,
+    /// 各报告的长度（位），不含报告 ID 字节
+    report_bits: BTreeMap<(ReportKind, u8), u32>,
+    has_report_ids: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Globals {
+    usage_page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    /// 按无符号数解释的逻辑最大值
+    logical_max_unsigned: u32,
+    report_size: u32,
+    report_count: u32,
+    report_id: Option<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Locals {
+    usages: Vec<u32>,
+    usage_min: Option<u32>,
+    usage_max: Option<u32>,
+}
+
+impl Locals {
+    fn extended(globals: &Globals, item: &crate::item::Item) -> u32 {
+        // 4 字节的用途自带用途页
+        if item.size == 4 {
+            item.data
+        } else {
+            (globals.usage_page as u32) << 16 | (item.data & 0xFFFF)
+        }
+    }
+
+    fn take_usages(&mut self) -> Result<Vec<u32>, ParseError> {
+        let mut usages = core::mem::take(&mut self.usages);
+        match (self.usage_min.take(), self.usage_max.take()) {
+            (Some(min), Some(max)) if min <= max => {
+                if max - min > 0xFFFF {
+                    return Err(ParseError::InvalidUsageRange);
+                }
+                usages.extend(min..=max);
+            }
+            (None, None) => {}
+            _ => return Err(ParseError::InvalidUsageRange),
+        }
+        Ok(usages)
+    }
+}
+
+impl ReportDescriptor {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        let mut out = Self::default();
+        let mut globals = Globals::default();
+        let mut stack = Vec::new();
+        let mut locals = Locals::default();
+        // 集合栈，记录 (是否为应用集合, 用途)
+        let mut collections: Vec<(bool, u32)> = Vec::new();
+
+        for item in items(data) {
+            let item = item?;
+            match (item.ty, item.tag) {
+                (ItemType::Main, 0x8 | 0x9 | 0xB) => {
+                    let kind = match item.tag {
+                        0x8 => ReportKind::Input,
+                        0x9 => ReportKind::Output,
+                        _ => ReportKind::Feature,
+                    };
+                    let usages = locals.take_usages()?;
+                    locals = Locals::default();
+                    out.add_field(kind, &globals, item.unsigned(), usages, &collections)?;
+                }
+                (ItemType::Main, 0xA) => {
+                    let usage = locals.take_usages()?.first().copied().unwrap_or(0);
+                    locals = Locals::default();
+                    // 0x01 为应用集合
+                    collections.push((item.unsigned() == 0x01, usage));
+                }
+                (ItemType::Main, 0xC) => {
+                    collections.pop().ok_or(ParseError::UnbalancedCollection)?;
+                    locals = Locals::default();
+                }
+                (ItemType::Global, 0x0) => globals.usage_page = item.unsigned() as u16,
+                (ItemType::Global, 0x1) => globals.logical_min = item.signed(),
+                (ItemType::Global, 0x2) => {
+                    globals.logical_max = item.signed();
+                    globals.logical_max_unsigned = item.unsigned();
+                }
+                (ItemType::Global, 0x7) => globals.report_size = item.unsigned(),
+                (ItemType::Global, 0x8) => {
+                    let id = item.unsigned();
+                    if id == 0 || id > 0xFF {
+                        return Err(ParseError::InvalidReportId);
+                    }
+                    globals.report_id = Some(id as u8);
+                    out.has_report_ids = true;
+                }
+                (ItemType::Global, 0x9) => globals.report_count = item.unsigned(),
+                (ItemType::Global, 0xA) => stack.push(globals),
+                (ItemType::Global, 0xB) => {
+                    globals = stack.pop().ok_or(ParseError::UnbalancedPush)?;
+                }
+                (ItemType::Local, 0x0) => {
+                    let usage = Locals::extended(&globals, &item);
+                    locals.usages.push(usage);
+                }
+                (ItemType::Local, 0x1) => {
+                    locals.usage_min = Some(Locals::extended(&globals, &item));
+                }
+                (ItemType::Local, 0x2) => {
+                    locals.usage_max = Some(Locals::extended(&globals, &item));
+                }
+                // 物理范围、单位、字符串与指示符等不影响解码
+                _ => {}
+            }
+        }
+
+        if !collections.is_empty() {
+            return Err(ParseError::UnbalancedCollection);
+        }
+        Ok(out)
+    }
+
+    fn add_field(
+        &mut self,
+        kind: ReportKind,
+        globals: &Globals,
+        flags: u32,
+        usages: Vec<u32>,
+        collections: &[(bool, u32)],
+    ) -> Result<(), ParseError> {
+        let bits = self
+            .report_bits
+            .entry((kind, globals.report_id.unwrap_or(0)))
+            .or_default();
+        let bit_offset = *bits;
+        // 大小与个数均来自设备，畸形描述符可能使报告长度溢出
+        *bits = globals
+            .report_size
+            .checked_mul(globals.report_count)
+            .and_then(|size| size.checked_add(bit_offset))
+            .ok_or(ParseError::ReportTooLong)?;
+
+        // 逻辑最小值非负时，最大值按无符号解释（如 1 字节编码的 255）
+        let logical_max = if globals.logical_min >= 0 {
+            globals.logical_max_unsigned.min(i32::MAX as u32) as i32
+        } else {
+            globals.logical_max
+        };
+
+        let application = collections
+            .iter()
+            .find(|(application, _)| *application)
+            .map_or(0, |&(_, usage)| usage);
+
+        self.fields.push(ReportField {
+            kind,
+            report_id: globals.report_id,
+            bit_offset,
+            bit_size: globals.report_size,
+            count: globals.report_count,
+            flags: MainFlags(flags),
+            logical_min: globals.logical_min,
+            logical_max,
+            usages,
+            application,
+        });
+        Ok(())
+    }
+
+    pub fn fields(&self) -> &[ReportField] {
+        &self.fields
+    }
+
+    /// 报告以 1 字节的报告 ID 开头
+    pub fn has_report_ids(&self) -> bool {
+        self.has_report_ids
+    }
+
+    /// 报告的字节数，包括报告 ID 字节
+    pub fn report_len(&self, kind: ReportKind, report_id: Option<u8>) -> usize {
+        let bits = self
+            .report_bits
+            .get(&(kind, report_id.unwrap_or(0)))
+            .copied()
+            .unwrap_or(0);
+        bits.div_ceil(8) as usize + self.has_report_ids as usize
+    }
+
+    /// 最长 Input 报告的字节数，可用作中断 IN 传输的缓冲区大小
+    pub fn max_input_len(&self) -> usize {
+        self.report_bits
+            .iter()
+            .filter(|((kind, _), _)| *kind == ReportKind::Input)
+            .map(|(_, bits)| bits.div_ceil(8) as usize + self.has_report_ids as usize)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 解码一份 Input 报告，跳过常量字段
+    ///
+    /// 变量字段每个元素产生一个用途与取值；数组字段只为非空元素产生对应用途，取值为 1，
+    /// 如键盘报告中按下的各键。
+    pub fn decode(&self, report: &[u8]) -> Vec<UsageValue> {
+        let (report_id, data) = match (self.has_report_ids, report.split_first()) {
+            (true, Some((&id, data))) => (Some(id), data),
+            (true, None) => return Vec::new(),
+            (false, _) => (None, report),
+        };
+
+        let mut out = Vec::new();
+        for field in self.fields.iter().filter(|f| {
+            f.kind == ReportKind::Input && f.report_id == report_id && !f.flags.is_constant()
+        }) {
+            for index in 0..field.count {
+                let Some(value) = field.value(data, index) else {
+                    break;
+                };
+                if field.flags.is_variable() {
+                    if let Some(usage) = field.usage(index) {
+                        out.push(UsageValue { usage, value });
+                    }
+                } else if (field.logical_min..=field.logical_max).contains(&value)
+                    && let Some(&usage) = field.usages.get((value - field.logical_min) as usize)
+                    && usage & 0xFFFF != 0
+                {
+                    out.push(UsageValue { usage, value: 1 });
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{usage, usage_page};
+
+    /// HID 规范附录 E.6 的引导键盘报告描述符
+    const BOOT_KEYBOARD: &[u8] = &[
+        0x05, 0x01, 0x09, 0x06, 0xA1, 0x01, 0x05, 0x07, 0x19, 0xE0, 0x29, 0xE7, 0x15, 0x00, 0x25,
+        0x01, 0x75, 0x01, 0x95, 0x08, 0x81, 0x02, 0x95, 0x01, 0x75, 0x08, 0x81, 0x01, 0x95, 0x05,
+        0x75, 0x01, 0x05, 0x08, 0x19, 0x01, 0x29, 0x05, 0x91, 0x02, 0x95, 0x01, 0x75, 0x03, 0x91,
+        0x01, 0x95, 0x06, 0x75, 0x08, 0x15, 0x00, 0x25, 0x65, 0x05, 0x07, 0x19, 0x00, 0x29, 0x65,
+        0x81, 0x00, 0xC0,
+    ];
+
+    /// 带报告 ID 的复合设备：ID 1 为鼠标，ID 2 为消费类控制
+    const COMPOSITE: &[u8] = &[
+        0x05, 0x01, 0x09, 0x02, 0xA1, 0x01, 0x85, 0x01, 0x09, 0x01, 0xA1, 0x00, 0x05, 0x09, 0x19,
+        0x01, 0x29, 0x03, 0x15, 0x00, 0x25, 0x01, 0x95, 0x03, 0x75, 0x01, 0x81, 0x02, 0x95, 0x01,
+        0x75, 0x05, 0x81, 0x03, 0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x15, 0x81, 0x25, 0x7F, 0x75,
+        0x08, 0x95, 0x02, 0x81, 0x06, 0xC0, 0xC0, 0x05, 0x0C, 0x09, 0x01, 0xA1, 0x01, 0x85, 0x02,
+        0x15, 0x00, 0x26, 0xFF, 0x03, 0x19, 0x00, 0x2A, 0xFF, 0x03, 0x75, 0x10, 0x95, 0x01, 0x81,
+        0x00, 0xC0,
+    ];
+
+    #[test]
+    fn boot_keyboard_layout() {
+        let desc = ReportDescriptor::parse(BOOT_KEYBOARD).unwrap();
+        assert!(!desc.has_report_ids());
+        assert_eq!(desc.report_len(ReportKind::Input, None), 8);
+        assert_eq!(desc.report_len(ReportKind::Output, None), 1);
+
+        let keys = desc.fields().last().unwrap();
+        assert_eq!((keys.bit_offset, keys.bit_size, keys.count), (16, 8, 6));
+        assert_eq!(keys.application, usage(usage_page::GENERIC_DESKTOP, 0x06));
+
+        // 左 Shift + A
+        let values = desc.decode(&[0x02, 0, 0x04, 0, 0, 0, 0, 0]);
+        assert!(values.contains(&UsageValue {
+            usage: usage(usage_page::KEYBOARD, 0xE1),
+            value: 1,
+        }));
+        assert!(values.contains(&UsageValue {
+            usage: usage(usage_page::KEYBOARD, 0x04),
+            value: 1,
+        }));
+        assert!(!values.contains(&UsageValue {
+            usage: usage(usage_page::KEYBOARD, 0xE0),
+            value: 1,
+        }));
+    }
+
+    #[test]
+    fn composite_with_report_ids() {
+        let desc = ReportDescriptor::parse(COMPOSITE).unwrap();
+        assert!(desc.has_report_ids());
+        assert_eq!(desc.report_len(ReportKind::Input, Some(1)), 4);
+        assert_eq!(desc.max_input_len(), 4);
+
+        let values = desc.decode(&[1, 0x01, 0xFB, 0x03]);
+        assert_eq!(
+            values,
+            [
+                UsageValue {
+                    usage: usage(usage_page::BUTTON, 1),
+                    value: 1
+                },
+                UsageValue {
+                    usage: usage(usage_page::BUTTON, 2),
+                    value: 0
+                },
+                UsageValue {
+                    usage: usage(usage_page::BUTTON, 3),
+                    value: 0
+                },
+                UsageValue {
+                    usage: usage(usage_page::GENERIC_DESKTOP, 0x30),
+                    value: -5
+                },
+                UsageValue {
+                    usage: usage(usage_page::GENERIC_DESKTOP, 0x31),
+                    value: 3
+                },
+            ]
+        );
+
+        // 音量加（0xE9），16 位数组字段
+        let values = desc.decode(&[2, 0xE9, 0x00]);
+        assert_eq!(
+            values,
+            [UsageValue {
+                usage: usage(usage_page::CONSUMER, 0xE9),
+                value: 1
+            }]
+        );
+        let consumer = desc.fields().last().unwrap();
+        assert_eq!(consumer.logical_max, 0x3FF);
+        assert_eq!(consumer.application, usage(usage_page::CONSUMER, 0x01));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        assert_eq!(
+            ReportDescriptor::parse(&[0xA1, 0x01]).unwrap_err(),
+            ParseError::UnbalancedCollection
+        );
+        assert_eq!(
+            ReportDescriptor::parse(&[0x26, 0xFF]).unwrap_err(),
+            ParseError::Truncated
+        );
+        assert_eq!(
+            ReportDescriptor::parse(&[0xB4]).unwrap_err(),
+            ParseError::UnbalancedPush
+        );
+        // Report Size 0x10000 × Report Count 0x10000
+        assert_eq!(
+            ReportDescriptor::parse(&[0x77, 0, 0, 1, 0, 0x97, 0, 0, 1, 0, 0x81, 0x02]).unwrap_err(),
+            ParseError::ReportTooLong
+        );
+        // 两个字段的总长超出 u32
+        assert_eq!(
+            ReportDescriptor::parse(&[0x75, 0x20, 0x97, 0, 0, 0, 0x04, 0x81, 0x02, 0x81, 0x02])
+                .unwrap_err(),
+            ParseError::ReportTooLong
+        );
+    }
+
+    #[test]
+    fn value_rejects_out_of_range_offset() {
+        let field = ReportField {
+            kind: ReportKind::Input,
+            report_id: None,
+            bit_offset: u32::MAX - 4,
+            bit_size: 8,
+            count: u32::MAX,
+            flags: MainFlags(0x02),
+            logical_min: 0,
+            logical_max: 0xFF,
+            usages: Vec::new(),
+            application: 0,
+        };
+        assert_eq!(field.value(&[0; 4], 0), None);
+        assert_eq!(field.value(&[0; 4], 0x2000_0000), None);
+    }
+}
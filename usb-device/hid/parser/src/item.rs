@@ -0,0 +1,86 @@
+//! 报告描述符条目（HID 6.2.2）
+
+use crate::ParseError;
+
+/// 条目类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemType {
+    Main,
+    Global,
+    Local,
+}
+
+/// 短条目，数据按小端读取
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Item {
+    pub ty: ItemType,
+    pub tag: u8,
+    /// 条目数据，长度 0、1、2、4 字节
+    pub data: u32,
+    pub size: u8,
+}
+
+impl Item {
+    pub fn unsigned(&self) -> u32 {
+        self.data
+    }
+
+    /// 按数据长度符号扩展
+    pub fn signed(&self) -> i32 {
+        match self.size {
+            1 => self.data as u8 as i8 as i32,
+            2 => self.data as u16 as i16 as i32,
+            _ => self.data as i32,
+        }
+    }
+}
+
+/// 逐个读取短条目，跳过长条目
+pub fn items(mut data: &[u8]) -> impl Iterator<Item = Result<Item, ParseError>> + '_ {
+    core::iter::from_fn(move || {
+        loop {
+            let (&prefix, rest) = data.split_first()?;
+            // 长条目：0xFE、bDataSize、bLongItemTag 与数据
+            if prefix == 0xFE {
+                let Some(&len) = rest.first() else {
+                    data = &[];
+                    return Some(Err(ParseError::Truncated));
+                };
+                let skip = 2 + len as usize;
+                if rest.len() < skip {
+                    data = &[];
+                    return Some(Err(ParseError::Truncated));
+                }
+                data = &rest[skip..];
+                continue;
+            }
+
+            let size = match prefix & 0x03 {
+                3 => 4,
+                n => n as usize,
+            };
+            if rest.len() < size {
+                data = &[];
+                return Some(Err(ParseError::Truncated));
+            }
+            let value = rest[..size]
+                .iter()
+                .rev()
+                .fold(0u32, |acc, &b| (acc << 8) | b as u32);
+            data = &rest[size..];
+
+            let ty = match (prefix >> 2) & 0x03 {
+                0 => ItemType::Main,
+                1 => ItemType::Global,
+                2 => ItemType::Local,
+                _ => return Some(Err(ParseError::ReservedItem(prefix))),
+            };
+            return Some(Ok(Item {
+                ty,
+                tag: prefix >> 4,
+                data: value,
+                size: size as u8,
+            }));
+        }
+    })
+}
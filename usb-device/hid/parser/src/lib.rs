@@ -0,0 +1,148 @@
+//! HID 报告描述符解析
+//!
+//! 读取接口的报告描述符，解析出各报告的字段（用途、报告 ID、位偏移），
+//! 用于解码非引导协议的键盘、游戏手柄以及复合 HID 设备的报告。
+
+#![no_std]
+
+extern crate alloc;
+use alloc::{vec, vec::Vec};
+
+use crab_usb::{Device, err::USBError};
+use log::debug;
+use usb_if::{
+    descriptor::ConfigurationDescriptor,
+    host::ControlSetup,
+    transfer::{Recipient, Request, RequestType},
+};
+
+mod descriptor;
+mod item;
+
+pub use descriptor::{MainFlags, ReportDescriptor, ReportField, ReportKind, UsageValue};
+
+/// HID 类描述符类型
+pub const HID_DESCRIPTOR: u8 = 0x21;
+/// 报告描述符类型
+pub const REPORT_DESCRIPTOR: u8 = 0x22;
+
+/// 常用用途页（HID Usage Tables）
+pub mod usage_page {
+    pub const GENERIC_DESKTOP: u16 = 0x01;
+    pub const KEYBOARD: u16 = 0x07;
+    pub const LED: u16 = 0x08;
+    pub const BUTTON: u16 = 0x09;
+    pub const CONSUMER: u16 = 0x0C;
+}
+
+/// 组合用途页与用途编号为扩展用途
+pub const fn usage(page: u16, id: u16) -> u32 {
+    (page as u32) << 16 | id as u32
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("Truncated item")]
+    Truncated,
+    #[error("Reserved item type: {0:#04x}")]
+    ReservedItem(u8),
+    #[error("Unbalanced collection")]
+    UnbalancedCollection,
+    #[error("Pop without push")]
+    UnbalancedPush,
+    #[error("Invalid report id")]
+    InvalidReportId,
+    #[error("Invalid usage range")]
+    InvalidUsageRange,
+    #[error("Report too long")]
+    ReportTooLong,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("USB error: {0}")]
+    Usb(#[from] USBError),
+    #[error("Parse error: {0}")]
+    Parse(#[from] ParseError),
+}
+
+/// 从配置描述符中查找接口的 HID 描述符，返回报告描述符的长度
+pub fn report_descriptor_len(config: &ConfigurationDescriptor, interface: u8) -> Option<u16> {
+    let mut data = config.raw.as_slice();
+    let mut in_interface = false;
+    while data.len() >= 2 {
+        let len = data[0] as usize;
+        if len < 2 || len > data.len() {
+            break;
+        }
+        let desc = &data[..len];
+        match desc[1] {
+            // 接口描述符，只看备用设置 0
+            0x04 if len >= 4 => in_interface = desc[2] == interface && desc[3] == 0,
+            HID_DESCRIPTOR if in_interface && len >= 9 => {
+                // bNumDescriptors 之后依次为 (bDescriptorType, wDescriptorLength)
+                return desc[6..]
+                    .chunks_exact(3)
+                    .find(|d| d[0] == REPORT_DESCRIPTOR)
+                    .map(|d| u16::from_le_bytes([d[1], d[2]]));
+            }
+            _ => {}
+        }
+        data = &data[len..];
+    }
+    None
+}
+
+/// 读取接口 `interface` 的原始报告描述符
+///
+/// 长度取自配置描述符中的 HID 描述符，找不到时返回 [`USBError::NotFound`]。
+pub async fn fetch_report_descriptor(
+    device: &mut Device,
+    interface: u8,
+) -> Result<Vec<u8>, USBError> {
+    let len = device
+        .configurations()
+        .iter()
+        .find_map(|config| report_descriptor_len(config, interface))
+        .ok_or(USBError::NotFound)?;
+
+    let mut data = vec![0u8; len as usize];
+    let n = device
+        .control_in(
+            ControlSetup {
+                request_type: RequestType::Standard,
+                recipient: Recipient::Interface,
+                request: Request::GetDescriptor,
+                value: (REPORT_DESCRIPTOR as u16) << 8,
+                index: interface as u16,
+            },
+            &mut data,
+        )
+        .await?;
+    data.truncate(n);
+    debug!("Report descriptor of interface {interface}: {n} bytes");
+    Ok(data)
+}
+
+/// 读取并解析接口 `interface` 的报告描述符
+pub async fn read_report_descriptor(
+    device: &mut Device,
+    interface: u8,
+) -> Result<ReportDescriptor, Error> {
+    let data = fetch_report_descriptor(device, interface).await?;
+    Ok(ReportDescriptor::parse(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crab_usb::fixtures::DeviceFixture;
+
+    use super::*;
+
+    #[test]
+    fn finds_report_descriptor_len() {
+        let config = &DeviceFixture::hid_keyboard().configuration_descriptors()[0];
+        assert_eq!(report_descriptor_len(config, 0), Some(63));
+        assert_eq!(report_descriptor_len(config, 1), None);
+    }
+}
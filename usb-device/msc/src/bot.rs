@@ -0,0 +1,88 @@
+//! Bulk-Only Transport 封装（USB Mass Storage Class Bulk-Only Transport 1.0）
+//!
+//! 每条命令由三个阶段组成：主机发送 31 字节的 CBW，可选的数据阶段，
+//! 设备返回 13 字节的 CSW。
+
+pub const CBW_SIGNATURE: u32 = 0x4342_5355;
+pub const CSW_SIGNATURE: u32 = 0x5342_5355;
+pub const CBW_LEN: usize = 31;
+pub const CSW_LEN: usize = 13;
+
+/// 数据阶段方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataDirection {
+    None,
+    In,
+    Out,
+}
+
+/// Command Block Wrapper
+#[derive(Debug, Clone)]
+pub struct CommandBlockWrapper<'a> {
+    pub tag: u32,
+    pub data_length: u32,
+    pub direction: DataDirection,
+    pub lun: u8,
+    /// SCSI 命令块，长度 1..=16
+    pub command: &'a [u8],
+}
+
+impl CommandBlockWrapper<'_> {
+    pub fn to_bytes(&self) -> [u8; CBW_LEN] {
+        let mut buf = [0u8; CBW_LEN];
+        buf[0..4].copy_from_slice(&CBW_SIGNATURE.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.tag.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.data_length.to_le_bytes());
+        buf[12] = match self.direction {
+            DataDirection::In => 0x80,
+            _ => 0,
+        };
+        buf[13] = self.lun & 0x0f;
+        let len = self.command.len().min(16);
+        buf[14] = len as u8;
+        buf[15..15 + len].copy_from_slice(&self.command[..len]);
+        buf
+    }
+}
+
+/// CSW 中的命令状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStatus {
+    Passed,
+    /// 命令失败，原因需通过 REQUEST SENSE 获取
+    Failed,
+    /// 主机与设备对数据阶段理解不一致，需要复位恢复
+    PhaseError,
+}
+
+/// Command Status Wrapper
+#[derive(Debug, Clone, Copy)]
+pub struct CommandStatusWrapper {
+    pub tag: u32,
+    /// 期望长度与实际处理长度之差
+    pub data_residue: u32,
+    pub status: CommandStatus,
+}
+
+impl CommandStatusWrapper {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < CSW_LEN {
+            return None;
+        }
+        let signature = u32::from_le_bytes(data[0..4].try_into().ok()?);
+        if signature != CSW_SIGNATURE {
+            return None;
+        }
+        let status = match data[12] {
+            0 => CommandStatus::Passed,
+            1 => CommandStatus::Failed,
+            2 => CommandStatus::PhaseError,
+            _ => return None,
+        };
+        Some(Self {
+            tag: u32::from_le_bytes(data[4..8].try_into().ok()?),
+            data_residue: u32::from_le_bytes(data[8..12].try_into().ok()?),
+            status,
+        })
+    }
+}
@@ -0,0 +1,602 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec;
+use anyhow::anyhow;
+use crab_usb::{Device, DeviceInfo, Endpoint, err::USBError};
+use log::*;
+use usb_if::{
+    descriptor::{Class, EndpointType, InterfaceDescriptor},
+    endpoint::TransferRequest,
+    err::TransferError,
+    host::ControlSetup,
+    transfer::{Direction, Recipient, Request, RequestType},
+};
+
+pub mod bot;
+pub mod scsi;
+
+use bot::{CSW_LEN, CommandBlockWrapper, CommandStatus, CommandStatusWrapper, DataDirection};
+pub use scsi::{Capacity, Inquiry, MediaType, ModeParameters, SenseData, Toc, TocEntry};
+
+/// SCSI transparent command set
+const SUBCLASS_SCSI: u8 = 0x06;
+/// MMC-5（ATAPI），部分 USB 光驱使用
+const SUBCLASS_MMC: u8 = 0x02;
+/// Bulk-Only Transport
+const PROTOCOL_BOT: u8 = 0x50;
+/// 单次 bulk 传输的最大长度，大于它的数据阶段拆分为多次传输
+const MAX_TRANSFER: usize = 64 * 1024;
+/// 设备上电或复位后会先报告 UNIT ATTENTION，需重试 TEST UNIT READY
+const READY_RETRIES: usize = 5;
+/// BOT 类请求：Get Max LUN
+const GET_MAX_LUN: u8 = 0xfe;
+/// BOT 类请求：Bulk-Only Mass Storage Reset
+const BULK_ONLY_RESET: u8 = 0xff;
+/// READ TOC 缓冲区：4 字节头部加最多 100 个轨道描述符
+const TOC_LEN: usize = 4 + 100 * 8;
+
+/// 写缓存策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritePolicy {
+    /// 写入可停留在设备缓存中，调用 [`MassStorage::flush`] 后才保证落盘
+    #[default]
+    WriteBack,
+    /// 每次写入完成即落盘：设备支持 FUA 时置 FUA，否则写后立即同步缓存
+    WriteThrough,
+}
+
+/// 数据阶段缓冲区
+enum Data<'a> {
+    None,
+    In(&'a mut [u8]),
+    Out(&'a [u8]),
+}
+
+/// USB 大容量存储设备（SCSI over Bulk-Only Transport）
+///
+/// 默认使用 LUN 0，可通过 [`MassStorage::select_lun`] 切换。CD/DVD 设备
+/// （包括 IODD、Ventoy 等模拟光驱的 U 盘）按 2048 字节扇区只读访问。
+pub struct MassStorage {
+    device: Device,
+    interface: u8,
+    bulk_in: Endpoint,
+    bulk_out: Endpoint,
+    lun: u8,
+    max_lun: u8,
+    tag: u32,
+    inquiry: Inquiry,
+    media: MediaType,
+    capacity: Capacity,
+    mode: ModeParameters,
+    policy: WritePolicy,
+    /// 上次同步缓存后是否有 write-back 写入
+    dirty: bool,
+}
+
+impl MassStorage {
+    /// 检查设备是否为 BOT 大容量存储设备
+    pub fn check(info: &DeviceInfo) -> bool {
+        info.interface_descriptors().any(is_bot)
+    }
+
+    pub async fn new(mut device: Device) -> Result<Self, USBError> {
+        let (interface, alternate, ep_in, ep_out) = {
+            let config = device.configurations().first().ok_or(USBError::NotFound)?;
+            config
+                .interfaces
+                .iter()
+                .map(|iface| iface.first_alt_setting())
+                .find_map(|alt| {
+                    if !is_bot(&alt) {
+                        return None;
+                    }
+                    let find = |dir: Direction| {
+                        alt.endpoints
+                            .iter()
+                            .find(|ep| {
+                                ep.transfer_type == EndpointType::Bulk && ep.direction == dir
+                            })
+                            .map(|ep| ep.address)
+                    };
+                    Some((
+                        alt.interface_number,
+                        alt.alternate_setting,
+                        find(Direction::In)?,
+                        find(Direction::Out)?,
+                    ))
+                })
+                .ok_or(USBError::NotFound)?
+        };
+        debug!("MSC interface {interface}, bulk in {ep_in:#x}, bulk out {ep_out:#x}");
+
+        device.claim_interface(interface, alternate).await?;
+        let bulk_in = device.endpoint(ep_in)?;
+        let bulk_out = device.endpoint(ep_out)?;
+
+        let mut msc = Self {
+            device,
+            interface,
+            bulk_in,
+            bulk_out,
+            lun: 0,
+            max_lun: 0,
+            tag: 0,
+            inquiry: Inquiry {
+                device_type: scsi::PERIPHERAL_DIRECT_ACCESS,
+                removable: false,
+            },
+            media: MediaType::Disk,
+            capacity: Capacity {
+                block_count: 0,
+                block_size: 0,
+            },
+            mode: ModeParameters::default(),
+            policy: WritePolicy::default(),
+            dirty: false,
+        };
+
+        msc.max_lun = msc.get_max_lun().await?;
+        msc.probe().await?;
+        Ok(msc)
+    }
+
+    pub fn capacity(&self) -> Capacity {
+        self.capacity
+    }
+
+    /// 介质是否写保护（MODE SENSE 报告的 WP 位），光盘介质总是写保护
+    pub fn is_write_protected(&self) -> bool {
+        self.mode.write_protected
+    }
+
+    /// 当前 LUN 的 INQUIRY 数据
+    pub fn inquiry(&self) -> Inquiry {
+        self.inquiry
+    }
+
+    /// 当前 LUN 的介质类型
+    pub fn media_type(&self) -> MediaType {
+        self.media
+    }
+
+    /// 设备的最大 LUN 编号
+    pub fn max_lun(&self) -> u8 {
+        self.max_lun
+    }
+
+    pub fn lun(&self) -> u8 {
+        self.lun
+    }
+
+    /// 切换到另一个 LUN 并重新探测介质
+    ///
+    /// IODD、Ventoy 等设备把模拟光驱放在单独的 LUN 上。切换前会同步当前 LUN 的写缓存。
+    pub async fn select_lun(&mut self, lun: u8) -> Result<(), USBError> {
+        if lun > self.max_lun {
+            return Err(USBError::InvalidParameter);
+        }
+        self.flush().await?;
+        self.lun = lun;
+        self.probe().await
+    }
+
+    /// 读取光盘的 TOC（READ TOC 格式 0），非 CD/DVD 设备返回 [`USBError::NotSupported`]
+    pub async fn read_toc(&mut self) -> Result<Toc, USBError> {
+        if !self.inquiry.is_mmc() {
+            return Err(USBError::NotSupported);
+        }
+        let mut buf = vec![0u8; TOC_LEN];
+        let len = self
+            .command(scsi::read_toc(TOC_LEN as _), Data::In(&mut buf))
+            .await?;
+        Ok(Toc::parse(&buf[..len]).ok_or(anyhow!("invalid TOC"))?)
+    }
+
+    pub fn write_policy(&self) -> WritePolicy {
+        self.policy
+    }
+
+    /// 切换写缓存策略，从 write-back 切换到 write-through 时先同步已缓存的写入
+    pub async fn set_write_policy(&mut self, policy: WritePolicy) -> Result<(), USBError> {
+        if policy == WritePolicy::WriteThrough {
+            self.flush().await?;
+        }
+        self.policy = policy;
+        Ok(())
+    }
+
+    /// 读取从 `lba` 开始的块，`buf` 长度须为块大小的整数倍
+    pub async fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), USBError> {
+        let block_size = self.check_range(lba, buf.len())?;
+        let blocks_per_cmd = (MAX_TRANSFER / block_size).max(1);
+
+        for (i, chunk) in buf.chunks_mut(blocks_per_cmd * block_size).enumerate() {
+            let lba = lba + (i * blocks_per_cmd) as u64;
+            let blocks = (chunk.len() / block_size) as u32;
+            self.command(scsi::read(lba, blocks), Data::In(chunk))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// 写入从 `lba` 开始的块，`buf` 长度须为块大小的整数倍
+    ///
+    /// 写保护介质返回 [`USBError::NotSupported`]。
+    pub async fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<(), USBError> {
+        if self.mode.write_protected {
+            return Err(USBError::NotSupported);
+        }
+        let block_size = self.check_range(lba, buf.len())?;
+        let blocks_per_cmd = (MAX_TRANSFER / block_size).max(1);
+        let fua = self.policy == WritePolicy::WriteThrough && self.mode.dpofua;
+
+        for (i, chunk) in buf.chunks(blocks_per_cmd * block_size).enumerate() {
+            let lba = lba + (i * blocks_per_cmd) as u64;
+            let blocks = (chunk.len() / block_size) as u32;
+            self.command(scsi::write(lba, blocks, fua), Data::Out(chunk))
+                .await?;
+        }
+
+        self.dirty = true;
+        if self.policy == WritePolicy::WriteThrough && !fua {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// 同步设备写缓存（SYNCHRONIZE CACHE），返回后之前的写入均已落盘
+    ///
+    /// 没有写缓存的设备可能以 ILLEGAL REQUEST 拒绝该命令，此时视为成功。
+    pub async fn flush(&mut self) -> Result<(), USBError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let long_lba = self.capacity.block_count > u32::MAX as u64;
+        match self
+            .command(scsi::synchronize_cache(long_lba), Data::None)
+            .await
+        {
+            Ok(_) => {}
+            Err(CommandError::Sense(sense)) if sense.key == scsi::sense_key::ILLEGAL_REQUEST => {
+                debug!("SYNCHRONIZE CACHE not supported: {sense}");
+            }
+            Err(e) => return Err(e.into()),
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn check_range(&self, lba: u64, len: usize) -> Result<usize, USBError> {
+        let block_size = self.capacity.block_size as usize;
+        if block_size == 0 || !len.is_multiple_of(block_size) {
+            return Err(USBError::InvalidParameter);
+        }
+        let end = lba
+            .checked_add((len / block_size) as u64)
+            .ok_or(USBError::InvalidParameter)?;
+        if end > self.capacity.block_count {
+            return Err(USBError::InvalidParameter);
+        }
+        Ok(block_size)
+    }
+
+    /// 探测当前 LUN：设备类型、介质类型、容量与写保护
+    async fn probe(&mut self) -> Result<(), USBError> {
+        self.inquiry = self.read_inquiry().await?;
+        self.capacity = Capacity {
+            block_count: 0,
+            block_size: 0,
+        };
+        self.mode = ModeParameters::default();
+
+        match self.wait_ready().await {
+            Ok(()) => {}
+            // 光驱中没有光盘时仍可使用，插入光盘后重新 select_lun
+            Err(CommandError::Sense(sense))
+                if self.inquiry.is_mmc() && sense.asc == scsi::ASC_MEDIUM_NOT_PRESENT =>
+            {
+                info!("MSC LUN {}: no medium", self.lun);
+                self.media = MediaType::NoMedia;
+                return Ok(());
+            }
+            Err(CommandError::Sense(sense)) => {
+                return Err(anyhow!("unit not ready: {sense}").into());
+            }
+            Err(CommandError::Usb(e)) => return Err(e),
+        }
+
+        self.capacity = self.read_capacity().await?;
+        if self.inquiry.is_mmc() {
+            self.media = self.current_profile().await;
+            // 光驱可能按原始扇区（2352 字节等）报告块大小，READ(10) 始终以 2048 字节寻址
+            if self.capacity.block_size != scsi::CD_SECTOR_SIZE {
+                debug!(
+                    "optical block size {} reported, use {}",
+                    self.capacity.block_size,
+                    scsi::CD_SECTOR_SIZE
+                );
+                self.capacity.block_size = scsi::CD_SECTOR_SIZE;
+            }
+            self.mode.write_protected = true;
+        } else {
+            self.media = MediaType::Disk;
+            self.mode = self.mode_sense().await;
+        }
+        info!(
+            "MSC LUN {} {:?}: {} blocks x {} bytes, {:?}",
+            self.lun, self.media, self.capacity.block_count, self.capacity.block_size, self.mode
+        );
+        Ok(())
+    }
+
+    /// Get Max LUN；不支持多 LUN 的设备可以 STALL 该请求
+    async fn get_max_lun(&mut self) -> Result<u8, USBError> {
+        let mut buf = [0u8; 1];
+        let setup = ControlSetup {
+            request_type: RequestType::Class,
+            recipient: Recipient::Interface,
+            request: Request::Class(GET_MAX_LUN),
+            value: 0,
+            index: self.interface as u16,
+        };
+        match self.device.control_in(setup, &mut buf).await {
+            Ok(1) => Ok(buf[0].min(15)),
+            Ok(_) => Ok(0),
+            Err(e) if e.is_stall() => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn read_inquiry(&mut self) -> Result<Inquiry, USBError> {
+        let mut buf = [0u8; 36];
+        let len = self
+            .command(scsi::inquiry(buf.len() as _), Data::In(&mut buf))
+            .await?;
+        Ok(Inquiry::parse(&buf[..len]).ok_or(anyhow!("invalid INQUIRY data"))?)
+    }
+
+    /// 读取 MMC 当前配置文件；不支持 GET CONFIGURATION 的老光驱按 CD 处理
+    async fn current_profile(&mut self) -> MediaType {
+        let mut buf = [0u8; 8];
+        match self
+            .command(scsi::get_configuration(buf.len() as _), Data::In(&mut buf))
+            .await
+        {
+            Ok(len) => MediaType::parse_configuration(&buf[..len]).unwrap_or(MediaType::Cd),
+            Err(e) => {
+                debug!("GET CONFIGURATION failed, assume CD: {e:?}");
+                MediaType::Cd
+            }
+        }
+    }
+
+    /// 等待设备就绪，失败时返回最后一次的 Sense 数据；介质不存在时不再重试
+    async fn wait_ready(&mut self) -> Result<(), CommandError> {
+        let mut last = None;
+        for _ in 0..READY_RETRIES {
+            match self.command(scsi::test_unit_ready(), Data::None).await {
+                Ok(_) => return Ok(()),
+                Err(CommandError::Sense(sense))
+                    if matches!(
+                        sense.key,
+                        scsi::sense_key::UNIT_ATTENTION | scsi::sense_key::NOT_READY
+                    ) =>
+                {
+                    debug!("TEST UNIT READY: {sense}");
+                    last = Some(sense);
+                    if sense.asc == scsi::ASC_MEDIUM_NOT_PRESENT {
+                        break;
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(CommandError::Sense(last.unwrap()))
+    }
+
+    async fn read_capacity(&mut self) -> Result<Capacity, USBError> {
+        let mut buf = [0u8; 8];
+        self.command(scsi::read_capacity_10(), Data::In(&mut buf))
+            .await?;
+        let capacity = Capacity::parse_10(&buf).ok_or(anyhow!("invalid READ CAPACITY(10)"))?;
+        if capacity.block_count <= u32::MAX as u64 {
+            return Ok(capacity);
+        }
+
+        let mut buf = [0u8; 32];
+        self.command(scsi::read_capacity_16(buf.len() as _), Data::In(&mut buf))
+            .await?;
+        Ok(Capacity::parse_16(&buf).ok_or(anyhow!("invalid READ CAPACITY(16)"))?)
+    }
+
+    /// 读取模式参数头；部分设备不支持 MODE SENSE，此时按可写处理
+    async fn mode_sense(&mut self) -> ModeParameters {
+        let mut buf = [0u8; 192];
+        match self
+            .command(scsi::mode_sense_6(0x3f, buf.len() as _), Data::In(&mut buf))
+            .await
+        {
+            Ok(len) => ModeParameters::parse_6(&buf[..len]).unwrap_or_default(),
+            Err(e) => {
+                warn!("MODE SENSE failed, assume writable: {e:?}");
+                ModeParameters::default()
+            }
+        }
+    }
+
+    async fn request_sense(&mut self) -> Result<SenseData, USBError> {
+        let mut buf = [0u8; 18];
+        let len = self
+            .transport(scsi::request_sense(buf.len() as _), Data::In(&mut buf))
+            .await?;
+        if len != 0 {
+            // REQUEST SENSE 自身失败时无法再获取原因
+            return Err(anyhow!("REQUEST SENSE failed").into());
+        }
+        SenseData::parse(&buf).ok_or(anyhow!("invalid sense data").into())
+    }
+
+    /// 执行命令，失败时读取 Sense 数据，返回数据阶段实际传输的字节数
+    async fn command(&mut self, cdb: scsi::Cdb, data: Data<'_>) -> Result<usize, CommandError> {
+        let expected = match &data {
+            Data::None => 0,
+            Data::In(buf) => buf.len(),
+            Data::Out(buf) => buf.len(),
+        };
+        let residue = self.transport(cdb, data).await?;
+        if residue == u32::MAX as usize {
+            let sense = self.request_sense().await?;
+            debug!("SCSI command {:#04x} failed: {sense}", cdb.opcode());
+            return Err(CommandError::Sense(sense));
+        }
+        Ok(expected.saturating_sub(residue))
+    }
+
+    /// 完成一次 BOT 事务，返回 CSW 中的 residue；命令失败时返回 `u32::MAX`
+    ///
+    /// CSW 无效、相位错误或传输出错时执行 Reset Recovery（BOT 规范 5.3.4），
+    /// 使设备可以接收下一个 CBW，再返回原来的错误。
+    async fn transport(&mut self, cdb: scsi::Cdb, data: Data<'_>) -> Result<usize, USBError> {
+        let err = match self.transaction(cdb, data).await {
+            Ok(residue) => return Ok(residue),
+            Err(USBError::TransferError(TransferError::NoDevice)) => {
+                return Err(TransferError::NoDevice.into());
+            }
+            Err(e) => e,
+        };
+        warn!(
+            "BOT command {:#04x} failed, reset recovery: {err:?}",
+            cdb.opcode()
+        );
+        if let Err(e) = self.reset_recovery().await {
+            warn!("BOT reset recovery failed: {e:?}");
+        }
+        Err(err)
+    }
+
+    /// Reset Recovery：Bulk-Only Mass Storage Reset 后清除两个批量端点的 Halt
+    async fn reset_recovery(&mut self) -> Result<(), USBError> {
+        let setup = ControlSetup {
+            request_type: RequestType::Class,
+            recipient: Recipient::Interface,
+            request: Request::Class(BULK_ONLY_RESET),
+            value: 0,
+            index: self.interface as u16,
+        };
+        self.device.control_out(setup, &[]).await?;
+        self.bulk_in.clear_halt(&mut self.device).await?;
+        self.bulk_out.clear_halt(&mut self.device).await?;
+        Ok(())
+    }
+
+    async fn transaction(&mut self, cdb: scsi::Cdb, data: Data<'_>) -> Result<usize, USBError> {
+        self.tag = self.tag.wrapping_add(1);
+        let (direction, len) = match &data {
+            Data::None => (DataDirection::None, 0),
+            Data::In(buf) => (DataDirection::In, buf.len()),
+            Data::Out(buf) => (DataDirection::Out, buf.len()),
+        };
+        let cbw = CommandBlockWrapper {
+            tag: self.tag,
+            data_length: len as u32,
+            direction,
+            lun: self.lun,
+            command: cdb.as_slice(),
+        }
+        .to_bytes();
+        self.bulk_out.wait(TransferRequest::bulk_out(&cbw)).await?;
+
+        match data {
+            Data::None => {}
+            Data::In(buf) => {
+                for chunk in buf.chunks_mut(MAX_TRANSFER) {
+                    let want = chunk.len();
+                    match self.bulk_in.wait(TransferRequest::bulk_in(chunk)).await {
+                        // 短包表示设备提前结束数据阶段，剩余长度由 CSW 报告
+                        Ok(t) if t.actual_length < want => break,
+                        Ok(_) => {}
+                        // 设备以 STALL 结束数据阶段，清除后仍可读取 CSW
+                        Err(TransferError::Stall) => {
+                            self.bulk_in.clear_halt(&mut self.device).await?;
+                            break;
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            Data::Out(buf) => {
+                for chunk in buf.chunks(MAX_TRANSFER) {
+                    match self.bulk_out.wait(TransferRequest::bulk_out(chunk)).await {
+                        Ok(_) => {}
+                        Err(TransferError::Stall) => {
+                            self.bulk_out.clear_halt(&mut self.device).await?;
+                            break;
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+        }
+
+        // CSW 阶段 STALL 时清除后再读一次
+        let mut csw = vec![0u8; CSW_LEN];
+        let len = match self.bulk_in.wait(TransferRequest::bulk_in(&mut csw)).await {
+            Err(TransferError::Stall) => {
+                self.bulk_in.clear_halt(&mut self.device).await?;
+                self.bulk_in
+                    .wait(TransferRequest::bulk_in(&mut csw))
+                    .await?
+            }
+            res => res?,
+        }
+        .actual_length;
+        if len != CSW_LEN {
+            return Err(anyhow!("invalid CSW length {len}").into());
+        }
+        let csw = CommandStatusWrapper::parse(&csw).ok_or(anyhow!("invalid CSW"))?;
+        if csw.tag != self.tag {
+            return Err(anyhow!("CSW tag mismatch: {} != {}", csw.tag, self.tag).into());
+        }
+        match csw.status {
+            CommandStatus::Passed => Ok(csw.data_residue as usize),
+            CommandStatus::Failed => Ok(u32::MAX as usize),
+            CommandStatus::PhaseError => {
+                Err(anyhow!("phase error on command {:#04x}", cdb.opcode()).into())
+            }
+        }
+    }
+}
+
+fn is_bot(alt: &InterfaceDescriptor) -> bool {
+    matches!(alt.class(), Class::MassStorage)
+        && matches!(alt.subclass, SUBCLASS_SCSI | SUBCLASS_MMC)
+        && alt.protocol == PROTOCOL_BOT
+}
+
+/// 命令失败的原因
+#[derive(Debug)]
+enum CommandError {
+    /// 设备报告命令失败
+    Sense(SenseData),
+    Usb(USBError),
+}
+
+impl From<USBError> for CommandError {
+    fn from(e: USBError) -> Self {
+        Self::Usb(e)
+    }
+}
+
+impl From<CommandError> for USBError {
+    fn from(e: CommandError) -> Self {
+        match e {
+            CommandError::Sense(sense) if sense.key == scsi::sense_key::DATA_PROTECT => {
+                USBError::NotSupported
+            }
+            CommandError::Sense(sense) => anyhow!("SCSI command failed: {sense}").into(),
+            CommandError::Usb(e) => e,
+        }
+    }
+}
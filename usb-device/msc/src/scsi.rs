@@ -0,0 +1,452 @@
+//! SCSI 命令块构建与响应解析（SPC-4 / SBC-3 / MMC-6 子集）
+
+use alloc::vec::Vec;
+use core::fmt::{self, Display};
+
+pub mod opcode {
+    pub const TEST_UNIT_READY: u8 = 0x00;
+    pub const REQUEST_SENSE: u8 = 0x03;
+    pub const INQUIRY: u8 = 0x12;
+    pub const MODE_SENSE_6: u8 = 0x1a;
+    pub const READ_CAPACITY_10: u8 = 0x25;
+    pub const READ_10: u8 = 0x28;
+    pub const WRITE_10: u8 = 0x2a;
+    pub const SYNCHRONIZE_CACHE_10: u8 = 0x35;
+    pub const READ_TOC: u8 = 0x43;
+    pub const GET_CONFIGURATION: u8 = 0x46;
+    pub const READ_16: u8 = 0x88;
+    pub const WRITE_16: u8 = 0x8a;
+    pub const SYNCHRONIZE_CACHE_16: u8 = 0x91;
+    pub const SERVICE_ACTION_IN_16: u8 = 0x9e;
+}
+
+/// SERVICE ACTION IN(16) 中 READ CAPACITY(16) 的服务动作码
+const SA_READ_CAPACITY_16: u8 = 0x10;
+/// WRITE(10/16) 的 FUA 位：命令完成前数据必须写入介质
+const FUA: u8 = 1 << 3;
+/// MODE SENSE 头部 Device-Specific Parameter 中的写保护位
+const DSP_WP: u8 = 1 << 7;
+/// MODE SENSE 头部 Device-Specific Parameter 中的 DPOFUA 位
+const DSP_DPOFUA: u8 = 1 << 4;
+
+/// INQUIRY 外设类型：直接访问块设备
+pub const PERIPHERAL_DIRECT_ACCESS: u8 = 0x00;
+/// INQUIRY 外设类型：CD/DVD（MMC）设备
+pub const PERIPHERAL_CD_DVD: u8 = 0x05;
+/// 光盘数据扇区（Mode 1 / Mode 2 Form 1 用户数据）的大小
+pub const CD_SECTOR_SIZE: u32 = 2048;
+/// READ TOC 中引出区（lead-out）的轨道号
+pub const LEAD_OUT_TRACK: u8 = 0xaa;
+/// GET CONFIGURATION 的 RT 字段：只返回头部与起始特性
+const RT_ONE_FEATURE: u8 = 0x02;
+
+/// 命令块，最长 16 字节
+#[derive(Debug, Clone, Copy)]
+pub struct Cdb {
+    bytes: [u8; 16],
+    len: u8,
+}
+
+impl Cdb {
+    fn new<const N: usize>(bytes: [u8; N]) -> Self {
+        let mut cdb = Self {
+            bytes: [0; 16],
+            len: N as u8,
+        };
+        cdb.bytes[..N].copy_from_slice(&bytes);
+        cdb
+    }
+
+    pub fn opcode(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+pub fn test_unit_ready() -> Cdb {
+    Cdb::new([opcode::TEST_UNIT_READY, 0, 0, 0, 0, 0])
+}
+
+pub fn request_sense(alloc_len: u8) -> Cdb {
+    Cdb::new([opcode::REQUEST_SENSE, 0, 0, 0, alloc_len, 0])
+}
+
+pub fn inquiry(alloc_len: u8) -> Cdb {
+    Cdb::new([opcode::INQUIRY, 0, 0, 0, alloc_len, 0])
+}
+
+/// MODE SENSE(6)，置 DBD 不返回块描述符
+pub fn mode_sense_6(page: u8, alloc_len: u8) -> Cdb {
+    Cdb::new([opcode::MODE_SENSE_6, 1 << 3, page & 0x3f, 0, alloc_len, 0])
+}
+
+pub fn read_capacity_10() -> Cdb {
+    Cdb::new([opcode::READ_CAPACITY_10, 0, 0, 0, 0, 0, 0, 0, 0, 0])
+}
+
+pub fn read_capacity_16(alloc_len: u32) -> Cdb {
+    let mut cdb = [0u8; 16];
+    cdb[0] = opcode::SERVICE_ACTION_IN_16;
+    cdb[1] = SA_READ_CAPACITY_16;
+    cdb[10..14].copy_from_slice(&alloc_len.to_be_bytes());
+    Cdb::new(cdb)
+}
+
+/// READ TOC/PMA/ATIP，格式 0（TOC），LBA 寻址，从第一个轨道开始
+pub fn read_toc(alloc_len: u16) -> Cdb {
+    let mut cdb = [0u8; 10];
+    cdb[0] = opcode::READ_TOC;
+    cdb[7..9].copy_from_slice(&alloc_len.to_be_bytes());
+    Cdb::new(cdb)
+}
+
+/// GET CONFIGURATION，只读取特性头部中的当前配置文件（介质类型）
+pub fn get_configuration(alloc_len: u16) -> Cdb {
+    let mut cdb = [0u8; 10];
+    cdb[0] = opcode::GET_CONFIGURATION;
+    cdb[1] = RT_ONE_FEATURE;
+    cdb[7..9].copy_from_slice(&alloc_len.to_be_bytes());
+    Cdb::new(cdb)
+}
+
+/// LBA 或块数超出 10 字节命令的范围时需要 16 字节命令
+fn need_16(lba: u64, blocks: u32) -> bool {
+    lba + blocks as u64 > u32::MAX as u64 || blocks > u16::MAX as u32
+}
+
+fn rw(op10: u8, op16: u8, lba: u64, blocks: u32, flags: u8) -> Cdb {
+    if need_16(lba, blocks) {
+        let mut cdb = [0u8; 16];
+        cdb[0] = op16;
+        cdb[1] = flags;
+        cdb[2..10].copy_from_slice(&lba.to_be_bytes());
+        cdb[10..14].copy_from_slice(&blocks.to_be_bytes());
+        Cdb::new(cdb)
+    } else {
+        let mut cdb = [0u8; 10];
+        cdb[0] = op10;
+        cdb[1] = flags;
+        cdb[2..6].copy_from_slice(&(lba as u32).to_be_bytes());
+        cdb[7..9].copy_from_slice(&(blocks as u16).to_be_bytes());
+        Cdb::new(cdb)
+    }
+}
+
+pub fn read(lba: u64, blocks: u32) -> Cdb {
+    rw(opcode::READ_10, opcode::READ_16, lba, blocks, 0)
+}
+
+/// `fua` 为真时要求命令完成前数据已写入介质
+pub fn write(lba: u64, blocks: u32, fua: bool) -> Cdb {
+    let flags = if fua { FUA } else { 0 };
+    rw(opcode::WRITE_10, opcode::WRITE_16, lba, blocks, flags)
+}
+
+/// 同步整个介质的缓存（LBA 0，块数 0 表示直到介质末尾）
+///
+/// 容量超出 32 位 LBA 时使用 16 字节命令。
+pub fn synchronize_cache(long_lba: bool) -> Cdb {
+    if long_lba {
+        let mut cdb = [0u8; 16];
+        cdb[0] = opcode::SYNCHRONIZE_CACHE_16;
+        Cdb::new(cdb)
+    } else {
+        let mut cdb = [0u8; 10];
+        cdb[0] = opcode::SYNCHRONIZE_CACHE_10;
+        Cdb::new(cdb)
+    }
+}
+
+/// 介质容量
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capacity {
+    pub block_count: u64,
+    pub block_size: u32,
+}
+
+impl Capacity {
+    /// 解析 READ CAPACITY(10) 响应，最大 LBA 为 `0xffffffff` 时需改用 16 字节命令
+    pub fn parse_10(data: &[u8]) -> Option<Self> {
+        let last_lba = u32::from_be_bytes(data.get(0..4)?.try_into().ok()?);
+        let block_size = u32::from_be_bytes(data.get(4..8)?.try_into().ok()?);
+        Some(Self {
+            block_count: last_lba as u64 + 1,
+            block_size,
+        })
+    }
+
+    pub fn parse_16(data: &[u8]) -> Option<Self> {
+        let last_lba = u64::from_be_bytes(data.get(0..8)?.try_into().ok()?);
+        let block_size = u32::from_be_bytes(data.get(8..12)?.try_into().ok()?);
+        Some(Self {
+            block_count: last_lba.checked_add(1)?,
+            block_size,
+        })
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.block_count * self.block_size as u64
+    }
+}
+
+/// MODE SENSE(6) 参数头中的设备参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModeParameters {
+    /// 介质写保护
+    pub write_protected: bool,
+    /// 设备支持 DPO/FUA 位
+    pub dpofua: bool,
+}
+
+impl ModeParameters {
+    pub fn parse_6(data: &[u8]) -> Option<Self> {
+        let dsp = *data.get(2)?;
+        Some(Self {
+            write_protected: dsp & DSP_WP != 0,
+            dpofua: dsp & DSP_DPOFUA != 0,
+        })
+    }
+}
+
+/// INQUIRY 标准数据中驱动关心的字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Inquiry {
+    /// 外设类型，如 [`PERIPHERAL_DIRECT_ACCESS`]、[`PERIPHERAL_CD_DVD`]
+    pub device_type: u8,
+    /// 可移动介质
+    pub removable: bool,
+}
+
+impl Inquiry {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        Some(Self {
+            device_type: data.first()? & 0x1f,
+            removable: data.get(1)? & 0x80 != 0,
+        })
+    }
+
+    /// 是否为 CD/DVD 等 MMC 设备
+    pub fn is_mmc(&self) -> bool {
+        self.device_type == PERIPHERAL_CD_DVD
+    }
+}
+
+/// 介质类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaType {
+    /// 直接访问块设备（硬盘、U 盘）
+    Disk,
+    /// 光驱中没有介质
+    NoMedia,
+    Cd,
+    Dvd,
+    BluRay,
+    /// 其他 MMC 配置文件（Profile）
+    Other(u16),
+}
+
+impl MediaType {
+    /// 按 MMC 配置文件编号分类
+    pub fn from_profile(profile: u16) -> Self {
+        match profile {
+            0x0000 => Self::NoMedia,
+            0x0008..=0x000a => Self::Cd,
+            0x0010..=0x002b => Self::Dvd,
+            0x0040..=0x0043 => Self::BluRay,
+            _ => Self::Other(profile),
+        }
+    }
+
+    /// 解析 GET CONFIGURATION 响应头中的当前配置文件
+    pub fn parse_configuration(data: &[u8]) -> Option<Self> {
+        let profile = u16::from_be_bytes(data.get(6..8)?.try_into().ok()?);
+        Some(Self::from_profile(profile))
+    }
+
+    /// 是否为光盘介质，光盘只读并以 2048 字节扇区访问
+    pub fn is_optical(&self) -> bool {
+        matches!(self, Self::Cd | Self::Dvd | Self::BluRay | Self::Other(_))
+    }
+}
+
+/// TOC 中的一个轨道
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TocEntry {
+    /// 轨道号，引出区为 [`LEAD_OUT_TRACK`]
+    pub track: u8,
+    /// ADR/CONTROL 字节的低 4 位（CONTROL）
+    pub control: u8,
+    /// 轨道起始 LBA
+    pub lba: u32,
+}
+
+impl TocEntry {
+    /// 数据轨道（CONTROL 的 bit 2），否则为音轨
+    pub fn is_data(&self) -> bool {
+        self.control & 0x04 != 0
+    }
+}
+
+/// READ TOC 格式 0 的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toc {
+    pub first_track: u8,
+    pub last_track: u8,
+    /// 各轨道，最后一项为引出区
+    pub entries: Vec<TocEntry>,
+}
+
+impl Toc {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let len = u16::from_be_bytes(data.get(0..2)?.try_into().ok()?) as usize;
+        // 数据长度不含自身的 2 字节
+        let end = (len + 2).min(data.len());
+        let entries = data
+            .get(4..end)?
+            .chunks_exact(8)
+            .map(|d| TocEntry {
+                track: d[2],
+                control: d[1] & 0x0f,
+                lba: u32::from_be_bytes([d[4], d[5], d[6], d[7]]),
+            })
+            .collect();
+        Some(Self {
+            first_track: data[2],
+            last_track: data[3],
+            entries,
+        })
+    }
+
+    /// 第一个数据轨道，ISO 9660 文件系统从这里开始
+    pub fn first_data_track(&self) -> Option<TocEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.is_data() && e.track != LEAD_OUT_TRACK)
+            .copied()
+    }
+}
+
+pub mod sense_key {
+    pub const NO_SENSE: u8 = 0x0;
+    pub const NOT_READY: u8 = 0x2;
+    pub const MEDIUM_ERROR: u8 = 0x3;
+    pub const ILLEGAL_REQUEST: u8 = 0x5;
+    pub const UNIT_ATTENTION: u8 = 0x6;
+    pub const DATA_PROTECT: u8 = 0x7;
+}
+
+/// ASC：介质不存在
+pub const ASC_MEDIUM_NOT_PRESENT: u8 = 0x3a;
+
+/// 固定格式的 Sense 数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SenseData {
+    pub key: u8,
+    pub asc: u8,
+    pub ascq: u8,
+}
+
+impl SenseData {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        // 0x70/0x71：当前/延迟错误，固定格式
+        if !matches!(data.first()? & 0x7f, 0x70 | 0x71) {
+            return None;
+        }
+        Some(Self {
+            key: data.get(2)? & 0x0f,
+            asc: data.get(12).copied().unwrap_or(0),
+            ascq: data.get(13).copied().unwrap_or(0),
+        })
+    }
+}
+
+impl Display for SenseData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sense key {:#x}, asc {:#04x}, ascq {:#04x}",
+            self.key, self.asc, self.ascq
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_fua_and_long_lba() {
+        let cdb = write(0x1000, 8, true);
+        assert_eq!(cdb.as_slice().len(), 10);
+        assert_eq!(cdb.opcode(), opcode::WRITE_10);
+        assert_eq!(cdb.as_slice()[1], FUA);
+
+        // 超出 32 位 LBA 时切换为 16 字节命令
+        let cdb = write(u32::MAX as u64, 1, false);
+        assert_eq!(cdb.as_slice().len(), 16);
+        assert_eq!(cdb.opcode(), opcode::WRITE_16);
+        assert_eq!(cdb.as_slice()[1], 0);
+    }
+
+    #[test]
+    fn test_synchronize_cache() {
+        assert_eq!(
+            synchronize_cache(false).as_slice(),
+            &[opcode::SYNCHRONIZE_CACHE_10, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            synchronize_cache(true).opcode(),
+            opcode::SYNCHRONIZE_CACHE_16
+        );
+    }
+
+    #[test]
+    fn test_mmc_commands() {
+        assert_eq!(
+            read_toc(804).as_slice(),
+            &[opcode::READ_TOC, 0, 0, 0, 0, 0, 0, 0x03, 0x24, 0]
+        );
+        assert_eq!(get_configuration(8).as_slice()[..2], [0x46, 0x02]);
+
+        let header = [0, 0, 0, 0x1c, 0, 0, 0x00, 0x10];
+        assert_eq!(
+            MediaType::parse_configuration(&header),
+            Some(MediaType::Dvd)
+        );
+        assert_eq!(MediaType::from_profile(0x0008), MediaType::Cd);
+        assert!(!MediaType::from_profile(0).is_optical());
+
+        let inquiry = Inquiry::parse(&[0x05, 0x80, 0x05, 0x32]).unwrap();
+        assert!(inquiry.is_mmc() && inquiry.removable);
+    }
+
+    #[test]
+    fn test_toc() {
+        // 音轨 1、数据轨 2 与引出区
+        #[rustfmt::skip]
+        let data = [
+            0, 26, 1, 2,
+            0, 0x10, 1, 0, 0, 0, 0, 0,
+            0, 0x14, 2, 0, 0, 0, 0x30, 0x00,
+            0, 0x14, LEAD_OUT_TRACK, 0, 0, 0, 0x40, 0x00,
+            0xff, 0xff,
+        ];
+        let toc = Toc::parse(&data).unwrap();
+        assert_eq!((toc.first_track, toc.last_track), (1, 2));
+        assert_eq!(toc.entries.len(), 3);
+        let data_track = toc.first_data_track().unwrap();
+        assert_eq!((data_track.track, data_track.lba), (2, 0x3000));
+        assert_eq!(Toc::parse(&[0, 2]), None);
+    }
+
+    #[test]
+    fn test_mode_parameters() {
+        let mode = ModeParameters::parse_6(&[3, 0, 0x90, 0]).unwrap();
+        assert!(mode.write_protected);
+        assert!(mode.dpofua);
+        assert_eq!(ModeParameters::parse_6(&[3, 0]), None);
+    }
+}
@@ -0,0 +1,403 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{vec, vec::Vec};
+use anyhow::anyhow;
+use core::time::Duration;
+use crab_usb::{Device, DeviceInfo, Endpoint, err::USBError};
+use log::*;
+use usb_if::{
+    descriptor::{EndpointType, InterfaceDescriptor},
+    endpoint::TransferRequest,
+    host::ControlSetup,
+    transfer::{Direction, Recipient, Request, RequestType},
+};
+
+pub mod msg;
+
+use msg::{InitializeInfo, Packets, Response, filter, oid, status};
+
+/// CDC 类请求：SEND_ENCAPSULATED_COMMAND
+const SEND_ENCAPSULATED_COMMAND: u8 = 0x00;
+/// CDC 类请求：GET_ENCAPSULATED_RESPONSE
+const GET_ENCAPSULATED_RESPONSE: u8 = 0x01;
+/// 控制响应缓冲区，规范要求至少 1025 字节
+const RESPONSE_LEN: usize = 1025;
+/// 等待控制消息完成的上限，与 Linux rndis_host 的 RNDIS_CONTROL_TIMEOUT_MS 相同
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+/// RESPONSE_AVAILABLE 通知的长度：通知码 0x00000001 与保留字段
+const NOTIFICATION_LEN: usize = 8;
+/// 主机单次批量接收的最大字节数，在 INITIALIZE 时告知设备
+const MAX_RX_TRANSFER: usize = 16 * 1024;
+/// CDC Data 接口类
+const CLASS_CDC_DATA: u8 = 0x0a;
+/// 默认接收的帧类型
+const DEFAULT_FILTER: u32 = filter::DIRECTED | filter::MULTICAST | filter::BROADCAST;
+
+/// RNDIS 控制接口的 (class, subclass, protocol)
+///
+/// Windows 与多数设备使用 CDC ACM 加厂商协议；Android 共享网络使用无线控制器类，
+/// 部分设备使用杂项类中的 RNDIS over Ethernet。
+const CONTROL_INTERFACES: [(u8, u8, u8); 3] =
+    [(0x02, 0x02, 0xff), (0xe0, 0x01, 0x03), (0xef, 0x04, 0x01)];
+
+/// RNDIS 网卡（Android USB 共享网络及常见 RNDIS 网卡）
+///
+/// 控制消息经默认控制端点封装收发，以太网帧以 `REMOTE_NDIS_PACKET_MSG` 封装后经批量端点
+/// 传输。发送控制消息后等待中断端点的 RESPONSE_AVAILABLE 通知再读取响应，没有通知端点的
+/// 设备直接轮询，两种情况都以 [`CONTROL_TIMEOUT`] 为上限。
+pub struct Rndis {
+    device: Device,
+    control_interface: u8,
+    /// 控制接口的中断 IN 端点，用于接收 RESPONSE_AVAILABLE 通知
+    notification: Option<Endpoint>,
+    bulk_in: Endpoint,
+    bulk_out: Endpoint,
+    bulk_out_packet_size: usize,
+    request_id: u32,
+    mac: [u8; 6],
+    max_frame_size: usize,
+    info: InitializeInfo,
+    /// 上一次批量接收的数据，设备可在一次传输中放多个数据包
+    rx: Vec<u8>,
+    rx_offset: usize,
+}
+
+impl Rndis {
+    /// 检查设备是否带有 RNDIS 控制接口
+    pub fn check(info: &DeviceInfo) -> bool {
+        info.interface_descriptors().any(is_control)
+    }
+
+    pub async fn new(mut device: Device) -> Result<Self, USBError> {
+        let (control, data_interface, alternate, ep_in, ep_out, out_packet) = {
+            let config = device.configurations().first().ok_or(USBError::NotFound)?;
+            let control_alt = config
+                .interfaces
+                .iter()
+                .map(|iface| iface.first_alt_setting())
+                .find(is_control)
+                .ok_or(USBError::NotFound)?;
+            let notify = control_alt
+                .endpoints
+                .iter()
+                .find(|ep| {
+                    ep.transfer_type == EndpointType::Interrupt && ep.direction == Direction::In
+                })
+                .map(|ep| ep.address);
+            let control = (
+                control_alt.interface_number,
+                control_alt.alternate_setting,
+                notify,
+            );
+            // 数据接口通常紧跟控制接口
+            let mut data: Vec<_> = config
+                .interfaces
+                .iter()
+                .flat_map(|iface| iface.alt_settings.iter())
+                .filter(|alt| alt.class == CLASS_CDC_DATA)
+                .filter_map(|alt| {
+                    let find = |dir: Direction| {
+                        alt.endpoints.iter().find(|ep| {
+                            ep.transfer_type == EndpointType::Bulk && ep.direction == dir
+                        })
+                    };
+                    let ep_in = find(Direction::In)?;
+                    let ep_out = find(Direction::Out)?;
+                    Some((
+                        alt.interface_number,
+                        alt.alternate_setting,
+                        ep_in.address,
+                        ep_out.address,
+                        ep_out.max_packet_size as usize,
+                    ))
+                })
+                .collect();
+            data.sort_by_key(|d| d.0 != control.0.wrapping_add(1));
+            let (iface, alt, ep_in, ep_out, out_packet) =
+                data.first().copied().ok_or(USBError::NotFound)?;
+            (control, iface, alt, ep_in, ep_out, out_packet)
+        };
+        let (control_interface, control_alternate, notify) = control;
+        debug!(
+            "RNDIS control interface {control_interface}, data interface {data_interface}, \
+             bulk in {ep_in:#x}, bulk out {ep_out:#x}, notification {notify:x?}"
+        );
+
+        // 类请求发往控制接口，需先声明；通知端点只能在其为当前接口时取得
+        device
+            .claim_interface(control_interface, control_alternate)
+            .await?;
+        let notification = match notify {
+            Some(addr) => {
+                let mut ep = device.endpoint(addr)?;
+                ep.set_timeout(Some(CONTROL_TIMEOUT));
+                Some(ep)
+            }
+            None => None,
+        };
+        device.claim_interface(data_interface, alternate).await?;
+        let bulk_in = device.endpoint(ep_in)?;
+        let bulk_out = device.endpoint(ep_out)?;
+
+        let mut rndis = Self {
+            device,
+            control_interface,
+            notification,
+            bulk_in,
+            bulk_out,
+            bulk_out_packet_size: out_packet.max(1),
+            request_id: 0,
+            mac: [0; 6],
+            max_frame_size: 1500,
+            info: InitializeInfo {
+                major_version: 1,
+                minor_version: 0,
+                medium: 0,
+                max_packets_per_transfer: 1,
+                max_transfer_size: 0,
+                packet_alignment_factor: 0,
+            },
+            rx: Vec::new(),
+            rx_offset: 0,
+        };
+        rndis.initialize().await?;
+
+        let mac = rndis.query(oid::ETH_PERMANENT_ADDRESS).await?;
+        rndis.mac = mac
+            .get(..6)
+            .and_then(|m| m.try_into().ok())
+            .ok_or(anyhow!("invalid MAC address"))?;
+        if let Ok(size) = rndis.query_u32(oid::GEN_MAXIMUM_FRAME_SIZE).await {
+            rndis.max_frame_size = size as usize;
+        }
+        rndis.set_packet_filter(DEFAULT_FILTER).await?;
+
+        info!(
+            "RNDIS {:02x?}, max frame {}, device max transfer {}",
+            rndis.mac, rndis.max_frame_size, rndis.info.max_transfer_size
+        );
+        Ok(rndis)
+    }
+
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.mac
+    }
+
+    /// 不含以太网头的最大帧长（MTU）
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// INITIALIZE 时设备报告的参数
+    pub fn device_info(&self) -> InitializeInfo {
+        self.info
+    }
+
+    /// 链路是否已连接
+    pub async fn media_connected(&mut self) -> Result<bool, USBError> {
+        Ok(self.query_u32(oid::GEN_MEDIA_CONNECT_STATUS).await? == msg::MEDIA_STATE_CONNECTED)
+    }
+
+    /// 链路速率，单位为 100 bps
+    pub async fn link_speed(&mut self) -> Result<u32, USBError> {
+        self.query_u32(oid::GEN_LINK_SPEED).await
+    }
+
+    /// 设置接收的帧类型，取值见 [`msg::filter`]；设为 0 时设备停止上送数据
+    pub async fn set_packet_filter(&mut self, filter: u32) -> Result<(), USBError> {
+        self.set(oid::GEN_CURRENT_PACKET_FILTER, &filter.to_le_bytes())
+            .await
+    }
+
+    /// 发送 KEEPALIVE，确认设备仍然响应
+    pub async fn keepalive(&mut self) -> Result<(), USBError> {
+        let id = self.next_request_id();
+        self.request(msg::keepalive(id), msg::KEEPALIVE_MSG, id)
+            .await
+            .map(|_| ())
+    }
+
+    /// 软复位设备，完成后重新设置默认的接收过滤
+    pub async fn reset(&mut self) -> Result<(), USBError> {
+        self.send_command(&msg::reset()).await?;
+        let deadline = self.bulk_in.now() + CONTROL_TIMEOUT;
+        loop {
+            let buf = self.next_response(deadline).await?;
+            if let Some(Response::ResetComplete { status }) = Response::parse(&buf) {
+                check_status(status)?;
+                self.rx.clear();
+                self.rx_offset = 0;
+                return self.set_packet_filter(DEFAULT_FILTER).await;
+            }
+        }
+    }
+
+    /// 通知设备停止工作，之后需要重新创建
+    pub async fn halt(mut self) -> Result<(), USBError> {
+        let id = self.next_request_id();
+        self.send_command(&msg::halt(id)).await
+    }
+
+    /// 发送一个以太网帧
+    pub async fn send(&mut self, frame: &[u8]) -> Result<(), USBError> {
+        let mut buf = Vec::with_capacity(msg::PACKET_HEADER_LEN + frame.len() + 1);
+        msg::encode_packet(&mut buf, frame);
+        // 长度恰为最大包长整数倍时补一个字节，代替零长度包结束传输
+        if buf.len() % self.bulk_out_packet_size == 0 {
+            buf.push(0);
+        }
+        self.bulk_out.wait(TransferRequest::bulk_out(&buf)).await?;
+        Ok(())
+    }
+
+    /// 接收一个以太网帧，返回帧长；帧长超过 `buf` 时截断
+    pub async fn recv(&mut self, buf: &mut [u8]) -> Result<usize, USBError> {
+        loop {
+            let mut packets = Packets::new(&self.rx[self.rx_offset..]);
+            if let Some(frame) = packets.next() {
+                let len = frame.len().min(buf.len());
+                buf[..len].copy_from_slice(&frame[..len]);
+                self.rx_offset = self.rx.len() - packets.remaining();
+                return Ok(frame.len());
+            }
+
+            self.rx.resize(MAX_RX_TRANSFER, 0);
+            let t = self
+                .bulk_in
+                .wait(TransferRequest::bulk_in(&mut self.rx))
+                .await?;
+            self.rx.truncate(t.actual_length);
+            self.rx_offset = 0;
+        }
+    }
+
+    async fn initialize(&mut self) -> Result<(), USBError> {
+        let id = self.next_request_id();
+        let body = self
+            .request(
+                msg::initialize(id, MAX_RX_TRANSFER as _),
+                msg::INITIALIZE_MSG,
+                id,
+            )
+            .await?;
+        self.info = InitializeInfo::parse(&body).ok_or(anyhow!("invalid INITIALIZE_CMPLT"))?;
+        Ok(())
+    }
+
+    async fn query(&mut self, oid: u32) -> Result<Vec<u8>, USBError> {
+        let id = self.next_request_id();
+        let body = self
+            .request(msg::query(id, oid), msg::QUERY_MSG, id)
+            .await?;
+        Ok(msg::query_result(&body)
+            .ok_or(anyhow!("invalid QUERY_CMPLT for OID {oid:#010x}"))?
+            .to_vec())
+    }
+
+    async fn query_u32(&mut self, oid: u32) -> Result<u32, USBError> {
+        let data = self.query(oid).await?;
+        let bytes = data
+            .get(..4)
+            .and_then(|b| b.try_into().ok())
+            .ok_or(anyhow!("short QUERY_CMPLT for OID {oid:#010x}"))?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    async fn set(&mut self, oid: u32, value: &[u8]) -> Result<(), USBError> {
+        let id = self.next_request_id();
+        self.request(msg::set(id, oid, value), msg::SET_MSG, id)
+            .await
+            .map(|_| ())
+    }
+
+    fn next_request_id(&mut self) -> u32 {
+        // RequestId 0 保留给 RESET
+        self.request_id = self.request_id.wrapping_add(1).max(1);
+        self.request_id
+    }
+
+    /// 发送控制消息并等待对应的完成消息，返回 Status 之后的内容
+    async fn request(&mut self, cmd: Vec<u8>, ty: u32, id: u32) -> Result<Vec<u8>, USBError> {
+        self.send_command(&cmd).await?;
+        let deadline = self.bulk_in.now() + CONTROL_TIMEOUT;
+        loop {
+            let buf = self.next_response(deadline).await?;
+            match Response::parse(&buf) {
+                Some(Response::Completion {
+                    ty: t,
+                    request_id,
+                    status,
+                    body,
+                }) if t == ty && request_id == id => {
+                    check_status(status)?;
+                    return Ok(body.to_vec());
+                }
+                Some(Response::IndicateStatus { status }) => {
+                    debug!("RNDIS status indication {status:#010x}");
+                }
+                Some(Response::KeepAlive { request_id }) => {
+                    self.send_command(&msg::keepalive_complete(request_id))
+                        .await?;
+                }
+                Some(other) => trace!("RNDIS skip response {other:?}"),
+                None => {}
+            }
+        }
+    }
+
+    /// 等待设备的下一个封装响应，时钟超过 `deadline` 后返回超时
+    ///
+    /// 有通知端点时先等待 RESPONSE_AVAILABLE，通知端点的超时同样为 [`CONTROL_TIMEOUT`]；
+    /// 设备尚无响应时返回空。
+    async fn next_response(&mut self, deadline: Duration) -> Result<Vec<u8>, USBError> {
+        if self.bulk_in.now() >= deadline {
+            return Err(USBError::Timeout);
+        }
+        if let Some(ep) = self.notification.as_mut() {
+            let mut buf = [0u8; NOTIFICATION_LEN];
+            ep.wait(TransferRequest::interrupt_in(&mut buf)).await?;
+        }
+        self.get_response().await
+    }
+
+    async fn send_command(&mut self, cmd: &[u8]) -> Result<(), USBError> {
+        let setup = ControlSetup {
+            request_type: RequestType::Class,
+            recipient: Recipient::Interface,
+            request: Request::Class(SEND_ENCAPSULATED_COMMAND),
+            value: 0,
+            index: self.control_interface as u16,
+        };
+        self.device.control_out(setup, cmd).await?;
+        Ok(())
+    }
+
+    /// 读取一个封装的响应；设备尚无响应时返回空
+    async fn get_response(&mut self) -> Result<Vec<u8>, USBError> {
+        let mut buf = vec![0u8; RESPONSE_LEN];
+        let setup = ControlSetup {
+            request_type: RequestType::Class,
+            recipient: Recipient::Interface,
+            request: Request::Class(GET_ENCAPSULATED_RESPONSE),
+            value: 0,
+            index: self.control_interface as u16,
+        };
+        let len = self.device.control_in(setup, &mut buf).await?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+fn is_control(alt: &InterfaceDescriptor) -> bool {
+    CONTROL_INTERFACES.contains(&(alt.class, alt.subclass, alt.protocol))
+}
+
+fn check_status(status: u32) -> Result<(), USBError> {
+    match status {
+        status::SUCCESS => Ok(()),
+        status::NOT_SUPPORTED => Err(USBError::NotSupported),
+        s => Err(anyhow!("RNDIS request failed: status {s:#010x}").into()),
+    }
+}
@@ -0,0 +1,400 @@
+//! RNDIS 消息编码（Remote NDIS Specification 1.0）
+//!
+//! 控制消息经控制端点的 SEND_ENCAPSULATED_COMMAND / GET_ENCAPSULATED_RESPONSE 收发，
+//! 数据包以 `REMOTE_NDIS_PACKET_MSG` 封装后经批量端点收发。所有字段均为小端序 u32，
+//! 消息头为类型与长度，偏移字段从第 8 字节（RequestId 或 DataOffset）起算。
+
+use alloc::vec::Vec;
+
+pub const PACKET_MSG: u32 = 0x0000_0001;
+pub const INITIALIZE_MSG: u32 = 0x0000_0002;
+pub const HALT_MSG: u32 = 0x0000_0003;
+pub const QUERY_MSG: u32 = 0x0000_0004;
+pub const SET_MSG: u32 = 0x0000_0005;
+pub const RESET_MSG: u32 = 0x0000_0006;
+pub const INDICATE_STATUS_MSG: u32 = 0x0000_0007;
+pub const KEEPALIVE_MSG: u32 = 0x0000_0008;
+/// 完成消息的类型为请求类型置最高位
+pub const COMPLETION: u32 = 0x8000_0000;
+
+/// 控制消息的状态码
+pub mod status {
+    pub const SUCCESS: u32 = 0x0000_0000;
+    pub const FAILURE: u32 = 0xc000_0001;
+    pub const INVALID_DATA: u32 = 0xc001_0015;
+    pub const NOT_SUPPORTED: u32 = 0xc000_00bb;
+    pub const MEDIA_CONNECT: u32 = 0x4001_000b;
+    pub const MEDIA_DISCONNECT: u32 = 0x4001_000c;
+}
+
+/// 本驱动使用的 NDIS OID
+pub mod oid {
+    pub const GEN_MAXIMUM_FRAME_SIZE: u32 = 0x0001_0106;
+    pub const GEN_LINK_SPEED: u32 = 0x0001_0107;
+    pub const GEN_CURRENT_PACKET_FILTER: u32 = 0x0001_010e;
+    pub const GEN_MEDIA_CONNECT_STATUS: u32 = 0x0001_0114;
+    pub const ETH_PERMANENT_ADDRESS: u32 = 0x0101_0101;
+    pub const ETH_CURRENT_ADDRESS: u32 = 0x0101_0102;
+}
+
+/// `OID_GEN_CURRENT_PACKET_FILTER` 的取值
+pub mod filter {
+    pub const DIRECTED: u32 = 0x0000_0001;
+    pub const MULTICAST: u32 = 0x0000_0002;
+    pub const ALL_MULTICAST: u32 = 0x0000_0004;
+    pub const BROADCAST: u32 = 0x0000_0008;
+    pub const PROMISCUOUS: u32 = 0x0000_0020;
+}
+
+/// `OID_GEN_MEDIA_CONNECT_STATUS` 中表示已连接的值
+pub const MEDIA_STATE_CONNECTED: u32 = 0;
+
+/// `REMOTE_NDIS_PACKET_MSG` 头部长度
+pub const PACKET_HEADER_LEN: usize = 44;
+
+const QUERY_HEADER_LEN: usize = 28;
+
+fn put(buf: &mut Vec<u8>, words: &[u32]) {
+    for w in words {
+        buf.extend_from_slice(&w.to_le_bytes());
+    }
+}
+
+fn word(data: &[u8], index: usize) -> Option<u32> {
+    let bytes = data.get(index * 4..index * 4 + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// `REMOTE_NDIS_INITIALIZE_MSG`，`max_transfer_size` 为主机单次可接收的字节数
+pub fn initialize(request_id: u32, max_transfer_size: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(24);
+    put(
+        &mut buf,
+        &[INITIALIZE_MSG, 24, request_id, 1, 0, max_transfer_size],
+    );
+    buf
+}
+
+/// `REMOTE_NDIS_QUERY_MSG`，不携带输入缓冲区
+pub fn query(request_id: u32, oid: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(QUERY_HEADER_LEN);
+    put(
+        &mut buf,
+        &[QUERY_MSG, QUERY_HEADER_LEN as u32, request_id, oid, 0, 0, 0],
+    );
+    buf
+}
+
+/// `REMOTE_NDIS_SET_MSG`
+pub fn set(request_id: u32, oid: u32, value: &[u8]) -> Vec<u8> {
+    let len = QUERY_HEADER_LEN + value.len();
+    let mut buf = Vec::with_capacity(len);
+    put(
+        &mut buf,
+        &[
+            SET_MSG,
+            len as u32,
+            request_id,
+            oid,
+            value.len() as u32,
+            (QUERY_HEADER_LEN - 8) as u32,
+            0,
+        ],
+    );
+    buf.extend_from_slice(value);
+    buf
+}
+
+/// `REMOTE_NDIS_KEEPALIVE_MSG`
+pub fn keepalive(request_id: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(12);
+    put(&mut buf, &[KEEPALIVE_MSG, 12, request_id]);
+    buf
+}
+
+/// 回复设备发来的 `REMOTE_NDIS_KEEPALIVE_MSG`
+pub fn keepalive_complete(request_id: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(16);
+    put(
+        &mut buf,
+        &[KEEPALIVE_MSG | COMPLETION, 16, request_id, status::SUCCESS],
+    );
+    buf
+}
+
+/// `REMOTE_NDIS_RESET_MSG`，没有 RequestId
+pub fn reset() -> Vec<u8> {
+    let mut buf = Vec::with_capacity(12);
+    put(&mut buf, &[RESET_MSG, 12, 0]);
+    buf
+}
+
+/// `REMOTE_NDIS_HALT_MSG`，设备不回复
+pub fn halt(request_id: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(12);
+    put(&mut buf, &[HALT_MSG, 12, request_id]);
+    buf
+}
+
+/// 设备返回的控制消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response<'a> {
+    /// 请求的完成消息，`ty` 为不含 [`COMPLETION`] 位的请求类型
+    Completion {
+        ty: u32,
+        request_id: u32,
+        status: u32,
+        /// RequestId、Status 之后的内容
+        body: &'a [u8],
+    },
+    /// `REMOTE_NDIS_RESET_CMPLT`，没有 RequestId
+    ResetComplete { status: u32 },
+    /// 设备主动上报的状态，如链路连接与断开
+    IndicateStatus { status: u32 },
+    /// 设备发来的 `REMOTE_NDIS_KEEPALIVE_MSG`，需要回复
+    KeepAlive { request_id: u32 },
+}
+
+impl<'a> Response<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let ty = word(data, 0)?;
+        let len = (word(data, 1)? as usize).min(data.len());
+        let data = &data[..len];
+        match ty {
+            INDICATE_STATUS_MSG => Some(Self::IndicateStatus {
+                status: word(data, 2)?,
+            }),
+            KEEPALIVE_MSG => Some(Self::KeepAlive {
+                request_id: word(data, 2)?,
+            }),
+            t if t == RESET_MSG | COMPLETION => Some(Self::ResetComplete {
+                status: word(data, 2)?,
+            }),
+            t if t & COMPLETION != 0 => Some(Self::Completion {
+                ty: t & !COMPLETION,
+                request_id: word(data, 2)?,
+                status: word(data, 3)?,
+                body: &data[16..],
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// `REMOTE_NDIS_INITIALIZE_CMPLT` 中的设备参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitializeInfo {
+    pub major_version: u32,
+    pub minor_version: u32,
+    pub medium: u32,
+    /// 设备单次传输可接收的最多数据包数
+    pub max_packets_per_transfer: u32,
+    /// 设备单次传输可接收的最大字节数
+    pub max_transfer_size: u32,
+    /// 同一传输中各数据包按 `2^n` 字节对齐
+    pub packet_alignment_factor: u32,
+}
+
+impl InitializeInfo {
+    /// 解析完成消息中 Status 之后的内容
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        Some(Self {
+            major_version: word(body, 0)?,
+            minor_version: word(body, 1)?,
+            medium: word(body, 3)?,
+            max_packets_per_transfer: word(body, 4)?,
+            max_transfer_size: word(body, 5)?,
+            packet_alignment_factor: word(body, 6)?,
+        })
+    }
+}
+
+/// 从 `REMOTE_NDIS_QUERY_CMPLT` 的内容中取出信息缓冲区
+pub fn query_result(body: &[u8]) -> Option<&[u8]> {
+    let len = word(body, 0)? as usize;
+    // 偏移从 RequestId 起算，body 从第 16 字节开始
+    let offset = (word(body, 1)? as usize).checked_sub(8)?;
+    body.get(offset..offset.checked_add(len)?)
+}
+
+/// 以 `REMOTE_NDIS_PACKET_MSG` 封装以太网帧，追加到 `buf`
+pub fn encode_packet(buf: &mut Vec<u8>, frame: &[u8]) {
+    let len = (PACKET_HEADER_LEN + frame.len()) as u32;
+    put(
+        buf,
+        &[
+            PACKET_MSG,
+            len,
+            (PACKET_HEADER_LEN - 8) as u32,
+            frame.len() as u32,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ],
+    );
+    buf.extend_from_slice(frame);
+}
+
+/// 一次批量传输中的数据包
+///
+/// 设备可以把多个 `REMOTE_NDIS_PACKET_MSG` 放在同一次传输中，每个消息由其长度字段定位。
+/// 非数据包消息被跳过，长度字段不合法时停止解析。
+pub struct Packets<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Packets<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// 尚未解析的字节数
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl<'a> Iterator for Packets<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let ty = word(self.data, 0)?;
+            let len = word(self.data, 1)? as usize;
+            if len < 8 || len > self.data.len() {
+                self.data = &[];
+                return None;
+            }
+            let (msg, rest) = self.data.split_at(len);
+            self.data = rest;
+            if ty != PACKET_MSG {
+                continue;
+            }
+            let offset = (word(msg, 2)? as usize).checked_add(8)?;
+            let data_len = word(msg, 3)? as usize;
+            if let Some(frame) = msg.get(offset..offset.checked_add(data_len)?) {
+                return Some(frame);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_message_layout() {
+        let msg = set(7, oid::GEN_CURRENT_PACKET_FILTER, &0x0du32.to_le_bytes());
+        assert_eq!(msg.len(), 32);
+        assert_eq!(word(&msg, 0), Some(SET_MSG));
+        assert_eq!(word(&msg, 1), Some(32));
+        assert_eq!(word(&msg, 2), Some(7));
+        assert_eq!(word(&msg, 4), Some(4));
+        // 信息缓冲区位于 8 + 20 = 28 字节处
+        assert_eq!(word(&msg, 5), Some(20));
+        assert_eq!(word(&msg, 7), Some(0x0d));
+    }
+
+    #[test]
+    fn query_completion() {
+        let mut cmplt = Vec::new();
+        put(&mut cmplt, &[QUERY_MSG | COMPLETION, 30, 3, 0, 6, 16]);
+        cmplt.extend_from_slice(&[0x02, 0x11, 0x22, 0x33, 0x44, 0x55]);
+
+        let Some(Response::Completion {
+            ty,
+            request_id,
+            status,
+            body,
+        }) = Response::parse(&cmplt)
+        else {
+            panic!("not a completion");
+        };
+        assert_eq!((ty, request_id, status), (QUERY_MSG, 3, status::SUCCESS));
+        assert_eq!(
+            query_result(body),
+            Some(&[0x02, 0x11, 0x22, 0x33, 0x44, 0x55][..])
+        );
+    }
+
+    #[test]
+    fn initialize_completion() {
+        let mut cmplt = Vec::new();
+        put(
+            &mut cmplt,
+            &[
+                INITIALIZE_MSG | COMPLETION,
+                52,
+                1,
+                0,
+                1,
+                0,
+                1,
+                0,
+                1,
+                0x4000,
+                3,
+                0,
+                0,
+            ],
+        );
+        let Some(Response::Completion { body, .. }) = Response::parse(&cmplt) else {
+            panic!("not a completion");
+        };
+        let info = InitializeInfo::parse(body).unwrap();
+        assert_eq!(info.major_version, 1);
+        assert_eq!(info.max_packets_per_transfer, 1);
+        assert_eq!(info.max_transfer_size, 0x4000);
+        assert_eq!(info.packet_alignment_factor, 3);
+    }
+
+    #[test]
+    fn status_and_reset() {
+        let mut msg = Vec::new();
+        put(
+            &mut msg,
+            &[INDICATE_STATUS_MSG, 20, status::MEDIA_CONNECT, 0, 0],
+        );
+        assert_eq!(
+            Response::parse(&msg),
+            Some(Response::IndicateStatus {
+                status: status::MEDIA_CONNECT
+            })
+        );
+        let mut msg = Vec::new();
+        put(&mut msg, &[RESET_MSG | COMPLETION, 16, 0, 1]);
+        assert_eq!(
+            Response::parse(&msg),
+            Some(Response::ResetComplete { status: 0 })
+        );
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        let mut transfer = Vec::new();
+        encode_packet(&mut transfer, &[1, 2, 3]);
+        // 中间夹一个非数据包消息
+        put(&mut transfer, &[INDICATE_STATUS_MSG, 12, 0]);
+        encode_packet(&mut transfer, &[4; 60]);
+        // 末尾的填充不足一个消息头
+        transfer.push(0);
+
+        let mut packets = Packets::new(&transfer);
+        assert_eq!(packets.next(), Some(&[1, 2, 3][..]));
+        assert_eq!(packets.next(), Some(&[4; 60][..]));
+        assert_eq!(packets.next(), None);
+    }
+
+    #[test]
+    fn malformed_length_stops() {
+        let mut transfer = Vec::new();
+        encode_packet(&mut transfer, &[9; 10]);
+        transfer[4] = 0xff;
+        assert_eq!(Packets::new(&transfer).next(), None);
+    }
+}
@@ -0,0 +1,513 @@
+#![no_std]
+
+extern crate alloc;
+
+use core::time::Duration;
+
+use alloc::vec;
+use anyhow::anyhow;
+use crab_usb::{Device, DeviceInfo, Endpoint, err::USBError};
+use log::*;
+use usb_if::{
+    descriptor::{EndpointType, InterfaceDescriptor},
+    endpoint::TransferRequest,
+    err::TransferError,
+    host::ControlSetup,
+    transfer::{Direction, Recipient, Request, RequestType},
+};
+
+pub mod msg;
+
+pub use msg::{Capabilities, Notification};
+use msg::{DevDepMsgIn, HEADER_LEN, Tag, request, status};
+
+/// Application Specific 接口类
+const CLASS_APPLICATION: u8 = 0xfe;
+/// USBTMC 子类
+const SUBCLASS_USBTMC: u8 = 0x03;
+/// USB488 子类协议
+const PROTOCOL_USB488: u8 = 0x01;
+/// 单个 DEV_DEP_MSG 携带的最大数据长度，更长的消息拆分为多次传输
+const MAX_TRANSFER: usize = 64 * 1024;
+/// 批量传输的默认超时
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// 中止或清除请求处于 PENDING 时的查询次数
+const PENDING_RETRIES: usize = 100;
+
+/// USBTMC 测试测量仪器（示波器、万用表、信号源等）
+///
+/// 按 USBTMC 批量消息收发仪器命令与响应，传输超时后自动执行中止流程，
+/// 使设备与主机的消息状态重新同步。USB488 设备还支持触发、状态字节与服务请求。
+pub struct Usbtmc {
+    device: Device,
+    interface: u8,
+    bulk_in: Endpoint,
+    bulk_out: Endpoint,
+    interrupt_in: Option<Endpoint>,
+    ep_in: u8,
+    ep_out: u8,
+    in_packet_size: usize,
+    usb488: bool,
+    caps: Capabilities,
+    tag: Tag,
+    status_tag: Tag,
+    term_char: Option<u8>,
+    /// 等待状态字节时收到的服务请求
+    pending_srq: Option<u8>,
+}
+
+impl Usbtmc {
+    /// 检查设备是否带有 USBTMC 接口
+    pub fn check(info: &DeviceInfo) -> bool {
+        info.interface_descriptors().any(is_usbtmc)
+    }
+
+    pub async fn new(mut device: Device) -> Result<Self, USBError> {
+        let (interface, alternate, usb488, ep_in, ep_out, ep_int, in_packet_size) = {
+            let config = device.configurations().first().ok_or(USBError::NotFound)?;
+            config
+                .interfaces
+                .iter()
+                .map(|iface| iface.first_alt_setting())
+                .find_map(|alt| {
+                    if !is_usbtmc(&alt) {
+                        return None;
+                    }
+                    let find = |ty: EndpointType, dir: Direction| {
+                        alt.endpoints
+                            .iter()
+                            .find(|ep| ep.transfer_type == ty && ep.direction == dir)
+                    };
+                    let ep_in = find(EndpointType::Bulk, Direction::In)?;
+                    let ep_out = find(EndpointType::Bulk, Direction::Out)?;
+                    Some((
+                        alt.interface_number,
+                        alt.alternate_setting,
+                        alt.protocol == PROTOCOL_USB488,
+                        ep_in.address,
+                        ep_out.address,
+                        find(EndpointType::Interrupt, Direction::In).map(|ep| ep.address),
+                        ep_in.max_packet_size as usize,
+                    ))
+                })
+                .ok_or(USBError::NotFound)?
+        };
+        debug!(
+            "USBTMC interface {interface}, bulk in {ep_in:#x}, bulk out {ep_out:#x}, \
+             interrupt in {ep_int:x?}"
+        );
+
+        device.claim_interface(interface, alternate).await?;
+        let mut bulk_in = device.endpoint(ep_in)?;
+        let mut bulk_out = device.endpoint(ep_out)?;
+        bulk_in.set_timeout(Some(DEFAULT_TIMEOUT));
+        bulk_out.set_timeout(Some(DEFAULT_TIMEOUT));
+        let interrupt_in = match ep_int {
+            Some(addr) => Some(device.endpoint(addr)?),
+            None => None,
+        };
+
+        let mut tmc = Self {
+            device,
+            interface,
+            bulk_in,
+            bulk_out,
+            interrupt_in,
+            ep_in,
+            ep_out,
+            in_packet_size: in_packet_size.max(1),
+            usb488,
+            caps: Capabilities::default(),
+            tag: Tag::default(),
+            status_tag: Tag::default(),
+            term_char: None,
+            pending_srq: None,
+        };
+
+        let mut buf = [0u8; Capabilities::LEN];
+        tmc.class_in(Recipient::Interface, request::GET_CAPABILITIES, 0, &mut buf)
+            .await?;
+        check_status(request::GET_CAPABILITIES, buf[0])?;
+        tmc.caps = Capabilities::parse(&buf).ok_or(anyhow!("invalid GET_CAPABILITIES"))?;
+        info!("USBTMC {:?}", tmc.caps);
+
+        // 上次使用后设备中可能残留未读完的响应
+        tmc.clear().await?;
+        Ok(tmc)
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.caps
+    }
+
+    /// 是否为 USB488 设备
+    pub fn is_usb488(&self) -> bool {
+        self.usb488
+    }
+
+    /// 设置批量传输的超时，默认 5 秒；`None` 表示不超时
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.bulk_in.set_timeout(timeout);
+        self.bulk_out.set_timeout(timeout);
+    }
+
+    /// 设置读取的结束字符，设备发送该字符后结束本次传输
+    ///
+    /// 设备不支持 TermChar 时返回 [`USBError::NotSupported`]。
+    pub fn set_term_char(&mut self, term_char: Option<u8>) -> Result<(), USBError> {
+        if term_char.is_some() && !self.caps.term_char {
+            return Err(USBError::NotSupported);
+        }
+        self.term_char = term_char;
+        Ok(())
+    }
+
+    /// 发送一条完整的设备消息（通常是一条 SCPI 命令）
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), USBError> {
+        let mut chunks = data.chunks(MAX_TRANSFER).peekable();
+        if chunks.peek().is_none() {
+            return self.send(&[], true).await;
+        }
+        while let Some(chunk) = chunks.next() {
+            self.send(chunk, chunks.peek().is_none()).await?;
+        }
+        Ok(())
+    }
+
+    /// 读取设备的响应消息，返回读取的字节数
+    ///
+    /// 收到消息结束（EOM）、结束字符或 `buf` 已满时返回；`buf` 已满而消息未结束时，
+    /// 再次调用读取剩余部分。
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, USBError> {
+        let mut len = 0;
+        while len < buf.len() {
+            let want = (buf.len() - len).min(MAX_TRANSFER);
+            let tag = self.tag.advance();
+            let req = msg::request_dev_dep_msg_in(tag, want as u32, self.term_char);
+            self.bulk_out_msg(tag, &req).await?;
+
+            let rx_len = (HEADER_LEN + want)
+                .next_multiple_of(4)
+                .next_multiple_of(self.in_packet_size);
+            let mut rx = vec![0u8; rx_len];
+            let n = match self.bulk_in.read_until_short(&mut rx).await {
+                Ok(n) => n,
+                Err(e) => {
+                    self.recover_in(tag, &e).await;
+                    return Err(e.into());
+                }
+            };
+            let header = DevDepMsgIn::parse(&rx[..n]).ok_or(anyhow!("invalid DEV_DEP_MSG_IN"))?;
+            if header.tag != tag {
+                return Err(anyhow!("bTag mismatch: {} != {}", header.tag, tag).into());
+            }
+            let size = (header.transfer_size as usize)
+                .min(n - HEADER_LEN)
+                .min(want);
+            buf[len..len + size].copy_from_slice(&rx[HEADER_LEN..HEADER_LEN + size]);
+            len += size;
+
+            let term = size > 0 && self.term_char == Some(buf[len - 1]);
+            if header.eom || term || size == 0 {
+                break;
+            }
+        }
+        Ok(len)
+    }
+
+    /// 发送命令并读取响应
+    pub async fn query(&mut self, command: &[u8], buf: &mut [u8]) -> Result<usize, USBError> {
+        self.write(command).await?;
+        self.read(buf).await
+    }
+
+    /// 中止最近一次 Bulk-OUT 传输
+    pub async fn abort_bulk_out(&mut self) -> Result<(), USBError> {
+        let tag = self.tag.last();
+        let mut buf = [0u8; 2];
+        self.class_in(
+            Recipient::Endpoint,
+            request::INITIATE_ABORT_BULK_OUT,
+            tag as u16,
+            &mut buf,
+        )
+        .await?;
+        match buf[0] {
+            status::SUCCESS => {}
+            // 设备上没有正在进行的传输
+            status::FAILED => return Ok(()),
+            s => return check_status(request::INITIATE_ABORT_BULK_OUT, s),
+        }
+
+        self.wait_pending(Recipient::Endpoint, request::CHECK_ABORT_BULK_OUT_STATUS)
+            .await?;
+        self.bulk_out.clear_halt(&mut self.device).await?;
+        Ok(())
+    }
+
+    /// 中止最近一次 Bulk-IN 传输，丢弃设备尚未发出的数据
+    pub async fn abort_bulk_in(&mut self) -> Result<(), USBError> {
+        let tag = self.tag.last();
+        let mut buf = [0u8; 2];
+        self.class_in(
+            Recipient::Endpoint,
+            request::INITIATE_ABORT_BULK_IN,
+            tag as u16,
+            &mut buf,
+        )
+        .await?;
+        match buf[0] {
+            status::SUCCESS => {}
+            status::FAILED => return Ok(()),
+            s => return check_status(request::INITIATE_ABORT_BULK_IN, s),
+        }
+
+        self.drain_bulk_in().await;
+        self.wait_pending(Recipient::Endpoint, request::CHECK_ABORT_BULK_IN_STATUS)
+            .await
+    }
+
+    /// 清除设备的输入输出缓冲区与消息状态（相当于 IEEE 488.1 的 SDC）
+    pub async fn clear(&mut self) -> Result<(), USBError> {
+        let mut buf = [0u8; 1];
+        self.class_in(Recipient::Interface, request::INITIATE_CLEAR, 0, &mut buf)
+            .await?;
+        check_status(request::INITIATE_CLEAR, buf[0])?;
+
+        self.wait_pending(Recipient::Interface, request::CHECK_CLEAR_STATUS)
+            .await?;
+        self.bulk_out.clear_halt(&mut self.device).await?;
+        Ok(())
+    }
+
+    /// 让设备的指示灯闪烁，用于在多台仪器中辨认设备
+    pub async fn indicator_pulse(&mut self) -> Result<(), USBError> {
+        if !self.caps.indicator_pulse {
+            return Err(USBError::NotSupported);
+        }
+        self.simple_request(request::INDICATOR_PULSE, 0).await
+    }
+
+    /// USB488 TRIGGER
+    pub async fn trigger(&mut self) -> Result<(), USBError> {
+        if !self.caps.trigger {
+            return Err(USBError::NotSupported);
+        }
+        let tag = self.tag.advance();
+        self.bulk_out_msg(tag, &msg::trigger(tag)).await
+    }
+
+    /// 读取 IEEE 488.2 状态字节
+    ///
+    /// 有中断端点时状态字节经中断端点返回，期间收到的服务请求留给
+    /// [`Usbtmc::wait_service_request`]。
+    pub async fn read_status_byte(&mut self) -> Result<u8, USBError> {
+        if !self.usb488 {
+            return Err(USBError::NotSupported);
+        }
+        let tag = self.status_tag.next_status();
+        let mut buf = [0u8; 3];
+        self.class_in(
+            Recipient::Interface,
+            request::READ_STATUS_BYTE,
+            tag as u16,
+            &mut buf,
+        )
+        .await?;
+        check_status(request::READ_STATUS_BYTE, buf[0])?;
+
+        if self.interrupt_in.is_none() {
+            return Ok(buf[2]);
+        }
+        loop {
+            match self.next_notification().await? {
+                Notification::StatusByte { tag: t, status } if t == tag => return Ok(status),
+                Notification::ServiceRequest(status) => self.pending_srq = Some(status),
+                n => trace!("USBTMC skip notification {n:?}"),
+            }
+        }
+    }
+
+    /// 等待设备发出服务请求（SRQ），返回附带的状态字节
+    pub async fn wait_service_request(&mut self) -> Result<u8, USBError> {
+        if let Some(status) = self.pending_srq.take() {
+            return Ok(status);
+        }
+        loop {
+            match self.next_notification().await? {
+                Notification::ServiceRequest(status) => return Ok(status),
+                n => trace!("USBTMC skip notification {n:?}"),
+            }
+        }
+    }
+
+    /// USB488 REN_CONTROL，置位后设备在收到命令时进入远程状态
+    pub async fn ren_control(&mut self, enable: bool) -> Result<(), USBError> {
+        if !self.caps.remote_local {
+            return Err(USBError::NotSupported);
+        }
+        self.simple_request(request::REN_CONTROL, enable as u16)
+            .await
+    }
+
+    /// USB488 GO_TO_LOCAL
+    pub async fn go_to_local(&mut self) -> Result<(), USBError> {
+        if !self.caps.remote_local {
+            return Err(USBError::NotSupported);
+        }
+        self.simple_request(request::GO_TO_LOCAL, 0).await
+    }
+
+    /// USB488 LOCAL_LOCKOUT，禁用仪器面板上的本地操作
+    pub async fn local_lockout(&mut self) -> Result<(), USBError> {
+        if !self.caps.remote_local {
+            return Err(USBError::NotSupported);
+        }
+        self.simple_request(request::LOCAL_LOCKOUT, 0).await
+    }
+
+    async fn send(&mut self, data: &[u8], eom: bool) -> Result<(), USBError> {
+        let tag = self.tag.advance();
+        self.bulk_out_msg(tag, &msg::dev_dep_msg_out(tag, data, eom))
+            .await
+    }
+
+    async fn bulk_out_msg(&mut self, tag: u8, data: &[u8]) -> Result<(), USBError> {
+        if let Err(e) = self.bulk_out.wait(TransferRequest::bulk_out(data)).await {
+            self.recover_out(tag, &e).await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Bulk-OUT 传输失败后恢复：超时时中止传输，STALL 时清除端点
+    async fn recover_out(&mut self, tag: u8, err: &TransferError) {
+        let res = match err {
+            TransferError::Timeout => {
+                debug!("USBTMC bulk out timeout, abort bTag {tag}");
+                self.abort_bulk_out().await
+            }
+            e if e.is_stall() => self
+                .bulk_out
+                .clear_halt(&mut self.device)
+                .await
+                .map_err(USBError::from),
+            _ => Ok(()),
+        };
+        if let Err(e) = res {
+            warn!("USBTMC bulk out recovery failed: {e:?}");
+        }
+    }
+
+    /// Bulk-IN 传输失败后恢复，同 [`Usbtmc::recover_out`]
+    async fn recover_in(&mut self, tag: u8, err: &TransferError) {
+        let res = match err {
+            TransferError::Timeout => {
+                debug!("USBTMC bulk in timeout, abort bTag {tag}");
+                self.abort_bulk_in().await
+            }
+            e if e.is_stall() => self
+                .bulk_in
+                .clear_halt(&mut self.device)
+                .await
+                .map_err(USBError::from),
+            _ => Ok(()),
+        };
+        if let Err(e) = res {
+            warn!("USBTMC bulk in recovery failed: {e:?}");
+        }
+    }
+
+    /// 读取 Bulk-IN 直到短包，丢弃数据
+    async fn drain_bulk_in(&mut self) {
+        let mut buf = vec![0u8; self.in_packet_size * 8];
+        if let Err(e) = self.bulk_in.read_until_short(&mut buf).await {
+            debug!("USBTMC drain bulk in: {e:?}");
+        }
+    }
+
+    /// 查询中止或清除的状态直到完成，期间按设备要求清空 Bulk-IN
+    async fn wait_pending(&mut self, recipient: Recipient, req: u8) -> Result<(), USBError> {
+        // CHECK_CLEAR_STATUS 响应 2 字节，CHECK_ABORT_* 响应 8 字节
+        let len = if req == request::CHECK_CLEAR_STATUS {
+            2
+        } else {
+            8
+        };
+        let mut buf = [0u8; 8];
+        for _ in 0..PENDING_RETRIES {
+            self.class_in(recipient, req, 0, &mut buf[..len]).await?;
+            match buf[0] {
+                status::PENDING => {
+                    // bmAbortBulkIn / bmClear 的 bit 0：Bulk-IN 中仍有数据
+                    if req != request::CHECK_ABORT_BULK_OUT_STATUS && buf[1] & 1 != 0 {
+                        self.drain_bulk_in().await;
+                    }
+                }
+                s => return check_status(req, s),
+            }
+        }
+        Err(USBError::Timeout)
+    }
+
+    async fn next_notification(&mut self) -> Result<Notification, USBError> {
+        let ep = self.interrupt_in.as_mut().ok_or(USBError::NotSupported)?;
+        let mut buf = [0u8; 2];
+        loop {
+            let t = ep.wait(TransferRequest::interrupt_in(&mut buf)).await?;
+            if let Some(n) = Notification::parse(&buf[..t.actual_length]) {
+                return Ok(n);
+            }
+        }
+    }
+
+    /// 只返回状态字节的接口请求
+    async fn simple_request(&mut self, req: u8, value: u16) -> Result<(), USBError> {
+        let mut buf = [0u8; 1];
+        self.class_in(Recipient::Interface, req, value, &mut buf)
+            .await?;
+        check_status(req, buf[0])
+    }
+
+    /// 发起类请求，接收者为端点时按请求选择 Bulk-IN 或 Bulk-OUT 端点
+    async fn class_in(
+        &mut self,
+        recipient: Recipient,
+        req: u8,
+        value: u16,
+        buf: &mut [u8],
+    ) -> Result<(), USBError> {
+        let index = match (recipient, req) {
+            (
+                Recipient::Endpoint,
+                request::INITIATE_ABORT_BULK_OUT | request::CHECK_ABORT_BULK_OUT_STATUS,
+            ) => self.ep_out,
+            (Recipient::Endpoint, _) => self.ep_in,
+            _ => self.interface,
+        };
+        let setup = ControlSetup {
+            request_type: RequestType::Class,
+            recipient,
+            request: Request::Class(req),
+            value,
+            index: index as u16,
+        };
+        let len = self.device.control_in(setup, buf).await?;
+        if len == 0 {
+            return Err(anyhow!("empty response to USBTMC request {req}").into());
+        }
+        Ok(())
+    }
+}
+
+fn is_usbtmc(alt: &InterfaceDescriptor) -> bool {
+    alt.class == CLASS_APPLICATION && alt.subclass == SUBCLASS_USBTMC
+}
+
+fn check_status(req: u8, status: u8) -> Result<(), USBError> {
+    match status {
+        status::SUCCESS => Ok(()),
+        status::FAILED => Err(anyhow!("USBTMC request {req} failed").into()),
+        s => Err(anyhow!("USBTMC request {req}: status {s:#04x}").into()),
+    }
+}
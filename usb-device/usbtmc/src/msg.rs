@@ -0,0 +1,321 @@
+//! USBTMC / USB488 消息格式（USBTMC 1.0、USBTMC-USB488 1.0）
+//!
+//! 批量端点上的每个消息以 12 字节头部开始：MsgID、bTag、bTag 反码、保留字节，
+//! 其后 8 字节由 MsgID 决定。消息总长按 4 字节对齐，不足时补 0。
+
+use alloc::vec::Vec;
+
+/// 批量消息头部长度
+pub const HEADER_LEN: usize = 12;
+
+/// 批量消息的 MsgID
+pub mod msg_id {
+    pub const DEV_DEP_MSG_OUT: u8 = 1;
+    /// Bulk-OUT 上为 REQUEST_DEV_DEP_MSG_IN，Bulk-IN 上为 DEV_DEP_MSG_IN
+    pub const DEV_DEP_MSG_IN: u8 = 2;
+    pub const VENDOR_SPECIFIC_OUT: u8 = 126;
+    pub const REQUEST_VENDOR_SPECIFIC_IN: u8 = 127;
+    /// USB488 TRIGGER
+    pub const TRIGGER: u8 = 128;
+}
+
+/// USBTMC 与 USB488 类请求
+pub mod request {
+    pub const INITIATE_ABORT_BULK_OUT: u8 = 1;
+    pub const CHECK_ABORT_BULK_OUT_STATUS: u8 = 2;
+    pub const INITIATE_ABORT_BULK_IN: u8 = 3;
+    pub const CHECK_ABORT_BULK_IN_STATUS: u8 = 4;
+    pub const INITIATE_CLEAR: u8 = 5;
+    pub const CHECK_CLEAR_STATUS: u8 = 6;
+    pub const GET_CAPABILITIES: u8 = 7;
+    pub const INDICATOR_PULSE: u8 = 64;
+    pub const READ_STATUS_BYTE: u8 = 128;
+    pub const REN_CONTROL: u8 = 160;
+    pub const GO_TO_LOCAL: u8 = 161;
+    pub const LOCAL_LOCKOUT: u8 = 162;
+}
+
+/// 类请求响应中的 USBTMC_status
+pub mod status {
+    pub const SUCCESS: u8 = 0x01;
+    pub const PENDING: u8 = 0x02;
+    pub const INTERRUPT_IN_BUSY: u8 = 0x20;
+    pub const FAILED: u8 = 0x80;
+    pub const TRANSFER_NOT_IN_PROGRESS: u8 = 0x81;
+    pub const SPLIT_NOT_IN_PROGRESS: u8 = 0x82;
+    pub const SPLIT_IN_PROGRESS: u8 = 0x83;
+}
+
+/// DEV_DEP_MSG 的 bmTransferAttributes
+const ATTR_EOM: u8 = 0x01;
+const ATTR_TERM_CHAR: u8 = 0x02;
+
+fn header(buf: &mut Vec<u8>, id: u8, tag: u8) {
+    buf.extend_from_slice(&[id, tag, !tag, 0]);
+}
+
+fn pad(buf: &mut Vec<u8>) {
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+/// DEV_DEP_MSG_OUT，`eom` 表示 `data` 是消息的最后一段
+pub fn dev_dep_msg_out(tag: u8, data: &[u8], eom: bool) -> Vec<u8> {
+    let mut buf = Vec::with_capacity((HEADER_LEN + data.len()).next_multiple_of(4));
+    header(&mut buf, msg_id::DEV_DEP_MSG_OUT, tag);
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&[if eom { ATTR_EOM } else { 0 }, 0, 0, 0]);
+    buf.extend_from_slice(data);
+    pad(&mut buf);
+    buf
+}
+
+/// REQUEST_DEV_DEP_MSG_IN，请求设备最多发送 `size` 字节
+///
+/// 指定 `term_char` 时设备在发送该字符后结束本次传输，需要设备支持 TermChar。
+pub fn request_dev_dep_msg_in(tag: u8, size: u32, term_char: Option<u8>) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN);
+    header(&mut buf, msg_id::DEV_DEP_MSG_IN, tag);
+    buf.extend_from_slice(&size.to_le_bytes());
+    match term_char {
+        Some(c) => buf.extend_from_slice(&[ATTR_TERM_CHAR, c, 0, 0]),
+        None => buf.extend_from_slice(&[0; 4]),
+    }
+    buf
+}
+
+/// USB488 TRIGGER，相当于 IEEE 488.1 的 GET
+pub fn trigger(tag: u8) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN);
+    header(&mut buf, msg_id::TRIGGER, tag);
+    buf.extend_from_slice(&[0; 8]);
+    buf
+}
+
+/// Bulk-IN 上的 DEV_DEP_MSG_IN 头部
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DevDepMsgIn {
+    pub tag: u8,
+    /// 本次传输的数据字节数，不含头部与对齐
+    pub transfer_size: u32,
+    /// 数据是消息的最后一段
+    pub eom: bool,
+}
+
+impl DevDepMsgIn {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let h = data.get(..HEADER_LEN)?;
+        if h[0] != msg_id::DEV_DEP_MSG_IN || h[1] != !h[2] {
+            return None;
+        }
+        Some(Self {
+            tag: h[1],
+            transfer_size: u32::from_le_bytes(h[4..8].try_into().ok()?),
+            eom: h[8] & ATTR_EOM != 0,
+        })
+    }
+}
+
+/// GET_CAPABILITIES 的响应
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// bcdUSBTMC
+    pub usbtmc_version: u16,
+    pub indicator_pulse: bool,
+    /// 只能发送数据（talk-only）
+    pub talk_only: bool,
+    /// 只能接收数据（listen-only）
+    pub listen_only: bool,
+    /// 支持 REQUEST_DEV_DEP_MSG_IN 的 TermChar
+    pub term_char: bool,
+    /// bcdUSB488，非 USB488 设备为 0
+    pub usb488_version: u16,
+    /// 支持 IEEE 488.2 命令
+    pub ieee488_2: bool,
+    /// 支持 REN_CONTROL、GO_TO_LOCAL、LOCAL_LOCKOUT
+    pub remote_local: bool,
+    /// 支持 TRIGGER
+    pub trigger: bool,
+    /// 理解 SCPI 命令
+    pub scpi: bool,
+    /// 支持服务请求（SR1）
+    pub service_request: bool,
+}
+
+impl Capabilities {
+    /// 响应长度
+    pub const LEN: usize = 0x18;
+
+    /// 解析响应，首字节为 USBTMC_status
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < Self::LEN {
+            return None;
+        }
+        let bit = |byte: u8, n: u8| byte & (1 << n) != 0;
+        Some(Self {
+            usbtmc_version: u16::from_le_bytes([data[2], data[3]]),
+            indicator_pulse: bit(data[4], 2),
+            talk_only: bit(data[4], 1),
+            listen_only: bit(data[4], 0),
+            term_char: bit(data[5], 0),
+            usb488_version: u16::from_le_bytes([data[12], data[13]]),
+            ieee488_2: bit(data[14], 2),
+            remote_local: bit(data[14], 1),
+            trigger: bit(data[14], 0),
+            scpi: bit(data[15], 3),
+            service_request: bit(data[15], 2),
+        })
+    }
+}
+
+/// USB488 中断端点上的通知
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notification {
+    /// 设备请求服务，附带状态字节
+    ServiceRequest(u8),
+    /// READ_STATUS_BYTE 的响应
+    StatusByte { tag: u8, status: u8 },
+    /// 厂商自定义通知
+    Vendor([u8; 2]),
+}
+
+impl Notification {
+    /// bNotify1 为 0x81 时表示 SRQ，bTag 1 因此不用于 READ_STATUS_BYTE
+    pub const SRQ: u8 = 0x81;
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let &[b1, b2, ..] = data else {
+            return None;
+        };
+        Some(match b1 {
+            Self::SRQ => Self::ServiceRequest(b2),
+            b if b & 0x80 != 0 => Self::StatusByte {
+                tag: b & 0x7f,
+                status: b2,
+            },
+            _ => Self::Vendor([b1, b2]),
+        })
+    }
+}
+
+/// 批量消息的 bTag，取值 1..=255
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tag(u8);
+
+impl Tag {
+    pub fn advance(&mut self) -> u8 {
+        self.0 = self.0.wrapping_add(1).max(1);
+        self.0
+    }
+
+    /// 最近一次分配的 bTag
+    pub fn last(&self) -> u8 {
+        self.0
+    }
+
+    /// READ_STATUS_BYTE 使用的 bTag，取值 2..=127
+    pub fn next_status(&mut self) -> u8 {
+        self.0 = if (2..127).contains(&self.0) {
+            self.0 + 1
+        } else {
+            2
+        };
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_message_is_padded() {
+        let msg = dev_dep_msg_out(3, b"*IDN?\n", true);
+        assert_eq!(msg.len(), 20);
+        assert_eq!(&msg[..4], &[1, 3, 0xfc, 0]);
+        assert_eq!(&msg[4..8], &6u32.to_le_bytes());
+        assert_eq!(msg[8], ATTR_EOM);
+        assert_eq!(&msg[12..18], b"*IDN?\n");
+        assert_eq!(&msg[18..], &[0, 0]);
+
+        let msg = dev_dep_msg_out(4, b"ABCD", false);
+        assert_eq!(msg.len(), 16);
+        assert_eq!(msg[8], 0);
+    }
+
+    #[test]
+    fn request_in_with_term_char() {
+        let msg = request_dev_dep_msg_in(0xff, 1024, Some(b'\n'));
+        assert_eq!(&msg[..4], &[2, 0xff, 0x00, 0]);
+        assert_eq!(&msg[4..8], &1024u32.to_le_bytes());
+        assert_eq!(&msg[8..], &[ATTR_TERM_CHAR, b'\n', 0, 0]);
+        assert_eq!(request_dev_dep_msg_in(1, 8, None)[8], 0);
+    }
+
+    #[test]
+    fn parse_in_header() {
+        let mut data = [2, 7, !7, 0, 5, 0, 0, 0, 1, 0, 0, 0].to_vec();
+        data.extend_from_slice(b"1.23\n\0\0\0");
+        assert_eq!(
+            DevDepMsgIn::parse(&data),
+            Some(DevDepMsgIn {
+                tag: 7,
+                transfer_size: 5,
+                eom: true
+            })
+        );
+        // bTag 反码不符
+        data[2] = 0;
+        assert_eq!(DevDepMsgIn::parse(&data), None);
+    }
+
+    #[test]
+    fn capabilities() {
+        let mut data = [0u8; Capabilities::LEN];
+        data[0] = status::SUCCESS;
+        data[2..4].copy_from_slice(&0x0100u16.to_le_bytes());
+        data[4] = 0b100;
+        data[5] = 1;
+        data[12..14].copy_from_slice(&0x0100u16.to_le_bytes());
+        data[14] = 0b111;
+        data[15] = 0b1100;
+        let caps = Capabilities::parse(&data).unwrap();
+        assert!(caps.indicator_pulse && caps.term_char && caps.trigger);
+        assert!(caps.scpi && caps.service_request && caps.remote_local);
+        assert!(!caps.talk_only && !caps.listen_only);
+        assert_eq!(caps.usb488_version, 0x0100);
+        assert_eq!(Capabilities::parse(&data[..8]), None);
+    }
+
+    #[test]
+    fn notifications() {
+        assert_eq!(
+            Notification::parse(&[0x81, 0x40]),
+            Some(Notification::ServiceRequest(0x40))
+        );
+        assert_eq!(
+            Notification::parse(&[0x85, 0x10]),
+            Some(Notification::StatusByte {
+                tag: 5,
+                status: 0x10
+            })
+        );
+        assert_eq!(
+            Notification::parse(&[0x01, 0x02]),
+            Some(Notification::Vendor([1, 2]))
+        );
+        assert_eq!(Notification::parse(&[0x81]), None);
+    }
+
+    #[test]
+    fn tags_skip_reserved_values() {
+        let mut tag = Tag::default();
+        assert_eq!(tag.advance(), 1);
+        let mut tag = Tag(255);
+        assert_eq!(tag.advance(), 1);
+        assert_eq!(tag.next_status(), 2);
+        let mut tag = Tag(127);
+        assert_eq!(tag.next_status(), 2);
+        let mut tag = Tag(126);
+        assert_eq!(tag.next_status(), 127);
+    }
+}
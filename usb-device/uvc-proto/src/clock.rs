@@ -0,0 +1,129 @@
+//! 设备时钟与主机时间的映射（2.4.3.3）
+//!
+//! 载荷头中的 PTS 与 SCR 的 STC 部分以设备时钟（dwClockFrequency）计数，
+//! SCR 的 SOF 部分为采样时的 USB 帧号。把带 SCR 的载荷与主机收到它的时间配对，
+//! 拟合设备时钟相对主机时钟的速率后，即可把帧的 PTS 换算成主机时间，用于音视频同步。
+
+use alloc::collections::VecDeque;
+use core::time::Duration;
+
+/// 载荷头中的 Source Clock Reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceClock {
+    /// 设备时钟计数（dwSourceTimeClock 的 STC 部分）
+    pub stc: u32,
+    /// 采样时的 USB 帧号，低 11 位有效
+    pub sof: u16,
+}
+
+impl SourceClock {
+    pub fn sof_frame(&self) -> u16 {
+        self.sof & 0x7ff
+    }
+}
+
+/// 参与拟合的样本数
+const CLOCK_SAMPLES: usize = 32;
+
+/// 把设备时钟计数换算为主机时间
+///
+/// 只有一个样本时按标称频率换算；有多个样本时用窗口内最早与最新的样本估计设备时钟相对
+/// 主机时钟的速率，消除晶振偏差带来的漂移。样本的主机时间通常取传输完成的时刻，
+/// 固定的传输延迟使结果整体偏后，但不影响帧间间隔。
+#[derive(Debug, Clone)]
+pub struct ClockMapper {
+    frequency: u32,
+    samples: VecDeque<(u32, Duration)>,
+}
+
+impl ClockMapper {
+    /// `frequency` 为设备时钟频率（Hz），为 0 时无法换算，返回 `None`
+    pub fn new(frequency: u32) -> Option<Self> {
+        (frequency != 0).then(|| Self {
+            frequency,
+            samples: VecDeque::with_capacity(CLOCK_SAMPLES),
+        })
+    }
+
+    pub fn frequency(&self) -> u32 {
+        self.frequency
+    }
+
+    /// 记录一个样本：主机在 `host` 时刻收到了 STC 为 `scr.stc` 的载荷
+    ///
+    /// 主机时间回退时（例如换了时钟源）丢弃之前的样本。
+    pub fn add_sample(&mut self, scr: SourceClock, host: Duration) {
+        if self.samples.back().is_some_and(|&(_, last)| host < last) {
+            self.samples.clear();
+        }
+        if self.samples.len() == CLOCK_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((scr.stc, host));
+    }
+
+    /// 丢弃全部样本，例如重新开流之后
+    pub fn reset(&mut self) {
+        self.samples.clear();
+    }
+
+    /// 把设备时钟计数 `ticks`（例如帧的 PTS）换算为主机时间，没有样本时为 `None`
+    ///
+    /// 设备时钟按 32 位回绕，`ticks` 与最新样本的差按有符号数处理，
+    /// 因此只能换算前后约 2^31 个时钟周期内的时刻。
+    pub fn to_host(&self, ticks: u32) -> Option<Duration> {
+        let &(stc1, host1) = self.samples.back()?;
+        let &(stc0, host0) = self.samples.front()?;
+
+        let dev_ticks = stc1.wrapping_sub(stc0) as i128;
+        let host_ns = (host1 - host0).as_nanos() as i128;
+        let delta = ticks.wrapping_sub(stc1) as i32 as i128;
+        let offset_ns = if dev_ticks > 0 && host_ns > 0 {
+            delta * host_ns / dev_ticks
+        } else {
+            delta * 1_000_000_000 / self.frequency as i128
+        };
+
+        let ns = host1.as_nanos() as i128 + offset_ns;
+        (ns >= 0).then(|| Duration::from_nanos(ns as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scr(stc: u32) -> SourceClock {
+        SourceClock { stc, sof: 0 }
+    }
+
+    #[test]
+    fn nominal_rate_with_one_sample() {
+        let mut m = ClockMapper::new(1_000_000).unwrap();
+        assert_eq!(m.to_host(0), None);
+        m.add_sample(scr(5_000), Duration::from_millis(100));
+        assert_eq!(m.to_host(15_000), Some(Duration::from_millis(110)));
+        assert_eq!(m.to_host(4_000), Some(Duration::from_millis(99)));
+    }
+
+    #[test]
+    fn fitted_rate_and_wraparound() {
+        // 设备时钟比标称值快 1%
+        let mut m = ClockMapper::new(1_000_000).unwrap();
+        m.add_sample(scr(u32::MAX - 9_999), Duration::from_secs(1));
+        // 1 秒内计数 1_010_000 次，期间 STC 回绕
+        m.add_sample(scr(1_000_000), Duration::from_secs(2));
+        // 最新样本之后 101_000 个周期对应主机 100ms
+        let host = m.to_host(1_101_000).unwrap();
+        assert_eq!(host, Duration::from_millis(2_100));
+    }
+
+    #[test]
+    fn host_time_going_back_restarts() {
+        let mut m = ClockMapper::new(1_000).unwrap();
+        m.add_sample(scr(0), Duration::from_secs(10));
+        m.add_sample(scr(1_000), Duration::from_secs(1));
+        assert_eq!(m.to_host(2_000), Some(Duration::from_secs(2)));
+        assert_eq!(ClockMapper::new(0).map(|m| m.frequency()), None);
+    }
+}
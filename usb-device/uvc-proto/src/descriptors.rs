@@ -0,0 +1,1286 @@
+use alloc::vec::Vec;
+use anyhow::anyhow;
+use log::trace;
+use usb_if::err::USBError;
+
+// UVC描述符解析和常量定义模块
+// 参考libuvc的实现结构
+
+/// UVC类特定请求常量 (A.8)
+pub mod request_codes {
+    pub const SET_CUR: u8 = 0x01;
+    pub const GET_CUR: u8 = 0x81;
+    pub const GET_MIN: u8 = 0x82;
+    pub const GET_MAX: u8 = 0x83;
+    pub const GET_RES: u8 = 0x84;
+    pub const GET_LEN: u8 = 0x85;
+    pub const GET_INFO: u8 = 0x86;
+    pub const GET_DEF: u8 = 0x87;
+}
+
+/// UVC接口子类代码 (A.2)
+pub mod interface_subclass {
+    pub const UNDEFINED: u8 = 0x00;
+    pub const VIDEO_CONTROL: u8 = 0x01;
+    pub const VIDEO_STREAMING: u8 = 0x02;
+    pub const VIDEO_INTERFACE_COLLECTION: u8 = 0x03;
+}
+
+/// UVC协议代码 (A.3)
+pub mod protocol_codes {
+    pub const UNDEFINED: u8 = 0x00;
+}
+
+/// VideoControl接口描述符子类型 (A.5)
+pub mod vc_descriptor_subtypes {
+    pub const UNDEFINED: u8 = 0x00;
+    pub const HEADER: u8 = 0x01;
+    pub const INPUT_TERMINAL: u8 = 0x02;
+    pub const OUTPUT_TERMINAL: u8 = 0x03;
+    pub const SELECTOR_UNIT: u8 = 0x04;
+    pub const PROCESSING_UNIT: u8 = 0x05;
+    pub const EXTENSION_UNIT: u8 = 0x06;
+}
+
+/// VideoStreaming接口描述符子类型 (A.6)
+pub mod vs_descriptor_subtypes {
+    pub const UNDEFINED: u8 = 0x00;
+    pub const INPUT_HEADER: u8 = 0x01;
+    pub const OUTPUT_HEADER: u8 = 0x02;
+    pub const STILL_IMAGE_FRAME: u8 = 0x03;
+    pub const FORMAT_UNCOMPRESSED: u8 = 0x04;
+    pub const FRAME_UNCOMPRESSED: u8 = 0x05;
+    pub const FORMAT_MJPEG: u8 = 0x06;
+    pub const FRAME_MJPEG: u8 = 0x07;
+    pub const FORMAT_MPEG2TS: u8 = 0x0A;
+    pub const FORMAT_DV: u8 = 0x0C;
+    pub const COLORFORMAT: u8 = 0x0D;
+    pub const FORMAT_FRAME_BASED: u8 = 0x10;
+    pub const FRAME_FRAME_BASED: u8 = 0x11;
+    pub const FORMAT_STREAM_BASED: u8 = 0x12;
+    pub const FORMAT_H264: u8 = 0x13;
+    pub const FRAME_H264: u8 = 0x14;
+    pub const FORMAT_H264_SIMULCAST: u8 = 0x15;
+}
+
+/// UVC描述符类型
+pub mod descriptor_types {
+    pub const DEVICE: u8 = 0x01;
+    pub const CONFIGURATION: u8 = 0x02;
+    pub const STRING: u8 = 0x03;
+    pub const INTERFACE: u8 = 0x04;
+    pub const ENDPOINT: u8 = 0x05;
+    pub const CS_INTERFACE: u8 = 0x24;
+    pub const CS_ENDPOINT: u8 = 0x25;
+}
+
+/// 摄像头终端控制选择器 (A.9.4)
+pub mod camera_terminal_controls {
+    pub const UNDEFINED: u8 = 0x00;
+    pub const SCANNING_MODE: u8 = 0x01;
+    pub const AE_MODE: u8 = 0x02;
+    pub const AE_PRIORITY: u8 = 0x03;
+    pub const EXPOSURE_TIME_ABSOLUTE: u8 = 0x04;
+    pub const EXPOSURE_TIME_RELATIVE: u8 = 0x05;
+    pub const FOCUS_ABSOLUTE: u8 = 0x06;
+    pub const FOCUS_RELATIVE: u8 = 0x07;
+    pub const FOCUS_AUTO: u8 = 0x08;
+    pub const IRIS_ABSOLUTE: u8 = 0x09;
+    pub const IRIS_RELATIVE: u8 = 0x0A;
+    pub const ZOOM_ABSOLUTE: u8 = 0x0B;
+    pub const ZOOM_RELATIVE: u8 = 0x0C;
+    pub const PANTILT_ABSOLUTE: u8 = 0x0D;
+    pub const PANTILT_RELATIVE: u8 = 0x0E;
+    pub const ROLL_ABSOLUTE: u8 = 0x0F;
+    pub const ROLL_RELATIVE: u8 = 0x10;
+    pub const PRIVACY: u8 = 0x11;
+    pub const FOCUS_SIMPLE: u8 = 0x12;
+    pub const DIGITAL_WINDOW: u8 = 0x13;
+    pub const REGION_OF_INTEREST: u8 = 0x14;
+}
+
+/// 处理单元控制选择器 (A.9.5)
+pub mod processing_unit_controls {
+    pub const UNDEFINED: u8 = 0x00;
+    pub const BACKLIGHT_COMPENSATION: u8 = 0x01;
+    pub const BRIGHTNESS: u8 = 0x02;
+    pub const CONTRAST: u8 = 0x03;
+    pub const GAIN: u8 = 0x04;
+    pub const POWER_LINE_FREQUENCY: u8 = 0x05;
+    pub const HUE: u8 = 0x06;
+    pub const SATURATION: u8 = 0x07;
+    pub const SHARPNESS: u8 = 0x08;
+    pub const GAMMA: u8 = 0x09;
+    pub const WHITE_BALANCE_TEMPERATURE: u8 = 0x0A;
+    pub const WHITE_BALANCE_TEMPERATURE_AUTO: u8 = 0x0B;
+    pub const WHITE_BALANCE_COMPONENT: u8 = 0x0C;
+    pub const WHITE_BALANCE_COMPONENT_AUTO: u8 = 0x0D;
+    pub const DIGITAL_MULTIPLIER: u8 = 0x0E;
+    pub const DIGITAL_MULTIPLIER_LIMIT: u8 = 0x0F;
+    pub const HUE_AUTO: u8 = 0x10;
+    pub const ANALOG_VIDEO_STANDARD: u8 = 0x11;
+    pub const ANALOG_LOCK_STATUS: u8 = 0x12;
+    pub const CONTRAST_AUTO: u8 = 0x13;
+}
+
+/// 处理单元控制值每个分量的字节数与是否有符号 (4.2.2.3)
+///
+/// White Balance Component 由蓝、红两个 u16 分量组成，分量数见 [`pu_control_components`]。
+pub fn pu_control_layout(selector: u8) -> Option<(usize, bool)> {
+    use processing_unit_controls::*;
+    Some(match selector {
+        BRIGHTNESS | HUE => (2, true),
+        CONTRAST
+        | SATURATION
+        | SHARPNESS
+        | GAMMA
+        | WHITE_BALANCE_TEMPERATURE
+        | BACKLIGHT_COMPENSATION
+        | GAIN
+        | DIGITAL_MULTIPLIER
+        | DIGITAL_MULTIPLIER_LIMIT
+        | WHITE_BALANCE_COMPONENT => (2, false),
+        POWER_LINE_FREQUENCY
+        | HUE_AUTO
+        | WHITE_BALANCE_TEMPERATURE_AUTO
+        | WHITE_BALANCE_COMPONENT_AUTO
+        | ANALOG_VIDEO_STANDARD
+        | ANALOG_LOCK_STATUS
+        | CONTRAST_AUTO => (1, false),
+        _ => return None,
+    })
+}
+
+/// 处理单元控制值的分量数，White Balance Component 依次为蓝、红两个分量
+pub fn pu_control_components(selector: u8) -> usize {
+    match selector {
+        processing_unit_controls::WHITE_BALANCE_COMPONENT => 2,
+        _ => 1,
+    }
+}
+
+/// 处理单元控制支持的查询请求 (4.2.2.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlRequests {
+    /// 支持 GET_MIN/GET_MAX/GET_RES/GET_DEF
+    Range,
+    /// 开关或枚举类控制只支持 GET_CUR/GET_DEF/GET_INFO，取值范围由规范固定
+    Default { min: i64, max: i64 },
+    /// 只读状态只支持 GET_CUR/GET_INFO
+    ReadOnly { min: i64, max: i64 },
+}
+
+/// 处理单元控制支持的查询请求，不能对只支持 GET_DEF 的控制发送 GET_MIN 等请求
+pub fn pu_control_requests(selector: u8) -> ControlRequests {
+    use processing_unit_controls::*;
+    match selector {
+        HUE_AUTO
+        | WHITE_BALANCE_TEMPERATURE_AUTO
+        | WHITE_BALANCE_COMPONENT_AUTO
+        | CONTRAST_AUTO => ControlRequests::Default { min: 0, max: 1 },
+        // UVC 1.5 增加了 Auto (3)
+        POWER_LINE_FREQUENCY => ControlRequests::Default { min: 0, max: 3 },
+        ANALOG_VIDEO_STANDARD => ControlRequests::ReadOnly { min: 0, max: 5 },
+        ANALOG_LOCK_STATUS => ControlRequests::ReadOnly { min: 0, max: 1 },
+        _ => ControlRequests::Range,
+    }
+}
+
+/// 按控制值布局解码小端整数
+pub fn decode_control_value(data: &[u8], signed: bool) -> Option<i64> {
+    Some(match (data.len(), signed) {
+        (1, false) => data[0] as i64,
+        (1, true) => data[0] as i8 as i64,
+        (2, false) => u16::from_le_bytes(data.try_into().ok()?) as i64,
+        (2, true) => i16::from_le_bytes(data.try_into().ok()?) as i64,
+        (4, false) => u32::from_le_bytes(data.try_into().ok()?) as i64,
+        (4, true) => i32::from_le_bytes(data.try_into().ok()?) as i64,
+        _ => return None,
+    })
+}
+
+/// 控制的取值范围（GET_MIN/GET_MAX/GET_RES/GET_DEF）与能力（GET_INFO）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlRange {
+    pub min: i64,
+    pub max: i64,
+    /// 步长
+    pub res: i64,
+    /// 默认值
+    pub def: i64,
+    /// GET_INFO 返回的能力位，见 [`control_capabilities`]
+    pub info: u8,
+}
+
+impl ControlRange {
+    /// 是否支持 SET_CUR
+    pub fn is_settable(&self) -> bool {
+        self.info & control_capabilities::SET != 0
+    }
+
+    /// 控制当前被禁用（例如对应的自动模式开启）
+    pub fn is_disabled(&self) -> bool {
+        self.info & control_capabilities::DISABLED != 0
+    }
+
+    /// 把 `value` 限制在范围内并对齐到步长
+    ///
+    /// 部分设备报告的 GET_MIN 大于 GET_MAX，按两者中较小、较大的值作为范围。
+    pub fn clamp(&self, value: i64) -> i64 {
+        let min = self.min.min(self.max);
+        let value = value.clamp(min, self.min.max(self.max));
+        if self.res > 1 {
+            min + (value - min) / self.res * self.res
+        } else {
+            value
+        }
+    }
+}
+
+/// VideoStreaming接口控制选择器 (A.9.7)
+pub mod video_streaming_controls {
+    pub const UNDEFINED: u8 = 0x00;
+    pub const PROBE: u8 = 0x01;
+    pub const COMMIT: u8 = 0x02;
+    pub const STILL_PROBE: u8 = 0x03;
+    pub const STILL_COMMIT: u8 = 0x04;
+    pub const STILL_IMAGE_TRIGGER: u8 = 0x05;
+    pub const STREAM_ERROR_CODE: u8 = 0x06;
+    pub const GENERATE_KEY_FRAME: u8 = 0x07;
+    pub const UPDATE_FRAME_SEGMENT: u8 = 0x08;
+    pub const SYNC_DELAY: u8 = 0x09;
+}
+
+/// 终端类型常量 (B.1-B.4)
+pub mod terminal_types {
+    // USB终端类型 (B.1)
+    pub const TT_VENDOR_SPECIFIC: u16 = 0x0100;
+    pub const TT_STREAMING: u16 = 0x0101;
+
+    // 输入终端类型 (B.2)
+    pub const ITT_VENDOR_SPECIFIC: u16 = 0x0200;
+    pub const ITT_CAMERA: u16 = 0x0201;
+    pub const ITT_MEDIA_TRANSPORT_INPUT: u16 = 0x0202;
+
+    // 输出终端类型 (B.3)
+    pub const OTT_VENDOR_SPECIFIC: u16 = 0x0300;
+    pub const OTT_DISPLAY: u16 = 0x0301;
+    pub const OTT_MEDIA_TRANSPORT_OUTPUT: u16 = 0x0302;
+
+    // 外部终端类型 (B.4)
+    pub const EXTERNAL_VENDOR_SPECIFIC: u16 = 0x0400;
+    pub const COMPOSITE_CONNECTOR: u16 = 0x0401;
+    pub const SVIDEO_CONNECTOR: u16 = 0x0402;
+    pub const COMPONENT_CONNECTOR: u16 = 0x0403;
+}
+
+/// UVC格式GUID常量
+pub mod format_guids {
+    // YUY2 格式 GUID
+    pub const YUY2: [u8; 16] = [
+        0x59, 0x55, 0x59, 0x32, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b,
+        0x71,
+    ];
+
+    // NV12 格式 GUID
+    pub const NV12: [u8; 16] = [
+        0x4e, 0x56, 0x31, 0x32, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b,
+        0x71,
+    ];
+
+    // RGB24 格式 GUID (RGB3)
+    pub const RGB24: [u8; 16] = [
+        0x52, 0x47, 0x42, 0x33, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b,
+        0x71,
+    ];
+
+    // UYVY 格式 GUID
+    pub const UYVY: [u8; 16] = [
+        0x55, 0x59, 0x56, 0x59, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b,
+        0x71,
+    ];
+
+    // Y800 格式 GUID，8 位灰度（GREY）
+    pub const Y800: [u8; 16] = [
+        0x59, 0x38, 0x30, 0x30, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b,
+        0x71,
+    ];
+
+    // BGR24 格式 GUID (BGR3)
+    pub const BGR24: [u8; 16] = [
+        0x42, 0x47, 0x52, 0x33, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b,
+        0x71,
+    ];
+}
+
+/// 载荷头标志 (2.4.3.3)
+pub mod payload_header_flags {
+    pub const EOH: u8 = 1 << 7; // End of Header
+    pub const ERR: u8 = 1 << 6; // Error
+    pub const STI: u8 = 1 << 5; // Still Image
+    pub const RES: u8 = 1 << 4; // Reserved
+    pub const SCR: u8 = 1 << 3; // Source Clock Reference
+    pub const PTS: u8 = 1 << 2; // Presentation Time Stamp
+    pub const EOF: u8 = 1 << 1; // End of Frame
+    pub const FID: u8 = 1 << 0; // Frame ID
+}
+
+/// 控制能力标志 (4.1.2)
+pub mod control_capabilities {
+    pub const GET: u8 = 1 << 0;
+    pub const SET: u8 = 1 << 1;
+    pub const DISABLED: u8 = 1 << 2;
+    pub const AUTOUPDATE: u8 = 1 << 3;
+    pub const ASYNCHRONOUS: u8 = 1 << 4;
+}
+
+/// UVC描述符解析器
+pub struct DescriptorParser;
+
+impl DescriptorParser {
+    /// 创建新的描述符解析器实例
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// 解析VideoControl头描述符
+    pub fn parse_vc_header(&self, data: &[u8]) -> Result<VcHeaderDescriptor, USBError> {
+        if data.len() < 12 {
+            Err(anyhow!("VC header descriptor too short"))?;
+        }
+
+        let length = data[0] as usize;
+        let descriptor_type = data[1];
+        let descriptor_subtype = data[2];
+
+        if descriptor_type != descriptor_types::CS_INTERFACE
+            || descriptor_subtype != vc_descriptor_subtypes::HEADER
+        {
+            Err(anyhow!("Not a VC header descriptor"))?;
+        }
+
+        let bcd_uvc = u16::from_le_bytes([data[3], data[4]]);
+        let total_length = u16::from_le_bytes([data[5], data[6]]);
+        let clock_frequency = u32::from_le_bytes([data[7], data[8], data[9], data[10]]);
+        let in_collection = data[11];
+
+        trace!(
+            "VC Header: UVC {}.{}, total_len={}, clock={} Hz, interfaces={}",
+            bcd_uvc >> 8,
+            bcd_uvc & 0xff,
+            total_length,
+            clock_frequency,
+            in_collection
+        );
+
+        Ok(VcHeaderDescriptor {
+            length,
+            bcd_uvc,
+            total_length,
+            clock_frequency,
+            in_collection,
+        })
+    }
+
+    /// 解析输入终端描述符
+    pub fn parse_input_terminal(&self, data: &[u8]) -> Result<InputTerminalDescriptor, USBError> {
+        if data.len() < 8 {
+            Err(anyhow!("Input terminal descriptor too short"))?;
+        }
+
+        let length = data[0] as usize;
+        let terminal_id = data[3];
+        let terminal_type = u16::from_le_bytes([data[4], data[5]]);
+        let associated_terminal = data[6];
+
+        trace!(
+            "Input Terminal: ID={terminal_id}, type=0x{terminal_type:04x}, associated={associated_terminal}"
+        );
+
+        // 摄像头终端有额外字段
+        if terminal_type == terminal_types::ITT_CAMERA && data.len() >= 15 {
+            let objective_focal_length_min = u16::from_le_bytes([data[8], data[9]]);
+            let objective_focal_length_max = u16::from_le_bytes([data[10], data[11]]);
+            let ocular_focal_length = u16::from_le_bytes([data[12], data[13]]);
+            let controls_size = data[14] as usize;
+
+            let controls = if data.len() >= 15 + controls_size {
+                data[15..15 + controls_size].to_vec()
+            } else {
+                vec![]
+            };
+
+            Ok(InputTerminalDescriptor::Camera {
+                length,
+                terminal_id,
+                terminal_type,
+                associated_terminal,
+                objective_focal_length_min,
+                objective_focal_length_max,
+                ocular_focal_length,
+                controls,
+            })
+        } else {
+            Ok(InputTerminalDescriptor::Generic {
+                length,
+                terminal_id,
+                terminal_type,
+                associated_terminal,
+            })
+        }
+    }
+
+    /// 解析输出终端描述符
+    pub fn parse_output_terminal(&self, data: &[u8]) -> Result<OutputTerminalDescriptor, USBError> {
+        if data.len() < 9 {
+            Err(anyhow!("Output terminal descriptor too short"))?;
+        }
+
+        let terminal = OutputTerminalDescriptor {
+            length: data[0] as usize,
+            terminal_id: data[3],
+            terminal_type: u16::from_le_bytes([data[4], data[5]]),
+            associated_terminal: data[6],
+            source_id: data[7],
+        };
+        trace!(
+            "Output Terminal: ID={}, type=0x{:04x}, source={}",
+            terminal.terminal_id, terminal.terminal_type, terminal.source_id
+        );
+        Ok(terminal)
+    }
+
+    /// 解析处理单元描述符
+    pub fn parse_processing_unit(&self, data: &[u8]) -> Result<ProcessingUnitDescriptor, USBError> {
+        if data.len() < 10 {
+            Err(anyhow!("Processing unit descriptor too short"))?;
+        }
+
+        let length = data[0] as usize;
+        let unit_id = data[3];
+        let source_id = data[4];
+        let max_multiplier = u16::from_le_bytes([data[5], data[6]]);
+        let controls_size = data[7] as usize;
+
+        if length < 8 + controls_size {
+            Err(anyhow!("Processing unit controls data incomplete"))?;
+        }
+
+        let controls = data[8..8 + controls_size].to_vec();
+
+        trace!(
+            "Processing Unit: ID={unit_id}, source={source_id}, max_mult={max_multiplier}, controls={controls:02x?}"
+        );
+
+        Ok(ProcessingUnitDescriptor {
+            length,
+            unit_id,
+            source_id,
+            max_multiplier,
+            controls,
+        })
+    }
+
+    /// 解析扩展单元描述符
+    pub fn parse_extension_unit(&self, data: &[u8]) -> Result<ExtensionUnitDescriptor, USBError> {
+        if data.len() < 23 {
+            Err(anyhow!("Extension unit descriptor too short"))?;
+        }
+
+        let length = data[0] as usize;
+        let unit_id = data[3];
+        let mut guid = [0u8; 16];
+        guid.copy_from_slice(&data[4..20]);
+        let num_controls = data[20];
+        let num_pins = data[21] as usize;
+        let controls_pos = 23 + num_pins;
+        if data.len() < controls_pos {
+            Err(anyhow!("Extension unit source IDs incomplete"))?;
+        }
+        let source_ids = data[22..22 + num_pins].to_vec();
+        let controls_size = data[22 + num_pins] as usize;
+        let controls = data
+            .get(controls_pos..controls_pos + controls_size)
+            .ok_or(anyhow!("Extension unit controls data incomplete"))?
+            .to_vec();
+
+        trace!(
+            "Extension Unit: ID={unit_id}, GUID={guid:02x?}, sources={source_ids:?}, controls={controls:02x?}"
+        );
+
+        Ok(ExtensionUnitDescriptor {
+            length,
+            unit_id,
+            guid,
+            num_controls,
+            source_ids,
+            controls,
+        })
+    }
+
+    /// 解析VideoStreaming输入头描述符
+    pub fn parse_vs_input_header(&self, data: &[u8]) -> Result<VsInputHeaderDescriptor, USBError> {
+        if data.len() < 13 {
+            Err(anyhow!("VS input header descriptor too short"))?;
+        }
+
+        let length = data[0] as usize;
+        let num_formats = data[3];
+        let total_length = u16::from_le_bytes([data[4], data[5]]);
+        let endpoint_address = data[6];
+        let info = data[7];
+        let terminal_link = data[8];
+        let still_capture_method = data[9];
+        let trigger_support = data[10];
+        let trigger_usage = data[11];
+        let controls_size = data[12] as usize;
+
+        if length < 13 + controls_size * num_formats as usize {
+            Err(anyhow!("VS input header format controls data incomplete"))?;
+        }
+
+        let format_controls = data[13..13 + controls_size * num_formats as usize].to_vec();
+
+        trace!(
+            "VS Input Header: formats={num_formats}, total_len={total_length}, endpoint=0x{endpoint_address:02x}, terminal={terminal_link}"
+        );
+
+        Ok(VsInputHeaderDescriptor {
+            length,
+            num_formats,
+            total_length,
+            endpoint_address,
+            info,
+            terminal_link,
+            still_capture_method,
+            trigger_support,
+            trigger_usage,
+            format_controls,
+        })
+    }
+
+    /// 解析未压缩格式描述符
+    pub fn parse_uncompressed_format(
+        &self,
+        data: &[u8],
+    ) -> Result<UncompressedFormatDescriptor, USBError> {
+        if data.len() < 27 {
+            Err(anyhow!("Uncompressed format descriptor too short"))?;
+        }
+
+        let length = data[0] as usize;
+        let format_index = data[3];
+        let num_frame_descriptors = data[4];
+        let mut guid = [0u8; 16];
+        guid.copy_from_slice(&data[5..21]);
+        let bits_per_pixel = data[21];
+        let default_frame_index = data[22];
+        let aspect_ratio_x = data[23];
+        let aspect_ratio_y = data[24];
+        let interlace_flags = data[25];
+        let copy_protect = data[26];
+
+        trace!(
+            "Uncompressed Format: index={format_index}, frames={num_frame_descriptors}, GUID={guid:02x?}, bpp={bits_per_pixel}"
+        );
+
+        Ok(UncompressedFormatDescriptor {
+            length,
+            format_index,
+            num_frame_descriptors,
+            guid,
+            bits_per_pixel,
+            default_frame_index,
+            aspect_ratio_x,
+            aspect_ratio_y,
+            interlace_flags,
+            copy_protect,
+        })
+    }
+
+    /// 解析MJPEG格式描述符
+    pub fn parse_mjpeg_format(&self, data: &[u8]) -> Result<MjpegFormatDescriptor, USBError> {
+        if data.len() < 11 {
+            Err(anyhow!("MJPEG format descriptor too short"))?;
+        }
+
+        let length = data[0] as usize;
+        let format_index = data[3];
+        let num_frame_descriptors = data[4];
+        let flags = data[5];
+        let default_frame_index = data[6];
+        let aspect_ratio_x = data[7];
+        let aspect_ratio_y = data[8];
+        let interlace_flags = data[9];
+        let copy_protect = data[10];
+
+        trace!(
+            "MJPEG Format: index={format_index}, frames={num_frame_descriptors}, flags=0x{flags:02x}"
+        );
+
+        Ok(MjpegFormatDescriptor {
+            length,
+            format_index,
+            num_frame_descriptors,
+            flags,
+            default_frame_index,
+            aspect_ratio_x,
+            aspect_ratio_y,
+            interlace_flags,
+            copy_protect,
+        })
+    }
+
+    /// 解析帧描述符
+    pub fn parse_frame_descriptor(&self, data: &[u8]) -> Result<FrameDescriptor, USBError> {
+        if data.len() < 26 {
+            Err(anyhow!("Frame descriptor too short"))?;
+        }
+
+        let length = data[0] as usize;
+        let frame_index = data[3];
+        let capabilities = data[4];
+        let width = u16::from_le_bytes([data[5], data[6]]);
+        let height = u16::from_le_bytes([data[7], data[8]]);
+        let min_bit_rate = u32::from_le_bytes([data[9], data[10], data[11], data[12]]);
+        let max_bit_rate = u32::from_le_bytes([data[13], data[14], data[15], data[16]]);
+        let max_video_frame_buffer_size =
+            u32::from_le_bytes([data[17], data[18], data[19], data[20]]);
+        let default_frame_interval = u32::from_le_bytes([data[21], data[22], data[23], data[24]]);
+        let frame_interval_type = data[25];
+
+        trace!(
+            "Frame: {width}x{height}, bitrate={min_bit_rate}-{max_bit_rate}, buffer_size={max_video_frame_buffer_size}, interval={default_frame_interval}, type={frame_interval_type}"
+        );
+
+        // 解析帧间隔数据
+        let mut frame_intervals = Vec::new();
+        let mut pos = 26;
+
+        match frame_interval_type {
+            0 => {
+                // 连续帧间隔
+                if length >= pos + 12 {
+                    let min_frame_interval = u32::from_le_bytes([
+                        data[pos],
+                        data[pos + 1],
+                        data[pos + 2],
+                        data[pos + 3],
+                    ]);
+                    let max_frame_interval = u32::from_le_bytes([
+                        data[pos + 4],
+                        data[pos + 5],
+                        data[pos + 6],
+                        data[pos + 7],
+                    ]);
+                    let step_frame_interval = u32::from_le_bytes([
+                        data[pos + 8],
+                        data[pos + 9],
+                        data[pos + 10],
+                        data[pos + 11],
+                    ]);
+
+                    frame_intervals =
+                        vec![min_frame_interval, max_frame_interval, step_frame_interval];
+                }
+            }
+            n if n > 0 => {
+                // 离散帧间隔
+                for _ in 0..n {
+                    if pos + 4 <= length {
+                        let interval = u32::from_le_bytes([
+                            data[pos],
+                            data[pos + 1],
+                            data[pos + 2],
+                            data[pos + 3],
+                        ]);
+                        frame_intervals.push(interval);
+                        pos += 4;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(FrameDescriptor {
+            length,
+            frame_index,
+            capabilities,
+            width,
+            height,
+            min_bit_rate,
+            max_bit_rate,
+            max_video_frame_buffer_size,
+            default_frame_interval,
+            frame_interval_type,
+            frame_intervals,
+        })
+    }
+
+    /// 解析 H.264 帧描述符（UVC 1.5 H.264 负载规范 3.1.2），返回通用帧信息与编码分层信息
+    ///
+    /// H.264 帧描述符只有离散帧间隔，也不带最大帧缓冲大小，对应字段为 0。
+    pub fn parse_h264_frame_descriptor(
+        &self,
+        data: &[u8],
+    ) -> Result<(FrameDescriptor, H264LayerInfo), USBError> {
+        if data.len() < 44 {
+            Err(anyhow!("H.264 frame descriptor too short"))?;
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let u32_at =
+            |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+
+        let length = (data[0] as usize).min(data.len());
+        let num_intervals = data[43] as usize;
+        let frame_intervals = (0..num_intervals)
+            .map(|i| 44 + i * 4)
+            .take_while(|&pos| pos + 4 <= length)
+            .map(u32_at)
+            .collect();
+
+        let frame = FrameDescriptor {
+            length,
+            frame_index: data[3],
+            capabilities: data[21],
+            width: u16_at(4),
+            height: u16_at(6),
+            min_bit_rate: u32_at(31),
+            max_bit_rate: u32_at(35),
+            max_video_frame_buffer_size: 0,
+            default_frame_interval: u32_at(39),
+            frame_interval_type: data[43],
+            frame_intervals,
+        };
+        let svc = u32_at(23);
+        let layers = H264LayerInfo {
+            profile: u16_at(12),
+            level_idc: data[14],
+            supported_usages: u32_at(17),
+            svc_capabilities: svc,
+            max_temporal_layers: (svc & 0x7) as u8 + 1,
+            max_spatial_layers: ((svc >> 11) & 0x7) as u8 + 1,
+            simulcast: false,
+        };
+
+        trace!(
+            "H.264 Frame: {}x{}, profile=0x{:04x}, level={}, svc=0x{svc:08x}",
+            frame.width, frame.height, layers.profile, layers.level_idc
+        );
+        Ok((frame, layers))
+    }
+
+    /// 计算帧率（从帧间隔）
+    pub fn interval_to_fps(interval: u32) -> u32 {
+        if interval > 0 {
+            10_000_000 / interval // 100ns单位转换为fps
+        } else {
+            0
+        }
+    }
+
+    /// 计算帧间隔（从帧率）
+    pub fn fps_to_interval(fps: u32) -> u32 {
+        if fps > 0 {
+            10_000_000 / fps // fps转换为100ns单位
+        } else {
+            0
+        }
+    }
+}
+
+/// VideoControl头描述符
+#[derive(Debug, Clone)]
+pub struct VcHeaderDescriptor {
+    pub length: usize,
+    pub bcd_uvc: u16,
+    pub total_length: u16,
+    pub clock_frequency: u32,
+    pub in_collection: u8,
+}
+
+/// 输入终端描述符
+#[derive(Debug, Clone)]
+pub enum InputTerminalDescriptor {
+    Camera {
+        length: usize,
+        terminal_id: u8,
+        terminal_type: u16,
+        associated_terminal: u8,
+        objective_focal_length_min: u16,
+        objective_focal_length_max: u16,
+        ocular_focal_length: u16,
+        controls: Vec<u8>,
+    },
+    Generic {
+        length: usize,
+        terminal_id: u8,
+        terminal_type: u16,
+        associated_terminal: u8,
+    },
+}
+
+impl InputTerminalDescriptor {
+    pub fn terminal_id(&self) -> u8 {
+        match self {
+            Self::Camera { terminal_id, .. } | Self::Generic { terminal_id, .. } => *terminal_id,
+        }
+    }
+
+    pub fn terminal_type(&self) -> u16 {
+        match self {
+            Self::Camera { terminal_type, .. } | Self::Generic { terminal_type, .. } => {
+                *terminal_type
+            }
+        }
+    }
+
+    /// 摄像头终端的 bmControls 是否声明了控制选择器 `selector`
+    pub fn supports(&self, selector: u8) -> bool {
+        let Self::Camera { controls, .. } = self else {
+            return false;
+        };
+        use camera_terminal_controls::*;
+        // FOCUS_AUTO 的选择器夹在中间，对应的位却在 D17
+        let bit = match selector {
+            FOCUS_AUTO => 17,
+            SCANNING_MODE..=FOCUS_RELATIVE => selector - 1,
+            IRIS_ABSOLUTE..=ROLL_RELATIVE => selector - 2,
+            PRIVACY => 18,
+            FOCUS_SIMPLE => 19,
+            DIGITAL_WINDOW => 20,
+            REGION_OF_INTEREST => 21,
+            _ => return false,
+        };
+        control_bit(controls, bit as usize)
+    }
+}
+
+/// 输出终端描述符
+#[derive(Debug, Clone)]
+pub struct OutputTerminalDescriptor {
+    pub length: usize,
+    pub terminal_id: u8,
+    pub terminal_type: u16,
+    pub associated_terminal: u8,
+    pub source_id: u8,
+}
+
+/// 处理单元描述符
+#[derive(Debug, Clone)]
+pub struct ProcessingUnitDescriptor {
+    pub length: usize,
+    pub unit_id: u8,
+    pub source_id: u8,
+    pub max_multiplier: u16,
+    pub controls: Vec<u8>,
+}
+
+impl ProcessingUnitDescriptor {
+    /// bmControls 是否声明了控制选择器 `selector`
+    pub fn supports(&self, selector: u8) -> bool {
+        use processing_unit_controls::*;
+        let bit = match selector {
+            BRIGHTNESS => 0,
+            CONTRAST => 1,
+            HUE => 2,
+            SATURATION => 3,
+            SHARPNESS => 4,
+            GAMMA => 5,
+            WHITE_BALANCE_TEMPERATURE => 6,
+            WHITE_BALANCE_COMPONENT => 7,
+            BACKLIGHT_COMPENSATION => 8,
+            GAIN => 9,
+            POWER_LINE_FREQUENCY => 10,
+            HUE_AUTO => 11,
+            WHITE_BALANCE_TEMPERATURE_AUTO => 12,
+            WHITE_BALANCE_COMPONENT_AUTO => 13,
+            DIGITAL_MULTIPLIER => 14,
+            DIGITAL_MULTIPLIER_LIMIT => 15,
+            ANALOG_VIDEO_STANDARD => 16,
+            ANALOG_LOCK_STATUS => 17,
+            CONTRAST_AUTO => 18,
+            _ => return false,
+        };
+        control_bit(&self.controls, bit)
+    }
+}
+
+/// 扩展单元描述符
+#[derive(Debug, Clone)]
+pub struct ExtensionUnitDescriptor {
+    pub length: usize,
+    pub unit_id: u8,
+    /// guidExtensionCode，标识厂商定义的控制集合
+    pub guid: [u8; 16],
+    pub num_controls: u8,
+    pub source_ids: Vec<u8>,
+    pub controls: Vec<u8>,
+}
+
+impl ExtensionUnitDescriptor {
+    /// bmControls 是否声明了控制选择器 `selector`（第 selector-1 位）
+    pub fn supports(&self, selector: u8) -> bool {
+        selector != 0 && control_bit(&self.controls, selector as usize - 1)
+    }
+}
+
+fn control_bit(controls: &[u8], bit: usize) -> bool {
+    controls
+        .get(bit / 8)
+        .is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+}
+
+/// VideoControl 接口的拓扑：头描述符以及各终端、单元
+///
+/// 由 VC 接口描述符之后的类特定描述符解析得到，控制请求使用其中的真实单元 ID。
+#[derive(Debug, Clone, Default)]
+pub struct VideoControlTopology {
+    pub header: Option<VcHeaderDescriptor>,
+    pub input_terminals: Vec<InputTerminalDescriptor>,
+    pub output_terminals: Vec<OutputTerminalDescriptor>,
+    pub processing_units: Vec<ProcessingUnitDescriptor>,
+    pub extension_units: Vec<ExtensionUnitDescriptor>,
+}
+
+impl VideoControlTopology {
+    /// 解析 VC 接口的类特定描述符序列，无法识别或格式错误的描述符会被跳过
+    pub fn parse(descriptors: &[u8]) -> Self {
+        let parser = DescriptorParser::new();
+        let mut topology = Self::default();
+        let mut pos = 0;
+
+        while pos + 3 <= descriptors.len() {
+            let length = descriptors[pos] as usize;
+            if length < 3 || pos + length > descriptors.len() {
+                break;
+            }
+            let desc = &descriptors[pos..pos + length];
+            pos += length;
+            if desc[1] != descriptor_types::CS_INTERFACE {
+                continue;
+            }
+
+            let res = match desc[2] {
+                vc_descriptor_subtypes::HEADER => parser
+                    .parse_vc_header(desc)
+                    .map(|d| topology.header = Some(d)),
+                vc_descriptor_subtypes::INPUT_TERMINAL => parser
+                    .parse_input_terminal(desc)
+                    .map(|d| topology.input_terminals.push(d)),
+                vc_descriptor_subtypes::OUTPUT_TERMINAL => parser
+                    .parse_output_terminal(desc)
+                    .map(|d| topology.output_terminals.push(d)),
+                vc_descriptor_subtypes::PROCESSING_UNIT => parser
+                    .parse_processing_unit(desc)
+                    .map(|d| topology.processing_units.push(d)),
+                vc_descriptor_subtypes::EXTENSION_UNIT => parser
+                    .parse_extension_unit(desc)
+                    .map(|d| topology.extension_units.push(d)),
+                _ => Ok(()),
+            };
+            if let Err(e) = res {
+                trace!("Skip VC descriptor subtype {:#04x}: {e:?}", desc[2]);
+            }
+        }
+        topology
+    }
+
+    /// 实体（终端或单元）的上游实体 ID；输入终端与未知实体为 `None`
+    ///
+    /// 扩展单元有多个输入时取第一个。
+    pub fn source_of(&self, id: u8) -> Option<u8> {
+        if let Some(ot) = self.output_terminals.iter().find(|t| t.terminal_id == id) {
+            return Some(ot.source_id);
+        }
+        if let Some(pu) = self.processing_units.iter().find(|u| u.unit_id == id) {
+            return Some(pu.source_id);
+        }
+        self.extension_units
+            .iter()
+            .find(|u| u.unit_id == id)
+            .and_then(|u| u.source_ids.first().copied())
+    }
+
+    /// 视频数据经过的处理单元
+    ///
+    /// 从 USB 流输出终端沿输入方向查找，路径上没有处理单元时取第一个处理单元。
+    pub fn processing_unit(&self) -> Option<&ProcessingUnitDescriptor> {
+        let streaming = self
+            .output_terminals
+            .iter()
+            .filter(|t| t.terminal_type == terminal_types::TT_STREAMING);
+        for ot in streaming {
+            let mut id = ot.source_id;
+            // 单元数量有限，步数上限用于防止描述符中的环
+            for _ in 0..=self.processing_units.len() + self.extension_units.len() {
+                if let Some(pu) = self.processing_units.iter().find(|u| u.unit_id == id) {
+                    return Some(pu);
+                }
+                match self.source_of(id) {
+                    Some(src) => id = src,
+                    None => break,
+                }
+            }
+        }
+        self.processing_units.first()
+    }
+
+    /// 摄像头输入终端
+    pub fn camera_terminal(&self) -> Option<&InputTerminalDescriptor> {
+        self.input_terminals
+            .iter()
+            .find(|t| matches!(t, InputTerminalDescriptor::Camera { .. }))
+    }
+
+    /// 按 guidExtensionCode 查找扩展单元
+    pub fn extension_unit(&self, guid: &[u8; 16]) -> Option<&ExtensionUnitDescriptor> {
+        self.extension_units.iter().find(|u| &u.guid == guid)
+    }
+}
+
+/// VideoStreaming输入头描述符
+#[derive(Debug, Clone)]
+pub struct VsInputHeaderDescriptor {
+    pub length: usize,
+    pub num_formats: u8,
+    pub total_length: u16,
+    pub endpoint_address: u8,
+    pub info: u8,
+    pub terminal_link: u8,
+    pub still_capture_method: u8,
+    pub trigger_support: u8,
+    pub trigger_usage: u8,
+    pub format_controls: Vec<u8>,
+}
+
+/// 未压缩格式描述符
+#[derive(Debug, Clone)]
+pub struct UncompressedFormatDescriptor {
+    pub length: usize,
+    pub format_index: u8,
+    pub num_frame_descriptors: u8,
+    pub guid: [u8; 16],
+    pub bits_per_pixel: u8,
+    pub default_frame_index: u8,
+    pub aspect_ratio_x: u8,
+    pub aspect_ratio_y: u8,
+    pub interlace_flags: u8,
+    pub copy_protect: u8,
+}
+
+/// MJPEG格式描述符
+#[derive(Debug, Clone)]
+pub struct MjpegFormatDescriptor {
+    pub length: usize,
+    pub format_index: u8,
+    pub num_frame_descriptors: u8,
+    pub flags: u8,
+    pub default_frame_index: u8,
+    pub aspect_ratio_x: u8,
+    pub aspect_ratio_y: u8,
+    pub interlace_flags: u8,
+    pub copy_protect: u8,
+}
+
+/// H.264 帧描述符中的编码与分层能力
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct H264LayerInfo {
+    /// wProfile，如 0x4240 为 Constrained Baseline
+    pub profile: u16,
+    /// bLevelIDC
+    pub level_idc: u8,
+    /// bmSupportedUsages
+    pub supported_usages: u32,
+    /// bmSVCCapabilities 原始值
+    pub svc_capabilities: u32,
+    /// 最多时域层数
+    pub max_temporal_layers: u8,
+    /// 最多空域层数
+    pub max_spatial_layers: u8,
+    /// 是否属于 VS_FORMAT_H264_SIMULCAST 格式，可同时输出多路流
+    pub simulcast: bool,
+}
+
+/// 帧描述符
+#[derive(Debug, Clone)]
+pub struct FrameDescriptor {
+    pub length: usize,
+    pub frame_index: u8,
+    pub capabilities: u8,
+    pub width: u16,
+    pub height: u16,
+    pub min_bit_rate: u32,
+    pub max_bit_rate: u32,
+    pub max_video_frame_buffer_size: u32,
+    pub default_frame_interval: u32,
+    pub frame_interval_type: u8,
+    pub frame_intervals: Vec<u32>,
+}
+
+impl Default for DescriptorParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_h264_frame() {
+        let mut data = vec![0u8; 52];
+        data[0] = 52;
+        data[1] = 0x24;
+        data[2] = vs_descriptor_subtypes::FRAME_H264;
+        data[3] = 2;
+        data[4..6].copy_from_slice(&1280u16.to_le_bytes());
+        data[6..8].copy_from_slice(&720u16.to_le_bytes());
+        data[12..14].copy_from_slice(&0x6400u16.to_le_bytes());
+        data[14] = 41;
+        // 3 个时域层，2 个空域层
+        data[23..27].copy_from_slice(&(2u32 | 1 << 11).to_le_bytes());
+        data[39..43].copy_from_slice(&333_333u32.to_le_bytes());
+        data[43] = 2;
+        data[44..48].copy_from_slice(&333_333u32.to_le_bytes());
+        data[48..52].copy_from_slice(&666_666u32.to_le_bytes());
+
+        let (frame, layers) = DescriptorParser::new()
+            .parse_h264_frame_descriptor(&data)
+            .unwrap();
+        assert_eq!(
+            (frame.frame_index, frame.width, frame.height),
+            (2, 1280, 720)
+        );
+        assert_eq!(frame.frame_intervals, [333_333, 666_666]);
+        assert_eq!(layers.profile, 0x6400);
+        assert_eq!(layers.level_idc, 41);
+        assert_eq!(layers.max_temporal_layers, 3);
+        assert_eq!(layers.max_spatial_layers, 2);
+    }
+
+    #[test]
+    fn parse_vc_topology() {
+        #[rustfmt::skip]
+        let data = [
+            // 头描述符，UVC 1.1
+            0x0d, 0x24, 0x01, 0x10, 0x01, 0x4f, 0x00, 0x00, 0x6c, 0xdc, 0x02, 0x01, 0x01,
+            // 摄像头终端 1：Auto-Exposure Mode、Focus Auto
+            0x12, 0x24, 0x02, 0x01, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x03, 0x02, 0x00, 0x02,
+            // 处理单元 5，源为终端 1：Brightness、Power Line Frequency
+            0x0b, 0x24, 0x05, 0x05, 0x01, 0x00, 0x00, 0x02, 0x01, 0x04, 0x00,
+            // 另一个不在流路径上的处理单元 7
+            0x0b, 0x24, 0x05, 0x07, 0x01, 0x00, 0x00, 0x02, 0xff, 0xff, 0x00,
+            // 扩展单元 6，源为处理单元 5，控制 1 与 3
+            0x1b, 0x24, 0x06, 0x06, 0xaa, 0xbb, 0xcc, 0xdd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x03, 0x01, 0x05, 0x02, 0x05, 0x00, 0x00,
+            // USB 流输出终端 3，源为扩展单元 6
+            0x09, 0x24, 0x03, 0x03, 0x01, 0x01, 0x00, 0x06, 0x00,
+            // 类特定中断端点描述符不属于 VC 接口
+            0x05, 0x25, 0x03, 0x10, 0x00,
+        ];
+        let topology = VideoControlTopology::parse(&data);
+
+        assert_eq!(topology.header.as_ref().unwrap().bcd_uvc, 0x0110);
+        assert_eq!(topology.processing_units.len(), 2);
+        let pu = topology.processing_unit().unwrap();
+        assert_eq!(pu.unit_id, 5);
+        assert!(pu.supports(processing_unit_controls::BRIGHTNESS));
+        assert!(pu.supports(processing_unit_controls::POWER_LINE_FREQUENCY));
+        assert!(!pu.supports(processing_unit_controls::CONTRAST));
+
+        let ct = topology.camera_terminal().unwrap();
+        assert_eq!(ct.terminal_id(), 1);
+        assert!(ct.supports(camera_terminal_controls::AE_MODE));
+        assert!(ct.supports(camera_terminal_controls::FOCUS_AUTO));
+        assert!(!ct.supports(camera_terminal_controls::FOCUS_ABSOLUTE));
+
+        let mut guid = [0u8; 16];
+        guid[..4].copy_from_slice(&[0xaa, 0xbb, 0xcc, 0xdd]);
+        guid[15] = 0x01;
+        let xu = topology.extension_unit(&guid).unwrap();
+        assert_eq!((xu.unit_id, xu.num_controls), (6, 3));
+        assert!(xu.supports(1) && !xu.supports(2) && xu.supports(3));
+        assert_eq!(topology.source_of(3), Some(6));
+        assert_eq!(topology.source_of(6), Some(5));
+        assert_eq!(topology.source_of(1), None);
+    }
+
+    #[test]
+    fn control_range_values() {
+        use processing_unit_controls::*;
+        assert_eq!(pu_control_layout(BRIGHTNESS), Some((2, true)));
+        assert_eq!(pu_control_layout(POWER_LINE_FREQUENCY), Some((1, false)));
+        assert_eq!(pu_control_layout(UNDEFINED), None);
+
+        assert_eq!(decode_control_value(&[0xc0, 0xff], true), Some(-64));
+        assert_eq!(decode_control_value(&[0xc0, 0xff], false), Some(0xffc0));
+        assert_eq!(decode_control_value(&[1, 2, 3], false), None);
+
+        let range = ControlRange {
+            min: -64,
+            max: 64,
+            res: 4,
+            def: 0,
+            info: control_capabilities::GET | control_capabilities::SET,
+        };
+        assert!(range.is_settable() && !range.is_disabled());
+        assert_eq!(range.clamp(100), 64);
+        assert_eq!(range.clamp(-63), -64);
+        assert_eq!(range.clamp(7), 4);
+
+        let inverted = ControlRange {
+            min: 10,
+            max: 0,
+            res: 1,
+            ..range
+        };
+        assert_eq!(inverted.clamp(20), 10);
+        assert_eq!(inverted.clamp(-5), 0);
+    }
+
+    #[test]
+    fn control_requests_by_selector() {
+        use processing_unit_controls::*;
+        assert_eq!(pu_control_requests(BRIGHTNESS), ControlRequests::Range);
+        assert_eq!(
+            pu_control_requests(POWER_LINE_FREQUENCY),
+            ControlRequests::Default { min: 0, max: 3 }
+        );
+        assert_eq!(
+            pu_control_requests(HUE_AUTO),
+            ControlRequests::Default { min: 0, max: 1 }
+        );
+        assert!(matches!(
+            pu_control_requests(ANALOG_LOCK_STATUS),
+            ControlRequests::ReadOnly { .. }
+        ));
+        assert_eq!(pu_control_layout(WHITE_BALANCE_COMPONENT), Some((2, false)));
+        assert_eq!(pu_control_components(WHITE_BALANCE_COMPONENT), 2);
+        assert_eq!(pu_control_components(BRIGHTNESS), 1);
+    }
+
+    #[test]
+    fn test_fps_conversion() {
+        // 测试30fps
+        let interval_30fps = 333333; // 100ns单位
+        assert_eq!(DescriptorParser::interval_to_fps(interval_30fps), 30);
+        assert_eq!(DescriptorParser::fps_to_interval(30), 333333);
+
+        // 测试60fps
+        let interval_60fps = 166666;
+        assert_eq!(DescriptorParser::interval_to_fps(interval_60fps), 60);
+        assert_eq!(DescriptorParser::fps_to_interval(60), 166666);
+    }
+
+    #[test]
+    fn test_guid_constants() {
+        // 确保GUID常量正确定义
+        assert_eq!(format_guids::YUY2[0..4], [0x59, 0x55, 0x59, 0x32]);
+        assert_eq!(format_guids::NV12[0..4], [0x4e, 0x56, 0x31, 0x32]);
+        assert_eq!(format_guids::RGB24[0..4], [0x52, 0x47, 0x42, 0x33]);
+        assert_eq!(format_guids::Y800[0..4], [0x59, 0x38, 0x30, 0x30]);
+    }
+}
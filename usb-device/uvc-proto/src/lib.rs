@@ -0,0 +1,19 @@
+//! UVC 协议定义
+//!
+//! 描述符、Probe/Commit 控制与载荷头的解析与序列化，设备时钟换算，以及相关常量。
+//! 不依赖主机控制器，主机端驱动（crab-uvc）与设备端 UVC 功能均可复用。
+
+#![no_std]
+
+#[macro_use]
+extern crate alloc;
+
+pub mod clock;
+pub mod descriptors;
+pub mod payload;
+pub mod probe;
+
+pub use clock::{ClockMapper, SourceClock};
+pub use descriptors::*;
+pub use payload::UvcPayloadHeader;
+pub use probe::{H264StreamLayout, StillCaptureMethod, StreamControl};
@@ -0,0 +1,160 @@
+use alloc::vec::Vec;
+
+use crate::clock::SourceClock;
+use crate::descriptors::payload_header_flags as flags;
+
+/// UVC 载荷头（2.4.3.3）
+#[derive(Debug, Clone, Default)]
+pub struct UvcPayloadHeader {
+    pub length: u8,              // bLength
+    pub info: u8,                // bmHeaderInfo
+    pub fid: bool,               // Frame ID
+    pub eof: bool,               // End of Frame
+    pub pts: Option<u32>,        // Presentation Time Stamp (4 bytes, 设备时钟计数)
+    pub scr: Option<(u32, u16)>, // Source Clock Reference: SOF timestamp (32) + SOF count (16)
+    pub has_err: bool,
+}
+
+impl UvcPayloadHeader {
+    /// 从字节流解析 UVC 载荷头；若数据不合法，返回 None 以允许上层丢弃该包。
+    pub fn parse(buf: &[u8]) -> Option<(Self, usize)> {
+        if buf.len() < 2 {
+            return None;
+        }
+        let b_length = buf[0] as usize;
+        let info = buf[1];
+        if b_length < 2 || b_length > buf.len() {
+            return None;
+        }
+
+        let fid = (info & flags::FID) != 0;
+        let eof = (info & flags::EOF) != 0;
+        let has_pts = (info & flags::PTS) != 0;
+        let has_scr = (info & flags::SCR) != 0;
+        let has_err = (info & flags::ERR) != 0;
+
+        // 可选字段顺序：PTS(4) -> SCR(6)
+        let mut offset = 2usize;
+        let pts = if has_pts {
+            if offset + 4 > b_length {
+                return None;
+            }
+            let v = u32::from_le_bytes([
+                buf[offset],
+                buf[offset + 1],
+                buf[offset + 2],
+                buf[offset + 3],
+            ]);
+            offset += 4;
+            Some(v)
+        } else {
+            None
+        };
+
+        let scr = if has_scr {
+            if offset + 6 > b_length {
+                return None;
+            }
+            let stc = u32::from_le_bytes([
+                buf[offset],
+                buf[offset + 1],
+                buf[offset + 2],
+                buf[offset + 3],
+            ]);
+            let sof = u16::from_le_bytes([buf[offset + 4], buf[offset + 5]]);
+            Some((stc, sof))
+        } else {
+            None
+        };
+
+        // 剩余可忽略的扩展字段由 b_length 统一跳过
+        let header = UvcPayloadHeader {
+            length: b_length as u8,
+            info,
+            fid,
+            eof,
+            pts,
+            scr,
+            has_err,
+        };
+
+        Some((header, b_length))
+    }
+}
+
+impl UvcPayloadHeader {
+    /// 载荷头中的 SCR
+    pub fn source_clock(&self) -> Option<SourceClock> {
+        self.scr.map(|(stc, sof)| SourceClock { stc, sof })
+    }
+}
+
+impl UvcPayloadHeader {
+    /// 按 `info` 中的 FID/EOF/ERR 与可选字段生成载荷头，`EOH` 总是置位
+    ///
+    /// 设备端在每个载荷前写入该头部；`length` 字段被忽略，按实际长度填写。
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut info = self.info & (flags::STI | flags::RES) | flags::EOH;
+        if self.fid {
+            info |= flags::FID;
+        }
+        if self.eof {
+            info |= flags::EOF;
+        }
+        if self.has_err {
+            info |= flags::ERR;
+        }
+        if self.pts.is_some() {
+            info |= flags::PTS;
+        }
+        if self.scr.is_some() {
+            info |= flags::SCR;
+        }
+
+        let mut out = vec![0, info];
+        if let Some(pts) = self.pts {
+            out.extend_from_slice(&pts.to_le_bytes());
+        }
+        if let Some((stc, sof)) = self.scr {
+            out.extend_from_slice(&stc.to_le_bytes());
+            out.extend_from_slice(&sof.to_le_bytes());
+        }
+        out[0] = out.len() as u8;
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_with_pts_and_scr() {
+        let header = UvcPayloadHeader {
+            fid: true,
+            eof: true,
+            pts: Some(0x1234_5678),
+            scr: Some((0xCAFE_BABE, 0x07FF)),
+            ..Default::default()
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(bytes.len(), 12);
+        assert_eq!(
+            bytes[1],
+            flags::EOH | flags::SCR | flags::PTS | flags::EOF | flags::FID
+        );
+
+        let (parsed, len) = UvcPayloadHeader::parse(&bytes).unwrap();
+        assert_eq!(len, 12);
+        assert!(parsed.fid && parsed.eof && !parsed.has_err);
+        assert_eq!(parsed.pts, header.pts);
+        assert_eq!(parsed.scr, header.scr);
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        // bLength 声明了 PTS，但只有 4 字节
+        assert!(UvcPayloadHeader::parse(&[4, flags::EOH | flags::PTS, 0, 0]).is_none());
+        assert!(UvcPayloadHeader::parse(&[12, 0x80]).is_none());
+    }
+}
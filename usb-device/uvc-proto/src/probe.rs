@@ -0,0 +1,299 @@
+//! 视频流协商（PROBE / COMMIT）控制块
+//!
+//! 参考 UVC 规范 4.3.1.1。控制块长度取决于设备的 UVC 版本：1.0 为 26 字节，
+//! 1.1 为 34 字节，1.5 为 48 字节，请求长度与版本不符时部分设备会 STALL。
+
+use alloc::vec::Vec;
+
+use anyhow::anyhow;
+use usb_if::err::USBError;
+
+use crate::descriptors::DescriptorParser;
+
+/// Video Probe and Commit Controls
+///
+/// 主机端提交后读取 GET_CUR 得到设备实际采用的值，设备端据此回应 PROBE/COMMIT 请求。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamControl {
+    /// bmHint
+    pub hint: u16,
+    /// bFormatIndex
+    pub format_index: u8,
+    /// bFrameIndex
+    pub frame_index: u8,
+    /// dwFrameInterval，100ns 为单位
+    pub frame_interval: u32,
+    /// wKeyFrameRate
+    pub key_frame_rate: u16,
+    /// wPFrameRate
+    pub p_frame_rate: u16,
+    /// wCompQuality
+    pub comp_quality: u16,
+    /// wCompWindowSize
+    pub comp_window_size: u16,
+    /// wDelay，毫秒
+    pub delay: u16,
+    /// dwMaxVideoFrameSize
+    pub max_video_frame_size: u32,
+    /// dwMaxPayloadTransferSize
+    pub max_payload_transfer_size: u32,
+    /// dwClockFrequency，UVC 1.1 起有效
+    pub clock_frequency: u32,
+    /// bmFramingInfo，见 [`StreamControl::FRAMING_FID`]、[`StreamControl::FRAMING_EOF`]，UVC 1.1 起有效
+    pub framing_info: u8,
+    /// bPreferedVersion，UVC 1.1 起有效
+    pub preferred_version: u8,
+    /// bMinVersion，UVC 1.1 起有效
+    pub min_version: u8,
+    /// bMaxVersion，UVC 1.1 起有效
+    pub max_version: u8,
+    /// bUsage，UVC 1.5 起有效
+    pub usage: u8,
+    /// bBitDepthLuma，UVC 1.5 起有效
+    pub bit_depth_luma: u8,
+    /// bmSettings，UVC 1.5 起有效
+    pub settings: u8,
+    /// bMaxNumberOfRefFramesPlus1，UVC 1.5 起有效
+    pub max_ref_frames_plus1: u8,
+    /// bmRateControlModes，每路流 4 位，UVC 1.5 起有效
+    pub rate_control_modes: u16,
+    /// bmLayoutPerStream，每路联播流的分层结构，见 [`H264StreamLayout`]，UVC 1.5 起有效
+    pub layout_per_stream: [u16; 4],
+}
+
+impl StreamControl {
+    /// bmFramingInfo：负载头中的 FID 位必须有效
+    pub const FRAMING_FID: u8 = 1 << 0;
+    /// bmFramingInfo：负载头中可能出现 EOF 位
+    pub const FRAMING_EOF: u8 = 1 << 1;
+
+    const LEN_UVC10: usize = 26;
+    const LEN_UVC11: usize = 34;
+    const LEN_UVC15: usize = 48;
+
+    /// 按 bcdUVC 返回控制块长度
+    pub fn len_for_version(bcd_uvc: u16) -> usize {
+        match bcd_uvc {
+            ..0x0110 => Self::LEN_UVC10,
+            0x0110..0x0150 => Self::LEN_UVC11,
+            _ => Self::LEN_UVC15,
+        }
+    }
+
+    /// 按 dwFrameInterval 换算的帧率
+    pub fn frame_rate(&self) -> u32 {
+        DescriptorParser::interval_to_fps(self.frame_interval)
+    }
+
+    /// 是否按帧划分负载，即负载头的 FID / EOF 可以用于切分帧
+    pub fn has_framing_info(&self) -> bool {
+        self.framing_info & (Self::FRAMING_FID | Self::FRAMING_EOF) != 0
+    }
+
+    /// 序列化为 `len` 字节，超出 `len` 的字段被截掉
+    pub fn to_bytes(&self, len: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(Self::LEN_UVC15);
+        data.extend(&self.hint.to_le_bytes());
+        data.push(self.format_index);
+        data.push(self.frame_index);
+        data.extend(&self.frame_interval.to_le_bytes());
+        data.extend(&self.key_frame_rate.to_le_bytes());
+        data.extend(&self.p_frame_rate.to_le_bytes());
+        data.extend(&self.comp_quality.to_le_bytes());
+        data.extend(&self.comp_window_size.to_le_bytes());
+        data.extend(&self.delay.to_le_bytes());
+        data.extend(&self.max_video_frame_size.to_le_bytes());
+        data.extend(&self.max_payload_transfer_size.to_le_bytes());
+        data.extend(&self.clock_frequency.to_le_bytes());
+        data.push(self.framing_info);
+        data.push(self.preferred_version);
+        data.push(self.min_version);
+        data.push(self.max_version);
+        data.push(self.usage);
+        data.push(self.bit_depth_luma);
+        data.push(self.settings);
+        data.push(self.max_ref_frames_plus1);
+        data.extend(&self.rate_control_modes.to_le_bytes());
+        for layout in self.layout_per_stream {
+            data.extend(&layout.to_le_bytes());
+        }
+        data.resize(len, 0);
+        data
+    }
+
+    /// 解析设备返回的控制块，设备版本中不存在的字段保持为 0
+    pub fn parse(data: &[u8]) -> Result<Self, USBError> {
+        if data.len() < Self::LEN_UVC10 {
+            Err(anyhow!("Stream control response too short"))?;
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let u32_at =
+            |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+
+        let mut ctrl = Self {
+            hint: u16_at(0),
+            format_index: data[2],
+            frame_index: data[3],
+            frame_interval: u32_at(4),
+            key_frame_rate: u16_at(8),
+            p_frame_rate: u16_at(10),
+            comp_quality: u16_at(12),
+            comp_window_size: u16_at(14),
+            delay: u16_at(16),
+            max_video_frame_size: u32_at(18),
+            max_payload_transfer_size: u32_at(22),
+            ..Default::default()
+        };
+        if data.len() >= Self::LEN_UVC11 {
+            ctrl.clock_frequency = u32_at(26);
+            ctrl.framing_info = data[30];
+            ctrl.preferred_version = data[31];
+            ctrl.min_version = data[32];
+            ctrl.max_version = data[33];
+        }
+        if data.len() >= Self::LEN_UVC15 {
+            ctrl.usage = data[34];
+            ctrl.bit_depth_luma = data[35];
+            ctrl.settings = data[36];
+            ctrl.max_ref_frames_plus1 = data[37];
+            ctrl.rate_control_modes = u16_at(38);
+            for (i, layout) in ctrl.layout_per_stream.iter_mut().enumerate() {
+                *layout = u16_at(40 + i * 2);
+            }
+        }
+        Ok(ctrl)
+    }
+}
+
+/// H.264 联播 / SVC 单路流的分层结构，对应 bmLayoutPerStream 中的一项
+///
+/// 层数越少码率越低，带宽受限时可以只请求基本层。各层数至少为 1。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct H264StreamLayout {
+    pub temporal_layers: u8,
+    pub spatial_layers: u8,
+    pub quality_layers: u8,
+}
+
+impl H264StreamLayout {
+    /// 只有基本层
+    pub const BASE: Self = Self {
+        temporal_layers: 1,
+        spatial_layers: 1,
+        quality_layers: 1,
+    };
+
+    /// 编码为 bmLayoutPerStream 项：D2..0 时域层数减一，D5..3 空域层数减一，D8..6 质量层数减一
+    pub fn to_bits(self) -> u16 {
+        let field = |n: u8| (n.clamp(1, 8) - 1) as u16;
+        field(self.temporal_layers)
+            | field(self.spatial_layers) << 3
+            | field(self.quality_layers) << 6
+    }
+
+    pub fn from_bits(bits: u16) -> Self {
+        let field = |shift: u16| ((bits >> shift) & 0x7) as u8 + 1;
+        Self {
+            temporal_layers: field(0),
+            spatial_layers: field(3),
+            quality_layers: field(6),
+        }
+    }
+}
+
+impl Default for H264StreamLayout {
+    fn default() -> Self {
+        Self::BASE
+    }
+}
+
+/// VS 输入头中的 bStillCaptureMethod
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StillCaptureMethod {
+    /// 不支持静态图像捕获
+    #[default]
+    None,
+    /// 方法 1：从视频流中取一帧
+    Method1,
+    /// 方法 2：暂停视频流，在同一端点上按静态图像参数传输
+    Method2,
+    /// 方法 3：通过专用的批量端点传输
+    Method3,
+    Other(u8),
+}
+
+impl From<u8> for StillCaptureMethod {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::None,
+            1 => Self::Method1,
+            2 => Self::Method2,
+            3 => Self::Method3,
+            other => Self::Other(other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn len_follows_uvc_version() {
+        assert_eq!(StreamControl::len_for_version(0x0100), 26);
+        assert_eq!(StreamControl::len_for_version(0x0110), 34);
+        assert_eq!(StreamControl::len_for_version(0x0150), 48);
+    }
+
+    #[test]
+    fn round_trip_uvc11() {
+        let ctrl = StreamControl {
+            hint: 1,
+            format_index: 2,
+            frame_index: 3,
+            frame_interval: 333_333,
+            delay: 40,
+            max_video_frame_size: 614_400,
+            max_payload_transfer_size: 3072,
+            clock_frequency: 48_000_000,
+            framing_info: StreamControl::FRAMING_FID | StreamControl::FRAMING_EOF,
+            max_version: 2,
+            ..Default::default()
+        };
+        let data = ctrl.to_bytes(34);
+        assert_eq!(data.len(), 34);
+        assert_eq!(StreamControl::parse(&data).unwrap(), ctrl);
+        assert_eq!(ctrl.frame_rate(), 30);
+
+        // UVC 1.0 设备只返回 26 字节，1.1 字段保持为 0
+        let short = StreamControl::parse(&data[..26]).unwrap();
+        assert_eq!(short.framing_info, 0);
+        assert_eq!(short.max_payload_transfer_size, 3072);
+        assert!(StreamControl::parse(&data[..20]).is_err());
+    }
+
+    #[test]
+    fn round_trip_uvc15_layout() {
+        let layout = H264StreamLayout {
+            temporal_layers: 3,
+            spatial_layers: 2,
+            quality_layers: 1,
+        };
+        assert_eq!(layout.to_bits(), 0b000_001_010);
+        assert_eq!(H264StreamLayout::from_bits(layout.to_bits()), layout);
+
+        let ctrl = StreamControl {
+            format_index: 1,
+            usage: 1,
+            rate_control_modes: 0x0003,
+            layout_per_stream: [layout.to_bits(), H264StreamLayout::BASE.to_bits(), 0, 0],
+            ..Default::default()
+        };
+        let data = ctrl.to_bytes(48);
+        assert_eq!(StreamControl::parse(&data).unwrap(), ctrl);
+
+        // 按 1.1 长度发送时 1.5 字段被截掉
+        let short = StreamControl::parse(&ctrl.to_bytes(34)).unwrap();
+        assert_eq!(short.layout_per_stream, [0; 4]);
+    }
+}
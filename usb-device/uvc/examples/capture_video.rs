@@ -232,6 +232,7 @@ async fn create_video_from_frames(
                 crab_uvc::UncompressedFormat::Nv12 => "nv12",
                 crab_uvc::UncompressedFormat::Rgb24 => "rgb24",
                 crab_uvc::UncompressedFormat::Rgb32 => "rgba",
+                crab_uvc::UncompressedFormat::Y800 => "gray",
             };
             (*width, *height, ffmpeg_format)
         }
@@ -371,7 +372,8 @@ async fn create_video_from_mjpeg_frames(
                         if buffer.len() >= 2 && buffer[0] == 0xFF && buffer[1] == 0xD8 {
                             // 这是JPEG数据，我们需要解码它
                             // 创建临时文件来解码JPEG
-                            let temp_jpeg_path = format!("/tmp/temp_frame_{}.jpg", i);
+                            let temp_jpeg_path =
+                                std::env::temp_dir().join(format!("temp_frame_{}.jpg", i));
                             std::fs::write(&temp_jpeg_path, &buffer)?;
 
                             // 使用ffmpeg解码JPEG
@@ -775,6 +777,7 @@ async fn write_format_info(
                 crab_uvc::UncompressedFormat::Nv12 => "nv12",
                 crab_uvc::UncompressedFormat::Rgb24 => "rgb24",
                 crab_uvc::UncompressedFormat::Rgb32 => "rgba",
+                crab_uvc::UncompressedFormat::Y800 => "gray",
             };
             (*width, *height, ffmpeg_format)
         }
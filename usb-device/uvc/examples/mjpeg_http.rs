@@ -0,0 +1,149 @@
+//! 通过 HTTP 推送 UVC MJPEG 视频流
+//!
+//! 运行后在浏览器打开 `http://127.0.0.1:8080/` 即可实时查看摄像头画面。
+//! 监听地址可通过第一个命令行参数修改，例如 `0.0.0.0:8080`。
+
+use crab_usb::USBHost;
+use crab_uvc::{
+    UvcDevice, VideoFormatType,
+    frame::FrameEvent,
+    sink::{FrameSink, SinkError},
+};
+use futures::future::{BoxFuture, FutureExt, ready};
+use log::{info, warn};
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+};
+
+const BOUNDARY: &str = "crabusbframe";
+
+const INDEX: &str = "<!DOCTYPE html><html><head><title>CrabUSB UVC</title></head>\
+<body style=\"margin:0;background:#000\">\
+<img src=\"/stream\" style=\"display:block;margin:auto;max-width:100%\"></body></html>";
+
+/// 只保留最新一帧，慢速客户端跳帧而不会阻塞采集
+struct LatestFrame(watch::Sender<Option<Arc<Vec<u8>>>>);
+
+impl FrameSink for LatestFrame {
+    fn write<'a>(&'a mut self, frame: FrameEvent) -> BoxFuture<'a, Result<(), SinkError>> {
+        self.0.send_replace(Some(Arc::new(frame.data)));
+        ready(Ok(())).boxed()
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .init();
+
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".into());
+
+    let mut host = USBHost::new_libusb()?;
+    host.init().await?;
+
+    let mut uvc = None;
+    for probed in host.probe_devices().await? {
+        let Some(info) = probed.into_device_info() else {
+            continue;
+        };
+        if UvcDevice::check(&info) {
+            let device = host.open_device(&info).await?;
+            uvc = Some(UvcDevice::new(device).await?);
+            break;
+        }
+    }
+    let Some(mut uvc) = uvc else {
+        warn!("No UVC device found. Make sure a USB camera is connected.");
+        return Ok(());
+    };
+
+    let formats = uvc.get_supported_formats().await?;
+    let Some(format) = formats
+        .into_iter()
+        .filter(|f| matches!(f.format_type, VideoFormatType::Mjpeg))
+        .max_by_key(|f| (f.width as u32 * f.height as u32, f.frame_rate))
+    else {
+        warn!("Camera does not support MJPEG");
+        return Ok(());
+    };
+    info!("Using format: {format:?}");
+    uvc.set_format(format).await?;
+    let mut stream = uvc.start_streaming().await?;
+
+    let (tx, rx) = watch::channel(None);
+    let listener = TcpListener::bind(&addr).await?;
+    info!("Serving MJPEG stream on http://{addr}/");
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((conn, peer)) => {
+                    info!("Client connected: {peer}");
+                    tokio::spawn(serve(conn, rx.clone()));
+                }
+                Err(e) => warn!("Accept failed: {e:?}"),
+            }
+        }
+    });
+
+    let mut sink = LatestFrame(tx);
+    let mut last_err = String::new();
+    loop {
+        if let Err(e) = stream.pump(&mut sink).await
+            && e.to_string() != last_err
+        {
+            warn!("Error receiving frame: {e:?}");
+            last_err = e.to_string();
+        }
+    }
+}
+
+async fn serve(mut conn: TcpStream, mut frames: watch::Receiver<Option<Arc<Vec<u8>>>>) {
+    let mut req = [0u8; 1024];
+    let Ok(n) = conn.read(&mut req).await else {
+        return;
+    };
+    let path = std::str::from_utf8(&req[..n])
+        .ok()
+        .and_then(|req| req.split_whitespace().nth(1))
+        .unwrap_or("/");
+
+    if path != "/stream" {
+        let resp = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{INDEX}",
+            INDEX.len()
+        );
+        let _ = conn.write_all(resp.as_bytes()).await;
+        return;
+    }
+
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={BOUNDARY}\r\n\
+         Cache-Control: no-cache\r\nConnection: close\r\n\r\n"
+    );
+    if conn.write_all(head.as_bytes()).await.is_err() {
+        return;
+    }
+
+    while frames.changed().await.is_ok() {
+        let Some(jpeg) = frames.borrow_and_update().clone() else {
+            continue;
+        };
+        let part = format!(
+            "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            jpeg.len()
+        );
+        if conn.write_all(part.as_bytes()).await.is_err()
+            || conn.write_all(&jpeg).await.is_err()
+            || conn.write_all(b"\r\n").await.is_err()
+        {
+            break;
+        }
+    }
+    info!("Client disconnected");
+}
@@ -0,0 +1,277 @@
+//! 基于真实设备配置描述符的解析回归测试
+//!
+//! 描述符转储位于 `tests/fixtures/`，格式与贡献方式见该目录下的 README。
+
+use alloc::vec::Vec;
+
+use crate::{
+    StillCaptureMethod, StreamControl, UncompressedFormat, UvcDevice, VideoControlTopology,
+    VideoFormat, VideoFormatType, processing_unit_controls,
+};
+
+/// 解析十六进制转储：`#` 之后为注释，其余空白分隔的两位十六进制数为字节
+fn load(hex: &str) -> Vec<u8> {
+    hex.lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(str::split_whitespace)
+        .map(|byte| u8::from_str_radix(byte, 16).expect("invalid hex byte in fixture"))
+        .collect()
+}
+
+struct Fixture {
+    name: &'static str,
+    data: &'static str,
+    vs_interface: u8,
+    /// (格式, 宽, 高, 默认帧率)，按描述符出现顺序排列
+    formats: &'static [(VideoFormatType, u16, u16, u32)],
+}
+
+const YUY2: VideoFormatType = VideoFormatType::Uncompressed(UncompressedFormat::Yuy2);
+const NV12: VideoFormatType = VideoFormatType::Uncompressed(UncompressedFormat::Nv12);
+const Y800: VideoFormatType = VideoFormatType::Uncompressed(UncompressedFormat::Y800);
+const MJPEG: VideoFormatType = VideoFormatType::Mjpeg;
+
+const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "webcam_mjpeg_yuy2",
+        data: include_str!("../tests/fixtures/webcam_mjpeg_yuy2.hex"),
+        vs_interface: 1,
+        formats: &[
+            (MJPEG, 640, 480, 30),
+            (MJPEG, 1280, 720, 30),
+            (MJPEG, 320, 240, 30),
+            (YUY2, 640, 480, 30),
+            (YUY2, 1280, 720, 10),
+        ],
+    },
+    Fixture {
+        name: "nv12_continuous_interval",
+        data: include_str!("../tests/fixtures/nv12_continuous_interval.hex"),
+        vs_interface: 1,
+        formats: &[(NV12, 1920, 1080, 15), (NV12, 1280, 720, 25)],
+    },
+    Fixture {
+        name: "dual_stream_ir",
+        data: include_str!("../tests/fixtures/dual_stream_ir.hex"),
+        vs_interface: 1,
+        formats: &[(MJPEG, 1280, 720, 30)],
+    },
+    Fixture {
+        name: "dual_stream_ir",
+        data: include_str!("../tests/fixtures/dual_stream_ir.hex"),
+        vs_interface: 2,
+        formats: &[(Y800, 640, 360, 15)],
+    },
+];
+
+#[test]
+fn fixtures_are_well_formed() {
+    for fixture in FIXTURES {
+        let data = load(fixture.data);
+        assert!(data.len() >= 9, "{}: too short", fixture.name);
+        assert_eq!(
+            data[1], 0x02,
+            "{}: not a configuration descriptor",
+            fixture.name
+        );
+        let total = u16::from_le_bytes([data[2], data[3]]) as usize;
+        assert_eq!(total, data.len(), "{}: wTotalLength mismatch", fixture.name);
+    }
+}
+
+#[test]
+fn parse_fixture_formats() {
+    for fixture in FIXTURES {
+        let data = load(fixture.data);
+        let formats = UvcDevice::parse_vs_interface_descriptors(&data, fixture.vs_interface)
+            .unwrap_or_else(|e| panic!("{}: {e:?}", fixture.name));
+        let got: Vec<_> = formats
+            .iter()
+            .map(|f| (f.format_type, f.width, f.height, f.frame_rate))
+            .collect();
+        assert_eq!(
+            got, fixture.formats,
+            "{} (VS interface {})",
+            fixture.name, fixture.vs_interface
+        );
+    }
+}
+
+#[test]
+fn parse_fixture_class_info() {
+    for fixture in FIXTURES {
+        let data = load(fixture.data);
+        let info = UvcDevice::parse_class_info(&data, 0, fixture.vs_interface);
+        assert_eq!(info, (0x0100, StillCaptureMethod::None), "{}", fixture.name);
+    }
+
+    // 改写为 UVC 1.1、静态图像方法 2
+    let mut data = load(FIXTURES[0].data);
+    let vc = data
+        .windows(4)
+        .position(|w| w == [0x24, 0x01, 0x00, 0x01])
+        .unwrap();
+    data[vc + 2] = 0x10;
+    let vs = data
+        .windows(4)
+        .position(|w| w == [0x24, 0x01, 0x02, 0xf7])
+        .unwrap();
+    data[vs + 8] = 2;
+    assert_eq!(
+        UvcDevice::parse_class_info(&data, 0, 1),
+        (0x0110, StillCaptureMethod::Method2)
+    );
+}
+
+fn format(format_type: VideoFormatType, width: u16, height: u16, frame_rate: u32) -> VideoFormat {
+    VideoFormat {
+        width,
+        height,
+        frame_rate,
+        format_type,
+        h264: None,
+    }
+}
+
+#[test]
+fn parse_builder_camera() {
+    let camera = crab_usb::fixtures::DeviceFixture::uvc_camera();
+    let data = &camera.configurations[0];
+    let formats = UvcDevice::parse_vs_interface_descriptors(data, 1).unwrap();
+    let parsed: Vec<_> = formats
+        .iter()
+        .map(|f| (f.format_type, f.width, f.height, f.frame_rate))
+        .collect();
+    assert_eq!(parsed, [(MJPEG, 640, 480, 30), (MJPEG, 1280, 720, 30)]);
+    assert_eq!(
+        UvcDevice::parse_class_info(data, 0, 1),
+        (0x0100, StillCaptureMethod::None)
+    );
+}
+
+#[test]
+fn find_frame_uses_descriptor_indices() {
+    let data = load(FIXTURES[0].data);
+    let find = |f: VideoFormat| {
+        UvcDevice::find_frame(&data, 1, &f).map(|(format_index, frame)| {
+            (format_index, frame.frame_index, frame.width, frame.height)
+        })
+    };
+    assert_eq!(find(format(MJPEG, 1280, 720, 30)), Some((1, 2, 1280, 720)));
+    assert_eq!(find(format(YUY2, 1280, 720, 10)), Some((2, 2, 1280, 720)));
+    // 分辨率不存在时退回同一格式的第一个帧
+    assert_eq!(find(format(YUY2, 1920, 1080, 30)), Some((2, 1, 640, 480)));
+    assert_eq!(find(format(NV12, 640, 480, 30)), None);
+}
+
+#[test]
+fn choose_frame_interval_snaps_to_descriptor() {
+    let data = load(FIXTURES[0].data);
+    let (_, frame) = UvcDevice::find_frame(&data, 1, &format(MJPEG, 640, 480, 30)).unwrap();
+    assert_eq!(
+        UvcDevice::choose_frame_interval(&frame, 0),
+        frame.default_frame_interval
+    );
+    let interval = UvcDevice::choose_frame_interval(&frame, 29);
+    assert!(frame.frame_intervals.contains(&interval));
+    assert_eq!(interval, 333_333);
+
+    // 连续帧间隔按步长取整并限制在范围内
+    let data = load(FIXTURES[1].data);
+    let (_, frame) = UvcDevice::find_frame(&data, 1, &format(NV12, 1920, 1080, 15)).unwrap();
+    let [min, max, step] = frame.frame_intervals[..] else {
+        panic!("expected continuous intervals");
+    };
+    assert_eq!(UvcDevice::choose_frame_interval(&frame, 1000), min);
+    let interval = UvcDevice::choose_frame_interval(&frame, 1);
+    assert!(interval <= max && interval + step > max);
+    for fps in [1, 20, 24] {
+        let interval = UvcDevice::choose_frame_interval(&frame, fps);
+        assert_eq!((interval - min) % step, 0);
+    }
+}
+
+#[test]
+fn degrade_lowers_frame_rate_then_payload() {
+    let data = load(FIXTURES[0].data);
+    let (_, frame) = UvcDevice::find_frame(&data, 1, &format(MJPEG, 640, 480, 30)).unwrap();
+    let longest = *frame.frame_intervals.iter().max().unwrap();
+
+    let mut ctrl = StreamControl {
+        frame_interval: *frame.frame_intervals.iter().min().unwrap(),
+        max_payload_transfer_size: 3072,
+        ..Default::default()
+    };
+    while ctrl.frame_interval < longest {
+        let next = UvcDevice::degrade_stream_control(&frame, &ctrl).unwrap();
+        assert!(frame.frame_intervals.contains(&next.frame_interval));
+        assert!(next.frame_interval > ctrl.frame_interval);
+        assert_eq!(next.max_payload_transfer_size, 3072);
+        ctrl = next;
+    }
+
+    let mut payloads = Vec::new();
+    while let Some(next) = UvcDevice::degrade_stream_control(&frame, &ctrl) {
+        assert_eq!(next.frame_interval, longest);
+        payloads.push(next.max_payload_transfer_size);
+        ctrl = next;
+    }
+    assert_eq!(payloads, [1536, 768, 384, 192]);
+
+    // 连续帧间隔加倍后仍落在步长上
+    let data = load(FIXTURES[1].data);
+    let (_, frame) = UvcDevice::find_frame(&data, 1, &format(NV12, 1920, 1080, 15)).unwrap();
+    let [min, max, step] = frame.frame_intervals[..] else {
+        panic!("expected continuous intervals");
+    };
+    let ctrl = StreamControl {
+        frame_interval: min,
+        ..Default::default()
+    };
+    let next = UvcDevice::degrade_stream_control(&frame, &ctrl).unwrap();
+    assert!(next.frame_interval >= (2 * min).min(max));
+    assert!(next.frame_interval == max || (next.frame_interval - min) % step == 0);
+}
+
+#[test]
+fn streaming_alt_settings_step_down_by_capacity() {
+    let data = load(FIXTURES[0].data);
+    let config = usb_if::descriptor::ConfigurationDescriptor::parse(&data).unwrap();
+    let vs = config
+        .interfaces
+        .iter()
+        .find(|i| i.first_alt_setting().interface_number == FIXTURES[0].vs_interface)
+        .unwrap();
+    let alts = UvcDevice::streaming_alt_settings(vs);
+    let order: Vec<_> = alts
+        .iter()
+        .map(|(alt, ep)| (*alt, ep.max_packet_size))
+        .collect();
+    // alt 0 没有端点；首选 1024 字节的 alt 3，带宽不足时依次降到 512、128
+    assert_eq!(order, [(3, 1024), (2, 512), (1, 128)]);
+
+    // 没有适中大小的端点时从最大者开始
+    let mut small = vs.clone();
+    small
+        .alt_settings
+        .retain(|alt| alt.alternate_setting != 2 && alt.alternate_setting != 3);
+    let alts = UvcDevice::streaming_alt_settings(&small);
+    assert_eq!(alts.iter().map(|(alt, _)| *alt).collect::<Vec<_>>(), [1]);
+}
+
+#[test]
+fn parse_fixture_vc_topology() {
+    let data = load(FIXTURES[0].data);
+    let config = usb_if::descriptor::ConfigurationDescriptor::parse(&data).unwrap();
+    let vc = config.interfaces[0].first_alt_setting();
+    let topology = VideoControlTopology::parse(&vc.extra);
+
+    // 处理单元 ID 为 2，不是常见的 1
+    let pu = topology.processing_unit().unwrap();
+    assert_eq!((pu.unit_id, pu.source_id), (2, 1));
+    assert!(pu.supports(processing_unit_controls::BRIGHTNESS));
+    assert!(pu.supports(processing_unit_controls::POWER_LINE_FREQUENCY));
+    assert!(!pu.supports(processing_unit_controls::WHITE_BALANCE_COMPONENT));
+    assert_eq!(topology.camera_terminal().unwrap().terminal_id(), 1);
+    assert_eq!(topology.source_of(3), Some(2));
+}
@@ -1,97 +1,50 @@
-use crate::descriptors::payload_header_flags as flags;
 use alloc::vec::Vec;
-use core::fmt::Debug;
+use core::{fmt::Debug, time::Duration};
 use log::{debug, warn};
 use usb_if::err::TransferError;
+use uvc_proto::SourceClock;
+pub use uvc_proto::payload::UvcPayloadHeader;
 
-/// UVC 载荷头（2.4.3.3）
-#[derive(Debug, Clone, Default)]
-pub struct UvcPayloadHeader {
-    pub length: u8,              // bLength
-    pub info: u8,                // bmHeaderInfo
-    pub fid: bool,               // Frame ID
-    pub eof: bool,               // End of Frame
-    pub pts: Option<u32>,        // Presentation Time Stamp (4 bytes, 90kHz)
-    pub scr: Option<(u32, u16)>, // Source Clock Reference: SOF timestamp (32) + SOF count (16)
-    pub has_err: bool,
-}
-
-impl UvcPayloadHeader {
-    /// 从字节流解析 UVC 载荷头；若数据不合法，返回 None 以允许上层丢弃该包。
-    pub fn parse(buf: &[u8]) -> Option<(Self, usize)> {
-        if buf.len() < 2 {
-            return None;
-        }
-        let b_length = buf[0] as usize;
-        let info = buf[1];
-        if b_length < 2 || b_length > buf.len() {
-            return None;
-        }
-
-        let fid = (info & flags::FID) != 0;
-        let eof = (info & flags::EOF) != 0;
-        let has_pts = (info & flags::PTS) != 0;
-        let has_scr = (info & flags::SCR) != 0;
-        let has_err = (info & flags::ERR) != 0;
-
-        // 可选字段顺序：PTS(4) -> SCR(6)
-        let mut _offset = 2usize;
-        let pts = if has_pts {
-            if _offset + 4 > b_length {
-                return None;
-            }
-            let v = u32::from_le_bytes([
-                buf[_offset],
-                buf[_offset + 1],
-                buf[_offset + 2],
-                buf[_offset + 3],
-            ]);
-            _offset += 4;
-            Some(v)
-        } else {
-            None
-        };
-
-        let scr = if has_scr {
-            if _offset + 6 > b_length {
-                return None;
-            }
-            let stc = u32::from_le_bytes([
-                buf[_offset],
-                buf[_offset + 1],
-                buf[_offset + 2],
-                buf[_offset + 3],
-            ]);
-            let sof = u16::from_le_bytes([buf[_offset + 4], buf[_offset + 5]]);
-            _offset += 6;
-            Some((stc, sof))
-        } else {
-            None
-        };
-
-        // 剩余可忽略的扩展字段由 b_length 统一跳过
-        let header = UvcPayloadHeader {
-            length: b_length as u8,
-            info,
-            fid,
-            eof,
-            pts,
-            scr,
-            has_err,
-        };
-
-        Some((header, b_length))
-    }
-}
+use crate::rate::FrameRateLimiter;
 
 /// 帧组装事件（供上层转换为具体视频帧结构）
 #[derive(Debug, Clone)]
 pub struct FrameEvent {
     pub data: Vec<u8>,
+    /// 载荷头中的 PTS，以设备时钟（dwClockFrequency）计数
     pub pts_90khz: Option<u32>,
+    /// 帧中第一个载荷头的 SCR
+    pub scr: Option<SourceClock>,
+    /// 按 [`ClockMapper`](uvc_proto::ClockMapper) 换算的 PTS 主机时间，由 [`VideoStream`](crate::stream::VideoStream) 填写
+    pub host_time: Option<Duration>,
     pub eof: bool,
     pub fid: bool,
     pub frame_number: u32,
+    /// 帧不完整的原因，仅在 [`CorruptFramePolicy::Flag`] 下可能为 `Some`
+    pub defect: Option<FrameDefect>,
+}
+
+/// 帧完整性问题
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDefect {
+    /// 帧内有载荷头置位 ERR 的包
+    PayloadError,
+    /// 帧内有传输失败的包
+    PacketLost,
+    /// MJPEG 帧不以 SOI（FF D8）开头
+    MissingSoi,
+    /// MJPEG 帧不以 EOI（FF D9）结尾
+    MissingEoi,
+}
+
+/// 不完整帧的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CorruptFramePolicy {
+    /// 丢弃并计数，见 [`FrameParser::corrupt_frame_count`]
+    #[default]
+    Drop,
+    /// 照常输出，在 [`FrameEvent::defect`] 中标明原因
+    Flag,
 }
 
 /// UVC 帧解析/组装器（参考 libuvc 的 FID 翻转与 EOF 逻辑）
@@ -100,10 +53,25 @@ pub struct FrameParser {
     buffer: Option<Vec<u8>>,
     last_fid: Option<bool>,
     last_pts: Option<u32>,
+    /// 正在组装的帧中第一个 SCR
+    frame_scr: Option<SourceClock>,
+    /// 最近收到的 SCR，包括被丢弃与跳过的帧
+    latest_scr: Option<SourceClock>,
     frame_number: u32,
     error_packet_count: u32, // 统计错误包数量
+    lost_packet_count: u32,  // 统计传输失败的包数量
     frame_size: usize,
     rsv_eof: bool, // 记录上一个包的 EOF 状态，辅助调试
+    /// 正在组装的帧已发现的问题
+    defect: Option<FrameDefect>,
+    /// 为真时检查 MJPEG 的 SOI/EOI 标记
+    mjpeg: bool,
+    policy: CorruptFramePolicy,
+    corrupt_frame_count: u32,
+    limiter: Option<FrameRateLimiter>,
+    /// 正在组装的帧被帧率限制跳过，不保存其载荷
+    skipping: bool,
+    skipped_frame_count: u32,
 }
 
 impl FrameParser {
@@ -113,12 +81,38 @@ impl FrameParser {
             last_fid: None,
             frame_number: 0,
             last_pts: None,
+            frame_scr: None,
+            latest_scr: None,
             error_packet_count: 0,
+            lost_packet_count: 0,
             frame_size,
             rsv_eof: false,
+            defect: None,
+            mjpeg: false,
+            policy: CorruptFramePolicy::default(),
+            corrupt_frame_count: 0,
+            limiter: None,
+            skipping: false,
+            skipped_frame_count: 0,
         }
     }
 
+    /// 设备以 `source_fps` 输出时最多向上层输出 `max_fps` 帧每秒，`None` 表示不限制
+    pub fn set_rate_limit(&mut self, source_fps: u32, max_fps: Option<u32>) {
+        self.limiter = max_fps.and_then(|max| FrameRateLimiter::new(source_fps, max));
+    }
+
+    /// 按 MJPEG 校验帧的 SOI/EOI 标记
+    pub fn with_mjpeg(mut self, mjpeg: bool) -> Self {
+        self.mjpeg = mjpeg;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: CorruptFramePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
     fn check_fid(&mut self, fid: bool) {
         let Some(last) = self.last_fid else {
             self.last_fid = Some(fid);
@@ -133,7 +127,39 @@ impl FrameParser {
 
         self.last_fid = Some(fid);
 
-        self.buffer = Some(Vec::with_capacity(self.frame_size));
+        self.skipping = self.limiter.as_mut().is_some_and(|l| !l.admit());
+        self.buffer = Some(if self.skipping {
+            Vec::new()
+        } else {
+            Vec::with_capacity(self.frame_size)
+        });
+        self.defect = None;
+        self.frame_scr = None;
+    }
+
+    /// 记录正在组装的帧的问题，丢弃策略下同时清空已收到的数据
+    fn mark_defect(&mut self, defect: FrameDefect) {
+        self.defect.get_or_insert(defect);
+        if self.policy == CorruptFramePolicy::Drop && !self.skipping {
+            self.buffer = Some(Vec::with_capacity(self.frame_size));
+        }
+    }
+
+    /// 帧结束时的完整性检查
+    fn check_frame(&self, data: &[u8]) -> Option<FrameDefect> {
+        if self.defect.is_some() {
+            return self.defect;
+        }
+        if !self.mjpeg {
+            return None;
+        }
+        if !data.starts_with(&[0xff, 0xd8]) {
+            Some(FrameDefect::MissingSoi)
+        } else if !data.ends_with(&[0xff, 0xd9]) {
+            Some(FrameDefect::MissingEoi)
+        } else {
+            None
+        }
     }
 
     /// 获取错误包统计信息
@@ -141,9 +167,45 @@ impl FrameParser {
         self.error_packet_count
     }
 
+    /// 获取传输失败而丢失的包数量
+    pub fn lost_packet_count(&self) -> u32 {
+        self.lost_packet_count
+    }
+
+    /// 因不完整被丢弃的帧数量
+    pub fn corrupt_frame_count(&self) -> u32 {
+        self.corrupt_frame_count
+    }
+
+    /// 最近收到的 SCR，用于关联设备时钟与主机时间
+    pub fn latest_scr(&self) -> Option<SourceClock> {
+        self.latest_scr
+    }
+
+    /// 因帧率限制被跳过的帧数量
+    pub fn skipped_frame_count(&self) -> u32 {
+        self.skipped_frame_count
+    }
+
     /// 重置错误包统计
     pub fn reset_error_count(&mut self) {
         self.error_packet_count = 0;
+        self.lost_packet_count = 0;
+        self.corrupt_frame_count = 0;
+    }
+
+    /// 记录一个传输失败的包；正在组装的帧缺少数据，整帧丢弃
+    pub fn drop_packet(&mut self) {
+        self.lost_packet_count += 1;
+        if self.buffer.as_ref().is_some_and(|b| !b.is_empty()) {
+            debug!(
+                "ISO packet lost; dropping current buffer, total lost packets: {}",
+                self.lost_packet_count
+            );
+        }
+        self.mark_defect(FrameDefect::PacketLost);
+        self.last_pts = None;
+        self.frame_scr = None;
     }
 
     /// 处理一包 UVC 传输数据；返回完整帧事件（若 EOF 收到）
@@ -163,6 +225,9 @@ impl FrameParser {
             }
         };
         // debug!("UVC payload header: {:?}", hdr);
+        // 先按 FID 判断帧边界，错误包记在它所属的帧上
+        self.check_fid(hdr.fid);
+
         if hdr.has_err {
             // 记录统计信息，了解错误频率
             self.error_packet_count += 1;
@@ -190,14 +255,13 @@ impl FrameParser {
                 );
             }
 
-            self.buffer = Some(Vec::with_capacity(self.frame_size));
+            self.mark_defect(FrameDefect::PayloadError);
             self.last_pts = None;
+            self.frame_scr = None;
             // 继续后面的包，不要因为单个错误包就停止
             return Ok(None);
         }
 
-        self.check_fid(hdr.fid);
-
         let Some(ref mut buffer) = self.buffer else {
             // 理论上不应发生
             // warn!("Internal buffer is None, resetting");
@@ -206,7 +270,7 @@ impl FrameParser {
         };
 
         // 载荷数据在头之后
-        if hdr_len <= data.len() {
+        if !self.skipping && hdr_len <= data.len() {
             let payload = &data[hdr_len..];
 
             // 高效地trim尾部全0：找到最后一个非0字节，直接截取
@@ -217,11 +281,27 @@ impl FrameParser {
         if let Some(pts) = hdr.pts {
             self.last_pts = Some(pts);
         }
+        if let Some(scr) = hdr.source_clock() {
+            self.latest_scr = Some(scr);
+            self.frame_scr.get_or_insert(scr);
+        }
 
         if hdr.eof {
             if !self.rsv_eof {
                 self.rsv_eof = true;
                 self.buffer = Some(Vec::with_capacity(self.frame_size));
+                self.defect = None;
+                return Ok(None);
+            }
+
+            if self.skipping {
+                self.skipping = false;
+                self.skipped_frame_count += 1;
+                self.frame_number = self.frame_number.wrapping_add(1);
+                self.buffer = None;
+                self.defect = None;
+                self.last_pts = None;
+                self.frame_scr = None;
                 return Ok(None);
             }
 
@@ -230,18 +310,154 @@ impl FrameParser {
                 return Ok(None);
             }
             let data = self.buffer.take().unwrap();
+            let defect = self.check_frame(&data);
+            self.defect = None;
+            let frame_number = self.frame_number;
+            // 丢弃的帧也占用帧序号，上层可据此发现缺帧
+            self.frame_number = self.frame_number.wrapping_add(1);
+
+            if let Some(defect) = defect
+                && self.policy == CorruptFramePolicy::Drop
+            {
+                self.corrupt_frame_count += 1;
+                debug!("Dropping corrupt frame {frame_number}: {defect:?}");
+                self.last_pts = None;
+                self.frame_scr = None;
+                return Ok(None);
+            }
 
             let evt = FrameEvent {
                 data,
                 pts_90khz: self.last_pts.take(),
+                scr: self.frame_scr.take(),
+                host_time: None,
                 eof: true,
                 fid: hdr.fid,
-                frame_number: self.frame_number,
+                frame_number,
+                defect,
             };
-            self.frame_number = self.frame_number.wrapping_add(1);
             return Ok(Some(evt));
         }
 
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    /// 2 字节载荷头：bit0 为 FID，bit1 为 EOF，bit6 为 ERR，bit7 为 EOH
+    fn payload(fid: bool, eof: bool, err: bool, data: &[u8]) -> Vec<u8> {
+        let mut p = vec![
+            2,
+            0x80 | fid as u8 | ((eof as u8) << 1) | ((err as u8) << 6),
+        ];
+        p.extend_from_slice(data);
+        p
+    }
+
+    /// 送入首个 EOF 对齐帧边界，之后 FID 为 true 的包属于新帧
+    fn aligned(parser: FrameParser) -> FrameParser {
+        let mut parser = parser;
+        assert!(
+            parser
+                .push_packet(&payload(false, true, false, &[1]))
+                .unwrap()
+                .is_none()
+        );
+        parser
+    }
+
+    fn frames(parser: &mut FrameParser, packets: &[Vec<u8>]) -> Vec<FrameEvent> {
+        packets
+            .iter()
+            .filter_map(|p| parser.push_packet(p).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn error_mid_frame_drops_frame() {
+        let mut parser = aligned(FrameParser::new(64));
+        let out = frames(
+            &mut parser,
+            &[
+                payload(true, false, false, &[1]),
+                payload(true, false, true, &[]),
+                payload(true, true, false, &[2]),
+                payload(false, true, false, &[3]),
+            ],
+        );
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].data, [3]);
+        assert_eq!(out[0].defect, None);
+        // 被丢弃的帧占用序号 0
+        assert_eq!(out[0].frame_number, 1);
+        assert_eq!(parser.corrupt_frame_count(), 1);
+    }
+
+    #[test]
+    fn flag_policy_keeps_data() {
+        let mut parser = aligned(FrameParser::new(64).with_policy(CorruptFramePolicy::Flag));
+        parser
+            .push_packet(&payload(true, false, false, &[1]))
+            .unwrap();
+        parser.drop_packet();
+        let out = parser
+            .push_packet(&payload(true, true, false, &[2]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(out.data, [1, 2]);
+        assert_eq!(out.defect, Some(FrameDefect::PacketLost));
+        assert_eq!(parser.corrupt_frame_count(), 0);
+    }
+
+    #[test]
+    fn rate_limit_skips_frames() {
+        let mut parser = aligned(FrameParser::new(64));
+        parser.set_rate_limit(30, Some(15));
+        let out = frames(
+            &mut parser,
+            &[
+                payload(true, true, false, &[1]),
+                payload(false, true, false, &[2]),
+                payload(true, true, false, &[3]),
+                payload(false, true, false, &[4]),
+            ],
+        );
+        let data: Vec<_> = out
+            .iter()
+            .map(|f| (f.data.clone(), f.frame_number))
+            .collect();
+        assert_eq!(data, [(vec![1], 0), (vec![3], 2)]);
+        assert_eq!(parser.skipped_frame_count(), 2);
+    }
+
+    #[test]
+    fn mjpeg_markers() {
+        let mut parser = aligned(
+            FrameParser::new(64)
+                .with_mjpeg(true)
+                .with_policy(CorruptFramePolicy::Flag),
+        );
+        let out = frames(
+            &mut parser,
+            &[
+                payload(true, true, false, &[0xff, 0xd8, 7, 0xff, 0xd9]),
+                payload(false, true, false, &[0xff, 0xd8, 7]),
+                payload(true, true, false, &[7, 0xff, 0xd9]),
+            ],
+        );
+        let defects: Vec<_> = out.iter().map(|f| f.defect).collect();
+        assert_eq!(
+            defects,
+            [
+                None,
+                Some(FrameDefect::MissingEoi),
+                Some(FrameDefect::MissingSoi)
+            ]
+        );
+    }
+}
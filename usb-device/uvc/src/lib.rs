@@ -8,24 +8,43 @@ use alloc::{
     vec::Vec,
 };
 use anyhow::anyhow;
-use crab_usb::{Device, DeviceInfo, err::USBError};
+use core::time::Duration;
+use crab_usb::{
+    Device, DeviceInfo,
+    err::{TransferError, USBError},
+};
 use log::*;
-use usb_if::descriptor::EndpointType;
+use usb_if::descriptor::{
+    ConfigurationDescriptor, EndpointDescriptor, EndpointType, InterfaceDescriptors,
+};
 use usb_if::{
     descriptor::Class,
     host::ControlSetup,
     transfer::{Direction, Recipient, Request, RequestType},
 };
 
-// 导入描述符解析模块
-pub mod descriptors;
-pub use descriptors::*;
+// 描述符与 Probe/Commit 控制的解析位于 uvc-proto，保留原有模块路径
+pub use uvc_proto::descriptors;
+pub use uvc_proto::descriptors::*;
+pub use uvc_proto::probe;
+pub use uvc_proto::{ClockMapper, SourceClock, clock};
 
+pub mod sink;
 pub mod stream;
+pub mod warmup;
+
+#[cfg(test)]
+mod fixture_tests;
+
 // 帧解析模块（参考 libuvc 的包头解析与帧组装）
 pub mod frame;
+mod rate;
 
+use crate::frame::FrameEvent;
+pub use crate::frame::{CorruptFramePolicy, FrameDefect};
+pub use crate::probe::{H264StreamLayout, StillCaptureMethod, StreamControl};
 use crate::stream::VideoStream;
+pub use crate::warmup::{StreamConfig, Warmup};
 
 // 保持向后兼容的常量别名
 pub mod uvc_requests {
@@ -122,9 +141,11 @@ pub struct VideoFormat {
     pub height: u16,
     pub frame_rate: u32, // 帧率 (fps)
     pub format_type: VideoFormatType,
+    /// H.264 帧描述符中的编码与分层能力，其他格式为 `None`
+    pub h264: Option<H264LayerInfo>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VideoFormatType {
     Uncompressed(UncompressedFormat),
     Mjpeg,
@@ -140,6 +161,7 @@ impl VideoFormat {
                     UncompressedFormat::Nv12 => 1,  // NV12 每像素1字节 (平均)
                     UncompressedFormat::Rgb24 => 3, // RGB24 每像素3字节
                     UncompressedFormat::Rgb32 => 4, // RGB32 每像素4字节
+                    UncompressedFormat::Y800 => 1,  // Y800 每像素1字节
                 };
                 (self.width as usize) * (self.height as usize) * pixel_size
             }
@@ -166,6 +188,8 @@ pub enum UncompressedFormat {
     Rgb24,
     /// RGB32 格式
     Rgb32,
+    /// Y800 (GREY) 8 位灰度格式
+    Y800,
 }
 
 /// 视频控制事件
@@ -185,6 +209,30 @@ pub enum VideoControlEvent {
     Error(String),
 }
 
+/// 电源频率（抗闪烁）设置，对应 PU_POWER_LINE_FREQUENCY_CONTROL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerLineFrequency {
+    Disabled = 0,
+    Hz50 = 1,
+    Hz60 = 2,
+    /// UVC 1.5 新增
+    Auto = 3,
+}
+
+impl TryFrom<u8> for PowerLineFrequency {
+    type Error = USBError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Disabled),
+            1 => Ok(Self::Hz50),
+            2 => Ok(Self::Hz60),
+            3 => Ok(Self::Auto),
+            _ => Err(anyhow!("Invalid power line frequency value: {value}").into()),
+        }
+    }
+}
+
 /// 视频数据帧
 #[derive(Debug)]
 pub struct VideoFrame {
@@ -198,8 +246,43 @@ pub struct VideoFrame {
     pub format: VideoFormat,
     /// 是否是帧结束标志
     pub end_of_frame: bool,
+    /// 帧不完整的原因，见 [`CorruptFramePolicy`]
+    pub defect: Option<FrameDefect>,
+    /// 载荷头中的 PTS，以设备时钟计数
+    pub pts: Option<u32>,
+    /// 帧中第一个载荷头的 SCR
+    pub scr: Option<SourceClock>,
+    /// PTS 换算的主机时间，与 [`Endpoint::now`](crab_usb::Endpoint::now) 同一时钟；
+    /// 设备未声明时钟频率或尚未收到 SCR 时为 `None`
+    pub host_time: Option<Duration>,
+}
+
+impl VideoFrame {
+    /// 由重组得到的帧事件构造，`timestamp` 为 PTS，没有 PTS 时为 0
+    pub(crate) fn from_event(event: FrameEvent, format: VideoFormat) -> Self {
+        Self {
+            data: event.data,
+            timestamp: event.pts_90khz.unwrap_or(0) as u64,
+            frame_number: event.frame_number,
+            format,
+            end_of_frame: event.eof,
+            defect: event.defect,
+            pts: event.pts_90khz,
+            scr: event.scr,
+            host_time: event.host_time,
+        }
+    }
 }
 
+/// 降低负载大小重试 COMMIT 时 dwMaxPayloadTransferSize 的下限
+const MIN_PAYLOAD_TRANSFER_SIZE: u32 = 128;
+/// COMMIT 被拒绝后最多重新协商的次数
+const MAX_COMMIT_RETRIES: usize = 8;
+/// [`UvcDevice::photo`] 对未压缩格式丢弃的预热帧数
+const PHOTO_WARMUP_FRAMES: u32 = 15;
+/// [`UvcDevice::photo`] 等待有效帧时最多接收的传输次数
+const PHOTO_MAX_TRANSFERS: usize = 2000;
+
 /// UVC 设备状态
 #[derive(Debug, Clone, PartialEq)]
 pub enum UvcDeviceState {
@@ -213,30 +296,20 @@ pub enum UvcDeviceState {
     Error(String),
 }
 
-/// UVC Stream Control 结构体 (参考 UVC 规范 4.3.1.1)
-#[derive(Debug, Clone)]
-struct StreamControl {
-    hint: u16,                      // bmHint
-    format_index: u8,               // bFormatIndex
-    frame_index: u8,                // bFrameIndex
-    frame_interval: u32,            // dwFrameInterval (100ns units)
-    key_frame_rate: u16,            // wKeyFrameRate
-    p_frame_rate: u16,              // wPFrameRate
-    comp_quality: u16,              // wCompQuality
-    comp_window_size: u16,          // wCompWindowSize
-    delay: u16,                     // wDelay
-    max_video_frame_size: u32,      // dwMaxVideoFrameSize
-    max_payload_transfer_size: u32, // dwMaxPayloadTransferSize
-}
-
 pub struct UvcDevice {
     device: Device,
 
+    video_control_interface_num: u8,
     video_streaming_interface_num: u8,
-    processing_unit_id: Option<u8>, // 处理单元ID
+    /// VC 接口的终端与单元
+    topology: VideoControlTopology,
     current_format: Option<VideoFormat>,
+    /// VC 头描述符中的 bcdUVC，首次协商时读取
+    bcd_uvc: Option<u16>,
+    still_capture_method: StillCaptureMethod,
+    /// 设备确认的 COMMIT 控制块
+    committed: Option<StreamControl>,
     state: UvcDeviceState,
-    descriptor_parser: DescriptorParser, // 新增描述符解析器
 }
 
 impl UvcDevice {
@@ -305,12 +378,17 @@ impl UvcDevice {
                 (
                     video_control_iface.interface_number,
                     video_control_iface.alternate_setting,
+                    VideoControlTopology::parse(&video_control_iface.extra),
                 ),
                 video_streaming_iface.map(|vs| (vs.interface_number, vs.alternate_setting)),
             )
         };
 
-        debug!("Using Video Control interface: {video_control_info:?}");
+        debug!(
+            "Using Video Control interface {}, processing unit {:?}",
+            video_control_info.0,
+            video_control_info.2.processing_unit().map(|pu| pu.unit_id)
+        );
 
         device
             .claim_interface(video_control_info.0, video_control_info.1)
@@ -318,15 +396,18 @@ impl UvcDevice {
 
         Ok(Self {
             device,
+            video_control_interface_num: video_control_info.0,
             // video_streaming_interface,
             video_streaming_interface_num: video_streaming_info
                 .map(|(num, _)| num)
                 .expect("Video Streaming interface number is required"),
-            processing_unit_id: Some(1), // 通常处理单元ID为1，实际应用中应该解析描述符
+            topology: video_control_info.2,
             // ep_in,
             current_format: None,
+            bcd_uvc: None,
+            still_capture_method: StillCaptureMethod::None,
+            committed: None,
             state: UvcDeviceState::Configured,
-            descriptor_parser: DescriptorParser::new(),
         })
     }
 
@@ -348,7 +429,7 @@ impl UvcDevice {
 
                 // 解析配置描述符中的VS接口部分
                 if let Ok(parsed_formats) =
-                    self.parse_vs_interface_descriptors(&config_data, vs_interface_num)
+                    Self::parse_vs_interface_descriptors(&config_data, vs_interface_num)
                     && !parsed_formats.is_empty()
                 {
                     trace!(
@@ -389,48 +470,18 @@ impl UvcDevice {
 
     /// 通过控制请求获取完整的配置描述符
     async fn get_full_configuration_descriptor(&mut self) -> Result<Vec<u8>, USBError> {
-        let setup = ControlSetup {
-            request_type: RequestType::Standard,
-            recipient: Recipient::Device,
-            request: Request::GetDescriptor,
-            value: (0x02 << 8), // Configuration descriptor type
-            index: 0,           // Configuration index
-        };
-
-        // 首先获取配置描述符头来确定总长度
-        let mut header_buffer = vec![0u8; 9]; // 配置描述符头是9字节
-        self.device.control_in(setup, &mut header_buffer).await?;
-
-        if header_buffer.len() < 4 {
-            Err(anyhow!("Failed to read configuration descriptor header"))?;
-        }
-
-        // 提取总长度（小端格式）
-        let total_length = u16::from_le_bytes([header_buffer[2], header_buffer[3]]) as usize;
-        trace!("Configuration descriptor total length: {total_length} bytes");
-
-        if total_length < 9 {
-            Err(anyhow!("Invalid configuration descriptor length"))?;
-        }
-
-        // 获取完整的配置描述符
-        let mut full_buffer = alloc::vec![0u8; total_length];
-        let setup_full = ControlSetup {
-            request_type: RequestType::Standard,
-            recipient: Recipient::Device,
-            request: Request::GetDescriptor,
-            value: (0x02 << 8), // Configuration descriptor type
-            index: 0,           // Configuration index
-        };
-
-        self.device.control_in(setup_full, &mut full_buffer).await?;
-
-        Ok(full_buffer)
+        let desc: ConfigurationDescriptor = self.device.read_descriptor(0, 0).await?;
+        trace!(
+            "Configuration descriptor total length: {} bytes",
+            desc.raw.len()
+        );
+        Ok(desc.raw)
     }
 
     /// 解析VS接口描述符中的格式信息
-    fn parse_vs_interface_descriptors(
-        &self,
+    ///
+    /// `config_data` 为完整的配置描述符，只依赖描述符内容，不需要打开设备。
+    pub fn parse_vs_interface_descriptors(
         config_data: &[u8],
         vs_interface_num: u8,
     ) -> Result<Vec<VideoFormat>, USBError> {
@@ -438,6 +489,7 @@ impl UvcDevice {
         let mut pos = 0;
         let mut found_vs_interface = false;
         let mut current_format_type: Option<VideoFormatType> = None;
+        let mut current_simulcast = false;
 
         trace!(
             "Parsing configuration descriptor of {} bytes for VS interface {}",
@@ -499,22 +551,45 @@ impl UvcDevice {
                             }
                             uvc_interface_subtypes::VS_FORMAT_UNCOMPRESSED => {
                                 trace!("Parsing uncompressed format descriptor");
-                                if let Ok(format_type) = self
-                                    .parse_uncompressed_format_type(&config_data[pos..pos + length])
-                                {
+                                if let Ok(format_type) = Self::parse_uncompressed_format_type(
+                                    &config_data[pos..pos + length],
+                                ) {
                                     current_format_type =
                                         Some(VideoFormatType::Uncompressed(format_type));
                                 }
                             }
-                            uvc_interface_subtypes::VS_FORMAT_H264 => {
-                                trace!("Found H264 format descriptor");
+                            uvc_interface_subtypes::VS_FORMAT_H264
+                            | uvc_interface_subtypes::VS_FORMAT_H264_SIMULCAST => {
+                                trace!("Found H264 format descriptor subtype 0x{subtype:02x}");
                                 current_format_type = Some(VideoFormatType::H264);
+                                current_simulcast =
+                                    subtype == uvc_interface_subtypes::VS_FORMAT_H264_SIMULCAST;
+                            }
+                            uvc_interface_subtypes::VS_FRAME_H264 => {
+                                trace!("Parsing H264 frame descriptor");
+                                if current_format_type == Some(VideoFormatType::H264)
+                                    && let Ok((frame, mut layers)) = DescriptorParser::new()
+                                        .parse_h264_frame_descriptor(
+                                            &config_data[pos..pos + length],
+                                        )
+                                {
+                                    layers.simulcast = current_simulcast;
+                                    formats.push(VideoFormat {
+                                        width: frame.width,
+                                        height: frame.height,
+                                        frame_rate: DescriptorParser::interval_to_fps(
+                                            frame.default_frame_interval,
+                                        ),
+                                        format_type: VideoFormatType::H264,
+                                        h264: Some(layers),
+                                    });
+                                }
                             }
                             uvc_interface_subtypes::VS_FRAME_MJPEG
                             | uvc_interface_subtypes::VS_FRAME_UNCOMPRESSED => {
                                 trace!("Parsing frame descriptor subtype 0x{subtype:02x}");
                                 if let Some(format_type) = current_format_type
-                                    && let Ok(frame_formats) = self.parse_frame_descriptor(
+                                    && let Ok(frame_formats) = Self::parse_frame_descriptor(
                                         &config_data[pos..pos + length],
                                         format_type,
                                     )
@@ -544,8 +619,8 @@ impl UvcDevice {
     }
 
     /// 解析未压缩格式类型（仅返回格式类型，不生成VideoFormat）
-    fn parse_uncompressed_format_type(&self, data: &[u8]) -> Result<UncompressedFormat, USBError> {
-        match self.descriptor_parser.parse_uncompressed_format(data) {
+    fn parse_uncompressed_format_type(data: &[u8]) -> Result<UncompressedFormat, USBError> {
+        match DescriptorParser::new().parse_uncompressed_format(data) {
             Ok(desc) => {
                 // 根据GUID确定格式类型
                 let format_type = if desc.guid == format_guids::YUY2 {
@@ -557,6 +632,9 @@ impl UvcDevice {
                 } else if desc.guid == format_guids::RGB24 {
                     debug!("Detected RGB24 format");
                     UncompressedFormat::Rgb24
+                } else if desc.guid == format_guids::Y800 {
+                    debug!("Detected Y800 format");
+                    UncompressedFormat::Y800
                 } else {
                     debug!(
                         "Unknown uncompressed format GUID: {:02x?}, defaulting to YUY2",
@@ -573,11 +651,10 @@ impl UvcDevice {
 
     /// 解析帧描述符
     fn parse_frame_descriptor(
-        &self,
         data: &[u8],
         format_type: VideoFormatType,
     ) -> Result<Vec<VideoFormat>, USBError> {
-        match self.descriptor_parser.parse_frame_descriptor(data) {
+        match DescriptorParser::new().parse_frame_descriptor(data) {
             Ok(frame_desc) => {
                 // 计算默认帧率 (frame interval 以100ns为单位)
                 let default_frame_rate =
@@ -589,6 +666,7 @@ impl UvcDevice {
                     height: frame_desc.height,
                     frame_rate: default_frame_rate,
                     format_type,
+                    h264: None,
                 };
 
                 trace!("Parsed frame format: {video_format:?}");
@@ -702,6 +780,7 @@ impl UvcDevice {
                 height,
                 frame_rate: 30,
                 format_type: VideoFormatType::Mjpeg,
+                h264: None,
             });
         }
 
@@ -744,6 +823,9 @@ impl UvcDevice {
         } else if guid == uvc_guids::RGB24 {
             debug!("Detected RGB24 format");
             UncompressedFormat::Rgb24
+        } else if guid == uvc_guids::Y800 {
+            debug!("Detected Y800 format");
+            UncompressedFormat::Y800
         } else {
             debug!("Unknown uncompressed format GUID: {guid:02x?}, defaulting to YUY2");
             UncompressedFormat::Yuy2 // 默认为YUY2
@@ -760,6 +842,7 @@ impl UvcDevice {
                 height,
                 frame_rate: 30, // 默认帧率，实际应该从帧描述符解析
                 format_type: VideoFormatType::Uncompressed(format_type),
+                h264: None,
             });
         }
 
@@ -770,38 +853,168 @@ impl UvcDevice {
         Ok(formats)
     }
 
-    /// 设置视频格式
-    pub async fn set_format(&mut self, format: VideoFormat) -> Result<(), USBError> {
-        debug!("Setting video format: {format:?}");
+    /// 设置视频格式，返回设备协商后的参数
+    ///
+    /// 依次执行 PROBE 的 GET_MIN / GET_MAX、SET_CUR、GET_CUR，再以设备返回的参数 COMMIT。
+    /// 设备可能调整帧间隔、最大帧大小等字段，返回值与 [`UvcDevice::stream_control`] 为实际采用的值。
+    /// COMMIT 被拒绝（通常是带宽不足）时先降低帧率、再减小 dwMaxPayloadTransferSize 后重新协商，
+    /// 调整后的帧率同时反映在 [`UvcDevice::get_current_format`] 中。
+    pub async fn set_format(&mut self, format: VideoFormat) -> Result<StreamControl, USBError> {
+        self.negotiate_format(format, &[]).await
+    }
+
+    /// 设置 H.264 联播 / SVC 格式，并为各路流选择分层结构
+    ///
+    /// `layouts` 依次对应 bmLayoutPerStream 中的流 0..3，可用的层数见 [`VideoFormat::h264`]。
+    /// 带宽受限时可以只请求 [`H264StreamLayout::BASE`]。需要 UVC 1.5 设备。
+    pub async fn set_format_with_layout(
+        &mut self,
+        format: VideoFormat,
+        layouts: &[H264StreamLayout],
+    ) -> Result<StreamControl, USBError> {
+        if format.format_type != VideoFormatType::H264 || layouts.is_empty() || layouts.len() > 4 {
+            Err(USBError::InvalidParameter)?;
+        }
+        if let Some(info) = &format.h264
+            && layouts.iter().any(|l| {
+                l.temporal_layers > info.max_temporal_layers
+                    || l.spatial_layers > info.max_spatial_layers
+            })
+        {
+            Err(anyhow!("Requested layout exceeds frame capabilities"))?;
+        }
+        if self.stream_control_len().await? < StreamControl::len_for_version(0x0150) {
+            Err(anyhow!("H.264 stream layout requires UVC 1.5"))?;
+        }
+        self.negotiate_format(format, layouts).await
+    }
+
+    async fn negotiate_format(
+        &mut self,
+        format: VideoFormat,
+        layouts: &[H264StreamLayout],
+    ) -> Result<StreamControl, USBError> {
+        debug!("Setting video format: {format:?}, layouts: {layouts:?}");
 
         // 参考 libuvc 实现，需要先 probe 然后 commit
+        let len = self.stream_control_len().await?;
+
         // 1. 构建 VS stream control 结构
-        let mut stream_ctrl = self.build_stream_control(&format).await?;
+        let (mut stream_ctrl, frame) = self.build_stream_control(&format).await?;
+        for (slot, layout) in stream_ctrl.layout_per_stream.iter_mut().zip(layouts) {
+            *slot = layout.to_bits();
+        }
 
-        // 2. 先发送 PROBE 控制请求
-        debug!("Sending PROBE control request");
-        self.send_vs_control(vs_controls::VS_PROBE_CONTROL, &stream_ctrl)
+        // 2. 按设备声明的范围限制帧间隔，部分设备不支持 GET_MIN / GET_MAX。
+        // PROBE 的 GET_MIN / GET_MAX 针对当前协商的格式与帧，须先以新格式 SET_CUR
+        self.send_vs_control(vs_controls::VS_PROBE_CONTROL, &stream_ctrl, len)
             .await?;
+        let min = self
+            .get_vs_control_with(uvc_requests::GET_MIN, vs_controls::VS_PROBE_CONTROL, len)
+            .await
+            .and_then(|data| StreamControl::parse(&data));
+        let max = self
+            .get_vs_control_with(uvc_requests::GET_MAX, vs_controls::VS_PROBE_CONTROL, len)
+            .await
+            .and_then(|data| StreamControl::parse(&data));
+        if let (Ok(min), Ok(max)) = (&min, &max)
+            && min.frame_interval > 0
+            && min.frame_interval <= max.frame_interval
+        {
+            stream_ctrl.frame_interval = stream_ctrl
+                .frame_interval
+                .clamp(min.frame_interval, max.frame_interval);
+        }
 
-        // 3. 获取设备的 PROBE 响应
-        debug!("Getting PROBE response");
-        let probe_response = self
-            .get_vs_control(vs_controls::VS_PROBE_CONTROL, 26)
-            .await?;
-        stream_ctrl = self.parse_stream_control(&probe_response)?;
+        let requested_interval = stream_ctrl.frame_interval;
+        let mut retries = 0;
+        loop {
+            // 3. 发送 PROBE 控制请求
+            debug!("Sending PROBE control request");
+            self.send_vs_control(vs_controls::VS_PROBE_CONTROL, &stream_ctrl, len)
+                .await?;
 
-        // 4. 发送 COMMIT 控制请求
-        debug!("Sending COMMIT control request");
-        self.send_vs_control(vs_controls::VS_COMMIT_CONTROL, &stream_ctrl)
-            .await?;
+            // 4. 获取设备的 PROBE 响应
+            debug!("Getting PROBE response");
+            let probe_response = self
+                .get_vs_control(vs_controls::VS_PROBE_CONTROL, len)
+                .await?;
+            let probed = StreamControl::parse(&probe_response)?;
+            if probed.format_index != stream_ctrl.format_index
+                || probed.frame_index != stream_ctrl.frame_index
+            {
+                warn!(
+                    "Device changed format/frame index from {}/{} to {}/{}",
+                    stream_ctrl.format_index,
+                    stream_ctrl.frame_index,
+                    probed.format_index,
+                    probed.frame_index
+                );
+            }
+            stream_ctrl = probed;
+
+            // 5. 发送 COMMIT 控制请求，被拒绝时降低带宽需求后重新 PROBE
+            debug!("Sending COMMIT control request");
+            let Err(e) = self
+                .send_vs_control(vs_controls::VS_COMMIT_CONTROL, &stream_ctrl, len)
+                .await
+            else {
+                break;
+            };
+            let next = Self::degrade_stream_control(&frame, &stream_ctrl)
+                .filter(|_| retries < MAX_COMMIT_RETRIES)
+                .ok_or(e)?;
+            warn!(
+                "COMMIT rejected, retrying with frame interval {} and payload size {}",
+                next.frame_interval, next.max_payload_transfer_size
+            );
+            stream_ctrl = next;
+            retries += 1;
+        }
+
+        // 6. 读回设备实际采用的参数，失败时以 PROBE 结果为准
+        let committed = match self
+            .get_vs_control(vs_controls::VS_COMMIT_CONTROL, len)
+            .await
+            .and_then(|data| StreamControl::parse(&data))
+        {
+            Ok(ctrl) => ctrl,
+            Err(e) => {
+                debug!("GET_CUR(COMMIT) failed, using probe result: {e:?}");
+                stream_ctrl
+            }
+        };
+        debug!("Committed stream control: {committed:?}");
+        if committed.frame_interval != requested_interval {
+            info!(
+                "Frame rate adjusted from {} to {} fps",
+                DescriptorParser::interval_to_fps(requested_interval),
+                committed.frame_rate()
+            );
+        }
 
         debug!("Video format set successfully");
-        self.current_format = Some(format);
-        Ok(())
+        self.current_format = Some(VideoFormat {
+            frame_rate: committed.frame_rate(),
+            ..format
+        });
+        self.committed = Some(committed.clone());
+        Ok(committed)
     }
 
     /// 开始视频流传输
+    ///
+    /// 主机带宽不足时换用更小的 alternate setting，必要时降低帧率或负载大小重新协商，
+    /// 调整后的帧率反映在 [`UvcDevice::get_current_format`] 中。
     pub async fn start_streaming(&mut self) -> Result<VideoStream, USBError> {
+        self.start_streaming_with(StreamConfig::default()).await
+    }
+
+    /// 按指定参数开始视频流传输，例如丢弃预热帧
+    pub async fn start_streaming_with(
+        &mut self,
+        stream_config: StreamConfig,
+    ) -> Result<VideoStream, USBError> {
         let vs_interface_num = self.video_streaming_interface_num;
 
         let current_format = self
@@ -809,84 +1022,219 @@ impl UvcDevice {
             .clone()
             .ok_or(anyhow!("No format selected"))?;
 
-        // 参考 libuvc 的实现，根据 dwMaxPayloadTransferSize 选择合适的 alternate setting
         let config = &self.device.configurations()[0];
         let vs_interface_group = config
             .interfaces
             .iter()
             .find(|iface| iface.first_alt_setting().interface_number == vs_interface_num)
             .ok_or(USBError::NotFound)?;
+        let candidates = Self::streaming_alt_settings(vs_interface_group);
+        if candidates.is_empty() {
+            Err(anyhow!("No isochronous IN endpoint found"))?;
+        }
 
-        let max_payload_size = current_format.frame_bytes();
-
-        debug!("Looking for alternate setting with payload size >= {max_payload_size}");
-
-        // 查找能够满足带宽要求的 alternate setting
-        let mut best_alt_setting = None;
-        let mut best_endpoint_size = 0;
+        debug!(
+            "Payload size {} bytes, streaming alt settings {:?}",
+            current_format.frame_bytes(),
+            candidates
+                .iter()
+                .map(|(alt, ep)| (alt, Self::iso_capacity(ep)))
+                .collect::<Vec<_>>()
+        );
 
-        for alt_setting in vs_interface_group.alt_settings.iter() {
-            for endpoint in &alt_setting.endpoints {
-                if matches!(endpoint.transfer_type, EndpointType::Isochronous)
-                    && matches!(endpoint.direction, Direction::In)
-                {
-                    let packet_size = endpoint.max_packet_size as usize;
-                    debug!(
-                        "Alt setting {}: endpoint size = {}",
-                        alt_setting.alternate_setting, packet_size
-                    );
+        // 带宽不足时依次换用更小的 alternate setting；更小的端点容纳不下已提交的
+        // dwMaxPayloadTransferSize 时，先降低帧率或负载大小重新 PROBE / COMMIT
+        let mut retries = 0;
+        let mut claimed = None;
+        for (i, (alt_setting, ep_desc)) in candidates.iter().enumerate() {
+            let capacity = Self::iso_capacity(ep_desc);
+            while i > 0
+                && retries < MAX_COMMIT_RETRIES
+                && self
+                    .committed
+                    .as_ref()
+                    .is_some_and(|c| c.max_payload_transfer_size > capacity)
+            {
+                if !self.renegotiate_degraded().await? {
+                    break;
+                }
+                retries += 1;
+            }
 
-                    // 选择适中的端点大小以获得稳定的带宽
-                    // 避免选择太小（<256）或太大（>1024）的端点
-                    if (256..=1024).contains(&packet_size) && packet_size > best_endpoint_size {
-                        best_alt_setting = Some(alt_setting.clone());
-                        best_endpoint_size = packet_size;
-                    } else if best_alt_setting.is_none() && packet_size > best_endpoint_size {
-                        // 如果没有找到理想范围内的，选择最大的
-                        best_alt_setting = Some(alt_setting.clone());
-                        best_endpoint_size = packet_size;
-                    }
+            debug!("Selected alternate setting {alt_setting} with endpoint capacity {capacity}");
+            match self
+                .device
+                .claim_interface(vs_interface_num, *alt_setting)
+                .await
+            {
+                Ok(()) => {
+                    claimed = Some(ep_desc.clone());
+                    break;
+                }
+                Err(USBError::TransferError(TransferError::NoBandwidth)) => {
+                    warn!("Not enough bandwidth for alternate setting {alt_setting}");
                 }
+                Err(e) => return Err(e),
             }
         }
+        let ep_desc = claimed.ok_or(TransferError::NoBandwidth)?;
+        let ep = self.device.endpoint(ep_desc.address)?;
 
-        let alt_setting =
-            best_alt_setting.unwrap_or(vs_interface_group.alt_settings.first().cloned().unwrap()); // 默认为 alt setting 1
-
-        debug!(
-            "Selected alternate setting {} with endpoint size {best_endpoint_size}",
-            alt_setting.alternate_setting
+        debug!("Starting video streaming");
+        self.state = UvcDeviceState::Streaming;
+        let mut stream = VideoStream::with_config(
+            ep,
+            ep_desc,
+            self.current_format.clone().unwrap(),
+            stream_config,
         );
+        stream.set_clock_frequency(self.clock_frequency());
+        Ok(stream)
+    }
+
+    /// 设备时钟频率：UVC 1.1 起取 COMMIT 确认的 dwClockFrequency，否则取 VC 头描述符
+    fn clock_frequency(&self) -> u32 {
+        self.committed
+            .as_ref()
+            .map(|c| c.clock_frequency)
+            .filter(|&hz| hz != 0)
+            .or_else(|| self.topology.header.as_ref().map(|h| h.clock_frequency))
+            .unwrap_or(0)
+    }
 
-        // 切换到选中的 alternate setting
+    /// 停止视频流传输，VS 接口切回不占用带宽的 alternate setting 0
+    pub async fn stop_streaming(&mut self, stream: VideoStream) -> Result<(), USBError> {
+        // 先释放端点，再切换 alternate setting
+        drop(stream);
         self.device
-            .claim_interface(vs_interface_num, alt_setting.alternate_setting)
+            .claim_interface(self.video_streaming_interface_num, 0)
             .await?;
+        self.state = UvcDeviceState::Configured;
+        Ok(())
+    }
 
-        let mut ep = None;
-        // 查找同步 IN 端点
-        for endpoint in alt_setting.endpoints.clone().into_iter() {
-            if matches!(endpoint.transfer_type, EndpointType::Isochronous)
-                && matches!(endpoint.direction, Direction::In)
-            {
-                debug!("Found isochronous IN endpoint: {endpoint:?}");
-                ep = Some(endpoint.clone());
+    /// 拍摄一帧：按 `format` 协商并开流，取预热结束后的第一个完整帧，然后停止流
+    ///
+    /// 压缩格式等待帧大小稳定（[`Warmup::stable_size`]），未压缩格式丢弃固定数量的帧，
+    /// 且只接受长度与分辨率相符的帧。长时间收不到有效帧时返回 [`USBError::Timeout`]。
+    pub async fn photo(&mut self, format: VideoFormat) -> Result<VideoFrame, USBError> {
+        let warmup = match format.format_type {
+            VideoFormatType::Uncompressed(_) => Warmup::DiscardFrames(PHOTO_WARMUP_FRAMES),
+            VideoFormatType::Mjpeg | VideoFormatType::H264 => Warmup::stable_size(),
+        };
+        self.set_format(format).await?;
+        let mut stream = self
+            .start_streaming_with(StreamConfig {
+                warmup,
+                ..Default::default()
+            })
+            .await?;
+        let format = stream.vedio_format.clone();
+
+        let mut frame = Err(USBError::Timeout);
+        for _ in 0..PHOTO_MAX_TRANSFERS {
+            let events = match stream.recv().await {
+                Ok(events) => events,
+                Err(e) => {
+                    frame = Err(e);
+                    break;
+                }
+            };
+            let clean = events.into_iter().find(|event| match format.format_type {
+                VideoFormatType::Uncompressed(_) => event.data.len() == format.frame_bytes(),
+                VideoFormatType::Mjpeg | VideoFormatType::H264 => !event.data.is_empty(),
+            });
+            if let Some(event) = clean {
+                frame = Ok(VideoFrame::from_event(event, format.clone()));
                 break;
             }
         }
+        debug!(
+            "Photo: {} warmup frames discarded",
+            stream.warmup_discarded_count()
+        );
 
-        let ep_desc = ep.ok_or(anyhow!("No isochronous IN endpoint found"))?;
-        let ep = self.device.endpoint(ep_desc.address)?;
+        self.stop_streaming(stream).await?;
+        frame
+    }
 
-        debug!("Starting video streaming");
-        self.state = UvcDeviceState::Streaming;
-        Ok(VideoStream::new(
-            ep,
-            ep_desc,
-            self.current_format.clone().unwrap(),
+    /// 设备确认的视频流参数，成功调用 [`UvcDevice::set_format`] 之后可用
+    ///
+    /// 其中的帧间隔、最大帧大小、延迟与 bmFramingInfo 为设备实际采用的值，可能与请求不同。
+    pub fn stream_control(&self) -> Option<&StreamControl> {
+        self.committed.as_ref()
+    }
+
+    /// VS 输入头声明的静态图像捕获方式，首次调用 [`UvcDevice::set_format`] 之后可用
+    pub fn still_capture_method(&self) -> StillCaptureMethod {
+        self.still_capture_method
+    }
+
+    /// 设备的 bcdUVC，首次调用 [`UvcDevice::set_format`] 之后可用
+    pub fn uvc_version(&self) -> Option<u16> {
+        self.bcd_uvc
+    }
+
+    /// 按设备 UVC 版本确定 PROBE / COMMIT 控制块长度
+    async fn stream_control_len(&mut self) -> Result<usize, USBError> {
+        if self.bcd_uvc.is_none() {
+            let config_data = self.get_full_configuration_descriptor().await?;
+            let (bcd_uvc, still) = Self::parse_class_info(
+                &config_data,
+                self.video_control_interface_num,
+                self.video_streaming_interface_num,
+            );
+            debug!("UVC version {bcd_uvc:#06x}, still capture {still:?}");
+            self.bcd_uvc = Some(bcd_uvc);
+            self.still_capture_method = still;
+        }
+        Ok(StreamControl::len_for_version(
+            self.bcd_uvc.unwrap_or(0x0100),
         ))
     }
 
+    /// 从配置描述符中读取 VC 头的 bcdUVC 与 VS 输入头的 bStillCaptureMethod
+    ///
+    /// 找不到 VC 头时按 UVC 1.0 处理。
+    pub(crate) fn parse_class_info(
+        config_data: &[u8],
+        vc_interface_num: u8,
+        vs_interface_num: u8,
+    ) -> (u16, StillCaptureMethod) {
+        let parser = DescriptorParser::new();
+        let mut bcd_uvc = 0x0100;
+        let mut still = StillCaptureMethod::None;
+        let mut current_interface = None;
+        let mut pos = 0;
+
+        while pos + 2 <= config_data.len() {
+            let length = config_data[pos] as usize;
+            if length < 2 || pos + length > config_data.len() {
+                break;
+            }
+            let desc = &config_data[pos..pos + length];
+            match desc[1] {
+                0x04 if length >= 9 => current_interface = Some(desc[2]),
+                uvc_descriptor_types::CS_INTERFACE if length >= 3 => {
+                    if current_interface == Some(vc_interface_num)
+                        && desc[2] == uvc_interface_subtypes::VC_HEADER
+                        && let Ok(header) = parser.parse_vc_header(desc)
+                    {
+                        bcd_uvc = header.bcd_uvc;
+                    } else if current_interface == Some(vs_interface_num)
+                        && desc[2] == uvc_interface_subtypes::VS_INPUT_HEADER
+                        && let Ok(header) = parser.parse_vs_input_header(desc)
+                    {
+                        still = header.still_capture_method.into();
+                    }
+                }
+                _ => {}
+            }
+            pos += length;
+        }
+        (bcd_uvc, still)
+    }
+
     /// 获取当前设备状态
     pub fn get_state(&self) -> &UvcDeviceState {
         &self.state
@@ -904,41 +1252,29 @@ impl UvcDevice {
     ) -> Result<(), USBError> {
         debug!("Sending video control command: {command:?}");
 
-        let processing_unit_id = self.processing_unit_id.ok_or(USBError::NotFound)?;
-
         match command {
             VideoControlEvent::BrightnessChanged(value) => {
                 debug!("Setting brightness to: {value}");
-                self.send_pu_control(
-                    pu_controls::PU_BRIGHTNESS_CONTROL,
-                    processing_unit_id,
-                    &value.to_le_bytes(),
-                )
-                .await?;
+                self.send_pu_control(pu_controls::PU_BRIGHTNESS_CONTROL, &value.to_le_bytes())
+                    .await?;
             }
             VideoControlEvent::ContrastChanged(value) => {
                 debug!("Setting contrast to: {value}");
                 self.send_pu_control(
                     pu_controls::PU_CONTRAST_CONTROL,
-                    processing_unit_id,
                     &(value as u16).to_le_bytes(),
                 )
                 .await?;
             }
             VideoControlEvent::HueChanged(value) => {
                 debug!("Setting hue to: {value}");
-                self.send_pu_control(
-                    pu_controls::PU_HUE_CONTROL,
-                    processing_unit_id,
-                    &value.to_le_bytes(),
-                )
-                .await?;
+                self.send_pu_control(pu_controls::PU_HUE_CONTROL, &value.to_le_bytes())
+                    .await?;
             }
             VideoControlEvent::SaturationChanged(value) => {
                 debug!("Setting saturation to: {value}");
                 self.send_pu_control(
                     pu_controls::PU_SATURATION_CONTROL,
-                    processing_unit_id,
                     &(value as u16).to_le_bytes(),
                 )
                 .await?;
@@ -951,19 +1287,103 @@ impl UvcDevice {
         Ok(())
     }
 
-    /// 发送处理单元控制请求
-    async fn send_pu_control(
+    /// 设置电源频率（抗闪烁）
+    pub async fn set_power_line_frequency(
         &mut self,
-        control_selector: u8,
-        unit_id: u8,
-        data: &[u8],
+        frequency: PowerLineFrequency,
     ) -> Result<(), USBError> {
+        debug!("Setting power line frequency to: {frequency:?}");
+        self.send_pu_control(
+            pu_controls::PU_POWER_LINE_FREQUENCY_CONTROL,
+            &[frequency as u8],
+        )
+        .await
+    }
+
+    /// 读取当前电源频率（抗闪烁）设置
+    pub async fn get_power_line_frequency(&mut self) -> Result<PowerLineFrequency, USBError> {
+        let data = self
+            .get_pu_control(pu_controls::PU_POWER_LINE_FREQUENCY_CONTROL, 1)
+            .await?;
+        PowerLineFrequency::try_from(data[0])
+    }
+
+    /// 开关自动白平衡（色温）
+    pub async fn set_white_balance_temperature_auto(&mut self, on: bool) -> Result<(), USBError> {
+        self.set_pu_auto(pu_controls::PU_WHITE_BALANCE_TEMPERATURE_AUTO_CONTROL, on)
+            .await
+    }
+
+    /// 读取自动白平衡（色温）是否开启
+    pub async fn get_white_balance_temperature_auto(&mut self) -> Result<bool, USBError> {
+        self.get_pu_auto(pu_controls::PU_WHITE_BALANCE_TEMPERATURE_AUTO_CONTROL)
+            .await
+    }
+
+    /// 开关自动白平衡（分量）
+    pub async fn set_white_balance_component_auto(&mut self, on: bool) -> Result<(), USBError> {
+        self.set_pu_auto(pu_controls::PU_WHITE_BALANCE_COMPONENT_AUTO_CONTROL, on)
+            .await
+    }
+
+    /// 读取自动白平衡（分量）是否开启
+    pub async fn get_white_balance_component_auto(&mut self) -> Result<bool, USBError> {
+        self.get_pu_auto(pu_controls::PU_WHITE_BALANCE_COMPONENT_AUTO_CONTROL)
+            .await
+    }
+
+    /// 开关自动色调
+    pub async fn set_hue_auto(&mut self, on: bool) -> Result<(), USBError> {
+        self.set_pu_auto(pu_controls::PU_HUE_AUTO_CONTROL, on).await
+    }
+
+    /// 读取自动色调是否开启
+    pub async fn get_hue_auto(&mut self) -> Result<bool, USBError> {
+        self.get_pu_auto(pu_controls::PU_HUE_AUTO_CONTROL).await
+    }
+
+    async fn set_pu_auto(&mut self, control_selector: u8, on: bool) -> Result<(), USBError> {
+        debug!("Setting PU auto control 0x{control_selector:02x} to: {on}");
+        self.send_pu_control(control_selector, &[on as u8]).await
+    }
+
+    async fn get_pu_auto(&mut self, control_selector: u8) -> Result<bool, USBError> {
+        let data = self.get_pu_control(control_selector, 1).await?;
+        Ok(data[0] != 0)
+    }
+
+    /// VC 接口的终端与单元，来自 VC 接口的类特定描述符
+    pub fn topology(&self) -> &VideoControlTopology {
+        &self.topology
+    }
+
+    /// 处理单元是否支持控制选择器 `control_selector`（bmControls 中的对应位）
+    pub fn supports_pu_control(&self, control_selector: u8) -> bool {
+        self.topology
+            .processing_unit()
+            .is_some_and(|pu| pu.supports(control_selector))
+    }
+
+    /// 处理单元控制请求的 wIndex：高字节为单元 ID，低字节为 VC 接口号
+    ///
+    /// 设备没有处理单元时返回 [`USBError::NotFound`]，处理单元未声明该控制时返回
+    /// [`USBError::NotSupported`]。
+    fn pu_control_index(&self, control_selector: u8) -> Result<u16, USBError> {
+        let pu = self.topology.processing_unit().ok_or(USBError::NotFound)?;
+        if !pu.supports(control_selector) {
+            return Err(USBError::NotSupported);
+        }
+        Ok(((pu.unit_id as u16) << 8) | self.video_control_interface_num as u16)
+    }
+
+    /// 发送处理单元控制请求
+    async fn send_pu_control(&mut self, control_selector: u8, data: &[u8]) -> Result<(), USBError> {
         let setup = ControlSetup {
             request_type: RequestType::Class,
             recipient: Recipient::Interface,
-            request: uvc_requests::SET_CUR.into(),
+            request: Request::Class(uvc_requests::SET_CUR),
             value: (control_selector as u16) << 8,
-            index: unit_id as u16,
+            index: self.pu_control_index(control_selector)?,
         };
 
         self.device.control_out(setup, data).await?;
@@ -971,45 +1391,157 @@ impl UvcDevice {
         Ok(())
     }
 
-    /// 构建 Stream Control 结构体
+    /// 读取处理单元控制的取值范围、步长、默认值与能力，用于呈现有效的调节范围
     ///
-    /// 此函数参考了 libuvc 的 uvc_get_stream_ctrl_format_size 实现，包括：
-    /// 1. 通过遍历设备描述符来查找匹配的格式和帧索引（而不是使用硬编码的值）
-    /// 2. 正确计算帧间隔（frame interval），使用100ns为单位
-    /// 3. 根据不同的格式类型估算最大帧大小
-    /// 4. 设置适当的 bmHint 标志位
+    /// 开关与枚举类控制（自动模式、电源频率等）不支持 GET_MIN/GET_MAX/GET_RES，
+    /// 只查询默认值，范围取规范规定的取值。控制未被处理单元声明或 GET_INFO 报告不支持
+    /// GET 时返回 [`USBError::NotSupported`]。White Balance Component 有两个分量，
+    /// 使用 [`UvcDevice::get_white_balance_component_range`]。
+    pub async fn get_control_range(
+        &mut self,
+        control_selector: u8,
+    ) -> Result<ControlRange, USBError> {
+        if pu_control_components(control_selector) != 1 {
+            return Err(USBError::InvalidParameter);
+        }
+        let [range] = self.control_ranges(control_selector).await?;
+        Ok(range)
+    }
+
+    /// White Balance Component 蓝、红两个分量各自的取值范围
+    pub async fn get_white_balance_component_range(
+        &mut self,
+    ) -> Result<[ControlRange; 2], USBError> {
+        self.control_ranges(pu_controls::PU_WHITE_BALANCE_COMPONENT_CONTROL)
+            .await
+    }
+
+    /// 读取有 `N` 个分量的处理单元控制的取值范围，每个分量单独解码
+    async fn control_ranges<const N: usize>(
+        &mut self,
+        control_selector: u8,
+    ) -> Result<[ControlRange; N], USBError> {
+        let (len, signed) = pu_control_layout(control_selector).ok_or(USBError::NotSupported)?;
+        let info = self
+            .pu_request(uvc_requests::GET_INFO, control_selector, 1)
+            .await?[0];
+        if info & control_capabilities::GET == 0 {
+            return Err(USBError::NotSupported);
+        }
+
+        let decode = |data: &[u8]| -> Result<[i64; N], USBError> {
+            let mut values = [0i64; N];
+            for (value, chunk) in values.iter_mut().zip(data.chunks_exact(len)) {
+                *value = decode_control_value(chunk, signed).ok_or(USBError::InvalidParameter)?;
+            }
+            Ok(values)
+        };
+        let requests = pu_control_requests(control_selector);
+        // 只读状态没有默认值，以当前值代替
+        let def_request = match requests {
+            ControlRequests::ReadOnly { .. } => uvc_requests::GET_CUR,
+            _ => uvc_requests::GET_DEF,
+        };
+        let def = decode(
+            &self
+                .pu_request(def_request, control_selector, len * N)
+                .await?,
+        )?;
+        let (min, max, res) = match requests {
+            ControlRequests::Range => {
+                let mut values = [[0i64; N]; 3];
+                let requests = [
+                    uvc_requests::GET_MIN,
+                    uvc_requests::GET_MAX,
+                    uvc_requests::GET_RES,
+                ];
+                for (value, request) in values.iter_mut().zip(requests) {
+                    *value = decode(&self.pu_request(request, control_selector, len * N).await?)?;
+                }
+                let [min, max, res] = values;
+                (min, max, res)
+            }
+            ControlRequests::Default { min, max } | ControlRequests::ReadOnly { min, max } => {
+                ([min; N], [max; N], [1; N])
+            }
+        };
+        Ok(core::array::from_fn(|i| ControlRange {
+            min: min[i],
+            max: max[i],
+            res: res[i],
+            def: def[i],
+            info,
+        }))
+    }
+
+    /// 读取处理单元控制的当前值（GET_CUR）
+    async fn get_pu_control(
+        &mut self,
+        control_selector: u8,
+        length: usize,
+    ) -> Result<Vec<u8>, USBError> {
+        self.pu_request(uvc_requests::GET_CUR, control_selector, length)
+            .await
+    }
+
+    /// 发送处理单元的 GET_* 请求
+    async fn pu_request(
+        &mut self,
+        request: u8,
+        control_selector: u8,
+        length: usize,
+    ) -> Result<Vec<u8>, USBError> {
+        let setup = ControlSetup {
+            request_type: RequestType::Class,
+            recipient: Recipient::Interface,
+            request: Request::Class(request),
+            value: (control_selector as u16) << 8,
+            index: self.pu_control_index(control_selector)?,
+        };
+
+        let mut buffer = vec![0u8; length];
+        let len = self.device.control_in(setup, &mut buffer).await?;
+        if len < length {
+            Err(anyhow!(
+                "PU control 0x{control_selector:02x} request 0x{request:02x} returned {len} bytes, expected {length}"
+            ))?;
+        }
+
+        Ok(buffer)
+    }
+
+    /// 构建 Stream Control 结构体
     ///
-    /// libuvc 参考：
-    /// - src/stream.c:uvc_get_stream_ctrl_format_size (line 474-524)
-    /// - src/stream.c:_uvc_find_frame_desc_stream_if (line 415-444)
+    /// 参考 libuvc 的 uvc_get_stream_ctrl_format_size：
+    /// 1. 遍历 VS 接口描述符，取匹配格式的 bFormatIndex 与匹配分辨率的 bFrameIndex
+    /// 2. 帧间隔取帧描述符支持的、与请求帧率最接近的值，100ns 为单位
+    /// 3. 最大帧大小优先使用帧描述符中的值，缺失时按格式估算
+    /// 4. 设置 bmHint 要求设备保持帧间隔
     async fn build_stream_control(
         &mut self,
         format: &VideoFormat,
-    ) -> Result<StreamControl, USBError> {
+    ) -> Result<(StreamControl, FrameDescriptor), USBError> {
         debug!("Building stream control for format: {format:?}");
 
-        // 获取支持的格式列表来查找对应的格式索引
-        let formats = self.get_supported_formats().await?;
-
-        // 查找匹配的格式和帧索引（参考 libuvc 的实现逻辑）
-        let (format_index, frame_index) =
-            self.find_format_indices(&formats, format).ok_or_else(|| {
-                debug!("Failed to find matching format for: {format:?}");
-                anyhow!("No matching format found")
-            })?;
-
-        // 计算帧间隔 (100ns 单位)，参考 libuvc 的计算方式
-        let frame_interval = if format.frame_rate > 0 {
-            10_000_000 / format.frame_rate // 100ns units，与 libuvc 一致
-        } else {
-            333333 // 默认 30fps (10,000,000 / 30)
-        };
+        let config_data = self.get_full_configuration_descriptor().await?;
+        let (format_index, frame) =
+            Self::find_frame(&config_data, self.video_streaming_interface_num, format).ok_or_else(
+                || {
+                    debug!("Failed to find matching format for: {format:?}");
+                    anyhow!("No matching format found")
+                },
+            )?;
+        let frame_interval = Self::choose_frame_interval(&frame, format.frame_rate);
+        debug!(
+            "Using format_index={format_index}, frame_index={}, interval={frame_interval}",
+            frame.frame_index
+        );
 
         // 根据格式类型估算最大帧大小
         let width = format.width as u32;
         let height = format.height as u32;
 
-        let max_frame_size = match format.format_type {
+        let estimated_frame_size = match format.format_type {
             VideoFormatType::Mjpeg => {
                 // MJPEG 压缩格式：参考 libuvc，通常为未压缩大小的一半左右
                 width * height * 2
@@ -1021,6 +1553,7 @@ impl UvcDevice {
                     UncompressedFormat::Nv12 => width * height * 3 / 2, // NV12: 1.5 bytes per pixel
                     UncompressedFormat::Rgb24 => width * height * 3, // RGB24: 3 bytes per pixel
                     UncompressedFormat::Rgb32 => width * height * 4, // RGB32: 4 bytes per pixel
+                    UncompressedFormat::Y800 => width * height,     // Y800: 1 byte per pixel
                 }
             }
             VideoFormatType::H264 => {
@@ -1028,11 +1561,15 @@ impl UvcDevice {
                 width * height / 2
             }
         };
+        let max_frame_size = match frame.max_video_frame_buffer_size {
+            0 => estimated_frame_size,
+            size => size,
+        };
 
-        Ok(StreamControl {
+        let ctrl = StreamControl {
             hint: 0x0001, // bmHint: dwFrameInterval field shall be kept fixed (参考 libuvc)
             format_index,
-            frame_index,
+            frame_index: frame.frame_index,
             frame_interval,
             key_frame_rate: 0,   // 默认为 0，让设备决定
             p_frame_rate: 0,     // 默认为 0，让设备决定
@@ -1041,77 +1578,227 @@ impl UvcDevice {
             delay: 0,            // 默认为 0
             max_video_frame_size: max_frame_size,
             max_payload_transfer_size: 0, // 让设备决定，参考 libuvc
-        })
+            // UVC 1.1 起请求设备在负载头中提供 FID / EOF
+            framing_info: if self.bcd_uvc.is_some_and(|v| v >= 0x0110) {
+                StreamControl::FRAMING_FID | StreamControl::FRAMING_EOF
+            } else {
+                0
+            },
+            ..Default::default()
+        };
+        Ok((ctrl, frame))
     }
 
-    /// 查找格式和帧索引
-    ///
-    /// 此函数参考了 libuvc 的 _uvc_find_frame_desc_stream_if 实现，提供了：
-    /// 1. 精确的格式类型匹配（包括未压缩格式的子类型）
-    /// 2. 分辨率匹配检查
-    /// 3. 优雅的降级策略（exact match -> format type match -> default）
-    /// 4. 符合 UVC 规范的索引计算（从1开始）
+    /// 在 VS 接口描述符中查找与 `target` 匹配的格式与帧描述符
     ///
-    /// libuvc 参考：
-    /// - src/stream.c:_uvc_find_frame_desc_stream_if (line 415-444)
-    /// - src/stream.c:uvc_find_frame_desc (line 444-474)
-    fn find_format_indices(
-        &self,
-        formats: &[VideoFormat],
+    /// 返回格式描述符的 bFormatIndex 与帧描述符。格式类型与分辨率都匹配时优先，
+    /// 否则退回同一格式类型的第一个帧描述符（参考 libuvc 的 _uvc_find_frame_desc_stream_if）。
+    pub(crate) fn find_frame(
+        config_data: &[u8],
+        vs_interface_num: u8,
         target: &VideoFormat,
-    ) -> Option<(u8, u8)> {
-        // 遍历所有支持的格式，寻找匹配的格式和帧配置
-        for (format_idx, format) in formats.iter().enumerate() {
-            // 检查格式类型是否匹配
-            if core::mem::discriminant(&format.format_type)
-                != core::mem::discriminant(&target.format_type)
-            {
-                continue;
+    ) -> Option<(u8, FrameDescriptor)> {
+        let parser = DescriptorParser::new();
+        let mut in_vs_interface = false;
+        // 当前格式描述符的 bFormatIndex 与是否匹配目标格式
+        let mut current_format: Option<(u8, bool)> = None;
+        let mut fallback = None;
+        let mut pos = 0;
+
+        while pos + 2 <= config_data.len() {
+            let length = config_data[pos] as usize;
+            if length < 2 || pos + length > config_data.len() {
+                break;
             }
+            let desc = &config_data[pos..pos + length];
+            pos += length;
 
-            // 对于未压缩格式，还需要检查具体的子格式
-            if let (
-                VideoFormatType::Uncompressed(format_type),
-                VideoFormatType::Uncompressed(target_type),
-            ) = (&format.format_type, &target.format_type)
-                && format_type != target_type
-            {
-                continue;
+            match desc[1] {
+                0x04 if length >= 9 => {
+                    in_vs_interface = desc[2] == vs_interface_num && desc[5] == 14 && desc[6] == 2;
+                    current_format = None;
+                }
+                uvc_descriptor_types::CS_INTERFACE if in_vs_interface && length >= 4 => {
+                    match desc[2] {
+                        uvc_interface_subtypes::VS_FORMAT_MJPEG => {
+                            current_format =
+                                Some((desc[3], target.format_type == VideoFormatType::Mjpeg));
+                        }
+                        uvc_interface_subtypes::VS_FORMAT_H264
+                        | uvc_interface_subtypes::VS_FORMAT_H264_SIMULCAST => {
+                            // 目标带有分层信息时只匹配同为联播或同为普通 H.264 的格式
+                            let simulcast =
+                                desc[2] == uvc_interface_subtypes::VS_FORMAT_H264_SIMULCAST;
+                            let matches = target.format_type == VideoFormatType::H264
+                                && target.h264.is_none_or(|l| l.simulcast == simulcast);
+                            current_format = Some((desc[3], matches));
+                        }
+                        uvc_interface_subtypes::VS_FORMAT_UNCOMPRESSED => {
+                            let matches =
+                                Self::parse_uncompressed_format_type(desc).is_ok_and(|t| {
+                                    target.format_type == VideoFormatType::Uncompressed(t)
+                                });
+                            current_format = Some((desc[3], matches));
+                        }
+                        uvc_interface_subtypes::VS_FRAME_MJPEG
+                        | uvc_interface_subtypes::VS_FRAME_UNCOMPRESSED
+                        | uvc_interface_subtypes::VS_FRAME_H264 => {
+                            let Some((format_index, true)) = current_format else {
+                                continue;
+                            };
+                            let frame = if desc[2] == uvc_interface_subtypes::VS_FRAME_H264 {
+                                parser
+                                    .parse_h264_frame_descriptor(desc)
+                                    .map(|(frame, _)| frame)
+                            } else {
+                                parser.parse_frame_descriptor(desc)
+                            };
+                            let Ok(frame) = frame else {
+                                continue;
+                            };
+                            if frame.width == target.width && frame.height == target.height {
+                                return Some((format_index, frame));
+                            }
+                            fallback.get_or_insert((format_index, frame));
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
             }
+        }
 
-            // 检查分辨率是否匹配
-            if format.width == target.width && format.height == target.height {
-                // 找到匹配的格式，返回索引（从 1 开始，符合 UVC 规范）
-                let format_index = (format_idx + 1) as u8;
-                let frame_index = 1u8; // 简化实现，假设每个格式只有一个帧配置
+        if let Some((format_index, frame)) = &fallback {
+            debug!(
+                "Using fallback frame: format_index={format_index}, {}x{}",
+                frame.width, frame.height
+            );
+        }
+        fallback
+    }
 
-                debug!(
-                    "Found matching format: format_index={}, frame_index={}",
-                    format_index, frame_index
-                );
-                return Some((format_index, frame_index));
+    /// 选择帧描述符支持的、与 `fps` 最接近的帧间隔，`fps` 为 0 时使用默认帧间隔
+    pub(crate) fn choose_frame_interval(frame: &FrameDescriptor, fps: u32) -> u32 {
+        let target = match fps {
+            0 => return frame.default_frame_interval,
+            fps => DescriptorParser::fps_to_interval(fps),
+        };
+        match (frame.frame_interval_type, frame.frame_intervals.as_slice()) {
+            (0, &[min, max, step]) => {
+                let interval = target.clamp(min, max);
+                if step == 0 {
+                    interval
+                } else {
+                    min + (interval - min + step / 2) / step * step
+                }
+                .min(max)
             }
+            (_, intervals) => intervals
+                .iter()
+                .copied()
+                .min_by_key(|i| i.abs_diff(target))
+                .unwrap_or(frame.default_frame_interval),
         }
+    }
 
-        // 如果没有找到完全匹配的，尝试找到相同格式类型的第一个配置
-        for (format_idx, format) in formats.iter().enumerate() {
-            if core::mem::discriminant(&format.format_type)
-                == core::mem::discriminant(&target.format_type)
-            {
-                let format_index = (format_idx + 1) as u8;
-                let frame_index = 1u8;
+    /// 带同步 IN 端点的 alternate setting 及其端点，按带宽不足时的尝试顺序排列
+    ///
+    /// 首选端点大小在 256..=1024 之间的最大者，没有时选端点容量最大者；
+    /// 其余容量更小的按容量从大到小排在其后。
+    pub(crate) fn streaming_alt_settings(
+        group: &InterfaceDescriptors,
+    ) -> Vec<(u8, EndpointDescriptor)> {
+        let mut alts: Vec<(u8, EndpointDescriptor)> = group
+            .alt_settings
+            .iter()
+            .filter_map(|alt| {
+                let ep = alt.endpoints.iter().find(|ep| {
+                    matches!(ep.transfer_type, EndpointType::Isochronous)
+                        && matches!(ep.direction, Direction::In)
+                })?;
+                Some((alt.alternate_setting, ep.clone()))
+            })
+            .collect();
+        alts.sort_by_key(|(alt, ep)| (core::cmp::Reverse(Self::iso_capacity(ep)), *alt));
+
+        // 选择适中的端点大小以获得稳定的带宽
+        let preferred = alts
+            .iter()
+            .position(|(_, ep)| (256..=1024).contains(&(ep.max_packet_size as usize)))
+            .unwrap_or(0);
+        alts.split_off(preferred)
+    }
 
-                debug!(
-                    "Using fallback format: format_index={}, frame_index={}",
-                    format_index, frame_index
-                );
-                return Some((format_index, frame_index));
+    /// 同步端点每个服务间隔最多传输的字节数
+    fn iso_capacity(ep: &EndpointDescriptor) -> u32 {
+        ep.max_packet_size as u32 * ep.packets_per_microframe.max(1) as u32
+    }
+
+    /// 以降低后的带宽需求重新 PROBE / COMMIT 当前格式，无可调整时返回 `false`
+    async fn renegotiate_degraded(&mut self) -> Result<bool, USBError> {
+        let (Some(format), Some(committed)) = (self.current_format.clone(), self.committed.clone())
+        else {
+            return Ok(false);
+        };
+        let (_, frame) = self.build_stream_control(&format).await?;
+        let Some(next) = Self::degrade_stream_control(&frame, &committed) else {
+            return Ok(false);
+        };
+        warn!(
+            "Renegotiating with frame interval {} and payload size {}",
+            next.frame_interval, next.max_payload_transfer_size
+        );
+        let len = self.stream_control_len().await?;
+        self.send_vs_control(vs_controls::VS_PROBE_CONTROL, &next, len)
+            .await?;
+        let probed = self
+            .get_vs_control(vs_controls::VS_PROBE_CONTROL, len)
+            .await
+            .and_then(|data| StreamControl::parse(&data))?;
+        self.send_vs_control(vs_controls::VS_COMMIT_CONTROL, &probed, len)
+            .await?;
+        info!("Frame rate adjusted to {} fps", probed.frame_rate());
+        self.current_format = Some(VideoFormat {
+            frame_rate: probed.frame_rate(),
+            ..format
+        });
+        self.committed = Some(probed);
+        Ok(true)
+    }
+
+    /// COMMIT 被拒绝后用于重新协商的参数，无可调整时返回 `None`
+    ///
+    /// 优先换用帧描述符中更长的帧间隔（连续间隔时加倍后按步长取整），帧间隔已到上限后
+    /// 将 dwMaxPayloadTransferSize 减半，不低于 [`MIN_PAYLOAD_TRANSFER_SIZE`]。
+    pub(crate) fn degrade_stream_control(
+        frame: &FrameDescriptor,
+        ctrl: &StreamControl,
+    ) -> Option<StreamControl> {
+        let current = ctrl.frame_interval;
+        let longer = match (frame.frame_interval_type, frame.frame_intervals.as_slice()) {
+            (0, &[min, max, step]) => {
+                let target = current.saturating_mul(2).clamp(min, max);
+                let interval = if step == 0 {
+                    target
+                } else {
+                    min + (target - min).div_ceil(step) * step
+                };
+                Some(interval.min(max)).filter(|&i| i > current)
             }
+            (_, intervals) => intervals.iter().copied().filter(|&i| i > current).min(),
+        };
+        if let Some(frame_interval) = longer {
+            return Some(StreamControl {
+                frame_interval,
+                ..ctrl.clone()
+            });
         }
 
-        // 如果还是没有找到，使用默认值（参考 libuvc 的错误处理）
-        debug!("No matching format found, using default indices");
-        None
+        let payload = ctrl.max_payload_transfer_size / 2;
+        (payload >= MIN_PAYLOAD_TRANSFER_SIZE).then(|| StreamControl {
+            max_payload_transfer_size: payload,
+            ..ctrl.clone()
+        })
     }
 
     /// 发送 VS 控制请求
@@ -1119,16 +1806,17 @@ impl UvcDevice {
         &mut self,
         control_selector: u8,
         stream_ctrl: &StreamControl,
+        len: usize,
     ) -> Result<(), USBError> {
         let vs_interface_num = self.video_streaming_interface_num;
 
         // 序列化 StreamControl 到字节数组
-        let data = self.serialize_stream_control(stream_ctrl);
+        let data = stream_ctrl.to_bytes(len);
 
         let setup = ControlSetup {
             request_type: RequestType::Class,
             recipient: Recipient::Interface,
-            request: uvc_requests::SET_CUR.into(),
+            request: Request::Class(uvc_requests::SET_CUR),
             value: (control_selector as u16) << 8,
             index: vs_interface_num as u16,
         };
@@ -1150,13 +1838,24 @@ impl UvcDevice {
         &mut self,
         control_selector: u8,
         length: usize,
+    ) -> Result<Vec<u8>, USBError> {
+        self.get_vs_control_with(uvc_requests::GET_CUR, control_selector, length)
+            .await
+    }
+
+    /// 以 GET_CUR / GET_MIN / GET_MAX 等请求读取 VS 控制
+    async fn get_vs_control_with(
+        &mut self,
+        request: u8,
+        control_selector: u8,
+        length: usize,
     ) -> Result<Vec<u8>, USBError> {
         let vs_interface_num = self.video_streaming_interface_num;
 
         let setup = ControlSetup {
             request_type: RequestType::Class,
             recipient: Recipient::Interface,
-            request: uvc_requests::GET_CUR.into(),
+            request: Request::Class(request),
             value: (control_selector as u16) << 8,
             index: vs_interface_num as u16,
         };
@@ -1173,85 +1872,6 @@ impl UvcDevice {
         Ok(buffer)
     }
 
-    /// 序列化 StreamControl 结构体
-    fn serialize_stream_control(&self, ctrl: &StreamControl) -> Vec<u8> {
-        let mut data = Vec::with_capacity(26);
-
-        // bmHint (2 bytes)
-        data.extend(&ctrl.hint.to_le_bytes());
-        // bFormatIndex (1 byte)
-        data.push(ctrl.format_index);
-        // bFrameIndex (1 byte)
-        data.push(ctrl.frame_index);
-        // dwFrameInterval (4 bytes)
-        data.extend(&ctrl.frame_interval.to_le_bytes());
-        // wKeyFrameRate (2 bytes)
-        data.extend(&ctrl.key_frame_rate.to_le_bytes());
-        // wPFrameRate (2 bytes)
-        data.extend(&ctrl.p_frame_rate.to_le_bytes());
-        // wCompQuality (2 bytes)
-        data.extend(&ctrl.comp_quality.to_le_bytes());
-        // wCompWindowSize (2 bytes)
-        data.extend(&ctrl.comp_window_size.to_le_bytes());
-        // wDelay (2 bytes)
-        data.extend(&ctrl.delay.to_le_bytes());
-        // dwMaxVideoFrameSize (4 bytes)
-        data.extend(&ctrl.max_video_frame_size.to_le_bytes());
-        // dwMaxPayloadTransferSize (4 bytes)
-        data.extend(&ctrl.max_payload_transfer_size.to_le_bytes());
-
-        debug!("Serialized stream control: {} bytes", data.len());
-        data
-    }
-
-    /// 解析 StreamControl 响应
-    fn parse_stream_control(&self, data: &[u8]) -> Result<StreamControl, USBError> {
-        if data.len() < 26 {
-            Err(anyhow!("Stream control response too short"))?;
-        }
-
-        let hint = u16::from_le_bytes([data[0], data[1]]);
-        let format_index = data[2];
-        let frame_index = data[3];
-        let frame_interval = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
-        let key_frame_rate = u16::from_le_bytes([data[8], data[9]]);
-        let p_frame_rate = u16::from_le_bytes([data[10], data[11]]);
-        let comp_quality = u16::from_le_bytes([data[12], data[13]]);
-        let comp_window_size = u16::from_le_bytes([data[14], data[15]]);
-        let delay = u16::from_le_bytes([data[16], data[17]]);
-        let max_video_frame_size = u32::from_le_bytes([data[18], data[19], data[20], data[21]]);
-        let max_payload_transfer_size =
-            u32::from_le_bytes([data[22], data[23], data[24], data[25]]);
-
-        debug!(
-            "Parsed stream control: format={format_index}, frame={frame_index}, interval={frame_interval}, max_frame_size={max_video_frame_size}"
-        );
-
-        Ok(StreamControl {
-            hint,
-            format_index,
-            frame_index,
-            frame_interval,
-            key_frame_rate,
-            p_frame_rate,
-            comp_quality,
-            comp_window_size,
-            delay,
-            max_video_frame_size,
-            max_payload_transfer_size,
-        })
-    }
-
-    // /// 获取当前的 Stream Control 参数
-    // async fn get_current_stream_control(&mut self) -> Result<StreamControl, USBError> {
-    //     // 发送 GET_CUR 请求获取当前的 commit 参数
-    //     debug!("Getting current stream control parameters");
-    //     let response = self
-    //         .get_vs_control(vs_controls::VS_COMMIT_CONTROL, 26)
-    //         .await?;
-    //     self.parse_stream_control(&response)
-    // }
-
     /// 获取设备信息字符串
     pub async fn get_device_info(&self) -> Result<String, USBError> {
         // 在实际实现中，这里可以读取设备的字符串描述符
@@ -0,0 +1,62 @@
+//! 软件帧率限制
+//!
+//! 设备仍按协商的帧率输出，应用只需要更低帧率时在重组阶段跳过多余的帧。
+//! 被跳过的帧不复制载荷、不分配缓冲区，在小型目标上节省 CPU 与内存带宽。
+
+/// 按帧率之比均匀挑选输出的帧
+#[derive(Debug, Clone)]
+pub(crate) struct FrameRateLimiter {
+    source: u32,
+    target: u32,
+    acc: u32,
+}
+
+impl FrameRateLimiter {
+    /// 设备帧率未知或不高于目标帧率时不需要限制，返回 `None`
+    pub fn new(source_fps: u32, target_fps: u32) -> Option<Self> {
+        if source_fps == 0 || target_fps >= source_fps {
+            return None;
+        }
+        // 第一帧总是输出
+        Some(Self {
+            source: source_fps,
+            target: target_fps,
+            acc: source_fps - target_fps,
+        })
+    }
+
+    /// 每帧开始时调用一次，返回是否输出该帧
+    pub fn admit(&mut self) -> bool {
+        self.acc += self.target;
+        if self.acc >= self.source {
+            self.acc -= self.source;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(source: u32, target: u32, frames: usize) -> alloc::vec::Vec<bool> {
+        let mut l = FrameRateLimiter::new(source, target).unwrap();
+        (0..frames).map(|_| l.admit()).collect()
+    }
+
+    #[test]
+    fn evenly_spaced() {
+        assert_eq!(pattern(30, 10, 6), [true, false, false, true, false, false]);
+        assert_eq!(pattern(30, 15, 4), [true, false, true, false]);
+        // 一秒内输出的帧数等于目标帧率
+        assert_eq!(pattern(30, 7, 30).iter().filter(|&&b| b).count(), 7);
+    }
+
+    #[test]
+    fn no_limit_needed() {
+        assert!(FrameRateLimiter::new(30, 30).is_none());
+        assert!(FrameRateLimiter::new(0, 10).is_none());
+    }
+}
@@ -0,0 +1,153 @@
+//! 帧输出端
+//!
+//! 采集循环只负责从端点取帧，帧的去向（写盘、环形缓冲、网络发送）由 [`FrameSink`]
+//! 实现决定。`write` 返回的 future 完成之前不会继续取帧，慢速输出端以此向采集循环
+//! 施加背压；不愿阻塞采集的输出端可以返回 [`SinkError::Full`] 丢弃本帧。
+
+use alloc::collections::VecDeque;
+use futures::future::{BoxFuture, FutureExt, ready};
+
+use crate::frame::FrameEvent;
+
+/// 输出端错误
+#[derive(thiserror::Error, Debug)]
+pub enum SinkError {
+    /// 输出端已满，本帧被丢弃，采集继续
+    #[error("Frame sink full")]
+    Full,
+    /// 输出端已关闭，采集应停止
+    #[error("Frame sink closed")]
+    Closed,
+    #[error("Frame sink error: {0}")]
+    Other(#[from] anyhow::Error),
+}
+
+/// 帧输出端
+pub trait FrameSink: Send {
+    /// 写入一帧完整数据
+    fn write<'a>(&'a mut self, frame: FrameEvent) -> BoxFuture<'a, Result<(), SinkError>>;
+
+    /// 将缓冲的数据写出，采集结束时调用
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), SinkError>> {
+        ready(Ok(())).boxed()
+    }
+}
+
+/// 环形缓冲区满时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// 丢弃最旧的帧，保证缓冲区内始终是最新画面
+    DropOldest,
+    /// 拒绝新帧并返回 [`SinkError::Full`]
+    DropNewest,
+}
+
+/// 固定容量的内存帧缓冲，适合没有文件系统的嵌入式目标
+pub struct RingBufferSink {
+    frames: VecDeque<FrameEvent>,
+    capacity: usize,
+    overflow: Overflow,
+    dropped: u64,
+}
+
+impl RingBufferSink {
+    pub fn new(capacity: usize, overflow: Overflow) -> Self {
+        assert!(capacity > 0, "ring buffer capacity must be non-zero");
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+            overflow,
+            dropped: 0,
+        }
+    }
+
+    /// 取出最旧的一帧
+    pub fn pop(&mut self) -> Option<FrameEvent> {
+        self.frames.pop_front()
+    }
+
+    /// 最新的一帧
+    pub fn latest(&self) -> Option<&FrameEvent> {
+        self.frames.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// 因缓冲区满被丢弃的帧数
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn push(&mut self, frame: FrameEvent) -> Result<(), SinkError> {
+        if self.frames.len() == self.capacity {
+            self.dropped += 1;
+            match self.overflow {
+                Overflow::DropOldest => {
+                    self.frames.pop_front();
+                }
+                Overflow::DropNewest => return Err(SinkError::Full),
+            }
+        }
+        self.frames.push_back(frame);
+        Ok(())
+    }
+}
+
+impl FrameSink for RingBufferSink {
+    fn write<'a>(&'a mut self, frame: FrameEvent) -> BoxFuture<'a, Result<(), SinkError>> {
+        ready(self.push(frame)).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(n: u32) -> FrameEvent {
+        FrameEvent {
+            data: vec![n as u8],
+            pts_90khz: None,
+            scr: None,
+            host_time: None,
+            eof: true,
+            fid: false,
+            frame_number: n,
+            defect: None,
+        }
+    }
+
+    fn write(sink: &mut RingBufferSink, n: u32) -> Result<(), SinkError> {
+        sink.write(frame(n)).now_or_never().unwrap()
+    }
+
+    #[test]
+    fn ring_drop_oldest() {
+        let mut sink = RingBufferSink::new(2, Overflow::DropOldest);
+        for n in 0..3 {
+            write(&mut sink, n).unwrap();
+        }
+        assert_eq!(sink.dropped(), 1);
+        assert_eq!(sink.latest().unwrap().frame_number, 2);
+        assert_eq!(sink.pop().unwrap().frame_number, 1);
+    }
+
+    #[test]
+    fn ring_drop_newest() {
+        let mut sink = RingBufferSink::new(1, Overflow::DropNewest);
+        write(&mut sink, 0).unwrap();
+        assert!(matches!(write(&mut sink, 1), Err(SinkError::Full)));
+        assert_eq!(sink.dropped(), 1);
+        assert_eq!(sink.pop().unwrap().frame_number, 0);
+        assert!(sink.is_empty());
+    }
+}
@@ -1,80 +1,394 @@
-use alloc::vec::Vec;
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+
+use anyhow::anyhow;
 use crab_usb::Endpoint;
+use futures::Stream;
 use log::debug;
-use usb_if::{descriptor::EndpointDescriptor, endpoint::TransferRequest, err::USBError};
+use usb_if::{
+    descriptor::EndpointDescriptor,
+    endpoint::IsoPacketResult,
+    err::{TransferError, USBError},
+};
+use uvc_proto::{ClockMapper, SourceClock};
 
 use crate::{
-    VideoFormat,
+    VideoFormat, VideoFormatType, VideoFrame,
     frame::{FrameEvent, FrameParser},
+    sink::{FrameSink, SinkError},
+    warmup::{StreamConfig, WarmupFilter},
 };
 
+/// 视频流
+///
+/// 开流后保持 [`StreamConfig::transfers`] 个等时传输同时在途，缓冲区在完成后原样重新提交，
+/// 不在每次接收时分配。载荷按 FID 翻转与 EOF 重组为帧，一次传输中的多个帧在内部排队。
+/// 作为 [`Stream`] 使用时只在被轮询时取出传输，调用方处理得慢则在途传输被设备数据填满后丢包，
+/// 不会无限堆积帧。
 pub struct VideoStream {
     ep: Endpoint,
-    frame_parser: FrameParser,
+    reassembler: Reassembler,
     pub vedio_format: VideoFormat,
     packets_per_transfer: usize,
-    packet_size: usize,
-    buffer: Vec<u8>,
+    transfers: usize,
+    sink_dropped: u64,
+    /// 设备已断开、传输被取消或缓冲区无法重新提交，[`Stream`] 不再产生新项
+    terminated: bool,
 }
 
 unsafe impl Send for VideoStream {}
 
 impl VideoStream {
     pub fn new(ep: Endpoint, desc: EndpointDescriptor, vfmt: VideoFormat) -> Self {
-        let max_packet_size = desc.max_packet_size;
+        Self::with_config(ep, desc, vfmt, StreamConfig::default())
+    }
+
+    pub fn with_config(
+        ep: Endpoint,
+        desc: EndpointDescriptor,
+        vfmt: VideoFormat,
+        config: StreamConfig,
+    ) -> Self {
+        // 高带宽端点每个服务间隔包含多个事务，一包的大小为 wMaxPacketSize * 事务数
+        let packet_size = desc.max_packet_size as usize * desc.packets_per_microframe.max(1);
         // 参考libusb计算逻辑:
         // packets_per_transfer = (dwMaxVideoFrameSize + endpoint_bytes_per_packet - 1) / endpoint_bytes_per_packet
         // 但保持合理的限制(最多32个包)
         let packets_per_transfer =
-            core::cmp::min(vfmt.frame_bytes().div_ceil(max_packet_size as _), 32);
-        let buffer = vec![0u8; (max_packet_size as usize) * packets_per_transfer];
+            core::cmp::min(vfmt.frame_bytes().div_ceil(packet_size), 32).max(1);
+        let transfers = config.transfers.max(1);
+        let mut parser = FrameParser::new(vfmt.frame_bytes())
+            .with_mjpeg(matches!(vfmt.format_type, VideoFormatType::Mjpeg))
+            .with_policy(config.corrupt_frames);
+        parser.set_rate_limit(vfmt.frame_rate, config.max_fps);
         debug!(
-            "VideoStream created: max_packet_size={}, packets_per_transfer={}, buffer_size={}",
-            max_packet_size,
-            packets_per_transfer,
-            buffer.len()
+            "VideoStream created: packet_size={packet_size}, packets_per_transfer={packets_per_transfer}, transfers={transfers}"
         );
         VideoStream {
             ep,
-
-            frame_parser: FrameParser::new(vfmt.frame_bytes()),
+            reassembler: Reassembler::new(parser, WarmupFilter::new(config.warmup)),
             vedio_format: vfmt,
             packets_per_transfer,
-            buffer,
-            packet_size: max_packet_size as usize,
+            transfers,
+            sink_dropped: 0,
+            terminated: false,
         }
     }
 
+    /// 接收一次等时传输并返回其中的完整帧
+    ///
+    /// 部分包失败时仍使用成功的包，失败的包会使正在组装的帧被丢弃。
+    /// 之前经 [`Stream`] 接口组装好但尚未取出的帧直接返回，不再等待传输。
     pub async fn recv(&mut self) -> Result<Vec<FrameEvent>, USBError> {
-        self.buffer.fill(0);
-
-        let packet_lengths = alloc::vec![self.packet_size; self.packets_per_transfer];
-        self.ep
-            .wait(TransferRequest::iso_in(&mut self.buffer, &packet_lengths))
-            .await?;
-
-        let mut events = Vec::new();
+        if self.reassembler.ready.is_empty() {
+            core::future::poll_fn(|cx| self.poll_transfer(cx)).await?;
+        }
+        Ok(self.reassembler.ready.drain(..).collect())
+    }
 
-        for data in self.buffer.chunks(self.packet_size) {
-            if data.iter().all(|&b| b == 0) {
-                // 空包，跳过
-                continue;
+    /// 补足在途传输并等待最早提交的一个完成，完成的缓冲区解析后重新提交
+    ///
+    /// 整个传输失败时缓冲区随错误丢弃，下次轮询时补充新的缓冲区。
+    ///
+    /// 提交失败、设备断开或传输被取消时标记流已结束。
+    fn poll_transfer(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), USBError>> {
+        let missing = self.transfers.saturating_sub(self.ep.iso_in_queued());
+        if missing > 0
+            && let Err(e) = self
+                .ep
+                .submit_iso_in_queue(missing, self.packets_per_transfer)
+        {
+            self.terminated = true;
+            return Poll::Ready(Err(e.into()));
+        }
+        match ready!(self.ep.poll_next_iso_in(cx)) {
+            Ok(buf) => {
+                let now = self.ep.now();
+                self.reassembler.push(buf.packets(), now);
+                if let Err(e) = self.ep.requeue_iso_in(buf) {
+                    self.terminated = true;
+                    return Poll::Ready(Err(e.into()));
+                }
+                Poll::Ready(Ok(()))
             }
-            if let Ok(Some(one)) = self.frame_parser.push_packet(data) {
-                events.push(one);
+            Err(e) => {
+                self.reassembler.parser.drop_packet();
+                self.terminated |= is_fatal(&e);
+                Poll::Ready(Err(e.into()))
             }
         }
+    }
 
-        Ok(events)
+    /// 接收一次传输并将其中的完整帧写入 `sink`，返回写入的帧数
+    ///
+    /// 输出端返回 [`SinkError::Full`] 时丢弃该帧并计数；返回其他错误时停止并上报。
+    pub async fn pump(&mut self, sink: &mut dyn FrameSink) -> Result<usize, USBError> {
+        let mut written = 0;
+        for frame in self.recv().await? {
+            match sink.write(frame).await {
+                Ok(()) => written += 1,
+                Err(SinkError::Full) => self.sink_dropped += 1,
+                Err(e) => return Err(anyhow!("{e}").into()),
+            }
+        }
+        Ok(written)
+    }
+
+    /// 因输出端已满被丢弃的帧数
+    pub fn sink_dropped_count(&self) -> u64 {
+        self.sink_dropped
+    }
+
+    /// 预热期间丢弃的帧数
+    pub fn warmup_discarded_count(&self) -> u32 {
+        self.reassembler.warmup.discarded()
     }
 
     /// 获取错误包统计信息
     pub fn error_packet_count(&self) -> u32 {
-        self.frame_parser.error_packet_count()
+        self.reassembler.parser.error_packet_count()
+    }
+
+    /// 获取传输失败而丢失的包数量
+    pub fn lost_packet_count(&self) -> u32 {
+        self.reassembler.parser.lost_packet_count()
+    }
+
+    /// 设置设备时钟频率（dwClockFrequency），之后输出的帧按 SCR 换算主机时间
+    ///
+    /// [`UvcDevice::start_streaming`](crate::UvcDevice::start_streaming) 已按设备描述设置，
+    /// 频率为 0 时不换算。
+    pub fn set_clock_frequency(&mut self, frequency: u32) {
+        self.reassembler.clock = ClockMapper::new(frequency);
+        self.reassembler.last_sample = None;
+    }
+
+    /// 调整向上层输出的最高帧率，`None` 表示输出设备的全部帧，见 [`StreamConfig::max_fps`]
+    pub fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.reassembler
+            .parser
+            .set_rate_limit(self.vedio_format.frame_rate, max_fps);
+    }
+
+    /// 因帧率限制跳过的帧数
+    pub fn skipped_frame_count(&self) -> u32 {
+        self.reassembler.parser.skipped_frame_count()
+    }
+
+    /// 因不完整被丢弃的帧数，见 [`StreamConfig::corrupt_frames`]
+    pub fn corrupt_frame_count(&self) -> u32 {
+        self.reassembler.parser.corrupt_frame_count()
     }
 
     /// 重置错误包统计
     pub fn reset_error_count(&mut self) {
-        self.frame_parser.reset_error_count();
+        self.reassembler.parser.reset_error_count();
+    }
+}
+
+impl Stream for VideoStream {
+    type Item = Result<VideoFrame, USBError>;
+
+    /// 逐帧取出，传输错误作为一项返回，之后仍可继续轮询
+    ///
+    /// 设备断开、传输被取消或缓冲区无法重新提交时，该错误是最后一项，之后流结束。
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(event) = this.reassembler.ready.pop_front() {
+                let frame = VideoFrame::from_event(event, this.vedio_format.clone());
+                return Poll::Ready(Some(Ok(frame)));
+            }
+            if this.terminated {
+                return Poll::Ready(None);
+            }
+            if let Err(e) = ready!(this.poll_transfer(cx)) {
+                return Poll::Ready(Some(Err(e)));
+            }
+        }
+    }
+}
+
+/// 之后的传输也不会成功的错误
+fn is_fatal(err: &TransferError) -> bool {
+    matches!(err, TransferError::NoDevice | TransferError::Cancelled)
+}
+
+/// 把等时包重组为帧，经预热过滤后按顺序排队
+#[derive(Debug)]
+struct Reassembler {
+    parser: FrameParser,
+    warmup: WarmupFilter,
+    ready: VecDeque<FrameEvent>,
+    /// 设备时钟频率未知时为 `None`，帧不带主机时间
+    clock: Option<ClockMapper>,
+    /// 已加入 `clock` 的最近一个 SCR，同一 SCR 不重复采样
+    last_sample: Option<SourceClock>,
+}
+
+impl Reassembler {
+    fn new(parser: FrameParser, warmup: WarmupFilter) -> Self {
+        Self {
+            parser,
+            warmup,
+            ready: VecDeque::new(),
+            clock: None,
+            last_sample: None,
+        }
+    }
+
+    /// 处理一次传输的包，`now` 为传输完成的主机时间
+    fn push<'a>(
+        &mut self,
+        packets: impl Iterator<Item = (&'a IsoPacketResult, &'a [u8])>,
+        now: Duration,
+    ) {
+        let queued = self.ready.len();
+        for (packet, data) in packets {
+            if !packet.is_ok() {
+                self.parser.drop_packet();
+                continue;
+            }
+            if data.is_empty() {
+                // 空包，跳过
+                continue;
+            }
+            if let Ok(Some(one)) = self.parser.push_packet(data)
+                && self.warmup.accept(one.data.len())
+            {
+                self.ready.push_back(one);
+            }
+        }
+
+        let Some(clock) = self.clock.as_mut() else {
+            return;
+        };
+        if let Some(scr) = self.parser.latest_scr()
+            && self.last_sample != Some(scr)
+        {
+            clock.add_sample(scr, now);
+            self.last_sample = Some(scr);
+        }
+        for frame in self.ready.range_mut(queued..) {
+            frame.host_time = frame.pts_90khz.and_then(|pts| clock.to_host(pts));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use usb_if::endpoint::TransferStatus;
+
+    use super::*;
+    use crate::{frame::UvcPayloadHeader, warmup::Warmup};
+
+    fn packet(len: usize, status: TransferStatus) -> IsoPacketResult {
+        IsoPacketResult {
+            requested_length: len,
+            actual_length: len,
+            status,
+        }
+    }
+
+    /// 2 字节载荷头：bit0 为 FID，bit1 为 EOF，bit7 为 EOH
+    fn payload(fid: bool, eof: bool, data: &[u8]) -> Vec<u8> {
+        let mut p = alloc::vec![2, 0x80 | fid as u8 | ((eof as u8) << 1)];
+        p.extend_from_slice(data);
+        p
+    }
+
+    #[test]
+    fn only_disconnect_and_cancel_end_the_stream() {
+        assert!(is_fatal(&TransferError::NoDevice));
+        assert!(is_fatal(&TransferError::Cancelled));
+        assert!(!is_fatal(&TransferError::Timeout));
+        assert!(!is_fatal(&TransferError::Stall));
+    }
+
+    #[test]
+    fn reassemble_across_transfers() {
+        let mut r = Reassembler::new(FrameParser::new(16), WarmupFilter::new(Warmup::None));
+        let ok = packet(0, TransferStatus::Completed);
+
+        // 首个 EOF 只用于对齐帧边界
+        let first = [payload(false, false, &[9]), payload(false, true, &[9])];
+        r.push(first.iter().map(|p| (&ok, p.as_slice())), Duration::ZERO);
+        assert!(r.ready.is_empty());
+
+        // 一帧跨两次传输，FID 翻转开始新帧
+        let a = [payload(true, false, &[1, 2])];
+        let b = [
+            payload(true, true, &[3]),
+            payload(false, false, &[4]),
+            payload(false, true, &[5]),
+        ];
+        r.push(a.iter().map(|p| (&ok, p.as_slice())), Duration::ZERO);
+        assert!(r.ready.is_empty());
+        r.push(b.iter().map(|p| (&ok, p.as_slice())), Duration::ZERO);
+
+        let frames: Vec<_> = r.ready.drain(..).map(|f| f.data).collect();
+        assert_eq!(frames, [alloc::vec![1, 2, 3], alloc::vec![4, 5]]);
+    }
+
+    #[test]
+    fn frames_carry_host_time() {
+        let mut r = Reassembler::new(FrameParser::new(16), WarmupFilter::new(Warmup::None));
+        r.clock = ClockMapper::new(1_000_000);
+        let ok = packet(0, TransferStatus::Completed);
+        let header = |fid, eof, pts, stc| UvcPayloadHeader {
+            fid,
+            eof,
+            pts: Some(pts),
+            scr: Some((stc, 0)),
+            ..Default::default()
+        };
+
+        let align = payload(false, true, &[]);
+        r.push(core::iter::once((&ok, align.as_slice())), Duration::ZERO);
+        // PTS 比 SCR 早 2ms，SCR 在主机 1s 时收到
+        let mut frame = header(true, true, 1_000_000, 1_002_000).to_bytes();
+        frame.push(7);
+        r.push(
+            core::iter::once((&ok, frame.as_slice())),
+            Duration::from_secs(1),
+        );
+
+        let frame = r.ready.pop_front().unwrap();
+        assert_eq!(frame.scr.map(|s| s.stc), Some(1_002_000));
+        assert_eq!(frame.host_time, Some(Duration::from_millis(998)));
+    }
+
+    #[test]
+    fn lost_packet_drops_frame() {
+        let mut r = Reassembler::new(FrameParser::new(16), WarmupFilter::new(Warmup::None));
+        let ok = packet(0, TransferStatus::Completed);
+        let lost = packet(0, TransferStatus::Error);
+
+        let align = payload(false, true, &[0xff]);
+        r.push(core::iter::once((&ok, align.as_slice())), Duration::ZERO);
+
+        let a = payload(true, false, &[1]);
+        let b = payload(true, true, &[2]);
+        let c = payload(false, true, &[3]);
+        r.push(
+            [
+                (&ok, a.as_slice()),
+                (&lost, &[][..]),
+                (&ok, b.as_slice()),
+                (&ok, c.as_slice()),
+            ]
+            .into_iter(),
+            Duration::ZERO,
+        );
+
+        // 丢包的帧被丢弃
+        let frames: Vec<_> = r.ready.drain(..).map(|f| f.data).collect();
+        assert_eq!(frames, [alloc::vec![3]]);
+        assert_eq!(r.parser.lost_packet_count(), 1);
+        assert_eq!(r.parser.corrupt_frame_count(), 1);
     }
 }
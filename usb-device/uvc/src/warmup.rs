@@ -0,0 +1,168 @@
+//! 启动预热：丢弃摄像头刚开始输出的无效帧
+//!
+//! 多数摄像头在开流后约 0.5 秒内输出全黑或偏绿的帧，自动曝光稳定后画面才正常。
+
+use alloc::collections::VecDeque;
+
+use crate::frame::CorruptFramePolicy;
+
+/// 开流时如何丢弃预热帧
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Warmup {
+    /// 不丢弃
+    #[default]
+    None,
+    /// 丢弃前 N 帧
+    DiscardFrames(u32),
+    /// 等待帧大小稳定
+    ///
+    /// 最近 `window` 帧大小的极差不超过均值的 `tolerance_percent`% 时视为曝光稳定。
+    /// 仅适用于 MJPEG 等压缩格式，未压缩格式帧大小恒定，请使用 [`Warmup::DiscardFrames`]。
+    /// 丢弃 `max_frames` 帧后仍不稳定时不再等待。
+    StableSize {
+        window: usize,
+        tolerance_percent: u32,
+        max_frames: u32,
+    },
+}
+
+impl Warmup {
+    /// 帧大小稳定判定的默认参数：5 帧窗口、15% 容差、最多丢弃 30 帧
+    pub const fn stable_size() -> Self {
+        Self::StableSize {
+            window: 5,
+            tolerance_percent: 15,
+            max_frames: 30,
+        }
+    }
+}
+
+/// 视频流参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamConfig {
+    pub warmup: Warmup,
+    /// 同时在途的等时传输数，至少为 1
+    pub transfers: usize,
+    /// 载荷出错、丢包或 MJPEG 标记缺失的帧的处理方式
+    pub corrupt_frames: CorruptFramePolicy,
+    /// 向上层输出的最高帧率，设备仍按协商的帧率传输，多余的帧在重组时跳过
+    pub max_fps: Option<u32>,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            warmup: Warmup::default(),
+            transfers: 4,
+            corrupt_frames: CorruptFramePolicy::default(),
+            max_fps: None,
+        }
+    }
+}
+
+/// 按 [`Warmup`] 决定每一帧是否输出
+#[derive(Debug)]
+pub(crate) struct WarmupFilter {
+    warmup: Warmup,
+    sizes: VecDeque<usize>,
+    discarded: u32,
+    done: bool,
+}
+
+impl WarmupFilter {
+    pub fn new(warmup: Warmup) -> Self {
+        Self {
+            warmup,
+            sizes: VecDeque::new(),
+            discarded: 0,
+            done: matches!(warmup, Warmup::None),
+        }
+    }
+
+    /// 预热期间已丢弃的帧数
+    pub fn discarded(&self) -> u32 {
+        self.discarded
+    }
+
+    /// 返回该帧是否应当输出；预热结束后总是返回 `true`
+    pub fn accept(&mut self, frame_len: usize) -> bool {
+        if self.done {
+            return true;
+        }
+        let ready = match self.warmup {
+            Warmup::None => true,
+            Warmup::DiscardFrames(n) => self.discarded >= n,
+            Warmup::StableSize {
+                window,
+                tolerance_percent,
+                max_frames,
+            } => {
+                let window = window.max(2);
+                if self.sizes.len() == window {
+                    self.sizes.pop_front();
+                }
+                self.sizes.push_back(frame_len);
+                self.discarded >= max_frames
+                    || (self.sizes.len() == window && self.is_stable(tolerance_percent))
+            }
+        };
+        if ready {
+            self.done = true;
+            self.sizes = VecDeque::new();
+        } else {
+            self.discarded += 1;
+        }
+        ready
+    }
+
+    fn is_stable(&self, tolerance_percent: u32) -> bool {
+        let min = self.sizes.iter().copied().min().unwrap_or(0);
+        let max = self.sizes.iter().copied().max().unwrap_or(0);
+        let mean = self.sizes.iter().sum::<usize>() / self.sizes.len().max(1);
+        mean > 0 && (max - min) * 100 <= mean * tolerance_percent as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn discard_first_frames() {
+        let mut f = WarmupFilter::new(Warmup::DiscardFrames(3));
+        let out: alloc::vec::Vec<_> = (0..5).map(|_| f.accept(1000)).collect();
+        assert_eq!(out, [false, false, false, true, true]);
+        assert_eq!(f.discarded(), 3);
+    }
+
+    #[test]
+    fn wait_for_stable_size() {
+        let mut f = WarmupFilter::new(Warmup::StableSize {
+            window: 3,
+            tolerance_percent: 10,
+            max_frames: 30,
+        });
+        // 曝光调整期间帧大小剧烈变化
+        for len in [200, 900, 4000, 9000, 10_000, 10_100] {
+            assert!(!f.accept(len));
+        }
+        assert!(f.accept(10_200));
+        // 之后不再过滤
+        assert!(f.accept(100));
+        assert_eq!(f.discarded(), 6);
+    }
+
+    #[test]
+    fn stable_size_gives_up() {
+        let mut f = WarmupFilter::new(Warmup::StableSize {
+            window: 3,
+            tolerance_percent: 1,
+            max_frames: 4,
+        });
+        let out: alloc::vec::Vec<_> = [100, 5000, 100, 5000, 100]
+            .into_iter()
+            .map(|len| f.accept(len))
+            .collect();
+        assert_eq!(out, [false, false, false, false, true]);
+    }
+}
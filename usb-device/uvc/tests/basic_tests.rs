@@ -120,11 +120,13 @@ fn test_uncompressed_format_types() {
         UncompressedFormat::Nv12,
         UncompressedFormat::Rgb24,
         UncompressedFormat::Rgb32,
+        UncompressedFormat::Y800,
     ];
 
-    assert_eq!(formats.len(), 4);
+    assert_eq!(formats.len(), 5);
     assert!(formats.contains(&UncompressedFormat::Yuy2));
     assert!(formats.contains(&UncompressedFormat::Nv12));
     assert!(formats.contains(&UncompressedFormat::Rgb24));
     assert!(formats.contains(&UncompressedFormat::Rgb32));
+    assert!(formats.contains(&UncompressedFormat::Y800));
 }
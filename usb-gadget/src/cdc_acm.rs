@@ -0,0 +1,332 @@
+//! CDC ACM 串口功能
+//!
+//! 对端主机上表现为 `ttyACM`（Linux）或 COM 口（Windows 10 以上免驱）。
+//! 波特率等线路参数只被记录，不影响实际传输速率。参考 CDC 1.2 与 PSTN 1.2。
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::fmt;
+
+use usb_if::{Speed, descriptor::EndpointType, err::USBError, transfer::Direction};
+
+use crate::{
+    descriptor,
+    function::{Function, IdAllocator},
+    setup::SetupPacket,
+    udc::{ControlReply, EndpointConfig, UdcOp},
+};
+
+/// 通信接口类：CDC / ACM / AT 命令
+const COMM_CLASS: (u8, u8, u8) = (0x02, 0x02, 0x01);
+const DATA_CLASS: (u8, u8, u8) = (0x0a, 0x00, 0x00);
+/// CS_INTERFACE 类特定描述符
+const CS_INTERFACE: u8 = 0x24;
+const NOTIFY_MAX_PACKET: u16 = 16;
+const NOTIFY_INTERVAL_MS: u8 = 16;
+/// 单次提交到 IN 端点的最大长度
+const TX_CHUNK: usize = 4096;
+const BUFFER_SIZE: usize = 16 * 1024;
+
+const SET_LINE_CODING: u8 = 0x20;
+const GET_LINE_CODING: u8 = 0x21;
+const SET_CONTROL_LINE_STATE: u8 = 0x22;
+const SEND_BREAK: u8 = 0x23;
+
+/// 线路参数，字段编码见 PSTN 1.2 表 17
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCoding {
+    pub baud_rate: u32,
+    /// 0: 1 位，1: 1.5 位，2: 2 位
+    pub stop_bits: u8,
+    /// 0: 无，1: 奇，2: 偶，3: Mark，4: Space
+    pub parity: u8,
+    pub data_bits: u8,
+}
+
+impl Default for LineCoding {
+    fn default() -> Self {
+        Self {
+            baud_rate: 115200,
+            stop_bits: 0,
+            parity: 0,
+            data_bits: 8,
+        }
+    }
+}
+
+impl LineCoding {
+    pub fn from_bytes(raw: [u8; 7]) -> Self {
+        Self {
+            baud_rate: u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]),
+            stop_bits: raw[4],
+            parity: raw[5],
+            data_bits: raw[6],
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 7] {
+        let [b0, b1, b2, b3] = self.baud_rate.to_le_bytes();
+        [b0, b1, b2, b3, self.stop_bits, self.parity, self.data_bits]
+    }
+}
+
+/// CDC ACM 功能
+///
+/// [`CdcAcm::write`] 与 [`CdcAcm::read`] 只读写软件缓冲区，数据在
+/// [`Gadget::poll`](crate::Gadget::poll) 中收发。实现了 [`core::fmt::Write`]。
+pub struct CdcAcm {
+    comm_interface: u8,
+    data_interface: u8,
+    notify_ep: u8,
+    out_ep: u8,
+    in_ep: u8,
+    speed: Speed,
+    enabled: bool,
+    line_coding: LineCoding,
+    /// SET_CONTROL_LINE_STATE 的 wValue
+    control_state: u16,
+    reply: [u8; 7],
+    rx: VecDeque<u8>,
+    tx: VecDeque<u8>,
+    rx_buf: Vec<u8>,
+}
+
+impl CdcAcm {
+    pub fn new() -> Self {
+        Self {
+            comm_interface: 0,
+            data_interface: 0,
+            notify_ep: 0,
+            out_ep: 0,
+            in_ep: 0,
+            speed: Speed::Full,
+            enabled: false,
+            line_coding: LineCoding::default(),
+            control_state: 0,
+            reply: [0; 7],
+            rx: VecDeque::new(),
+            tx: VecDeque::new(),
+            rx_buf: vec![0; TX_CHUNK],
+        }
+    }
+
+    /// 写入发送缓冲区，返回接受的字节数；缓冲区满时只接受能放下的部分
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(BUFFER_SIZE - self.tx.len());
+        self.tx.extend(&data[..n]);
+        n
+    }
+
+    /// 从接收缓冲区读出数据
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.rx.len());
+        for (dst, src) in buf.iter_mut().zip(self.rx.drain(..n)) {
+            *dst = src;
+        }
+        n
+    }
+
+    /// 主机已选择配置，端点可用
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// 主机端是否打开了串口
+    pub fn dtr(&self) -> bool {
+        self.control_state & 1 != 0
+    }
+
+    pub fn rts(&self) -> bool {
+        self.control_state & 2 != 0
+    }
+
+    pub fn line_coding(&self) -> LineCoding {
+        self.line_coding
+    }
+
+    fn receive(&mut self, udc: &mut dyn UdcOp) {
+        match udc.ep_read(self.out_ep, &mut self.rx_buf) {
+            Ok(Some(n)) => {
+                let n = n.min(BUFFER_SIZE - self.rx.len());
+                self.rx.extend(&self.rx_buf[..n]);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("CDC ACM receive failed: {e}"),
+        }
+    }
+}
+
+impl Default for CdcAcm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Function for CdcAcm {
+    fn bind(&mut self, ids: &mut IdAllocator) -> Result<(), USBError> {
+        self.comm_interface = ids.interface()?;
+        self.data_interface = ids.interface()?;
+        self.notify_ep = ids.endpoint(Direction::In)?;
+        self.out_ep = ids.endpoint(Direction::Out)?;
+        self.in_ep = ids.endpoint(Direction::In)?;
+        Ok(())
+    }
+
+    fn endpoints(&self, speed: Speed) -> Vec<EndpointConfig> {
+        let bulk = |address| EndpointConfig {
+            address,
+            transfer_type: EndpointType::Bulk,
+            max_packet_size: descriptor::bulk_max_packet(speed),
+            interval: 0,
+            max_burst: 0,
+        };
+        vec![
+            EndpointConfig {
+                address: self.notify_ep,
+                transfer_type: EndpointType::Interrupt,
+                max_packet_size: NOTIFY_MAX_PACKET,
+                interval: descriptor::interrupt_interval(speed, NOTIFY_INTERVAL_MS),
+                max_burst: 0,
+            },
+            bulk(self.out_ep),
+            bulk(self.in_ep),
+        ]
+    }
+
+    fn write_descriptors(&self, speed: Speed, out: &mut Vec<u8>) {
+        let [notify, data_out, data_in] = self.endpoints(speed).try_into().unwrap();
+        descriptor::interface_association(out, self.comm_interface, 2, COMM_CLASS);
+        descriptor::interface(out, self.comm_interface, 0, 1, COMM_CLASS);
+        // Header，bcdCDC 1.10
+        out.extend_from_slice(&[5, CS_INTERFACE, 0x00, 0x10, 0x01]);
+        // Call Management：不处理呼叫管理
+        out.extend_from_slice(&[5, CS_INTERFACE, 0x01, 0x00, self.data_interface]);
+        // ACM：支持线路参数与控制线状态
+        out.extend_from_slice(&[4, CS_INTERFACE, 0x02, 0x02]);
+        // Union
+        out.extend_from_slice(&[
+            5,
+            CS_INTERFACE,
+            0x06,
+            self.comm_interface,
+            self.data_interface,
+        ]);
+        descriptor::endpoint(out, &notify, speed);
+        descriptor::interface(out, self.data_interface, 0, 2, DATA_CLASS);
+        descriptor::endpoint(out, &data_out, speed);
+        descriptor::endpoint(out, &data_in, speed);
+    }
+
+    fn enable(&mut self, _udc: &mut dyn UdcOp, speed: Speed) {
+        self.speed = speed;
+        self.enabled = true;
+    }
+
+    fn disable(&mut self) {
+        self.enabled = false;
+        self.control_state = 0;
+    }
+
+    fn setup(&mut self, setup: &SetupPacket, data: &[u8]) -> ControlReply<'_> {
+        if setup.index as u8 != self.comm_interface {
+            return ControlReply::Stall;
+        }
+        match setup.request {
+            SET_LINE_CODING => match data.try_into() {
+                Ok(raw) => {
+                    self.line_coding = LineCoding::from_bytes(raw);
+                    debug!("CDC ACM line coding {:?}", self.line_coding);
+                    ControlReply::Ack
+                }
+                Err(_) => ControlReply::Stall,
+            },
+            GET_LINE_CODING => {
+                self.reply = self.line_coding.to_bytes();
+                ControlReply::Data(&self.reply)
+            }
+            SET_CONTROL_LINE_STATE => {
+                self.control_state = setup.value;
+                ControlReply::Ack
+            }
+            SEND_BREAK => ControlReply::Ack,
+            _ => ControlReply::Stall,
+        }
+    }
+
+    fn transfer_complete(&mut self, udc: &mut dyn UdcOp, ep: u8) {
+        if ep == self.out_ep {
+            self.receive(udc);
+        }
+    }
+
+    fn poll(&mut self, udc: &mut dyn UdcOp) {
+        if self.tx.is_empty() || udc.ep_busy(self.in_ep) {
+            return;
+        }
+        let mut len = self.tx.len().min(TX_CHUNK);
+        // 长度为最大包长整数倍时主机需要零长度包才能结束传输，少发一个字节即可避免
+        if len.is_multiple_of(descriptor::bulk_max_packet(self.speed) as usize) {
+            len -= 1;
+        }
+        let chunk = &self.tx.make_contiguous()[..len];
+        match udc.ep_write(self.in_ep, chunk) {
+            Ok(n) => {
+                self.tx.drain(..n);
+            }
+            Err(e) => warn!("CDC ACM send failed: {e}"),
+        }
+    }
+}
+
+impl fmt::Write for CdcAcm {
+    /// 发送缓冲区满时丢弃多出的部分
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bound() -> CdcAcm {
+        let mut acm = CdcAcm::new();
+        acm.bind(&mut IdAllocator::default()).unwrap();
+        acm
+    }
+
+    #[test]
+    fn descriptors() {
+        let acm = bound();
+        assert_eq!((acm.notify_ep, acm.out_ep, acm.in_ep), (0x81, 0x01, 0x82));
+        let mut out = Vec::new();
+        acm.write_descriptors(Speed::High, &mut out);
+        assert_eq!(out.len(), 8 + 9 + 5 + 5 + 4 + 5 + 7 + 9 + 7 + 7);
+        assert_eq!(out[..4], [8, 0x0b, 0, 2]);
+        // 通知端点在高速下 16ms 对应 bInterval 8
+        assert_eq!(out[36..43], [7, 5, 0x81, 3, 16, 0, 8]);
+    }
+
+    #[test]
+    fn line_coding_requests() {
+        let mut acm = bound();
+        let coding = LineCoding {
+            baud_rate: 1_500_000,
+            ..Default::default()
+        };
+        let set = SetupPacket::from_bytes([0x21, SET_LINE_CODING, 0, 0, 0, 0, 7, 0]);
+        assert_eq!(acm.setup(&set, &coding.to_bytes()), ControlReply::Ack);
+        assert_eq!(acm.setup(&set, &[0; 3]), ControlReply::Stall);
+
+        let get = SetupPacket::from_bytes([0xa1, GET_LINE_CODING, 0, 0, 0, 0, 7, 0]);
+        assert_eq!(acm.setup(&get, &[]), ControlReply::Data(&coding.to_bytes()));
+
+        let state = SetupPacket::from_bytes([0x21, SET_CONTROL_LINE_STATE, 3, 0, 0, 0, 0, 0]);
+        assert_eq!(acm.setup(&state, &[]), ControlReply::Ack);
+        assert!(acm.dtr() && acm.rts());
+
+        // 数据接口不处理类请求
+        let get = SetupPacket::from_bytes([0xa1, GET_LINE_CODING, 0, 0, 1, 0, 7, 0]);
+        assert_eq!(acm.setup(&get, &[]), ControlReply::Stall);
+    }
+}
@@ -0,0 +1,315 @@
+//! 描述符编码
+//!
+//! 功能通过这里的函数生成自己的接口与端点描述符，设备、配置、字符串与 BOS 描述符
+//! 由 [`Gadget`](crate::Gadget) 生成。参考 USB 2.0 9.6 与 USB 3.2 9.6。
+
+use alloc::vec::Vec;
+
+use usb_if::{Speed, descriptor::DescriptorType};
+
+use crate::udc::EndpointConfig;
+
+/// 字符串描述符最多容纳的 UTF-16 码元数
+const MAX_STRING_UNITS: usize = 126;
+
+/// 接口描述符，iInterface 固定为 0
+pub fn interface(
+    out: &mut Vec<u8>,
+    number: u8,
+    alternate: u8,
+    num_endpoints: u8,
+    class: (u8, u8, u8),
+) {
+    out.extend_from_slice(&[
+        9,
+        DescriptorType::INTERFACE.0,
+        number,
+        alternate,
+        num_endpoints,
+        class.0,
+        class.1,
+        class.2,
+        0,
+    ]);
+}
+
+/// 接口关联描述符，把连续的 `count` 个接口归为一个功能
+pub fn interface_association(out: &mut Vec<u8>, first: u8, count: u8, class: (u8, u8, u8)) {
+    out.extend_from_slice(&[
+        8,
+        DescriptorType::INTERFACE_ASSOCIATION.0,
+        first,
+        count,
+        class.0,
+        class.1,
+        class.2,
+        0,
+    ]);
+}
+
+/// 端点描述符，SuperSpeed 下附带端点伴随描述符
+pub fn endpoint(out: &mut Vec<u8>, config: &EndpointConfig, speed: Speed) {
+    let [mps_lo, mps_hi] = config.max_packet_size.to_le_bytes();
+    out.extend_from_slice(&[
+        7,
+        DescriptorType::ENDPOINT.0,
+        config.address,
+        config.transfer_type as u8,
+        mps_lo,
+        mps_hi,
+        config.interval,
+    ]);
+    if is_super_speed(speed) {
+        let bytes_per_interval = match config.transfer_type {
+            usb_if::descriptor::EndpointType::Bulk => 0,
+            _ => config.max_packet_size * (config.max_burst as u16 + 1),
+        };
+        let [bpi_lo, bpi_hi] = bytes_per_interval.to_le_bytes();
+        out.extend_from_slice(&[
+            6,
+            DescriptorType::SUPERSPEED_USB_ENDPOINT_COMPANION.0,
+            config.max_burst,
+            0,
+            bpi_lo,
+            bpi_hi,
+        ]);
+    }
+}
+
+/// 字符串描述符，超出的部分被截断
+pub fn string(s: &str) -> Vec<u8> {
+    let mut out = alloc::vec![0, DescriptorType::STRING.0];
+    for unit in s.encode_utf16().take(MAX_STRING_UNITS) {
+        out.extend_from_slice(&unit.to_le_bytes());
+    }
+    out[0] = out.len() as u8;
+    out
+}
+
+/// 批量端点在该速度下的最大包长
+pub fn bulk_max_packet(speed: Speed) -> u16 {
+    match speed {
+        Speed::Low | Speed::Full => 64,
+        Speed::High | Speed::Wireless => 512,
+        Speed::SuperSpeed | Speed::SuperSpeedPlus => 1024,
+    }
+}
+
+/// 中断端点的 bInterval：全速与低速以帧为单位，更高速率以 2 的幂次微帧编码
+pub fn interrupt_interval(speed: Speed, millis: u8) -> u8 {
+    let millis = millis.max(1);
+    match speed {
+        Speed::Low | Speed::Full => millis,
+        _ => {
+            let microframes = millis as u32 * 8;
+            (microframes.ilog2() as u8 + 1).min(16)
+        }
+    }
+}
+
+pub(crate) fn is_super_speed(speed: Speed) -> bool {
+    matches!(speed, Speed::SuperSpeed | Speed::SuperSpeedPlus)
+}
+
+/// 控制端点 0 的最大包长，SuperSpeed 下按 2 的幂次编码
+pub(crate) fn ep0_max_packet(speed: Speed) -> u8 {
+    match speed {
+        Speed::Low => 8,
+        Speed::SuperSpeed | Speed::SuperSpeedPlus => 9,
+        _ => 64,
+    }
+}
+
+/// 设备描述符中的设备标识
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DeviceIds {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub device_release: u16,
+}
+
+/// 设备描述符，字符串索引 1-3 依次为厂商、产品与序列号
+///
+/// 设备类为 0xEF/0x02/0x01，由各功能的接口关联描述符给出实际类别。
+pub(crate) fn device(ids: &DeviceIds, speed: Speed) -> [u8; 18] {
+    let bcd_usb: u16 = if is_super_speed(speed) {
+        0x0320
+    } else {
+        0x0200
+    };
+    let [usb_lo, usb_hi] = bcd_usb.to_le_bytes();
+    let [vid_lo, vid_hi] = ids.vendor_id.to_le_bytes();
+    let [pid_lo, pid_hi] = ids.product_id.to_le_bytes();
+    let [rel_lo, rel_hi] = ids.device_release.to_le_bytes();
+    [
+        18,
+        DescriptorType::DEVICE.0,
+        usb_lo,
+        usb_hi,
+        0xef,
+        0x02,
+        0x01,
+        ep0_max_packet(speed),
+        vid_lo,
+        vid_hi,
+        pid_lo,
+        pid_hi,
+        rel_lo,
+        rel_hi,
+        1,
+        2,
+        3,
+        1,
+    ]
+}
+
+/// 设备限定描述符，描述设备在另一种高速/全速下的工作方式
+pub(crate) fn device_qualifier() -> [u8; 10] {
+    [
+        10,
+        DescriptorType::DEVICE_QUALIFIER.0,
+        0x00,
+        0x02,
+        0xef,
+        0x02,
+        0x01,
+        64,
+        1,
+        0,
+    ]
+}
+
+/// 配置描述符头部，`body` 为接口及其后的全部描述符
+pub(crate) struct ConfigHeader {
+    pub num_interfaces: u8,
+    pub self_powered: bool,
+    pub remote_wakeup: bool,
+    pub max_power_ma: u16,
+}
+
+/// 完整的配置描述符，`descriptor_type` 取配置或其他速率配置
+pub(crate) fn configuration(
+    header: &ConfigHeader,
+    descriptor_type: DescriptorType,
+    speed: Speed,
+    body: &[u8],
+) -> Vec<u8> {
+    let total = (9 + body.len()) as u16;
+    let mut attributes = 0x80;
+    if header.self_powered {
+        attributes |= 1 << 6;
+    }
+    if header.remote_wakeup {
+        attributes |= 1 << 5;
+    }
+    // bMaxPower 在 SuperSpeed 下以 8mA 为单位，否则以 2mA 为单位
+    let unit = if is_super_speed(speed) { 8 } else { 2 };
+    let max_power = (header.max_power_ma / unit).min(u8::MAX as u16) as u8;
+    let [total_lo, total_hi] = total.to_le_bytes();
+    let mut out = alloc::vec![
+        9,
+        descriptor_type.0,
+        total_lo,
+        total_hi,
+        header.num_interfaces,
+        1,
+        0,
+        attributes,
+        max_power,
+    ];
+    out.extend_from_slice(body);
+    out
+}
+
+/// BOS 描述符，包含 USB 2.0 扩展与 SuperSpeed 设备能力
+pub(crate) fn bos() -> [u8; 22] {
+    [
+        5,
+        DescriptorType::BOS.0,
+        22,
+        0,
+        2,
+        // USB 2.0 Extension，不支持 LPM
+        7,
+        DescriptorType::DEVICE_CAPABILITY.0,
+        0x02,
+        0,
+        0,
+        0,
+        0,
+        // SuperSpeed USB：支持全速、高速与 SuperSpeed，全速即可完整工作
+        10,
+        DescriptorType::DEVICE_CAPABILITY.0,
+        0x03,
+        0,
+        0x0e,
+        0,
+        1,
+        0x0a,
+        0xff,
+        0x07,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use usb_if::descriptor::EndpointType;
+
+    use super::*;
+
+    #[test]
+    fn string_is_utf16() {
+        assert_eq!(string("Ab"), [6, 3, b'A', 0, b'b', 0]);
+        assert_eq!(string(&"x".repeat(200)).len(), 254);
+    }
+
+    #[test]
+    fn endpoint_companion_only_at_super_speed() {
+        let config = EndpointConfig {
+            address: 0x81,
+            transfer_type: EndpointType::Interrupt,
+            max_packet_size: 16,
+            interval: 8,
+            max_burst: 0,
+        };
+        let mut out = Vec::new();
+        endpoint(&mut out, &config, Speed::High);
+        assert_eq!(out, [7, 5, 0x81, 3, 16, 0, 8]);
+
+        out.clear();
+        endpoint(&mut out, &config, Speed::SuperSpeed);
+        assert_eq!(out[7..], [6, 0x30, 0, 0, 16, 0]);
+    }
+
+    #[test]
+    fn interval_encoding() {
+        assert_eq!(interrupt_interval(Speed::Full, 16), 16);
+        // 16ms = 128 微帧 = 2^(8-1)
+        assert_eq!(interrupt_interval(Speed::High, 16), 8);
+        assert_eq!(interrupt_interval(Speed::SuperSpeed, 1), 4);
+    }
+
+    #[test]
+    fn configuration_header() {
+        let header = ConfigHeader {
+            num_interfaces: 2,
+            self_powered: true,
+            remote_wakeup: false,
+            max_power_ma: 500,
+        };
+        let desc = configuration(
+            &header,
+            DescriptorType::CONFIGURATION,
+            Speed::High,
+            &[0; 10],
+        );
+        assert_eq!(desc[..9], [9, 2, 19, 0, 2, 1, 0, 0xc0, 250]);
+        let desc = configuration(
+            &header,
+            DescriptorType::CONFIGURATION,
+            Speed::SuperSpeed,
+            &[],
+        );
+        assert_eq!(desc[8], 62);
+    }
+}
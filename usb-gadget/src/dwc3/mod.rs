@@ -0,0 +1,797 @@
+//! DesignWare USB3 DRD 控制器（DWC3）设备模式驱动
+//!
+//! 控制器的时钟、复位与 PHY 须已由平台代码准备好（RK3588 上可由引导程序或主机模式
+//! 驱动完成），本驱动只操作控制器的设备部分。事件缓冲区的中断被屏蔽，事件全部在
+//! [`UdcOp::poll_event`] 中处理。每个端点同一时刻只有一个单 TRB 传输，数据经驱动
+//! 自有的缓冲区中转。参考 Linux drivers/usb/dwc3/gadget.c 与 ep0.c。
+
+mod reg;
+mod trb;
+
+use alloc::{collections::VecDeque, format, vec::Vec};
+use core::{ptr::NonNull, time::Duration};
+
+use crab_usb_hal::KernelOp;
+use dma_api::{DArray, DeviceDma, DmaDirection};
+use mbarrier::mb;
+use tock_registers::{fields::FieldValue, interfaces::*};
+use usb_if::{Speed, descriptor::EndpointType, err::USBError, transfer::Direction};
+
+use crate::{
+    setup::SetupPacket,
+    udc::{ControlReply, EndpointConfig, UdcEvent, UdcOp},
+};
+use reg::{DCFG, DCTL, DEPCMD, DEVTEN, DSTS, Dwc3Regs, GCTL, GEVNTSIZ};
+use trb::{
+    DeviceEvent, EndpointEvent, Event, LINK_STATE_U0, LINK_STATE_U3, STATUS_CONTROL_STATUS,
+    TRB_CTRL, Trb,
+};
+
+/// 事件缓冲区容纳的事件数，每个事件 4 字节
+const EVENT_BUFFER_LEN: usize = 256;
+/// 端点 0 数据阶段的最大长度
+const EP0_BUFFER_SIZE: usize = 4096;
+/// 非控制端点单次传输的最大长度
+const EP_BUFFER_SIZE: usize = 4096;
+const SETUP_LEN: usize = 8;
+const NUM_PHYS_EPS: usize = 32;
+
+const RESET_TIMEOUT: Duration = Duration::from_millis(500);
+const HALT_TIMEOUT: Duration = Duration::from_millis(100);
+const COMMAND_TIMEOUT: Duration = Duration::from_millis(10);
+/// 不等待命令完成事件时，EndTransfer 之后等待传输真正停止的时间
+const END_TRANSFER_DELAY: Duration = Duration::from_millis(1);
+
+/// SetEpConfig 参数：动作为修改已有配置
+const DEPCFG_ACTION_MODIFY: u32 = 2 << 30;
+const DEPCFG_XFER_COMPLETE_EN: u32 = 1 << 8;
+const DEPCFG_XFER_NOT_READY_EN: u32 = 1 << 10;
+
+/// DWC3 设备模式参数
+#[derive(Debug, Clone)]
+pub struct Dwc3Config {
+    /// 与主机协商的最高速度
+    pub max_speed: Speed,
+    /// 控制器可访问的地址范围
+    pub dma_mask: u64,
+}
+
+impl Default for Dwc3Config {
+    fn default() -> Self {
+        Self {
+            max_speed: Speed::High,
+            dma_mask: u32::MAX as u64,
+        }
+    }
+}
+
+/// 端点 0 的控制传输阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ep0State {
+    /// 等待 SETUP 包
+    Setup,
+    /// OUT 数据阶段进行中，完成后才上报 SETUP
+    DataOut,
+    /// 已上报 SETUP，等待 [`UdcOp::control_reply`]
+    Reply,
+    /// IN 数据阶段进行中
+    DataIn,
+    /// 等待主机发起状态阶段
+    WaitStatus,
+    /// 状态阶段进行中
+    Status,
+}
+
+struct Ep0 {
+    state: Ep0State,
+    setup: SetupPacket,
+    /// 收到状态阶段 XferNotReady 的物理端点
+    status_phys: Option<usize>,
+    /// IN 数据阶段后需要补发零长度包
+    zlp: bool,
+    max_packet_size: u16,
+}
+
+/// 一个物理端点（一个方向）
+struct Endpoint {
+    trb: DArray<Trb>,
+    buf: DArray<u8>,
+    /// 端点 0 以外的端点启用时的配置
+    config: Option<EndpointConfig>,
+    /// StartTransfer 分配的传输资源，EndTransfer 时使用
+    resource: u16,
+    busy: bool,
+    halted: bool,
+    requested: usize,
+    /// OUT 端点上已完成、尚未被取走的数据长度
+    received: Option<usize>,
+}
+
+/// DWC3 设备控制器
+pub struct Dwc3Udc {
+    reg: Dwc3Regs,
+    kernel: &'static dyn KernelOp,
+    dma: DeviceDma,
+    config: Dwc3Config,
+    events: DArray<u32>,
+    event_pos: usize,
+    pending: VecDeque<UdcEvent>,
+    eps: [Option<Endpoint>; NUM_PHYS_EPS],
+    ep0: Ep0,
+    suspended: bool,
+    /// 复位后是否已为非控制端点执行 StartConfig
+    started_config: bool,
+}
+
+unsafe impl Send for Dwc3Udc {}
+
+/// 端点地址对应的物理端点号
+fn phys(ep: u8) -> usize {
+    (((ep & 0x0f) << 1) | (ep >> 7)) as usize
+}
+
+impl Dwc3Udc {
+    pub fn new(mmio: NonNull<u8>, kernel: &'static dyn KernelOp) -> Result<Self, USBError> {
+        Self::new_with_config(mmio, kernel, Dwc3Config::default())
+    }
+
+    pub fn new_with_config(
+        mmio: NonNull<u8>,
+        kernel: &'static dyn KernelOp,
+        config: Dwc3Config,
+    ) -> Result<Self, USBError> {
+        let reg = unsafe { Dwc3Regs::new(mmio) };
+        let dma = DeviceDma::new(config.dma_mask, kernel);
+        let events = dma
+            .array_zero_with_align(EVENT_BUFFER_LEN, 64, DmaDirection::FromDevice)
+            .map_err(|_| USBError::NoMemory)?;
+        let mut eps = [const { None }; NUM_PHYS_EPS];
+        eps[0] = Some(Self::alloc_endpoint(&dma, EP0_BUFFER_SIZE, Direction::Out)?);
+        eps[1] = Some(Self::alloc_endpoint(&dma, EP0_BUFFER_SIZE, Direction::In)?);
+
+        Ok(Self {
+            reg,
+            kernel,
+            dma,
+            config,
+            events,
+            event_pos: 0,
+            pending: VecDeque::new(),
+            eps,
+            ep0: Ep0 {
+                state: Ep0State::Setup,
+                setup: SetupPacket::from_bytes([0; 8]),
+                status_phys: None,
+                zlp: false,
+                max_packet_size: 512,
+            },
+            suspended: false,
+            started_config: false,
+        })
+    }
+
+    fn alloc_endpoint(dma: &DeviceDma, len: usize, dir: Direction) -> Result<Endpoint, USBError> {
+        let direction = match dir {
+            Direction::In => DmaDirection::ToDevice,
+            Direction::Out => DmaDirection::FromDevice,
+        };
+        Ok(Endpoint {
+            trb: dma
+                .array_zero_with_align(1, 16, DmaDirection::Bidirectional)
+                .map_err(|_| USBError::NoMemory)?,
+            buf: dma
+                .array_zero_with_align(len, 64, direction)
+                .map_err(|_| USBError::NoMemory)?,
+            config: None,
+            resource: 0,
+            busy: false,
+            halted: false,
+            requested: 0,
+            received: None,
+        })
+    }
+
+    /// 在 `timeout` 内等待 `done` 成立
+    fn wait(&self, timeout: Duration, done: impl Fn() -> bool) -> Result<(), USBError> {
+        let deadline = self.kernel.now() + timeout;
+        while !done() {
+            if self.kernel.now() >= deadline {
+                return Err(USBError::Timeout);
+            }
+            self.kernel.delay(Duration::from_micros(10));
+        }
+        Ok(())
+    }
+
+    /// 执行端点命令并等待完成，返回命令寄存器中的参数
+    fn command(
+        &self,
+        phys: usize,
+        cmd: FieldValue<u32, DEPCMD::Register>,
+        params: [u32; 3],
+    ) -> Result<u16, USBError> {
+        let depcmd = &self.reg.regs().depcmd[phys];
+        depcmd.par0.set(params[0]);
+        depcmd.par1.set(params[1]);
+        depcmd.par2.set(params[2]);
+        depcmd.cmd.write(cmd + DEPCMD::CMDACT::SET);
+        self.wait(COMMAND_TIMEOUT, || !depcmd.cmd.is_set(DEPCMD::CMDACT))?;
+        let result = depcmd.cmd.extract();
+        match result.read(DEPCMD::STATUS) {
+            0 => Ok(result.read(DEPCMD::PARAM) as u16),
+            status => Err(format!("DWC3 endpoint {phys} command failed: status {status}").into()),
+        }
+    }
+
+    /// SetEpConfig 与 SetXferResource
+    fn configure_endpoint(
+        &self,
+        phys: usize,
+        config: &EndpointConfig,
+        modify: bool,
+    ) -> Result<(), USBError> {
+        let mut par0 = (config.transfer_type as u32) << 1
+            | (config.max_packet_size as u32 & 0x7ff) << 3
+            | (config.max_burst as u32 & 0xf) << 22;
+        if phys & 1 == 1 {
+            par0 |= (phys as u32 >> 1) << 17;
+        }
+        if modify {
+            par0 |= DEPCFG_ACTION_MODIFY;
+        }
+        let mut par1 = DEPCFG_XFER_COMPLETE_EN | (phys as u32) << 25;
+        if phys < 2 {
+            par1 |= DEPCFG_XFER_NOT_READY_EN;
+        }
+        if matches!(
+            config.transfer_type,
+            EndpointType::Interrupt | EndpointType::Isochronous
+        ) {
+            // 全速中断端点的 bInterval 以帧为单位，控制器需要以微帧为单位的指数
+            let interval = match self.speed() {
+                Speed::Full | Speed::Low => (config.interval.max(1) as u32 * 8).ilog2(),
+                _ => (config.interval.saturating_sub(1) as u32).min(13),
+            };
+            par1 |= interval << 16;
+        }
+        self.command(phys, DEPCMD::CMDTYP::SetEpConfig, [par0, par1, 0])?;
+        if !modify {
+            self.command(phys, DEPCMD::CMDTYP::SetXferResource, [1, 0, 0])?;
+        }
+        Ok(())
+    }
+
+    fn ep0_config(&self) -> EndpointConfig {
+        EndpointConfig {
+            address: 0,
+            transfer_type: EndpointType::Control,
+            max_packet_size: self.ep0.max_packet_size,
+            interval: 0,
+            max_burst: 0,
+        }
+    }
+
+    fn speed(&self) -> Speed {
+        match self.reg.regs().dsts.read(DSTS::CONNECTSPD) {
+            0 => Speed::High,
+            2 => Speed::Low,
+            4 => Speed::SuperSpeed,
+            5 => Speed::SuperSpeedPlus,
+            _ => Speed::Full,
+        }
+    }
+
+    /// 在物理端点上提交单个 TRB 的传输，数据须已写入缓冲区
+    fn start_transfer(
+        &mut self,
+        phys: usize,
+        len: usize,
+        kind: FieldValue<u32, TRB_CTRL::Register>,
+    ) -> Result<(), USBError> {
+        let ep = self.eps[phys].as_mut().ok_or(USBError::NotInitialized)?;
+        let trb = Trb::new(ep.buf.dma_addr().as_u64(), len, kind);
+        ep.trb.set(0, trb);
+        ep.requested = len;
+        let addr = ep.trb.dma_addr().as_u64();
+        mb();
+        let resource = self.command(
+            phys,
+            DEPCMD::CMDTYP::StartTransfer,
+            [(addr >> 32) as u32, addr as u32, 0],
+        )?;
+        let ep = self.eps[phys].as_mut().unwrap();
+        ep.resource = resource & 0x7f;
+        ep.busy = true;
+        Ok(())
+    }
+
+    /// 结束物理端点上未完成的传输
+    fn end_transfer(&mut self, phys: usize) {
+        let Some(ep) = self.eps[phys].as_ref() else {
+            return;
+        };
+        if !ep.busy {
+            return;
+        }
+        let cmd = DEPCMD::CMDTYP::EndTransfer
+            + DEPCMD::HIPRI_FORCERM::SET
+            + DEPCMD::PARAM.val(ep.resource as u32);
+        if let Err(e) = self.command(phys, cmd, [0; 3]) {
+            warn!("End transfer on {phys} failed: {e}");
+        }
+        self.kernel.delay(END_TRANSFER_DELAY);
+        if let Some(ep) = self.eps[phys].as_mut() {
+            ep.busy = false;
+        }
+    }
+
+    /// 启动 OUT 端点上的接收，长度取不超过缓冲区的最大包长整数倍
+    fn receive(&mut self, phys: usize) -> Result<(), USBError> {
+        let ep = self.eps[phys].as_ref().ok_or(USBError::NotInitialized)?;
+        let mps = ep
+            .config
+            .map(|c| c.max_packet_size as usize)
+            .ok_or(USBError::NotInitialized)?
+            .max(1);
+        self.start_transfer(phys, EP_BUFFER_SIZE / mps * mps, TRB_CTRL::TRBCTL::Normal)
+    }
+
+    /// 重新开始等待 SETUP 包
+    fn ep0_setup(&mut self) {
+        self.ep0.state = Ep0State::Setup;
+        self.ep0.status_phys = None;
+        self.ep0.zlp = false;
+        if let Err(e) = self.start_transfer(0, SETUP_LEN, TRB_CTRL::TRBCTL::ControlSetup) {
+            error!("Queue setup TRB failed: {e}");
+        }
+    }
+
+    /// STALL 当前控制传输，下一个 SETUP 包到达时控制器自动解除
+    fn ep0_stall(&mut self) {
+        if let Err(e) = self.command(0, DEPCMD::CMDTYP::SetStall, [0; 3]) {
+            warn!("Stall ep0 failed: {e}");
+        }
+        self.ep0_setup();
+    }
+
+    fn ep0_status(&mut self) {
+        let Some(phys) = self.ep0.status_phys.take() else {
+            return;
+        };
+        let kind = if self.ep0.setup.length == 0 {
+            TRB_CTRL::TRBCTL::ControlStatus2
+        } else {
+            TRB_CTRL::TRBCTL::ControlStatus3
+        };
+        self.ep0.state = Ep0State::Status;
+        if let Err(e) = self.start_transfer(phys, 0, kind) {
+            error!("Queue status TRB failed: {e}");
+            self.ep0_setup();
+        }
+    }
+
+    fn ep0_start_data(&mut self, phys: usize, len: usize, state: Ep0State) {
+        self.ep0.state = state;
+        if let Err(e) = self.start_transfer(phys, len, TRB_CTRL::TRBCTL::ControlData) {
+            error!("Queue ep0 data TRB failed: {e}");
+            self.ep0_stall();
+        }
+    }
+
+    fn ep0_complete(&mut self, phys: usize) {
+        let Some(ep) = self.eps[phys].as_mut() else {
+            return;
+        };
+        ep.busy = false;
+        let done = ep.requested - ep.trb.read(0).unwrap().remaining();
+        match self.ep0.state {
+            Ep0State::Setup => {
+                let raw = ep.buf.read_with(SETUP_LEN, |b| b.try_into().unwrap());
+                let setup = SetupPacket::from_bytes(raw);
+                self.ep0.setup = setup;
+                if setup.direction() == Direction::Out && setup.length > 0 {
+                    let len = setup.length as usize;
+                    if len > EP0_BUFFER_SIZE {
+                        warn!("Control OUT data too long: {len}");
+                        self.ep0_stall();
+                        return;
+                    }
+                    // OUT 数据阶段的长度须为最大包长的整数倍
+                    let len = len.next_multiple_of(self.ep0.max_packet_size as usize);
+                    self.ep0_start_data(0, len, Ep0State::DataOut);
+                } else {
+                    self.ep0.state = Ep0State::Reply;
+                    self.pending.push_back(UdcEvent::Setup {
+                        setup,
+                        data: Vec::new(),
+                    });
+                }
+            }
+            Ep0State::DataOut => {
+                let setup = self.ep0.setup;
+                let len = done.min(setup.length as usize);
+                let data = ep.buf.read_with(len, |b| b.to_vec());
+                self.ep0.state = Ep0State::Reply;
+                self.pending.push_back(UdcEvent::Setup { setup, data });
+            }
+            Ep0State::DataIn if self.ep0.zlp => {
+                self.ep0.zlp = false;
+                self.ep0_start_data(1, 0, Ep0State::DataIn);
+            }
+            Ep0State::DataIn => {
+                self.ep0.state = Ep0State::WaitStatus;
+                self.ep0_status();
+            }
+            Ep0State::Status => self.ep0_setup(),
+            state => warn!("Unexpected ep0 completion in {state:?}"),
+        }
+    }
+
+    fn ep0_event(&mut self, phys: usize, kind: EndpointEvent, status: u8) {
+        match kind {
+            EndpointEvent::XferComplete => self.ep0_complete(phys),
+            EndpointEvent::XferNotReady if status & 0b11 == STATUS_CONTROL_STATUS => {
+                self.ep0.status_phys = Some(phys);
+                if self.ep0.state == Ep0State::WaitStatus {
+                    self.ep0_status();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ep_complete(&mut self, phys: usize) {
+        let Some(ep) = self.eps[phys].as_mut() else {
+            return;
+        };
+        let Some(config) = ep.config else {
+            return;
+        };
+        if !ep.busy {
+            return;
+        }
+        ep.busy = false;
+        let done = ep.requested - ep.trb.read(0).unwrap().remaining();
+        if config.direction() == Direction::Out {
+            ep.received = Some(done);
+        }
+        self.pending
+            .push_back(UdcEvent::TransferComplete { ep: config.address });
+    }
+
+    /// 总线复位：停止所有端点，地址回到 0
+    fn bus_reset(&mut self) {
+        for phys in 2..NUM_PHYS_EPS {
+            self.end_transfer(phys);
+            if let Some(ep) = self.eps[phys].as_mut() {
+                ep.config = None;
+                ep.halted = false;
+                ep.received = None;
+            }
+        }
+        let regs = self.reg.regs();
+        regs.dalepena.set(0b11);
+        regs.dcfg.modify(DCFG::DEVADDR.val(0));
+        self.started_config = false;
+        self.suspended = false;
+        if self.ep0.state != Ep0State::Setup {
+            self.end_transfer(0);
+            self.end_transfer(1);
+            self.ep0_setup();
+        }
+    }
+
+    fn device_event(&mut self, event: DeviceEvent) {
+        match event {
+            DeviceEvent::Disconnect => {
+                self.suspended = false;
+                self.pending.push_back(UdcEvent::Disconnected);
+            }
+            DeviceEvent::Reset => {
+                self.bus_reset();
+                self.pending.push_back(UdcEvent::Reset);
+            }
+            DeviceEvent::ConnectDone => {
+                let speed = self.speed();
+                self.ep0.max_packet_size = match speed {
+                    Speed::SuperSpeed | Speed::SuperSpeedPlus => 512,
+                    Speed::Low => 8,
+                    _ => 64,
+                };
+                let config = self.ep0_config();
+                for phys in 0..2 {
+                    if let Err(e) = self.configure_endpoint(phys, &config, true) {
+                        warn!("Update ep0 max packet size failed: {e}");
+                    }
+                }
+                self.pending.push_back(UdcEvent::Connected(speed));
+            }
+            DeviceEvent::LinkStatusChange(LINK_STATE_U3) if !self.suspended => {
+                self.suspended = true;
+                self.pending.push_back(UdcEvent::Suspend);
+            }
+            DeviceEvent::LinkStatusChange(LINK_STATE_U0) | DeviceEvent::Wakeup
+                if self.suspended =>
+            {
+                self.suspended = false;
+                self.pending.push_back(UdcEvent::Resume);
+            }
+            DeviceEvent::LinkStatusChange(_) | DeviceEvent::Wakeup => {}
+            DeviceEvent::Other(kind) => debug!("Ignored device event {kind}"),
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Device(event) => self.device_event(event),
+            Event::Endpoint { phys, kind, status } if phys < 2 => {
+                self.ep0_event(phys as usize, kind, status)
+            }
+            Event::Endpoint {
+                phys,
+                kind: EndpointEvent::XferComplete,
+                ..
+            } => self.ep_complete(phys as usize),
+            Event::Endpoint { .. } => {}
+            Event::Unknown(raw) => debug!("Ignored event {raw:#010x}"),
+        }
+    }
+
+    /// 已启用的非控制端点
+    fn enabled_ep(&mut self, ep: u8) -> Result<&mut Endpoint, USBError> {
+        self.eps
+            .get_mut(phys(ep))
+            .and_then(|e| e.as_mut())
+            .filter(|e| e.config.is_some())
+            .ok_or(USBError::InvalidParameter)
+    }
+}
+
+impl UdcOp for Dwc3Udc {
+    fn start(&mut self) -> Result<(), USBError> {
+        let regs = self.reg.regs();
+        info!("DWC3 {:#x} starting in device mode", regs.gsnpsid.get());
+
+        regs.dctl.write(DCTL::CSFTRST::SET);
+        self.wait(RESET_TIMEOUT, || !regs.dctl.is_set(DCTL::CSFTRST))?;
+        regs.gctl.modify(GCTL::PRTCAPDIR::Device);
+
+        let addr = self.events.dma_addr().as_u64();
+        regs.gevnt.adrlo.set(addr as u32);
+        regs.gevnt.adrhi.set((addr >> 32) as u32);
+        regs.gevnt
+            .size
+            .write(GEVNTSIZ::SIZE.val((EVENT_BUFFER_LEN * 4) as u32) + GEVNTSIZ::INTMASK::SET);
+        regs.gevnt.count.set(regs.gevnt.count.get() & 0xffff);
+        self.event_pos = 0;
+
+        let speed = match self.config.max_speed {
+            Speed::Low | Speed::Full => DCFG::DEVSPD::FullSpeed,
+            Speed::High | Speed::Wireless => DCFG::DEVSPD::HighSpeed,
+            Speed::SuperSpeed => DCFG::DEVSPD::SuperSpeed,
+            Speed::SuperSpeedPlus => DCFG::DEVSPD::SuperSpeedPlus,
+        };
+        regs.dcfg.modify(speed + DCFG::DEVADDR.val(0));
+        regs.devten.write(
+            DEVTEN::DISCONNEVTEN::SET
+                + DEVTEN::USBRSTEN::SET
+                + DEVTEN::CONNECTDONEEN::SET
+                + DEVTEN::ULSTCNGEN::SET
+                + DEVTEN::WKUPEVTEN::SET
+                + DEVTEN::U3L2L1SUSPEN::SET,
+        );
+
+        self.command(0, DEPCMD::CMDTYP::StartConfig, [0; 3])?;
+        let config = self.ep0_config();
+        self.configure_endpoint(0, &config, false)?;
+        self.configure_endpoint(1, &config, false)?;
+        regs.dalepena.set(0b11);
+        self.ep0_setup();
+
+        regs.dctl.modify(DCTL::RUN_STOP::SET);
+        self.wait(HALT_TIMEOUT, || !regs.dsts.is_set(DSTS::DEVCTRLHLT))?;
+        debug!("DWC3 running");
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        for phys in (0..NUM_PHYS_EPS).rev() {
+            self.end_transfer(phys);
+        }
+        let regs = self.reg.regs();
+        regs.dctl.modify(DCTL::RUN_STOP::CLEAR);
+        if self
+            .wait(HALT_TIMEOUT, || regs.dsts.is_set(DSTS::DEVCTRLHLT))
+            .is_err()
+        {
+            warn!("DWC3 did not halt");
+        }
+        regs.dalepena.set(0);
+        for ep in self.eps.iter_mut().skip(2).flatten() {
+            ep.config = None;
+        }
+        self.ep0.state = Ep0State::Setup;
+        self.started_config = false;
+        self.pending.clear();
+    }
+
+    fn poll_event(&mut self) -> Option<UdcEvent> {
+        let regs = self.reg.regs();
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            if regs.gevnt.count.get() & 0xffff == 0 {
+                return None;
+            }
+            let raw = self.events.read(self.event_pos).unwrap();
+            self.event_pos = (self.event_pos + 1) % EVENT_BUFFER_LEN;
+            regs.gevnt.count.set(4);
+            self.handle_event(Event::decode(raw));
+        }
+    }
+
+    fn control_reply(&mut self, reply: ControlReply<'_>) {
+        if self.ep0.state != Ep0State::Reply {
+            warn!("Control reply without pending setup");
+            return;
+        }
+        let setup = self.ep0.setup;
+        match reply {
+            ControlReply::Stall => self.ep0_stall(),
+            reply if setup.direction() == Direction::In && setup.length > 0 => {
+                let data = match reply {
+                    ControlReply::Data(data) => data,
+                    _ => &[],
+                };
+                let len = data.len().min(setup.length as usize).min(EP0_BUFFER_SIZE);
+                let ep = self.eps[1].as_mut().unwrap();
+                ep.buf.write_with(len, |b| b.copy_from_slice(&data[..len]));
+                // 短于 wLength 且为最大包长整数倍时须以零长度包结束
+                self.ep0.zlp = len > 0
+                    && len < setup.length as usize
+                    && len.is_multiple_of(self.ep0.max_packet_size as usize);
+                self.ep0_start_data(1, len, Ep0State::DataIn);
+            }
+            ControlReply::Data(_) => {
+                warn!("Data reply to a request without IN data stage");
+                self.ep0_stall();
+            }
+            ControlReply::Ack => {
+                self.ep0.state = Ep0State::WaitStatus;
+                self.ep0_status();
+            }
+        }
+    }
+
+    fn set_address(&mut self, address: u8) {
+        self.reg
+            .regs()
+            .dcfg
+            .modify(DCFG::DEVADDR.val(address as u32));
+    }
+
+    fn ep_enable(&mut self, config: &EndpointConfig) -> Result<(), USBError> {
+        let phys = phys(config.address);
+        if !(2..NUM_PHYS_EPS).contains(&phys) {
+            return Err(USBError::InvalidParameter);
+        }
+        if !self.started_config {
+            // 为非控制端点重新分配传输资源
+            self.command(
+                0,
+                DEPCMD::CMDTYP::StartConfig + DEPCMD::PARAM.val(2),
+                [0; 3],
+            )?;
+            self.started_config = true;
+        }
+        if self.eps[phys].is_none() {
+            self.eps[phys] = Some(Self::alloc_endpoint(
+                &self.dma,
+                EP_BUFFER_SIZE,
+                config.direction(),
+            )?);
+        }
+        self.configure_endpoint(phys, config, false)?;
+        let regs = self.reg.regs();
+        regs.dalepena.set(regs.dalepena.get() | 1 << phys);
+
+        let ep = self.eps[phys].as_mut().unwrap();
+        ep.config = Some(*config);
+        ep.busy = false;
+        ep.halted = false;
+        ep.received = None;
+        if config.direction() == Direction::Out {
+            self.receive(phys)?;
+        }
+        Ok(())
+    }
+
+    fn ep_disable(&mut self, ep: u8) {
+        let phys = phys(ep);
+        if phys < 2 || self.eps.get(phys).is_none_or(|e| e.is_none()) {
+            return;
+        }
+        self.end_transfer(phys);
+        let regs = self.reg.regs();
+        regs.dalepena.set(regs.dalepena.get() & !(1 << phys));
+        if let Some(ep) = self.eps[phys].as_mut() {
+            ep.config = None;
+            ep.halted = false;
+            ep.received = None;
+        }
+        // 所有非控制端点均已停用，下次启用时重新分配传输资源
+        if regs.dalepena.get() == 0b11 {
+            self.started_config = false;
+        }
+    }
+
+    fn ep_set_halt(&mut self, ep: u8, halt: bool) -> Result<(), USBError> {
+        let phys = phys(ep);
+        if phys < 2 {
+            // 端点 0 的 STALL 在下一个 SETUP 包到达时自动解除
+            if halt {
+                self.command(0, DEPCMD::CMDTYP::SetStall, [0; 3])?;
+            }
+            return Ok(());
+        }
+        let endpoint = self.enabled_ep(ep)?;
+        if endpoint.halted == halt {
+            return Ok(());
+        }
+        let cmd = if halt {
+            DEPCMD::CMDTYP::SetStall
+        } else {
+            DEPCMD::CMDTYP::ClearStall
+        };
+        self.command(phys, cmd, [0; 3])?;
+        self.enabled_ep(ep)?.halted = halt;
+        Ok(())
+    }
+
+    fn ep_is_halted(&self, ep: u8) -> bool {
+        self.eps
+            .get(phys(ep))
+            .and_then(|e| e.as_ref())
+            .is_some_and(|e| e.halted)
+    }
+
+    fn ep_busy(&self, ep: u8) -> bool {
+        self.eps
+            .get(phys(ep))
+            .and_then(|e| e.as_ref())
+            .is_some_and(|e| e.busy)
+    }
+
+    fn ep_write(&mut self, ep: u8, data: &[u8]) -> Result<usize, USBError> {
+        let endpoint = self.enabled_ep(ep)?;
+        if endpoint.config.unwrap().direction() != Direction::In {
+            return Err(USBError::InvalidParameter);
+        }
+        if endpoint.busy || endpoint.halted {
+            return Ok(0);
+        }
+        let len = data.len().min(EP_BUFFER_SIZE);
+        endpoint
+            .buf
+            .write_with(len, |b| b.copy_from_slice(&data[..len]));
+        self.start_transfer(phys(ep), len, TRB_CTRL::TRBCTL::Normal)?;
+        Ok(len)
+    }
+
+    fn ep_read(&mut self, ep: u8, buf: &mut [u8]) -> Result<Option<usize>, USBError> {
+        let endpoint = self.enabled_ep(ep)?;
+        if endpoint.config.unwrap().direction() != Direction::Out {
+            return Err(USBError::InvalidParameter);
+        }
+        let Some(received) = endpoint.received.take() else {
+            return Ok(None);
+        };
+        let len = received.min(buf.len());
+        endpoint
+            .buf
+            .read_with(len, |b| buf[..len].copy_from_slice(b));
+        self.receive(phys(ep))?;
+        Ok(Some(len))
+    }
+}
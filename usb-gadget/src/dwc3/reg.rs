@@ -0,0 +1,137 @@
+//! DWC3 设备模式寄存器
+//!
+//! 偏移相对全局寄存器区（控制器基址 + 0xC100），参考 Linux drivers/usb/dwc3/core.h。
+
+use core::ptr::NonNull;
+
+use tock_registers::{register_bitfields, register_structs, registers::*};
+
+/// 全局寄存器区相对控制器基址的偏移
+const GLOBALS_REGS_START: usize = 0xc100;
+
+register_bitfields! [u32,
+    pub GCTL [
+        CORESOFTRESET OFFSET(11) NUMBITS(1) [],
+        PRTCAPDIR OFFSET(12) NUMBITS(2) [
+            Host = 1,
+            Device = 2,
+            Otg = 3,
+        ],
+    ],
+
+    pub GEVNTSIZ [
+        SIZE OFFSET(0) NUMBITS(16) [],
+        /// 屏蔽事件中断，事件仍写入缓冲区
+        INTMASK OFFSET(31) NUMBITS(1) [],
+    ],
+
+    pub DCFG [
+        DEVSPD OFFSET(0) NUMBITS(3) [
+            HighSpeed = 0,
+            FullSpeed = 1,
+            SuperSpeed = 4,
+            SuperSpeedPlus = 5,
+        ],
+        DEVADDR OFFSET(3) NUMBITS(7) [],
+    ],
+
+    pub DCTL [
+        CSFTRST OFFSET(30) NUMBITS(1) [],
+        RUN_STOP OFFSET(31) NUMBITS(1) [],
+    ],
+
+    pub DEVTEN [
+        DISCONNEVTEN OFFSET(0) NUMBITS(1) [],
+        USBRSTEN OFFSET(1) NUMBITS(1) [],
+        CONNECTDONEEN OFFSET(2) NUMBITS(1) [],
+        ULSTCNGEN OFFSET(3) NUMBITS(1) [],
+        WKUPEVTEN OFFSET(4) NUMBITS(1) [],
+        U3L2L1SUSPEN OFFSET(6) NUMBITS(1) [],
+    ],
+
+    pub DSTS [
+        CONNECTSPD OFFSET(0) NUMBITS(3) [],
+        DEVCTRLHLT OFFSET(22) NUMBITS(1) [],
+    ],
+
+    pub DEPCMD [
+        CMDTYP OFFSET(0) NUMBITS(4) [
+            SetEpConfig = 1,
+            SetXferResource = 2,
+            SetStall = 4,
+            ClearStall = 5,
+            StartTransfer = 6,
+            EndTransfer = 8,
+            StartConfig = 9,
+        ],
+        CMDIOC OFFSET(8) NUMBITS(1) [],
+        CMDACT OFFSET(10) NUMBITS(1) [],
+        /// EndTransfer 时强制移除传输资源
+        HIPRI_FORCERM OFFSET(11) NUMBITS(1) [],
+        STATUS OFFSET(12) NUMBITS(4) [],
+        /// 命令参数；StartTransfer 完成后为分配的传输资源索引
+        PARAM OFFSET(16) NUMBITS(16) [],
+    ],
+];
+
+/// 事件缓冲区寄存器
+#[repr(C)]
+pub struct Gevnt {
+    pub adrlo: ReadWrite<u32>,
+    pub adrhi: ReadWrite<u32>,
+    pub size: ReadWrite<u32, GEVNTSIZ::Register>,
+    /// 读出待处理的字节数，写入已处理的字节数
+    pub count: ReadWrite<u32>,
+}
+
+/// 物理端点的命令寄存器
+#[repr(C)]
+pub struct Depcmd {
+    pub par2: ReadWrite<u32>,
+    pub par1: ReadWrite<u32>,
+    pub par0: ReadWrite<u32>,
+    pub cmd: ReadWrite<u32, DEPCMD::Register>,
+}
+
+register_structs! {
+    pub Dwc3Registers {
+        (0x000 => _rsv0),
+        (0x010 => pub gctl: ReadWrite<u32, GCTL::Register>),
+        (0x014 => _rsv1),
+        (0x020 => pub gsnpsid: ReadOnly<u32>),
+        (0x024 => _rsv2),
+        (0x300 => pub gevnt: Gevnt),
+        (0x310 => _rsv3),
+        (0x600 => pub dcfg: ReadWrite<u32, DCFG::Register>),
+        (0x604 => pub dctl: ReadWrite<u32, DCTL::Register>),
+        (0x608 => pub devten: ReadWrite<u32, DEVTEN::Register>),
+        (0x60C => pub dsts: ReadOnly<u32, DSTS::Register>),
+        (0x610 => _rsv4),
+        /// 已启用的物理端点，位号为物理端点号
+        (0x620 => pub dalepena: ReadWrite<u32>),
+        (0x624 => _rsv5),
+        (0x700 => pub depcmd: [Depcmd; 32]),
+        (0x900 => @END),
+    }
+}
+
+/// DWC3 寄存器访问器
+#[derive(Clone, Copy)]
+pub struct Dwc3Regs {
+    base: usize,
+}
+
+impl Dwc3Regs {
+    /// # Safety
+    ///
+    /// 调用者必须确保 `mmio` 指向有效的 DWC3 控制器寄存器区域
+    pub unsafe fn new(mmio: NonNull<u8>) -> Self {
+        Self {
+            base: mmio.as_ptr() as usize + GLOBALS_REGS_START,
+        }
+    }
+
+    pub fn regs(&self) -> &'static Dwc3Registers {
+        unsafe { &*(self.base as *const Dwc3Registers) }
+    }
+}
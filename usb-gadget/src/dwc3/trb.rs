@@ -0,0 +1,164 @@
+//! DWC3 TRB 与事件格式
+//!
+//! 设备模式的 TRB 与 xHCI 不同：没有环，也没有 Cycle 位，控制器通过 HWO 位取得
+//! TRB 的所有权，完成后清除 HWO 并把剩余长度写回 BUFSIZ。参考 Linux
+//! drivers/usb/dwc3/core.h。
+
+use tock_registers::{LocalRegisterCopy, fields::FieldValue, register_bitfields};
+
+register_bitfields! [u32,
+    pub TRB_SIZE [
+        /// 提交时为缓冲区长度，完成后为未传输的长度
+        BUFSIZ OFFSET(0) NUMBITS(24) [],
+    ],
+
+    pub TRB_CTRL [
+        /// 由控制器持有
+        HWO OFFSET(0) NUMBITS(1) [],
+        /// 传输中的最后一个 TRB
+        LST OFFSET(1) NUMBITS(1) [],
+        TRBCTL OFFSET(4) NUMBITS(6) [
+            Normal = 1,
+            ControlSetup = 2,
+            ControlStatus2 = 3,
+            ControlStatus3 = 4,
+            ControlData = 5,
+        ],
+        IOC OFFSET(11) NUMBITS(1) [],
+    ],
+];
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Trb {
+    bpl: u32,
+    bph: u32,
+    size: u32,
+    ctrl: u32,
+}
+
+impl Trb {
+    /// 单个 TRB 构成的传输，完成时产生 XferComplete
+    pub fn new(addr: u64, len: usize, kind: FieldValue<u32, TRB_CTRL::Register>) -> Self {
+        Self {
+            bpl: addr as u32,
+            bph: (addr >> 32) as u32,
+            size: (TRB_SIZE::BUFSIZ.val(len as u32)).value,
+            ctrl: (TRB_CTRL::HWO::SET + TRB_CTRL::LST::SET + TRB_CTRL::IOC::SET + kind).value,
+        }
+    }
+
+    /// 未传输的长度
+    pub fn remaining(&self) -> usize {
+        LocalRegisterCopy::<u32, TRB_SIZE::Register>::new(self.size).read(TRB_SIZE::BUFSIZ) as usize
+    }
+}
+
+/// 设备事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Disconnect,
+    Reset,
+    ConnectDone,
+    /// 携带新的链路状态，U3 即挂起，U0 即工作
+    LinkStatusChange(u8),
+    Wakeup,
+    Other(u8),
+}
+
+/// 端点事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointEvent {
+    XferComplete,
+    XferInProgress,
+    XferNotReady,
+    Other(u8),
+}
+
+/// 事件缓冲区中的一项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Device(DeviceEvent),
+    Endpoint {
+        /// 物理端点号：端点号左移一位，IN 方向置最低位
+        phys: u8,
+        kind: EndpointEvent,
+        status: u8,
+    },
+    /// 其他类型的事件，例如 Carkit 或 I2C 事件
+    Unknown(u32),
+}
+
+/// XferNotReady 的 status 中表示控制传输状态阶段的值
+pub const STATUS_CONTROL_STATUS: u8 = 2;
+/// 链路状态：U0 / L0
+pub const LINK_STATE_U0: u8 = 0;
+/// 链路状态：U3 / L2
+pub const LINK_STATE_U3: u8 = 3;
+
+impl Event {
+    pub fn decode(raw: u32) -> Self {
+        if raw & 1 == 0 {
+            let kind = match (raw >> 6) & 0xf {
+                1 => EndpointEvent::XferComplete,
+                2 => EndpointEvent::XferInProgress,
+                3 => EndpointEvent::XferNotReady,
+                other => EndpointEvent::Other(other as u8),
+            };
+            return Event::Endpoint {
+                phys: ((raw >> 1) & 0x1f) as u8,
+                kind,
+                status: ((raw >> 12) & 0xf) as u8,
+            };
+        }
+        if (raw >> 1) & 0x7f != 0 {
+            return Event::Unknown(raw);
+        }
+        let info = ((raw >> 16) & 0x1ff) as u8;
+        Event::Device(match (raw >> 8) & 0xf {
+            0 => DeviceEvent::Disconnect,
+            1 => DeviceEvent::Reset,
+            2 => DeviceEvent::ConnectDone,
+            3 => DeviceEvent::LinkStatusChange(info & 0xf),
+            4 => DeviceEvent::Wakeup,
+            other => DeviceEvent::Other(other as u8),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trb_layout() {
+        let trb = Trb::new(0x1_2345_6780, 8, TRB_CTRL::TRBCTL::ControlSetup);
+        assert_eq!(trb.bpl, 0x2345_6780);
+        assert_eq!(trb.bph, 1);
+        assert_eq!(trb.size, 8);
+        assert_eq!(trb.ctrl, 1 | 1 << 1 | 2 << 4 | 1 << 11);
+        assert_eq!(trb.remaining(), 8);
+    }
+
+    #[test]
+    fn decode_events() {
+        assert_eq!(
+            Event::decode(0x0000_0101),
+            Event::Device(DeviceEvent::Reset)
+        );
+        assert_eq!(
+            Event::decode(0x0003_0301),
+            Event::Device(DeviceEvent::LinkStatusChange(LINK_STATE_U3))
+        );
+        // 物理端点 1 上的 XferNotReady，状态阶段
+        assert_eq!(
+            Event::decode(1 << 1 | 3 << 6 | 2 << 12),
+            Event::Endpoint {
+                phys: 1,
+                kind: EndpointEvent::XferNotReady,
+                status: STATUS_CONTROL_STATUS,
+            }
+        );
+        assert_eq!(Event::decode(0x0000_0003), Event::Unknown(3));
+    }
+}
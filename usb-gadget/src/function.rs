@@ -0,0 +1,134 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::any::Any;
+
+use usb_if::{Speed, err::USBError, transfer::Direction};
+
+use crate::{
+    setup::SetupPacket,
+    udc::{ControlReply, EndpointConfig, UdcOp},
+};
+
+/// 每个方向可分配的最大端点号
+const MAX_ENDPOINT: u8 = 15;
+
+/// [`Gadget::add_function`](crate::Gadget::add_function) 返回的功能句柄
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FunctionId(pub(crate) usize);
+
+/// 复合设备中的一个功能，例如 CDC ACM 串口
+///
+/// 功能在加入 [`Gadget`](crate::Gadget) 时分配接口号与端点地址，主机选择配置后
+/// 由 Gadget 启用 [`Function::endpoints`] 返回的全部端点，再调用 [`Function::enable`]。
+pub trait Function: Any + Send {
+    /// 通过 `ids` 分配接口号与端点地址，只调用一次
+    fn bind(&mut self, ids: &mut IdAllocator) -> Result<(), USBError>;
+
+    /// 该速度下的全部非控制端点
+    fn endpoints(&self, speed: Speed) -> Vec<EndpointConfig>;
+
+    /// 追加接口、类特定与端点描述符
+    fn write_descriptors(&self, speed: Speed, out: &mut Vec<u8>);
+
+    /// 配置已生效，端点均已启用
+    fn enable(&mut self, _udc: &mut dyn UdcOp, _speed: Speed) {}
+
+    /// 配置被撤销或总线复位，端点均已停用
+    fn disable(&mut self) {}
+
+    /// 发给本功能接口或端点的类与厂商请求，以及未由 Gadget 处理的标准请求
+    fn setup(&mut self, _setup: &SetupPacket, _data: &[u8]) -> ControlReply<'_> {
+        ControlReply::Stall
+    }
+
+    /// SET_INTERFACE，返回 `false` 表示不支持该备用设置
+    fn set_alt(&mut self, _interface: u8, alt: u8) -> bool {
+        alt == 0
+    }
+
+    fn alt_setting(&self, _interface: u8) -> u8 {
+        0
+    }
+
+    /// 本功能的端点上有传输完成
+    fn transfer_complete(&mut self, _udc: &mut dyn UdcOp, _ep: u8) {}
+
+    /// 配置生效期间每次 [`Gadget::poll`](crate::Gadget::poll) 调用一次，用于提交传输
+    fn poll(&mut self, _udc: &mut dyn UdcOp) {}
+}
+
+/// 接口号与端点地址分配器，同时记录其所属功能以便分发请求
+#[derive(Debug, Default)]
+pub struct IdAllocator {
+    current: usize,
+    /// 下标为接口号
+    interfaces: Vec<usize>,
+    endpoints: BTreeMap<u8, usize>,
+}
+
+impl IdAllocator {
+    pub(crate) fn bind_to(&mut self, function: usize) {
+        self.current = function;
+    }
+
+    /// 分配下一个接口号
+    pub fn interface(&mut self) -> Result<u8, USBError> {
+        let number = u8::try_from(self.interfaces.len()).map_err(|_| USBError::NoMemory)?;
+        self.interfaces.push(self.current);
+        Ok(number)
+    }
+
+    /// 分配该方向上最小的空闲端点地址
+    pub fn endpoint(&mut self, direction: Direction) -> Result<u8, USBError> {
+        let dir = match direction {
+            Direction::In => 0x80,
+            Direction::Out => 0,
+        };
+        let address = (1..=MAX_ENDPOINT)
+            .map(|n| n | dir)
+            .find(|a| !self.endpoints.contains_key(a))
+            .ok_or(USBError::NoMemory)?;
+        self.endpoints.insert(address, self.current);
+        Ok(address)
+    }
+
+    pub(crate) fn num_interfaces(&self) -> u8 {
+        self.interfaces.len() as u8
+    }
+
+    pub(crate) fn interface_owner(&self, interface: u8) -> Option<usize> {
+        self.interfaces.get(interface as usize).copied()
+    }
+
+    pub(crate) fn endpoint_owner(&self, ep: u8) -> Option<usize> {
+        self.endpoints.get(&ep).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocate_per_direction() {
+        let mut ids = IdAllocator::default();
+        ids.bind_to(0);
+        assert_eq!(ids.interface().unwrap(), 0);
+        assert_eq!(ids.endpoint(Direction::In).unwrap(), 0x81);
+        assert_eq!(ids.endpoint(Direction::Out).unwrap(), 0x01);
+        ids.bind_to(1);
+        assert_eq!(ids.interface().unwrap(), 1);
+        assert_eq!(ids.endpoint(Direction::In).unwrap(), 0x82);
+
+        assert_eq!(ids.interface_owner(1), Some(1));
+        assert_eq!(ids.endpoint_owner(0x01), Some(0));
+        assert_eq!(ids.endpoint_owner(0x02), None);
+
+        for _ in 0..13 {
+            ids.endpoint(Direction::In).unwrap();
+        }
+        assert!(matches!(
+            ids.endpoint(Direction::In),
+            Err(USBError::NoMemory)
+        ));
+    }
+}
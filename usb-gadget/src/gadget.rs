@@ -0,0 +1,684 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::any::Any;
+
+use usb_if::{
+    Speed,
+    descriptor::DescriptorType,
+    err::USBError,
+    transfer::{Recipient, Request, RequestType, StandardFeature},
+};
+
+use crate::{
+    descriptor::{self, ConfigHeader, DeviceIds, is_super_speed},
+    function::{Function, FunctionId, IdAllocator},
+    setup::SetupPacket,
+    udc::{ControlReply, UdcEvent, UdcOp},
+};
+
+/// 英语（美国）
+const LANGID_EN_US: u16 = 0x0409;
+/// 唯一的配置值
+const CONFIGURATION_VALUE: u8 = 1;
+
+/// 设备描述与配置属性
+#[derive(Debug, Clone)]
+pub struct GadgetConfig {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub device_release: u16,
+    /// 字符串描述符，超过 126 个 UTF-16 字符的部分被截断
+    pub manufacturer: &'static str,
+    pub product: &'static str,
+    pub serial: &'static str,
+    /// 从总线获取的最大电流
+    pub max_power_ma: u16,
+    pub self_powered: bool,
+    /// 是否声明支持远程唤醒
+    pub remote_wakeup: bool,
+}
+
+impl Default for GadgetConfig {
+    fn default() -> Self {
+        Self {
+            vendor_id: 0x1d6b,
+            product_id: 0x0104,
+            device_release: 0x0100,
+            manufacturer: "CrabUSB",
+            product: "CrabUSB Gadget",
+            serial: "0001",
+            max_power_ma: 100,
+            self_powered: false,
+            remote_wakeup: false,
+        }
+    }
+}
+
+/// 设备状态（USB 2.0 9.1），挂起单独由 [`Gadget::is_suspended`] 表示
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GadgetState {
+    /// 未连接到主机
+    Detached,
+    /// 已复位，使用地址 0
+    Default,
+    /// 已分配地址，未配置
+    Address,
+    /// 主机已选择配置，各功能可以收发数据
+    Configured,
+}
+
+/// 标准请求的处理结果，数据放在 `Gadget::reply` 中
+enum Outcome {
+    Data,
+    Ack,
+    Stall,
+}
+
+/// 复合设备
+///
+/// 处理枚举过程中的标准请求，把类与厂商请求分发给接口或端点所属的功能。
+/// 与 UDC 一样以轮询方式工作，需要周期性调用 [`Gadget::poll`]。
+pub struct Gadget<U: UdcOp> {
+    udc: U,
+    config: GadgetConfig,
+    functions: Vec<Box<dyn Function>>,
+    ids: IdAllocator,
+    state: GadgetState,
+    speed: Speed,
+    suspended: bool,
+    started: bool,
+    /// 主机是否通过 SET_FEATURE 允许远程唤醒
+    remote_wakeup_enabled: bool,
+    reply: Vec<u8>,
+}
+
+impl<U: UdcOp> Gadget<U> {
+    pub fn new(udc: U, config: GadgetConfig) -> Self {
+        Self {
+            udc,
+            config,
+            functions: Vec::new(),
+            ids: IdAllocator::default(),
+            state: GadgetState::Detached,
+            speed: Speed::Full,
+            suspended: false,
+            started: false,
+            remote_wakeup_enabled: false,
+            reply: Vec::new(),
+        }
+    }
+
+    /// 加入功能并为其分配接口与端点，须在 [`Gadget::start`] 之前调用
+    pub fn add_function(
+        &mut self,
+        function: impl Function + 'static,
+    ) -> Result<FunctionId, USBError> {
+        if self.started {
+            return Err(USBError::InvalidParameter);
+        }
+        let mut function = Box::new(function);
+        self.ids.bind_to(self.functions.len());
+        function.bind(&mut self.ids)?;
+        self.functions.push(function);
+        Ok(FunctionId(self.functions.len() - 1))
+    }
+
+    /// 按类型取回功能，`id` 与类型不匹配时返回 `None`
+    pub fn function_mut<T: Function>(&mut self, id: FunctionId) -> Option<&mut T> {
+        let function = self.functions.get_mut(id.0)?;
+        (function.as_mut() as &mut dyn Any).downcast_mut()
+    }
+
+    /// 启动控制器并连接到总线
+    pub fn start(&mut self) -> Result<(), USBError> {
+        self.udc.start()?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// 撤销配置并断开总线连接
+    pub fn stop(&mut self) {
+        self.deconfigure();
+        self.udc.stop();
+        self.state = GadgetState::Detached;
+        self.started = false;
+    }
+
+    /// 处理控制器事件，并让各功能提交传输
+    pub fn poll(&mut self) {
+        while let Some(event) = self.udc.poll_event() {
+            self.handle_event(event);
+        }
+        if self.state == GadgetState::Configured {
+            for function in &mut self.functions {
+                function.poll(&mut self.udc);
+            }
+        }
+    }
+
+    pub fn state(&self) -> GadgetState {
+        self.state
+    }
+
+    /// 与主机协商出的速度，连接前无意义
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    pub fn udc(&mut self) -> &mut U {
+        &mut self.udc
+    }
+
+    fn handle_event(&mut self, event: UdcEvent) {
+        match event {
+            UdcEvent::Reset => {
+                debug!("Bus reset");
+                self.deconfigure();
+                self.state = GadgetState::Default;
+                self.suspended = false;
+                self.remote_wakeup_enabled = false;
+            }
+            UdcEvent::Connected(speed) => {
+                debug!("Connected at {speed:?}");
+                self.speed = speed;
+                self.state = GadgetState::Default;
+            }
+            UdcEvent::Disconnected => {
+                debug!("Disconnected");
+                self.deconfigure();
+                self.state = GadgetState::Detached;
+                self.suspended = false;
+            }
+            UdcEvent::Suspend => self.suspended = true,
+            UdcEvent::Resume => self.suspended = false,
+            UdcEvent::Setup { setup, data } => self.handle_setup(&setup, &data),
+            UdcEvent::TransferComplete { ep } => {
+                if let Some(idx) = self.ids.endpoint_owner(ep) {
+                    self.functions[idx].transfer_complete(&mut self.udc, ep);
+                }
+            }
+        }
+    }
+
+    fn handle_setup(&mut self, setup: &SetupPacket, data: &[u8]) {
+        trace!("{setup:x?}");
+        if matches!(setup.kind(), RequestType::Standard) {
+            match self.standard_request(setup) {
+                Some(Outcome::Data) => self.udc.control_reply(ControlReply::Data(&self.reply)),
+                Some(Outcome::Ack) => self.udc.control_reply(ControlReply::Ack),
+                Some(Outcome::Stall) => self.udc.control_reply(ControlReply::Stall),
+                // 接口与端点上的其余标准请求交给功能，例如 HID 报告描述符
+                None => self.function_request(setup, data),
+            }
+        } else {
+            self.function_request(setup, data);
+        }
+    }
+
+    fn function_request(&mut self, setup: &SetupPacket, data: &[u8]) {
+        let target = setup.index as u8;
+        let owner = match setup.recipient() {
+            Recipient::Interface => self.ids.interface_owner(target),
+            Recipient::Endpoint => self.ids.endpoint_owner(target),
+            _ => None,
+        };
+        let reply = match owner {
+            Some(idx) if self.state == GadgetState::Configured => {
+                self.functions[idx].setup(setup, data)
+            }
+            _ => ControlReply::Stall,
+        };
+        self.udc.control_reply(reply);
+    }
+
+    /// 返回 `None` 表示请求应交给功能处理
+    fn standard_request(&mut self, setup: &SetupPacket) -> Option<Outcome> {
+        let recipient = setup.recipient();
+        let outcome = match (setup.standard_request(), recipient) {
+            (Request::GetDescriptor, Recipient::Device) => self.get_descriptor(setup),
+            (Request::SetAddress, Recipient::Device) => self.set_address(setup.value as u8),
+            (Request::GetConfiguration, Recipient::Device) => {
+                let value = match self.state {
+                    GadgetState::Configured => CONFIGURATION_VALUE,
+                    _ => 0,
+                };
+                self.reply_with(&[value])
+            }
+            (Request::SetConfiguration, Recipient::Device) => {
+                self.set_configuration(setup.value as u8)
+            }
+            (Request::GetStatus, _) => self.get_status(setup),
+            (Request::ClearFeature, _) => self.set_feature(setup, false),
+            (Request::SetFeature, _) => self.set_feature(setup, true),
+            (Request::GetInterface, Recipient::Interface) => {
+                let interface = setup.index as u8;
+                match self.ids.interface_owner(interface) {
+                    Some(idx) if self.state == GadgetState::Configured => {
+                        let alt = self.functions[idx].alt_setting(interface);
+                        self.reply_with(&[alt])
+                    }
+                    _ => Outcome::Stall,
+                }
+            }
+            (Request::SetInterface, Recipient::Interface) => {
+                let interface = setup.index as u8;
+                match self.ids.interface_owner(interface) {
+                    Some(idx)
+                        if self.state == GadgetState::Configured
+                            && self.functions[idx].set_alt(interface, setup.value as u8) =>
+                    {
+                        Outcome::Ack
+                    }
+                    _ => Outcome::Stall,
+                }
+            }
+            // U1/U2 退出延迟与等时延迟只影响链路电源管理，数据已随 SETUP 接收
+            (Request::SetSel | Request::SetIsochDelay, Recipient::Device) => Outcome::Ack,
+            (_, Recipient::Interface | Recipient::Endpoint) => return None,
+            _ => Outcome::Stall,
+        };
+        Some(outcome)
+    }
+
+    fn reply_with(&mut self, data: &[u8]) -> Outcome {
+        self.reply.clear();
+        self.reply.extend_from_slice(data);
+        Outcome::Data
+    }
+
+    fn get_descriptor(&mut self, setup: &SetupPacket) -> Outcome {
+        let kind = DescriptorType((setup.value >> 8) as u8);
+        let index = setup.value as u8;
+        let super_speed = is_super_speed(self.speed);
+        match kind {
+            DescriptorType::DEVICE => {
+                let ids = DeviceIds {
+                    vendor_id: self.config.vendor_id,
+                    product_id: self.config.product_id,
+                    device_release: self.config.device_release,
+                };
+                self.reply_with(&descriptor::device(&ids, self.speed))
+            }
+            DescriptorType::CONFIGURATION if index == 0 => {
+                self.reply = self.configuration(kind, self.speed);
+                Outcome::Data
+            }
+            // 只有高速与全速之间存在“另一种速率”
+            DescriptorType::OTHER_SPEED_CONFIGURATION if index == 0 && !super_speed => {
+                let other = match self.speed {
+                    Speed::High => Speed::Full,
+                    _ => Speed::High,
+                };
+                self.reply = self.configuration(kind, other);
+                Outcome::Data
+            }
+            DescriptorType::DEVICE_QUALIFIER if !super_speed => {
+                self.reply_with(&descriptor::device_qualifier())
+            }
+            DescriptorType::BOS if super_speed => self.reply_with(&descriptor::bos()),
+            DescriptorType::STRING => {
+                let s = match index {
+                    0 => {
+                        let [lo, hi] = LANGID_EN_US.to_le_bytes();
+                        return self.reply_with(&[4, DescriptorType::STRING.0, lo, hi]);
+                    }
+                    1 => self.config.manufacturer,
+                    2 => self.config.product,
+                    3 => self.config.serial,
+                    _ => return Outcome::Stall,
+                };
+                self.reply = descriptor::string(s);
+                Outcome::Data
+            }
+            _ => Outcome::Stall,
+        }
+    }
+
+    fn configuration(&self, kind: DescriptorType, speed: Speed) -> Vec<u8> {
+        let mut body = Vec::new();
+        for function in &self.functions {
+            function.write_descriptors(speed, &mut body);
+        }
+        let header = ConfigHeader {
+            num_interfaces: self.ids.num_interfaces(),
+            self_powered: self.config.self_powered,
+            remote_wakeup: self.config.remote_wakeup,
+            max_power_ma: self.config.max_power_ma,
+        };
+        descriptor::configuration(&header, kind, speed, &body)
+    }
+
+    fn set_address(&mut self, address: u8) -> Outcome {
+        if address > 127 || self.state == GadgetState::Configured {
+            return Outcome::Stall;
+        }
+        self.udc.set_address(address);
+        self.state = if address == 0 {
+            GadgetState::Default
+        } else {
+            GadgetState::Address
+        };
+        Outcome::Ack
+    }
+
+    fn set_configuration(&mut self, value: u8) -> Outcome {
+        if self.state == GadgetState::Default {
+            return Outcome::Stall;
+        }
+        match value {
+            0 => {
+                self.deconfigure();
+                self.state = GadgetState::Address;
+            }
+            CONFIGURATION_VALUE => {
+                // 重复选择同一配置时复位所有端点
+                self.deconfigure();
+                for function in &mut self.functions {
+                    for ep in function.endpoints(self.speed) {
+                        if let Err(e) = self.udc.ep_enable(&ep) {
+                            warn!("Enable endpoint {:#04x} failed: {e}", ep.address);
+                            return Outcome::Stall;
+                        }
+                    }
+                    function.enable(&mut self.udc, self.speed);
+                }
+                self.state = GadgetState::Configured;
+                info!("Configured at {:?}", self.speed);
+            }
+            _ => return Outcome::Stall,
+        }
+        Outcome::Ack
+    }
+
+    /// 停用所有功能的端点，状态回到 Address
+    fn deconfigure(&mut self) {
+        if self.state != GadgetState::Configured {
+            return;
+        }
+        for function in &mut self.functions {
+            for ep in function.endpoints(self.speed) {
+                self.udc.ep_disable(ep.address);
+            }
+            function.disable();
+        }
+        self.state = GadgetState::Address;
+    }
+
+    fn get_status(&mut self, setup: &SetupPacket) -> Outcome {
+        let target = setup.index as u8;
+        let status: u16 = match setup.recipient() {
+            Recipient::Device => {
+                (self.config.self_powered as u16) | (self.remote_wakeup_enabled as u16) << 1
+            }
+            Recipient::Interface if self.ids.interface_owner(target).is_some() => 0,
+            Recipient::Endpoint if self.endpoint_valid(target) => {
+                self.udc.ep_is_halted(target) as u16
+            }
+            _ => return Outcome::Stall,
+        };
+        self.reply_with(&status.to_le_bytes())
+    }
+
+    fn set_feature(&mut self, setup: &SetupPacket, set: bool) -> Outcome {
+        let target = setup.index as u8;
+        match setup.recipient() {
+            Recipient::Device if setup.value == StandardFeature::DeviceRemoteWakeup as u16 => {
+                if !self.config.remote_wakeup {
+                    return Outcome::Stall;
+                }
+                self.remote_wakeup_enabled = set;
+                Outcome::Ack
+            }
+            // U1/U2 使能由控制器自行处理
+            Recipient::Device if is_super_speed(self.speed) && matches!(setup.value, 48 | 49) => {
+                Outcome::Ack
+            }
+            // 功能挂起，接口只有一个功能时无需区分
+            Recipient::Interface if self.ids.interface_owner(target).is_some() => Outcome::Ack,
+            Recipient::Endpoint
+                if setup.value == StandardFeature::EndpointHalt as u16
+                    && self.endpoint_valid(target) =>
+            {
+                match self.udc.ep_set_halt(target, set) {
+                    Ok(()) => Outcome::Ack,
+                    Err(_) => Outcome::Stall,
+                }
+            }
+            _ => Outcome::Stall,
+        }
+    }
+
+    /// 端点 0 始终有效，其余端点只在配置后有效
+    fn endpoint_valid(&self, ep: u8) -> bool {
+        ep & 0x7f == 0
+            || (self.state == GadgetState::Configured && self.ids.endpoint_owner(ep).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{collections::BTreeSet, vec, vec::Vec};
+
+    use usb_if::descriptor::EndpointType;
+    use usb_if::transfer::Direction;
+
+    use super::*;
+    use crate::udc::EndpointConfig;
+
+    #[derive(Default)]
+    struct FakeUdc {
+        events: Vec<UdcEvent>,
+        replies: Vec<Result<Vec<u8>, bool>>,
+        address: u8,
+        enabled: BTreeSet<u8>,
+        halted: BTreeSet<u8>,
+    }
+
+    impl UdcOp for FakeUdc {
+        fn start(&mut self) -> Result<(), USBError> {
+            Ok(())
+        }
+
+        fn stop(&mut self) {}
+
+        fn poll_event(&mut self) -> Option<UdcEvent> {
+            (!self.events.is_empty()).then(|| self.events.remove(0))
+        }
+
+        /// 数据记为 `Ok`，应答记为 `Err(true)`，STALL 记为 `Err(false)`
+        fn control_reply(&mut self, reply: ControlReply<'_>) {
+            self.replies.push(match reply {
+                ControlReply::Data(data) => Ok(data.to_vec()),
+                ControlReply::Ack => Err(true),
+                ControlReply::Stall => Err(false),
+            });
+        }
+
+        fn set_address(&mut self, address: u8) {
+            self.address = address;
+        }
+
+        fn ep_enable(&mut self, config: &EndpointConfig) -> Result<(), USBError> {
+            self.enabled.insert(config.address);
+            Ok(())
+        }
+
+        fn ep_disable(&mut self, ep: u8) {
+            self.enabled.remove(&ep);
+        }
+
+        fn ep_set_halt(&mut self, ep: u8, halt: bool) -> Result<(), USBError> {
+            if halt {
+                self.halted.insert(ep);
+            } else {
+                self.halted.remove(&ep);
+            }
+            Ok(())
+        }
+
+        fn ep_is_halted(&self, ep: u8) -> bool {
+            self.halted.contains(&ep)
+        }
+
+        fn ep_busy(&self, _ep: u8) -> bool {
+            false
+        }
+
+        fn ep_write(&mut self, _ep: u8, data: &[u8]) -> Result<usize, USBError> {
+            Ok(data.len())
+        }
+
+        fn ep_read(&mut self, _ep: u8, _buf: &mut [u8]) -> Result<Option<usize>, USBError> {
+            Ok(None)
+        }
+    }
+
+    /// 一个接口、一个批量 IN 端点，厂商请求回显 wValue
+    #[derive(Default)]
+    struct Loopback {
+        interface: u8,
+        ep: u8,
+        enabled: bool,
+        reply: [u8; 2],
+    }
+
+    impl Function for Loopback {
+        fn bind(&mut self, ids: &mut IdAllocator) -> Result<(), USBError> {
+            self.interface = ids.interface()?;
+            self.ep = ids.endpoint(Direction::In)?;
+            Ok(())
+        }
+
+        fn endpoints(&self, speed: Speed) -> Vec<EndpointConfig> {
+            vec![EndpointConfig {
+                address: self.ep,
+                transfer_type: EndpointType::Bulk,
+                max_packet_size: descriptor::bulk_max_packet(speed),
+                interval: 0,
+                max_burst: 0,
+            }]
+        }
+
+        fn write_descriptors(&self, speed: Speed, out: &mut Vec<u8>) {
+            descriptor::interface(out, self.interface, 0, 1, (0xff, 0, 0));
+            for ep in self.endpoints(speed) {
+                descriptor::endpoint(out, &ep, speed);
+            }
+        }
+
+        fn enable(&mut self, _udc: &mut dyn UdcOp, _speed: Speed) {
+            self.enabled = true;
+        }
+
+        fn disable(&mut self) {
+            self.enabled = false;
+        }
+
+        fn setup(&mut self, setup: &SetupPacket, _data: &[u8]) -> ControlReply<'_> {
+            self.reply = setup.value.to_le_bytes();
+            ControlReply::Data(&self.reply)
+        }
+    }
+
+    fn setup(raw: [u8; 8]) -> UdcEvent {
+        UdcEvent::Setup {
+            setup: SetupPacket::from_bytes(raw),
+            data: Vec::new(),
+        }
+    }
+
+    fn enumerate(speed: Speed) -> (Gadget<FakeUdc>, FunctionId) {
+        let mut gadget = Gadget::new(FakeUdc::default(), GadgetConfig::default());
+        let id = gadget.add_function(Loopback::default()).unwrap();
+        gadget.start().unwrap();
+        gadget.udc().events = vec![
+            UdcEvent::Reset,
+            UdcEvent::Connected(speed),
+            setup([0x80, 6, 0, 1, 0, 0, 64, 0]),
+            setup([0x00, 5, 7, 0, 0, 0, 0, 0]),
+            setup([0x80, 6, 0, 2, 0, 0, 0xff, 0]),
+            setup([0x00, 9, 1, 0, 0, 0, 0, 0]),
+        ];
+        gadget.poll();
+        (gadget, id)
+    }
+
+    #[test]
+    fn enumerate_high_speed() {
+        let (mut gadget, id) = enumerate(Speed::High);
+        assert_eq!(gadget.state(), GadgetState::Configured);
+        assert_eq!(gadget.udc().address, 7);
+        assert!(gadget.udc().enabled.contains(&0x81));
+        assert!(gadget.function_mut::<Loopback>(id).unwrap().enabled);
+
+        let replies = core::mem::take(&mut gadget.udc().replies);
+        let device = replies[0].as_ref().unwrap();
+        assert_eq!(device[..8], [18, 1, 0x00, 0x02, 0xef, 2, 1, 64]);
+        assert_eq!(replies[1], Err(true));
+        let config = replies[2].as_ref().unwrap();
+        assert_eq!(config.len(), 9 + 9 + 7);
+        assert_eq!(config[4], 1);
+        // 高速批量端点的最大包长
+        assert_eq!(config[22..24], [0x00, 0x02]);
+        assert_eq!(replies[3], Err(true));
+    }
+
+    #[test]
+    fn super_speed_descriptors() {
+        let (mut gadget, _) = enumerate(Speed::SuperSpeed);
+        gadget.udc().events = vec![
+            setup([0x80, 6, 0, 6, 0, 0, 10, 0]),
+            setup([0x80, 6, 0, 0x0f, 0, 0, 5, 0]),
+        ];
+        gadget.poll();
+        let replies = &gadget.udc().replies;
+        assert_eq!(
+            replies[0].as_ref().unwrap()[..8],
+            [18, 1, 0x20, 0x03, 0xef, 2, 1, 9]
+        );
+        // 端点描述符后带伴随描述符
+        assert_eq!(replies[2].as_ref().unwrap().len(), 9 + 9 + 7 + 6);
+        assert_eq!(replies[4], Err(false));
+        assert_eq!(replies[5].as_ref().unwrap()[..5], [5, 0x0f, 22, 0, 2]);
+    }
+
+    #[test]
+    fn dispatch_and_halt() {
+        let (mut gadget, id) = enumerate(Speed::High);
+        gadget.udc().replies.clear();
+        gadget.udc().events = vec![
+            // 厂商请求发给接口 0
+            setup([0xc1, 0x01, 0x34, 0x12, 0, 0, 2, 0]),
+            // 发给不存在的接口
+            setup([0xc1, 0x01, 0, 0, 5, 0, 2, 0]),
+            setup([0x02, 3, 0, 0, 0x81, 0, 0, 0]),
+            setup([0x82, 0, 0, 0, 0x81, 0, 2, 0]),
+            setup([0x02, 1, 0, 0, 0x81, 0, 0, 0]),
+            setup([0x81, 10, 0, 0, 0, 0, 1, 0]),
+            setup([0x01, 11, 1, 0, 0, 0, 0, 0]),
+        ];
+        gadget.poll();
+        let replies = core::mem::take(&mut gadget.udc().replies);
+        assert_eq!(replies[0], Ok(vec![0x34, 0x12]));
+        assert_eq!(replies[1], Err(false));
+        assert_eq!(replies[2], Err(true));
+        assert_eq!(replies[3], Ok(vec![1, 0]));
+        assert_eq!(replies[4], Err(true));
+        assert!(gadget.udc().halted.is_empty());
+        assert_eq!(replies[5], Ok(vec![0]));
+        assert_eq!(replies[6], Err(false));
+
+        gadget.udc().events = vec![UdcEvent::Reset];
+        gadget.poll();
+        assert_eq!(gadget.state(), GadgetState::Default);
+        assert!(gadget.udc().enabled.is_empty());
+        assert!(!gadget.function_mut::<Loopback>(id).unwrap().enabled);
+        assert!(matches!(
+            gadget.add_function(Loopback::default()),
+            Err(USBError::InvalidParameter)
+        ));
+    }
+}
@@ -0,0 +1,46 @@
+//! CrabUSB 设备（Gadget）模式协议栈
+//!
+//! 让板卡作为外设接入另一台主机，分为三层：
+//!
+//! - [`UdcOp`]：设备控制器驱动，负责端点 0 的控制传输阶段与其他端点上的数据传输，
+//!   目前实现了 DWC3（[`Dwc3Udc`]）；
+//! - [`Gadget`]：处理枚举过程中的标准请求，生成设备、配置与字符串描述符；
+//! - [`Function`]：复合设备中的功能，例如 CDC ACM 串口（[`CdcAcm`]）。
+//!
+//! 与 xHCI DbC 一样以轮询方式工作，不依赖中断与异步执行器：
+//!
+//! ```ignore
+//! let udc = Dwc3Udc::new(mmio, &KERNEL)?;
+//! let mut gadget = Gadget::new(udc, GadgetConfig::default());
+//! let acm = gadget.add_function(CdcAcm::new())?;
+//! gadget.start()?;
+//! loop {
+//!     gadget.poll();
+//!     let serial = gadget.function_mut::<CdcAcm>(acm).unwrap();
+//!     let n = serial.read(&mut buf);
+//!     serial.write(&buf[..n]);
+//! }
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+#[macro_use]
+extern crate log;
+
+mod cdc_acm;
+pub mod descriptor;
+mod dwc3;
+mod function;
+mod gadget;
+mod setup;
+mod udc;
+
+pub use cdc_acm::{CdcAcm, LineCoding};
+pub use dwc3::{Dwc3Config, Dwc3Udc};
+pub use function::{Function, FunctionId, IdAllocator};
+pub use gadget::{Gadget, GadgetConfig, GadgetState};
+pub use setup::SetupPacket;
+pub use udc::{ControlReply, EndpointConfig, UdcEvent, UdcOp};
+pub use usb_if::Speed;
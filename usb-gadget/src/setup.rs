@@ -0,0 +1,72 @@
+use usb_if::transfer::{Direction, Recipient, Request, RequestType};
+
+/// 主机发来的 SETUP 包（USB 2.0 9.3）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetupPacket {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl SetupPacket {
+    pub fn from_bytes(raw: [u8; 8]) -> Self {
+        Self {
+            request_type: raw[0],
+            request: raw[1],
+            value: u16::from_le_bytes([raw[2], raw[3]]),
+            index: u16::from_le_bytes([raw[4], raw[5]]),
+            length: u16::from_le_bytes([raw[6], raw[7]]),
+        }
+    }
+
+    /// 数据阶段方向，没有数据阶段的请求按 OUT 处理
+    pub fn direction(&self) -> Direction {
+        Direction::from_address(self.request_type)
+    }
+
+    pub fn kind(&self) -> RequestType {
+        match (self.request_type >> 5) & 0b11 {
+            0 => RequestType::Standard,
+            1 => RequestType::Class,
+            2 => RequestType::Vendor,
+            _ => RequestType::Reserved,
+        }
+    }
+
+    /// 保留的接收者编码按 [`Recipient::Other`] 处理
+    pub fn recipient(&self) -> Recipient {
+        match self.request_type & 0x1f {
+            0 => Recipient::Device,
+            1 => Recipient::Interface,
+            2 => Recipient::Endpoint,
+            _ => Recipient::Other,
+        }
+    }
+
+    pub fn standard_request(&self) -> Request {
+        Request::from(self.request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_get_descriptor() {
+        let setup = SetupPacket::from_bytes([0x80, 6, 0x00, 0x02, 0, 0, 0xff, 0]);
+        assert_eq!(setup.direction(), Direction::In);
+        assert!(matches!(setup.kind(), RequestType::Standard));
+        assert!(matches!(setup.recipient(), Recipient::Device));
+        assert!(matches!(setup.standard_request(), Request::GetDescriptor));
+        assert_eq!((setup.value, setup.index, setup.length), (0x0200, 0, 255));
+
+        // CDC SET_LINE_CODING，发给接口 2
+        let setup = SetupPacket::from_bytes([0x21, 0x20, 0, 0, 2, 0, 7, 0]);
+        assert_eq!(setup.direction(), Direction::Out);
+        assert!(matches!(setup.kind(), RequestType::Class));
+        assert!(matches!(setup.recipient(), Recipient::Interface));
+    }
+}
@@ -0,0 +1,98 @@
+use alloc::vec::Vec;
+
+use usb_if::{Speed, descriptor::EndpointType, err::USBError, transfer::Direction};
+
+use crate::setup::SetupPacket;
+
+/// 非控制端点的配置，同时用于生成端点描述符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndpointConfig {
+    /// 端点地址，最高位为方向
+    pub address: u8,
+    pub transfer_type: EndpointType,
+    pub max_packet_size: u16,
+    /// 描述符中的 bInterval，编码随速度不同
+    pub interval: u8,
+    /// SuperSpeed 每次突发的包数减 1，低速率下忽略
+    pub max_burst: u8,
+}
+
+impl EndpointConfig {
+    pub fn direction(&self) -> Direction {
+        Direction::from_address(self.address)
+    }
+}
+
+/// 对控制请求的应答
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlReply<'a> {
+    /// IN 数据阶段，超出 wLength 的部分被截断
+    Data(&'a [u8]),
+    /// 请求已处理，完成状态阶段；OUT 数据阶段的数据此前已随 SETUP 交付
+    Ack,
+    /// 请求不受支持或参数错误
+    Stall,
+}
+
+/// 设备控制器报告的事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UdcEvent {
+    /// 总线复位，设备回到地址 0、未配置状态
+    Reset,
+    /// 复位完成，携带与主机协商出的速度
+    Connected(Speed),
+    Disconnected,
+    Suspend,
+    Resume,
+    /// 控制请求，OUT 数据阶段的数据已接收在 `data` 中；须以
+    /// [`UdcOp::control_reply`] 回应
+    Setup {
+        setup: SetupPacket,
+        data: Vec<u8>,
+    },
+    /// 非控制端点上的传输完成，`ep` 为端点地址
+    TransferComplete {
+        ep: u8,
+    },
+}
+
+/// 设备控制器（UDC）驱动
+///
+/// 以轮询方式工作：[`UdcOp::poll_event`] 处理控制器事件并返回需要上层处理的事件。
+/// 每个端点同一时刻只有一个传输，数据经驱动自有的 DMA 缓冲区中转。
+pub trait UdcOp {
+    /// 初始化控制器并连接到总线
+    fn start(&mut self) -> Result<(), USBError>;
+
+    /// 断开总线连接并停止控制器
+    fn stop(&mut self);
+
+    fn poll_event(&mut self) -> Option<UdcEvent>;
+
+    /// 回应最近一次 [`UdcEvent::Setup`]
+    fn control_reply(&mut self, reply: ControlReply<'_>);
+
+    /// 设置设备地址，须在 SET_ADDRESS 的状态阶段之前调用
+    fn set_address(&mut self, address: u8);
+
+    /// 启用端点，OUT 端点随即开始接收
+    fn ep_enable(&mut self, config: &EndpointConfig) -> Result<(), USBError>;
+
+    /// 停用端点并丢弃未完成的传输
+    fn ep_disable(&mut self, ep: u8);
+
+    fn ep_set_halt(&mut self, ep: u8, halt: bool) -> Result<(), USBError>;
+
+    fn ep_is_halted(&self, ep: u8) -> bool;
+
+    /// 端点上是否有未完成的传输
+    fn ep_busy(&self, ep: u8) -> bool;
+
+    /// 在 IN 端点上发送，端点忙时返回 0，否则返回被接受的字节数
+    fn ep_write(&mut self, ep: u8, data: &[u8]) -> Result<usize, USBError>;
+
+    /// 取出 OUT 端点上已完成传输的数据并重新开始接收，没有已完成的传输时返回 `None`
+    ///
+    /// `buf` 短于收到的数据时多出的部分被丢弃。
+    fn ep_read(&mut self, ep: u8, buf: &mut [u8]) -> Result<Option<usize>, USBError>;
+}
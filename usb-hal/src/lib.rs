@@ -0,0 +1,43 @@
+//! CrabUSB 的 OS 抽象层
+//!
+//! 只包含内核需要实现的 trait 与 DMA 相关类型，内核的 HAL 层依赖本 crate 即可实现
+//! [`KernelOp`]，无需引入整个主机协议栈。`crab-usb` 会原样重新导出这些定义。
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::{future::Future, pin::Pin, task::Waker, time::Duration};
+
+pub use dma_api::{DmaAddr, DmaDirection, DmaError, DmaHandle, DmaMapHandle, DmaOp};
+
+pub trait KernelOp: DmaOp {
+    fn delay(&self, duration: Duration);
+
+    /// 单调时钟，返回自启动以来经过的时间
+    ///
+    /// 会在中断处理中调用，须直接读取硬件计数器，不能等待。
+    fn now(&self) -> Duration;
+
+    /// [`KernelOp::now`] 到达 `deadline` 后唤醒 `waker`，`deadline` 已过时立即唤醒
+    ///
+    /// 周期端点的提交间隔、请求超时与重试退避用它等待，内核应以定时器实现。
+    /// 默认实现立即唤醒，等待方退化为反复轮询时钟。
+    fn wake_at(&self, deadline: Duration, waker: &Waker) {
+        let _ = deadline;
+        waker.wake_by_ref();
+    }
+}
+
+/// 交给 [`Spawner`] 运行的后台任务
+pub type Task = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// 后台任务的执行器
+///
+/// 看门狗、自动重提交等需要持续运行的功能通过它启动任务，而不是自行创建线程或要求
+/// 调用者逐个轮询。桌面平台可使用 tokio，裸机上由内核的执行器实现。
+pub trait Spawner: Send + Sync {
+    /// 在后台运行 `task` 直到完成
+    fn spawn(&self, task: Task);
+}
@@ -1,11 +1,27 @@
 fn main() {
     println!("cargo::rustc-check-cfg=cfg(umod)");
     println!("cargo::rustc-check-cfg=cfg(kmod)");
+    println!("cargo::rustc-check-cfg=cfg(nmod)");
+    println!("cargo::rustc-check-cfg=cfg(mmod)");
 
     let os = std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
-    if os == "none" {
+    let libusb = std::env::var("CARGO_FEATURE_LIBUSB").is_ok();
+    // 与 libusb 同时启用时由 lib.rs 报错，这里不再启用 kmod 以免产生大量无关错误
+    let vfio = os == "linux" && std::env::var("CARGO_FEATURE_VFIO").is_ok() && !libusb;
+    if os == "none" || vfio {
         println!("cargo::rustc-cfg=kmod");
-    } else if std::env::var("CARGO_FEATURE_LIBUSB").is_ok() {
+    }
+    if os != "none" && libusb {
         println!("cargo::rustc-cfg=umod");
     }
+    // nusb 同样与 libusb、vfio 互斥
+    let nusb = std::env::var("CARGO_FEATURE_NUSB").is_ok();
+    if os != "none" && nusb && !libusb && !vfio {
+        println!("cargo::rustc-cfg=nmod");
+    }
+    // 模拟后端可与 libusb、nusb 共存，与 vfio 互斥
+    let mock = std::env::var("CARGO_FEATURE_MOCK").is_ok();
+    if os != "none" && mock && !vfio {
+        println!("cargo::rustc-cfg=mmod");
+    }
 }
@@ -1,6 +1,6 @@
 use dma_api::{DArray, DmaDirection};
 
-use crate::osal::Kernel;
+use crate::{backend::kmod::mem::MemTag, osal::Kernel};
 
 pub struct EventBuffer {
     pub buffer: DArray<u8>,
@@ -12,6 +12,7 @@ impl EventBuffer {
         //     .map_err(|_| crate::err::USBError::NoMemory)?;
 
         let buffer = kernel
+            .with_tag(MemTag::EventBuffer)
             .array_zero_with_align(size, 0x1000, DmaDirection::FromDevice)
             .map_err(|_| crate::err::USBError::NoMemory)?;
 
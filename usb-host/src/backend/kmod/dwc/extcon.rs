@@ -0,0 +1,95 @@
+//! USB2 PHY VBUS/ID 状态检测
+//!
+//! RK3588 USB2PHY GRF 的 `utmi_bvalid` / `utmi_iddig` 位反映 Type-C / Micro-AB
+//! 接口上的 VBUS 与 ID 引脚状态，等价于 Linux 中 extcon 的 `EXTCON_USB` 与
+//! `EXTCON_USB_HOST`。
+//!
+//! 参考 Linux: drivers/phy/rockchip/phy-rockchip-inno-usb2.c
+//! `rockchip_usb2phy_otg_sm_work()`
+
+use usb_if::DrMode;
+
+use super::usb2phy::Usb2PhyGrfReg;
+use super::{consts::genmask, udphy::regmap::Regmap, usb2phy::Usb2PhyPortCfg};
+
+/// VBUS/ID 状态快照
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExtconState {
+    /// 检测到外部主机提供的 VBUS（B-device 会话有效）
+    pub vbus: bool,
+    /// ID 引脚接地（OTG 线缆 A 端插入，本机应作为主机）
+    pub id_grounded: bool,
+}
+
+impl ExtconState {
+    /// 根据 VBUS/ID 推导当前应处的数据角色
+    ///
+    /// ID 接地时作为主机；否则检测到 VBUS 时作为设备；两者都没有时返回 `None`，
+    /// 表示没有线缆连接，由调用者保持当前角色。
+    pub fn role(&self) -> Option<DrMode> {
+        if self.id_grounded {
+            Some(DrMode::Host)
+        } else if self.vbus {
+            Some(DrMode::Peripheral)
+        } else {
+            None
+        }
+    }
+}
+
+/// OTG 端口 VBUS/ID 监视器
+///
+/// 不依赖中断，由调用者周期性调用 [`Usb2PhyExtcon::poll`] 获取状态变化。
+#[derive(Clone)]
+pub struct Usb2PhyExtcon {
+    grf: Regmap,
+    bvalid: Usb2PhyGrfReg,
+    iddig: Option<Usb2PhyGrfReg>,
+    last: Option<ExtconState>,
+}
+
+impl Usb2PhyExtcon {
+    pub(crate) fn new(grf: Regmap, port_cfg: &Usb2PhyPortCfg) -> Self {
+        // 未配置 IDDIG 的 PHY（offset 为 0）只能检测 VBUS
+        let iddig = (port_cfg.utmi_iddig.offset != 0).then(|| port_cfg.utmi_iddig.clone());
+        Self {
+            grf,
+            bvalid: port_cfg.utmi_bvalid.clone(),
+            iddig,
+            last: None,
+        }
+    }
+
+    fn property_read(&self, reg: &Usb2PhyGrfReg) -> bool {
+        let mask = genmask(reg.bitend, reg.bitstart) as u32;
+        let value = (self.grf.reg_read(reg.offset) & mask) >> reg.bitstart;
+        value == reg.enable
+    }
+
+    /// 读取当前 VBUS/ID 状态
+    pub fn state(&self) -> ExtconState {
+        let vbus = self.property_read(&self.bvalid);
+        // IDDIG 为 0 表示 ID 引脚接地
+        let id_grounded = self
+            .iddig
+            .as_ref()
+            .is_some_and(|reg| !self.property_read(reg));
+        ExtconState { vbus, id_grounded }
+    }
+
+    /// 状态与上次调用相比发生变化时返回新状态，首次调用总是返回当前状态
+    pub fn poll(&mut self) -> Option<ExtconState> {
+        let state = self.state();
+        if self.last == Some(state) {
+            return None;
+        }
+        debug!(
+            "USB2PHY extcon: vbus={}, id_grounded={}, role={:?}",
+            state.vbus,
+            state.id_grounded,
+            state.role()
+        );
+        self.last = Some(state);
+        Some(state)
+    }
+}
@@ -16,14 +16,18 @@ use tock_registers::interfaces::*;
 pub use usb_if::DrMode;
 use usb_if::Speed;
 
+use crate::backend::kmod::{PerfCounters, SelfTestReport, mem::MemTag};
 use crate::backend::ty::Event;
 use crate::backend::{
-    kmod::{hub::HubOp, kcore::CoreOp, xhci::Xhci},
+    kmod::{
+        hub::HubOp,
+        kcore::CoreOp,
+        xhci::{EnumerationMode, Xhci, XhciConfig},
+    },
     ty::{DeviceOp, EventHandlerOp},
 };
 use crate::osal::Kernel;
 use crate::{DeviceAddressInfo, KernelOp, Mmio};
-use reg::GUSB2PHYCFG;
 use {
     event::EventBuffer,
     reg::{GCTL, GHWPARAMS0, GHWPARAMS1, GHWPARAMS3, GHWPARAMS4, GUCTL1},
@@ -33,6 +37,7 @@ use {
 use crate::err::{Result, USBError};
 use reg::GEVNTSIZ;
 
+use extcon::Usb2PhyExtcon;
 use usb2phy::Usb2Phy;
 pub use usb2phy::Usb2PhyParam;
 
@@ -48,6 +53,7 @@ pub enum UsbPhyInterfaceMode {
     UtmiWide,
 }
 
+pub mod extcon;
 pub mod grf;
 // pub mod phy;
 mod consts;
@@ -58,7 +64,7 @@ pub mod usb2phy;
 
 // pub use phy::{UsbDpMode, UsbDpPhy, UsbDpPhyConfig};
 use consts::*;
-use reg::Dwc3Regs;
+pub use reg::Dwc3Regs;
 pub use udphy::UdphyParam;
 // pub use usb2phy::Usb2Phy;
 
@@ -114,6 +120,8 @@ pub struct DwcParams {
     pub tx_de_emphasis_quirk: bool,
     pub tx_de_emphasis: u8,        // 2 bits
     pub usb2_phyif_utmi_width: u8, // 5 bits
+    /// 内部 xHCI 控制器配置
+    pub xhci: XhciConfig,
 }
 
 /// DWC3 控制器
@@ -140,7 +148,7 @@ impl Dwc {
         let mmio_base = params.ctrl.as_ptr() as usize;
         params.params.max_speed = Speed::Full;
         let cru = Arc::new(params.cru);
-        let xhci = Xhci::new(params.ctrl, params.kernel)?;
+        let xhci = Xhci::new_with_config(params.ctrl, params.kernel, params.params.xhci.clone())?;
 
         let phy = Udphy::new(params.phy, cru.clone(), params.phy_param);
         let usb2_phy = Usb2Phy::new(cru.clone(), params.usb2_phy_param, xhci.kernel().clone());
@@ -261,21 +269,16 @@ impl Dwc {
         self.dwc_regs.core_soft_reset(self.kernel()).await;
 
         // **关键调试：检查 PHY 软复位后的寄存器状态**
-        let gusb3_val = self.dwc_regs.globals().gusb3pipectl0.extract();
-        let gusb2_val = self.dwc_regs.globals().gusb2phycfg0.extract();
         info!(
             "DWC3: After core_soft_reset - GUSB3PIPECTL={:#010x}, GUSB2PHYCFG={:#010x}",
-            gusb3_val.get(),
-            gusb2_val.get()
+            self.dwc_regs.usb3_pipe_ctl(),
+            self.dwc_regs.usb2_phy_cfg()
         );
 
         // **关键修复：在初始化开始时清除 suspendusb20 位（RK3588 TRM 要求）**
         // TRM 明确说明：如果此位为 1，应用程序必须在 power-on reset 后清除此位
         info!("DWC3: Clearing suspendusb20 bit (TRM requirement)");
-        self.dwc_regs
-            .globals()
-            .gusb2phycfg0
-            .modify(GUSB2PHYCFG::SUSPHY::Disable);
+        self.dwc_regs.set_suspend_usb2_phy(false);
         if self.revistion >= DWC3_REVISION_250A {
             debug!("DWC3: Revision 250A or later detected");
 
@@ -353,29 +356,8 @@ impl Dwc {
     /// - Utmi: 8-bit UTMI 接口 (USBTRDTIM=9, PHYIF=0)
     /// - UtmiWide: 16-bit UTMI 接口 (USBTRDTIM=5, PHYIF=1)
     fn hsphy_mode_setup(&mut self) {
-        use reg::GUSB2PHYCFG;
-
-        match self.hsphy_mode {
-            UsbPhyInterfaceMode::Utmi => {
-                // 8-bit UTMI 接口
-                self.dwc_regs.globals().gusb2phycfg0.modify(
-                    GUSB2PHYCFG::PHYIF.val(0) + // UTMI_PHYIF_8_BIT
-                    GUSB2PHYCFG::USBTRDTIM.val(9), // USBTRDTIM_UTMI_8_BIT
-                );
-                debug!("DWC3: HS PHY configured as UTMI 8-bit");
-            }
-            UsbPhyInterfaceMode::UtmiWide => {
-                // 16-bit UTMI 接口
-                self.dwc_regs.globals().gusb2phycfg0.modify(
-                    GUSB2PHYCFG::PHYIF.val(1) + // UTMI_PHYIF_16_BIT
-                    GUSB2PHYCFG::USBTRDTIM.val(5), // USBTRDTIM_UTMI_16_BIT
-                );
-                debug!("DWC3: HS PHY configured as UTMI 16-bit");
-            }
-            UsbPhyInterfaceMode::Unknown => {
-                debug!("DWC3: HS PHY mode unknown, using default configuration");
-            }
-        }
+        self.dwc_regs.set_usb2_phy_interface(self.hsphy_mode);
+        debug!("DWC3: HS PHY configured as {:?}", self.hsphy_mode);
     }
 
     async fn phy_setup(&mut self) -> Result<()> {
@@ -393,10 +375,9 @@ impl Dwc {
 
         // === USB3 PHY 配置 ===
         // **关键：读取当前寄存器值（保留硬件状态）**
-        let gusb3_init = self.dwc_regs.globals().gusb3pipectl0.extract();
         info!(
             "DWC3: Initial GUSB3PIPECTL = {:#010x} before config",
-            gusb3_init.get()
+            self.dwc_regs.usb3_pipe_ctl()
         );
 
         let mut gusb3 = self.dwc_regs.globals().gusb3pipectl0.extract();
@@ -462,10 +443,9 @@ impl Dwc {
 
         // === USB2 PHY 配置 ===
         // **关键：读取当前寄存器值（保留硬件状态）**
-        let gusb2_init = self.dwc_regs.globals().gusb2phycfg0.extract();
         info!(
             "DWC3: Initial GUSB2PHYCFG = {:#010x} before config",
-            gusb2_init.get()
+            self.dwc_regs.usb2_phy_cfg()
         );
 
         let mut gusb2 = self.dwc_regs.globals().gusb2phycfg0.extract();
@@ -523,6 +503,7 @@ impl Dwc {
 
         let scratchbuf = self
             .kernel()
+            .with_tag(MemTag::Scratchpad)
             .array_zero_with_align(
                 scratch_size,
                 self.kernel().page_size(),
@@ -557,12 +538,26 @@ impl Dwc {
         match self.dr_mode {
             DrMode::Host => {
                 info!("DWC3: Initializing in HOST mode");
-                self.dwc_regs.globals().gctl.modify(GCTL::PRTCAPDIR::Host);
+                self.dwc_regs.set_port_capability(DrMode::Host);
             }
             DrMode::Otg => {
-                todo!()
+                // 根据 VBUS/ID 状态决定初始角色，未连接线缆时默认作为主机
+                let state = self.usb2_phy.extcon().state();
+                match state.role() {
+                    Some(DrMode::Peripheral) => {
+                        warn!("DWC3: OTG port attached to a host, device mode not supported");
+                        return Err(USBError::NotSupported);
+                    }
+                    role => {
+                        info!("DWC3: Initializing OTG port in HOST mode ({role:?})");
+                        self.dwc_regs.set_port_capability(DrMode::Host);
+                    }
+                }
+            }
+            DrMode::Peripheral => {
+                warn!("DWC3: peripheral mode is handled by crab-usb-gadget");
+                return Err(USBError::NotSupported);
             }
-            DrMode::Peripheral => todo!(),
         }
 
         Ok(())
@@ -734,6 +729,46 @@ impl CoreOp for Dwc {
     fn kernel(&self) -> &Kernel {
         self.xhci.kernel()
     }
+
+    fn perf_counters(&self) -> PerfCounters {
+        self.xhci.perf_counters()
+    }
+
+    fn self_test(&mut self, count: u32) -> BoxFuture<'_, Result<SelfTestReport>> {
+        self.xhci.self_test(count)
+    }
+
+    fn eject_slot(&mut self, slot_id: u8, power_off_port: Option<u8>) -> BoxFuture<'_, Result<()>> {
+        self.xhci.eject_slot(slot_id, power_off_port)
+    }
+
+    fn enumeration_mode(&self) -> EnumerationMode {
+        self.xhci.enumeration_mode()
+    }
+
+    fn frame_index(&self) -> Option<u16> {
+        self.xhci.frame_index()
+    }
+
+    fn reinit_phy(&mut self) -> BoxFuture<'_, Result<()>> {
+        async {
+            info!("DWC3: Reinitializing PHY");
+            self.usb2_phy.setup().await?;
+            let kernel = self.kernel().clone();
+            self.usb3_phy.setup(&kernel).await?;
+            self.phy_setup().await
+        }
+        .boxed()
+    }
+
+    fn extcon(&self) -> Option<Usb2PhyExtcon> {
+        Some(self.usb2_phy.extcon())
+    }
+
+    #[cfg(feature = "fault-injection")]
+    fn inject_port_disable(&mut self, port: u8) -> Result<()> {
+        self.xhci.inject_port_disable(port)
+    }
 }
 
 impl Deref for Dwc {
@@ -762,4 +797,12 @@ impl EventHandlerOp for DwcEventHandler {
 
         self.xhci.handle_event()
     }
+
+    fn handle_interrupter(&self, index: u16) -> Event {
+        self.xhci.handle_interrupter(index)
+    }
+
+    fn interrupter_count(&self) -> u16 {
+        self.xhci.interrupter_count()
+    }
 }
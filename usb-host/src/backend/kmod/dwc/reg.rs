@@ -6,7 +6,10 @@ use core::time::Duration;
 use tock_registers::interfaces::*;
 use tock_registers::{register_bitfields, register_structs, registers::*};
 
+use usb_if::DrMode;
+
 use super::super::osal::Kernel;
+use super::UsbPhyInterfaceMode;
 use crate::osal::SpinWhile;
 
 /// DWC3 全局寄存器基址偏移 (相对于 xHCI 寄存器区域)
@@ -882,6 +885,91 @@ impl Dwc3Regs {
         self.globals().gsnpsid.read(GSNPSID::REVISION) << 16
     }
 
+    /// GUSB3PIPECTL 原始值，用于调试输出
+    pub fn usb3_pipe_ctl(&self) -> u32 {
+        self.globals().gusb3pipectl0.get()
+    }
+
+    /// GUSB2PHYCFG 原始值，用于调试输出
+    pub fn usb2_phy_cfg(&self) -> u32 {
+        self.globals().gusb2phycfg0.get()
+    }
+
+    /// GUSB3PIPECTL.SUSPHY：允许 USB3 PHY 进入低功耗暂停
+    pub fn set_suspend_usb3_phy(&self, suspend: bool) {
+        self.globals().gusb3pipectl0.modify(if suspend {
+            GUSB3PIPECTL::SUSPHY::Enable
+        } else {
+            GUSB3PIPECTL::SUSPHY::Disable
+        });
+    }
+
+    /// GUSB2PHYCFG.SUSPHY：允许 USB2 PHY 进入低功耗暂停
+    pub fn set_suspend_usb2_phy(&self, suspend: bool) {
+        self.globals().gusb2phycfg0.modify(if suspend {
+            GUSB2PHYCFG::SUSPHY::Enable
+        } else {
+            GUSB2PHYCFG::SUSPHY::Disable
+        });
+    }
+
+    /// GUSB3PIPECTL.PHYSOFTRST：保持或释放 USB3 PHY 复位
+    pub fn set_usb3_phy_reset(&self, reset: bool) {
+        self.globals().gusb3pipectl0.modify(if reset {
+            GUSB3PIPECTL::PHYSOFTRST::Reset
+        } else {
+            GUSB3PIPECTL::PHYSOFTRST::Normal
+        });
+    }
+
+    /// GUSB2PHYCFG.PHYSOFTRST：保持或释放 USB2 PHY 复位
+    pub fn set_usb2_phy_reset(&self, reset: bool) {
+        self.globals().gusb2phycfg0.modify(if reset {
+            GUSB2PHYCFG::PHYSOFTRST::Reset
+        } else {
+            GUSB2PHYCFG::PHYSOFTRST::Normal
+        });
+    }
+
+    /// GCTL.CORESOFTRESET：保持或释放核心复位
+    pub fn set_core_reset(&self, reset: bool) {
+        self.globals().gctl.modify(if reset {
+            GCTL::CORESOFTRESET::Reset
+        } else {
+            GCTL::CORESOFTRESET::Normal
+        });
+    }
+
+    /// GUSB2PHYCFG.PHYIF / USBTRDTIM：按 UTMI 数据宽度设置接口与周转时间，`Unknown` 不修改
+    pub fn set_usb2_phy_interface(&self, mode: UsbPhyInterfaceMode) {
+        let value = match mode {
+            UsbPhyInterfaceMode::Utmi => {
+                GUSB2PHYCFG::PHYIF::EightBit + GUSB2PHYCFG::USBTRDTIM.val(9)
+            }
+            UsbPhyInterfaceMode::UtmiWide => {
+                GUSB2PHYCFG::PHYIF::SixteenBit + GUSB2PHYCFG::USBTRDTIM.val(5)
+            }
+            UsbPhyInterfaceMode::Unknown => return,
+        };
+        self.globals().gusb2phycfg0.modify(value);
+    }
+
+    /// GUSB3PIPECTL.TX_DEEPH：USB3 发送去加重
+    pub fn set_tx_de_emphasis(&self, value: u8) {
+        self.globals()
+            .gusb3pipectl0
+            .modify(GUSB3PIPECTL::TX_DEEPH.val(value as u32));
+    }
+
+    /// GCTL.PRTCAPDIR：端口角色
+    pub fn set_port_capability(&self, mode: DrMode) {
+        self.globals().gctl.modify(match mode {
+            DrMode::Host => GCTL::PRTCAPDIR::Host,
+            DrMode::Peripheral => GCTL::PRTCAPDIR::Device,
+            DrMode::Otg => GCTL::PRTCAPDIR::OTG,
+        });
+    }
+
     pub async fn device_soft_reset(&mut self) {
         self.globals().dctl.modify(DCTL::CSFTRST::Reset);
         trace!("DWC3: Device waiting for soft reset...");
@@ -889,35 +977,26 @@ impl Dwc3Regs {
         trace!("DWC3: Device soft reset completed");
     }
 
-    pub async fn core_soft_reset(&self, kernel: &Kernel) {
+    pub(crate) async fn core_soft_reset(&self, kernel: &Kernel) {
         // Before Resetting PHY, put Core in Reset
-        self.globals().gctl.modify(GCTL::CORESOFTRESET::Reset);
+        self.set_core_reset(true);
 
         // Assert USB3 PHY reset
-        self.globals()
-            .gusb3pipectl0
-            .modify(GUSB3PIPECTL::PHYSOFTRST::Reset);
-
-        self.globals()
-            .gusb2phycfg0
-            .modify(GUSB2PHYCFG::PHYSOFTRST::Reset);
+        self.set_usb3_phy_reset(true);
+        self.set_usb2_phy_reset(true);
 
         kernel.delay(Duration::from_millis(100));
 
         // Clear USB3 PHY reset
-        self.globals()
-            .gusb3pipectl0
-            .modify(GUSB3PIPECTL::PHYSOFTRST::Normal);
+        self.set_usb3_phy_reset(false);
 
         // Clear USB2 PHY reset
-        self.globals()
-            .gusb2phycfg0
-            .modify(GUSB2PHYCFG::PHYSOFTRST::Normal);
+        self.set_usb2_phy_reset(false);
 
         kernel.delay(Duration::from_millis(100));
 
         // After PHYs are stable we can take Core out of reset state
-        self.globals().gctl.modify(GCTL::CORESOFTRESET::Normal);
+        self.set_core_reset(false);
 
         debug!("DWC3: Core soft reset completed");
     }
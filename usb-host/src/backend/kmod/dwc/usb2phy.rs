@@ -11,6 +11,7 @@ use super::super::osal::Kernel;
 use super::{
     CruOp,
     consts::genmask,
+    extcon::Usb2PhyExtcon,
     udphy::{config::UdphyGrfReg, regmap::Regmap},
 };
 use crate::{Mmio, err::Result};
@@ -61,6 +62,8 @@ pub struct Usb2PhyPortCfg {
     pub utmi_ls: Usb2PhyGrfReg,
     /// UTMI IDDIG 状态（OTG 模式，HOST 模式为 None）
     pub utmi_iddig: Usb2PhyGrfReg,
+    /// UTMI BVALID 状态（VBUS 由外部主机提供）
+    pub utmi_bvalid: Usb2PhyGrfReg,
 }
 
 impl Usb2PhyPortCfg {
@@ -202,6 +205,11 @@ impl Usb2Phy {
 
     fn power_on(&self) {}
 
+    /// 创建 OTG 端口的 VBUS/ID 状态监视器
+    pub fn extcon(&self) -> Usb2PhyExtcon {
+        Usb2PhyExtcon::new(self.grf, &self.cfg.port_cfg[Usb2PhyPortId::Otg as usize])
+    }
+
     /// 打印 USB2 PHY 关键寄存器状态（用于调试）
     pub fn dump_registers(&self) {
         info!("=== USB2 PHY Register Dump ===");
@@ -316,6 +324,7 @@ const RK3588_PHY_CFGS: &[Usb2PhyCfg] = &[
                 phy_sus: Usb2PhyGrfReg::new(0x000c, 11, 11, 0, 1),
                 utmi_ls: Usb2PhyGrfReg::new(0x00c0, 10, 9, 0, 1),
                 utmi_iddig: Usb2PhyGrfReg::new(0x00c0, 5, 5, 0, 1),
+                utmi_bvalid: Usb2PhyGrfReg::new(0x00c0, 6, 6, 0, 1),
             },
             Usb2PhyPortCfg::default(),
         ],
@@ -330,6 +339,7 @@ const RK3588_PHY_CFGS: &[Usb2PhyCfg] = &[
                 phy_sus: Usb2PhyGrfReg::new(0x000c, 11, 11, 0, 0),
                 utmi_ls: Usb2PhyGrfReg::new(0x00c0, 10, 9, 0, 1),
                 utmi_iddig: Usb2PhyGrfReg::default(),
+                utmi_bvalid: Usb2PhyGrfReg::new(0x00c0, 6, 6, 0, 1),
             },
             Usb2PhyPortCfg::default(),
         ],
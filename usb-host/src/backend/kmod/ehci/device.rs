@@ -0,0 +1,373 @@
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{sync::atomic::AtomicBool, time::Duration};
+
+use futures::{FutureExt, future::BoxFuture};
+use usb_if::{
+    descriptor::{
+        ConfigurationDescriptor, DescriptorType, DeviceDescriptor, DeviceDescriptorBase,
+        DeviceQualifierDescriptor, EndpointDescriptor, EndpointType,
+        OtherSpeedConfigurationDescriptor,
+    },
+    endpoint::EndpointInfo,
+    err::USBError,
+    host::{ControlSetup, hub::Speed},
+    transfer::{Recipient, Request, RequestType},
+};
+
+use super::{
+    endpoint::Endpoint as EhciEndpoint,
+    qtd::{QhInfo, interrupt_s_mask, interrupt_service_interval},
+    schedule::Schedule,
+};
+use crate::{
+    DeviceAddressInfo,
+    backend::ty::{DeviceOp, HubParams, ep::Endpoint},
+    err::Result,
+    osal::{DmaConfig, Kernel},
+};
+
+/// SET_ADDRESS 之后设备的恢复时间 TDSETADDR
+const SET_ADDRESS_RECOVERY: Duration = Duration::from_millis(2);
+
+pub struct Device {
+    address: u8,
+    desc: DeviceDescriptor,
+    ctrl_ep: Option<Endpoint>,
+    schedule: Arc<Schedule>,
+    kernel: Kernel,
+    /// 传输缓冲区使用的 DMA 约束，由 `kernel` 按设备配置派生
+    transfer_dma: Kernel,
+    config_desc: Vec<ConfigurationDescriptor>,
+    qualifier: Option<DeviceQualifierDescriptor>,
+    other_speed_desc: Vec<ConfigurationDescriptor>,
+    speed: Speed,
+    /// 全速/低速设备所在高速 Hub 的地址与端口号
+    tt: Option<(u8, u8)>,
+    eps: BTreeMap<u8, Endpoint>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Device {
+    pub(crate) fn new(
+        address: u8,
+        schedule: Arc<Schedule>,
+        kernel: &Kernel,
+        info: &DeviceAddressInfo,
+        closed: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            address,
+            desc: unsafe { core::mem::zeroed() },
+            ctrl_ep: None,
+            schedule,
+            kernel: kernel.clone(),
+            transfer_dma: kernel.clone(),
+            config_desc: Vec::new(),
+            qualifier: None,
+            other_speed_desc: Vec::new(),
+            speed: info.port_speed,
+            tt: transaction_translator(info),
+            eps: BTreeMap::new(),
+            closed,
+        }
+    }
+
+    fn control_endpoint_mut(&mut self) -> &mut Endpoint {
+        self.ctrl_ep.as_mut().unwrap()
+    }
+
+    fn new_ep(&self, info: QhInfo) -> Result<EhciEndpoint> {
+        let mut ep = EhciEndpoint::new(
+            self.schedule.clone(),
+            &self.kernel,
+            info,
+            self.closed.clone(),
+        )?;
+        ep.set_transfer_dma(self.transfer_dma.clone());
+        Ok(ep)
+    }
+
+    fn qh_info(&self, endpoint: u8, max_packet: u16) -> QhInfo {
+        QhInfo {
+            address: self.address,
+            endpoint,
+            speed: self.speed,
+            max_packet,
+            control: false,
+            tt: self.tt,
+            s_mask: 0,
+            mult: 1,
+        }
+    }
+
+    /// 在地址 0 上创建控制端点，分配地址后读取描述符并选择第一个配置
+    pub(crate) async fn init(&mut self) -> Result {
+        let default_max_packet = if self.speed == Speed::Low { 8 } else { 64 };
+        let info = QhInfo {
+            address: 0,
+            control: true,
+            ..self.qh_info(0, default_max_packet)
+        };
+        let ep = self.new_ep(info)?;
+        self.ctrl_ep = Some(Endpoint::new(EndpointInfo::control(), ep));
+
+        let address = self.address;
+        self.control_endpoint_mut()
+            .control_out(
+                ControlSetup {
+                    request_type: RequestType::Standard,
+                    recipient: Recipient::Device,
+                    request: Request::SetAddress,
+                    value: address as _,
+                    index: 0,
+                },
+                &[],
+            )
+            .await?;
+        self.kernel.delay(SET_ADDRESS_RECOVERY);
+        self.update_ctrl_ep(|info| info.address = address)?;
+        debug!("Device address {address} assigned");
+
+        let mut data = [0u8; DeviceDescriptorBase::LEN];
+        self.control_endpoint_mut()
+            .get_descriptor(DescriptorType::DEVICE, 0, 0, &mut data)
+            .await?;
+        let base =
+            DeviceDescriptorBase::parse(&data).ok_or_else(|| anyhow!("short device descriptor"))?;
+        let max_packet = match base.max_packet_size_0 {
+            0 => 8,
+            n => n as u16,
+        };
+        self.update_ctrl_ep(|info| info.max_packet = max_packet)?;
+
+        self.desc = self.control_endpoint_mut().get_device_descriptor().await?;
+        for i in 0..self.desc.num_configurations {
+            let config_desc = self
+                .control_endpoint_mut()
+                .get_configuration_descriptor(i)
+                .await?;
+            self.config_desc.push(config_desc);
+        }
+
+        if self.speed == Speed::High {
+            self.read_other_speed_descriptors().await?;
+        }
+
+        if let Some(config) = self.config_desc.first() {
+            let config_value = config.configuration_value;
+            self._set_configuration(config_value).await?;
+        }
+        Ok(())
+    }
+
+    fn update_ctrl_ep(&mut self, f: impl FnOnce(&mut QhInfo)) -> Result {
+        self.control_endpoint_mut()
+            .with_raw_mut(|ep: &mut EhciEndpoint| ep.update(f))
+    }
+
+    /// 读取 Device Qualifier 与全部 Other Speed Configuration 描述符
+    async fn read_other_speed_descriptors(&mut self) -> Result {
+        let qualifier: DeviceQualifierDescriptor =
+            self.control_endpoint_mut().read_descriptor(0, 0).await?;
+        for i in 0..qualifier.num_configurations {
+            let desc: OtherSpeedConfigurationDescriptor =
+                self.control_endpoint_mut().read_descriptor(i, 0).await?;
+            self.other_speed_desc.push(desc.0);
+        }
+        self.qualifier = Some(qualifier);
+        Ok(())
+    }
+
+    async fn _set_configuration(&mut self, configuration_value: u8) -> Result {
+        self.control_endpoint_mut()
+            .set_configuration(configuration_value)
+            .await?;
+        self.eps.clear();
+        debug!(
+            "Device {} configuration set to {configuration_value}",
+            self.address
+        );
+        Ok(())
+    }
+
+    async fn _claim_interface(&mut self, interface: u8, alternate: u8) -> Result {
+        self.control_endpoint_mut()
+            .control_out(
+                ControlSetup {
+                    request_type: RequestType::Standard,
+                    recipient: Recipient::Interface,
+                    request: Request::SetInterface,
+                    value: alternate as _,
+                    index: interface as _,
+                },
+                &[],
+            )
+            .await?;
+        self.remove_interface_endpoints(interface);
+
+        for desc in self.find_interface_endpoints(interface, alternate)? {
+            let mut info = self.qh_info(desc.address & 0x0f, desc.max_packet_size);
+            let mut service_interval = None;
+            match desc.transfer_type {
+                EndpointType::Bulk => {}
+                EndpointType::Interrupt => {
+                    service_interval = Some(interrupt_service_interval(self.speed, desc.interval));
+                    info.s_mask = interrupt_s_mask(self.speed, desc.interval);
+                    if self.speed == Speed::High {
+                        info.mult = desc.packets_per_microframe as u8;
+                    }
+                }
+                _ => {
+                    debug!(
+                        "ep {:#x}: {:?} endpoint not supported",
+                        desc.address, desc.transfer_type
+                    );
+                    continue;
+                }
+            }
+            let ep = self.new_ep(info)?;
+            self.eps.insert(
+                desc.address,
+                Endpoint::new((&desc).into(), ep).with_service_interval(service_interval),
+            );
+        }
+        debug!("Interface {interface} set successfully");
+        Ok(())
+    }
+
+    /// 释放接口全部备用设置的端点，QH 随端点一起从调度中摘除
+    fn remove_interface_endpoints(&mut self, interface: u8) {
+        let addrs: Vec<u8> = self
+            .config_desc
+            .iter()
+            .flat_map(|c| &c.interfaces)
+            .filter(|i| i.interface_number == interface)
+            .flat_map(|i| &i.alt_settings)
+            .flat_map(|alt| &alt.endpoints)
+            .map(|ep| ep.address)
+            .collect();
+        for addr in addrs {
+            self.eps.remove(&addr);
+        }
+    }
+
+    fn find_interface_endpoints(
+        &self,
+        interface: u8,
+        alternate: u8,
+    ) -> Result<Vec<EndpointDescriptor>> {
+        self.config_desc
+            .iter()
+            .flat_map(|c| &c.interfaces)
+            .filter(|i| i.interface_number == interface)
+            .flat_map(|i| &i.alt_settings)
+            .find(|alt| alt.alternate_setting == alternate)
+            .map(|alt| alt.endpoints.clone())
+            .ok_or(USBError::NotFound)
+    }
+}
+
+/// 全速/低速设备经过的第一个高速 Hub 的地址与端口号
+fn transaction_translator(info: &DeviceAddressInfo) -> Option<(u8, u8)> {
+    if !matches!(info.port_speed, Speed::Low | Speed::Full) {
+        return None;
+    }
+    let mut parent_id = info.parent_hub;
+    let mut tt_port = info.port_id;
+    while let Some(p) = parent_id {
+        let parent_hub = info.infos.get(&p)?;
+        if parent_hub.hub_depth == -1 {
+            return None;
+        }
+        if parent_hub.speed == Speed::High {
+            return Some((parent_hub.slot_id, tt_port));
+        }
+        tt_port = parent_hub.port_id;
+        parent_id = parent_hub.parent;
+    }
+    None
+}
+
+impl DeviceOp for Device {
+    fn id(&self) -> usize {
+        self.address as usize
+    }
+
+    fn backend_name(&self) -> &str {
+        "ehci"
+    }
+
+    fn descriptor(&self) -> &DeviceDescriptor {
+        &self.desc
+    }
+
+    fn configuration_descriptors(&self) -> &[ConfigurationDescriptor] {
+        &self.config_desc
+    }
+
+    fn device_qualifier(&self) -> Option<&DeviceQualifierDescriptor> {
+        self.qualifier.as_ref()
+    }
+
+    fn other_speed_configurations(&self) -> &[ConfigurationDescriptor] {
+        &self.other_speed_desc
+    }
+
+    fn set_descriptors(&mut self, desc: DeviceDescriptor, configs: Vec<ConfigurationDescriptor>) {
+        self.desc = desc;
+        self.config_desc = configs;
+    }
+
+    fn ctrl_ep_ref(&self) -> &Endpoint {
+        self.ctrl_ep.as_ref().unwrap()
+    }
+
+    fn ctrl_ep_mut(&mut self) -> &mut Endpoint {
+        self.control_endpoint_mut()
+    }
+
+    fn claim_interface<'a>(
+        &'a mut self,
+        interface: u8,
+        alternate: u8,
+    ) -> BoxFuture<'a, Result<()>> {
+        self._claim_interface(interface, alternate).boxed()
+    }
+
+    fn release_interface<'a>(
+        &'a mut self,
+        interface: u8,
+        _alternate: u8,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            self.remove_interface_endpoints(interface);
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn set_configuration<'a>(&'a mut self, configuration_value: u8) -> BoxFuture<'a, Result<()>> {
+        self._set_configuration(configuration_value).boxed()
+    }
+
+    fn endpoint(&mut self, desc: &EndpointDescriptor) -> Result<Endpoint> {
+        if desc.transfer_type == EndpointType::Isochronous {
+            return Err(USBError::NotSupported);
+        }
+        self.eps.remove(&desc.address).ok_or(USBError::NotFound)
+    }
+
+    /// QH 自带 TT 信息，Hub 本身无需更新
+    fn update_hub(&mut self, _params: HubParams) -> BoxFuture<'_, Result<()>> {
+        async { Ok(()) }.boxed()
+    }
+
+    fn set_dma_config(&mut self, config: DmaConfig) -> Result<()> {
+        self.transfer_dma = self.kernel.with_dma_config(config);
+        let dma = self.transfer_dma.clone();
+        for ep in self.ctrl_ep.iter_mut().chain(self.eps.values_mut()) {
+            ep.with_raw_mut(|ep: &mut EhciEndpoint| ep.set_transfer_dma(dma.clone()));
+        }
+        Ok(())
+    }
+}
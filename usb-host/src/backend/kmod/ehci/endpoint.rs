@@ -0,0 +1,445 @@
+//! EHCI 端点
+//!
+//! 每个端点一个 QH 与一个 qTD 池。队列末尾始终保留一个未激活的 dummy qTD：
+//! 提交时把新请求的第一个 qTD 写进 dummy 所在的槽并最后激活，控制器看到的
+//! 队列因此总是完整的（EHCI 规范 4.10.2）。
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    mem::ManuallyDrop,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Waker},
+    time::Duration,
+};
+
+use dma_api::{DArray, DmaDirection};
+use futures::{FutureExt, future::BoxFuture};
+use mbarrier::mb;
+use usb_if::{
+    endpoint::{RequestId, TransferCompletion, TransferRequest},
+    err::{TransferError, USBError},
+    transfer::Direction,
+};
+
+use super::{
+    qtd::{
+        self, Pid, Progress, QhInfo, QtdError, Stage, TERMINATE, TOKEN_ACTIVE, TOKEN_HALTED,
+        TOKEN_TOGGLE,
+    },
+    schedule::{QH_ALT, QH_CURRENT, QH_NEXT, QH_TOKEN, SLOT_SIZE, Schedule, Slot},
+};
+use crate::{
+    backend::{
+        kmod::{le::DmaLe, mem::MemTag},
+        ty::{
+            ep::{EndpointOp, transfer_to_completion},
+            timer::Timer,
+            transfer::{Transfer, TransferKind},
+        },
+    },
+    osal::Kernel,
+};
+
+/// 每个端点的 qTD 数量，一个 qTD 最多覆盖 20K 数据
+const QTD_POOL: usize = 64;
+/// SETUP 包放在 SETUP qTD 所在槽的空闲部分（第 8 字起）
+const SETUP_WORD: usize = 8;
+
+struct Request {
+    transfer: Transfer,
+    /// 占用的 qTD 槽与其阶段，按执行顺序
+    tds: Vec<(usize, Stage)>,
+}
+
+pub(crate) struct Endpoint {
+    schedule: Arc<Schedule>,
+    qh: usize,
+    info: QhInfo,
+    /// 控制器未确认丢弃 QH 缓存时随 QH 一起泄漏
+    tds: ManuallyDrop<DArray<Slot>>,
+    free: Vec<usize>,
+    /// 队尾未激活的 qTD
+    dummy: usize,
+    requests: BTreeMap<RequestId, Request>,
+    next_id: u64,
+    /// 传输缓冲区使用的 DMA 约束
+    kernel: Kernel,
+    /// 设备已弹出，拒绝新请求
+    closed: Arc<AtomicBool>,
+}
+
+unsafe impl Send for Endpoint {}
+unsafe impl Sync for Endpoint {}
+
+impl Endpoint {
+    pub fn new(
+        schedule: Arc<Schedule>,
+        kernel: &Kernel,
+        info: QhInfo,
+        closed: Arc<AtomicBool>,
+    ) -> Result<Self, USBError> {
+        let mut tds = kernel
+            .with_tag(MemTag::Ring)
+            .array_zero_with_align::<Slot>(QTD_POOL, SLOT_SIZE, DmaDirection::Bidirectional)
+            .map_err(|_| USBError::NoMemory)?;
+        let dummy = 0;
+        tds.set_le(dummy, inactive_qtd());
+        let first = tds.dma_addr().as_u64() as u32;
+        let qh = schedule.alloc(&info, first)?;
+        schedule.link(qh);
+
+        Ok(Self {
+            schedule,
+            qh,
+            info,
+            tds: ManuallyDrop::new(tds),
+            free: (1..QTD_POOL).rev().collect(),
+            dummy,
+            requests: BTreeMap::new(),
+            next_id: 1,
+            kernel: kernel.clone(),
+            closed,
+        })
+    }
+
+    /// 替换传输缓冲区使用的 DMA 约束，QH 与 qTD 仍使用创建时的内存
+    pub fn set_transfer_dma(&mut self, kernel: Kernel) {
+        self.kernel = kernel;
+    }
+
+    /// 修改设备地址或最大包长，用于 SET_ADDRESS 与读取 bMaxPacketSize0 之后
+    pub fn update(&mut self, f: impl FnOnce(&mut QhInfo)) -> Result<(), USBError> {
+        f(&mut self.info);
+        self.schedule.update(self.qh, &self.info)
+    }
+
+    fn td_addr(&self, slot: usize) -> u32 {
+        (self.tds.dma_addr().as_u64() as usize + slot * SLOT_SIZE) as u32
+    }
+
+    fn token(&self, slot: usize) -> u32 {
+        self.tds.read_le(slot).unwrap()[2]
+    }
+
+    fn progress(&self, req: &Request) -> Progress {
+        let tds: Vec<(Stage, u32)> = req
+            .tds
+            .iter()
+            .map(|&(slot, stage)| (stage, self.token(slot)))
+            .collect();
+        qtd::progress(&tds)
+    }
+
+    fn release(&mut self, req: &Request) {
+        self.free.extend(req.tds.iter().map(|&(slot, _)| slot));
+    }
+
+    /// 各 qTD 的 `(阶段, PID, 缓冲区地址, 长度, 数据切换位)`
+    fn build(&self, transfer: &Transfer, data: u32) -> Vec<(Stage, Pid, u32, usize, bool)> {
+        let len = transfer.buffer_len();
+        let data_pid = match transfer.direction {
+            Direction::In => Pid::In,
+            Direction::Out => Pid::Out,
+        };
+        let mps = self.info.max_packet.max(1) as usize;
+        let mut out = Vec::new();
+
+        if let TransferKind::Control(_) = transfer.kind {
+            out.push((Stage::Setup, Pid::Setup, 0, 8, false));
+            // 数据阶段从 DATA1 开始，每个 qTD 之后按其包数翻转
+            let mut toggle = true;
+            if len > 0 {
+                for (addr, n) in qtd::split_buffer(data, len, mps) {
+                    out.push((Stage::Data(n), data_pid, addr, n, toggle));
+                    toggle ^= n.div_ceil(mps) % 2 == 1;
+                }
+            }
+            let status_pid = match (len, transfer.direction) {
+                (0, _) | (_, Direction::Out) => Pid::In,
+                _ => Pid::Out,
+            };
+            out.push((Stage::Status, status_pid, 0, 0, true));
+        } else {
+            for (addr, n) in qtd::split_buffer(data, len, mps) {
+                out.push((Stage::Data(n), data_pid, addr, n, false));
+            }
+        }
+        out
+    }
+
+    fn setup_words(transfer: &Transfer) -> Option<[u32; 2]> {
+        let TransferKind::Control(setup) = &transfer.kind else {
+            return None;
+        };
+        let bytes = setup.to_bytes(transfer.direction, transfer.buffer_len() as u16);
+        Some([
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        ])
+    }
+
+    /// 摘除 QH 后丢弃请求，修补前一个请求与 overlay 中指向它的链接
+    async fn cancel(&mut self, id: RequestId) -> Result<Option<TransferCompletion>, TransferError> {
+        if !self.requests.contains_key(&id) {
+            return Err(TransferError::InvalidEndpoint);
+        }
+        if let Some(res) = self.reclaim_request(id) {
+            return res.map(Some);
+        }
+
+        // 控制器可能仍缓存着 QH，不能改写 overlay
+        if self.schedule.unlink(self.qh).is_err() {
+            self.schedule.link(self.qh);
+            return Err(TransferError::Timeout);
+        }
+        // 摘除期间可能已完成
+        if let Some(res) = self.reclaim_request(id) {
+            self.schedule.link(self.qh);
+            return res.map(Some);
+        }
+
+        let req = self.requests.remove(&id).unwrap();
+        let cancelled: Vec<u32> = req.tds.iter().map(|&(s, _)| self.td_addr(s)).collect();
+        let first = cancelled[0];
+        let next_first = self
+            .requests
+            .range(id..)
+            .next()
+            .map_or(self.td_addr(self.dummy), |(_, r)| self.td_addr(r.tds[0].0));
+
+        if let Some((_, prev)) = self.requests.range(..id).next_back() {
+            for &(slot, _) in &prev.tds {
+                let mut td = self.tds.read_le(slot).unwrap();
+                for w in &mut td[..2] {
+                    if *w == first {
+                        *w = next_first;
+                    }
+                }
+                self.tds.set_le(slot, td);
+            }
+        }
+
+        self.schedule.modify(self.qh, |qh| {
+            let executing = qh[QH_TOKEN] & (TOKEN_ACTIVE | TOKEN_HALTED) != 0;
+            if executing && cancelled.contains(&qh[QH_CURRENT]) {
+                // 正在执行被取消的请求：丢弃执行到一半的事务，保留数据切换位
+                qh[QH_NEXT] = next_first;
+                qh[QH_ALT] = TERMINATE;
+                qh[QH_TOKEN] &= TOKEN_TOGGLE;
+            } else {
+                for w in [QH_NEXT, QH_ALT] {
+                    if cancelled.contains(&qh[w]) {
+                        qh[w] = next_first;
+                    }
+                }
+            }
+        });
+        self.release(&req);
+        self.schedule.link(self.qh);
+        Ok(None)
+    }
+
+    /// 清除 overlay 的 Halted，从最早未完成的请求继续执行
+    async fn reset_halted(&mut self) -> Result<(), TransferError> {
+        if self.schedule.read(self.qh)[QH_TOKEN] & TOKEN_HALTED == 0 {
+            return Ok(());
+        }
+        if self.schedule.unlink(self.qh).is_err() {
+            self.schedule.link(self.qh);
+            return Err(TransferError::Timeout);
+        }
+        let next = self
+            .requests
+            .values()
+            .next()
+            .map_or(self.td_addr(self.dummy), |r| self.td_addr(r.tds[0].0));
+        self.schedule.modify(self.qh, |qh| {
+            qh[QH_NEXT] = next;
+            qh[QH_ALT] = TERMINATE;
+            // 数据切换位与设备侧一起归零
+            qh[QH_TOKEN] = 0;
+        });
+        self.schedule.link(self.qh);
+        Ok(())
+    }
+}
+
+fn inactive_qtd() -> Slot {
+    let mut slot = Slot::default();
+    slot[0] = TERMINATE;
+    slot[1] = TERMINATE;
+    slot
+}
+
+fn qtd_error(e: QtdError) -> TransferError {
+    match e {
+        QtdError::Stall => TransferError::Stall,
+        QtdError::StatusStall => TransferError::StatusStall,
+        e => TransferError::Other(anyhow!("EHCI transfer error: {e:?}")),
+    }
+}
+
+impl Timer for Endpoint {
+    fn now(&self) -> Duration {
+        self.kernel.now()
+    }
+
+    fn wake_at(&self, deadline: Duration, waker: &Waker) {
+        self.kernel.wake_at(deadline, waker)
+    }
+}
+
+impl EndpointOp for Endpoint {
+    fn submit_request(&mut self, request: TransferRequest) -> Result<RequestId, TransferError> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(TransferError::NoDevice);
+        }
+        let transfer = Transfer::from_request(&self.kernel, request)?;
+        if matches!(transfer.kind, TransferKind::Isochronous { .. }) {
+            return Err(TransferError::NotSupported);
+        }
+
+        let mut data = 0;
+        if transfer.buffer_len() > 0 {
+            if matches!(transfer.direction, Direction::Out) {
+                transfer.confirm_write_all();
+            }
+            let addr = transfer.dma_addr();
+            if addr + transfer.buffer_len() as u64 > u32::MAX as u64 {
+                return Err(TransferError::Other(anyhow!(
+                    "DMA address {addr:#x} exceeds EHCI 32-bit addressing"
+                )));
+            }
+            data = addr as u32;
+        }
+
+        let stages = self.build(&transfer, data);
+        // 除复用的 dummy 外，每个 qTD 需要一个新槽，另需一个新的 dummy
+        if stages.len() > self.free.len() {
+            return Err(TransferError::QueueFull);
+        }
+        let mut slots = Vec::with_capacity(stages.len());
+        slots.push(self.dummy);
+        for _ in 1..stages.len() {
+            slots.push(self.free.pop().unwrap());
+        }
+        let new_dummy = self.free.pop().unwrap();
+        self.tds.set_le(new_dummy, inactive_qtd());
+
+        let status = slots[stages.len() - 1];
+        let setup = Self::setup_words(&transfer);
+        let is_control = setup.is_some();
+        let mut first = None;
+        for (i, &(stage, pid, addr, len, toggle)) in stages.iter().enumerate() {
+            let slot = slots[i];
+            let last = i + 1 == stages.len();
+            let next = match slots.get(i + 1) {
+                Some(&s) => self.td_addr(s),
+                None => self.td_addr(new_dummy),
+            };
+            // 短包时控制传输跳到状态阶段，其余传输跳到下一个请求
+            let alt = match stage {
+                Stage::Data(_) if is_control => self.td_addr(status),
+                Stage::Data(_) => self.td_addr(new_dummy),
+                _ => TERMINATE,
+            };
+            let buf = match stage {
+                Stage::Setup => self.td_addr(slot) + (SETUP_WORD * 4) as u32,
+                _ => addr,
+            };
+            let token = qtd::token(pid, len, toggle, last);
+            let mut td = Slot::default();
+            td[..8].copy_from_slice(&qtd::qtd(next, alt, token, buf, len));
+            if let (Stage::Setup, Some(setup)) = (stage, setup) {
+                td[SETUP_WORD..SETUP_WORD + 2].copy_from_slice(&setup);
+            }
+            if i == 0 {
+                first = Some(td);
+            } else {
+                self.tds.set_le(slot, td);
+            }
+        }
+
+        // 控制器可能正在读取 dummy，先写入未激活的内容，再单独激活
+        let first = first.unwrap();
+        let mut inactive = first;
+        inactive[2] &= !TOKEN_ACTIVE;
+        self.tds.set_le(slots[0], inactive);
+        mb();
+        self.tds.set_le(slots[0], first);
+        mb();
+
+        self.dummy = new_dummy;
+        let id = RequestId::new(self.next_id);
+        self.next_id += 1;
+        self.requests.insert(
+            id,
+            Request {
+                transfer,
+                tds: slots.into_iter().zip(stages.iter().map(|s| s.0)).collect(),
+            },
+        );
+        Ok(id)
+    }
+
+    fn reclaim_request(
+        &mut self,
+        id: RequestId,
+    ) -> Option<Result<TransferCompletion, TransferError>> {
+        let req = self.requests.get(&id)?;
+        let progress = self.progress(req);
+        if progress == Progress::Pending {
+            return None;
+        }
+        let req = self.requests.remove(&id).unwrap();
+        self.release(&req);
+        let mut t = req.transfer;
+        match progress {
+            Progress::Done(actual) => {
+                if actual > 0 && matches!(t.direction, Direction::In) {
+                    t.prepare_read_all();
+                }
+                t.transfer_len = actual;
+                Some(Ok(transfer_to_completion(id, t)))
+            }
+            Progress::Failed(e) => Some(Err(qtd_error(e))),
+            Progress::Pending => unreachable!(),
+        }
+    }
+
+    fn register_waker(&self, _id: RequestId, cx: &mut Context<'_>) {
+        self.schedule.register_waker(self.qh, cx.waker());
+    }
+
+    fn pending_requests(&self) -> Vec<RequestId> {
+        self.requests.keys().copied().collect()
+    }
+
+    fn cancel_request(
+        &mut self,
+        id: RequestId,
+    ) -> BoxFuture<'_, Result<Option<TransferCompletion>, TransferError>> {
+        self.cancel(id).boxed()
+    }
+
+    fn reset_halt(&mut self) -> BoxFuture<'_, Result<(), TransferError>> {
+        self.reset_halted().boxed()
+    }
+
+    #[cfg(feature = "dma-mapping")]
+    fn dma_mapping(&self, id: RequestId) -> Option<crate::backend::kmod::DmaMapping> {
+        self.requests.get(&id)?.transfer.dma_mapping()
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        // 归还前等待控制器不再访问 QH，qTD 池随后释放
+        match self.schedule.free(self.qh) {
+            // SAFETY: QH 已摘除且控制器已确认，qTD 池不再被访问，此后不再使用 `tds`
+            Ok(()) => unsafe { ManuallyDrop::drop(&mut self.tds) },
+            // 控制器可能仍在读取 qTD 与其指向的传输缓冲区
+            Err(_) => core::mem::forget(core::mem::take(&mut self.requests)),
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use futures::{FutureExt, future::BoxFuture};
+use tock_registers::interfaces::*;
+use usb_if::err::USBError;
+
+use super::{
+    device::Device,
+    hub::{EhciRootHub, PortChangeWaker},
+    reg::{CAPLENGTH, CONFIGFLAG, EhciRegs, HCCPARAMS, HCSPARAMS, PORTSC, USBCMD, USBINTR, USBSTS},
+    schedule::Schedule,
+};
+use crate::{
+    DeviceAddressInfo, KernelOp, Mmio,
+    backend::{
+        kmod::{PerfCounters, SelfTestReport, hub::HubOp, kcore::CoreOp, perf::PerfStats},
+        ty::{DeviceOp, Event, EventHandlerOp},
+    },
+    err::Result,
+    osal::Kernel,
+};
+
+/// 停止或复位控制器的上限，见 EHCI 2.3.1
+const HALT_TIMEOUT: Duration = Duration::from_millis(20);
+const RESET_TIMEOUT: Duration = Duration::from_millis(250);
+/// USBSTS 中写 1 清除的中断位
+const USBSTS_INT_MASK: u32 = 0x3f;
+
+pub struct Ehci {
+    reg: EhciRegs,
+    kernel: Kernel,
+    schedule: Arc<Schedule>,
+    root_hub: Option<EhciRootHub>,
+    event_handler: Option<EventHandler>,
+    stats: Arc<PerfStats>,
+    /// 已分配的设备地址及其弹出标志，地址 0 保留给枚举
+    devices: BTreeMap<u8, Arc<AtomicBool>>,
+}
+
+unsafe impl Send for Ehci {}
+unsafe impl Sync for Ehci {}
+
+impl CoreOp for Ehci {
+    fn init<'a>(&'a mut self) -> BoxFuture<'a, core::result::Result<(), USBError>> {
+        self._init().boxed()
+    }
+
+    fn root_hub(&mut self) -> Box<dyn HubOp> {
+        Box::new(
+            self.root_hub
+                .take()
+                .expect("Root hub can only be taken once"),
+        )
+    }
+
+    fn new_addressed_device<'a>(
+        &'a mut self,
+        addr: DeviceAddressInfo,
+    ) -> BoxFuture<'a, Result<Box<dyn DeviceOp>>> {
+        self.new_device(addr).boxed()
+    }
+
+    fn create_event_handler(&mut self) -> Box<dyn EventHandlerOp> {
+        Box::new(
+            self.event_handler
+                .take()
+                .expect("Event handler can only be created once"),
+        )
+    }
+
+    fn kernel(&self) -> &Kernel {
+        &self.kernel
+    }
+
+    fn perf_counters(&self) -> PerfCounters {
+        self.stats.snapshot()
+    }
+
+    /// EHCI 没有命令环
+    fn self_test<'a>(&'a mut self, _count: u32) -> BoxFuture<'a, Result<SelfTestReport>> {
+        async { Err(USBError::NotSupported) }.boxed()
+    }
+
+    fn eject_slot<'a>(
+        &'a mut self,
+        slot_id: u8,
+        power_off_port: Option<u8>,
+    ) -> BoxFuture<'a, Result> {
+        self._eject_slot(slot_id, power_off_port).boxed()
+    }
+
+    fn frame_index(&self) -> Option<u16> {
+        let op = self.reg.op();
+        if op.usbsts.is_set(USBSTS::HCHALTED) {
+            return None;
+        }
+        Some((op.frindex.get() & 0x3fff) as u16)
+    }
+}
+
+impl Ehci {
+    pub fn new(mmio: Mmio, kernel: &'static dyn KernelOp) -> Result<Self> {
+        let reg = unsafe { EhciRegs::new(mmio) };
+        let cap = reg.cap();
+        info!(
+            "EHCI {:#x}: {} ports, 64-bit addressing {}",
+            cap.caplength.read(CAPLENGTH::HCIVERSION),
+            reg.port_count(),
+            cap.hccparams.is_set(HCCPARAMS::AC64)
+        );
+        // 数据结构与传输缓冲区都放在 4G 以内，CTRLDSSEGMENT 固定为 0
+        let kernel = Kernel::new(u32::MAX as u64, kernel);
+        let schedule = Arc::new(Schedule::new(reg, &kernel)?);
+        let stats = Arc::new(PerfStats::default());
+        let root_hub = EhciRootHub::new(reg, kernel.clone());
+        let event_handler = EventHandler {
+            reg,
+            schedule: schedule.clone(),
+            ports: root_hub.waker(),
+            stats: stats.clone(),
+        };
+
+        Ok(Self {
+            reg,
+            kernel,
+            schedule,
+            root_hub: Some(root_hub),
+            event_handler: Some(event_handler),
+            stats,
+            devices: BTreeMap::new(),
+        })
+    }
+
+    /// 在 `timeout` 内等待 `done` 成立
+    fn wait(&self, timeout: Duration, done: impl Fn() -> bool) -> Result {
+        let deadline = self.kernel.now() + timeout;
+        while !done() {
+            if self.kernel.now() >= deadline {
+                return Err(USBError::Timeout);
+            }
+            self.kernel.delay(Duration::from_micros(100));
+        }
+        Ok(())
+    }
+
+    /// EHCI 4.1 Host Controller Initialization
+    async fn _init(&mut self) -> Result {
+        let op = self.reg.op();
+        op.usbcmd.modify(USBCMD::RS::CLEAR);
+        self.wait(HALT_TIMEOUT, || op.usbsts.is_set(USBSTS::HCHALTED))?;
+
+        op.usbcmd.modify(USBCMD::HCRESET::SET);
+        self.wait(RESET_TIMEOUT, || !op.usbcmd.is_set(USBCMD::HCRESET))?;
+        debug!("EHCI reset done");
+
+        op.ctrldssegment.set(0);
+        op.periodiclistbase.set(self.schedule.frame_list_addr());
+        op.asynclistaddr.set(self.schedule.async_list_addr());
+        op.usbsts.set(USBSTS_INT_MASK);
+        op.usbintr.write(
+            USBINTR::USBINT::SET
+                + USBINTR::USBERRINT::SET
+                + USBINTR::PCD::SET
+                + USBINTR::HSE::SET
+                + USBINTR::IAA::SET,
+        );
+        // 1024 项帧表，每个微帧结束都可以产生中断
+        op.usbcmd.write(
+            USBCMD::ITC.val(1)
+                + USBCMD::FLS.val(0)
+                + USBCMD::PSE::SET
+                + USBCMD::ASE::SET
+                + USBCMD::RS::SET,
+        );
+        // 之后端口默认路由到 EHCI，未识别为高速的设备再逐个交给伴随控制器
+        op.configflag.write(CONFIGFLAG::CF::SET);
+        self.wait(HALT_TIMEOUT, || !op.usbsts.is_set(USBSTS::HCHALTED))?;
+        debug!("EHCI running");
+        Ok(())
+    }
+
+    fn alloc_address(&self) -> Result<u8> {
+        (1..=127u8)
+            .find(|a| !self.devices.contains_key(a))
+            .ok_or(USBError::NoMemory)
+    }
+
+    async fn new_device(&mut self, info: DeviceAddressInfo) -> Result<Box<dyn DeviceOp>> {
+        let address = self.alloc_address()?;
+        let closed = Arc::new(AtomicBool::new(false));
+        let mut device = Device::new(
+            address,
+            self.schedule.clone(),
+            &self.kernel,
+            &info,
+            closed.clone(),
+        );
+        device.init().await?;
+        self.devices.insert(address, closed);
+        Ok(Box::new(device))
+    }
+
+    async fn _eject_slot(&mut self, address: u8, power_off_port: Option<u8>) -> Result {
+        if let Some(port) = power_off_port {
+            let idx = (port as usize)
+                .checked_sub(1)
+                .filter(|&i| i < self.reg.port_count())
+                .ok_or(USBError::InvalidParameter)?;
+            if !self.reg.cap().hcsparams.is_set(HCSPARAMS::PPC) {
+                return Err(USBError::NotSupported);
+            }
+            self.reg.modify_portsc(idx, |v| v.modify(PORTSC::PP::CLEAR));
+        }
+
+        let closed = self.devices.remove(&address).ok_or(USBError::NotFound)?;
+        closed.store(true, Ordering::Release);
+        // 端点可能仍由调用方持有，先摘除 QH，端点释放时再归还
+        self.schedule.unlink_device(address)?;
+        debug!("Device {address} ejected");
+        Ok(())
+    }
+}
+
+pub struct EventHandler {
+    reg: EhciRegs,
+    schedule: Arc<Schedule>,
+    ports: Arc<PortChangeWaker>,
+    stats: Arc<PerfStats>,
+}
+
+unsafe impl Send for EventHandler {}
+unsafe impl Sync for EventHandler {}
+
+impl EventHandlerOp for EventHandler {
+    fn handle_event(&self) -> Event {
+        let op = self.reg.op();
+        let sts = op.usbsts.get() & USBSTS_INT_MASK;
+        if sts == 0 {
+            return Event::Nothing;
+        }
+        op.usbsts.set(sts);
+        self.stats.irq();
+
+        let sts = tock_registers::LocalRegisterCopy::<u32, USBSTS::Register>::new(sts);
+        if sts.is_set(USBSTS::USBINT) || sts.is_set(USBSTS::USBERRINT) {
+            self.stats.event();
+            self.schedule.wake_all();
+        }
+        if sts.is_set(USBSTS::IAA) {
+            self.schedule.async_advanced();
+        }
+        if sts.is_set(USBSTS::HSE) {
+            error!("EHCI host system error, controller halted");
+        }
+        if sts.is_set(USBSTS::PCD) {
+            self.ports.set_changed();
+            let port = (0..self.reg.port_count())
+                .find(|&i| {
+                    let portsc = self.reg.portsc(i);
+                    portsc.is_set(PORTSC::CSC) || portsc.is_set(PORTSC::PEDC)
+                })
+                .unwrap_or(0);
+            return Event::PortChange {
+                port: port as u8 + 1,
+            };
+        }
+        Event::Nothing
+    }
+}
@@ -0,0 +1,246 @@
+//! EHCI Root Hub
+//!
+//! EHCI 只处理高速设备：低速设备在复位前由线状态识别，全速设备在复位后端口
+//! 未启用时识别，二者都交给伴随控制器（OHCI/UHCI）。参考 EHCI 规范 4.2。
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+    time::Duration,
+};
+
+use futures::{FutureExt, future::BoxFuture, task::AtomicWaker};
+use tock_registers::interfaces::*;
+use usb_if::{err::USBError, host::hub::Speed};
+
+use super::reg::{EhciRegs, HCSPARAMS, PORTSC};
+use crate::backend::kmod::{
+    hub::{HubInfo, HubOp, HubPortStatus, PortChangeInfo, PortState},
+    port_event::PortTransition,
+};
+use crate::osal::Kernel;
+
+/// 根端口复位信号持续时间 TDRSTR
+const PORT_RESET: Duration = Duration::from_millis(50);
+/// 写 0 结束复位后，控制器清除 PR 的上限
+const RESET_COMPLETE_TIMEOUT: Duration = Duration::from_millis(2);
+/// 端口上电后等待电源稳定
+const POWER_ON_DELAY: Duration = Duration::from_millis(20);
+
+/// 中断中置位的端口变化标志，PCD 不指明具体端口
+#[derive(Default)]
+pub struct PortChangeWaker {
+    changed: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl PortChangeWaker {
+    /// 在中断中调用：只操作原子变量
+    pub fn set_changed(&self) {
+        self.changed.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+pub struct EhciRootHub {
+    reg: EhciRegs,
+    kernel: Kernel,
+    states: Vec<PortState>,
+    waker: Arc<PortChangeWaker>,
+    /// 清除变化位时记录的端口状态变化，见 [`HubOp::take_port_events`]
+    events: Vec<(u8, PortTransition)>,
+}
+
+unsafe impl Send for EhciRootHub {}
+
+impl EhciRootHub {
+    pub fn new(reg: EhciRegs, kernel: Kernel) -> Self {
+        Self {
+            states: alloc::vec![PortState::Uninit; reg.port_count()],
+            reg,
+            kernel,
+            waker: Arc::new(PortChangeWaker::default()),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn waker(&self) -> Arc<PortChangeWaker> {
+        self.waker.clone()
+    }
+
+    /// 清除端口的变化位，同时记录连接、过流与错误禁用
+    fn ack_port_changes(&mut self, idx: usize) {
+        let portsc = self.reg.portsc(idx);
+        let port = (idx + 1) as u8;
+        if portsc.is_set(PORTSC::OCC) && portsc.is_set(PORTSC::OCA) {
+            warn!("Port {port} over-current");
+            self.events.push((port, PortTransition::OverCurrent));
+        }
+        if portsc.is_set(PORTSC::CSC) && portsc.is_set(PORTSC::CCS) {
+            self.events.push((port, PortTransition::Connected));
+        }
+        // 软件禁用端口不会置位 PEDC
+        if portsc.is_set(PORTSC::PEDC) && !portsc.is_set(PORTSC::PED) && portsc.is_set(PORTSC::CCS)
+        {
+            self.events.push((port, PortTransition::Error));
+        }
+        self.reg.ack_port_changes(idx);
+    }
+
+    /// 把端口交给伴随控制器
+    fn release_port(&self, idx: usize, reason: &str) {
+        let companions = self.reg.cap().hcsparams.read(HCSPARAMS::N_CC);
+        if companions == 0 {
+            warn!("Port {}: {reason} device, no companion controller", idx + 1);
+        } else {
+            info!(
+                "Port {}: {reason} device, handed to companion controller",
+                idx + 1
+            );
+        }
+        self.reg.modify_portsc(idx, |v| v.modify(PORTSC::PO::SET));
+    }
+
+    /// 复位端口，返回端口是否启用（即连接的是高速设备）
+    fn reset_port(&self, idx: usize) -> Result<bool, USBError> {
+        self.reg
+            .modify_portsc(idx, |v| v.modify(PORTSC::PR::SET + PORTSC::PED::CLEAR));
+        self.kernel.delay(PORT_RESET);
+        self.reg.modify_portsc(idx, |v| v.modify(PORTSC::PR::CLEAR));
+
+        let deadline = self.kernel.now() + RESET_COMPLETE_TIMEOUT;
+        while self.reg.portsc(idx).is_set(PORTSC::PR) {
+            if self.kernel.now() >= deadline {
+                return Err(USBError::Timeout);
+            }
+            self.kernel.delay(Duration::from_micros(100));
+        }
+        Ok(self.reg.portsc(idx).is_set(PORTSC::PED))
+    }
+
+    async fn _changed_ports(&mut self) -> Result<Vec<PortChangeInfo>, USBError> {
+        let mut out = Vec::new();
+        for idx in 0..self.states.len() {
+            // 已探测端口的断开由 take_disconnected_ports 处理，保留其变化位
+            if self.states[idx] == PortState::Probed {
+                continue;
+            }
+            self.ack_port_changes(idx);
+            let portsc = self.reg.portsc(idx);
+            if !portsc.is_set(PORTSC::CCS) || portsc.is_set(PORTSC::PO) {
+                self.states[idx] = PortState::Uninit;
+                continue;
+            }
+            let port = (idx + 1) as u8;
+            if portsc.matches_all(PORTSC::LS::KState) {
+                self.release_port(idx, "low-speed");
+                continue;
+            }
+
+            // 单个端口复位失败不影响其余端口的扫描
+            let enabled = match self.reset_port(idx) {
+                Ok(enabled) => enabled,
+                Err(e) => {
+                    warn!("Port {port} reset: {e:?}");
+                    self.events.push((port, PortTransition::Error));
+                    continue;
+                }
+            };
+            if !enabled {
+                self.release_port(idx, "full-speed");
+                continue;
+            }
+            self.ack_port_changes(idx);
+            debug!("Port {port} high-speed device connected");
+            self.states[idx] = PortState::Probed;
+            out.push(PortChangeInfo {
+                root_port_id: port,
+                port_id: port,
+                port_speed: Speed::High,
+                tt_port_on_hub: None,
+            });
+        }
+        Ok(out)
+    }
+}
+
+impl HubOp for EhciRootHub {
+    fn init(&mut self, info: HubInfo) -> BoxFuture<'_, Result<HubInfo, USBError>> {
+        async move {
+            let mut info = info;
+            info.speed = Speed::High;
+            if self.reg.cap().hcsparams.is_set(HCSPARAMS::PPC) {
+                for idx in 0..self.states.len() {
+                    self.reg.modify_portsc(idx, |v| v.modify(PORTSC::PP::SET));
+                }
+                self.kernel.delay(POWER_ON_DELAY);
+            }
+            Ok(info)
+        }
+        .boxed()
+    }
+
+    fn changed_ports(&mut self) -> BoxFuture<'_, Result<Vec<PortChangeInfo>, USBError>> {
+        self._changed_ports().boxed()
+    }
+
+    fn slot_id(&self) -> u8 {
+        0
+    }
+
+    fn wait_port_change(&mut self) -> BoxFuture<'_, ()> {
+        let waker = self.waker.clone();
+        core::future::poll_fn(move |cx| {
+            waker.waker.register(cx.waker());
+            if waker.changed.swap(false, Ordering::AcqRel) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .boxed()
+    }
+
+    fn take_port_events(&mut self) -> Vec<(u8, PortTransition)> {
+        core::mem::take(&mut self.events)
+    }
+
+    fn port_status(&self) -> Vec<HubPortStatus> {
+        self.states
+            .iter()
+            .enumerate()
+            .map(|(idx, &state)| {
+                let portsc = self.reg.portsc(idx);
+                // 交给伴随控制器的端口不由本控制器管理
+                let connected = portsc.is_set(PORTSC::CCS) && !portsc.is_set(PORTSC::PO);
+                HubPortStatus {
+                    port: (idx + 1) as u8,
+                    state,
+                    connected,
+                    speed: (connected && portsc.is_set(PORTSC::PED)).then_some(Speed::High),
+                }
+            })
+            .collect()
+    }
+
+    fn take_disconnected_ports(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for idx in 0..self.states.len() {
+            if self.states[idx] != PortState::Probed {
+                continue;
+            }
+            let portsc = self.reg.portsc(idx);
+            self.ack_port_changes(idx);
+            // CSC 置位而 CCS 仍为 1 说明设备已被换下，按断开处理后重新枚举
+            if portsc.is_set(PORTSC::CCS) && !portsc.is_set(PORTSC::CSC) {
+                continue;
+            }
+            let port = (idx + 1) as u8;
+            debug!("Port {port} device disconnected");
+            self.states[idx] = PortState::Uninit;
+            out.push(port);
+        }
+        out
+    }
+}
@@ -0,0 +1,14 @@
+//! EHCI 主机控制器
+//!
+//! 用于只有 USB 2.0 控制器的旧平台。支持控制、批量与中断传输，等时传输暂不支持；
+//! 全速/低速设备只能经高速 Hub 的 TT 访问，直连根端口时交给伴随控制器（见 `new_ohci`）。
+
+mod device;
+mod endpoint;
+mod host;
+mod hub;
+mod qtd;
+mod reg;
+mod schedule;
+
+pub use host::Ehci;
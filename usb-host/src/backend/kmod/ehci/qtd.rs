@@ -0,0 +1,359 @@
+//! qTD 与 QH 字段编码
+//!
+//! 只负责把一次传输翻译成 qTD 字、判断完成状态以及生成 QH 的端点特征字，
+//! 不接触 DMA 与寄存器。模块不依赖 kmod 其他部分，主机上也会编译以运行单元测试。
+//!
+//! 参考 EHCI 规范 3.5（qTD）与 3.6（QH）。
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use usb_if::host::hub::Speed;
+
+/// 链接指针的 T 位，表示没有下一项
+pub(crate) const TERMINATE: u32 = 1;
+
+pub(crate) const TOKEN_ACTIVE: u32 = 1 << 7;
+pub(crate) const TOKEN_HALTED: u32 = 1 << 6;
+const TOKEN_DATA_BUFFER_ERR: u32 = 1 << 5;
+const TOKEN_BABBLE: u32 = 1 << 4;
+const TOKEN_XACT_ERR: u32 = 1 << 3;
+const TOKEN_MISSED_UFRAME: u32 = 1 << 2;
+const TOKEN_CERR_SHIFT: u32 = 10;
+const TOKEN_IOC: u32 = 1 << 15;
+const TOKEN_BYTES_SHIFT: u32 = 16;
+const TOKEN_BYTES_MASK: u32 = 0x7fff;
+pub(crate) const TOKEN_TOGGLE: u32 = 1 << 31;
+
+const PAGE_SIZE: usize = 0x1000;
+/// 一个 qTD 的 5 个缓冲区指针最多覆盖 5 页
+const QTD_MAX_BYTES: usize = 5 * PAGE_SIZE;
+
+/// 中断 QH 经 TT 访问全速/低速设备时，在第 2~4 微帧发出 complete-split
+const SPLIT_C_MASK: u8 = 0x1c;
+/// 高速异步端点的 NAK 计数重载值
+const NAK_RELOAD_HS: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pid {
+    Out = 0,
+    In = 1,
+    Setup = 2,
+}
+
+/// 构造激活的 token，CERR 为 3
+pub(crate) fn token(pid: Pid, len: usize, toggle: bool, ioc: bool) -> u32 {
+    let mut token = TOKEN_ACTIVE
+        | (pid as u32) << 8
+        | 3 << TOKEN_CERR_SHIFT
+        | (len as u32 & TOKEN_BYTES_MASK) << TOKEN_BYTES_SHIFT;
+    if ioc {
+        token |= TOKEN_IOC;
+    }
+    if toggle {
+        token |= TOKEN_TOGGLE;
+    }
+    token
+}
+
+/// token 中尚未传输的字节数
+pub(crate) fn remaining(token: u32) -> usize {
+    (token >> TOKEN_BYTES_SHIFT & TOKEN_BYTES_MASK) as usize
+}
+
+/// qTD 的 8 个字：下一项、备用下一项、token 与 5 个缓冲区页指针
+pub(crate) fn qtd(next: u32, alt: u32, token: u32, buf: u32, len: usize) -> [u32; 8] {
+    let mut words = [next, alt, token, buf, 0, 0, 0, 0];
+    let page = buf & !(PAGE_SIZE as u32 - 1);
+    let pages = (buf as usize % PAGE_SIZE + len).div_ceil(PAGE_SIZE);
+    for i in 1..pages.min(5) {
+        words[3 + i] = page + (i * PAGE_SIZE) as u32;
+    }
+    words
+}
+
+/// 把缓冲区拆分为 qTD，返回每个 qTD 的 `(起始地址, 长度)`
+///
+/// 除最后一个外每段都是 `max_packet` 的整数倍，短包只会出现在最后一个事务。
+/// 长度为 0 时返回一个零长度段。
+pub(crate) fn split_buffer(addr: u32, len: usize, max_packet: usize) -> Vec<(u32, usize)> {
+    let mut out = Vec::new();
+    let mut addr = addr as usize;
+    let mut left = len;
+    loop {
+        let mut chunk = left.min(QTD_MAX_BYTES - addr % PAGE_SIZE);
+        if chunk < left {
+            chunk -= chunk % max_packet.max(1);
+        }
+        out.push((addr as u32, chunk));
+        addr += chunk;
+        left -= chunk;
+        if left == 0 {
+            return out;
+        }
+    }
+}
+
+/// qTD 在请求中的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Setup,
+    /// 数据 qTD，记录请求的字节数
+    Data(usize),
+    Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum QtdError {
+    Stall,
+    /// 控制请求的状态阶段被 STALL
+    StatusStall,
+    Babble,
+    DataBuffer,
+    Transaction,
+    MissedMicroframe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Progress {
+    Pending,
+    /// 完成，携带实际传输的数据字节数
+    Done(usize),
+    Failed(QtdError),
+}
+
+/// 根据各 qTD 回写的 token 判断请求进度
+///
+/// 短包后控制器经备用下一项跳过剩余的数据 qTD，它们保持 Active：批量与中断请求
+/// 此时已完成，控制请求继续等待状态阶段。
+pub(crate) fn progress(tds: &[(Stage, u32)]) -> Progress {
+    let mut actual = 0;
+    let mut short = false;
+    for &(stage, token) in tds {
+        if short && matches!(stage, Stage::Data(_)) {
+            continue;
+        }
+        if token & TOKEN_HALTED != 0 {
+            return Progress::Failed(match qtd_error(token) {
+                QtdError::Stall if stage == Stage::Status => QtdError::StatusStall,
+                e => e,
+            });
+        }
+        if token & TOKEN_ACTIVE != 0 {
+            return Progress::Pending;
+        }
+        if let Stage::Data(len) = stage {
+            let left = remaining(token);
+            actual += len.saturating_sub(left);
+            short = left > 0;
+        }
+    }
+    Progress::Done(actual)
+}
+
+/// Halted 的原因，没有其他错误位时为设备返回 STALL
+fn qtd_error(token: u32) -> QtdError {
+    if token & TOKEN_BABBLE != 0 {
+        QtdError::Babble
+    } else if token & TOKEN_DATA_BUFFER_ERR != 0 {
+        QtdError::DataBuffer
+    } else if token & TOKEN_XACT_ERR != 0 {
+        QtdError::Transaction
+    } else if token & TOKEN_MISSED_UFRAME != 0 {
+        QtdError::MissedMicroframe
+    } else {
+        QtdError::Stall
+    }
+}
+
+/// QH 的静态字段：端点特征（第 1 字）与端点能力（第 2 字）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct QhInfo {
+    pub address: u8,
+    pub endpoint: u8,
+    pub speed: Speed,
+    pub max_packet: u16,
+    pub control: bool,
+    /// 全速/低速设备所在高速 Hub 的地址与端口号
+    pub tt: Option<(u8, u8)>,
+    /// 周期端点发起事务的微帧，异步端点为 0
+    pub s_mask: u8,
+    /// 高速周期端点每微帧的事务数
+    pub mult: u8,
+}
+
+impl QhInfo {
+    pub(crate) fn characteristics(&self) -> u32 {
+        let eps = match self.speed {
+            Speed::Low => 1,
+            Speed::High => 2,
+            _ => 0,
+        };
+        let mut v = self.address as u32 & 0x7f
+            | (self.endpoint as u32 & 0xf) << 8
+            | eps << 12
+            | (self.max_packet as u32 & 0x7ff) << 16;
+        if self.control {
+            // 控制端点的数据切换位由各 qTD 指定
+            v |= 1 << 14;
+            if self.speed != Speed::High {
+                v |= 1 << 27;
+            }
+        }
+        if self.speed == Speed::High && self.s_mask == 0 {
+            v |= NAK_RELOAD_HS << 28;
+        }
+        v
+    }
+
+    pub(crate) fn capabilities(&self) -> u32 {
+        let mut v = self.s_mask as u32 | (self.mult.clamp(1, 3) as u32) << 30;
+        if let Some((hub, port)) = self.tt {
+            v |= (hub as u32 & 0x7f) << 16 | (port as u32 & 0x7f) << 23;
+            if self.s_mask != 0 {
+                v |= (SPLIT_C_MASK as u32) << 8;
+            }
+        }
+        v
+    }
+}
+
+/// 中断端点的 S-mask
+///
+/// 周期表每帧都指向全部中断 QH，周期不小于 1 帧的端点按每帧一次轮询；
+/// 高速端点周期小于 1 帧时在帧内按周期选取微帧。
+pub(crate) fn interrupt_s_mask(speed: Speed, interval: u8) -> u8 {
+    if speed != Speed::High {
+        return 0x01;
+    }
+    let period = 1usize << (interval.clamp(1, 16) - 1);
+    if period >= 8 {
+        return 0x01;
+    }
+    (0..8).step_by(period).fold(0, |mask, i| mask | 1 << i)
+}
+
+/// 中断端点实际的服务间隔，与 [`interrupt_s_mask`] 对应
+pub(crate) fn interrupt_service_interval(speed: Speed, interval: u8) -> Duration {
+    if speed != Speed::High {
+        return Duration::from_millis(1);
+    }
+    Duration::from_micros(125 << (interval.clamp(1, 4) - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_respects_pages_and_packets() {
+        // 页内偏移 0x100，第一个 qTD 最多 0x4f00 字节，向下取整到 512 的倍数
+        let parts = split_buffer(0x1000_0100, 0x6000, 512);
+        assert_eq!(parts, [(0x1000_0100, 0x4e00), (0x1000_4f00, 0x1200)]);
+        assert_eq!(split_buffer(0x2000, 0, 64), [(0x2000, 0)]);
+        assert_eq!(split_buffer(0x2000, 0x5000, 512), [(0x2000, 0x5000)]);
+    }
+
+    #[test]
+    fn qtd_fills_page_pointers() {
+        let words = qtd(TERMINATE, TERMINATE, 0, 0x1000_0f00, 0x200);
+        assert_eq!(&words[3..], &[0x1000_0f00, 0x1000_1000, 0, 0, 0]);
+
+        let t = token(Pid::In, 0x200, true, true);
+        assert_eq!(remaining(t), 0x200);
+        assert_eq!(t & 0x300, 1 << 8);
+        assert_ne!(t & TOKEN_TOGGLE, 0);
+        assert_ne!(t & TOKEN_ACTIVE, 0);
+        assert_eq!(token(Pid::Setup, 8, false, false) & 0x300, 2 << 8);
+    }
+
+    #[test]
+    fn short_packet_completes_bulk_but_waits_for_status() {
+        let done = |left: usize| token(Pid::In, left, false, false) & !TOKEN_ACTIVE;
+        let active = token(Pid::In, 512, false, false);
+
+        // 第一个 qTD 短包，第二个被跳过
+        let bulk = [(Stage::Data(1024), done(100)), (Stage::Data(512), active)];
+        assert_eq!(progress(&bulk), Progress::Done(924));
+
+        let ctrl = [
+            (Stage::Setup, done(0)),
+            (Stage::Data(64), done(46)),
+            (Stage::Status, token(Pid::Out, 0, true, true)),
+        ];
+        assert_eq!(progress(&ctrl), Progress::Pending);
+        let ctrl = [ctrl[0], ctrl[1], (Stage::Status, done(0))];
+        assert_eq!(progress(&ctrl), Progress::Done(18));
+    }
+
+    #[test]
+    fn halted_reports_error() {
+        let stall = TOKEN_HALTED;
+        let babble = TOKEN_HALTED | TOKEN_BABBLE;
+        assert_eq!(
+            progress(&[(Stage::Data(8), stall)]),
+            Progress::Failed(QtdError::Stall)
+        );
+        assert_eq!(
+            progress(&[(Stage::Setup, 0), (Stage::Data(8), babble)]),
+            Progress::Failed(QtdError::Babble)
+        );
+        assert_eq!(
+            progress(&[
+                (Stage::Setup, 0),
+                (Stage::Data(8), 0),
+                (Stage::Status, stall)
+            ]),
+            Progress::Failed(QtdError::StatusStall)
+        );
+    }
+
+    #[test]
+    fn qh_fields() {
+        // 高速 Hub 3 号端口下的低速中断端点
+        let info = QhInfo {
+            address: 5,
+            endpoint: 1,
+            speed: Speed::Low,
+            max_packet: 8,
+            control: false,
+            tt: Some((2, 3)),
+            s_mask: interrupt_s_mask(Speed::Low, 10),
+            mult: 1,
+        };
+        assert_eq!(info.characteristics(), 5 | 1 << 8 | 1 << 12 | 8 << 16);
+        assert_eq!(
+            info.capabilities(),
+            0x01 | 0x1c << 8 | 2 << 16 | 3 << 23 | 1 << 30
+        );
+
+        let info = QhInfo {
+            address: 0,
+            endpoint: 0,
+            speed: Speed::High,
+            max_packet: 64,
+            control: true,
+            tt: None,
+            s_mask: 0,
+            mult: 1,
+        };
+        assert_eq!(
+            info.characteristics(),
+            2 << 12 | 1 << 14 | 64 << 16 | 4 << 28
+        );
+        assert_eq!(info.capabilities(), 1 << 30);
+
+        assert_eq!(interrupt_s_mask(Speed::High, 1), 0xff);
+        assert_eq!(interrupt_s_mask(Speed::High, 3), 0x11);
+        assert_eq!(interrupt_s_mask(Speed::High, 4), 0x01);
+    }
+
+    #[test]
+    fn interrupt_service_interval_follows_s_mask() {
+        let us = Duration::from_micros;
+        assert_eq!(interrupt_service_interval(Speed::High, 1), us(125));
+        assert_eq!(interrupt_service_interval(Speed::High, 3), us(500));
+        assert_eq!(interrupt_service_interval(Speed::High, 7), us(1000));
+        assert_eq!(interrupt_service_interval(Speed::Full, 10), us(1000));
+    }
+}
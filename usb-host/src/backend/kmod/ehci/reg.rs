@@ -0,0 +1,175 @@
+//! EHCI 寄存器定义
+//!
+//! 参考 EHCI 规范第 2 章。能力寄存器按 32 位访问，部分 SoC 不支持字节读。
+
+use tock_registers::interfaces::*;
+use tock_registers::{LocalRegisterCopy, register_bitfields, register_structs, registers::*};
+
+use crate::Mmio;
+
+register_bitfields! [u32,
+    pub CAPLENGTH [
+        CAPLENGTH OFFSET(0) NUMBITS(8) [],
+        HCIVERSION OFFSET(16) NUMBITS(16) [],
+    ],
+
+    pub HCSPARAMS [
+        /// 根端口数量
+        N_PORTS OFFSET(0) NUMBITS(4) [],
+        /// 端口电源可由软件控制
+        PPC OFFSET(4) NUMBITS(1) [],
+        /// 伴随控制器数量，0 表示全速/低速设备无法使用
+        N_CC OFFSET(12) NUMBITS(4) [],
+    ],
+
+    pub HCCPARAMS [
+        AC64 OFFSET(0) NUMBITS(1) [],
+        /// 帧表长度可编程
+        PFLF OFFSET(1) NUMBITS(1) [],
+        /// 扩展能力在 PCI 配置空间中的偏移
+        EECP OFFSET(8) NUMBITS(8) [],
+    ],
+
+    pub USBCMD [
+        RS OFFSET(0) NUMBITS(1) [],
+        HCRESET OFFSET(1) NUMBITS(1) [],
+        /// 帧表长度，0 为 1024 项
+        FLS OFFSET(2) NUMBITS(2) [],
+        PSE OFFSET(4) NUMBITS(1) [],
+        ASE OFFSET(5) NUMBITS(1) [],
+        /// 异步调度推进时产生中断（Interrupt on Async Advance Doorbell）
+        IAAD OFFSET(6) NUMBITS(1) [],
+        /// 中断阈值，单位为微帧
+        ITC OFFSET(16) NUMBITS(8) [],
+    ],
+
+    pub USBSTS [
+        USBINT OFFSET(0) NUMBITS(1) [],
+        USBERRINT OFFSET(1) NUMBITS(1) [],
+        PCD OFFSET(2) NUMBITS(1) [],
+        FLR OFFSET(3) NUMBITS(1) [],
+        HSE OFFSET(4) NUMBITS(1) [],
+        IAA OFFSET(5) NUMBITS(1) [],
+        HCHALTED OFFSET(12) NUMBITS(1) [],
+        PSS OFFSET(14) NUMBITS(1) [],
+        ASS OFFSET(15) NUMBITS(1) [],
+    ],
+
+    pub USBINTR [
+        USBINT OFFSET(0) NUMBITS(1) [],
+        USBERRINT OFFSET(1) NUMBITS(1) [],
+        PCD OFFSET(2) NUMBITS(1) [],
+        FLR OFFSET(3) NUMBITS(1) [],
+        HSE OFFSET(4) NUMBITS(1) [],
+        IAA OFFSET(5) NUMBITS(1) [],
+    ],
+
+    pub CONFIGFLAG [
+        /// 置位后全部端口默认路由到 EHCI
+        CF OFFSET(0) NUMBITS(1) [],
+    ],
+
+    pub PORTSC [
+        CCS OFFSET(0) NUMBITS(1) [],
+        CSC OFFSET(1) NUMBITS(1) [],
+        PED OFFSET(2) NUMBITS(1) [],
+        PEDC OFFSET(3) NUMBITS(1) [],
+        OCA OFFSET(4) NUMBITS(1) [],
+        OCC OFFSET(5) NUMBITS(1) [],
+        FPR OFFSET(6) NUMBITS(1) [],
+        SUSPEND OFFSET(7) NUMBITS(1) [],
+        PR OFFSET(8) NUMBITS(1) [],
+        /// 复位前的 D+/D- 线状态，K 态表示低速设备
+        LS OFFSET(10) NUMBITS(2) [
+            SE0 = 0,
+            KState = 1,
+            JState = 2,
+        ],
+        PP OFFSET(12) NUMBITS(1) [],
+        /// 端口归伴随控制器所有
+        PO OFFSET(13) NUMBITS(1) [],
+        PTC OFFSET(16) NUMBITS(4) [],
+    ],
+];
+
+register_structs! {
+    pub CapRegisters {
+        (0x00 => pub caplength: ReadOnly<u32, CAPLENGTH::Register>),
+        (0x04 => pub hcsparams: ReadOnly<u32, HCSPARAMS::Register>),
+        (0x08 => pub hccparams: ReadOnly<u32, HCCPARAMS::Register>),
+        (0x0C => @END),
+    },
+
+    pub OpRegisters {
+        (0x00 => pub usbcmd: ReadWrite<u32, USBCMD::Register>),
+        (0x04 => pub usbsts: ReadWrite<u32, USBSTS::Register>),
+        (0x08 => pub usbintr: ReadWrite<u32, USBINTR::Register>),
+        (0x0C => pub frindex: ReadWrite<u32>),
+        (0x10 => pub ctrldssegment: ReadWrite<u32>),
+        (0x14 => pub periodiclistbase: ReadWrite<u32>),
+        (0x18 => pub asynclistaddr: ReadWrite<u32>),
+        (0x1C => _rsv),
+        (0x40 => pub configflag: ReadWrite<u32, CONFIGFLAG::Register>),
+        (0x44 => pub portsc: [ReadWrite<u32, PORTSC::Register>; 15]),
+        (0x80 => @END),
+    }
+}
+
+/// PORTSC 中写 1 清除的变化位
+const PORTSC_CHANGE_MASK: u32 = (1 << 1) | (1 << 3) | (1 << 5);
+
+/// EHCI 寄存器访问器
+#[derive(Clone, Copy)]
+pub struct EhciRegs {
+    cap: usize,
+    op: usize,
+}
+
+impl EhciRegs {
+    /// # Safety
+    ///
+    /// 调用者必须确保 `mmio` 指向有效的 EHCI 寄存器区域
+    pub unsafe fn new(mmio: Mmio) -> Self {
+        let cap = mmio.as_ptr() as usize;
+        let caplength = unsafe { &*(cap as *const CapRegisters) }
+            .caplength
+            .read(CAPLENGTH::CAPLENGTH) as usize;
+        Self {
+            cap,
+            op: cap + caplength,
+        }
+    }
+
+    pub fn cap(&self) -> &'static CapRegisters {
+        unsafe { &*(self.cap as *const CapRegisters) }
+    }
+
+    pub fn op(&self) -> &'static OpRegisters {
+        unsafe { &*(self.op as *const OpRegisters) }
+    }
+
+    pub fn port_count(&self) -> usize {
+        self.cap().hcsparams.read(HCSPARAMS::N_PORTS) as usize
+    }
+
+    pub fn portsc(&self, idx: usize) -> LocalRegisterCopy<u32, PORTSC::Register> {
+        LocalRegisterCopy::new(self.op().portsc[idx].get())
+    }
+
+    /// 读改写 PORTSC，写回时屏蔽变化位，避免误清除
+    pub fn modify_portsc(
+        &self,
+        idx: usize,
+        f: impl FnOnce(&mut LocalRegisterCopy<u32, PORTSC::Register>),
+    ) {
+        let mut v = LocalRegisterCopy::new(self.op().portsc[idx].get() & !PORTSC_CHANGE_MASK);
+        f(&mut v);
+        self.op().portsc[idx].set(v.get());
+    }
+
+    /// 清除端口的全部变化位
+    pub fn ack_port_changes(&self, idx: usize) {
+        let v = self.op().portsc[idx].get();
+        self.op().portsc[idx].set(v);
+    }
+}
@@ -0,0 +1,356 @@
+//! 异步与周期调度
+//!
+//! 全部 QH 来自同一个池，摘除 QH 时可以直接改写前驱的水平链接。异步调度是以头 QH
+//! 为首的环形链表；周期帧表的每一项都指向同一条中断 QH 链，即每帧访问一次全部
+//! 中断端点。参考 EHCI 规范 4.8（异步调度）与 4.6（周期调度）。
+
+use alloc::vec::Vec;
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    task::Waker,
+    time::Duration,
+};
+
+use dma_api::{DArray, DmaDirection};
+use futures::task::AtomicWaker;
+use mbarrier::mb;
+use spin::Mutex;
+use tock_registers::interfaces::*;
+use usb_if::{err::USBError, host::hub::Speed};
+
+use super::{
+    qtd::{QhInfo, TERMINATE, TOKEN_HALTED},
+    reg::{EhciRegs, USBCMD, USBSTS},
+};
+use crate::{
+    backend::kmod::{le::DmaLe, mem::MemTag},
+    osal::Kernel,
+};
+
+/// QH 与 qTD 各占 64 字节，避免与控制器回写的相邻结构共享缓存行
+pub(crate) type Slot = [u32; 16];
+pub(crate) const SLOT_SIZE: usize = core::mem::size_of::<Slot>();
+
+/// 水平链接指针的类型字段：QH
+const LINK_TYPE_QH: u32 = 1 << 1;
+/// QH 端点特征字的 H 位，标记异步调度的头
+const QH_HEAD: u32 = 1 << 15;
+
+/// QH 各字的下标
+pub(crate) const QH_LINK: usize = 0;
+const QH_CHARACTERISTICS: usize = 1;
+const QH_CAPABILITIES: usize = 2;
+pub(crate) const QH_CURRENT: usize = 3;
+pub(crate) const QH_NEXT: usize = 4;
+pub(crate) const QH_ALT: usize = 5;
+pub(crate) const QH_TOKEN: usize = 6;
+
+/// QH 池大小，下标 0 为异步调度的头 QH
+const MAX_QH: usize = 64;
+const FRAME_LIST_LEN: usize = 1024;
+const ASYNC_HEAD: usize = 0;
+/// 等待 Interrupt on Async Advance 的上限
+const IAA_TIMEOUT: Duration = Duration::from_millis(10);
+/// 摘除中断 QH 后等待控制器离开当前帧
+const PERIODIC_UNLINK_DELAY: Duration = Duration::from_millis(2);
+
+pub(crate) struct Schedule {
+    reg: EhciRegs,
+    kernel: Kernel,
+    inner: Mutex<Inner>,
+    /// 串行化 Async Advance Doorbell，每次摘除独占一次门铃
+    doorbell: Mutex<()>,
+    /// 中断处理中确认到的 IAA 次数
+    iaa: AtomicU64,
+    wakers: Vec<AtomicWaker>,
+}
+
+struct Inner {
+    qhs: DArray<Slot>,
+    free: Vec<usize>,
+    frame_list: DArray<u32>,
+    /// 异步链表中头 QH 之后的 QH，按链接顺序
+    async_list: Vec<usize>,
+    /// 中断 QH 链，按链接顺序
+    periodic: Vec<usize>,
+    /// 各 QH 所属的设备地址
+    owner: [u8; MAX_QH],
+    /// 摘除后未等到 Async Advance 的 QH，控制器可能仍缓存着它们
+    unconfirmed: Vec<usize>,
+}
+
+impl Inner {
+    fn link_ptr(&self, qh: usize) -> u32 {
+        (self.qhs.dma_addr().as_u64() as usize + qh * SLOT_SIZE) as u32 | LINK_TYPE_QH
+    }
+
+    fn set_link(&mut self, qh: usize, link: u32) {
+        let mut slot = self.qhs.read_le(qh).unwrap();
+        slot[QH_LINK] = link;
+        self.qhs.set_le(qh, slot);
+    }
+
+    fn set_frame_list(&mut self, link: u32) {
+        for i in 0..FRAME_LIST_LEN {
+            self.frame_list.set_le(i, link);
+        }
+    }
+}
+
+impl Schedule {
+    pub fn new(reg: EhciRegs, kernel: &Kernel) -> Result<Self, USBError> {
+        let dma = kernel.with_tag(MemTag::Ring);
+        let qhs = dma
+            .array_zero_with_align::<Slot>(MAX_QH, SLOT_SIZE, DmaDirection::Bidirectional)
+            .map_err(|_| USBError::NoMemory)?;
+        let frame_list = dma
+            .array_zero_with_align::<u32>(FRAME_LIST_LEN, 4096, DmaDirection::ToDevice)
+            .map_err(|_| USBError::NoMemory)?;
+
+        let mut inner = Inner {
+            qhs,
+            free: (1..MAX_QH).rev().collect(),
+            frame_list,
+            async_list: Vec::new(),
+            periodic: Vec::new(),
+            owner: [0; MAX_QH],
+            unconfirmed: Vec::new(),
+        };
+        inner.set_frame_list(TERMINATE);
+
+        // 头 QH 自成环，overlay 处于 Halted，控制器只用它检测一轮遍历结束
+        let mut head = Slot::default();
+        head[QH_LINK] = inner.link_ptr(ASYNC_HEAD);
+        head[QH_CHARACTERISTICS] = QH_HEAD
+            | QhInfo {
+                address: 0,
+                endpoint: 0,
+                speed: Speed::High,
+                max_packet: 64,
+                control: false,
+                tt: None,
+                s_mask: 0,
+                mult: 1,
+            }
+            .characteristics();
+        head[QH_NEXT] = TERMINATE;
+        head[QH_ALT] = TERMINATE;
+        head[QH_TOKEN] = TOKEN_HALTED;
+        inner.qhs.set_le(ASYNC_HEAD, head);
+
+        Ok(Self {
+            reg,
+            kernel: kernel.clone(),
+            inner: Mutex::new(inner),
+            doorbell: Mutex::new(()),
+            iaa: AtomicU64::new(0),
+            wakers: (0..MAX_QH).map(|_| AtomicWaker::new()).collect(),
+        })
+    }
+
+    pub fn async_list_addr(&self) -> u32 {
+        self.inner.lock().link_ptr(ASYNC_HEAD) & !LINK_TYPE_QH
+    }
+
+    pub fn frame_list_addr(&self) -> u32 {
+        self.inner.lock().frame_list.dma_addr().as_u64() as u32
+    }
+
+    /// 从池中分配 QH，overlay 指向 `first` 处的 qTD，尚未加入调度
+    pub fn alloc(&self, info: &QhInfo, first: u32) -> Result<usize, USBError> {
+        let mut inner = self.inner.lock();
+        let qh = inner.free.pop().ok_or(USBError::NoMemory)?;
+        let mut slot = Slot::default();
+        slot[QH_LINK] = TERMINATE;
+        slot[QH_CHARACTERISTICS] = info.characteristics();
+        slot[QH_CAPABILITIES] = info.capabilities();
+        slot[QH_NEXT] = first;
+        slot[QH_ALT] = TERMINATE;
+        inner.qhs.set_le(qh, slot);
+        inner.owner[qh] = info.address;
+        Ok(qh)
+    }
+
+    /// 加入调度，`s_mask` 非零的 QH 加入周期调度
+    pub fn link(&self, qh: usize) {
+        let mut inner = self.inner.lock();
+        if inner.async_list.contains(&qh) || inner.periodic.contains(&qh) {
+            return;
+        }
+        let slot = inner.qhs.read_le(qh).unwrap();
+        let ptr = inner.link_ptr(qh);
+        if slot[QH_CAPABILITIES] & 0xff != 0 {
+            // 新 QH 插在链首，先指向原链首再发布
+            let next = inner
+                .periodic
+                .first()
+                .map_or(TERMINATE, |&first| inner.link_ptr(first));
+            inner.set_link(qh, next);
+            mb();
+            inner.set_frame_list(ptr);
+            inner.periodic.insert(0, qh);
+        } else {
+            let prev = inner.async_list.last().copied().unwrap_or(ASYNC_HEAD);
+            let head = inner.link_ptr(ASYNC_HEAD);
+            inner.set_link(qh, head);
+            mb();
+            inner.set_link(prev, ptr);
+            inner.async_list.push(qh);
+        }
+    }
+
+    /// 摘除 QH，成功返回时控制器已不再访问它
+    ///
+    /// 等待 Async Advance 超时返回 [`USBError::Timeout`]，此时 QH 已不在调度中，
+    /// 但控制器可能仍缓存着它，调用方不能改写或释放 QH 及其 qTD。
+    pub fn unlink(&self, qh: usize) -> Result<(), USBError> {
+        let mut inner = self.inner.lock();
+        if let Some(i) = inner.async_list.iter().position(|&q| q == qh) {
+            let _doorbell = self.doorbell.lock();
+            let prev = if i == 0 {
+                ASYNC_HEAD
+            } else {
+                inner.async_list[i - 1]
+            };
+            let next = inner.qhs.read_le(qh).unwrap()[QH_LINK];
+            inner.set_link(prev, next);
+            inner.async_list.remove(i);
+            drop(inner);
+            mb();
+            let res = self.wait_async_advance();
+            let mut inner = self.inner.lock();
+            match res {
+                // 门铃确认时控制器已丢弃此前摘除的全部 QH 的缓存
+                Ok(()) => inner.unconfirmed.clear(),
+                Err(_) => inner.unconfirmed.push(qh),
+            }
+            return res;
+        } else if let Some(i) = inner.periodic.iter().position(|&q| q == qh) {
+            let next = inner.qhs.read_le(qh).unwrap()[QH_LINK];
+            if i == 0 {
+                inner.set_frame_list(next);
+            } else {
+                let prev = inner.periodic[i - 1];
+                inner.set_link(prev, next);
+            }
+            inner.periodic.remove(i);
+            drop(inner);
+            mb();
+            if self.running() {
+                self.kernel.delay(PERIODIC_UNLINK_DELAY);
+            }
+        }
+        if self.inner.lock().unconfirmed.contains(&qh) {
+            return Err(USBError::Timeout);
+        }
+        Ok(())
+    }
+
+    /// 摘除并归还 QH
+    ///
+    /// 控制器未确认丢弃缓存时 QH 不再复用，返回错误，调用方需同样保留其 qTD。
+    pub fn free(&self, qh: usize) -> Result<(), USBError> {
+        let res = self.unlink(qh);
+        let mut inner = self.inner.lock();
+        if res.is_err() {
+            warn!("EHCI QH {qh} may still be cached, leaking it");
+            return res;
+        }
+        inner.owner[qh] = 0;
+        inner.free.push(qh);
+        Ok(())
+    }
+
+    /// 摘除设备的全部 QH，端点释放时再归还
+    pub fn unlink_device(&self, address: u8) -> Result<(), USBError> {
+        let qhs: Vec<usize> = {
+            let inner = self.inner.lock();
+            inner
+                .async_list
+                .iter()
+                .chain(&inner.periodic)
+                .copied()
+                .filter(|&qh| inner.owner[qh] == address)
+                .collect()
+        };
+        let mut res = Ok(());
+        for qh in qhs {
+            res = res.and(self.unlink(qh));
+        }
+        res
+    }
+
+    pub fn read(&self, qh: usize) -> Slot {
+        self.inner.lock().qhs.read_le(qh).unwrap()
+    }
+
+    /// 改写 QH，调用者需保证控制器此时不会访问被改写的字段
+    pub fn modify(&self, qh: usize, f: impl FnOnce(&mut Slot)) {
+        let mut inner = self.inner.lock();
+        let mut slot = inner.qhs.read_le(qh).unwrap();
+        f(&mut slot);
+        inner.qhs.set_le(qh, slot);
+    }
+
+    /// 更新 QH 的静态字段，先摘除再重新加入
+    pub fn update(&self, qh: usize, info: &QhInfo) -> Result<(), USBError> {
+        if let Err(e) = self.unlink(qh) {
+            self.link(qh);
+            return Err(e);
+        }
+        self.modify(qh, |slot| {
+            slot[QH_CHARACTERISTICS] = info.characteristics();
+            slot[QH_CAPABILITIES] = info.capabilities();
+        });
+        self.inner.lock().owner[qh] = info.address;
+        self.link(qh);
+        Ok(())
+    }
+
+    pub fn register_waker(&self, qh: usize, waker: &Waker) {
+        self.wakers[qh].register(waker);
+    }
+
+    /// 在中断中调用：控制器不报告完成的是哪个 QH，唤醒全部端点
+    pub fn wake_all(&self) {
+        for waker in &self.wakers {
+            waker.wake();
+        }
+    }
+
+    /// 在中断中调用：记录一次 Interrupt on Async Advance
+    pub fn async_advanced(&self) {
+        self.iaa.fetch_add(1, Ordering::AcqRel);
+    }
+
+    fn running(&self) -> bool {
+        !self.reg.op().usbsts.is_set(USBSTS::HCHALTED)
+    }
+
+    /// 敲响 Async Advance Doorbell，等待控制器丢弃对已摘除 QH 的缓存
+    ///
+    /// 中断处理可能先一步清除 USBSTS.IAA，因此同时检查中断中记录的次数。
+    fn wait_async_advance(&self) -> Result<(), USBError> {
+        let op = self.reg.op();
+        if !self.running() || !op.usbcmd.is_set(USBCMD::ASE) {
+            return Ok(());
+        }
+        let seen = self.iaa.load(Ordering::Acquire);
+        op.usbcmd.modify(USBCMD::IAAD::SET);
+        let deadline = self.kernel.now() + IAA_TIMEOUT;
+        loop {
+            if op.usbsts.is_set(USBSTS::IAA) {
+                op.usbsts.write(USBSTS::IAA::SET);
+                return Ok(());
+            }
+            if self.iaa.load(Ordering::Acquire) != seen {
+                return Ok(());
+            }
+            if self.kernel.now() >= deadline {
+                warn!("EHCI async advance doorbell timeout");
+                return Err(USBError::Timeout);
+            }
+            self.kernel.delay(Duration::from_micros(125));
+        }
+    }
+}
@@ -4,23 +4,31 @@
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
-use core::time::Duration;
+use core::{
+    future::poll_fn,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
 use futures::{FutureExt, future::BoxFuture};
 
 use usb_if::{
     descriptor::{Class, ConfigurationDescriptor, DeviceDescriptor, EndpointType},
+    endpoint::{RequestId, TransferRequest},
     err::USBError,
     host::{
         ControlSetup,
-        hub::{HubDescriptor, PortFeature, PortStatus, PortStatusChange, Speed},
+        hub::{HubDescriptor, PortFeature, PortStatus, PortStatusChange, Speed, TestSelector},
     },
     transfer::{Recipient, Request, RequestType},
 };
 
 use super::HubOp;
 use crate::{
-    Device,
-    backend::kmod::hub::{HubInfo, PortChangeInfo},
+    Device, Endpoint,
+    backend::kmod::{
+        hub::{HubInfo, HubPortStatus, PortChangeInfo},
+        port_event::PortTransition,
+    },
     osal::Kernel,
 };
 
@@ -35,6 +43,15 @@ const HUB_DEBOUNCE_STEP: u64 = 25;
 /// 防抖动稳定时间 (100ms)
 const HUB_DEBOUNCE_STABLE: u64 = 100;
 
+/// 端口恢复后、设备开始接收传输前的恢复时间 (USB 2.0 7.1.7.7 TRSMRCY)
+const HUB_RESUME_RECOVERY_MS: u64 = 10;
+
+/// 等待 Hub 结束恢复信号的上限，规范要求的恢复信号为 20ms
+const HUB_RESUME_TIMEOUT_MS: u64 = 100;
+
+/// 端口上电后的最短等待时间，描述符中的 bPwrOn2PwrGood 更短时按此等待 (参照 Linux hub_power_on)
+const HUB_POWER_ON_MIN_MS: u64 = 100;
+
 /*
  * Hub Device descriptor
  * USB Hub class device protocols
@@ -49,8 +66,22 @@ const HUB_PR_SS: u8 = 3; /* Super speed hub */
 /// 表示一个 Hub 设备（Root Hub 或 External Hub）。
 pub struct HubDevice {
     settings: HubSettings,
+    /// 状态变化中断端点，需先于 `data` 中的设备释放
+    status_ep: Option<Endpoint>,
+    /// 状态变化位图：bit 0 为 Hub 自身，bit N 为端口 N
+    status_buf: Vec<u8>,
+    /// 在途的状态变化请求，等待被取消时保留，下次继续等待同一请求
+    status_pending: Option<RequestId>,
     data: Box<Inner>,
     kernel: Kernel,
+    /// 读取端口状态时记录的状态变化，见 [`HubOp::take_port_events`]
+    events: Vec<(u8, PortTransition)>,
+    /// 已探测设备断开（或被换下）的端口，见 [`HubOp::take_disconnected_ports`]
+    disconnected: Vec<u8>,
+    /// 挂起后因远程唤醒恢复的端口，见 [`HubOp::take_resumed_ports`]
+    resumed: Vec<u8>,
+    /// 断开的端口上仍有设备，下一次等待立即返回以重新枚举
+    recheck: bool,
 }
 
 struct Inner {
@@ -77,6 +108,8 @@ pub struct HubSettings {
     pub config_value: u8,
     pub interface_number: u8,
     pub alt_setting: u8,
+    /// 状态变化中断 IN 端点地址
+    pub status_endpoint: u8,
 }
 
 impl HubOp for HubDevice {
@@ -91,11 +124,49 @@ impl HubOp for HubDevice {
     fn changed_ports<'a>(&'a mut self) -> BoxFuture<'a, Result<Vec<PortChangeInfo>, USBError>> {
         self.changed_ports().boxed()
     }
+
+    fn take_port_events(&mut self) -> Vec<(u8, PortTransition)> {
+        core::mem::take(&mut self.events)
+    }
+
+    fn wait_port_change<'a>(&'a mut self) -> BoxFuture<'a, ()> {
+        poll_fn(move |cx| self.poll_status_change(cx)).boxed()
+    }
+
+    fn take_disconnected_ports(&mut self) -> Vec<u8> {
+        let ports = core::mem::take(&mut self.disconnected);
+        self.recheck |= !ports.is_empty();
+        ports
+    }
+
+    fn suspend_port<'a>(&'a mut self, port: u8) -> BoxFuture<'a, Result<(), USBError>> {
+        self.suspend_port(port).boxed()
+    }
+
+    fn resume_port<'a>(&'a mut self, port: u8) -> BoxFuture<'a, Result<(), USBError>> {
+        self.resume_port(port).boxed()
+    }
+
+    fn take_resumed_ports(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.resumed)
+    }
+
+    fn port_status(&self) -> Vec<HubPortStatus> {
+        self.ports().collect()
+    }
+
+    fn set_port_test_mode<'a>(
+        &'a mut self,
+        port: u8,
+        selector: TestSelector,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        self.set_port_test_mode(port, selector).boxed()
+    }
 }
 
 impl HubDevice {
     /// returns (config_value, interface_number) if the device is a hub
-    pub fn is_hub(
+    pub(crate) fn is_hub(
         desc: &DeviceDescriptor,
         configs: &[ConfigurationDescriptor],
     ) -> Option<HubSettings> {
@@ -127,6 +198,7 @@ impl HubDevice {
                     config_value: config.configuration_value,
                     interface_number: interface.interface_number,
                     alt_setting: alt.alternate_setting,
+                    status_endpoint: alt.endpoints[0].address,
                 });
             }
         }
@@ -135,7 +207,7 @@ impl HubDevice {
     }
 
     /// 创建新的 Hub 设备
-    pub async fn new(
+    pub(crate) async fn new(
         dev: Device,
         settings: HubSettings,
         root_port_id: u8,
@@ -144,6 +216,9 @@ impl HubDevice {
     ) -> Result<Self, USBError> {
         Ok(Self {
             settings,
+            status_ep: None,
+            status_buf: Vec::new(),
+            status_pending: None,
             data: Box::new(Inner {
                 state: HubState::Uninitialized,
                 num_ports: 0,
@@ -154,10 +229,92 @@ impl HubDevice {
                 root_port_id,
             }),
             kernel: kernel.clone(),
+            events: Vec::new(),
+            disconnected: Vec::new(),
+            resumed: Vec::new(),
+            recheck: false,
+        })
+    }
+
+    /// 挂在 [`MockDevice`](crate::backend::kmod::test_core::MockDevice) 上、已有
+    /// `num_ports` 个未连接端口的 Hub
+    #[cfg(test)]
+    pub(crate) fn mock(id: usize, num_ports: u8) -> Self {
+        use crate::backend::kmod::test_core::MockDevice;
+
+        let dev: Box<dyn crate::backend::ty::DeviceOp> = Box::new(MockDevice::new(id, 0x09));
+        let settings = HubSettings {
+            config_value: 1,
+            interface_number: 0,
+            alt_setting: 0,
+            status_endpoint: 0x81,
+        };
+        let kernel = crate::backend::kmod::test_kernel::HeapKernel::kernel();
+        let mut hub = Self::new(dev.into(), settings, 1, 0, &kernel)
+            .now_or_never()
+            .expect("constructing a hub does not wait")
+            .expect("constructing a hub does not fail");
+        hub.data.num_ports = num_ports;
+        hub.data.ports = (1..=num_ports).map(Port::new).collect();
+        hub
+    }
+
+    /// 各下游端口的状态快照
+    ///
+    /// 连接与速度来自最近一次端口变化处理时读取的端口状态，不会向 Hub 发起请求。
+    pub fn ports(&self) -> impl Iterator<Item = HubPortStatus> + '_ {
+        self.data.ports.iter().map(|port| HubPortStatus {
+            port: port.id,
+            state: port.state,
+            connected: port.status.connected,
+            speed: (port.status.connected && port.status.enabled).then_some(port.status.speed),
         })
     }
 
-    pub async fn changed_ports(&mut self) -> Result<Vec<PortChangeInfo>, USBError> {
+    /// 等待状态变化端点报告任一端口（或 Hub 自身）有变化
+    ///
+    /// 端点出错（通常是 Hub 已被拔出）后不再返回，由上游 Hub 报告断开。
+    fn poll_status_change(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if core::mem::take(&mut self.recheck) {
+            return Poll::Ready(());
+        }
+        let Some(ep) = self.status_ep.as_mut() else {
+            return Poll::Pending;
+        };
+        let id = match self.status_pending {
+            Some(id) => id,
+            None => match ep.submit(TransferRequest::interrupt_in(&mut self.status_buf[..])) {
+                Ok(id) => {
+                    self.status_pending = Some(id);
+                    id
+                }
+                Err(e) => {
+                    warn!("Hub slot {} status endpoint: {e:?}", self.slot_id());
+                    self.status_ep = None;
+                    return Poll::Pending;
+                }
+            },
+        };
+        let res = ready!(ep.poll_request(id, cx));
+        self.status_pending = None;
+        match res {
+            Ok(_) => {
+                trace!(
+                    "Hub slot {} status change: {:02x?}",
+                    self.slot_id(),
+                    self.status_buf
+                );
+                Poll::Ready(())
+            }
+            Err(e) => {
+                debug!("Hub slot {} status endpoint stopped: {e:?}", self.slot_id());
+                self.status_ep = None;
+                Poll::Pending
+            }
+        }
+    }
+
+    pub(crate) async fn changed_ports(&mut self) -> Result<Vec<PortChangeInfo>, USBError> {
         let mut changed_ports = vec![];
 
         // 收集所有端口号，避免借用冲突
@@ -167,24 +324,55 @@ impl HubDevice {
             let (status, change) = self.get_port_status(port_id).await?;
 
             debug!("Port {} status: {:?}", port_id, status);
+            self.data.ports[port_idx as usize].status = status;
 
             if change.connection_changed {
                 info!("Port {} connection changed: {}", port_id, status.connected);
                 // 清除连接变化标志
                 self.clear_port_feature(port_id, PortFeature::CConnection)
                     .await?;
+                let port = &mut self.data.ports[port_idx as usize];
+                // 已探测的设备断开或被换下，先由主机释放，再重新枚举
+                if port.state == PortState::Probed {
+                    port.state = PortState::Uninit;
+                    port.tt_required = false;
+                    if !self.disconnected.contains(&port_id) {
+                        self.disconnected.push(port_id);
+                    }
+                }
+                if status.connected {
+                    self.events.push((port_id, PortTransition::Connected));
+                }
+            }
+
+            if change.over_current_changed {
+                self.clear_port_feature(port_id, PortFeature::COverCurrent)
+                    .await?;
+                if status.over_current {
+                    warn!("Port {} over-current", port_id);
+                    self.events.push((port_id, PortTransition::OverCurrent));
+                }
             }
 
-            if status.connected && self.data.ports[port_idx as usize].state == PortState::Uninit {
+            if status.connected
+                && self.data.ports[port_idx as usize].state == PortState::Uninit
+                && !self.disconnected.contains(&port_id)
+            {
                 info!(
                     "Port {} connection changed: connected={}, enabled={}",
                     port_id, status.connected, status.enabled
                 );
 
                 // 执行端口验证流程（参考 xHCI Root Hub）
-                let validation_result = self.handle_port_connection(port_id, &status).await?;
+                let validation_result = self
+                    .handle_port_connection(port_id, &status)
+                    .await
+                    .inspect_err(|_| self.events.push((port_id, PortTransition::Error)))?;
 
-                self.data.ports[port_idx as usize].state = PortState::Probed;
+                let port = &mut self.data.ports[port_idx as usize];
+                port.state = PortState::Probed;
+                port.status.enabled = true;
+                port.status.speed = validation_result.port_speed;
 
                 changed_ports.push(validation_result);
             }
@@ -193,8 +381,9 @@ impl HubDevice {
                 info!("Port {} enabled changed: {}", port_id, status.enabled);
                 self.clear_port_feature(port_id, PortFeature::CEnable)
                     .await?;
-                if let Some(port) = self.data.ports.iter_mut().find(|p| p.id == port_id) {
-                    port.status = status;
+                // Hub 只在因错误禁用端口时置位 C_PORT_ENABLE
+                if !status.enabled && status.connected {
+                    self.events.push((port_id, PortTransition::Error));
                 }
             }
 
@@ -202,8 +391,16 @@ impl HubDevice {
                 debug!("Port {} reset complete", port_id);
                 self.clear_port_feature(port_id, PortFeature::CReset)
                     .await?;
-                if let Some(port) = self.data.ports.iter_mut().find(|p| p.id == port_id) {
-                    port.status = status;
+            }
+
+            if change.suspend_changed {
+                self.clear_port_feature(port_id, PortFeature::CSuspend)
+                    .await?;
+                // 主机恢复端口时会自行清除 C_PORT_SUSPEND，这里只会是设备发起的远程唤醒，
+                // Hub 已发出恢复信号
+                if status.enabled && !status.suspended && !self.resumed.contains(&port_id) {
+                    info!("Port {} remote wakeup", port_id);
+                    self.resumed.push(port_id);
                 }
             }
         }
@@ -211,11 +408,112 @@ impl HubDevice {
         Ok(changed_ports)
     }
 
+    /// 挂起下游端口（USB 2.0 11.24.2.7.1.3）
+    ///
+    /// SuperSpeed Hub 需通过 PORT_LINK_STATE 请求进入 U3，暂不支持。
+    pub(crate) async fn suspend_port(&mut self, port_id: u8) -> Result<(), USBError> {
+        self.check_suspend_port(port_id)?;
+        let (status, _) = self.get_port_status(port_id).await?;
+        if !status.enabled {
+            return Err(USBError::NotFound);
+        }
+        if !status.suspended {
+            self.set_port_feature(port_id, PortFeature::Suspend).await?;
+        }
+        self.data.ports[(port_id - 1) as usize].status.suspended = true;
+        debug!("Hub slot {} port {port_id} suspended", self.slot_id());
+        Ok(())
+    }
+
+    /// 恢复挂起的下游端口
+    ///
+    /// Hub 发出 20ms 恢复信号后清除 PORT_SUSPEND 并置位 C_PORT_SUSPEND（USB 2.0 11.5.1.10）。
+    /// 远程唤醒时 Hub 已自行完成恢复信号，只需等待恢复时间。
+    pub(crate) async fn resume_port(&mut self, port_id: u8) -> Result<(), USBError> {
+        self.check_suspend_port(port_id)?;
+        let (status, _) = self.get_port_status(port_id).await?;
+        if status.suspended {
+            self.clear_port_feature(port_id, PortFeature::Suspend)
+                .await?;
+        }
+
+        const CHECK_INTERVAL_MS: u64 = 10;
+        let mut waited = 0;
+        loop {
+            let (status, change) = self.get_port_status(port_id).await?;
+            if change.suspend_changed {
+                self.clear_port_feature(port_id, PortFeature::CSuspend)
+                    .await?;
+            }
+            if !status.connected {
+                return Err(USBError::NotFound);
+            }
+            if !status.suspended {
+                break;
+            }
+            if waited >= HUB_RESUME_TIMEOUT_MS {
+                warn!("Port {port_id} resume timeout after {waited}ms");
+                return Err(USBError::Timeout);
+            }
+            self.kernel.delay(Duration::from_millis(CHECK_INTERVAL_MS));
+            waited += CHECK_INTERVAL_MS;
+        }
+
+        self.kernel
+            .delay(Duration::from_millis(HUB_RESUME_RECOVERY_MS));
+        self.data.ports[(port_id - 1) as usize].status.suspended = false;
+        debug!("Hub slot {} port {port_id} resumed", self.slot_id());
+        Ok(())
+    }
+
+    fn check_suspend_port(&self, port_id: u8) -> Result<(), USBError> {
+        if self.is_superspeed() {
+            return Err(USBError::NotSupported);
+        }
+        if port_id == 0 || port_id > self.data.num_ports {
+            return Err(USBError::InvalidParameter);
+        }
+        Ok(())
+    }
+
+    /// 使下游端口进入电气测试模式（USB 2.0 11.24.2.13）
+    ///
+    /// 规范要求 Hub 的其余端口处于禁用、断开或挂起状态，这里先挂起所有已启用的端口。
+    /// 测试结束后 Hub 需要复位或重新上电才能恢复工作。SuperSpeed Hub 的合规测试
+    /// 使用链路层的 Compliance 模式，不支持该请求。
+    pub(crate) async fn set_port_test_mode(
+        &mut self,
+        port_id: u8,
+        selector: TestSelector,
+    ) -> Result<(), USBError> {
+        if self.is_superspeed() {
+            return Err(USBError::NotSupported);
+        }
+        if port_id == 0 || port_id > self.data.num_ports {
+            return Err(USBError::InvalidParameter);
+        }
+
+        for port in 1..=self.data.num_ports {
+            let (status, _) = self.get_port_status(port).await?;
+            if status.enabled && !status.suspended {
+                debug!("Suspending port {port} before test mode");
+                self.set_port_feature(port, PortFeature::Suspend).await?;
+            }
+        }
+
+        info!(
+            "Hub slot {}: port {port_id} enters {selector:?}",
+            self.slot_id()
+        );
+        self.set_port_feature_indexed(PortFeature::Test, ((selector as u16) << 8) | port_id as u16)
+            .await
+    }
+
     pub fn is_superspeed(&self) -> bool {
         self.data.dev.descriptor().protocol == 3
     }
 
-    pub async fn configure(&mut self, info: HubInfo) -> Result<HubInfo, USBError> {
+    pub(crate) async fn configure(&mut self, info: HubInfo) -> Result<HubInfo, USBError> {
         // 第二阶段：获取 Hub 描述符（带重试）
         debug!("Configuring hub device, depth={}...", info.hub_depth);
         let mut info = info;
@@ -265,7 +563,13 @@ impl HubDevice {
             HUB_PR_HS_MULTI_TT => {
                 info.speed = Speed::High;
                 debug!("Hub is High Speed with Multiple TTs");
-                match self.data.dev.claim_interface(0, 1).await {
+                // Multi-TT Hub 的 alternate setting 1 启用每端口一个 TT
+                match self
+                    .data
+                    .dev
+                    .claim_interface(self.settings.interface_number, 1)
+                    .await
+                {
                     Ok(_) => {
                         debug!("TT per port");
                         info.tt.multi = true;
@@ -328,12 +632,27 @@ impl HubDevice {
             debug!("Set hub depth to {}", info.hub_depth);
         }
 
+        if !info.tt.multi {
+            self.data
+                .dev
+                .claim_interface(self.settings.interface_number, self.settings.alt_setting)
+                .await?;
+        }
+
         // 第三阶段：初始化端口状态（参考 Linux hub_activate）
         // 初始化所有端口为 Disconnected 状态
         self.data.ports = (1..=self.data.num_ports).map(Port::new).collect();
 
         self.hub_power_on().await?;
 
+        // 状态变化位图每端口一位，bit 0 为 Hub 自身
+        self.status_buf = vec![0u8; (self.data.num_ports as usize + 1).div_ceil(8)];
+        match self.data.dev.endpoint(self.settings.status_endpoint) {
+            Ok(ep) => self.status_ep = Some(ep),
+            // 没有状态变化端点时只能在主机轮询时发现端口变化
+            Err(e) => warn!("Hub status endpoint unavailable: {e:?}"),
+        }
+
         // 标记 Hub 为运行状态
         self.data.state = HubState::Running;
         debug!("Hub initialized with {} ports", self.data.num_ports);
@@ -348,7 +667,7 @@ impl HubDevice {
                 ControlSetup {
                     request_type: RequestType::Class,
                     recipient: Recipient::Device,
-                    request: Request::Other(0x0c),
+                    request: Request::Class(0x0c),
                     value: depth as _,
                     index: 0,
                 },
@@ -414,7 +733,10 @@ impl HubDevice {
             debug!("Powered on port {}", port_id);
         }
 
-        self.kernel.delay(Duration::from_millis(100));
+        // bPwrOn2PwrGood 以 2ms 为单位
+        let power_good = self.hub_descriptor().bPwrOn2PwrGood as u64 * 2;
+        self.kernel
+            .delay(Duration::from_millis(power_good.max(HUB_POWER_ON_MIN_MS)));
         Ok(())
     }
 
@@ -521,6 +843,16 @@ impl HubDevice {
         &mut self,
         port_index: u8,
         feature: PortFeature,
+    ) -> Result<(), USBError> {
+        self.set_port_feature_indexed(feature, port_index as u16)
+            .await
+    }
+
+    /// 设置端口特性，`index` 低字节为端口号，高字节由特性定义（如 PORT_TEST 的测试选择子）
+    async fn set_port_feature_indexed(
+        &mut self,
+        feature: PortFeature,
+        index: u16,
     ) -> Result<(), USBError> {
         self.data
             .dev
@@ -531,7 +863,7 @@ impl HubDevice {
                     recipient: Recipient::Other,
                     request: Request::SetFeature,
                     value: feature as u16,
-                    index: port_index as u16,
+                    index,
                 },
                 &[],
             )
@@ -829,11 +1161,15 @@ impl Port {
     }
 }
 
+/// 端口枚举状态
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum PortState {
+    /// 未连接，或已连接、尚未复位
     #[default]
     Uninit,
+    /// 已复位，等待分配地址
     Reseted,
+    /// 设备已枚举
     Probed,
 }
 
@@ -855,3 +1191,47 @@ impl HubStatus {
         (self.status & 0x0002) != 0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_report_speed_only_when_enabled() {
+        let mut hub = HubDevice::mock(4, 3);
+        let port = &mut hub.data.ports[1];
+        port.status.connected = true;
+        port.status.speed = Speed::High;
+        port.state = PortState::Probed;
+        let port = &mut hub.data.ports[2];
+        port.status.connected = true;
+        port.status.enabled = true;
+        port.status.speed = Speed::Full;
+        port.state = PortState::Probed;
+
+        assert_eq!(
+            hub.port_status(),
+            [
+                HubPortStatus {
+                    port: 1,
+                    state: PortState::Uninit,
+                    connected: false,
+                    speed: None,
+                },
+                HubPortStatus {
+                    port: 2,
+                    state: PortState::Probed,
+                    connected: true,
+                    speed: None,
+                },
+                HubPortStatus {
+                    port: 3,
+                    state: PortState::Probed,
+                    connected: true,
+                    speed: Some(Speed::Full),
+                },
+            ]
+        );
+        assert_eq!(hub.ports().count(), 3);
+    }
+}
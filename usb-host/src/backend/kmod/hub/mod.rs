@@ -8,15 +8,73 @@ use alloc::collections::btree_map::BTreeMap;
 use alloc::vec::Vec;
 use futures::future::BoxFuture;
 use usb_if::err::USBError;
-use usb_if::host::hub::Speed;
+use usb_if::host::hub::{Speed, TestSelector};
 // 重新导出常用类型
 pub use device::{HubDevice, PortState};
 use id_arena::Id;
 
+use super::port_event::PortTransition;
+
 pub trait HubOp: Send + 'static + Any {
     fn init<'a>(&'a mut self, info: HubInfo) -> BoxFuture<'a, Result<HubInfo, USBError>>;
     fn changed_ports<'a>(&'a mut self) -> BoxFuture<'a, Result<Vec<PortChangeInfo>, USBError>>;
     fn slot_id(&self) -> u8;
+
+    /// 等待任一端口状态变化，不支持变化通知的 Hub 永不返回
+    fn wait_port_change<'a>(&'a mut self) -> BoxFuture<'a, ()> {
+        Box::pin(core::future::pending())
+    }
+
+    /// 返回已探测设备断开（或断开后重新连接）的端口号，这些端口恢复为可重新枚举的状态
+    fn take_disconnected_ports(&mut self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// 挂起下游端口 `port`（从 1 开始），链路进入 U3
+    fn suspend_port<'a>(&'a mut self, _port: u8) -> BoxFuture<'a, Result<(), USBError>> {
+        Box::pin(async { Err(USBError::NotSupported) })
+    }
+
+    /// 恢复挂起的端口，包括完成设备发起的远程唤醒，链路回到 U0
+    fn resume_port<'a>(&'a mut self, _port: u8) -> BoxFuture<'a, Result<(), USBError>> {
+        Box::pin(async { Err(USBError::NotSupported) })
+    }
+
+    /// 返回挂起后检测到远程唤醒的端口号，需随后调用 [`HubOp::resume_port`] 完成恢复
+    fn take_resumed_ports(&mut self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// 返回自上次调用以来记录的端口状态变化，`Enabled` 与 `Disconnected` 由主机根据
+    /// [`HubOp::changed_ports`] 与 [`HubOp::take_disconnected_ports`] 的结果生成
+    fn take_port_events(&mut self) -> Vec<(u8, PortTransition)> {
+        Vec::new()
+    }
+
+    /// 各下游端口最近一次读取到的状态，按端口号排列
+    fn port_status(&self) -> Vec<HubPortStatus> {
+        Vec::new()
+    }
+
+    /// 使下游端口 `port`（从 1 开始）进入 USB 2.0 电气测试模式
+    fn set_port_test_mode<'a>(
+        &'a mut self,
+        _port: u8,
+        _selector: TestSelector,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        Box::pin(async { Err(USBError::NotSupported) })
+    }
+}
+
+/// Hub 下游端口的状态，见 [`HubOp::port_status`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HubPortStatus {
+    /// 端口号（从 1 开始）
+    pub port: u8,
+    pub state: PortState,
+    pub connected: bool,
+    /// 端口启用后协商的速度，未启用时为 `None`
+    pub speed: Option<Speed>,
 }
 
 #[derive(Debug, Clone)]
@@ -91,20 +149,3 @@ pub struct UsbTt {
     pub multi: bool,
     pub think_time_ns: usize,
 }
-
-#[cfg(test)]
-mod tests {
-
-    use super::RouteString;
-
-    #[test]
-    fn test_route_string() {
-        let mut rs = RouteString::follow_root();
-        rs.push_hub(3);
-        rs.push_hub(5);
-        rs.push_hub(2);
-        assert_eq!(rs.raw(), 0b0010_0101_0011);
-        assert_eq!(format!("{:?}", rs), "3.5.2");
-        println!("raw: {:#x}", rs.0);
-    }
-}
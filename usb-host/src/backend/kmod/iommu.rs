@@ -0,0 +1,299 @@
+//! IOMMU 转换
+//!
+//! 控制器位于开启了地址转换的 IOMMU（如 SMMU）之后时，交给控制器的地址必须是 IOVA。
+//! 平台实现的 [`KernelOp`] 仍返回物理地址，由 [`IommuDomain`] 在其上再映射一层。
+
+use alloc::{boxed::Box, collections::BTreeMap};
+use core::{alloc::Layout, fmt, num::NonZeroUsize, ptr::NonNull, time::Duration};
+
+use dma_api::{DmaAddr, DmaDirection, DmaError, DmaHandle, DmaMapHandle, DmaOp};
+use spin::Mutex;
+
+use crate::osal::KernelOp;
+
+/// 内核提供的 IOMMU 域，每个控制器一个
+///
+/// 由内核负责 IOVA 分配与页表维护。
+pub trait IommuDomain: Send + Sync + 'static {
+    /// 把物理地址 `paddr` 起的 `size` 字节映射到一段不超出 `dma_mask` 的 IOVA
+    fn map(
+        &self,
+        paddr: u64,
+        size: usize,
+        dma_mask: u64,
+        direction: DmaDirection,
+    ) -> Result<DmaAddr, DmaError>;
+
+    /// 解除 [`IommuDomain::map`] 建立的映射
+    fn unmap(&self, iova: DmaAddr, size: usize);
+}
+
+impl fmt::Debug for dyn IommuDomain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IommuDomain")
+    }
+}
+
+/// 经由 IOMMU 域返回 IOVA 的 `KernelOp`，其余操作转发给平台实现
+///
+/// 与 [`MemTracker`](super::mem) 一样在创建控制器时泄漏。
+pub(crate) struct IommuOp {
+    inner: &'static dyn KernelOp,
+    domain: &'static dyn IommuDomain,
+    /// IOVA -> 物理地址
+    phys: Mutex<BTreeMap<u64, u64>>,
+}
+
+impl IommuOp {
+    pub fn leak(
+        inner: &'static dyn KernelOp,
+        domain: &'static dyn IommuDomain,
+    ) -> &'static dyn KernelOp {
+        Box::leak(Box::new(Self {
+            inner,
+            domain,
+            phys: Mutex::new(BTreeMap::new()),
+        }))
+    }
+
+    fn map(
+        &self,
+        paddr: DmaAddr,
+        size: usize,
+        dma_mask: u64,
+        direction: DmaDirection,
+    ) -> Result<DmaAddr, DmaError> {
+        let iova = self.domain.map(paddr.as_u64(), size, dma_mask, direction)?;
+        // 域返回的范围越过地址空间末尾时视为 IOVA 空间耗尽
+        let Some(end) = iova.checked_add(size.saturating_sub(1) as u64) else {
+            self.domain.unmap(iova, size);
+            return Err(DmaError::NoMemory);
+        };
+        if end.as_u64() > dma_mask {
+            self.domain.unmap(iova, size);
+            return Err(DmaError::DmaMaskNotMatch {
+                addr: iova,
+                mask: dma_mask,
+            });
+        }
+        self.phys.lock().insert(iova.as_u64(), paddr.as_u64());
+        Ok(iova)
+    }
+
+    /// 解除 IOVA 映射，返回对应的物理地址；不是本域映射的 IOVA 返回 `None`
+    fn unmap(&self, iova: DmaAddr, size: usize) -> Option<DmaAddr> {
+        let Some(paddr) = self.phys.lock().remove(&iova.as_u64()) else {
+            error!("unmapping unknown IOVA {:#x}", iova.as_u64());
+            return None;
+        };
+        self.domain.unmap(iova, size);
+        Some(paddr.into())
+    }
+}
+
+impl DmaOp for IommuOp {
+    fn page_size(&self) -> usize {
+        self.inner.page_size()
+    }
+
+    unsafe fn map_single(
+        &self,
+        dma_mask: u64,
+        addr: NonNull<u8>,
+        size: NonZeroUsize,
+        align: usize,
+        direction: DmaDirection,
+    ) -> Result<DmaMapHandle, DmaError> {
+        // 物理地址不受控制器寻址能力限制，掩码只约束 IOVA
+        let handle = unsafe {
+            self.inner
+                .map_single(u64::MAX, addr, size, align, direction)?
+        };
+        match self.map(handle.dma_addr(), handle.size(), dma_mask, direction) {
+            Ok(iova) => {
+                Ok(unsafe { DmaMapHandle::new(addr, iova, handle.layout(), handle.alloc_virt()) })
+            }
+            Err(e) => {
+                unsafe { self.inner.unmap_single(handle) };
+                Err(e)
+            }
+        }
+    }
+
+    unsafe fn unmap_single(&self, handle: DmaMapHandle) {
+        let Some(paddr) = self.unmap(handle.dma_addr(), handle.size()) else {
+            return;
+        };
+        unsafe {
+            self.inner.unmap_single(DmaMapHandle::new(
+                handle.as_ptr(),
+                paddr,
+                handle.layout(),
+                handle.alloc_virt(),
+            ))
+        }
+    }
+
+    fn flush(&self, addr: NonNull<u8>, size: usize) {
+        self.inner.flush(addr, size)
+    }
+
+    fn invalidate(&self, addr: NonNull<u8>, size: usize) {
+        self.inner.invalidate(addr, size)
+    }
+
+    fn flush_invalidate(&self, addr: NonNull<u8>, size: usize) {
+        self.inner.flush_invalidate(addr, size)
+    }
+
+    unsafe fn alloc_coherent(&self, dma_mask: u64, layout: Layout) -> Option<DmaHandle> {
+        let handle = unsafe { self.inner.alloc_coherent(u64::MAX, layout)? };
+        match self.map(
+            handle.dma_addr(),
+            handle.size(),
+            dma_mask,
+            DmaDirection::Bidirectional,
+        ) {
+            Ok(iova) => Some(unsafe { DmaHandle::new(handle.as_ptr(), iova, layout) }),
+            Err(e) => {
+                warn!("IOMMU map of coherent buffer failed: {e:?}");
+                unsafe { self.inner.dealloc_coherent(handle) };
+                None
+            }
+        }
+    }
+
+    unsafe fn dealloc_coherent(&self, handle: DmaHandle) {
+        let Some(paddr) = self.unmap(handle.dma_addr(), handle.size()) else {
+            return;
+        };
+        unsafe {
+            self.inner
+                .dealloc_coherent(DmaHandle::new(handle.as_ptr(), paddr, handle.layout()))
+        }
+    }
+
+    fn prepare_read(
+        &self,
+        handle: &DmaMapHandle,
+        offset: usize,
+        size: usize,
+        direction: DmaDirection,
+    ) {
+        self.inner.prepare_read(handle, offset, size, direction)
+    }
+
+    fn confirm_write(
+        &self,
+        handle: &DmaMapHandle,
+        offset: usize,
+        size: usize,
+        direction: DmaDirection,
+    ) {
+        self.inner.confirm_write(handle, offset, size, direction)
+    }
+}
+
+impl KernelOp for IommuOp {
+    fn delay(&self, duration: Duration) {
+        self.inner.delay(duration)
+    }
+
+    fn now(&self) -> Duration {
+        self.inner.now()
+    }
+
+    fn wake_at(&self, deadline: Duration, waker: &core::task::Waker) {
+        self.inner.wake_at(deadline, waker)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::kmod::test_kernel::HeapKernel;
+
+    /// 总是返回固定 IOVA 的域，`None` 表示 IOVA 空间已耗尽
+    struct FixedDomain(Option<u64>);
+
+    impl IommuDomain for FixedDomain {
+        fn map(
+            &self,
+            _paddr: u64,
+            _size: usize,
+            _dma_mask: u64,
+            _direction: DmaDirection,
+        ) -> Result<DmaAddr, DmaError> {
+            self.0.map(DmaAddr::from).ok_or(DmaError::NoMemory)
+        }
+
+        fn unmap(&self, _iova: DmaAddr, _size: usize) {}
+    }
+
+    fn map(domain: &'static FixedDomain, dma_mask: u64, size: usize) -> Result<DmaAddr, DmaError> {
+        let op = IommuOp::leak(&HeapKernel, domain);
+        let mut buf = vec![0u8; size];
+        let handle = unsafe {
+            op.map_single(
+                dma_mask,
+                NonNull::new(buf.as_mut_ptr()).unwrap(),
+                NonZeroUsize::new(size).unwrap(),
+                1,
+                DmaDirection::ToDevice,
+            )?
+        };
+        let iova = handle.dma_addr();
+        unsafe { op.unmap_single(handle) };
+        Ok(iova)
+    }
+
+    #[test]
+    fn maps_through_domain() {
+        static DOMAIN: FixedDomain = FixedDomain(Some(0x1000));
+        assert_eq!(
+            map(&DOMAIN, u32::MAX as u64, 64).unwrap(),
+            DmaAddr::from(0x1000)
+        );
+    }
+
+    #[test]
+    fn iova_past_end_of_address_space_is_no_memory() {
+        static DOMAIN: FixedDomain = FixedDomain(Some(u64::MAX - 8));
+        assert!(matches!(
+            map(&DOMAIN, u64::MAX, 64),
+            Err(DmaError::NoMemory)
+        ));
+    }
+
+    #[test]
+    fn iova_outside_mask_is_rejected() {
+        static DOMAIN: FixedDomain = FixedDomain(Some(u32::MAX as u64 - 8));
+        assert!(matches!(
+            map(&DOMAIN, u32::MAX as u64, 64),
+            Err(DmaError::DmaMaskNotMatch { .. })
+        ));
+    }
+
+    #[test]
+    fn exhausted_domain_fails_allocation() {
+        static DOMAIN: FixedDomain = FixedDomain(None);
+        assert!(matches!(
+            map(&DOMAIN, u64::MAX, 64),
+            Err(DmaError::NoMemory)
+        ));
+        let op = IommuOp::leak(&HeapKernel, &DOMAIN);
+        let layout = Layout::from_size_align(64, 64).unwrap();
+        assert!(unsafe { op.alloc_coherent(u64::MAX, layout) }.is_none());
+    }
+
+    #[test]
+    fn unknown_iova_is_ignored() {
+        static DOMAIN: FixedDomain = FixedDomain(Some(0x1000));
+        let op = IommuOp {
+            inner: &HeapKernel,
+            domain: &DOMAIN,
+            phys: Mutex::new(BTreeMap::new()),
+        };
+        assert_eq!(op.unmap(DmaAddr::from(0x2000), 64), None);
+    }
+}
@@ -1,22 +1,42 @@
-use alloc::{boxed::Box, collections::btree_map::BTreeMap, vec::Vec};
-
-use futures::{
-    FutureExt,
-    future::{BoxFuture, LocalBoxFuture},
+use alloc::{
+    boxed::Box,
+    collections::{VecDeque, btree_map::BTreeMap, btree_set::BTreeSet},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, Ordering},
 };
+
+use futures::{FutureExt, future::BoxFuture};
 use id_arena::{Arena, Id};
 use usb_if::{
-    descriptor::{ConfigurationDescriptor, DeviceDescriptor},
+    descriptor::{ConfigurationDescriptor, DeviceDescriptor, DeviceQualifierDescriptor},
     err::USBError,
+    host::hub::TestSelector,
 };
 
-use super::osal::Kernel;
+use super::{
+    EnumerationMode, PendingPort,
+    dwc::extcon::Usb2PhyExtcon,
+    osal::Kernel,
+    perf::{PerfCounters, SelfTestReport},
+    port_event::{
+        PortEvent, PortEventCallback, PortSnapshot, PortSubscribers, PortSubscription,
+        PortTransition,
+    },
+    watchdog::{StallDetector, WatchdogConfig, WatchdogEvent},
+};
 use crate::{
-    Device, DeviceAddressInfo,
+    Device, DeviceAddressInfo, DeviceLocation,
     backend::{
         BackendOp,
         kmod::hub::{Hub, HubDevice, HubInfo, HubOp, PortChangeInfo},
-        ty::{DeviceInfoOp, DeviceOp, EventHandlerOp, ProbedDeviceInfoOp},
+        ty::{
+            DeviceInfoOp, DeviceOp, EventHandlerOp, HotplugEventOp, ProbedDeviceInfoOp,
+            timer::sleep_until,
+        },
     },
 };
 
@@ -34,6 +54,41 @@ pub trait CoreOp: Send + 'static {
     fn create_event_handler(&mut self) -> Box<dyn EventHandlerOp>;
 
     fn kernel(&self) -> &Kernel;
+
+    fn perf_counters(&self) -> PerfCounters;
+
+    /// 发送 `count` 条 NoOp 命令，检查事件投递并统计往返耗时
+    fn self_test<'a>(&'a mut self, count: u32) -> BoxFuture<'a, Result<SelfTestReport, USBError>>;
+
+    /// 停止端点并禁用槽，`power_off_port` 为根端口号时同时关闭该端口电源
+    fn eject_slot<'a>(
+        &'a mut self,
+        slot_id: u8,
+        power_off_port: Option<u8>,
+    ) -> BoxFuture<'a, Result<(), USBError>>;
+
+    fn enumeration_mode(&self) -> EnumerationMode {
+        EnumerationMode::Eager
+    }
+
+    /// 当前 MFINDEX，控制器未运行时返回 `None`
+    fn frame_index(&self) -> Option<u16>;
+
+    /// 重新初始化 PHY，用于从时钟丢失中恢复
+    fn reinit_phy<'a>(&'a mut self) -> BoxFuture<'a, Result<(), USBError>> {
+        async { Err(USBError::NotSupported) }.boxed()
+    }
+
+    /// OTG 端口 VBUS/ID 监视器，不支持双角色的控制器返回 `None`
+    fn extcon(&self) -> Option<Usb2PhyExtcon> {
+        None
+    }
+
+    /// 禁用根端口，模拟设备被意外拔出
+    #[cfg(feature = "fault-injection")]
+    fn inject_port_disable(&mut self, _port: u8) -> Result<(), USBError> {
+        Err(USBError::NotSupported)
+    }
 }
 
 pub struct Core {
@@ -41,6 +96,37 @@ pub struct Core {
     hubs: Arena<Hub>,
     root_hub: Option<Id<Hub>>,
     inited_devices: BTreeMap<usize, Box<dyn DeviceOp>>,
+    devices: BTreeMap<usize, DeviceRecord>,
+    /// 端口路径到设备编号的索引，同一根端口下的设备在其中连续排列
+    paths: BTreeMap<Vec<u8>, usize>,
+    /// Hub 设备编号到其 Hub 的索引，不含根 Hub
+    hub_devices: BTreeMap<usize, Id<Hub>>,
+    /// 已检测到、尚未交给调用方的热插拔事件
+    hotplug: VecDeque<HotplugEventOp>,
+    /// [`Core::_wait_hotplug`] 已观察到端口变化，尚未处理
+    port_changed: bool,
+    watchdog: Option<(WatchdogConfig, StallDetector)>,
+    /// 已挂起的设备，挂起期间 MFINDEX 可能停止计数
+    suspended: BTreeSet<usize>,
+    /// 延迟枚举模式下已连接、尚未枚举的根端口
+    pending: BTreeMap<u8, PortChangeInfo>,
+    port_events: Option<PortEventCallback>,
+    port_subscribers: PortSubscribers,
+}
+
+/// 已枚举设备的拓扑信息，用于弹出
+struct DeviceRecord {
+    /// 从根端口开始逐级的端口号
+    path: Vec<u8>,
+    on_root_hub: bool,
+    is_hub: bool,
+    stale: Arc<AtomicBool>,
+}
+
+impl DeviceRecord {
+    fn root_port_id(&self) -> u8 {
+        self.path[0]
+    }
 }
 
 impl Core {
@@ -50,9 +136,122 @@ impl Core {
             backend: Box::new(backend),
             hubs: Arena::new(),
             inited_devices: BTreeMap::new(),
+            devices: BTreeMap::new(),
+            paths: BTreeMap::new(),
+            hub_devices: BTreeMap::new(),
+            hotplug: VecDeque::new(),
+            port_changed: false,
+            watchdog: None,
+            suspended: BTreeSet::new(),
+            pending: BTreeMap::new(),
+            port_events: None,
+            port_subscribers: PortSubscribers::default(),
+        }
+    }
+
+    /// 从根端口开始逐级的端口号
+    fn port_path(&self, hub_id: Id<Hub>, port: u8) -> Vec<u8> {
+        let mut path = alloc::vec![port];
+        let mut id = Some(hub_id);
+        while let Some(hub) = id.and_then(|id| self.hubs.get(id)) {
+            if hub.info.parent.is_none() {
+                break;
+            }
+            path.push(hub.info.port_id);
+            id = hub.info.parent;
+        }
+        path.reverse();
+        path
+    }
+
+    fn notify_port(&mut self, hub_id: Id<Hub>, port: u8, transition: PortTransition) {
+        if self.port_events.is_none() && self.port_subscribers.is_empty() {
+            return;
+        }
+        let event = PortEvent {
+            path: self.port_path(hub_id, port),
+            transition,
+        };
+        if let Some(callback) = &self.port_events {
+            callback(&event);
+        }
+        self.port_subscribers.publish(&event);
+    }
+
+    /// 已枚举的外部 Hub 及其设备编号，按编号排序
+    fn hubs(&self) -> Vec<(usize, &HubDevice)> {
+        self.hub_devices
+            .iter()
+            .filter_map(|(&id, &hub_id)| {
+                let hub = self.hubs.get(hub_id)?;
+                let hub = (hub.backend.as_ref() as &dyn Any).downcast_ref::<HubDevice>()?;
+                Some((id, hub))
+            })
+            .collect()
+    }
+
+    /// 当前拓扑中全部 Hub 的下游端口，按端口路径排序
+    fn ports(&self) -> Vec<PortSnapshot> {
+        let mut out: Vec<PortSnapshot> = self
+            .root_hub
+            .into_iter()
+            .chain(self.external_hubs())
+            .filter_map(|id| Some((id, self.hubs.get(id)?)))
+            .flat_map(|(id, hub)| {
+                hub.backend.port_status().into_iter().map(move |status| {
+                    let path = self.port_path(id, status.port);
+                    PortSnapshot {
+                        device_id: self.paths.get(&path).copied(),
+                        path,
+                        state: status.state,
+                        connected: status.connected,
+                        speed: status.speed,
+                    }
+                })
+            })
+            .collect();
+        out.sort_by(|a, b| a.path.cmp(&b.path));
+        out
+    }
+
+    /// 转发 Hub 记录的端口状态变化，没有回调与订阅时直接丢弃
+    fn dispatch_port_events(&mut self, hub_id: Id<Hub>) {
+        let hub = self.hubs.get_mut(hub_id).expect("Hub id should be valid");
+        for (port, transition) in hub.backend.take_port_events() {
+            self.notify_port(hub_id, port, transition);
         }
     }
 
+    fn insert_record(&mut self, device_id: usize, record: DeviceRecord) {
+        self.paths.insert(record.path.clone(), device_id);
+        self.devices.insert(device_id, record);
+    }
+
+    /// 移除设备记录及其索引，并将其标记为失效
+    fn remove_record(&mut self, device_id: usize) {
+        let Some(record) = self.devices.remove(&device_id) else {
+            return;
+        };
+        self.paths.remove(&record.path);
+        self.hub_devices.remove(&device_id);
+        self.suspended.remove(&device_id);
+        record.stale.store(true, Ordering::Release);
+    }
+
+    /// 端口路径 `prefix` 上的设备及其下游的全部设备
+    fn devices_under(&self, prefix: &[u8]) -> Vec<usize> {
+        self.paths
+            .range(prefix.to_vec()..)
+            .take_while(|(path, _)| path.starts_with(prefix))
+            .map(|(_, &id)| id)
+            .collect()
+    }
+
+    /// 仍在拓扑中的外部 Hub
+    fn external_hubs(&self) -> Vec<Id<Hub>> {
+        self.hub_devices.values().copied().collect()
+    }
+
     fn hub_infos(&self) -> BTreeMap<Id<Hub>, HubInfo> {
         let mut out = BTreeMap::new();
         for (id, hub) in self.hubs.iter() {
@@ -62,75 +261,353 @@ impl Core {
         out
     }
 
-    async fn _probe_devices(&mut self) -> Result<(bool, Vec<ProbedDeviceInfoOp>), USBError> {
+    /// `lazy` 为真时根端口上的设备只记录到 `pending`，不分配地址
+    async fn _probe_devices(
+        &mut self,
+        lazy: bool,
+    ) -> Result<(bool, Vec<ProbedDeviceInfoOp>), USBError> {
         let mut is_have_new_hub = false;
         let mut out = Vec::new();
 
-        let hub_ids: Vec<Id<Hub>> = self.hubs.iter().map(|(id, _)| id).collect();
+        // 已拔出的 Hub 仍留在 arena 中，只遍历索引中的 Hub
+        let hub_ids: Vec<Id<Hub>> = self
+            .root_hub
+            .into_iter()
+            .chain(self.hub_devices.values().copied())
+            .collect();
 
         for id in hub_ids {
-            let addr_infos = self.hub_changed_ports(id).await?;
-            let parent_hub_id = self.hubs.get(id).unwrap().backend.slot_id();
+            let addr_infos = match self.hub_changed_ports(id).await {
+                Ok(infos) => infos,
+                // 外部 Hub 可能刚被拔出，其断开由上游 Hub 报告
+                Err(e) if Some(id) != self.root_hub => {
+                    warn!("Hub {id:?} port status: {e:?}");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
             for addr_info in addr_infos {
-                let info = DeviceAddressInfo {
-                    root_port_id: addr_info.root_port_id,
-                    port_speed: addr_info.port_speed,
-                    parent_hub: Some(id),
-                    port_id: addr_info.port_id,
-                    infos: self.hub_infos(),
-                };
-
-                let device = self.backend.new_addressed_device(info).await?;
-
-                let device_id = device.id();
-
-                if let Some(hub_settings) =
-                    HubDevice::is_hub(device.descriptor(), device.configuration_descriptors())
-                {
-                    let desc = device.descriptor().clone();
-                    let configs = device.configuration_descriptors().to_vec();
-                    let device_inner: Device = device.into();
-
-                    let hub_device = HubDevice::new(
-                        device_inner,
-                        hub_settings,
-                        addr_info.root_port_id,
-                        parent_hub_id,
-                        self.backend.kernel(),
-                    )
-                    .await?;
-                    let mut hub = Hub::new(
-                        Box::new(hub_device),
-                        &self.hub_infos(),
-                        addr_info.port_id,
-                        Some(id),
+                if lazy && Some(id) == self.root_hub {
+                    debug!(
+                        "Root port {} attached, enumeration deferred",
+                        addr_info.port_id
                     );
-                    let info = hub.backend.init(hub.info.clone()).await?;
-                    hub.info = info;
+                    self.pending.insert(addr_info.port_id, addr_info);
+                    continue;
+                }
+                let probed = self.enumerate(id, addr_info).await?;
+                is_have_new_hub |= matches!(probed, ProbedDeviceInfoOp::Hub(_));
+                out.push(probed);
+            }
+        }
+
+        Ok((is_have_new_hub, out))
+    }
+
+    /// 为 Hub `id` 下的端口分配地址并读取描述符，设备为 Hub 时同时初始化该 Hub
+    async fn enumerate(
+        &mut self,
+        id: Id<Hub>,
+        addr_info: PortChangeInfo,
+    ) -> Result<ProbedDeviceInfoOp, USBError> {
+        let parent_hub_id = self.hubs.get(id).unwrap().backend.slot_id();
+        let info = DeviceAddressInfo {
+            root_port_id: addr_info.root_port_id,
+            port_speed: addr_info.port_speed,
+            parent_hub: Some(id),
+            port_id: addr_info.port_id,
+            infos: self.hub_infos(),
+        };
+
+        let device = self.backend.new_addressed_device(info).await?;
+        let device_id = device.id();
+        let hub_settings =
+            HubDevice::is_hub(device.descriptor(), device.configuration_descriptors());
+        let record = DeviceRecord {
+            path: self.port_path(id, addr_info.port_id),
+            on_root_hub: Some(id) == self.root_hub,
+            is_hub: hub_settings.is_some(),
+            stale: Arc::new(AtomicBool::new(false)),
+        };
+        let device_info =
+            DeviceInfo::from_device(device.as_ref(), record.path.clone(), record.stale.clone());
+        self.insert_record(device_id, record);
+
+        if let Some(hub_settings) = hub_settings {
+            let hub_info = device_info;
+            let device_inner: Device = device.into();
+
+            let hub_device = HubDevice::new(
+                device_inner,
+                hub_settings,
+                addr_info.root_port_id,
+                parent_hub_id,
+                self.backend.kernel(),
+            )
+            .await?;
+            let mut hub = Hub::new(
+                Box::new(hub_device),
+                &self.hub_infos(),
+                addr_info.port_id,
+                Some(id),
+            );
+            let info = hub.backend.init(hub.info.clone()).await?;
+            hub.info = info;
+
+            let hub_id = self.hubs.alloc(hub);
+            self.hub_devices.insert(device_id, hub_id);
+            info!("Added new hub with id {:?}", hub_id);
+
+            Ok(ProbedDeviceInfoOp::Hub(Box::new(hub_info)))
+        } else {
+            self.inited_devices.insert(device_id, device);
+            Ok(ProbedDeviceInfoOp::Device(Box::new(device_info)))
+        }
+    }
 
-                    let hub_id = self.hubs.alloc(hub);
-                    is_have_new_hub = true;
+    fn pending_ports(&self) -> Vec<PendingPort> {
+        self.pending
+            .values()
+            .map(|info| PendingPort {
+                port: info.port_id,
+                speed: info.port_speed,
+            })
+            .collect()
+    }
 
-                    let hub_info = Box::new(DeviceInfo::new(device_id, desc, &configs))
-                        as Box<dyn DeviceInfoOp>;
-                    out.push(ProbedDeviceInfoOp::Hub(hub_info));
+    /// 枚举延迟的根端口，设备为 Hub 时同时枚举其下游设备
+    async fn _enumerate_port(&mut self, port: u8) -> Result<Vec<ProbedDeviceInfoOp>, USBError> {
+        let root_hub = self.root_hub.ok_or(USBError::NotInitialized)?;
+        let addr_info = self.pending.remove(&port).ok_or(USBError::NotFound)?;
+        let probed = self.enumerate(root_hub, addr_info).await?;
+        let is_hub = matches!(probed, ProbedDeviceInfoOp::Hub(_));
+        let mut out = alloc::vec![probed];
+        if is_hub {
+            out.extend(self.probe_devices(true).await?);
+        }
+        Ok(out)
+    }
 
-                    info!("Added new hub with id {:?}", hub_id);
-                } else {
-                    let desc = device.descriptor().clone();
-                    let configs = device.configuration_descriptors().to_vec();
+    async fn _eject(&mut self, device_id: usize, power_off_port: bool) -> Result<(), USBError> {
+        let record = self.devices.get(&device_id).ok_or(USBError::NotFound)?;
+        // Hub 下游设备需要先逐个弹出，暂不支持直接弹出 Hub
+        if record.is_hub {
+            return Err(USBError::NotSupported);
+        }
+        // 外部 Hub 端口的电源需通过 Hub 类请求控制，目前只支持根端口
+        let power_off_port = match power_off_port {
+            true if !record.on_root_hub => return Err(USBError::NotSupported),
+            true => Some(record.root_port_id()),
+            false => None,
+        };
+
+        // 尚未打开的设备由主机持有，其设备上下文需保留到槽禁用之后
+        let device = self.inited_devices.remove(&device_id);
+        self.backend
+            .eject_slot(device_id as u8, power_off_port)
+            .await?;
+        drop(device);
 
-                    self.inited_devices.insert(device_id, device);
+        self.remove_record(device_id);
+        Ok(())
+    }
 
-                    let device_info = Box::new(DeviceInfo::new(device_id, desc, &configs))
-                        as Box<dyn DeviceInfoOp>;
+    /// 挂起设备时操作的 Hub 与其下游端口，目前只支持非 Hub 设备
+    fn suspend_target(&self, device_id: usize) -> Result<(Id<Hub>, u8), USBError> {
+        let record = self.devices.get(&device_id).ok_or(USBError::NotFound)?;
+        // 挂起 Hub 会连带其下游设备
+        if record.is_hub {
+            return Err(USBError::NotSupported);
+        }
+        let (&port, parent) = record.path.split_last().expect("device path is not empty");
+        if record.on_root_hub {
+            return Ok((self.root_hub.ok_or(USBError::NotInitialized)?, port));
+        }
+        let hub_device = self.paths.get(parent).ok_or(USBError::NotFound)?;
+        let hub = self.hub_devices.get(hub_device).ok_or(USBError::NotFound)?;
+        Ok((*hub, port))
+    }
+
+    async fn _suspend_device(&mut self, device_id: usize) -> Result<(), USBError> {
+        let (hub_id, port) = self.suspend_target(device_id)?;
+        let hub = self.hubs.get_mut(hub_id).expect("Hub id should be valid");
+        hub.backend.suspend_port(port).await?;
+        self.suspended.insert(device_id);
+        Ok(())
+    }
 
-                    out.push(ProbedDeviceInfoOp::Device(device_info));
+    async fn _resume_device(&mut self, device_id: usize) -> Result<(), USBError> {
+        let (hub_id, port) = self.suspend_target(device_id)?;
+        let hub = self.hubs.get_mut(hub_id).expect("Hub id should be valid");
+        hub.backend.resume_port(port).await?;
+        self.suspended.remove(&device_id);
+        Ok(())
+    }
+
+    /// 完成 Hub `hub_id` 上设备发起的远程唤醒，并为端口下的设备生成唤醒事件
+    async fn resume_woken_ports(&mut self, hub_id: Id<Hub>) {
+        let hub = self.hubs.get_mut(hub_id).expect("Hub id should be valid");
+        let mut resumed = Vec::new();
+        for port in hub.backend.take_resumed_ports() {
+            match hub.backend.resume_port(port).await {
+                Ok(()) => resumed.push(port),
+                Err(e) => warn!("Resume port {port} after remote wakeup: {e:?}"),
+            }
+        }
+        for port in resumed {
+            let path = self.port_path(hub_id, port);
+            for id in self.devices_under(&path) {
+                info!("Device {id} remote wakeup on port {path:?}");
+                self.suspended.remove(&id);
+                self.hotplug.push_back(HotplugEventOp::RemoteWakeup { id });
+            }
+        }
+    }
+
+    /// 释放端口路径 `path` 上的设备及其下游的全部设备，返回其编号
+    async fn detach_path(&mut self, path: &[u8]) -> Vec<usize> {
+        let ids = self.devices_under(path);
+        for &id in &ids {
+            let device = self.inited_devices.remove(&id);
+            // 设备已不在总线上，停止端点可能失败，不影响禁用槽
+            if let Err(e) = self.backend.eject_slot(id as u8, None).await {
+                warn!("Release slot {id} of detached device: {e:?}");
+            }
+            drop(device);
+            self.remove_record(id);
+        }
+        ids
+    }
+
+    /// 等待根 Hub 或外部 Hub 报告端口变化，已有未处理的变化时立即返回
+    ///
+    /// 可在任意时刻取消：各 Hub 的等待在完成前取消不丢失变化，完成后记录在
+    /// `port_changed` 中，由下一次 [`Core::_poll_hotplug`] 处理。
+    async fn _wait_hotplug(&mut self) {
+        let Some(root_hub) = self.root_hub else {
+            return;
+        };
+        if self.port_changed || !self.hotplug.is_empty() {
+            return;
+        }
+        // 根 Hub 的端口变化经控制器中断通知，外部 Hub 经其状态变化端点通知
+        let mut watched = self.external_hubs();
+        watched.push(root_hub);
+        let waits: Vec<_> = self
+            .hubs
+            .iter_mut()
+            .filter(|(id, _)| watched.contains(id))
+            .map(|(_, hub)| hub.backend.wait_port_change())
+            .collect();
+        futures::future::select_all(waits).await;
+        self.port_changed = true;
+    }
+
+    async fn _poll_hotplug(&mut self) -> Result<Option<HotplugEventOp>, USBError> {
+        let root_hub = self.root_hub.ok_or(USBError::NotInitialized)?;
+        if let Some(event) = self.hotplug.pop_front() {
+            return Ok(Some(event));
+        }
+        if !core::mem::take(&mut self.port_changed) {
+            return Ok(None);
+        }
+
+        self.resume_woken_ports(root_hub).await;
+        let hub = self.hubs.get_mut(root_hub).expect("Hub id should be valid");
+        let disconnected = hub.backend.take_disconnected_ports();
+
+        for &port in &disconnected {
+            self.notify_port(root_hub, port, PortTransition::Disconnected);
+        }
+        self.dispatch_port_events(root_hub);
+
+        for port in disconnected {
+            // 延迟枚举的设备尚未分配地址，无需报告拔出
+            self.pending.remove(&port);
+            for id in self.detach_path(&[port]).await {
+                info!("Device {id} detached from root port {port}");
+                self.hotplug.push_back(HotplugEventOp::Detached { id });
+            }
+        }
+        // 外部 Hub 在读取端口状态时记录断开，需先于新设备报告
+        let attached = self.probe_devices(false).await?;
+        self.detach_external_ports().await;
+        // 外部 Hub 在读取端口状态时记录远程唤醒
+        for hub_id in self.external_hubs() {
+            self.resume_woken_ports(hub_id).await;
+        }
+        for dev in attached {
+            self.hotplug.push_back(HotplugEventOp::Attached(dev));
+        }
+        Ok(self.hotplug.pop_front())
+    }
+
+    /// 释放外部 Hub 报告断开的端口下的设备并生成拔出事件
+    async fn detach_external_ports(&mut self) {
+        for hub_id in self.external_hubs() {
+            // 上游端口断开时该 Hub 可能已随之移除
+            if !self.hub_devices.values().any(|&id| id == hub_id) {
+                continue;
+            }
+            let hub = self.hubs.get_mut(hub_id).expect("Hub id should be valid");
+            for port in hub.backend.take_disconnected_ports() {
+                self.notify_port(hub_id, port, PortTransition::Disconnected);
+                let path = self.port_path(hub_id, port);
+                for id in self.detach_path(&path).await {
+                    info!("Device {id} detached from hub port {path:?}");
+                    self.hotplug.push_back(HotplugEventOp::Detached { id });
                 }
             }
         }
+    }
 
-        Ok((is_have_new_hub, out))
+    async fn _poll_watchdog(&mut self) -> Result<Option<WatchdogEvent>, USBError> {
+        let (config, detector) = self.watchdog.as_mut().ok_or(USBError::NotInitialized)?;
+        // 控制器停止（USBSTS.HCH）时 frame_index 为 `None`；端口挂起期间 MFINDEX
+        // 也可能停止计数，两种情况都不检查，恢复后重新计时
+        let mfindex = if self.suspended.is_empty() {
+            self.backend.frame_index()
+        } else {
+            None
+        };
+        let event = detector.sample(mfindex, self.backend.kernel().now());
+        let Some(WatchdogEvent::HostStalled {
+            mfindex,
+            stalled_for,
+            ..
+        }) = event
+        else {
+            return Ok(event);
+        };
+
+        warn!("Host controller stalled: MFINDEX {mfindex} unchanged for {stalled_for:?}");
+        let mut reinit = false;
+        if config.auto_reinit {
+            // PHY 重新初始化后链路断开，端口变化经热插拔路径报告拔出与重新枚举
+            match self.backend.reinit_phy().await {
+                Ok(()) => reinit = true,
+                Err(e) => warn!("Reinit PHY after stall: {e:?}"),
+            }
+        }
+        Ok(Some(WatchdogEvent::HostStalled {
+            mfindex,
+            stalled_for,
+            reinit,
+        }))
+    }
+
+    async fn _set_hub_port_test_mode(
+        &mut self,
+        hub_device_id: usize,
+        port: u8,
+        selector: TestSelector,
+    ) -> Result<(), USBError> {
+        let hub_id = *self
+            .hub_devices
+            .get(&hub_device_id)
+            .ok_or(USBError::NotFound)?;
+        let hub = self.hubs.get_mut(hub_id).expect("Hub id should be valid");
+        hub.backend.set_port_test_mode(port, selector).await
     }
 
     async fn hub_changed_ports(
@@ -138,14 +615,24 @@ impl Core {
         hub_id: Id<Hub>,
     ) -> Result<Vec<PortChangeInfo>, USBError> {
         let hub = self.hubs.get_mut(hub_id).expect("Hub id should be valid");
-        hub.backend.changed_ports().await
+        let result = hub.backend.changed_ports().await;
+        self.dispatch_port_events(hub_id);
+        let ports = result?;
+        for info in &ports {
+            self.notify_port(
+                hub_id,
+                info.port_id,
+                PortTransition::Enabled(info.port_speed),
+            );
+        }
+        Ok(ports)
     }
 
-    async fn probe_devices(&mut self) -> Result<Vec<ProbedDeviceInfoOp>, USBError> {
+    async fn probe_devices(&mut self, lazy: bool) -> Result<Vec<ProbedDeviceInfoOp>, USBError> {
         let mut result = Vec::new();
 
         loop {
-            let (is_have_new_hub, mut devices) = self._probe_devices().await?;
+            let (is_have_new_hub, mut devices) = self._probe_devices(lazy).await?;
             result.append(&mut devices);
             if !is_have_new_hub {
                 break;
@@ -171,14 +658,29 @@ impl BackendOp for Core {
     }
 
     fn device_list<'a>(&'a mut self) -> BoxFuture<'a, Result<Vec<ProbedDeviceInfoOp>, USBError>> {
-        self.probe_devices().boxed()
+        let lazy = self.backend.enumeration_mode() == EnumerationMode::Lazy;
+        self.probe_devices(lazy).boxed()
+    }
+
+    fn pending_ports(&self) -> Vec<PendingPort> {
+        Core::pending_ports(self)
+    }
+
+    fn enumerate_port<'a>(
+        &'a mut self,
+        port: u8,
+    ) -> BoxFuture<'a, Result<Vec<ProbedDeviceInfoOp>, USBError>> {
+        self._enumerate_port(port).boxed()
     }
 
     fn open_device<'a>(
         &'a mut self,
         dev: &'a dyn crate::backend::ty::DeviceInfoOp,
-    ) -> LocalBoxFuture<'a, Result<Box<dyn DeviceOp>, USBError>> {
+    ) -> BoxFuture<'a, Result<Box<dyn DeviceOp>, USBError>> {
         async {
+            if dev.is_stale() {
+                return Err(USBError::NotFound);
+            }
             let device = self.inited_devices.remove(&dev.id()).unwrap_or_else(|| {
                 panic!("Device id {} not found in inited_devices", dev.id());
             });
@@ -188,9 +690,97 @@ impl BackendOp for Core {
         .boxed()
     }
 
+    fn wait_hotplug<'a>(&'a mut self) -> BoxFuture<'a, ()> {
+        self._wait_hotplug().boxed()
+    }
+
+    fn poll_hotplug<'a>(&'a mut self) -> BoxFuture<'a, Result<Option<HotplugEventOp>, USBError>> {
+        self._poll_hotplug().boxed()
+    }
+
     fn create_event_handler(&mut self) -> Box<dyn EventHandlerOp> {
         self.backend.create_event_handler()
     }
+
+    fn perf_counters(&self) -> PerfCounters {
+        self.backend.perf_counters()
+    }
+
+    fn extcon(&self) -> Option<Usb2PhyExtcon> {
+        self.backend.extcon()
+    }
+
+    fn self_test<'a>(&'a mut self, count: u32) -> BoxFuture<'a, Result<SelfTestReport, USBError>> {
+        self.backend.self_test(count)
+    }
+
+    fn eject<'a>(
+        &'a mut self,
+        device_id: usize,
+        power_off_port: bool,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        self._eject(device_id, power_off_port).boxed()
+    }
+
+    fn suspend_device<'a>(&'a mut self, device_id: usize) -> BoxFuture<'a, Result<(), USBError>> {
+        self._suspend_device(device_id).boxed()
+    }
+
+    fn resume_device<'a>(&'a mut self, device_id: usize) -> BoxFuture<'a, Result<(), USBError>> {
+        self._resume_device(device_id).boxed()
+    }
+
+    fn enable_watchdog(&mut self, config: WatchdogConfig) {
+        self.watchdog = Some((config, StallDetector::new(config.timeout)));
+    }
+
+    fn poll_watchdog<'a>(&'a mut self) -> BoxFuture<'a, Result<Option<WatchdogEvent>, USBError>> {
+        self._poll_watchdog().boxed()
+    }
+
+    fn watchdog_tick(&self) -> Option<BoxFuture<'static, ()>> {
+        let (config, _) = self.watchdog.as_ref()?;
+        let kernel = self.backend.kernel().clone();
+        // 检查周期取停滞判定时间的一半，停滞后最迟 1.5 倍判定时间内报告
+        let deadline = kernel.now() + config.timeout / 2;
+        Some(async move { sleep_until(&kernel, deadline).await }.boxed())
+    }
+
+    fn set_port_event_callback(&mut self, callback: PortEventCallback) {
+        self.port_events = Some(callback);
+    }
+
+    fn subscribe_ports(&mut self) -> PortSubscription {
+        self.port_subscribers.subscribe()
+    }
+
+    fn ports(&self) -> Vec<PortSnapshot> {
+        Core::ports(self)
+    }
+
+    fn hubs(&self) -> Vec<(usize, &HubDevice)> {
+        Core::hubs(self)
+    }
+
+    fn set_hub_port_test_mode<'a>(
+        &'a mut self,
+        hub_device_id: usize,
+        port: u8,
+        selector: TestSelector,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        self._set_hub_port_test_mode(hub_device_id, port, selector)
+            .boxed()
+    }
+
+    #[cfg(feature = "fault-injection")]
+    fn inject_port_disable(&mut self, port: u8) -> Result<(), USBError> {
+        self.backend.inject_port_disable(port)
+    }
+
+    #[cfg(feature = "mem-track")]
+    fn memory_report(&self) -> crate::backend::kmod::MemoryReport {
+        self.backend.kernel().memory_report()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -198,14 +788,26 @@ pub struct DeviceInfo {
     id: usize,
     desc: DeviceDescriptor,
     config_desc: Vec<ConfigurationDescriptor>,
+    qualifier: Option<DeviceQualifierDescriptor>,
+    other_speed_desc: Vec<ConfigurationDescriptor>,
+    path: Vec<u8>,
+    stale: Arc<AtomicBool>,
 }
 
 impl DeviceInfo {
-    pub fn new(id: usize, desc: DeviceDescriptor, config_desc: &[ConfigurationDescriptor]) -> Self {
+    pub(crate) fn from_device(
+        device: &dyn DeviceOp,
+        path: Vec<u8>,
+        stale: Arc<AtomicBool>,
+    ) -> Self {
         Self {
-            id,
-            desc,
-            config_desc: config_desc.to_vec(),
+            id: device.id(),
+            path,
+            desc: device.descriptor().clone(),
+            config_desc: device.configuration_descriptors().to_vec(),
+            qualifier: device.device_qualifier().cloned(),
+            other_speed_desc: device.other_speed_configurations().to_vec(),
+            stale,
         }
     }
 }
@@ -226,4 +828,202 @@ impl DeviceInfoOp for DeviceInfo {
     fn configuration_descriptors(&self) -> &[ConfigurationDescriptor] {
         &self.config_desc
     }
+
+    fn location(&self) -> DeviceLocation {
+        DeviceLocation {
+            bus: 0,
+            port_path: self.path.clone(),
+            address: self.id,
+        }
+    }
+
+    fn device_qualifier(&self) -> Option<&DeviceQualifierDescriptor> {
+        self.qualifier.as_ref()
+    }
+
+    fn other_speed_configurations(&self) -> &[ConfigurationDescriptor] {
+        &self.other_speed_desc
+    }
+
+    fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+impl Core {
+    /// 不经枚举登记位于端口路径 `path` 上的设备
+    pub(crate) fn insert_device(&mut self, id: usize, path: Vec<u8>, is_hub: bool) {
+        let record = DeviceRecord {
+            on_root_hub: path.len() == 1,
+            path,
+            is_hub,
+            stale: Arc::default(),
+        };
+        self.insert_record(id, record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{ops::ControlFlow, sync::atomic::Ordering, task::Context};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::backend::kmod::test_core::{MockCore, MockDevice, MockHub, PortLog};
+    use crate::{HostEvent, HotplugEvent, USBHost, spawn::TaskQueue};
+
+    /// 根端口 1 上是 Hub（设备 1），其端口 3 上是设备 2；根端口 2 上是设备 3
+    fn topology() -> (Core, Arc<Mutex<PortLog>>, Arc<Mutex<PortLog>>) {
+        let (root, root_log) = MockHub::new(0);
+        let mut core = Core::new(MockCore::new(root));
+        core.init().now_or_never().unwrap().unwrap();
+
+        let (hub, hub_log) = MockHub::new(1);
+        let hub = Hub::new(Box::new(hub), &core.hub_infos(), 1, core.root_hub);
+        let hub_id = core.hubs.alloc(hub);
+        core.hub_devices.insert(1, hub_id);
+        core.insert_device(1, alloc::vec![1], true);
+        core.insert_device(2, alloc::vec![1, 3], false);
+        core.insert_device(3, alloc::vec![2], false);
+        (core, root_log, hub_log)
+    }
+
+    /// 等待端口变化并处理，直到产生事件
+    fn next_hotplug_event(core: &mut Core) -> HotplugEventOp {
+        loop {
+            core._wait_hotplug().now_or_never().unwrap();
+            if let Some(event) = core._poll_hotplug().now_or_never().unwrap().unwrap() {
+                return event;
+            }
+        }
+    }
+
+    #[test]
+    fn location_keeps_ids_above_255() {
+        let location = |id| {
+            let device = MockDevice::new(id, 0);
+            DeviceInfo::from_device(&device, alloc::vec![1], Arc::default()).location()
+        };
+        let low = location(1);
+        let high = location(257);
+        assert_eq!(high.address, 257);
+        assert_ne!(low, high);
+        assert!(low < high);
+    }
+
+    #[test]
+    fn suspend_uses_the_parent_hub_port() {
+        let (mut core, root_log, hub_log) = topology();
+        core._suspend_device(2).now_or_never().unwrap().unwrap();
+        core._suspend_device(3).now_or_never().unwrap().unwrap();
+        assert_eq!(hub_log.lock().unwrap().suspended, [3]);
+        assert_eq!(root_log.lock().unwrap().suspended, [2]);
+        assert!(matches!(
+            core._suspend_device(1).now_or_never().unwrap(),
+            Err(USBError::NotSupported)
+        ));
+
+        core._resume_device(2).now_or_never().unwrap().unwrap();
+        assert!(hub_log.lock().unwrap().suspended.is_empty());
+        assert_eq!(core.suspended, BTreeSet::from([3]));
+    }
+
+    #[test]
+    fn remote_wakeup_behind_hub() {
+        let (mut core, root_log, hub_log) = topology();
+        core._suspend_device(2).now_or_never().unwrap().unwrap();
+        hub_log.lock().unwrap().wakeups.push(3);
+
+        let event = next_hotplug_event(&mut core);
+        assert!(matches!(event, HotplugEventOp::RemoteWakeup { id: 2 }));
+        assert_eq!(hub_log.lock().unwrap().resumed, [3]);
+        assert!(root_log.lock().unwrap().resumed.is_empty());
+        assert!(core.suspended.is_empty());
+        assert!(core.hotplug.is_empty());
+    }
+
+    #[test]
+    fn remote_wakeup_on_root_port() {
+        let (mut core, root_log, _) = topology();
+        core._suspend_device(3).now_or_never().unwrap().unwrap();
+        root_log.lock().unwrap().wakeups.push(2);
+
+        let event = next_hotplug_event(&mut core);
+        assert!(matches!(event, HotplugEventOp::RemoteWakeup { id: 3 }));
+        assert_eq!(root_log.lock().unwrap().resumed, [2]);
+        assert!(core.suspended.is_empty());
+    }
+
+    #[test]
+    fn monitor_task_reports_remote_wakeup() {
+        let (mut core, root_log, _) = topology();
+        core._suspend_device(3).now_or_never().unwrap().unwrap();
+        core.enable_watchdog(WatchdogConfig::default());
+        #[cfg(feature = "mem-track")]
+        let mem_guard = super::super::mem::LeakGuard(core.backend.kernel().mem_tracker());
+        let host = USBHost {
+            backend: Box::new(core),
+            raw_probe_order: false,
+            spawner: None,
+            #[cfg(feature = "mem-track")]
+            _mem_guard: mem_guard,
+        };
+        let mut host = host
+            .spawn_monitor(|_, _| async { ControlFlow::Break(()) }.boxed())
+            .unwrap_err();
+
+        let queue = Arc::new(TaskQueue::default());
+        host.set_spawner(queue.clone());
+        let woken = Arc::new(AtomicBool::new(false));
+        let flag = woken.clone();
+        let spawned = host.spawn_monitor(move |_, event| {
+            assert!(matches!(
+                event,
+                Ok(HostEvent::Hotplug(HotplugEvent::RemoteWakeup { id: 3 }))
+            ));
+            flag.store(true, Ordering::Relaxed);
+            async { ControlFlow::Break(()) }.boxed()
+        });
+        assert!(spawned.is_ok());
+
+        let mut task = queue.pop().unwrap();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        assert!(task.as_mut().poll(&mut cx).is_pending());
+        root_log.lock().unwrap().wakeups.push(2);
+        assert!(task.as_mut().poll(&mut cx).is_ready());
+        assert!(woken.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn hubs_and_ports_cover_external_hubs() {
+        let (mut core, _, _) = topology();
+        // 根端口 3 上是 HubDevice（设备 4），其端口 2 上是设备 5
+        let hub = Hub::new(
+            Box::new(HubDevice::mock(4, 2)),
+            &core.hub_infos(),
+            3,
+            core.root_hub,
+        );
+        let hub_id = core.hubs.alloc(hub);
+        core.hub_devices.insert(4, hub_id);
+        core.insert_device(4, alloc::vec![3], true);
+        core.insert_device(5, alloc::vec![3, 2], false);
+
+        // MockHub 不是 HubDevice，只列出设备 4
+        let hubs = core.hubs();
+        assert_eq!(hubs.len(), 1);
+        assert_eq!(hubs[0].0, 4);
+        assert_eq!(hubs[0].1.ports().count(), 2);
+
+        let ports: Vec<_> = core
+            .ports()
+            .into_iter()
+            .map(|p| (p.path, p.device_id))
+            .collect();
+        assert_eq!(
+            ports,
+            [(alloc::vec![3, 1], None), (alloc::vec![3, 2], Some(5))]
+        );
+    }
 }
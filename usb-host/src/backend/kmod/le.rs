@@ -0,0 +1,119 @@
+//! 控制器 DMA 数据结构的字节序
+//!
+//! xHCI、EHCI、OHCI 在内存中的数据结构（TRB、设备上下文、qTD/QH、TD/ED、帧表等）
+//! 一律为小端序。驱动按原生字节序构造这些字，只在写入 DMA 内存前和读出之后经
+//! [`LeWords::le`] 转换；小端平台上转换不产生任何代码。
+//!
+//! 访问这类内存应使用 [`DmaLe`] 提供的 `read_le`/`set_le`/`write_le`，
+//! 不要直接调用 `DArray::read`/`DArray::set`。
+//!
+//! 控制器寄存器（xhci crate 的 `Registers`、EHCI/OHCI 的 tock-registers）仍按原生字节序
+//! 访问，因此 `kmod` 后端只支持小端目标。
+
+use dma_api::{DArray, DBox};
+use xhci::context::{Device32Byte, Device64Byte, Input32Byte, Input64Byte};
+
+/// 由 32/64 位字组成、在 DMA 内存中按小端序存放的数据
+pub(crate) trait LeWords: Sized {
+    /// 在原生字节序与小端序之间转换，两个方向是同一个操作
+    fn le(self) -> Self;
+}
+
+impl LeWords for u32 {
+    fn le(self) -> Self {
+        self.to_le()
+    }
+}
+
+impl LeWords for u64 {
+    fn le(self) -> Self {
+        self.to_le()
+    }
+}
+
+impl<T: LeWords, const N: usize> LeWords for [T; N] {
+    fn le(self) -> Self {
+        self.map(LeWords::le)
+    }
+}
+
+/// xhci crate 的上下文结构只由 32 位字组成，按字数组整体转换
+macro_rules! le_words_by_u32 {
+    ($($ty:ty),*) => {
+        $(
+            impl LeWords for $ty {
+                fn le(self) -> Self {
+                    const N: usize = size_of::<$ty>() / 4;
+                    // SAFETY: 上下文结构体是 `[u32; _]` 的组合，没有填充与无效位模式
+                    let words: [u32; N] = unsafe { core::mem::transmute(self) };
+                    unsafe { core::mem::transmute(words.le()) }
+                }
+            }
+        )*
+    };
+}
+
+le_words_by_u32!(Device32Byte, Device64Byte, Input32Byte, Input64Byte);
+
+/// 按小端序读写 DMA 内存
+pub(crate) trait DmaLe<T> {
+    fn read_le(&self, index: usize) -> Option<T>;
+    fn set_le(&mut self, index: usize, value: T);
+}
+
+impl<T: LeWords> DmaLe<T> for DArray<T> {
+    fn read_le(&self, index: usize) -> Option<T> {
+        self.read(index).map(LeWords::le)
+    }
+
+    fn set_le(&mut self, index: usize, value: T) {
+        self.set(index, value.le());
+    }
+}
+
+/// 按小端序读写单个 DMA 对象
+pub(crate) trait DmaBoxLe<T> {
+    fn read_le(&self) -> T;
+    fn write_le(&mut self, value: T);
+}
+
+impl<T: LeWords> DmaBoxLe<T> for DBox<T> {
+    fn read_le(&self) -> T {
+        self.read().le()
+    }
+
+    fn write_le(&mut self, value: T) {
+        self.write(value.le());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_words_are_little_endian() {
+        let stored = 0x1234_5678u32.le();
+        assert_eq!(stored.to_ne_bytes(), [0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(stored.le(), 0x1234_5678);
+
+        let stored = [0x0102_0304_0506_0708u64, 1].le();
+        assert_eq!(stored[0].to_ne_bytes(), [8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(stored[1].to_ne_bytes(), [1, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn context_round_trip() {
+        use xhci::context::InputHandler;
+
+        let mut input = Input32Byte::new_32byte();
+        input.control_mut().set_add_context_flag(1);
+        let stored = input.le();
+        let words: [u32; size_of::<Input32Byte>() / 4] = unsafe { core::mem::transmute(stored) };
+        // Add Context Flags 位于输入控制上下文的第 2 个字
+        assert_eq!(words[1].to_ne_bytes(), [0x02, 0, 0, 0]);
+
+        let input: Input32Byte = unsafe { core::mem::transmute(words) };
+        assert!(input.le().control().add_context_flag(1));
+    }
+}
@@ -0,0 +1,258 @@
+//! DMA 内存记账
+//!
+//! 启用 `mem-track` feature 后，控制器的全部 DMA 分配与映射按用途标记计数，
+//! 用于在长时间运行的内核部署中排查泄漏；未启用时标记不产生任何开销。
+
+use core::fmt::{self, Display};
+
+/// DMA 内存用途
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MemTag {
+    /// 命令环、事件环与传输环
+    Ring,
+    /// DCBAA 与设备上下文
+    Context,
+    /// xHCI / DWC3 scratchpad
+    Scratchpad,
+    /// DWC3 事件缓冲区
+    EventBuffer,
+    /// 传输缓冲区映射（包括超出 DMA mask 时的回弹缓冲区）
+    Transfer,
+    /// 未标记的分配
+    Other,
+}
+
+impl MemTag {
+    pub const ALL: [MemTag; 6] = [
+        MemTag::Ring,
+        MemTag::Context,
+        MemTag::Scratchpad,
+        MemTag::EventBuffer,
+        MemTag::Transfer,
+        MemTag::Other,
+    ];
+}
+
+/// 单个用途的内存占用
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemUsage {
+    /// 未释放的分配数
+    pub count: usize,
+    /// 未释放的字节数
+    pub bytes: usize,
+    /// 字节数峰值
+    pub peak_bytes: usize,
+}
+
+/// 控制器 DMA 内存快照
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryReport {
+    usage: [MemUsage; MemTag::ALL.len()],
+}
+
+impl MemoryReport {
+    pub fn get(&self, tag: MemTag) -> MemUsage {
+        self.usage[tag as usize]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MemTag, MemUsage)> + '_ {
+        MemTag::ALL.into_iter().map(|tag| (tag, self.get(tag)))
+    }
+
+    /// 未释放的分配总数
+    pub fn outstanding(&self) -> usize {
+        self.usage.iter().map(|u| u.count).sum()
+    }
+
+    /// 未释放的字节总数
+    pub fn bytes(&self) -> usize {
+        self.usage.iter().map(|u| u.bytes).sum()
+    }
+}
+
+impl Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (tag, usage) in self.iter() {
+            writeln!(
+                f,
+                "{tag:?}: {} allocs, {} bytes (peak {})",
+                usage.count, usage.bytes, usage.peak_bytes
+            )?;
+        }
+        write!(
+            f,
+            "total: {} allocs, {} bytes",
+            self.outstanding(),
+            self.bytes()
+        )
+    }
+}
+
+#[cfg(feature = "mem-track")]
+pub(crate) use track::*;
+
+#[cfg(feature = "mem-track")]
+mod track {
+    use alloc::boxed::Box;
+    use core::{
+        alloc::Layout,
+        num::NonZeroUsize,
+        ptr::NonNull,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use dma_api::{DmaDirection, DmaError, DmaHandle, DmaMapHandle, DmaOp};
+
+    use super::{MemTag, MemUsage, MemoryReport};
+    use crate::osal::KernelOp;
+
+    #[derive(Default)]
+    struct TagUsage {
+        count: AtomicUsize,
+        bytes: AtomicUsize,
+        peak_bytes: AtomicUsize,
+    }
+
+    impl TagUsage {
+        fn alloc(&self, size: usize) {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            let bytes = self.bytes.fetch_add(size, Ordering::Relaxed) + size;
+            self.peak_bytes.fetch_max(bytes, Ordering::Relaxed);
+        }
+
+        fn free(&self, size: usize) {
+            self.count.fetch_sub(1, Ordering::Relaxed);
+            self.bytes.fetch_sub(size, Ordering::Relaxed);
+        }
+
+        fn snapshot(&self) -> MemUsage {
+            MemUsage {
+                count: self.count.load(Ordering::Relaxed),
+                bytes: self.bytes.load(Ordering::Relaxed),
+                peak_bytes: self.peak_bytes.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// 为某一用途计数的 `DmaOp`，其余操作转发给平台实现
+    struct TrackedOp {
+        inner: &'static dyn KernelOp,
+        usage: &'static TagUsage,
+    }
+
+    /// 每个控制器一份的记账表
+    ///
+    /// `DeviceDma` 只接受 `'static` 的 `DmaOp`，记账表在创建控制器时泄漏，
+    /// 只占用少量堆内存且不会被释放。
+    #[derive(Clone, Copy)]
+    pub(crate) struct MemTracker {
+        usage: &'static [TagUsage; MemTag::ALL.len()],
+        ops: &'static [TrackedOp; MemTag::ALL.len()],
+    }
+
+    impl MemTracker {
+        pub fn new(inner: &'static dyn KernelOp) -> Self {
+            let usage: &'static [TagUsage; MemTag::ALL.len()] = Box::leak(Box::default());
+            let ops = Box::leak(Box::new(core::array::from_fn(|i| TrackedOp {
+                inner,
+                usage: &usage[i],
+            })));
+            Self { usage, ops }
+        }
+
+        pub fn op(&self, tag: MemTag) -> &'static dyn DmaOp {
+            &self.ops[tag as usize]
+        }
+
+        pub fn report(&self) -> MemoryReport {
+            MemoryReport {
+                usage: core::array::from_fn(|i| self.usage[i].snapshot()),
+            }
+        }
+    }
+
+    /// 在 [`USBHost`](crate::USBHost) 释放时检查是否仍有未释放的 DMA 内存
+    ///
+    /// 作为 `USBHost` 的最后一个字段，在后端释放之后才被析构。
+    pub(crate) struct LeakGuard(pub MemTracker);
+
+    impl Drop for LeakGuard {
+        fn drop(&mut self) {
+            let report = self.0.report();
+            assert!(
+                report.outstanding() == 0,
+                "DMA memory leaked at shutdown:\n{report}"
+            );
+        }
+    }
+
+    impl DmaOp for TrackedOp {
+        fn page_size(&self) -> usize {
+            self.inner.page_size()
+        }
+
+        unsafe fn map_single(
+            &self,
+            dma_mask: u64,
+            addr: NonNull<u8>,
+            size: NonZeroUsize,
+            align: usize,
+            direction: DmaDirection,
+        ) -> Result<DmaMapHandle, DmaError> {
+            let handle = unsafe {
+                self.inner
+                    .map_single(dma_mask, addr, size, align, direction)?
+            };
+            self.usage.alloc(handle.size());
+            Ok(handle)
+        }
+
+        unsafe fn unmap_single(&self, handle: DmaMapHandle) {
+            self.usage.free(handle.size());
+            unsafe { self.inner.unmap_single(handle) }
+        }
+
+        fn flush(&self, addr: NonNull<u8>, size: usize) {
+            self.inner.flush(addr, size)
+        }
+
+        fn invalidate(&self, addr: NonNull<u8>, size: usize) {
+            self.inner.invalidate(addr, size)
+        }
+
+        fn flush_invalidate(&self, addr: NonNull<u8>, size: usize) {
+            self.inner.flush_invalidate(addr, size)
+        }
+
+        unsafe fn alloc_coherent(&self, dma_mask: u64, layout: Layout) -> Option<DmaHandle> {
+            let handle = unsafe { self.inner.alloc_coherent(dma_mask, layout)? };
+            self.usage.alloc(handle.size());
+            Some(handle)
+        }
+
+        unsafe fn dealloc_coherent(&self, handle: DmaHandle) {
+            self.usage.free(handle.size());
+            unsafe { self.inner.dealloc_coherent(handle) }
+        }
+
+        fn prepare_read(
+            &self,
+            handle: &DmaMapHandle,
+            offset: usize,
+            size: usize,
+            direction: DmaDirection,
+        ) {
+            self.inner.prepare_read(handle, offset, size, direction)
+        }
+
+        fn confirm_write(
+            &self,
+            handle: &DmaMapHandle,
+            offset: usize,
+            size: usize,
+            direction: DmaDirection,
+        ) {
+            self.inner.confirm_write(handle, offset, size, direction)
+        }
+    }
+}
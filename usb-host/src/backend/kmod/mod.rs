@@ -2,11 +2,25 @@ use crate::backend::kmod::hub::{Hub, HubInfo};
 use crate::{Mmio, USBHost};
 
 mod dwc;
+mod ehci;
 mod hub;
+mod iommu;
 mod kcore;
+mod le;
+pub(crate) mod mem;
+mod ohci;
 pub mod osal;
+mod perf;
+mod port_event;
 pub(crate) mod queue;
-mod transfer;
+#[cfg(test)]
+pub(crate) mod test_core;
+#[cfg(test)]
+pub(crate) mod test_kernel;
+pub(crate) mod transfer;
+#[cfg(all(feature = "vfio", target_os = "linux"))]
+mod vfio;
+mod watchdog;
 mod xhci;
 
 use crate::err::*;
@@ -15,34 +29,87 @@ use alloc::boxed::Box;
 
 use alloc::collections::btree_map::BTreeMap;
 use dwc::Dwc;
+use ehci::Ehci;
 use id_arena::Id;
+#[cfg(test)]
+pub(crate) use kcore::Core;
 use kcore::*;
+use ohci::Ohci;
 use usb_if::Speed;
 use xhci::Xhci;
+pub use xhci::{
+    DbcConfig, DbcState, EnumerationMode, ImodPolicy, InitProgress, InitStage, InterrupterMap,
+    ScratchpadPolicy, TransferRingSize, XhciConfig, XhciDbc,
+};
 
 pub use dwc::{
-    CruOp, DwcNewParams, DwcParams, UdphyParam, Usb2PhyParam, UsbPhyInterfaceMode,
+    CruOp, Dwc3Regs, DwcNewParams, DwcParams, UdphyParam, Usb2PhyParam, UsbPhyInterfaceMode,
+    extcon::{ExtconState, Usb2PhyExtcon},
     usb2phy::Usb2PhyPortId,
 };
+pub use hub::{HubDevice, HubPortStatus};
+pub use iommu::IommuDomain;
+pub use mem::{MemTag, MemUsage, MemoryReport};
 pub use osal::*;
+pub use perf::{PerfCounters, SelfTestReport};
+pub use port_event::{
+    PortEvent, PortEventCallback, PortSnapshot, PortState, PortSubscription, PortTransition,
+};
+#[cfg(feature = "dma-mapping")]
+pub use transfer::DmaMapping;
+#[cfg(all(feature = "vfio", target_os = "linux"))]
+pub use vfio::{VfioConfig, VfioIrq, VfioKernel, VfioPci};
+pub use watchdog::{WatchdogConfig, WatchdogEvent};
 
 impl USBHost {
     pub fn new_xhci(mmio: Mmio, kernel: &'static dyn KernelOp) -> Result<USBHost> {
         Ok(USBHost::new(Xhci::new(mmio, kernel)?))
     }
 
+    pub fn new_xhci_with_config(
+        mmio: Mmio,
+        kernel: &'static dyn KernelOp,
+        config: XhciConfig,
+    ) -> Result<USBHost> {
+        Ok(USBHost::new(Xhci::new_with_config(mmio, kernel, config)?))
+    }
+
+    /// 只有 USB 2.0 控制器的平台，全速/低速设备需经高速 Hub 连接
+    pub fn new_ehci(mmio: Mmio, kernel: &'static dyn KernelOp) -> Result<USBHost> {
+        Ok(USBHost::new(Ehci::new(mmio, kernel)?))
+    }
+
+    /// EHCI 的伴随控制器，EHCI 交出的全速/低速根端口设备出现在这里
+    pub fn new_ohci(mmio: Mmio, kernel: &'static dyn KernelOp) -> Result<USBHost> {
+        Ok(USBHost::new(Ohci::new(mmio, kernel)?))
+    }
+
     pub fn new_dwc(params: DwcNewParams<'_, impl CruOp>) -> Result<USBHost> {
         Ok(USBHost::new(Dwc::new(params)?))
     }
 
     pub(crate) fn new(backend: impl CoreOp) -> Self {
+        #[cfg(feature = "mem-track")]
+        let mem_guard = mem::LeakGuard(backend.kernel().mem_tracker());
         let b = Core::new(backend);
         Self {
             backend: Box::new(b),
+            raw_probe_order: false,
+            spawner: None,
+            #[cfg(feature = "mem-track")]
+            _mem_guard: mem_guard,
         }
     }
 }
 
+/// 已连接但尚未枚举的根端口，见 [`EnumerationMode::Lazy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingPort {
+    /// 根端口号，从 1 开始
+    pub port: u8,
+    pub speed: Speed,
+}
+
 pub struct DeviceAddressInfo {
     pub root_port_id: u8,
     pub parent_hub: Option<Id<Hub>>,
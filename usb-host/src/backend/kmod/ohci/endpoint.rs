@@ -0,0 +1,486 @@
+//! OHCI 端点
+//!
+//! 每个端点一个 ED 与一个 TD 池。ED 的 TailP 始终指向一个空 TD：提交时把新请求
+//! 的第一个 TD 写进该槽，最后改写 TailP 把整个请求交给控制器（OHCI 规范 5.2.8.2）。
+//! 控制器退役 TD 后推进 HeadP，HeadP 之前的 TD 都已完成。
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Waker},
+    time::Duration,
+};
+
+use dma_api::{DArray, DmaDirection};
+use futures::{FutureExt, future::BoxFuture};
+use mbarrier::mb;
+use usb_if::{
+    endpoint::{RequestId, TransferCompletion, TransferRequest},
+    err::{TransferError, USBError},
+    transfer::Direction,
+};
+
+use super::{
+    schedule::{ED_HALTED, ED_HEAD, ED_TAIL, ED_TOGGLE_CARRY, EdKind, PTR_MASK, Schedule},
+    td::{self, CC_DATA_UNDERRUN, EdInfo, Pid, Progress, Retired, Stage, TdError, Toggle},
+};
+use crate::{
+    backend::{
+        kmod::{le::DmaLe, mem::MemTag},
+        ty::{
+            ep::{EndpointOp, transfer_to_completion},
+            timer::Timer,
+            transfer::{Transfer, TransferKind},
+        },
+    },
+    osal::Kernel,
+};
+
+/// TD 占 16 字节，槽取 32 字节，后半部分存放 SETUP 包
+type TdSlot = [u32; 8];
+const TD_SLOT_SIZE: usize = core::mem::size_of::<TdSlot>();
+/// 每个端点的 TD 数量，一个 TD 最多覆盖 8K 数据
+const TD_POOL: usize = 64;
+const SETUP_WORD: usize = 4;
+
+struct Request {
+    transfer: Transfer,
+    /// 占用的 TD 槽与其阶段，按执行顺序
+    tds: Vec<(usize, Stage)>,
+}
+
+pub(crate) struct Endpoint {
+    schedule: Arc<Schedule>,
+    ed: usize,
+    info: EdInfo,
+    tds: DArray<TdSlot>,
+    free: Vec<usize>,
+    /// TailP 指向的空 TD
+    dummy: usize,
+    requests: BTreeMap<RequestId, Request>,
+    next_id: u64,
+    /// 传输缓冲区使用的 DMA 约束
+    kernel: Kernel,
+    /// 设备已弹出，拒绝新请求
+    closed: Arc<AtomicBool>,
+}
+
+unsafe impl Send for Endpoint {}
+unsafe impl Sync for Endpoint {}
+
+impl Endpoint {
+    pub fn new(
+        schedule: Arc<Schedule>,
+        kernel: &Kernel,
+        info: EdInfo,
+        kind: EdKind,
+        closed: Arc<AtomicBool>,
+    ) -> Result<Self, USBError> {
+        let tds = kernel
+            .with_tag(MemTag::Ring)
+            .array_zero_with_align::<TdSlot>(TD_POOL, TD_SLOT_SIZE, DmaDirection::Bidirectional)
+            .map_err(|_| USBError::NoMemory)?;
+        let dummy = 0;
+        let first = tds.dma_addr().as_u64() as u32;
+        let ed = schedule.alloc(&info, kind, first)?;
+        schedule.link(ed);
+
+        Ok(Self {
+            schedule,
+            ed,
+            info,
+            tds,
+            free: (1..TD_POOL).rev().collect(),
+            dummy,
+            requests: BTreeMap::new(),
+            next_id: 1,
+            kernel: kernel.clone(),
+            closed,
+        })
+    }
+
+    /// 替换传输缓冲区使用的 DMA 约束，ED 与 TD 仍使用创建时的内存
+    pub fn set_transfer_dma(&mut self, kernel: Kernel) {
+        self.kernel = kernel;
+    }
+
+    /// 修改设备地址或最大包长，用于 SET_ADDRESS 与读取 bMaxPacketSize0 之后
+    pub fn update(&mut self, f: impl FnOnce(&mut EdInfo)) {
+        f(&mut self.info);
+        self.schedule.update(self.ed, &self.info);
+    }
+
+    fn td_addr(&self, slot: usize) -> u32 {
+        (self.tds.dma_addr().as_u64() as usize + slot * TD_SLOT_SIZE) as u32
+    }
+
+    fn td_words(&self, slot: usize) -> [u32; 4] {
+        let s = self.tds.read_le(slot).unwrap();
+        [s[0], s[1], s[2], s[3]]
+    }
+
+    fn head(&self) -> u32 {
+        self.schedule.word(self.ed, ED_HEAD)
+    }
+
+    fn slot_of(&self, addr: u32) -> usize {
+        (addr & PTR_MASK).wrapping_sub(self.td_addr(0)) as usize / TD_SLOT_SIZE
+    }
+
+    /// 从 HeadP 所指 TD 开始直到队尾、控制器尚未退役的 TD 槽
+    fn queued(&self) -> Vec<usize> {
+        let head = self.slot_of(self.head());
+        let order: Vec<usize> = self
+            .requests
+            .values()
+            .flat_map(|r| r.tds.iter().map(|&(slot, _)| slot))
+            .collect();
+        match order.iter().position(|&slot| slot == head) {
+            Some(i) => order[i..].to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    /// 请求之后第一个 TD 的地址，没有后续请求时为 TailP 所指的空 TD
+    fn next_first(&self, id: RequestId) -> u32 {
+        self.requests
+            .range(id..)
+            .find(|&(&other, _)| other != id)
+            .map_or(self.td_addr(self.dummy), |(_, r)| self.td_addr(r.tds[0].0))
+    }
+
+    /// 改写 HeadP，保留 toggleCarry，`halted` 决定 ED 是否继续停止
+    fn set_head(&self, addr: u32, halted: bool) {
+        let mut head = addr | (self.head() & ED_TOGGLE_CARRY);
+        if halted {
+            head |= ED_HALTED;
+        }
+        self.schedule.set_word(self.ed, ED_HEAD, head);
+        if !halted {
+            self.schedule.fill(self.ed);
+        }
+    }
+
+    /// 短包使 ED 以 DataUnderrun 停止时，跳过请求剩余的数据 TD 后恢复执行
+    fn recover_short_packet(&mut self) {
+        let head = self.head();
+        if head & ED_HALTED == 0 {
+            return;
+        }
+        let head_slot = self.slot_of(head);
+        // 停止时最后退役的 TD 就是出错的 TD，它位于 HeadP 所指 TD 之前
+        let mut last = None;
+        'find: for (&id, req) in &self.requests {
+            for &(slot, stage) in &req.tds {
+                if slot == head_slot {
+                    break 'find;
+                }
+                last = Some((id, slot, stage));
+            }
+        }
+        let Some((id, slot, Stage::Data(_))) = last else {
+            return;
+        };
+        if td::condition(&self.td_words(slot)) != CC_DATA_UNDERRUN {
+            return;
+        }
+        let req = &self.requests[&id];
+        let next = match req.tds.last() {
+            Some(&(status, Stage::Status)) => self.td_addr(status),
+            _ => self.next_first(id),
+        };
+        trace!(
+            "ep {}: short packet, resume at {next:#x}",
+            self.info.endpoint
+        );
+        self.set_head(next, false);
+    }
+
+    fn progress(&self, req: &Request) -> Progress {
+        let queued = self.queued();
+        let tds: Vec<(Stage, Option<Retired>)> = req
+            .tds
+            .iter()
+            .map(|&(slot, stage)| {
+                let retired = (!queued.contains(&slot)).then(|| Retired::new(&self.td_words(slot)));
+                (stage, retired)
+            })
+            .collect();
+        td::progress(&tds)
+    }
+
+    fn release(&mut self, req: &Request) {
+        self.free.extend(req.tds.iter().map(|&(slot, _)| slot));
+    }
+
+    /// 各 TD 的 `(阶段, PID, 数据切换位, 允许短包, 缓冲区地址, 长度)`
+    fn build(&self, transfer: &Transfer, data: u32) -> Vec<(Stage, Pid, Toggle, bool, u32, usize)> {
+        let len = transfer.buffer_len();
+        let data_pid = match transfer.direction {
+            Direction::In => Pid::In,
+            Direction::Out => Pid::Out,
+        };
+        let mps = self.info.max_packet.max(1) as usize;
+        let parts = if len > 0 {
+            td::split_buffer(data, len, mps)
+        } else {
+            Vec::new()
+        };
+        let count = parts.len();
+        // 只有最后一个数据 TD 允许短包，其余 TD 短包时 ED 停止，由端点跳过剩余部分
+        let rounding = |i: usize| i + 1 == count;
+        let mut out = Vec::new();
+
+        if let TransferKind::Control(_) = transfer.kind {
+            out.push((Stage::Setup, Pid::Setup, Toggle::Data0, false, 0, 8));
+            // 数据阶段从 DATA1 开始，每个 TD 之后按其包数翻转
+            let mut toggle = true;
+            for (i, (addr, n)) in parts.into_iter().enumerate() {
+                let t = if toggle { Toggle::Data1 } else { Toggle::Data0 };
+                out.push((Stage::Data(n), data_pid, t, rounding(i), addr, n));
+                toggle ^= n.div_ceil(mps) % 2 == 1;
+            }
+            let status_pid = match (len, transfer.direction) {
+                (0, _) | (_, Direction::Out) => Pid::In,
+                _ => Pid::Out,
+            };
+            out.push((Stage::Status, status_pid, Toggle::Data1, false, 0, 0));
+        } else if count == 0 {
+            out.push((Stage::Data(0), data_pid, Toggle::Carry, false, 0, 0));
+        } else {
+            for (i, (addr, n)) in parts.into_iter().enumerate() {
+                out.push((
+                    Stage::Data(n),
+                    data_pid,
+                    Toggle::Carry,
+                    rounding(i),
+                    addr,
+                    n,
+                ));
+            }
+        }
+        out
+    }
+
+    fn setup_words(transfer: &Transfer) -> Option<[u32; 2]> {
+        let TransferKind::Control(setup) = &transfer.kind else {
+            return None;
+        };
+        let bytes = setup.to_bytes(transfer.direction, transfer.buffer_len() as u16);
+        Some([
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        ])
+    }
+
+    /// 跳过 ED 后丢弃请求，修补前一个请求与 HeadP 中指向它的链接
+    async fn cancel(&mut self, id: RequestId) -> Result<Option<TransferCompletion>, TransferError> {
+        if !self.requests.contains_key(&id) {
+            return Err(TransferError::InvalidEndpoint);
+        }
+        if let Some(res) = self.reclaim_request(id) {
+            return res.map(Some);
+        }
+
+        self.schedule.set_skip(self.ed, true);
+        // 等待期间可能已完成
+        if let Some(res) = self.reclaim_request(id) {
+            self.schedule.set_skip(self.ed, false);
+            return res.map(Some);
+        }
+
+        let next_first = self.next_first(id);
+        let req = self.requests.remove(&id).unwrap();
+        let cancelled: Vec<usize> = req.tds.iter().map(|&(slot, _)| slot).collect();
+
+        if let Some((_, prev)) = self.requests.range(..id).next_back() {
+            let (last, _) = *prev.tds.last().unwrap();
+            let mut td = self.tds.read_le(last).unwrap();
+            td[2] = next_first;
+            self.tds.set_le(last, td);
+        }
+        let head = self.head();
+        if cancelled.contains(&self.slot_of(head)) {
+            self.set_head(next_first, head & ED_HALTED != 0);
+        }
+        self.release(&req);
+        self.schedule.set_skip(self.ed, false);
+        self.schedule.fill(self.ed);
+        Ok(None)
+    }
+
+    /// 清除 ED 的 Halted，数据切换位与设备侧一起归零
+    async fn reset_halted(&mut self) -> Result<(), TransferError> {
+        let head = self.head();
+        if head & ED_HALTED == 0 {
+            return Ok(());
+        }
+        self.schedule.set_word(self.ed, ED_HEAD, head & PTR_MASK);
+        self.schedule.fill(self.ed);
+        Ok(())
+    }
+}
+
+fn td_error(e: TdError) -> TransferError {
+    match e {
+        TdError::Stall => TransferError::Stall,
+        TdError::StatusStall => TransferError::StatusStall,
+        e => TransferError::Other(anyhow!("OHCI transfer error: {e:?}")),
+    }
+}
+
+impl Timer for Endpoint {
+    fn now(&self) -> Duration {
+        self.kernel.now()
+    }
+
+    fn wake_at(&self, deadline: Duration, waker: &Waker) {
+        self.kernel.wake_at(deadline, waker)
+    }
+}
+
+impl EndpointOp for Endpoint {
+    fn submit_request(&mut self, request: TransferRequest) -> Result<RequestId, TransferError> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(TransferError::NoDevice);
+        }
+        let transfer = Transfer::from_request(&self.kernel, request)?;
+        if matches!(transfer.kind, TransferKind::Isochronous { .. }) {
+            return Err(TransferError::NotSupported);
+        }
+
+        let mut data = 0;
+        if transfer.buffer_len() > 0 {
+            if matches!(transfer.direction, Direction::Out) {
+                transfer.confirm_write_all();
+            }
+            let addr = transfer.dma_addr();
+            if addr + transfer.buffer_len() as u64 > u32::MAX as u64 {
+                return Err(TransferError::Other(anyhow!(
+                    "DMA address {addr:#x} exceeds OHCI 32-bit addressing"
+                )));
+            }
+            data = addr as u32;
+        }
+
+        let stages = self.build(&transfer, data);
+        // 除复用的空 TD 外，每个 TD 需要一个新槽，另需一个新的空 TD
+        if stages.len() > self.free.len() {
+            return Err(TransferError::QueueFull);
+        }
+        let mut slots = Vec::with_capacity(stages.len());
+        slots.push(self.dummy);
+        for _ in 1..stages.len() {
+            slots.push(self.free.pop().unwrap());
+        }
+        let new_dummy = self.free.pop().unwrap();
+        self.tds.set_le(new_dummy, TdSlot::default());
+
+        let setup = Self::setup_words(&transfer);
+        for (i, &(stage, pid, toggle, rounding, addr, len)) in stages.iter().enumerate() {
+            let slot = slots[i];
+            let last = i + 1 == stages.len();
+            let next = match slots.get(i + 1) {
+                Some(&s) => self.td_addr(s),
+                None => self.td_addr(new_dummy),
+            };
+            let buf = match stage {
+                Stage::Setup => self.td_addr(slot) + (SETUP_WORD * 4) as u32,
+                _ => addr,
+            };
+            let mut td = TdSlot::default();
+            td[..4].copy_from_slice(&td::td(pid, toggle, rounding, last, buf, len, next));
+            if let (Stage::Setup, Some(setup)) = (stage, setup) {
+                td[SETUP_WORD..SETUP_WORD + 2].copy_from_slice(&setup);
+            }
+            self.tds.set_le(slot, td);
+        }
+
+        // HeadP 等于 TailP 时控制器不访问队列，改写 TailP 后整个请求才生效
+        mb();
+        self.schedule
+            .set_word(self.ed, ED_TAIL, self.td_addr(new_dummy));
+        self.schedule.fill(self.ed);
+
+        self.dummy = new_dummy;
+        let id = RequestId::new(self.next_id);
+        self.next_id += 1;
+        self.requests.insert(
+            id,
+            Request {
+                transfer,
+                tds: slots.into_iter().zip(stages.iter().map(|s| s.0)).collect(),
+            },
+        );
+        Ok(id)
+    }
+
+    fn reclaim_request(
+        &mut self,
+        id: RequestId,
+    ) -> Option<Result<TransferCompletion, TransferError>> {
+        self.requests.get(&id)?;
+        self.recover_short_packet();
+        let progress = self.progress(&self.requests[&id]);
+        if progress == Progress::Pending {
+            return None;
+        }
+
+        let head = self.head();
+        // 失败请求的剩余 TD 仍在队列中，ED 保持停止，先把 HeadP 移到下一个请求
+        if head & ED_HALTED != 0
+            && self.requests[&id]
+                .tds
+                .iter()
+                .any(|&(slot, _)| slot == self.slot_of(head))
+        {
+            self.set_head(self.next_first(id), true);
+        }
+
+        let req = self.requests.remove(&id).unwrap();
+        self.release(&req);
+        let mut t = req.transfer;
+        match progress {
+            Progress::Done(actual) => {
+                if actual > 0 && matches!(t.direction, Direction::In) {
+                    t.prepare_read_all();
+                }
+                t.transfer_len = actual;
+                Some(Ok(transfer_to_completion(id, t)))
+            }
+            Progress::Failed(e) => Some(Err(td_error(e))),
+            Progress::Pending => unreachable!(),
+        }
+    }
+
+    fn register_waker(&self, _id: RequestId, cx: &mut Context<'_>) {
+        self.schedule.register_waker(self.ed, cx.waker());
+    }
+
+    fn pending_requests(&self) -> Vec<RequestId> {
+        self.requests.keys().copied().collect()
+    }
+
+    fn cancel_request(
+        &mut self,
+        id: RequestId,
+    ) -> BoxFuture<'_, Result<Option<TransferCompletion>, TransferError>> {
+        self.cancel(id).boxed()
+    }
+
+    fn reset_halt(&mut self) -> BoxFuture<'_, Result<(), TransferError>> {
+        self.reset_halted().boxed()
+    }
+
+    #[cfg(feature = "dma-mapping")]
+    fn dma_mapping(&self, id: RequestId) -> Option<crate::backend::kmod::DmaMapping> {
+        self.requests.get(&id)?.transfer.dma_mapping()
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        // 归还前等待控制器不再访问 ED，TD 池随后释放
+        self.schedule.free(self.ed);
+    }
+}
@@ -0,0 +1,293 @@
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use futures::{FutureExt, future::BoxFuture};
+use tock_registers::interfaces::*;
+use usb_if::err::USBError;
+
+use super::{
+    device::Device,
+    hub::{OhciRootHub, PortChangeWaker},
+    reg::{
+        HC_COMMAND_STATUS, HC_CONTROL, HC_FM_INTERVAL, HC_INTERRUPT, HC_RH_DESCRIPTOR_A,
+        HC_RH_PORT_STATUS, OhciRegs,
+    },
+    schedule::Schedule,
+};
+use crate::{
+    DeviceAddressInfo, KernelOp, Mmio,
+    backend::{
+        kmod::{PerfCounters, SelfTestReport, hub::HubOp, kcore::CoreOp, perf::PerfStats},
+        ty::{DeviceOp, Event, EventHandlerOp},
+    },
+    err::Result,
+    osal::Kernel,
+};
+
+/// 从 SMM 接管控制器与软件复位的上限，见 OHCI 5.1.1.3
+const TAKEOVER_TIMEOUT: Duration = Duration::from_millis(100);
+const RESET_TIMEOUT: Duration = Duration::from_millis(10);
+/// 1ms 帧对应的位时间减 1
+const FRAME_INTERVAL: u32 = 11999;
+/// 低速事务不得在帧剩余时间低于此值时开始
+const LS_THRESHOLD: u32 = 0x628;
+/// HcInterruptStatus 中写 1 清除的中断位
+const INTERRUPT_MASK: u32 = 0x7f | 1 << 30;
+
+pub struct Ohci {
+    reg: OhciRegs,
+    kernel: Kernel,
+    schedule: Arc<Schedule>,
+    root_hub: Option<OhciRootHub>,
+    event_handler: Option<EventHandler>,
+    stats: Arc<PerfStats>,
+    /// 已分配的设备地址及其弹出标志，地址 0 保留给枚举
+    devices: BTreeMap<u8, Arc<AtomicBool>>,
+}
+
+unsafe impl Send for Ohci {}
+unsafe impl Sync for Ohci {}
+
+impl CoreOp for Ohci {
+    fn init<'a>(&'a mut self) -> BoxFuture<'a, core::result::Result<(), USBError>> {
+        self._init().boxed()
+    }
+
+    fn root_hub(&mut self) -> Box<dyn HubOp> {
+        Box::new(
+            self.root_hub
+                .take()
+                .expect("Root hub can only be taken once"),
+        )
+    }
+
+    fn new_addressed_device<'a>(
+        &'a mut self,
+        addr: DeviceAddressInfo,
+    ) -> BoxFuture<'a, Result<Box<dyn DeviceOp>>> {
+        self.new_device(addr).boxed()
+    }
+
+    fn create_event_handler(&mut self) -> Box<dyn EventHandlerOp> {
+        Box::new(
+            self.event_handler
+                .take()
+                .expect("Event handler can only be created once"),
+        )
+    }
+
+    fn kernel(&self) -> &Kernel {
+        &self.kernel
+    }
+
+    fn perf_counters(&self) -> PerfCounters {
+        self.stats.snapshot()
+    }
+
+    /// OHCI 没有命令环
+    fn self_test<'a>(&'a mut self, _count: u32) -> BoxFuture<'a, Result<SelfTestReport>> {
+        async { Err(USBError::NotSupported) }.boxed()
+    }
+
+    fn eject_slot<'a>(
+        &'a mut self,
+        slot_id: u8,
+        power_off_port: Option<u8>,
+    ) -> BoxFuture<'a, Result> {
+        self._eject_slot(slot_id, power_off_port).boxed()
+    }
+
+    fn frame_index(&self) -> Option<u16> {
+        let regs = self.reg.regs();
+        if !regs.control.matches_all(HC_CONTROL::HCFS::Operational) {
+            return None;
+        }
+        Some(regs.fm_number.get() as u16)
+    }
+}
+
+impl Ohci {
+    pub fn new(mmio: Mmio, kernel: &'static dyn KernelOp) -> Result<Self> {
+        let reg = unsafe { OhciRegs::new(mmio) };
+        info!(
+            "OHCI {:#x}: {} ports",
+            reg.regs().revision.get() & 0xff,
+            reg.port_count()
+        );
+        // OHCI 只有 32 位地址
+        let kernel = Kernel::new(u32::MAX as u64, kernel);
+        let schedule = Arc::new(Schedule::new(reg, &kernel)?);
+        let stats = Arc::new(PerfStats::default());
+        let root_hub = OhciRootHub::new(reg, kernel.clone());
+        let event_handler = EventHandler {
+            reg,
+            schedule: schedule.clone(),
+            ports: root_hub.waker(),
+            stats: stats.clone(),
+        };
+
+        Ok(Self {
+            reg,
+            kernel,
+            schedule,
+            root_hub: Some(root_hub),
+            event_handler: Some(event_handler),
+            stats,
+            devices: BTreeMap::new(),
+        })
+    }
+
+    /// 在 `timeout` 内等待 `done` 成立
+    fn wait(&self, timeout: Duration, done: impl Fn() -> bool) -> Result {
+        let deadline = self.kernel.now() + timeout;
+        while !done() {
+            if self.kernel.now() >= deadline {
+                return Err(USBError::Timeout);
+            }
+            self.kernel.delay(Duration::from_micros(100));
+        }
+        Ok(())
+    }
+
+    /// OHCI 5.1.1 Initialization
+    async fn _init(&mut self) -> Result {
+        let regs = self.reg.regs();
+        if regs.control.is_set(HC_CONTROL::IR) {
+            // 固件仍在使用控制器，请求其交出所有权
+            regs.command_status.write(HC_COMMAND_STATUS::OCR::SET);
+            self.wait(TAKEOVER_TIMEOUT, || !regs.control.is_set(HC_CONTROL::IR))?;
+        }
+
+        regs.command_status.write(HC_COMMAND_STATUS::HCR::SET);
+        self.wait(RESET_TIMEOUT, || {
+            !regs.command_status.is_set(HC_COMMAND_STATUS::HCR)
+        })?;
+        debug!("OHCI reset done");
+
+        // 复位后控制器处于 Suspend，需在 2ms 内进入 Operational
+        regs.hcca.set(self.schedule.hcca_addr());
+        regs.control_head_ed.set(0);
+        regs.bulk_head_ed.set(0);
+        let toggle = !regs.fm_interval.is_set(HC_FM_INTERVAL::FIT);
+        regs.fm_interval.write(
+            HC_FM_INTERVAL::FI.val(FRAME_INTERVAL)
+                + HC_FM_INTERVAL::FSMPS.val(6 * (FRAME_INTERVAL - 210) / 7)
+                + HC_FM_INTERVAL::FIT.val(toggle as u32),
+        );
+        regs.periodic_start.set(FRAME_INTERVAL * 9 / 10);
+        regs.ls_threshold.set(LS_THRESHOLD);
+        regs.interrupt_status.set(INTERRUPT_MASK);
+        regs.interrupt_enable.write(
+            HC_INTERRUPT::MIE::SET
+                + HC_INTERRUPT::WDH::SET
+                + HC_INTERRUPT::RHSC::SET
+                + HC_INTERRUPT::UE::SET
+                + HC_INTERRUPT::SO::SET,
+        );
+        regs.control.write(
+            HC_CONTROL::CBSR.val(3)
+                + HC_CONTROL::PLE::SET
+                + HC_CONTROL::CLE::SET
+                + HC_CONTROL::BLE::SET
+                + HC_CONTROL::HCFS::Operational,
+        );
+        debug!("OHCI running");
+        Ok(())
+    }
+
+    fn alloc_address(&self) -> Result<u8> {
+        (1..=127u8)
+            .find(|a| !self.devices.contains_key(a))
+            .ok_or(USBError::NoMemory)
+    }
+
+    async fn new_device(&mut self, info: DeviceAddressInfo) -> Result<Box<dyn DeviceOp>> {
+        let address = self.alloc_address()?;
+        let closed = Arc::new(AtomicBool::new(false));
+        let mut device = Device::new(
+            address,
+            self.schedule.clone(),
+            &self.kernel,
+            &info,
+            closed.clone(),
+        );
+        device.init().await?;
+        self.devices.insert(address, closed);
+        Ok(Box::new(device))
+    }
+
+    async fn _eject_slot(&mut self, address: u8, power_off_port: Option<u8>) -> Result {
+        if let Some(port) = power_off_port {
+            let idx = (port as usize)
+                .checked_sub(1)
+                .filter(|&i| i < self.reg.port_count())
+                .ok_or(USBError::InvalidParameter)?;
+            if !self
+                .reg
+                .regs()
+                .rh_descriptor_a
+                .is_set(HC_RH_DESCRIPTOR_A::PSM)
+            {
+                return Err(USBError::NotSupported);
+            }
+            self.reg.port_command(idx, HC_RH_PORT_STATUS::LSDA::SET);
+        }
+
+        let closed = self.devices.remove(&address).ok_or(USBError::NotFound)?;
+        closed.store(true, Ordering::Release);
+        // 端点可能仍由调用方持有，先摘除 ED，端点释放时再归还
+        self.schedule.unlink_device(address);
+        debug!("Device {address} ejected");
+        Ok(())
+    }
+}
+
+pub struct EventHandler {
+    reg: OhciRegs,
+    schedule: Arc<Schedule>,
+    ports: Arc<PortChangeWaker>,
+    stats: Arc<PerfStats>,
+}
+
+unsafe impl Send for EventHandler {}
+unsafe impl Sync for EventHandler {}
+
+impl EventHandlerOp for EventHandler {
+    fn handle_event(&self) -> Event {
+        let regs = self.reg.regs();
+        let sts = regs.interrupt_status.get() & regs.interrupt_enable.get() & INTERRUPT_MASK;
+        if sts == 0 {
+            return Event::Nothing;
+        }
+        regs.interrupt_status.set(sts);
+        self.stats.irq();
+
+        let sts = tock_registers::LocalRegisterCopy::<u32, HC_INTERRUPT::Register>::new(sts);
+        if sts.is_set(HC_INTERRUPT::WDH) {
+            // 端点直接检查自己的 TD，不解析 HccaDoneHead
+            self.stats.event();
+            self.schedule.wake_all();
+        }
+        if sts.is_set(HC_INTERRUPT::SO) {
+            warn!("OHCI scheduling overrun");
+        }
+        if sts.is_set(HC_INTERRUPT::UE) {
+            error!("OHCI unrecoverable error, controller halted");
+        }
+        if sts.is_set(HC_INTERRUPT::RHSC) {
+            // 变化位由 Hub 任务清除，在此之前屏蔽 RHSC
+            regs.interrupt_disable.write(HC_INTERRUPT::RHSC::SET);
+            self.ports.set_changed();
+            let port = (0..self.reg.port_count())
+                .find(|&i| self.reg.port_status(i).get() & (0x1f << 16) != 0)
+                .unwrap_or(0);
+            return Event::PortChange {
+                port: port as u8 + 1,
+            };
+        }
+        Event::Nothing
+    }
+}
@@ -0,0 +1,240 @@
+//! OHCI Root Hub
+//!
+//! 端口复位由控制器计时，完成后置位 PRSC。与 EHCI 共用端口时，EHCI 把未识别为
+//! 高速的设备交出后，本控制器在端口上看到一次连接变化。参考 OHCI 规范 7.4。
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    task::Poll,
+    time::Duration,
+};
+
+use futures::{FutureExt, future::BoxFuture, task::AtomicWaker};
+use tock_registers::interfaces::*;
+use usb_if::{err::USBError, host::hub::Speed};
+
+use super::reg::{HC_INTERRUPT, HC_RH_DESCRIPTOR_A, HC_RH_PORT_STATUS, HC_RH_STATUS, OhciRegs};
+use crate::backend::kmod::{
+    hub::{HubInfo, HubOp, HubPortStatus, PortChangeInfo, PortState},
+    port_event::PortTransition,
+};
+use crate::osal::Kernel;
+
+/// 控制器完成端口复位的上限，复位信号本身约 10ms
+const RESET_TIMEOUT: Duration = Duration::from_millis(50);
+/// 端口上电后至少等待的时间，描述符中的 POTPGT 更长时以其为准
+const POWER_ON_DELAY: Duration = Duration::from_millis(20);
+
+/// 中断中置位的端口变化标志，RHSC 不指明具体端口
+#[derive(Default)]
+pub struct PortChangeWaker {
+    changed: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl PortChangeWaker {
+    /// 在中断中调用：只操作原子变量
+    pub fn set_changed(&self) {
+        self.changed.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+pub struct OhciRootHub {
+    reg: OhciRegs,
+    kernel: Kernel,
+    states: Vec<PortState>,
+    waker: Arc<PortChangeWaker>,
+    /// 清除变化位时记录的端口状态变化，见 [`HubOp::take_port_events`]
+    events: Vec<(u8, PortTransition)>,
+}
+
+unsafe impl Send for OhciRootHub {}
+
+impl OhciRootHub {
+    pub fn new(reg: OhciRegs, kernel: Kernel) -> Self {
+        Self {
+            states: alloc::vec![PortState::Uninit; reg.port_count()],
+            reg,
+            kernel,
+            waker: Arc::new(PortChangeWaker::default()),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn waker(&self) -> Arc<PortChangeWaker> {
+        self.waker.clone()
+    }
+
+    /// 清除端口的变化位，同时记录连接、过流与错误禁用
+    fn ack_port_changes(&mut self, idx: usize) {
+        let status = self.reg.port_status(idx);
+        let port = (idx + 1) as u8;
+        if status.is_set(HC_RH_PORT_STATUS::OCIC) && status.is_set(HC_RH_PORT_STATUS::POCI) {
+            warn!("Port {port} over-current");
+            self.events.push((port, PortTransition::OverCurrent));
+        }
+        if status.is_set(HC_RH_PORT_STATUS::CSC) && status.is_set(HC_RH_PORT_STATUS::CCS) {
+            self.events.push((port, PortTransition::Connected));
+        }
+        // 软件禁用端口不会置位 PESC
+        if status.is_set(HC_RH_PORT_STATUS::PESC)
+            && !status.is_set(HC_RH_PORT_STATUS::PES)
+            && status.is_set(HC_RH_PORT_STATUS::CCS)
+        {
+            self.events.push((port, PortTransition::Error));
+        }
+        self.reg.ack_port_changes(idx);
+    }
+
+    /// 复位端口，返回端口是否启用
+    fn reset_port(&self, idx: usize) -> Result<bool, USBError> {
+        self.reg.port_command(idx, HC_RH_PORT_STATUS::PRS::SET);
+        let deadline = self.kernel.now() + RESET_TIMEOUT;
+        while !self.reg.port_status(idx).is_set(HC_RH_PORT_STATUS::PRSC) {
+            if self.kernel.now() >= deadline {
+                return Err(USBError::Timeout);
+            }
+            self.kernel.delay(Duration::from_millis(1));
+        }
+        self.reg.port_command(idx, HC_RH_PORT_STATUS::PRSC::SET);
+        Ok(self.reg.port_status(idx).is_set(HC_RH_PORT_STATUS::PES))
+    }
+
+    async fn _changed_ports(&mut self) -> Result<Vec<PortChangeInfo>, USBError> {
+        let mut out = Vec::new();
+        for idx in 0..self.states.len() {
+            // 已探测端口的断开由 take_disconnected_ports 处理，保留其变化位
+            if self.states[idx] == PortState::Probed {
+                continue;
+            }
+            self.ack_port_changes(idx);
+            if !self.reg.port_status(idx).is_set(HC_RH_PORT_STATUS::CCS) {
+                self.states[idx] = PortState::Uninit;
+                continue;
+            }
+            let port = (idx + 1) as u8;
+            let enabled = self.reset_port(idx).inspect_err(|_| {
+                self.events.push((port, PortTransition::Error));
+            })?;
+            if !enabled {
+                warn!("Port {port} not enabled after reset");
+                self.events.push((port, PortTransition::Error));
+                continue;
+            }
+            self.ack_port_changes(idx);
+            let speed = if self.reg.port_status(idx).is_set(HC_RH_PORT_STATUS::LSDA) {
+                Speed::Low
+            } else {
+                Speed::Full
+            };
+            debug!("Port {port} device connected at speed {speed:?}");
+            self.states[idx] = PortState::Probed;
+            out.push(PortChangeInfo {
+                root_port_id: port,
+                port_id: port,
+                port_speed: speed,
+                tt_port_on_hub: None,
+            });
+        }
+        Ok(out)
+    }
+}
+
+impl HubOp for OhciRootHub {
+    fn init(&mut self, info: HubInfo) -> BoxFuture<'_, Result<HubInfo, USBError>> {
+        async move {
+            let mut info = info;
+            info.speed = Speed::Full;
+            let regs = self.reg.regs();
+            let desc = regs.rh_descriptor_a.extract();
+            if !desc.is_set(HC_RH_DESCRIPTOR_A::NPS) {
+                // 按端口切换时，PortPowerControlMask 未置位的端口仍受全局电源控制
+                regs.rh_status.write(HC_RH_STATUS::LPSC::SET);
+                if desc.is_set(HC_RH_DESCRIPTOR_A::PSM) {
+                    for idx in 0..self.states.len() {
+                        self.reg.port_command(idx, HC_RH_PORT_STATUS::PPS::SET);
+                    }
+                }
+                let potpgt = desc.read(HC_RH_DESCRIPTOR_A::POTPGT) as u64;
+                self.kernel
+                    .delay(POWER_ON_DELAY.max(Duration::from_millis(potpgt * 2)));
+            }
+            Ok(info)
+        }
+        .boxed()
+    }
+
+    fn changed_ports(&mut self) -> BoxFuture<'_, Result<Vec<PortChangeInfo>, USBError>> {
+        self._changed_ports().boxed()
+    }
+
+    fn slot_id(&self) -> u8 {
+        0
+    }
+
+    fn wait_port_change(&mut self) -> BoxFuture<'_, ()> {
+        let waker = self.waker.clone();
+        // 中断处理在 RHSC 后将其屏蔽，避免变化位清除前反复触发
+        self.reg
+            .regs()
+            .interrupt_enable
+            .write(HC_INTERRUPT::RHSC::SET);
+        core::future::poll_fn(move |cx| {
+            waker.waker.register(cx.waker());
+            if waker.changed.swap(false, Ordering::AcqRel) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .boxed()
+    }
+
+    fn take_port_events(&mut self) -> Vec<(u8, PortTransition)> {
+        core::mem::take(&mut self.events)
+    }
+
+    fn port_status(&self) -> Vec<HubPortStatus> {
+        self.states
+            .iter()
+            .enumerate()
+            .map(|(idx, &state)| {
+                let status = self.reg.port_status(idx);
+                let connected = status.is_set(HC_RH_PORT_STATUS::CCS);
+                let speed = if status.is_set(HC_RH_PORT_STATUS::LSDA) {
+                    Speed::Low
+                } else {
+                    Speed::Full
+                };
+                HubPortStatus {
+                    port: (idx + 1) as u8,
+                    state,
+                    connected,
+                    speed: (connected && status.is_set(HC_RH_PORT_STATUS::PES)).then_some(speed),
+                }
+            })
+            .collect()
+    }
+
+    fn take_disconnected_ports(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for idx in 0..self.states.len() {
+            if self.states[idx] != PortState::Probed {
+                continue;
+            }
+            let status = self.reg.port_status(idx);
+            self.ack_port_changes(idx);
+            // CSC 置位而 CCS 仍为 1 说明设备已被换下，按断开处理后重新枚举
+            if status.is_set(HC_RH_PORT_STATUS::CCS) && !status.is_set(HC_RH_PORT_STATUS::CSC) {
+                continue;
+            }
+            let port = (idx + 1) as u8;
+            debug!("Port {port} device disconnected");
+            self.states[idx] = PortState::Uninit;
+            out.push(port);
+        }
+        out
+    }
+}
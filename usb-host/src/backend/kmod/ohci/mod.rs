@@ -0,0 +1,15 @@
+//! OHCI 主机控制器
+//!
+//! 主要作为 EHCI 的伴随控制器：EHCI 把未识别为高速的根端口设备交出后，全速与
+//! 低速设备（键盘、鼠标等）出现在本控制器的根端口上。支持控制、批量与中断传输，
+//! 等时传输暂不支持。
+
+mod device;
+mod endpoint;
+mod host;
+mod hub;
+mod reg;
+mod schedule;
+mod td;
+
+pub use host::Ohci;
@@ -0,0 +1,171 @@
+//! OHCI 寄存器定义
+//!
+//! 参考 OHCI 规范第 7 章。根端口寄存器的各位写 0 无效，写 1 触发对应操作，
+//! 因此端口操作直接写入单个位，不做读改写。
+
+use tock_registers::interfaces::*;
+use tock_registers::{LocalRegisterCopy, register_bitfields, register_structs, registers::*};
+
+use crate::Mmio;
+
+register_bitfields! [u32,
+    pub HC_CONTROL [
+        /// 控制与批量 ED 的服务比例
+        CBSR OFFSET(0) NUMBITS(2) [],
+        PLE OFFSET(2) NUMBITS(1) [],
+        IE OFFSET(3) NUMBITS(1) [],
+        CLE OFFSET(4) NUMBITS(1) [],
+        BLE OFFSET(5) NUMBITS(1) [],
+        HCFS OFFSET(6) NUMBITS(2) [
+            Reset = 0,
+            Resume = 1,
+            Operational = 2,
+            Suspend = 3,
+        ],
+        /// 中断路由到 SMM，控制器仍归 BIOS 所有
+        IR OFFSET(8) NUMBITS(1) [],
+        RWC OFFSET(9) NUMBITS(1) [],
+        RWE OFFSET(10) NUMBITS(1) [],
+    ],
+
+    pub HC_COMMAND_STATUS [
+        HCR OFFSET(0) NUMBITS(1) [],
+        /// Control List Filled
+        CLF OFFSET(1) NUMBITS(1) [],
+        /// Bulk List Filled
+        BLF OFFSET(2) NUMBITS(1) [],
+        /// Ownership Change Request
+        OCR OFFSET(3) NUMBITS(1) [],
+    ],
+
+    pub HC_INTERRUPT [
+        SO OFFSET(0) NUMBITS(1) [],
+        /// HccaDoneHead 已写入
+        WDH OFFSET(1) NUMBITS(1) [],
+        SF OFFSET(2) NUMBITS(1) [],
+        RD OFFSET(3) NUMBITS(1) [],
+        UE OFFSET(4) NUMBITS(1) [],
+        FNO OFFSET(5) NUMBITS(1) [],
+        RHSC OFFSET(6) NUMBITS(1) [],
+        OC OFFSET(30) NUMBITS(1) [],
+        MIE OFFSET(31) NUMBITS(1) [],
+    ],
+
+    pub HC_FM_INTERVAL [
+        FI OFFSET(0) NUMBITS(14) [],
+        FSMPS OFFSET(16) NUMBITS(15) [],
+        FIT OFFSET(31) NUMBITS(1) [],
+    ],
+
+    pub HC_RH_DESCRIPTOR_A [
+        NDP OFFSET(0) NUMBITS(8) [],
+        /// 按端口切换电源
+        PSM OFFSET(8) NUMBITS(1) [],
+        /// 端口始终供电
+        NPS OFFSET(9) NUMBITS(1) [],
+        NOCP OFFSET(12) NUMBITS(1) [],
+        /// 上电到电源稳定的时间，单位 2ms
+        POTPGT OFFSET(24) NUMBITS(8) [],
+    ],
+
+    pub HC_RH_STATUS [
+        /// 写 1 关闭全局电源
+        LPS OFFSET(0) NUMBITS(1) [],
+        OCI OFFSET(1) NUMBITS(1) [],
+        /// 写 1 打开全局电源
+        LPSC OFFSET(16) NUMBITS(1) [],
+        OCIC OFFSET(17) NUMBITS(1) [],
+    ],
+
+    pub HC_RH_PORT_STATUS [
+        CCS OFFSET(0) NUMBITS(1) [],
+        /// 写 1 启用端口，写入 CCS 位则禁用端口
+        PES OFFSET(1) NUMBITS(1) [],
+        PSS OFFSET(2) NUMBITS(1) [],
+        POCI OFFSET(3) NUMBITS(1) [],
+        PRS OFFSET(4) NUMBITS(1) [],
+        PPS OFFSET(8) NUMBITS(1) [],
+        /// 读为低速设备，写 1 关闭端口电源
+        LSDA OFFSET(9) NUMBITS(1) [],
+        CSC OFFSET(16) NUMBITS(1) [],
+        PESC OFFSET(17) NUMBITS(1) [],
+        PSSC OFFSET(18) NUMBITS(1) [],
+        OCIC OFFSET(19) NUMBITS(1) [],
+        PRSC OFFSET(20) NUMBITS(1) [],
+    ],
+];
+
+register_structs! {
+    pub OhciRegisters {
+        (0x00 => pub revision: ReadOnly<u32>),
+        (0x04 => pub control: ReadWrite<u32, HC_CONTROL::Register>),
+        (0x08 => pub command_status: ReadWrite<u32, HC_COMMAND_STATUS::Register>),
+        (0x0C => pub interrupt_status: ReadWrite<u32, HC_INTERRUPT::Register>),
+        (0x10 => pub interrupt_enable: ReadWrite<u32, HC_INTERRUPT::Register>),
+        (0x14 => pub interrupt_disable: ReadWrite<u32, HC_INTERRUPT::Register>),
+        (0x18 => pub hcca: ReadWrite<u32>),
+        (0x1C => pub period_current_ed: ReadOnly<u32>),
+        (0x20 => pub control_head_ed: ReadWrite<u32>),
+        (0x24 => pub control_current_ed: ReadWrite<u32>),
+        (0x28 => pub bulk_head_ed: ReadWrite<u32>),
+        (0x2C => pub bulk_current_ed: ReadWrite<u32>),
+        (0x30 => pub done_head: ReadOnly<u32>),
+        (0x34 => pub fm_interval: ReadWrite<u32, HC_FM_INTERVAL::Register>),
+        (0x38 => pub fm_remaining: ReadOnly<u32>),
+        (0x3C => pub fm_number: ReadOnly<u32>),
+        (0x40 => pub periodic_start: ReadWrite<u32>),
+        (0x44 => pub ls_threshold: ReadWrite<u32>),
+        (0x48 => pub rh_descriptor_a: ReadWrite<u32, HC_RH_DESCRIPTOR_A::Register>),
+        (0x4C => pub rh_descriptor_b: ReadWrite<u32>),
+        (0x50 => pub rh_status: ReadWrite<u32, HC_RH_STATUS::Register>),
+        (0x54 => pub rh_port_status: [ReadWrite<u32, HC_RH_PORT_STATUS::Register>; 15]),
+        (0x90 => @END),
+    }
+}
+
+/// HcRhPortStatus 中写 1 清除的变化位
+const PORT_CHANGE_MASK: u32 = 0x1f << 16;
+
+/// OHCI 寄存器访问器
+#[derive(Clone, Copy)]
+pub struct OhciRegs {
+    base: usize,
+}
+
+impl OhciRegs {
+    /// # Safety
+    ///
+    /// 调用者必须确保 `mmio` 指向有效的 OHCI 寄存器区域
+    pub unsafe fn new(mmio: Mmio) -> Self {
+        Self {
+            base: mmio.as_ptr() as usize,
+        }
+    }
+
+    pub fn regs(&self) -> &'static OhciRegisters {
+        unsafe { &*(self.base as *const OhciRegisters) }
+    }
+
+    pub fn port_count(&self) -> usize {
+        (self.regs().rh_descriptor_a.read(HC_RH_DESCRIPTOR_A::NDP) as usize).min(15)
+    }
+
+    pub fn port_status(&self, idx: usize) -> LocalRegisterCopy<u32, HC_RH_PORT_STATUS::Register> {
+        LocalRegisterCopy::new(self.regs().rh_port_status[idx].get())
+    }
+
+    /// 写入端口命令位，其余位写 0 无效
+    pub fn port_command(
+        &self,
+        idx: usize,
+        cmd: tock_registers::fields::FieldValue<u32, HC_RH_PORT_STATUS::Register>,
+    ) {
+        self.regs().rh_port_status[idx].write(cmd);
+    }
+
+    /// 清除端口的全部变化位
+    pub fn ack_port_changes(&self, idx: usize) {
+        let v = self.regs().rh_port_status[idx].get() & PORT_CHANGE_MASK;
+        self.regs().rh_port_status[idx].set(v);
+    }
+}
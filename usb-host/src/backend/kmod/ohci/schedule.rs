@@ -0,0 +1,301 @@
+//! ED 链表与 HCCA
+//!
+//! 全部 ED 来自同一个池，按字存放，端点提交时只改写 TailP 一个字，不会覆盖控制器
+//! 回写的 HeadP。控制与批量 ED 各成一条链表，链首写入 HcControlHeadED 与
+//! HcBulkHeadED；HCCA 中断表的 32 项都指向同一条中断 ED 链，即每帧访问一次全部
+//! 中断端点。参考 OHCI 规范 5.2。
+
+use alloc::vec::Vec;
+use core::{task::Waker, time::Duration};
+
+use dma_api::{DArray, DmaDirection};
+use futures::task::AtomicWaker;
+use mbarrier::mb;
+use spin::Mutex;
+use tock_registers::interfaces::*;
+use usb_if::err::USBError;
+
+use super::{
+    reg::{HC_COMMAND_STATUS, HC_CONTROL, OhciRegs},
+    td::EdInfo,
+};
+use crate::{
+    backend::kmod::{le::DmaLe, mem::MemTag},
+    osal::Kernel,
+};
+
+/// ED 各字的下标
+const ED_CONTROL: usize = 0;
+pub(crate) const ED_TAIL: usize = 1;
+pub(crate) const ED_HEAD: usize = 2;
+const ED_NEXT: usize = 3;
+const ED_WORDS: usize = 4;
+
+/// ED 控制字的 K 位，控制器跳过该 ED
+const ED_SKIP: u32 = 1 << 14;
+/// HeadP 字的低位标志
+pub(crate) const ED_HALTED: u32 = 1 << 0;
+pub(crate) const ED_TOGGLE_CARRY: u32 = 1 << 1;
+/// ED 与 TD 指针按 16 字节对齐
+pub(crate) const PTR_MASK: u32 = !0xf;
+
+const MAX_ED: usize = 64;
+const HCCA_WORDS: usize = 64;
+const INTERRUPT_TABLE_LEN: usize = 32;
+/// 摘除 ED 后等待控制器进入下一帧，不再持有对它的引用
+const UNLINK_DELAY: Duration = Duration::from_millis(2);
+
+/// ED 所在的链表
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EdKind {
+    Control,
+    Bulk,
+    Interrupt,
+}
+
+pub(crate) struct Schedule {
+    reg: OhciRegs,
+    kernel: Kernel,
+    inner: Mutex<Inner>,
+    wakers: Vec<AtomicWaker>,
+}
+
+struct Inner {
+    eds: DArray<u32>,
+    free: Vec<usize>,
+    hcca: DArray<u32>,
+    /// 各链表中的 ED，按链接顺序
+    control: Vec<usize>,
+    bulk: Vec<usize>,
+    interrupt: Vec<usize>,
+    kind: [EdKind; MAX_ED],
+    /// 各 ED 所属的设备地址
+    owner: [u8; MAX_ED],
+}
+
+impl Inner {
+    fn ed_addr(&self, ed: usize) -> u32 {
+        (self.eds.dma_addr().as_u64() as usize + ed * ED_WORDS * 4) as u32
+    }
+
+    fn word(&self, ed: usize, w: usize) -> u32 {
+        self.eds.read_le(ed * ED_WORDS + w).unwrap()
+    }
+
+    fn set_word(&mut self, ed: usize, w: usize, v: u32) {
+        self.eds.set_le(ed * ED_WORDS + w, v);
+    }
+
+    fn list(&mut self, kind: EdKind) -> &mut Vec<usize> {
+        match kind {
+            EdKind::Control => &mut self.control,
+            EdKind::Bulk => &mut self.bulk,
+            EdKind::Interrupt => &mut self.interrupt,
+        }
+    }
+
+    fn set_interrupt_table(&mut self, ptr: u32) {
+        for i in 0..INTERRUPT_TABLE_LEN {
+            self.hcca.set_le(i, ptr);
+        }
+    }
+}
+
+impl Schedule {
+    pub fn new(reg: OhciRegs, kernel: &Kernel) -> Result<Self, USBError> {
+        let dma = kernel.with_tag(MemTag::Ring);
+        let eds = dma
+            .array_zero_with_align::<u32>(MAX_ED * ED_WORDS, 16, DmaDirection::Bidirectional)
+            .map_err(|_| USBError::NoMemory)?;
+        let hcca = dma
+            .array_zero_with_align::<u32>(HCCA_WORDS, 256, DmaDirection::Bidirectional)
+            .map_err(|_| USBError::NoMemory)?;
+
+        Ok(Self {
+            reg,
+            kernel: kernel.clone(),
+            inner: Mutex::new(Inner {
+                eds,
+                free: (0..MAX_ED).rev().collect(),
+                hcca,
+                control: Vec::new(),
+                bulk: Vec::new(),
+                interrupt: Vec::new(),
+                kind: [EdKind::Control; MAX_ED],
+                owner: [0; MAX_ED],
+            }),
+            wakers: (0..MAX_ED).map(|_| AtomicWaker::new()).collect(),
+        })
+    }
+
+    pub fn hcca_addr(&self) -> u32 {
+        self.inner.lock().hcca.dma_addr().as_u64() as u32
+    }
+
+    /// 从池中分配 ED，HeadP 与 TailP 都指向 `td` 处的空 TD，尚未加入链表
+    pub fn alloc(&self, info: &EdInfo, kind: EdKind, td: u32) -> Result<usize, USBError> {
+        let mut inner = self.inner.lock();
+        let ed = inner.free.pop().ok_or(USBError::NoMemory)?;
+        inner.set_word(ed, ED_CONTROL, info.control());
+        inner.set_word(ed, ED_TAIL, td);
+        inner.set_word(ed, ED_HEAD, td);
+        inner.set_word(ed, ED_NEXT, 0);
+        inner.kind[ed] = kind;
+        inner.owner[ed] = info.address;
+        Ok(ed)
+    }
+
+    /// 加入所属链表
+    pub fn link(&self, ed: usize) {
+        let mut inner = self.inner.lock();
+        let kind = inner.kind[ed];
+        if inner.list(kind).contains(&ed) {
+            return;
+        }
+        let ptr = inner.ed_addr(ed);
+        let regs = self.reg.regs();
+        match kind {
+            EdKind::Interrupt => {
+                // 新 ED 插在链首，先指向原链首再发布
+                let next = inner
+                    .interrupt
+                    .first()
+                    .map_or(0, |&first| inner.ed_addr(first));
+                inner.set_word(ed, ED_NEXT, next);
+                mb();
+                inner.set_interrupt_table(ptr);
+                inner.interrupt.insert(0, ed);
+            }
+            EdKind::Control | EdKind::Bulk => {
+                inner.set_word(ed, ED_NEXT, 0);
+                mb();
+                match inner.list(kind).last().copied() {
+                    Some(prev) => inner.set_word(prev, ED_NEXT, ptr),
+                    None if kind == EdKind::Control => regs.control_head_ed.set(ptr),
+                    None => regs.bulk_head_ed.set(ptr),
+                }
+                inner.list(kind).push(ed);
+            }
+        }
+    }
+
+    /// 摘除 ED，返回时控制器已不再访问它
+    ///
+    /// 被摘除 ED 的 NextED 保持不变，控制器若正停留在它上面仍能沿原链表继续。
+    pub fn unlink(&self, ed: usize) {
+        let mut inner = self.inner.lock();
+        let kind = inner.kind[ed];
+        let Some(i) = inner.list(kind).iter().position(|&e| e == ed) else {
+            return;
+        };
+        let next = inner.word(ed, ED_NEXT);
+        let prev = i.checked_sub(1).map(|p| inner.list(kind)[p]);
+        let regs = self.reg.regs();
+        match (prev, kind) {
+            (Some(prev), _) => inner.set_word(prev, ED_NEXT, next),
+            (None, EdKind::Interrupt) => inner.set_interrupt_table(next),
+            (None, EdKind::Control) => regs.control_head_ed.set(next),
+            (None, EdKind::Bulk) => regs.bulk_head_ed.set(next),
+        }
+        inner.list(kind).remove(i);
+        drop(inner);
+        mb();
+        self.wait_frame();
+    }
+
+    /// 摘除并归还 ED
+    pub fn free(&self, ed: usize) {
+        self.unlink(ed);
+        let mut inner = self.inner.lock();
+        inner.owner[ed] = 0;
+        inner.free.push(ed);
+    }
+
+    /// 摘除设备的全部 ED，端点释放时再归还
+    pub fn unlink_device(&self, address: u8) {
+        let eds: Vec<usize> = {
+            let inner = self.inner.lock();
+            inner
+                .control
+                .iter()
+                .chain(&inner.bulk)
+                .chain(&inner.interrupt)
+                .copied()
+                .filter(|&ed| inner.owner[ed] == address)
+                .collect()
+        };
+        for ed in eds {
+            self.unlink(ed);
+        }
+    }
+
+    pub fn word(&self, ed: usize, w: usize) -> u32 {
+        self.inner.lock().word(ed, w)
+    }
+
+    /// 改写 ED 的一个字
+    pub fn set_word(&self, ed: usize, w: usize, v: u32) {
+        self.inner.lock().set_word(ed, w, v);
+    }
+
+    /// 置位或清除 K 位，置位后等待控制器离开该 ED
+    pub fn set_skip(&self, ed: usize, skip: bool) {
+        let mut inner = self.inner.lock();
+        let mut control = inner.word(ed, ED_CONTROL) & !ED_SKIP;
+        if skip {
+            control |= ED_SKIP;
+        }
+        inner.set_word(ed, ED_CONTROL, control);
+        drop(inner);
+        if skip {
+            mb();
+            self.wait_frame();
+        }
+    }
+
+    /// 更新 ED 的静态字段，先摘除再重新加入
+    pub fn update(&self, ed: usize, info: &EdInfo) {
+        self.unlink(ed);
+        let mut inner = self.inner.lock();
+        inner.set_word(ed, ED_CONTROL, info.control());
+        inner.owner[ed] = info.address;
+        drop(inner);
+        self.link(ed);
+    }
+
+    /// 通知控制器 ED 上有新的 TD，中断链表每帧都会访问，无需通知
+    pub fn fill(&self, ed: usize) {
+        let kind = self.inner.lock().kind[ed];
+        let cmd = &self.reg.regs().command_status;
+        match kind {
+            EdKind::Control => cmd.write(HC_COMMAND_STATUS::CLF::SET),
+            EdKind::Bulk => cmd.write(HC_COMMAND_STATUS::BLF::SET),
+            EdKind::Interrupt => {}
+        }
+    }
+
+    pub fn register_waker(&self, ed: usize, waker: &Waker) {
+        self.wakers[ed].register(waker);
+    }
+
+    /// 在中断中调用：不解析完成队列，唤醒全部端点
+    pub fn wake_all(&self) {
+        for waker in &self.wakers {
+            waker.wake();
+        }
+    }
+
+    fn running(&self) -> bool {
+        self.reg
+            .regs()
+            .control
+            .matches_all(HC_CONTROL::HCFS::Operational)
+    }
+
+    /// 等待控制器进入下一帧
+    fn wait_frame(&self) {
+        if self.running() {
+            self.kernel.delay(UNLINK_DELAY);
+        }
+    }
+}
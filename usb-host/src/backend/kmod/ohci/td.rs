@@ -0,0 +1,356 @@
+//! TD 与 ED 字段编码
+//!
+//! 只负责把一次传输翻译成通用 TD 字、判断完成状态以及生成 ED 的控制字，
+//! 不接触 DMA 与寄存器。模块不依赖 kmod 其他部分，主机上也会编译以运行单元测试。
+//!
+//! 参考 OHCI 规范 4.2（ED）与 4.3.1（通用 TD）。
+
+use alloc::vec::Vec;
+
+const ED_LOW_SPEED: u32 = 1 << 13;
+
+const TD_ROUNDING: u32 = 1 << 18;
+const TD_DP_SHIFT: u32 = 19;
+const TD_DI_SHIFT: u32 = 21;
+/// DelayInterrupt 取 7 时 TD 完成不产生中断
+const TD_DI_NONE: u32 = 7;
+const TD_TOGGLE_SHIFT: u32 = 24;
+const TD_CC_SHIFT: u32 = 28;
+
+const CC_NO_ERROR: u8 = 0;
+const CC_CRC: u8 = 1;
+const CC_BIT_STUFFING: u8 = 2;
+const CC_TOGGLE_MISMATCH: u8 = 3;
+const CC_STALL: u8 = 4;
+const CC_NOT_RESPONDING: u8 = 5;
+const CC_DATA_OVERRUN: u8 = 8;
+pub(crate) const CC_DATA_UNDERRUN: u8 = 9;
+const CC_BUFFER_OVERRUN: u8 = 12;
+const CC_BUFFER_UNDERRUN: u8 = 13;
+/// 控制器尚未处理，取 14 或 15
+const CC_NOT_ACCESSED: u8 = 14;
+
+const PAGE_SIZE: usize = 0x1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pid {
+    Setup = 0,
+    Out = 1,
+    In = 2,
+}
+
+/// 数据切换位来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Toggle {
+    /// 使用 ED 中的 toggleCarry
+    Carry,
+    Data0,
+    Data1,
+}
+
+/// 构造通用 TD 的 4 个字：控制字、当前缓冲区指针、下一个 TD、缓冲区末尾
+///
+/// `rounding` 允许短包正常完成，否则短包以 DataUnderrun 结束并使 ED 停止。
+pub(crate) fn td(
+    pid: Pid,
+    toggle: Toggle,
+    rounding: bool,
+    ioc: bool,
+    buf: u32,
+    len: usize,
+    next: u32,
+) -> [u32; 4] {
+    let toggle = match toggle {
+        Toggle::Carry => 0,
+        Toggle::Data0 => 0b10,
+        Toggle::Data1 => 0b11,
+    };
+    let di = if ioc { 0 } else { TD_DI_NONE };
+    let mut control = (CC_NOT_ACCESSED as u32) << TD_CC_SHIFT
+        | toggle << TD_TOGGLE_SHIFT
+        | di << TD_DI_SHIFT
+        | (pid as u32) << TD_DP_SHIFT;
+    if rounding {
+        control |= TD_ROUNDING;
+    }
+    // 零长度 TD 的 CBP 与 BE 都为 0
+    let (cbp, be) = match len {
+        0 => (0, 0),
+        n => (buf, buf + n as u32 - 1),
+    };
+    [control, cbp, next, be]
+}
+
+/// TD 的 ConditionCode
+pub(crate) fn condition(td: &[u32; 4]) -> u8 {
+    (td[0] >> TD_CC_SHIFT) as u8
+}
+
+/// 已退役 TD 中尚未传输的字节数，CBP 为 0 表示全部传输完成
+pub(crate) fn remaining(td: &[u32; 4]) -> usize {
+    match td[1] {
+        0 => 0,
+        cbp => (td[3] - cbp) as usize + 1,
+    }
+}
+
+/// 把缓冲区拆分为 TD，返回每个 TD 的 `(起始地址, 长度)`
+///
+/// 一个 TD 的缓冲区最多跨越一个页边界。除最后一个外每段都是 `max_packet` 的
+/// 整数倍，短包只会出现在最后一个事务。长度为 0 时返回一个零长度段。
+pub(crate) fn split_buffer(addr: u32, len: usize, max_packet: usize) -> Vec<(u32, usize)> {
+    let mut out = Vec::new();
+    let mut addr = addr as usize;
+    let mut left = len;
+    loop {
+        let mut chunk = left.min(2 * PAGE_SIZE - addr % PAGE_SIZE);
+        if chunk < left {
+            chunk -= chunk % max_packet.max(1);
+        }
+        out.push((addr as u32, chunk));
+        addr += chunk;
+        left -= chunk;
+        if left == 0 {
+            return out;
+        }
+    }
+}
+
+/// TD 在请求中的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Setup,
+    /// 数据 TD，记录请求的字节数
+    Data(usize),
+    Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TdError {
+    Stall,
+    /// 控制请求的状态阶段被 STALL
+    StatusStall,
+    NotResponding,
+    Crc,
+    BitStuffing,
+    ToggleMismatch,
+    DataOverrun,
+    /// 控制器访问内存不及时
+    Buffer,
+    Other(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Progress {
+    Pending,
+    /// 完成，携带实际传输的数据字节数
+    Done(usize),
+    Failed(TdError),
+}
+
+/// 已退役 TD 回写的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Retired {
+    pub cc: u8,
+    pub remaining: usize,
+}
+
+impl Retired {
+    pub fn new(td: &[u32; 4]) -> Self {
+        Self {
+            cc: condition(td),
+            remaining: remaining(td),
+        }
+    }
+}
+
+/// 根据各 TD 的退役状态判断请求进度，`None` 表示 TD 仍在 ED 队列中
+///
+/// 短包之后的数据 TD 由端点从队列中跳过，不再等待：批量与中断请求此时已完成，
+/// 控制请求继续等待状态阶段。
+pub(crate) fn progress(tds: &[(Stage, Option<Retired>)]) -> Progress {
+    let mut actual = 0;
+    let mut short = false;
+    for &(stage, retired) in tds {
+        if short && matches!(stage, Stage::Data(_)) {
+            continue;
+        }
+        let Some(retired) = retired else {
+            return Progress::Pending;
+        };
+        match (retired.cc, stage) {
+            (CC_NO_ERROR, _) | (CC_DATA_UNDERRUN, Stage::Data(_)) => {}
+            // 控制器退役 TD 后才更新 ED，这里不会读到未处理的 TD
+            (cc, _) if cc >= CC_NOT_ACCESSED => return Progress::Pending,
+            (CC_STALL, Stage::Status) => return Progress::Failed(TdError::StatusStall),
+            (cc, _) => return Progress::Failed(td_error(cc)),
+        }
+        if let Stage::Data(len) = stage {
+            actual += len.saturating_sub(retired.remaining);
+            short = retired.remaining > 0;
+        }
+    }
+    Progress::Done(actual)
+}
+
+fn td_error(cc: u8) -> TdError {
+    match cc {
+        CC_STALL => TdError::Stall,
+        CC_NOT_RESPONDING => TdError::NotResponding,
+        CC_CRC => TdError::Crc,
+        CC_BIT_STUFFING => TdError::BitStuffing,
+        CC_TOGGLE_MISMATCH => TdError::ToggleMismatch,
+        CC_DATA_OVERRUN => TdError::DataOverrun,
+        CC_BUFFER_OVERRUN | CC_BUFFER_UNDERRUN => TdError::Buffer,
+        cc => TdError::Other(cc),
+    }
+}
+
+/// ED 的静态字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EdInfo {
+    pub address: u8,
+    pub endpoint: u8,
+    pub low_speed: bool,
+    pub max_packet: u16,
+}
+
+impl EdInfo {
+    /// ED 第 0 字，方向由各 TD 指定
+    pub(crate) fn control(&self) -> u32 {
+        let mut v = self.address as u32 & 0x7f
+            | (self.endpoint as u32 & 0xf) << 7
+            | (self.max_packet as u32 & 0x7ff) << 16;
+        if self.low_speed {
+            v |= ED_LOW_SPEED;
+        }
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_crosses_at_most_one_page() {
+        // 页内偏移 0x100，第一个 TD 最多 0x1f00 字节，向下取整到 64 的倍数
+        let parts = split_buffer(0x1000_0100, 0x3000, 64);
+        assert_eq!(parts, [(0x1000_0100, 0x1f00), (0x1000_2000, 0x1100)]);
+        assert_eq!(split_buffer(0x2000, 0, 64), [(0x2000, 0)]);
+        assert_eq!(split_buffer(0x2000, 0x2000, 512), [(0x2000, 0x2000)]);
+    }
+
+    #[test]
+    fn td_words() {
+        let words = td(
+            Pid::In,
+            Toggle::Data1,
+            true,
+            true,
+            0x1000_0ff0,
+            0x20,
+            0x2000,
+        );
+        assert_eq!(words[1], 0x1000_0ff0);
+        assert_eq!(words[2], 0x2000);
+        assert_eq!(words[3], 0x1000_100f);
+        assert_eq!(words[0] >> 19 & 0b11, 2);
+        assert_eq!(words[0] >> 21 & 0b111, 0);
+        assert_eq!(words[0] >> 24 & 0b11, 0b11);
+        assert_ne!(words[0] & TD_ROUNDING, 0);
+        assert_eq!(condition(&words), 14);
+        assert_eq!(remaining(&words), 0x20);
+
+        let words = td(Pid::Setup, Toggle::Data0, false, false, 0x3000, 8, 0);
+        assert_eq!(words[0] >> 19 & 0b11, 0);
+        assert_eq!(words[0] >> 21 & 0b111, 7);
+        assert_eq!(words[0] >> 24 & 0b11, 0b10);
+
+        let zlp = td(Pid::Out, Toggle::Carry, false, false, 0x3000, 0, 0);
+        assert_eq!((zlp[1], zlp[3]), (0, 0));
+        assert_eq!(
+            Retired::new(&zlp),
+            Retired {
+                cc: CC_NOT_ACCESSED,
+                remaining: 0
+            }
+        );
+    }
+
+    #[test]
+    fn short_packet_completes_bulk_but_waits_for_status() {
+        let done = |left: usize| {
+            Some(Retired {
+                cc: CC_NO_ERROR,
+                remaining: left,
+            })
+        };
+        let underrun = Some(Retired {
+            cc: CC_DATA_UNDERRUN,
+            remaining: 100,
+        });
+
+        // 第一个 TD 短包使 ED 停止，第二个被跳过
+        let bulk = [(Stage::Data(1024), underrun), (Stage::Data(512), None)];
+        assert_eq!(progress(&bulk), Progress::Done(924));
+
+        let ctrl = [
+            (Stage::Setup, done(0)),
+            (Stage::Data(64), done(46)),
+            (Stage::Status, None),
+        ];
+        assert_eq!(progress(&ctrl), Progress::Pending);
+        let ctrl = [ctrl[0], ctrl[1], (Stage::Status, done(0))];
+        assert_eq!(progress(&ctrl), Progress::Done(18));
+    }
+
+    #[test]
+    fn errors() {
+        let stall = Some(Retired {
+            cc: CC_STALL,
+            remaining: 8,
+        });
+        assert_eq!(
+            progress(&[(Stage::Data(8), stall)]),
+            Progress::Failed(TdError::Stall)
+        );
+        assert_eq!(
+            progress(&[
+                (
+                    Stage::Setup,
+                    Some(Retired {
+                        cc: CC_NO_ERROR,
+                        remaining: 0
+                    })
+                ),
+                (Stage::Status, stall)
+            ]),
+            Progress::Failed(TdError::StatusStall)
+        );
+        let underrun = Some(Retired {
+            cc: CC_DATA_UNDERRUN,
+            remaining: 0,
+        });
+        assert_eq!(
+            progress(&[(Stage::Status, underrun)]),
+            Progress::Failed(TdError::Other(CC_DATA_UNDERRUN))
+        );
+        let not_accessed = Some(Retired {
+            cc: 15,
+            remaining: 0,
+        });
+        assert_eq!(progress(&[(Stage::Setup, not_accessed)]), Progress::Pending);
+    }
+
+    #[test]
+    fn ed_control() {
+        let info = EdInfo {
+            address: 5,
+            endpoint: 1,
+            low_speed: true,
+            max_packet: 8,
+        };
+        assert_eq!(info.control(), 5 | 1 << 7 | 1 << 13 | 8 << 16);
+    }
+}
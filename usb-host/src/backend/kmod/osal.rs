@@ -1,26 +1,128 @@
 use core::ops::Deref;
 use core::time::Duration;
 
+pub use crab_usb_hal::{DmaAddr, DmaDirection, DmaError, DmaHandle, DmaMapHandle, DmaOp, KernelOp};
 use dma_api::DeviceDma;
-pub use dma_api::{DmaAddr, DmaDirection, DmaError, DmaHandle, DmaMapHandle, DmaOp};
+
+use super::mem::MemTag;
+#[cfg(feature = "mem-track")]
+use super::mem::{MemTracker, MemoryReport};
+use crate::backend::ty::timer::Timer;
+
+/// 设备传输缓冲区的 DMA 约束
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DmaConfig {
+    /// 设备可寻址的 DMA 掩码，与控制器掩码取交集；`None` 沿用控制器掩码
+    pub dma_mask: Option<u64>,
+    pub bounce: BouncePolicy,
+}
+
+/// 传输缓冲区的反弹策略
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BouncePolicy {
+    /// 直接映射调用方缓冲区，超出掩码时由 [`DmaOp::map_single`] 自行决定是否反弹
+    #[default]
+    Auto,
+    /// 总是复制到按掩码分配的一致性内存，适用于无法反弹的平台或调用方缓冲区位置不可控的场景
+    Always,
+}
 
 #[derive(Clone)]
 pub(crate) struct Kernel {
     dma: DeviceDma,
     osal: &'static dyn KernelOp,
+    bounce: BouncePolicy,
+    #[cfg(feature = "mem-track")]
+    mem: MemTracker,
 }
 
 impl Kernel {
+    #[cfg(not(feature = "mem-track"))]
     pub fn new(dma_mask: u64, osal: &'static dyn KernelOp) -> Self {
         Self {
             dma: DeviceDma::new(dma_mask, osal),
             osal,
+            bounce: BouncePolicy::Auto,
+        }
+    }
+
+    #[cfg(feature = "mem-track")]
+    pub fn new(dma_mask: u64, osal: &'static dyn KernelOp) -> Self {
+        let mem = MemTracker::new(osal);
+        Self {
+            dma: DeviceDma::new(dma_mask, mem.op(MemTag::Other)),
+            osal,
+            bounce: BouncePolicy::Auto,
+            mem,
+        }
+    }
+
+    /// 以 `tag` 标记经由返回值进行的 DMA 分配
+    #[cfg(feature = "mem-track")]
+    pub fn with_tag(&self, tag: MemTag) -> Self {
+        Self {
+            dma: DeviceDma::new(self.dma.dma_mask(), self.mem.op(tag)),
+            ..self.clone()
+        }
+    }
+
+    /// 未启用 `mem-track` 时不记账
+    #[cfg(not(feature = "mem-track"))]
+    pub fn with_tag(&self, _tag: MemTag) -> Self {
+        self.clone()
+    }
+
+    /// 按设备约束派生传输缓冲区使用的 `Kernel`，掩码不超出当前掩码
+    pub fn with_dma_config(&self, config: DmaConfig) -> Self {
+        let mask = config
+            .dma_mask
+            .map_or(self.dma.dma_mask(), |mask| mask & self.dma.dma_mask());
+        #[cfg(feature = "mem-track")]
+        let op = self.mem.op(MemTag::Transfer);
+        #[cfg(not(feature = "mem-track"))]
+        let op = self.osal;
+        Self {
+            dma: DeviceDma::new(mask, op),
+            bounce: config.bounce,
+            ..self.clone()
         }
     }
 
+    pub fn bounce(&self) -> BouncePolicy {
+        self.bounce
+    }
+
+    #[cfg(feature = "mem-track")]
+    pub fn mem_tracker(&self) -> MemTracker {
+        self.mem
+    }
+
+    #[cfg(feature = "mem-track")]
+    pub fn memory_report(&self) -> MemoryReport {
+        self.mem.report()
+    }
+
     pub fn delay(&self, duration: Duration) {
         self.osal.delay(duration)
     }
+
+    pub fn now(&self) -> Duration {
+        self.osal.now()
+    }
+
+    pub fn wake_at(&self, deadline: Duration, waker: &core::task::Waker) {
+        self.osal.wake_at(deadline, waker)
+    }
+}
+
+impl Timer for Kernel {
+    fn now(&self) -> Duration {
+        Kernel::now(self)
+    }
+
+    fn wake_at(&self, deadline: Duration, waker: &core::task::Waker) {
+        Kernel::wake_at(self, deadline, waker)
+    }
 }
 
 impl Deref for Kernel {
@@ -31,10 +133,6 @@ impl Deref for Kernel {
     }
 }
 
-pub trait KernelOp: DmaOp {
-    fn delay(&self, duration: Duration);
-}
-
 pub(crate) struct SpinWhile<F>
 where
     F: Fn() -> bool,
@@ -0,0 +1,134 @@
+//! 控制器运行时统计
+//!
+//! 统计门铃、事件、中断与命令次数，供集成方判断事件循环是否跟得上，
+//! 并据此调整中断节流（IMOD）参数。
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use super::xhci::{IMOD_UNIT_NS, ImodPolicy};
+
+/// 控制器统计快照
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PerfCounters {
+    /// 已敲响的门铃次数（包含命令环与各端点）
+    pub doorbells: u64,
+    /// 事件环中已处理的事件数
+    pub events: u64,
+    /// 控制器报告事件环已满（Event Ring Full Error）的次数
+    pub event_ring_full: u64,
+    /// 已处理的中断次数（`handle_event` 中确认到 EINT 的次数）
+    pub irqs: u64,
+    /// 已完成的命令数
+    pub commands: u64,
+    /// 命令从入队到完成的平均耗时
+    pub avg_command_latency: Duration,
+    /// 中断节流策略
+    pub imod_policy: ImodPolicy,
+    /// 当前中断节流间隔
+    pub imod_interval: Duration,
+    /// 自适应节流调整间隔的次数
+    pub imod_adjustments: u64,
+}
+
+/// 控制器自检结果
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// 已完成的 NoOp 命令数
+    pub commands: u32,
+    /// 自检期间事件环收到的事件数
+    pub events: u64,
+    /// 已确认事件环与中断使能配置正确的中断器数
+    pub interrupters: u16,
+    /// 单条命令往返的最短耗时
+    pub min_latency: Duration,
+    /// 单条命令往返的最长耗时
+    pub max_latency: Duration,
+    /// 单条命令往返的平均耗时
+    pub avg_latency: Duration,
+}
+
+#[derive(Default)]
+pub(crate) struct PerfStats {
+    doorbells: AtomicU64,
+    events: AtomicU64,
+    event_ring_full: AtomicU64,
+    irqs: AtomicU64,
+    commands: AtomicU64,
+    command_latency_ns: AtomicU64,
+    imod_interval: AtomicU64,
+    imod_adjustments: AtomicU64,
+}
+
+impl PerfStats {
+    pub fn doorbell(&self) {
+        self.doorbells.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn event(&self) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn event_ring_full(&self) {
+        self.event_ring_full.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn irq(&self) {
+        self.irqs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn command(&self, latency: Duration) {
+        self.commands.fetch_add(1, Ordering::Relaxed);
+        self.command_latency_ns
+            .fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// 记录写入 IMODI 的间隔（250ns 单位），`adjusted` 表示由自适应算法调整
+    pub fn imod(&self, interval: u16, adjusted: bool) {
+        self.imod_interval.store(interval as u64, Ordering::Relaxed);
+        if adjusted {
+            self.imod_adjustments.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> PerfCounters {
+        let commands = self.commands.load(Ordering::Relaxed);
+        let latency_ns = self.command_latency_ns.load(Ordering::Relaxed);
+        let avg_command_latency = if commands == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_nanos(latency_ns / commands)
+        };
+
+        PerfCounters {
+            doorbells: self.doorbells.load(Ordering::Relaxed),
+            events: self.events.load(Ordering::Relaxed),
+            event_ring_full: self.event_ring_full.load(Ordering::Relaxed),
+            irqs: self.irqs.load(Ordering::Relaxed),
+            commands,
+            avg_command_latency,
+            imod_policy: ImodPolicy::default(),
+            imod_interval: Duration::from_nanos(
+                self.imod_interval.load(Ordering::Relaxed) * IMOD_UNIT_NS,
+            ),
+            imod_adjustments: self.imod_adjustments.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imod_snapshot() {
+        let stats = PerfStats::default();
+        stats.imod(40, false);
+        stats.imod(80, true);
+        let counters = stats.snapshot();
+        assert_eq!(counters.imod_interval, Duration::from_micros(20));
+        assert_eq!(counters.imod_adjustments, 1);
+    }
+}
@@ -0,0 +1,215 @@
+//! 端口状态通知
+//!
+//! 供板级代码驱动前面板 LED 或记录诊断信息。各 Hub 记录检测到的端口状态变化，
+//! 主机在处理端口变化时补全端口路径并调用
+//! [`USBHost::set_port_event_callback`](crate::USBHost::set_port_event_callback) 注册的回调，
+//! 同时投递给 [`USBHost::subscribe_ports`](crate::USBHost::subscribe_ports) 返回的订阅。
+//! 端口的当前状态见 [`USBHost::ports`](crate::USBHost::ports)。
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{Stream, task::AtomicWaker};
+use spin::Mutex;
+use usb_if::Speed;
+
+pub use super::hub::PortState;
+
+/// 端口状态变化
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortTransition {
+    /// 检测到设备接入，端口尚未启用
+    Connected,
+    /// 端口复位完成并启用，设备即将枚举
+    Enabled(Speed),
+    /// 已枚举的设备断开，目前只报告根端口
+    Disconnected,
+    /// 端口复位或启用失败，或控制器因错误禁用了端口
+    Error,
+    /// 端口报告过流
+    OverCurrent,
+}
+
+/// 端口状态通知
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortEvent {
+    /// 从根端口开始逐级的端口号，最后一项为发生变化的端口
+    pub path: Vec<u8>,
+    pub transition: PortTransition,
+}
+
+impl PortEvent {
+    pub fn root_port(&self) -> u8 {
+        self.path[0]
+    }
+}
+
+/// 在处理端口变化的任务中同步调用，不应阻塞
+pub type PortEventCallback = Box<dyn Fn(&PortEvent) + Send + Sync>;
+
+/// 端口状态快照，见 [`USBHost::ports`](crate::USBHost::ports)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortSnapshot {
+    /// 从根端口开始逐级的端口号，最后一项为本端口
+    pub path: Vec<u8>,
+    pub state: PortState,
+    pub connected: bool,
+    /// 端口启用后协商的速度，未启用时为 `None`
+    pub speed: Option<Speed>,
+    /// 端口上已枚举设备的 [`ProbedDevice::id`](crate::ProbedDevice::id)
+    pub device_id: Option<usize>,
+}
+
+impl PortSnapshot {
+    pub fn root_port(&self) -> u8 {
+        self.path[0]
+    }
+
+    /// 端口所在 Hub 的层级，根端口为 0
+    pub fn depth(&self) -> usize {
+        self.path.len() - 1
+    }
+}
+
+/// 订阅队列的容量，超出后丢弃最旧的事件
+const SUBSCRIPTION_CAPACITY: usize = 64;
+
+#[derive(Default)]
+struct Queue {
+    events: VecDeque<PortEvent>,
+    /// 因队列已满丢弃的事件数
+    lost: u64,
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    waker: AtomicWaker,
+}
+
+/// 端口状态变化订阅，见 [`USBHost::subscribe_ports`](crate::USBHost::subscribe_ports)
+///
+/// 作为 [`Stream`] 逐个取出事件，主机释放后结束。调用方取得慢时保留最近的
+/// 64 个事件，更早的被丢弃并计入 [`PortSubscription::lost`]。
+pub struct PortSubscription {
+    shared: Arc<Shared>,
+}
+
+impl PortSubscription {
+    /// 因队列已满丢弃的事件数
+    pub fn lost(&self) -> u64 {
+        self.shared.queue.lock().lost
+    }
+}
+
+impl Stream for PortSubscription {
+    type Item = PortEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<PortEvent>> {
+        self.shared.waker.register(cx.waker());
+        if let Some(event) = self.shared.queue.lock().events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        // 主机持有唯一的弱引用，弱引用消失说明主机已释放
+        if Arc::weak_count(&self.shared) == 0 {
+            return Poll::Ready(None);
+        }
+        Poll::Pending
+    }
+}
+
+/// 主机一侧的订阅者列表，订阅释放后自动移除
+#[derive(Default)]
+pub(crate) struct PortSubscribers {
+    subscribers: Vec<Weak<Shared>>,
+}
+
+impl PortSubscribers {
+    pub fn subscribe(&mut self) -> PortSubscription {
+        let shared = Arc::new(Shared::default());
+        self.subscribers.push(Arc::downgrade(&shared));
+        PortSubscription { shared }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    pub fn publish(&mut self, event: &PortEvent) {
+        self.subscribers.retain(|weak| {
+            let Some(shared) = weak.upgrade() else {
+                return false;
+            };
+            let mut queue = shared.queue.lock();
+            if queue.events.len() == SUBSCRIPTION_CAPACITY {
+                queue.events.pop_front();
+                queue.lost += 1;
+            }
+            queue.events.push_back(event.clone());
+            drop(queue);
+            shared.waker.wake();
+            true
+        });
+    }
+}
+
+impl Drop for PortSubscribers {
+    fn drop(&mut self) {
+        // 唤醒等待中的订阅，使其在弱引用消失后结束
+        let subscribers = core::mem::take(&mut self.subscribers);
+        let alive: Vec<_> = subscribers.iter().filter_map(Weak::upgrade).collect();
+        drop(subscribers);
+        for shared in alive {
+            shared.waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{FutureExt, StreamExt};
+
+    use super::*;
+
+    fn event(port: u8) -> PortEvent {
+        PortEvent {
+            path: alloc::vec![port],
+            transition: PortTransition::Connected,
+        }
+    }
+
+    #[test]
+    fn subscription_queues_and_ends() {
+        let mut subscribers = PortSubscribers::default();
+        let mut sub = subscribers.subscribe();
+        let dropped = subscribers.subscribe();
+        drop(dropped);
+
+        subscribers.publish(&event(1));
+        assert_eq!(subscribers.subscribers.len(), 1);
+        for port in 0..SUBSCRIPTION_CAPACITY as u8 {
+            subscribers.publish(&event(port + 2));
+        }
+        assert_eq!(sub.lost(), 1);
+
+        let first = sub.next().now_or_never().unwrap().unwrap();
+        assert_eq!(first.path, [2]);
+        assert!(sub.next().now_or_never().is_some());
+
+        drop(subscribers);
+        let mut rest = 0;
+        while let Some(Some(_)) = sub.next().now_or_never() {
+            rest += 1;
+        }
+        assert_eq!(rest, SUBSCRIPTION_CAPACITY - 2);
+        assert_eq!(sub.next().now_or_never(), Some(None));
+    }
+}
@@ -1,19 +1,44 @@
+//! TRB 完成结果的无锁投递
+//!
+//! 每个 TRB 对应一个容量为 1 的完成槽：中断上下文中的事件处理器是生产者，
+//! 等待该 TRB 的任务是消费者。槽的状态由一个原子变量驱动，生产者与消费者都不会自旋，
+//! 因此中断打断持有槽的任务时不会死锁。
+//!
+//! 槽表在创建时按 Ring 的 TRB 地址一次性建立，之后只读，中断路径查表无需加锁。
+//!
+//! # 中断安全
+//!
+//! | 调用 | 上下文 |
+//! |------|--------|
+//! | [`Finished::set_finished`] | 中断安全，可并发调用 |
+//! | [`Finished::get_finished`]、[`Finished::is_finished`]、[`Finished::register_cx`] | 中断安全，通常在任务中调用 |
+//! | [`Finished::clear_finished`] | 仅任务上下文，TRB 重新入队前调用 |
+//! | [`Finished::take_waiter`]、[`TWaiter`] | 仅任务上下文 |
+
+use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 use core::pin::Pin;
 use core::task::Context;
 use core::task::Poll;
 use core::{
     cell::UnsafeCell,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
 };
 use futures::task::AtomicWaker;
 
-use alloc::collections::BTreeMap;
-
 use crate::BusAddr;
 
+/// 槽为空，可以写入
+const EMPTY: u8 = 0;
+/// 生产者正在写入
+const WRITING: u8 = 1;
+/// 结果已就绪
+const READY: u8 = 2;
+/// 消费者正在取出
+const TAKING: u8 = 3;
+
 pub struct Finished<C> {
-    inner: Arc<FinishedInner<C>>,
+    inner: Arc<BTreeMap<BusAddr, Arc<FinishedData<C>>>>,
 }
 
 impl<C> Clone for Finished<C> {
@@ -24,81 +49,107 @@ impl<C> Clone for Finished<C> {
     }
 }
 
-pub struct FinishedInner<C> {
-    data: UnsafeCell<BTreeMap<BusAddr, Arc<FinishedData<C>>>>,
-}
-
 pub struct FinishedData<C> {
     taken: AtomicBool,
-    finished: AtomicBool,
+    state: AtomicU8,
     waker: AtomicWaker,
     data: UnsafeCell<Option<C>>,
 }
 
+// `data` 只在持有 WRITING 或 TAKING 状态时访问，状态转换保证同一时刻只有一方访问
+unsafe impl<C: Send> Send for FinishedData<C> {}
+unsafe impl<C: Send> Sync for FinishedData<C> {}
+
 impl<C> FinishedData<C> {
     fn new() -> Self {
         Self {
-            finished: AtomicBool::new(false),
             taken: AtomicBool::new(false),
+            state: AtomicU8::new(EMPTY),
             waker: AtomicWaker::new(),
             data: UnsafeCell::new(None),
         }
     }
-}
 
-unsafe impl<C> Send for FinishedInner<C> {}
-unsafe impl<C> Sync for FinishedInner<C> {}
-unsafe impl<C> Send for FinishedData<C> {}
-unsafe impl<C> Sync for FinishedData<C> {}
-
-impl<C> FinishedInner<C> {
-    fn clear_finished(&self, addr: BusAddr) {
-        if let Some(data) = unsafe { &mut *self.data.get() }.get(&addr) {
-            data.finished.store(false, Ordering::Release);
-            data.taken.store(false, Ordering::Release);
-            unsafe {
-                (*data.data.get()).take();
-            }
+    /// 写入结果并唤醒等待者；槽中已有未取走的结果时丢弃新结果
+    fn put(&self, value: C) {
+        if self
+            .state
+            .compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        unsafe {
+            *self.data.get() = Some(value);
         }
+        self.state.store(READY, Ordering::Release);
+        self.waker.wake();
+    }
+
+    /// 取出已就绪的结果，槽随即回到空状态
+    fn take(&self) -> Option<C> {
+        self.take_if(|_| true)
+    }
+
+    /// 结果满足 `f` 时取出，否则保留在槽中
+    fn take_if(&self, f: impl FnOnce(&C) -> bool) -> Option<C> {
+        self.state
+            .compare_exchange(READY, TAKING, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        let data = unsafe { &mut *self.data.get() };
+        if !data.as_ref().is_some_and(f) {
+            self.state.store(READY, Ordering::Release);
+            return None;
+        }
+        let value = data.take();
+        self.state.store(EMPTY, Ordering::Release);
+        value
     }
 }
 
 impl<C> Finished<C> {
     pub fn new(addrs: impl Iterator<Item = BusAddr>) -> Self {
-        let mut data = BTreeMap::new();
-
-        for addr in addrs {
-            data.insert(addr, Arc::new(FinishedData::new()));
-        }
+        let data = addrs
+            .map(|addr| (addr, Arc::new(FinishedData::new())))
+            .collect();
         Self {
-            inner: Arc::new(FinishedInner {
-                data: UnsafeCell::new(data),
-            }),
+            inner: Arc::new(data),
         }
     }
 
+    /// 丢弃 TRB 上一次使用留下的结果并重置等待者标记
     pub fn clear_finished(&self, addr: BusAddr) {
-        self.inner.clear_finished(addr);
+        if let Some(slot) = self.inner.get(&addr) {
+            slot.take();
+            slot.taken.store(false, Ordering::Release);
+        }
     }
 
+    /// 投递 TRB 的完成结果，未知地址的事件被忽略
+    ///
+    /// 在中断中调用：不加锁、不分配内存、不输出日志。
     pub fn set_finished(&self, addr: BusAddr, value: C) {
-        let data = unsafe { &mut *self.inner.data.get() };
-        if let Some(slot) = data.get_mut(&addr) {
-            unsafe {
-                *slot.data.get() = Some(value);
-            }
-            slot.finished.store(true, Ordering::Release);
-            slot.waker.wake();
+        if let Some(slot) = self.inner.get(&addr) {
+            slot.put(value);
         }
     }
 
     pub fn get_finished(&self, addr: BusAddr) -> Option<C> {
-        self.waiter(addr).get_finished()
+        self.waiter(addr).take()
+    }
+
+    /// 结果已就绪，不取出
+    pub fn is_finished(&self, addr: BusAddr) -> bool {
+        self.waiter(addr).state.load(Ordering::Acquire) == READY
+    }
+
+    /// 结果满足 `f` 时取出，否则不改变槽的状态
+    pub fn take_finished_if(&self, addr: BusAddr, f: impl FnOnce(&C) -> bool) -> Option<C> {
+        self.waiter(addr).take_if(f)
     }
 
     fn waiter(&self, addr: BusAddr) -> &FinishedData<C> {
-        let data = unsafe { &mut *self.inner.data.get() };
-        let slot = data.get(&addr).unwrap();
+        let slot = self.inner.get(&addr).unwrap();
         if slot.taken.load(Ordering::Acquire) {
             panic!("waiter called after take_waiter");
         }
@@ -111,7 +162,7 @@ impl<C> Finished<C> {
     }
 
     pub fn take_waiter(&self, addr: BusAddr) -> TWaiter<C> {
-        let data = unsafe { &mut *self.inner.data.get() }.get(&addr).unwrap();
+        let data = self.inner.get(&addr).unwrap();
         if data.taken.swap(true, Ordering::AcqRel) {
             panic!("take_waiter called multiple times for the same addr");
         }
@@ -130,11 +181,12 @@ impl<C> Future for TWaiter<C> {
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
-        if let Some(res) = this.finished.get_finished() {
-            return Poll::Ready(res);
-        }
+        // 先注册再检查，避免结果在两步之间到达而丢失唤醒
         this.finished.register(cx.waker());
-        Poll::Pending
+        match this.finished.take() {
+            Some(res) => Poll::Ready(res),
+            None => Poll::Pending,
+        }
     }
 }
 
@@ -142,11 +194,4 @@ impl<C> FinishedData<C> {
     pub fn register(&self, waker: &core::task::Waker) {
         self.waker.register(waker);
     }
-
-    pub fn get_finished(&self) -> Option<C> {
-        if !self.finished.load(Ordering::Acquire) {
-            return None;
-        }
-        unsafe { (*self.data.get()).take() }
-    }
 }
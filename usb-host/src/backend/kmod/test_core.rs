@@ -0,0 +1,259 @@
+//! 单元测试使用的 [`CoreOp`]、[`HubOp`] 与 [`DeviceOp`]，不访问硬件，只记录主机对端口的操作
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use std::sync::Mutex;
+
+use futures::{FutureExt, future::BoxFuture};
+use usb_if::{
+    descriptor::{ConfigurationDescriptor, DeviceDescriptor, EndpointDescriptor, EndpointType},
+    endpoint::{EndpointAddress, EndpointInfo},
+    err::USBError,
+};
+
+use super::{
+    hub::{HubInfo, HubOp, PortChangeInfo},
+    kcore::CoreOp,
+    osal::Kernel,
+    perf::{PerfCounters, SelfTestReport},
+    test_kernel::HeapKernel,
+};
+use crate::{
+    DeviceAddressInfo,
+    backend::ty::{
+        DeviceOp, EventHandlerOp, HubParams,
+        ep::{Endpoint, fake::Completing},
+    },
+};
+
+/// [`MockHub`] 的端口操作记录，测试中可预置远程唤醒
+#[derive(Debug, Default)]
+pub(crate) struct PortLog {
+    /// 当前挂起的端口
+    pub suspended: Vec<u8>,
+    /// 待报告远程唤醒的端口，非空时 [`HubOp::wait_port_change`] 立即返回
+    pub wakeups: Vec<u8>,
+    /// 依次调用 [`HubOp::resume_port`] 的端口
+    pub resumed: Vec<u8>,
+}
+
+pub(crate) struct MockHub {
+    slot_id: u8,
+    log: Arc<Mutex<PortLog>>,
+}
+
+impl MockHub {
+    pub(crate) fn new(slot_id: u8) -> (Self, Arc<Mutex<PortLog>>) {
+        let log = Arc::new(Mutex::new(PortLog::default()));
+        (
+            Self {
+                slot_id,
+                log: log.clone(),
+            },
+            log,
+        )
+    }
+}
+
+impl HubOp for MockHub {
+    fn init<'a>(&'a mut self, info: HubInfo) -> BoxFuture<'a, Result<HubInfo, USBError>> {
+        async move { Ok(info) }.boxed()
+    }
+
+    fn changed_ports<'a>(&'a mut self) -> BoxFuture<'a, Result<Vec<PortChangeInfo>, USBError>> {
+        async { Ok(Vec::new()) }.boxed()
+    }
+
+    fn slot_id(&self) -> u8 {
+        self.slot_id
+    }
+
+    fn wait_port_change<'a>(&'a mut self) -> BoxFuture<'a, ()> {
+        let log = self.log.clone();
+        core::future::poll_fn(move |_| {
+            if log.lock().unwrap().wakeups.is_empty() {
+                core::task::Poll::Pending
+            } else {
+                core::task::Poll::Ready(())
+            }
+        })
+        .boxed()
+    }
+
+    fn suspend_port<'a>(&'a mut self, port: u8) -> BoxFuture<'a, Result<(), USBError>> {
+        self.log.lock().unwrap().suspended.push(port);
+        async { Ok(()) }.boxed()
+    }
+
+    fn resume_port<'a>(&'a mut self, port: u8) -> BoxFuture<'a, Result<(), USBError>> {
+        let mut log = self.log.lock().unwrap();
+        log.suspended.retain(|&p| p != port);
+        log.resumed.push(port);
+        async { Ok(()) }.boxed()
+    }
+
+    fn take_resumed_ports(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.log.lock().unwrap().wakeups)
+    }
+}
+
+/// 只有根 Hub 的控制器，不会枚举出新设备
+pub(crate) struct MockCore {
+    root_hub: Option<MockHub>,
+    kernel: Kernel,
+}
+
+impl MockCore {
+    pub(crate) fn new(root_hub: MockHub) -> Self {
+        Self {
+            root_hub: Some(root_hub),
+            kernel: HeapKernel::kernel(),
+        }
+    }
+}
+
+impl CoreOp for MockCore {
+    fn init<'a>(&'a mut self) -> BoxFuture<'a, Result<(), USBError>> {
+        async { Ok(()) }.boxed()
+    }
+
+    fn root_hub(&mut self) -> Box<dyn HubOp> {
+        Box::new(self.root_hub.take().expect("root hub is taken once"))
+    }
+
+    fn new_addressed_device<'a>(
+        &'a mut self,
+        _addr: DeviceAddressInfo,
+    ) -> BoxFuture<'a, Result<Box<dyn DeviceOp>, USBError>> {
+        async { Err(USBError::NotSupported) }.boxed()
+    }
+
+    fn create_event_handler(&mut self) -> Box<dyn EventHandlerOp> {
+        unimplemented!("mock controller raises no events")
+    }
+
+    fn kernel(&self) -> &Kernel {
+        &self.kernel
+    }
+
+    fn perf_counters(&self) -> PerfCounters {
+        PerfCounters::default()
+    }
+
+    fn self_test<'a>(&'a mut self, _count: u32) -> BoxFuture<'a, Result<SelfTestReport, USBError>> {
+        async { Err(USBError::NotSupported) }.boxed()
+    }
+
+    fn eject_slot<'a>(
+        &'a mut self,
+        _slot_id: u8,
+        _power_off_port: Option<u8>,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        async { Ok(()) }.boxed()
+    }
+
+    fn frame_index(&self) -> Option<u16> {
+        Some(0)
+    }
+}
+
+/// 没有接口的设备，控制传输立即完成且不返回数据
+pub(crate) struct MockDevice {
+    id: usize,
+    desc: DeviceDescriptor,
+    ctrl_ep: Endpoint,
+}
+
+impl MockDevice {
+    pub(crate) fn new(id: usize, class: u8) -> Self {
+        let address = EndpointAddress::new(0);
+        Self {
+            id,
+            desc: DeviceDescriptor {
+                usb_version: 0x0200,
+                class,
+                subclass: 0,
+                protocol: 0,
+                max_packet_size_0: 64,
+                vendor_id: 0,
+                product_id: 0,
+                device_version: 0,
+                manufacturer_string_index: None,
+                product_string_index: None,
+                serial_number_string_index: None,
+                num_configurations: 0,
+            },
+            ctrl_ep: Endpoint::new(
+                EndpointInfo {
+                    address,
+                    transfer_type: EndpointType::Control,
+                    direction: address.direction(),
+                    max_packet_size: 64,
+                    packets_per_microframe: 1,
+                    interval: 0,
+                },
+                Completing::new(0),
+            ),
+        }
+    }
+}
+
+impl DeviceOp for MockDevice {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn backend_name(&self) -> &str {
+        "mock"
+    }
+
+    fn descriptor(&self) -> &DeviceDescriptor {
+        &self.desc
+    }
+
+    fn configuration_descriptors(&self) -> &[ConfigurationDescriptor] {
+        &[]
+    }
+
+    fn set_descriptors(&mut self, desc: DeviceDescriptor, _configs: Vec<ConfigurationDescriptor>) {
+        self.desc = desc;
+    }
+
+    fn ctrl_ep_ref(&self) -> &Endpoint {
+        &self.ctrl_ep
+    }
+
+    fn ctrl_ep_mut(&mut self) -> &mut Endpoint {
+        &mut self.ctrl_ep
+    }
+
+    fn claim_interface<'a>(
+        &'a mut self,
+        _interface: u8,
+        _alternate: u8,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        async { Ok(()) }.boxed()
+    }
+
+    fn release_interface<'a>(
+        &'a mut self,
+        _interface: u8,
+        _alternate: u8,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        async { Ok(()) }.boxed()
+    }
+
+    fn set_configuration<'a>(
+        &'a mut self,
+        _configuration_value: u8,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        async { Ok(()) }.boxed()
+    }
+
+    fn endpoint(&mut self, _desc: &EndpointDescriptor) -> Result<Endpoint, USBError> {
+        Err(USBError::NotFound)
+    }
+
+    fn update_hub(&mut self, _params: HubParams) -> BoxFuture<'_, Result<(), USBError>> {
+        async { Ok(()) }.boxed()
+    }
+}
@@ -0,0 +1,133 @@
+//! 单元测试使用的 [`KernelOp`]，DMA 内存直接从堆上分配，总线地址即虚拟地址
+
+use core::{
+    alloc::Layout,
+    num::NonZeroUsize,
+    ptr::NonNull,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use super::osal::{DmaAddr, DmaDirection, DmaError, DmaHandle, DmaMapHandle, DmaOp, KernelOp};
+
+pub(crate) struct HeapKernel;
+
+impl HeapKernel {
+    pub(crate) fn kernel() -> super::osal::Kernel {
+        super::osal::Kernel::new(u64::MAX, &HeapKernel)
+    }
+}
+
+impl DmaOp for HeapKernel {
+    fn page_size(&self) -> usize {
+        4096
+    }
+
+    unsafe fn map_single(
+        &self,
+        _dma_mask: u64,
+        addr: NonNull<u8>,
+        size: NonZeroUsize,
+        align: usize,
+        _direction: DmaDirection,
+    ) -> Result<DmaMapHandle, DmaError> {
+        let layout = Layout::from_size_align(size.get(), align)?;
+        let addr_dma = DmaAddr::from(addr.as_ptr() as u64);
+        Ok(unsafe { DmaMapHandle::new(addr, addr_dma, layout, None) })
+    }
+
+    unsafe fn unmap_single(&self, _handle: DmaMapHandle) {}
+
+    unsafe fn alloc_coherent(&self, _dma_mask: u64, layout: Layout) -> Option<DmaHandle> {
+        let virt = NonNull::new(unsafe { std::alloc::alloc_zeroed(layout) })?;
+        Some(unsafe { DmaHandle::new(virt, (virt.as_ptr() as u64).into(), layout) })
+    }
+
+    unsafe fn dealloc_coherent(&self, handle: DmaHandle) {
+        unsafe { std::alloc::dealloc(handle.as_ptr().as_ptr(), handle.layout()) };
+    }
+
+    fn flush(&self, _addr: NonNull<u8>, _size: usize) {}
+
+    fn invalidate(&self, _addr: NonNull<u8>, _size: usize) {}
+
+    fn flush_invalidate(&self, _addr: NonNull<u8>, _size: usize) {}
+}
+
+impl KernelOp for HeapKernel {
+    fn delay(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+
+    fn now(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// 时钟由测试推进的 [`KernelOp`]，DMA 操作同 [`HeapKernel`]
+pub(crate) struct ClockKernel {
+    now_ns: AtomicU64,
+}
+
+impl ClockKernel {
+    /// 每个测试各用一个，互不影响
+    pub(crate) fn leak() -> &'static Self {
+        Box::leak(Box::new(Self {
+            now_ns: AtomicU64::new(0),
+        }))
+    }
+
+    pub(crate) fn kernel(&'static self) -> super::osal::Kernel {
+        super::osal::Kernel::new(u64::MAX, self)
+    }
+
+    pub(crate) fn advance(&self, duration: Duration) {
+        self.now_ns
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl DmaOp for ClockKernel {
+    fn page_size(&self) -> usize {
+        HeapKernel.page_size()
+    }
+
+    unsafe fn map_single(
+        &self,
+        dma_mask: u64,
+        addr: NonNull<u8>,
+        size: NonZeroUsize,
+        align: usize,
+        direction: DmaDirection,
+    ) -> Result<DmaMapHandle, DmaError> {
+        unsafe { HeapKernel.map_single(dma_mask, addr, size, align, direction) }
+    }
+
+    unsafe fn unmap_single(&self, handle: DmaMapHandle) {
+        unsafe { HeapKernel.unmap_single(handle) }
+    }
+
+    unsafe fn alloc_coherent(&self, dma_mask: u64, layout: Layout) -> Option<DmaHandle> {
+        unsafe { HeapKernel.alloc_coherent(dma_mask, layout) }
+    }
+
+    unsafe fn dealloc_coherent(&self, handle: DmaHandle) {
+        unsafe { HeapKernel.dealloc_coherent(handle) }
+    }
+
+    fn flush(&self, _addr: NonNull<u8>, _size: usize) {}
+
+    fn invalidate(&self, _addr: NonNull<u8>, _size: usize) {}
+
+    fn flush_invalidate(&self, _addr: NonNull<u8>, _size: usize) {}
+}
+
+impl KernelOp for ClockKernel {
+    fn delay(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.now_ns.load(Ordering::Relaxed))
+    }
+}
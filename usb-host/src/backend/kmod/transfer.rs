@@ -1,18 +1,39 @@
 use core::ptr::NonNull;
 
 use alloc::vec::Vec;
-use dma_api::DmaDirection;
+use dma_api::{DArray, DmaDirection, SArrayPtr};
 use usb_if::endpoint::TransferRequest;
 use usb_if::err::TransferError;
 use usb_if::transfer::Direction;
 
 use crate::{
+    backend::kmod::{BouncePolicy, mem::MemTag},
     backend::ty::transfer::{Transfer, TransferKind},
     osal::Kernel,
 };
 
 const ALIGN: usize = 64;
 
+/// 传输缓冲区的 DMA 视图
+pub(crate) enum TransferDma {
+    /// 直接映射的调用方缓冲区
+    Mapped(SArrayPtr<u8>),
+    /// 按设备掩码分配的反弹缓冲区，IN 传输完成后复制回 `user`
+    Bounced { buf: DArray<u8>, user: NonNull<u8> },
+}
+
+/// 已提交请求的缓冲区在总线上的映射，见 [`crate::Endpoint::dma_mapping`]
+#[cfg(feature = "dma-mapping")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaMapping {
+    /// 控制器访问的总线地址
+    pub dma_addr: u64,
+    pub len: usize,
+    /// 为真时控制器访问的是反弹缓冲区，而不是调用方缓冲区：OUT 数据已在提交时
+    /// 复制进去，IN 数据在回收时才复制回调用方缓冲区
+    pub bounced: bool,
+}
+
 impl Transfer {
     pub(crate) fn new(
         dma: &Kernel,
@@ -24,14 +45,27 @@ impl Transfer {
             Direction::In => DmaDirection::FromDevice,
             Direction::Out => DmaDirection::ToDevice,
         };
-        let mapping = if let Some((ptr, len)) = buff.filter(|(_, len)| *len > 0) {
-            let slice = unsafe { core::slice::from_raw_parts_mut(ptr.as_ptr(), len) };
-            Some(
-                dma.map_single_array(slice, ALIGN, dma_direction)
-                    .map_err(|err| TransferError::Other(anyhow!("DMA mapping failed: {err}")))?,
-            )
-        } else {
-            None
+        let mapping = match buff.filter(|(_, len)| *len > 0) {
+            Some((ptr, len)) => {
+                let slice = unsafe { core::slice::from_raw_parts_mut(ptr.as_ptr(), len) };
+                let dma = dma.with_tag(MemTag::Transfer);
+                let mapping = match dma.bounce() {
+                    BouncePolicy::Auto => dma
+                        .map_single_array(slice, ALIGN, dma_direction)
+                        .map(TransferDma::Mapped),
+                    BouncePolicy::Always => dma
+                        .array_zero_with_align(len, ALIGN, dma_direction)
+                        .map(|mut buf| {
+                            if matches!(direction, Direction::Out) {
+                                buf.copy_from_slice(slice);
+                            }
+                            TransferDma::Bounced { buf, user: ptr }
+                        }),
+                }
+                .map_err(|err| TransferError::Other(anyhow!("DMA mapping failed: {err}")))?;
+                Some(mapping)
+            }
+            None => None,
         };
 
         Ok(Self {
@@ -40,6 +74,7 @@ impl Transfer {
             mapping,
             transfer_len: 0,
             iso_packet_actual_lengths: Vec::new(),
+            iso_packet_status: Vec::new(),
         })
     }
 
@@ -104,30 +139,48 @@ impl Transfer {
     // }
 
     pub fn buffer_len(&self) -> usize {
-        if let Some(ref mapping) = self.mapping {
-            mapping.len()
-        } else {
-            0
+        match &self.mapping {
+            Some(TransferDma::Mapped(mapping)) => mapping.len(),
+            Some(TransferDma::Bounced { buf, .. }) => buf.len(),
+            None => 0,
         }
     }
 
     pub fn dma_addr(&self) -> u64 {
-        if let Some(ref mapping) = self.mapping {
-            mapping.dma_addr().as_u64()
-        } else {
-            0
+        match &self.mapping {
+            Some(TransferDma::Mapped(mapping)) => mapping.dma_addr().as_u64(),
+            Some(TransferDma::Bounced { buf, .. }) => buf.dma_addr().as_u64(),
+            None => 0,
         }
     }
 
+    #[cfg(feature = "dma-mapping")]
+    pub fn dma_mapping(&self) -> Option<DmaMapping> {
+        self.mapping.as_ref().map(|mapping| DmaMapping {
+            dma_addr: self.dma_addr(),
+            len: self.buffer_len(),
+            bounced: matches!(mapping, TransferDma::Bounced { .. }),
+        })
+    }
+
     pub fn prepare_read_all(&self) {
-        if let Some(ref mapping) = self.mapping {
-            mapping.prepare_read_all();
+        match &self.mapping {
+            Some(TransferDma::Mapped(mapping)) => mapping.prepare_read_all(),
+            Some(TransferDma::Bounced { buf, user }) => {
+                buf.prepare_read_all();
+                buf.read_with(buf.len(), |data| unsafe {
+                    core::ptr::copy_nonoverlapping(data.as_ptr(), user.as_ptr(), data.len());
+                });
+            }
+            None => {}
         }
     }
 
     pub fn confirm_write_all(&self) {
-        if let Some(ref mapping) = self.mapping {
-            mapping.confirm_write_all();
+        match &self.mapping {
+            Some(TransferDma::Mapped(mapping)) => mapping.confirm_write_all(),
+            Some(TransferDma::Bounced { buf, .. }) => buf.confirm_write_all(),
+            None => {}
         }
     }
 }
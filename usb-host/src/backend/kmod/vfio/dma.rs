@@ -0,0 +1,241 @@
+//! VFIO 下的 DMA 内存
+//!
+//! 启动时申请一整块（优先使用大页的）匿名内存，一次性映射到 IOMMU，
+//! 之后所有一致性分配与流式映射都从这块内存中切分，不再逐次调用 `VFIO_IOMMU_MAP_DMA`。
+
+use core::{alloc::Layout, num::NonZeroUsize, ptr::NonNull, time::Duration};
+use std::{collections::BTreeMap, io, os::fd::AsRawFd};
+
+use dma_api::{DmaAddr, DmaDirection, DmaError, DmaHandle, DmaMapHandle, DmaOp};
+use spin::Mutex;
+
+use super::sys::*;
+use crate::backend::kmod::KernelOp;
+
+const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+/// 空闲区间表，按偏移首次适配分配，释放时与相邻区间合并
+#[derive(Debug)]
+pub(crate) struct FreeList {
+    /// 起始偏移 -> 长度
+    free: BTreeMap<usize, usize>,
+}
+
+impl FreeList {
+    pub fn new(size: usize) -> Self {
+        let mut free = BTreeMap::new();
+        free.insert(0, size);
+        Self { free }
+    }
+
+    pub fn alloc(&mut self, size: usize, align: usize) -> Option<usize> {
+        let (start, len, offset) = self.free.iter().find_map(|(&start, &len)| {
+            let offset = start.next_multiple_of(align);
+            (offset + size <= start + len).then_some((start, len, offset))
+        })?;
+        self.free.remove(&start);
+        if offset > start {
+            self.free.insert(start, offset - start);
+        }
+        if offset + size < start + len {
+            self.free.insert(offset + size, start + len - offset - size);
+        }
+        Some(offset)
+    }
+
+    pub fn dealloc(&mut self, mut offset: usize, mut size: usize) {
+        if let Some((&prev, &prev_len)) = self.free.range(..offset).next_back()
+            && prev + prev_len == offset
+        {
+            self.free.remove(&prev);
+            offset = prev;
+            size += prev_len;
+        }
+        if let Some(next_len) = self.free.remove(&(offset + size)) {
+            size += next_len;
+        }
+        self.free.insert(offset, size);
+    }
+
+    #[cfg(test)]
+    fn free_bytes(&self) -> usize {
+        self.free.values().sum()
+    }
+}
+
+/// 已映射到 IOMMU 的 DMA 内存池，同时实现 [`KernelOp`]
+pub struct VfioKernel {
+    virt: NonNull<u8>,
+    iova: u64,
+    size: usize,
+    huge: bool,
+    free: Mutex<FreeList>,
+}
+
+// `virt` 指向的内存只通过 `free` 分配出去，不同分配之间互不重叠
+unsafe impl Send for VfioKernel {}
+unsafe impl Sync for VfioKernel {}
+
+impl VfioKernel {
+    /// 申请 `size` 字节内存并映射到 IOVA `iova` 处
+    pub(crate) fn new(container: &impl AsRawFd, iova: u64, size: usize) -> io::Result<Self> {
+        let size = size.next_multiple_of(HUGE_PAGE_SIZE);
+        let (virt, huge) = match mmap_anon(size, libc::MAP_HUGETLB) {
+            Ok(virt) => (virt, true),
+            Err(e) => {
+                warn!("Huge pages unavailable ({e}), falling back to normal pages");
+                (mmap_anon(size, 0)?, false)
+            }
+        };
+
+        let map = VfioIommuType1DmaMap {
+            argsz: size_of::<VfioIommuType1DmaMap>() as _,
+            flags: VFIO_DMA_MAP_FLAG_READ | VFIO_DMA_MAP_FLAG_WRITE,
+            vaddr: virt.as_ptr() as u64,
+            iova,
+            size: size as u64,
+        };
+        let ret = unsafe { libc::ioctl(container.as_raw_fd(), VFIO_IOMMU_MAP_DMA, &map) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            unsafe { libc::munmap(virt.as_ptr().cast(), size) };
+            return Err(err);
+        }
+
+        debug!("VFIO DMA pool: {size:#x} bytes at iova {iova:#x}, huge pages: {huge}",);
+
+        Ok(Self {
+            virt,
+            iova,
+            size,
+            huge,
+            free: Mutex::new(FreeList::new(size)),
+        })
+    }
+
+    /// DMA 内存池大小
+    pub fn pool_size(&self) -> usize {
+        self.size
+    }
+
+    /// 是否使用了大页
+    pub fn huge_pages(&self) -> bool {
+        self.huge
+    }
+
+    fn alloc(&self, dma_mask: u64, layout: Layout) -> Result<(NonNull<u8>, DmaAddr), DmaError> {
+        let offset = self
+            .free
+            .lock()
+            .alloc(layout.size(), layout.align())
+            .ok_or(DmaError::NoMemory)?;
+        let iova = self.iova + offset as u64;
+        if iova + layout.size() as u64 - 1 > dma_mask {
+            self.free.lock().dealloc(offset, layout.size());
+            return Err(DmaError::DmaMaskNotMatch {
+                addr: iova.into(),
+                mask: dma_mask,
+            });
+        }
+        let virt = unsafe { self.virt.add(offset) };
+        Ok((virt, iova.into()))
+    }
+
+    fn dealloc(&self, virt: NonNull<u8>, size: usize) {
+        let offset = unsafe { virt.offset_from(self.virt) } as usize;
+        self.free.lock().dealloc(offset, size);
+    }
+}
+
+impl DmaOp for VfioKernel {
+    fn page_size(&self) -> usize {
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+    }
+
+    unsafe fn map_single(
+        &self,
+        dma_mask: u64,
+        addr: NonNull<u8>,
+        size: NonZeroUsize,
+        align: usize,
+        _direction: DmaDirection,
+    ) -> Result<DmaMapHandle, DmaError> {
+        // 用户态缓冲区不在 IOMMU 映射范围内，统一使用池内的反弹缓冲区
+        let layout = Layout::from_size_align(size.get(), align)?;
+        let (virt, iova) = self.alloc(dma_mask, layout)?;
+        unsafe {
+            core::ptr::copy_nonoverlapping(addr.as_ptr(), virt.as_ptr(), size.get());
+        }
+        Ok(unsafe { DmaMapHandle::new(addr, iova, layout, Some(virt)) })
+    }
+
+    unsafe fn unmap_single(&self, handle: DmaMapHandle) {
+        if let Some(virt) = handle.alloc_virt() {
+            self.dealloc(virt, handle.layout().size());
+        }
+    }
+
+    unsafe fn alloc_coherent(&self, dma_mask: u64, layout: Layout) -> Option<DmaHandle> {
+        let (virt, iova) = self.alloc(dma_mask, layout).ok()?;
+        Some(unsafe { DmaHandle::new(virt, iova, layout) })
+    }
+
+    unsafe fn dealloc_coherent(&self, handle: DmaHandle) {
+        self.dealloc(handle.as_ptr(), handle.layout().size());
+    }
+}
+
+impl KernelOp for VfioKernel {
+    fn delay(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+
+    fn now(&self) -> Duration {
+        crate::backend::std_timer::now()
+    }
+
+    fn wake_at(&self, deadline: Duration, waker: &core::task::Waker) {
+        crate::backend::std_timer::wake_at(deadline, waker);
+    }
+}
+
+fn mmap_anon(size: usize, extra_flags: i32) -> io::Result<NonNull<u8>> {
+    let ptr = unsafe {
+        libc::mmap(
+            core::ptr::null_mut(),
+            size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_POPULATE | extra_flags,
+            -1,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(NonNull::new(ptr.cast()).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FreeList;
+
+    #[test]
+    fn free_list_alloc_and_merge() {
+        let mut list = FreeList::new(0x10000);
+        let a = list.alloc(0x100, 0x40).unwrap();
+        let b = list.alloc(0x1000, 0x1000).unwrap();
+        let c = list.alloc(0x10, 0x40).unwrap();
+        assert_eq!(a, 0);
+        assert_eq!(b, 0x1000);
+        // 对齐留下的空洞会被后续的小分配复用
+        assert_eq!(c, 0x100);
+
+        list.dealloc(b, 0x1000);
+        list.dealloc(a, 0x100);
+        list.dealloc(c, 0x10);
+        assert_eq!(list.free_bytes(), 0x10000);
+        assert_eq!(list.alloc(0x10000, 1), Some(0));
+        assert_eq!(list.alloc(1, 1), None);
+    }
+}
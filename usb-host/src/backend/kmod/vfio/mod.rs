@@ -0,0 +1,335 @@
+//! 通过 Linux VFIO 在用户态运行 xHCI 后端
+//!
+//! 仅用于开发调试：把一个物理 xHCI 控制器绑定到 `vfio-pci`，
+//! 即可在普通 Linux 进程中运行与裸机相同的驱动代码，出错时无需重启开发板。
+//!
+//! - MMIO：`mmap` BAR0
+//! - DMA：一块映射到 IOMMU 的大页内存池，见 [`VfioKernel`]
+//! - 中断：MSI-X / MSI / INTx 通过 eventfd 投递，见 [`VfioIrq`]
+//!
+//! ```bash
+//! echo 0000:03:00.0 > /sys/bus/pci/devices/0000:03:00.0/driver/unbind
+//! echo vfio-pci > /sys/bus/pci/devices/0000:03:00.0/driver_override
+//! echo 0000:03:00.0 > /sys/bus/pci/drivers_probe
+//! ```
+
+mod dma;
+mod sys;
+
+use core::{ptr::NonNull, time::Duration};
+use std::{
+    ffi::CString,
+    fs::{File, OpenOptions},
+    io,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::fs::FileExt,
+    },
+    path::Path,
+};
+
+pub use dma::VfioKernel;
+use sys::*;
+
+use super::XhciConfig;
+use crate::{Mmio, USBHost, err::Result};
+
+/// VFIO 后端参数
+#[derive(Debug, Clone)]
+pub struct VfioConfig {
+    /// DMA 内存池大小，按 2MiB 向上取整
+    pub dma_pool_size: usize,
+    /// DMA 内存池在 IOMMU 中的起始地址，需位于控制器的 DMA 掩码之内
+    pub dma_iova: u64,
+    pub xhci: XhciConfig,
+}
+
+impl Default for VfioConfig {
+    fn default() -> Self {
+        Self {
+            dma_pool_size: 64 * 1024 * 1024,
+            // 低于 4GiB，兼容不支持 64 位地址（AC64 = 0）的控制器
+            dma_iova: 0x1000_0000,
+            xhci: XhciConfig::default(),
+        }
+    }
+}
+
+/// 绑定到 `vfio-pci` 的 PCI 设备
+pub struct VfioPci {
+    _container: File,
+    _group: File,
+    device: File,
+    bar0: NonNull<u8>,
+    bar0_size: usize,
+    kernel: &'static VfioKernel,
+}
+
+// BAR0 映射只通过 `Mmio` 交给驱动访问
+unsafe impl Send for VfioPci {}
+unsafe impl Sync for VfioPci {}
+
+impl VfioPci {
+    /// 打开 PCI 地址为 `bdf`（如 `0000:03:00.0`）的设备
+    pub fn open(bdf: &str, config: &VfioConfig) -> io::Result<Self> {
+        let group_link = Path::new("/sys/bus/pci/devices")
+            .join(bdf)
+            .join("iommu_group");
+        let group_id = std::fs::read_link(&group_link)?
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(str::to_owned)
+            .ok_or_else(|| io::Error::other("invalid iommu_group link"))?;
+
+        let container = open_rw("/dev/vfio/vfio")?;
+        if unsafe { libc::ioctl(container.as_raw_fd(), VFIO_GET_API_VERSION) } != VFIO_API_VERSION {
+            return Err(io::Error::other("unsupported VFIO API version"));
+        }
+
+        let group = open_rw(format!("/dev/vfio/{group_id}"))?;
+        let mut status = VfioGroupStatus {
+            argsz: size_of::<VfioGroupStatus>() as _,
+            ..Default::default()
+        };
+        ioctl(&group, VFIO_GROUP_GET_STATUS, &mut status)?;
+        if status.flags & VFIO_GROUP_FLAGS_VIABLE == 0 {
+            return Err(io::Error::other(format!(
+                "IOMMU group {group_id} is not viable, bind all devices in it to vfio-pci"
+            )));
+        }
+        ioctl(&group, VFIO_GROUP_SET_CONTAINER, &mut container.as_raw_fd())?;
+
+        let iommu = [VFIO_TYPE1V2_IOMMU, VFIO_TYPE1_IOMMU]
+            .into_iter()
+            .find(|&ty| unsafe { libc::ioctl(container.as_raw_fd(), VFIO_CHECK_EXTENSION, ty) } > 0)
+            .ok_or_else(|| io::Error::other("no supported IOMMU type"))?;
+        if unsafe { libc::ioctl(container.as_raw_fd(), VFIO_SET_IOMMU, iommu) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let name = CString::new(bdf).map_err(io::Error::other)?;
+        let fd = unsafe { libc::ioctl(group.as_raw_fd(), VFIO_GROUP_GET_DEVICE_FD, name.as_ptr()) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let device = unsafe { File::from_raw_fd(fd) };
+        if unsafe { libc::ioctl(device.as_raw_fd(), VFIO_DEVICE_RESET) } < 0 {
+            debug!(
+                "VFIO device reset not supported: {}",
+                io::Error::last_os_error()
+            );
+        }
+
+        let bar0 = region_info(&device, VFIO_PCI_BAR0_REGION_INDEX)?;
+        if bar0.flags & VFIO_REGION_INFO_FLAG_MMAP == 0 {
+            return Err(io::Error::other("BAR0 does not support mmap"));
+        }
+        let bar0_size = bar0.size as usize;
+        let ptr = unsafe {
+            libc::mmap(
+                core::ptr::null_mut(),
+                bar0_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                device.as_raw_fd(),
+                bar0.offset as _,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let kernel = Box::leak(Box::new(VfioKernel::new(
+            &container,
+            config.dma_iova,
+            config.dma_pool_size,
+        )?));
+
+        let pci = Self {
+            _container: container,
+            _group: group,
+            device,
+            bar0: NonNull::new(ptr.cast()).unwrap(),
+            bar0_size,
+            kernel,
+        };
+        pci.update_command(|cmd| cmd | PCI_COMMAND_MEMORY | PCI_COMMAND_MASTER)?;
+
+        info!("VFIO: opened {bdf} (IOMMU group {group_id}), BAR0 {bar0_size:#x} bytes");
+        Ok(pci)
+    }
+
+    /// BAR0 的映射地址
+    pub fn mmio(&self) -> Mmio {
+        self.bar0
+    }
+
+    /// 映射到该设备 IOMMU 的 DMA 内存池
+    pub fn kernel(&self) -> &'static VfioKernel {
+        self.kernel
+    }
+
+    /// 为设备配置中断，依次尝试 MSI-X、MSI、INTx
+    pub fn enable_irq(&self) -> io::Result<VfioIrq> {
+        for index in [
+            VFIO_PCI_MSIX_IRQ_INDEX,
+            VFIO_PCI_MSI_IRQ_INDEX,
+            VFIO_PCI_INTX_IRQ_INDEX,
+        ] {
+            let mut info = VfioIrqInfo {
+                argsz: size_of::<VfioIrqInfo>() as _,
+                index,
+                ..Default::default()
+            };
+            if ioctl(&self.device, VFIO_DEVICE_GET_IRQ_INFO, &mut info).is_err() || info.count == 0
+            {
+                continue;
+            }
+
+            let eventfd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+            if eventfd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let eventfd = unsafe { OwnedFd::from_raw_fd(eventfd) };
+            let mut set = VfioIrqSet {
+                argsz: size_of::<VfioIrqSet>() as _,
+                flags: VFIO_IRQ_SET_DATA_EVENTFD | VFIO_IRQ_SET_ACTION_TRIGGER,
+                index,
+                start: 0,
+                count: 1,
+                data: eventfd.as_raw_fd(),
+            };
+            if let Err(e) = ioctl(&self.device, VFIO_DEVICE_SET_IRQS, &mut set) {
+                debug!("VFIO: IRQ index {index} unavailable: {e}");
+                continue;
+            }
+
+            let intx = index == VFIO_PCI_INTX_IRQ_INDEX;
+            self.update_command(|cmd| {
+                if intx {
+                    cmd & !PCI_COMMAND_INTX_DISABLE
+                } else {
+                    cmd | PCI_COMMAND_INTX_DISABLE
+                }
+            })?;
+
+            debug!("VFIO: using IRQ index {index}");
+            return Ok(VfioIrq {
+                eventfd,
+                device: self.device.try_clone()?,
+                intx,
+            });
+        }
+        Err(io::Error::other("no usable interrupt"))
+    }
+
+    fn update_command(&self, f: impl FnOnce(u16) -> u16) -> io::Result<()> {
+        let config = region_info(&self.device, VFIO_PCI_CONFIG_REGION_INDEX)?;
+        let mut buf = [0u8; 2];
+        self.device
+            .read_exact_at(&mut buf, config.offset + PCI_COMMAND)?;
+        let cmd = f(u16::from_le_bytes(buf));
+        self.device
+            .write_all_at(&cmd.to_le_bytes(), config.offset + PCI_COMMAND)
+    }
+}
+
+impl Drop for VfioPci {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.bar0.as_ptr().cast(), self.bar0_size) };
+    }
+}
+
+/// 设备中断，每次中断到来时 eventfd 计数加一
+///
+/// 在独立线程中循环调用 [`VfioIrq::wait`]，返回后调用 [`crate::EventHandler::handle_event`]。
+pub struct VfioIrq {
+    eventfd: OwnedFd,
+    device: File,
+    intx: bool,
+}
+
+impl VfioIrq {
+    /// 等待中断，`timeout` 为 `None` 时一直等待；超时返回 `false`
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        let mut pfd = libc::pollfd {
+            fd: self.eventfd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        let ret = unsafe { libc::poll(&mut pfd, 1, timeout) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if ret == 0 {
+            return Ok(false);
+        }
+
+        let mut count = 0u64;
+        let ret = unsafe {
+            libc::read(
+                self.eventfd.as_raw_fd(),
+                (&mut count as *mut u64).cast(),
+                size_of::<u64>(),
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(true)
+    }
+
+    /// 中断处理完成后调用；INTx 由 VFIO 自动屏蔽，需要手动解除
+    pub fn ack(&self) -> io::Result<()> {
+        if !self.intx {
+            return Ok(());
+        }
+        let mut set = VfioIrqSet {
+            argsz: (size_of::<VfioIrqSet>() - size_of::<i32>()) as _,
+            flags: VFIO_IRQ_SET_DATA_NONE | VFIO_IRQ_SET_ACTION_UNMASK,
+            index: VFIO_PCI_INTX_IRQ_INDEX,
+            start: 0,
+            count: 1,
+            data: 0,
+        };
+        ioctl(&self.device, VFIO_DEVICE_SET_IRQS, &mut set)
+    }
+}
+
+impl USBHost {
+    /// 通过 VFIO 打开 PCI 地址为 `bdf` 的 xHCI 控制器
+    ///
+    /// 设备映射在进程退出前一直保留。返回的 [`VfioIrq`] 用于驱动事件循环。
+    pub fn new_vfio(bdf: &str, config: VfioConfig) -> Result<(USBHost, VfioIrq)> {
+        let pci: &'static VfioPci = Box::leak(Box::new(
+            VfioPci::open(bdf, &config).map_err(|e| anyhow!("VFIO open {bdf}: {e}"))?,
+        ));
+        let irq = pci
+            .enable_irq()
+            .map_err(|e| anyhow!("VFIO irq {bdf}: {e}"))?;
+        let host = USBHost::new_xhci_with_config(pci.mmio(), pci.kernel(), config.xhci)?;
+        Ok((host, irq))
+    }
+}
+
+fn open_rw(path: impl AsRef<Path>) -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).open(path)
+}
+
+fn ioctl<T>(file: &impl AsRawFd, request: libc::c_ulong, arg: &mut T) -> io::Result<()> {
+    if unsafe { libc::ioctl(file.as_raw_fd(), request, arg as *mut T) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn region_info(device: &File, index: u32) -> io::Result<VfioRegionInfo> {
+    let mut info = VfioRegionInfo {
+        argsz: size_of::<VfioRegionInfo>() as _,
+        index,
+        ..Default::default()
+    };
+    ioctl(device, VFIO_DEVICE_GET_REGION_INFO, &mut info)?;
+    Ok(info)
+}
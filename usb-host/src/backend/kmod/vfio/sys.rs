@@ -0,0 +1,111 @@
+//! `linux/vfio.h` 中用到的常量与结构体
+
+#![allow(dead_code)]
+
+use libc::c_ulong;
+
+const VFIO_TYPE: c_ulong = b';' as c_ulong;
+const VFIO_BASE: c_ulong = 100;
+
+const fn vfio_io(nr: c_ulong) -> c_ulong {
+    (VFIO_TYPE << 8) | (VFIO_BASE + nr)
+}
+
+pub const VFIO_API_VERSION: i32 = 0;
+pub const VFIO_TYPE1_IOMMU: c_ulong = 1;
+pub const VFIO_TYPE1V2_IOMMU: c_ulong = 3;
+
+pub const VFIO_GET_API_VERSION: c_ulong = vfio_io(0);
+pub const VFIO_CHECK_EXTENSION: c_ulong = vfio_io(1);
+pub const VFIO_SET_IOMMU: c_ulong = vfio_io(2);
+pub const VFIO_GROUP_GET_STATUS: c_ulong = vfio_io(3);
+pub const VFIO_GROUP_SET_CONTAINER: c_ulong = vfio_io(4);
+pub const VFIO_GROUP_GET_DEVICE_FD: c_ulong = vfio_io(6);
+pub const VFIO_DEVICE_GET_REGION_INFO: c_ulong = vfio_io(8);
+pub const VFIO_DEVICE_GET_IRQ_INFO: c_ulong = vfio_io(9);
+pub const VFIO_DEVICE_SET_IRQS: c_ulong = vfio_io(10);
+pub const VFIO_DEVICE_RESET: c_ulong = vfio_io(11);
+pub const VFIO_IOMMU_MAP_DMA: c_ulong = vfio_io(13);
+pub const VFIO_IOMMU_UNMAP_DMA: c_ulong = vfio_io(14);
+
+pub const VFIO_GROUP_FLAGS_VIABLE: u32 = 1 << 0;
+
+pub const VFIO_REGION_INFO_FLAG_MMAP: u32 = 1 << 2;
+
+pub const VFIO_DMA_MAP_FLAG_READ: u32 = 1 << 0;
+pub const VFIO_DMA_MAP_FLAG_WRITE: u32 = 1 << 1;
+
+pub const VFIO_IRQ_SET_DATA_NONE: u32 = 1 << 0;
+pub const VFIO_IRQ_SET_DATA_EVENTFD: u32 = 1 << 2;
+pub const VFIO_IRQ_SET_ACTION_UNMASK: u32 = 1 << 4;
+pub const VFIO_IRQ_SET_ACTION_TRIGGER: u32 = 1 << 5;
+
+pub const VFIO_PCI_BAR0_REGION_INDEX: u32 = 0;
+pub const VFIO_PCI_CONFIG_REGION_INDEX: u32 = 7;
+
+pub const VFIO_PCI_INTX_IRQ_INDEX: u32 = 0;
+pub const VFIO_PCI_MSI_IRQ_INDEX: u32 = 1;
+pub const VFIO_PCI_MSIX_IRQ_INDEX: u32 = 2;
+
+pub const PCI_COMMAND: u64 = 0x04;
+pub const PCI_COMMAND_MEMORY: u16 = 1 << 1;
+pub const PCI_COMMAND_MASTER: u16 = 1 << 2;
+pub const PCI_COMMAND_INTX_DISABLE: u16 = 1 << 10;
+
+#[repr(C)]
+#[derive(Default)]
+pub struct VfioGroupStatus {
+    pub argsz: u32,
+    pub flags: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct VfioRegionInfo {
+    pub argsz: u32,
+    pub flags: u32,
+    pub index: u32,
+    pub cap_offset: u32,
+    pub size: u64,
+    pub offset: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct VfioIrqInfo {
+    pub argsz: u32,
+    pub flags: u32,
+    pub index: u32,
+    pub count: u32,
+}
+
+/// `vfio_irq_set`，`data` 只携带一个 eventfd
+#[repr(C)]
+#[derive(Default)]
+pub struct VfioIrqSet {
+    pub argsz: u32,
+    pub flags: u32,
+    pub index: u32,
+    pub start: u32,
+    pub count: u32,
+    pub data: i32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct VfioIommuType1DmaMap {
+    pub argsz: u32,
+    pub flags: u32,
+    pub vaddr: u64,
+    pub iova: u64,
+    pub size: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct VfioIommuType1DmaUnmap {
+    pub argsz: u32,
+    pub flags: u32,
+    pub iova: u64,
+    pub size: u64,
+}
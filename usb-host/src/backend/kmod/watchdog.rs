@@ -0,0 +1,139 @@
+//! 控制器时钟看门狗
+//!
+//! xHCI 运行时 MFINDEX 每 125us 加一，停止增长说明控制器时钟已丢失（如 RK3588 上
+//! PHY PLL 失锁），此时所有传输都会停滞而不报错。看门狗由调用者周期性调用
+//! [`USBHost::poll_watchdog`](crate::USBHost::poll_watchdog) 驱动，或交给
+//! [`USBHost::spawn_monitor`](crate::USBHost::spawn_monitor) 的后台任务。
+
+use core::time::Duration;
+
+/// 看门狗配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// MFINDEX 持续不变多久判定为停滞，应大于调用者的检查周期
+    pub timeout: Duration,
+    /// 判定停滞后自动重新初始化 PHY，不支持的控制器只上报事件
+    pub auto_reinit: bool,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(100),
+            auto_reinit: false,
+        }
+    }
+}
+
+/// 看门狗事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogEvent {
+    /// MFINDEX 停止增长，每次停滞只报告一次
+    HostStalled {
+        mfindex: u16,
+        stalled_for: Duration,
+        /// 已自动重新初始化 PHY，原有设备将经热插拔事件报告拔出并重新枚举
+        reinit: bool,
+    },
+    /// MFINDEX 恢复增长
+    Recovered,
+}
+
+/// 根据 MFINDEX 采样判断控制器是否停滞
+///
+/// MFINDEX 为 14 位，约 2.048s 回绕一次，检查周期需明显小于该值。
+#[derive(Debug)]
+pub(crate) struct StallDetector {
+    timeout: Duration,
+    /// 最近一次变化时的取值与时间
+    last: Option<(u16, Duration)>,
+    stalled: bool,
+}
+
+impl StallDetector {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            last: None,
+            stalled: false,
+        }
+    }
+
+    /// `mfindex` 为 `None` 表示控制器未运行，此时重新开始计时
+    pub fn sample(&mut self, mfindex: Option<u16>, now: Duration) -> Option<WatchdogEvent> {
+        let Some(mfindex) = mfindex else {
+            self.last = None;
+            return None;
+        };
+        match self.last {
+            Some((last, since)) if last == mfindex => {
+                let stalled_for = now.saturating_sub(since);
+                if self.stalled || stalled_for < self.timeout {
+                    return None;
+                }
+                self.stalled = true;
+                Some(WatchdogEvent::HostStalled {
+                    mfindex,
+                    stalled_for,
+                    reinit: false,
+                })
+            }
+            _ => {
+                self.last = Some((mfindex, now));
+                if core::mem::take(&mut self.stalled) {
+                    Some(WatchdogEvent::Recovered)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn reports_stall_once_and_recovery() {
+        let mut detector = StallDetector::new(ms(100));
+        assert_eq!(detector.sample(Some(10), ms(0)), None);
+        assert_eq!(detector.sample(Some(10), ms(99)), None);
+        assert!(matches!(
+            detector.sample(Some(10), ms(100)),
+            Some(WatchdogEvent::HostStalled { mfindex: 10, .. })
+        ));
+        assert_eq!(detector.sample(Some(10), ms(300)), None);
+        assert_eq!(
+            detector.sample(Some(11), ms(310)),
+            Some(WatchdogEvent::Recovered)
+        );
+        assert_eq!(detector.sample(Some(12), ms(320)), None);
+    }
+
+    #[test]
+    fn halted_controller_restarts_timer() {
+        let mut detector = StallDetector::new(ms(100));
+        detector.sample(Some(5), ms(0));
+        assert_eq!(detector.sample(None, ms(50)), None);
+        assert_eq!(detector.sample(Some(5), ms(120)), None);
+        assert_eq!(detector.sample(Some(5), ms(200)), None);
+        assert!(detector.sample(Some(5), ms(220)).is_some());
+    }
+
+    #[test]
+    fn frozen_mfindex_across_suspend_is_not_a_stall() {
+        let mut detector = StallDetector::new(ms(100));
+        detector.sample(Some(7), ms(0));
+        // 挂起期间不检查，MFINDEX 停在原值
+        for t in (10..1000).step_by(10) {
+            assert_eq!(detector.sample(None, ms(t)), None);
+        }
+        assert_eq!(detector.sample(Some(7), ms(1000)), None);
+        assert_eq!(detector.sample(Some(8), ms(1010)), None);
+    }
+}
@@ -9,7 +9,7 @@ use xhci::{
 };
 
 use super::{reg::XhciRegisters, ring::SendRing};
-use crate::{err::ConvertXhciError, osal::Kernel, queue::Finished};
+use crate::{backend::kmod::perf::PerfStats, err::ConvertXhciError, osal::Kernel, queue::Finished};
 
 #[derive(Clone)]
 pub struct CommandRing(Arc<Mutex<Inner>>);
@@ -19,9 +19,15 @@ impl CommandRing {
         direction: crate::osal::DmaDirection,
         dma: &Kernel,
         reg: Arc<RwLock<XhciRegisters>>,
+        stats: Arc<PerfStats>,
     ) -> crate::err::Result<Self> {
         let ring = SendRing::new(direction, dma)?;
-        let inner = Inner { ring, reg };
+        let inner = Inner {
+            ring,
+            reg,
+            kernel: dma.clone(),
+            stats,
+        };
         Ok(Self(Arc::new(Mutex::new(inner))))
     }
 
@@ -44,7 +50,18 @@ impl CommandRing {
         &mut self,
         trb: command::Allowed,
     ) -> Result<CommandCompletion, TransferError> {
-        let fur = {
+        let res = self.cmd_completion(trb).await;
+        match res.completion_code() {
+            Ok(code) => code.to_result()?,
+            Err(e) => Err(TransferError::Other(anyhow!("Command failed: {e:?}")))?,
+        }
+
+        Ok(res)
+    }
+
+    /// 提交命令并返回完成事件，由调用方检查完成码
+    pub async fn cmd_completion(&mut self, trb: command::Allowed) -> CommandCompletion {
+        let (fur, start, kernel, stats) = {
             let mut inner = self.0.lock();
             let trb_addr = inner.ring.enque_command(trb);
             let fur = inner.ring.take_finished_future(trb_addr);
@@ -54,21 +71,24 @@ impl CommandRing {
                 .write()
                 .doorbell
                 .write_volatile_at(0, doorbell::Register::default());
-            fur
+            inner.stats.doorbell();
+            (
+                fur,
+                inner.kernel.now(),
+                inner.kernel.clone(),
+                inner.stats.clone(),
+            )
         };
 
         let res = fur.await;
-
-        match res.completion_code() {
-            Ok(code) => code.to_result()?,
-            Err(e) => Err(TransferError::Other(anyhow!("Command failed: {e:?}")))?,
-        }
-
-        Ok(res)
+        stats.command(kernel.now().saturating_sub(start));
+        res
     }
 }
 
 struct Inner {
     ring: SendRing<CommandCompletion>,
     reg: Arc<RwLock<XhciRegisters>>,
+    kernel: Kernel,
+    stats: Arc<PerfStats>,
 }
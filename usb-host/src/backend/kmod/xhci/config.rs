@@ -0,0 +1,234 @@
+use core::time::Duration;
+
+use usb_if::{descriptor::EndpointType, host::hub::Speed};
+
+use crate::BusAddr;
+use crate::backend::kmod::IommuDomain;
+
+/// xHCI 控制器初始化配置
+#[derive(Debug, Default, Clone)]
+pub struct XhciConfig {
+    /// Scratchpad 缓冲区分配策略
+    pub scratchpad: ScratchpadPolicy,
+    /// 主中断器的中断节流策略
+    pub imod: ImodPolicy,
+    /// 控制器所在的 IOMMU 域，设置后交给控制器的地址均为该域中的 IOVA
+    pub iommu: Option<&'static dyn IommuDomain>,
+    /// 初始化进度回调，每个阶段开始与结束时各调用一次
+    ///
+    /// 初始化卡住时最后一个 [`InitProgress::Begin`] 即为卡住的阶段，无需打开调试日志。
+    pub on_init_progress: Option<fn(InitProgress)>,
+    /// 已连接设备的枚举方式
+    pub enumeration: EnumerationMode,
+    /// 主事件环的段数，为 0 时使用单段，超过控制器的 ERST Max 时截断
+    ///
+    /// 每段两页。高带宽等时传输（UVC、音频）在一次中断内产生大量事件，
+    /// 事件环写满后控制器会暂停写入直到软件推进 ERDP，可增加段数避免丢失事件。
+    pub event_ring_segments: usize,
+    /// 启用的中断器数，为 0 时只使用主中断器，超过 HCSPARAMS1 中的 MaxIntrs 时截断
+    ///
+    /// 每个中断器有独立的事件环与节流间隔，`imod` 对所有中断器生效。MSI-X 下集成方按
+    /// [`EventHandler::interrupter_count`](crate::EventHandler::interrupter_count)
+    /// 分配向量，把各向量绑定到不同 CPU。
+    pub interrupters: u16,
+    /// 传输事件投递到哪个中断器
+    pub interrupter_map: InterrupterMap,
+    /// 各端点传输环的大小
+    ///
+    /// 环空间不足时提交返回 [`TransferError::QueueFull`](usb_if::err::TransferError::QueueFull)。
+    /// 高吞吐的批量设备（大容量存储、网卡）同时挂起的请求较多，可为其分配更大的环。
+    pub transfer_ring: TransferRingSize,
+}
+
+/// 传输环的页数
+///
+/// 每个端点一个单段传输环，段不能跨越 64KB 边界，超过 64KB 的部分截断。
+/// 启用流的端点各流的环大小固定，不受此配置影响。
+#[derive(Debug, Default, Clone, Copy)]
+pub enum TransferRingSize {
+    /// 每个端点两页
+    #[default]
+    Default,
+    /// 所有端点使用相同的页数
+    Pages(usize),
+    /// SuperSpeed 及以上的批量端点使用 64KB，其余端点两页
+    HighThroughput,
+    /// 由回调按槽位、端点地址与类型决定页数，返回 0 时为两页
+    Custom(fn(slot_id: u8, endpoint: u8, ty: EndpointType) -> usize),
+}
+
+impl TransferRingSize {
+    /// 默认页数
+    pub(crate) const DEFAULT_PAGES: usize = 2;
+
+    /// 端点传输环的字节数，`endpoint` 为端点地址，控制端点为 0
+    pub(crate) fn bytes(
+        &self,
+        slot_id: u8,
+        endpoint: u8,
+        ty: EndpointType,
+        speed: Speed,
+        page_size: usize,
+    ) -> usize {
+        const MAX_BYTES: usize = 0x10000;
+        let pages = match *self {
+            Self::Default => Self::DEFAULT_PAGES,
+            Self::Pages(pages) => pages,
+            Self::HighThroughput
+                if ty == EndpointType::Bulk
+                    && matches!(speed, Speed::SuperSpeed | Speed::SuperSpeedPlus) =>
+            {
+                return MAX_BYTES.max(page_size);
+            }
+            Self::HighThroughput => Self::DEFAULT_PAGES,
+            Self::Custom(f) => f(slot_id, endpoint, ty),
+        };
+        let pages = if pages == 0 {
+            Self::DEFAULT_PAGES
+        } else {
+            pages
+        };
+        // 页大于 64KB 时仍至少一页，由对齐保证不跨界
+        pages
+            .saturating_mul(page_size)
+            .min(MAX_BYTES.max(page_size))
+    }
+}
+
+/// 传输事件的中断器分配
+///
+/// 命令完成与端口事件总是投递到 0 号中断器。
+#[derive(Debug, Default, Clone, Copy)]
+pub enum InterrupterMap {
+    /// 全部投递到 0 号中断器
+    #[default]
+    Primary,
+    /// 按槽位轮流分配，同一设备的端点共享一个中断器
+    PerSlot,
+    /// 由回调按槽位与端点的设备上下文索引（DCI）决定，超出已启用数目时投递到 0 号中断器
+    Custom(fn(slot_id: u8, dci: u8) -> u16),
+}
+
+impl InterrupterMap {
+    pub(crate) fn target(&self, slot_id: u8, dci: u8, count: u16) -> u16 {
+        let target = match self {
+            Self::Primary => 0,
+            Self::PerSlot => slot_id as u16 % count.max(1),
+            Self::Custom(f) => f(slot_id, dci),
+        };
+        if target < count { target } else { 0 }
+    }
+}
+
+/// 已连接设备的枚举方式
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EnumerationMode {
+    /// [`USBHost::probe_devices`](crate::USBHost::probe_devices) 为每个已连接的设备分配地址并读取描述符
+    #[default]
+    Eager,
+    /// 只记录根端口上有设备连接，调用
+    /// [`USBHost::enumerate_port`](crate::USBHost::enumerate_port) 时才完成枚举
+    ///
+    /// 外部 Hub 下游的设备仍在 Hub 枚举后一并枚举，避免多个设备同时处于默认地址。
+    /// 热插拔事件不受影响，新插入的设备总是立即枚举。
+    Lazy,
+}
+
+/// 控制器初始化阶段，按执行顺序排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitStage {
+    /// 遍历扩展能力，从 BIOS 取得控制器所有权
+    ExtCaps,
+    /// 停止控制器并复位，等待 CNR 清零
+    Reset,
+    /// 设置 MaxSlotsEn 并分配设备上下文
+    Slots,
+    /// 写入 DCBAAP
+    Dcbaa,
+    /// 写入命令环地址
+    CommandRing,
+    /// 配置主中断器与事件环
+    Irq,
+    /// 分配 scratchpad 缓冲区
+    Scratchpads,
+    /// 置位 Run/Stop 并等待控制器运行
+    Start,
+}
+
+/// 初始化进度事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitProgress {
+    /// 阶段开始
+    Begin(InitStage),
+    /// 阶段成功结束，`elapsed` 为该阶段耗时
+    End { stage: InitStage, elapsed: Duration },
+}
+
+/// 中断节流（IMOD）策略，间隔单位为 250ns
+///
+/// 间隔越小事件延迟越低（HID），越大中断次数越少（高带宽等时传输）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImodPolicy {
+    /// 固定间隔
+    Fixed(u16),
+    /// 根据事件速率在 `[min, max]` 之间调整，使中断速率不超过 `target_irq_rate`（次/秒）
+    Adaptive {
+        min: u16,
+        max: u16,
+        target_irq_rate: u32,
+    },
+}
+
+impl Default for ImodPolicy {
+    fn default() -> Self {
+        Self::Fixed(0x1F)
+    }
+}
+
+/// Scratchpad 缓冲区分配策略
+///
+/// 控制器通过 HCSPARAMS2 声明所需的 scratchpad 页数，部分控制器需要上百页，
+/// 可能在初始化时耗尽较小的 DMA 池。
+#[derive(Debug, Default, Clone)]
+pub enum ScratchpadPolicy {
+    /// 按控制器需求从 DMA 池逐页分配
+    #[default]
+    Allocate,
+    /// 从 DMA 池分配，但页数超过上限时初始化失败
+    Limit(usize),
+    /// 使用集成方预留的物理连续区域
+    ///
+    /// 区域需按页对齐、长度不小于 `所需页数 * 页大小`，并在控制器运行期间保持有效，
+    /// 仅由控制器访问。
+    Reserved { bus_addr: BusAddr, size: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_ring_bytes() {
+        let bulk =
+            |size: TransferRingSize, speed| size.bytes(1, 0x81, EndpointType::Bulk, speed, 0x1000);
+        assert_eq!(bulk(TransferRingSize::Default, Speed::SuperSpeed), 0x2000);
+        assert_eq!(bulk(TransferRingSize::Pages(4), Speed::High), 0x4000);
+        assert_eq!(bulk(TransferRingSize::Pages(0), Speed::High), 0x2000);
+        assert_eq!(bulk(TransferRingSize::Pages(64), Speed::High), 0x10000);
+        assert_eq!(
+            bulk(TransferRingSize::HighThroughput, Speed::SuperSpeed),
+            0x10000
+        );
+        assert_eq!(bulk(TransferRingSize::HighThroughput, Speed::High), 0x2000);
+
+        let custom = TransferRingSize::Custom(|_, ep, _| if ep == 0x02 { 8 } else { 0 });
+        assert_eq!(
+            custom.bytes(1, 0x02, EndpointType::Bulk, Speed::High, 0x1000),
+            0x8000
+        );
+        assert_eq!(
+            custom.bytes(1, 0, EndpointType::Control, Speed::High, 0x1000),
+            0x2000
+        );
+    }
+}
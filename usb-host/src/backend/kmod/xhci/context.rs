@@ -1,10 +1,20 @@
 use alloc::vec::Vec;
 
 use dma_api::{DArray, DBox, DmaDirection};
-use xhci::context::{Device32Byte, Device64Byte, Input32Byte, Input64Byte, InputHandler};
+use xhci::{
+    context::{Device32Byte, Device64Byte, Input32Byte, Input64Byte, InputHandler},
+    ring::trb::event::TransferEvent,
+};
 
-use super::SlotId;
-use crate::{err::*, osal::Kernel};
+use super::{ScratchpadPolicy, SlotId, ring::SendRing};
+use crate::{
+    backend::kmod::{
+        le::{DmaBoxLe, DmaLe},
+        mem::MemTag,
+    },
+    err::*,
+    osal::Kernel,
+};
 
 pub struct DeviceContextList {
     pub dcbaa: DArray<u64>,
@@ -30,6 +40,7 @@ pub(crate) enum ContextData {
 
 impl ContextData {
     pub fn new(is_64: bool, dma: &Kernel) -> core::result::Result<Self, HostError> {
+        let dma = &dma.with_tag(MemTag::Context);
         if is_64 {
             Ok(ContextData::Context64(Context64 {
                 // out: DBox::zero_with_align(dma_mask as _, dma_api::Direction::FromDevice, 64)?,
@@ -55,12 +66,12 @@ impl ContextData {
             ContextData::Context32(ctx) => {
                 let mut input = Input32Byte::new_32byte();
                 f(&mut input);
-                ctx.input.write(input);
+                ctx.input.write_le(input);
             }
             ContextData::Context64(ctx) => {
                 let mut input = Input64Byte::new_64byte();
                 f(&mut input);
-                ctx.input.write(input);
+                ctx.input.write_le(input);
             }
         }
     }
@@ -71,14 +82,14 @@ impl ContextData {
     {
         match self {
             ContextData::Context32(ctx) => {
-                let mut input = ctx.input.read();
+                let mut input = ctx.input.read_le();
                 f(&mut input);
-                ctx.input.write(input);
+                ctx.input.write_le(input);
             }
             ContextData::Context64(ctx) => {
-                let mut input = ctx.input.read();
+                let mut input = ctx.input.read_le();
                 f(&mut input);
-                ctx.input.write(input);
+                ctx.input.write_le(input);
             }
         }
     }
@@ -118,6 +129,7 @@ impl DeviceContextList {
         // let dcbaa = DVec::zeros(dma_mask as _, 256, 0x1000, dma_api::Direction::ToDevice)
         //     .map_err(|_| USBError::NoMemory)?;
         let dcbaa = dma
+            .with_tag(MemTag::Context)
             .array_zero_with_align(256, dma.page_size(), DmaDirection::ToDevice)
             .map_err(|_| USBError::NoMemory)?;
         Ok(Self { dcbaa, max_slots })
@@ -128,9 +140,86 @@ impl DeviceContextList {
             Err(USBError::SlotLimitReached)?;
         }
         let ctx = ContextData::new(is_64, dma)?;
-        self.dcbaa.set(slot_id.as_usize(), ctx.dcbaa());
+        self.dcbaa.set_le(slot_id.as_usize(), ctx.dcbaa());
         Ok(ctx)
     }
+
+    /// 槽禁用后清除 DCBAA 表项，控制器不再访问该槽的设备上下文
+    pub fn release_ctx(&mut self, slot_id: SlotId) {
+        self.dcbaa.set_le(slot_id.as_usize(), 0);
+    }
+}
+
+/// 每个流环的 TRB 数，UAS 每个流同一时刻只有一个命令在途，一页足够
+const STREAM_RING_LEN: usize = 256;
+/// 每个流占用一个环，为限制内存占用最多分配的流数量
+const MAX_STREAMS: usize = 31;
+/// Stream Context Type：主流数组中的传输环
+const SCT_PRIMARY_TR: u64 = 1;
+
+/// 线性主流上下文数组（xHCI 4.12.2）及各流的传输环
+///
+/// 数组长度为 2 的幂，第 0 项保留，流 ID `i` 对应 `rings[i - 1]`。
+pub struct StreamContextArray {
+    entries: DArray<[u64; 2]>,
+    rings: Vec<SendRing<TransferEvent>>,
+}
+
+unsafe impl Send for StreamContextArray {}
+unsafe impl Sync for StreamContextArray {}
+
+impl StreamContextArray {
+    /// 分配至多 `streams` 个流，实际数量向下对齐为 `2^n - 1` 且不超过 31
+    ///
+    /// `max_psa_size` 为 HCCPARAMS1.MaxPSASize，数组最多 `2^(MaxPSASize + 1)` 项。
+    pub fn new(streams: usize, max_psa_size: u8, dma: &Kernel) -> Result<Self> {
+        let max_entries = 1usize << (max_psa_size as usize + 1).min(16);
+        let entries_len = (streams.min(MAX_STREAMS) + 1).min(max_entries);
+        // 向下取 2 的幂，避免分配设备用不到的流
+        let entries_len = 1usize << entries_len.ilog2();
+        if entries_len < 2 {
+            return Err(USBError::NotSupported);
+        }
+
+        let mut entries = dma
+            .with_tag(MemTag::Context)
+            .array_zero_with_align(entries_len, 64, DmaDirection::Bidirectional)
+            .map_err(|_| USBError::NoMemory)?;
+        let mut rings = Vec::with_capacity(entries_len - 1);
+        for i in 1..entries_len {
+            let ring = SendRing::new_with_len(STREAM_RING_LEN, DmaDirection::Bidirectional, dma)?;
+            // 新环的 cycle 为 1，DCS 置位
+            entries.set_le(i, [ring.bus_addr().raw() | SCT_PRIMARY_TR << 1 | 1, 0]);
+            rings.push(ring);
+        }
+        Ok(Self { entries, rings })
+    }
+
+    pub fn bus_addr(&self) -> u64 {
+        self.entries.dma_addr().as_u64()
+    }
+
+    /// 可用的流数量
+    pub fn streams(&self) -> u16 {
+        self.rings.len() as u16
+    }
+
+    /// 端点上下文的 MaxPStreams，数组长度为 `2^(MaxPStreams + 1)`
+    pub fn max_primary_streams(&self) -> u8 {
+        (self.entries.len().ilog2() - 1) as u8
+    }
+
+    pub fn ring(&self, stream_id: u16) -> Option<&SendRing<TransferEvent>> {
+        self.rings.get((stream_id as usize).checked_sub(1)?)
+    }
+
+    pub fn ring_mut(&mut self, stream_id: u16) -> Option<&mut SendRing<TransferEvent>> {
+        self.rings.get_mut((stream_id as usize).checked_sub(1)?)
+    }
+
+    pub fn rings(&self) -> impl Iterator<Item = &SendRing<TransferEvent>> {
+        self.rings.iter()
+    }
 }
 
 pub struct ScratchpadBufferArray {
@@ -139,45 +228,59 @@ pub struct ScratchpadBufferArray {
 }
 
 impl ScratchpadBufferArray {
-    pub fn new(entries: usize, dma: &Kernel) -> Result<Self> {
-        // let mut entries_vec = DVec::zeros(
-        //     dma_mask as _,
-        //     entries,
-        //     64,
-        //     dma_api::Direction::Bidirectional,
-        // )
-        // .map_err(|_| USBError::NoMemory)?;
+    pub fn new(entries: usize, policy: &ScratchpadPolicy, dma: &Kernel) -> Result<Self> {
+        let page_size = dma.page_size();
+        let dma = &dma.with_tag(MemTag::Scratchpad);
+
+        if let ScratchpadPolicy::Limit(max) = policy
+            && entries > *max
+        {
+            return Err(anyhow!(
+                "xHCI requests {entries} scratchpad pages, exceeding the configured limit of {max}"
+            )
+            .into());
+        }
 
         let mut entries_vec = dma
             .array_zero_with_align(entries, 64, DmaDirection::Bidirectional)
             .map_err(|_| USBError::NoMemory)?;
 
-        // let pages: Vec<DVec<u8>> = (0..entries_vec.len())
-        //     .map(|_| {
-        //         DVec::<u8>::zeros(
-        //             dma_mask as _,
-        //             0x1000,
-        //             0x1000,
-        //             dma_api::Direction::Bidirectional,
-        //         )
-        //         .map_err(|_| USBError::NoMemory)
-        //     })
-        //     .try_collect()?;
-        let mut pages: Vec<DArray<u8>> = Vec::with_capacity(entries_vec.len());
-        for _ in 0..entries_vec.len() {
-            let page = dma
-                .array_zero_with_align(
-                    dma.page_size(),
-                    dma.page_size(),
-                    DmaDirection::Bidirectional,
-                )
-                .map_err(|_| USBError::NoMemory)?;
-            pages.push(page);
-        }
-
-        // 将每个页面的地址写入到 entries 数组中
-        for (i, page) in pages.iter().enumerate() {
-            entries_vec.set(i, page.dma_addr().as_u64());
+        let mut pages: Vec<DArray<u8>> = Vec::new();
+        match policy {
+            ScratchpadPolicy::Allocate | ScratchpadPolicy::Limit(_) => {
+                pages.reserve(entries);
+                for i in 0..entries {
+                    let page = dma
+                        .array_zero_with_align(page_size, page_size, DmaDirection::Bidirectional)
+                        .map_err(|_| {
+                            anyhow!(
+                                "failed to allocate scratchpad page {}/{entries} ({page_size} bytes each)",
+                                i + 1
+                            )
+                        })?;
+                    entries_vec.set_le(i, page.dma_addr().as_u64());
+                    pages.push(page);
+                }
+            }
+            ScratchpadPolicy::Reserved { bus_addr, size } => {
+                let base = bus_addr.raw();
+                let need = entries * page_size;
+                if !base.is_multiple_of(page_size as u64) {
+                    return Err(anyhow!(
+                        "reserved scratchpad region {base:#x} is not aligned to {page_size:#x}"
+                    )
+                    .into());
+                }
+                if *size < need {
+                    return Err(anyhow!(
+                        "reserved scratchpad region is {size:#x} bytes, xHCI needs {need:#x} ({entries} pages)"
+                    )
+                    .into());
+                }
+                for i in 0..entries {
+                    entries_vec.set_le(i, base + (i * page_size) as u64);
+                }
+            }
         }
 
         Ok(Self {
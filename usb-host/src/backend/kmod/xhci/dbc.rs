@@ -0,0 +1,618 @@
+//! xHCI Debug Capability（DbC）
+//!
+//! DbC 把控制器的一个根端口切换为设备模式，对端主机看到的是一个带一对批量端点的
+//! 调试设备（Linux 上为 `usb_debug` / `ttyUSB`），可以用作裸机目标的调试串口。
+//! DbC 与主机控制器的运行状态无关，不需要先创建 [`USBHost`](crate::USBHost)；
+//! 被 DbC 占用的端口在 DbC 启用期间不再报告给主机栈。
+//!
+//! 整个驱动以轮询方式工作，不依赖中断与异步执行器，适合在早期启动阶段使用。
+
+use alloc::collections::VecDeque;
+use core::time::Duration;
+
+use dma_api::{DArray, DmaDirection};
+use mbarrier::mb;
+use xhci::{
+    ExtendedCapability,
+    extended_capabilities::{List, debug::Debug},
+    ring::trb::{
+        event::{Allowed as EventAllowed, CompletionCode, TransferEvent},
+        transfer::{Allowed, Normal},
+    },
+};
+
+use super::{
+    event::EventRing,
+    reg::{MemMapper, XhciRegisters},
+    ring::Ring,
+};
+use crate::{
+    KernelOp, Mmio,
+    backend::kmod::{
+        le::{DmaLe, LeWords},
+        mem::MemTag,
+    },
+    err::*,
+    osal::Kernel,
+};
+
+/// DbC 批量端点的最大包长
+const MAX_PACKET_SIZE: usize = 1024;
+/// 单个 OUT 传输的最大长度
+const OUT_BUFFER_SIZE: usize = 4096;
+/// 每个字符串描述符占用的空间
+const STRING_SLOT_SIZE: usize = 64;
+/// 传输事件中 OUT / IN 端点的编号
+const EP_ID_OUT: u8 = 2;
+const EP_ID_IN: u8 = 3;
+/// 门铃目标
+const DOORBELL_OUT: u8 = 0;
+const DOORBELL_IN: u8 = 1;
+/// 端点上下文中的端点类型
+const EP_TYPE_BULK_OUT: u32 = 2;
+const EP_TYPE_BULK_IN: u32 = 6;
+
+/// DbC 设备描述
+#[derive(Debug, Clone)]
+pub struct DbcConfig {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub device_revision: u16,
+    /// 设备描述符中的协议，Linux `usb_debug` 驱动匹配 1（GNU 远程调试）
+    pub protocol: u8,
+    /// 字符串描述符，超过 31 个 UTF-16 字符的部分被截断
+    pub manufacturer: &'static str,
+    pub product: &'static str,
+    pub serial: &'static str,
+    /// 发送缓冲区大小，缓冲区满时 [`XhciDbc::write`] 只接受能放下的部分
+    pub tx_buffer_size: usize,
+}
+
+impl Default for DbcConfig {
+    fn default() -> Self {
+        Self {
+            vendor_id: 0x1d6b,
+            product_id: 0x0010,
+            device_revision: 0x0010,
+            protocol: 1,
+            manufacturer: "CrabUSB",
+            product: "xHCI Debug Capability",
+            serial: "0001",
+            tx_buffer_size: 16 * 1024,
+        }
+    }
+}
+
+/// DbC 状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbcState {
+    /// 未启用
+    Disabled,
+    /// 已启用，等待对端主机连接
+    Enabled,
+    /// 已连接，等待对端主机完成枚举
+    Connected,
+    /// 对端主机已配置设备，可以收发数据
+    Configured,
+}
+
+#[repr(C, align(64))]
+#[derive(Clone, Copy)]
+struct DbcInfoContext {
+    string0: u64,
+    manufacturer: u64,
+    product: u64,
+    serial: u64,
+    /// 依次为四个字符串描述符的长度，每个一字节
+    length: u32,
+    _reserved: [u32; 7],
+}
+
+impl LeWords for DbcInfoContext {
+    fn le(self) -> Self {
+        Self {
+            string0: self.string0.le(),
+            manufacturer: self.manufacturer.le(),
+            product: self.product.le(),
+            serial: self.serial.le(),
+            length: self.length.le(),
+            _reserved: self._reserved,
+        }
+    }
+}
+
+/// DbC 上下文：信息上下文与 OUT、IN 端点上下文，各 64 字节
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DbcContext {
+    info: DbcInfoContext,
+    ep_out: [u32; 16],
+    ep_in: [u32; 16],
+}
+
+impl LeWords for DbcContext {
+    fn le(self) -> Self {
+        Self {
+            info: self.info.le(),
+            ep_out: self.ep_out.le(),
+            ep_in: self.ep_in.le(),
+        }
+    }
+}
+
+/// xHCI Debug Capability 驱动
+///
+/// 通过 [`XhciDbc::poll`] 推进状态、处理事件并提交传输，[`XhciDbc::write`] 与
+/// [`XhciDbc::read`] 只读写软件缓冲区，不会阻塞。实现了 [`core::fmt::Write`]，
+/// 可直接作为日志输出。
+pub struct XhciDbc {
+    reg: Debug<MemMapper>,
+    kernel: Kernel,
+    config: DbcConfig,
+    event_ring: EventRing,
+    ring_out: Ring,
+    ring_in: Ring,
+    ctx: DArray<DbcContext>,
+    _strings: DArray<u8>,
+    buf_out: DArray<u8>,
+    buf_in: DArray<u8>,
+    out_in_flight: bool,
+    in_in_flight: bool,
+    tx: VecDeque<u8>,
+    rx: VecDeque<u8>,
+    state: DbcState,
+}
+
+unsafe impl Send for XhciDbc {}
+
+impl XhciDbc {
+    /// 查找控制器的 Debug Capability，控制器不支持时返回 [`USBError::NotSupported`]
+    pub fn new(mmio: Mmio, kernel: &'static dyn KernelOp) -> Result<Self> {
+        Self::new_with_config(mmio, kernel, DbcConfig::default())
+    }
+
+    pub fn new_with_config(
+        mmio: Mmio,
+        kernel: &'static dyn KernelOp,
+        config: DbcConfig,
+    ) -> Result<Self> {
+        let reg = XhciRegisters::new(mmio);
+        let hccparams1 = reg.capability.hccparams1.read_volatile();
+        let mut list = unsafe { List::new(reg.mmio_base, hccparams1, MemMapper) }
+            .ok_or(USBError::NotSupported)?;
+        let dbc = (&mut list)
+            .into_iter()
+            .map_while(|cap| cap.ok())
+            .find_map(|cap| match cap {
+                ExtendedCapability::Debug(dbc) => Some(dbc),
+                _ => None,
+            })
+            .ok_or(USBError::NotSupported)?;
+
+        let dma_mask = if hccparams1.addressing_capability() {
+            u64::MAX
+        } else {
+            u32::MAX as u64
+        };
+        let kernel = Kernel::new(dma_mask, kernel);
+
+        let event_ring = EventRing::new(&kernel)?;
+        let ring_out = Ring::new(true, DmaDirection::Bidirectional, &kernel)?;
+        let ring_in = Ring::new(true, DmaDirection::Bidirectional, &kernel)?;
+
+        let mut strings = kernel
+            .with_tag(MemTag::Context)
+            .array_zero_with_align::<u8>(
+                4 * STRING_SLOT_SIZE,
+                STRING_SLOT_SIZE,
+                DmaDirection::ToDevice,
+            )
+            .map_err(|_| USBError::NoMemory)?;
+        let mut length = 0u32;
+        strings.write_with(4 * STRING_SLOT_SIZE, |buf| {
+            let (string0, rest) = buf.split_at_mut(STRING_SLOT_SIZE);
+            // 只支持英语（美国）
+            string0[..4].copy_from_slice(&[4, 3, 0x09, 0x04]);
+            length = 4;
+            for (i, (slot, s)) in rest
+                .chunks_mut(STRING_SLOT_SIZE)
+                .zip([config.manufacturer, config.product, config.serial])
+                .enumerate()
+            {
+                let len = string_descriptor(slot, s);
+                length |= (len as u32) << (8 * (i + 1));
+            }
+        });
+        let strings_addr = strings.dma_addr().as_u64();
+
+        let mut ctx = kernel
+            .with_tag(MemTag::Context)
+            .array_zero_with_align::<DbcContext>(1, 64, DmaDirection::Bidirectional)
+            .map_err(|_| USBError::NoMemory)?;
+        let max_burst = dbc.dcctrl.read_volatile().debug_max_burst_size() as u32;
+        ctx.set_le(
+            0,
+            DbcContext {
+                info: DbcInfoContext {
+                    string0: strings_addr,
+                    manufacturer: strings_addr + STRING_SLOT_SIZE as u64,
+                    product: strings_addr + 2 * STRING_SLOT_SIZE as u64,
+                    serial: strings_addr + 3 * STRING_SLOT_SIZE as u64,
+                    length,
+                    _reserved: [0; 7],
+                },
+                ep_out: ep_context(EP_TYPE_BULK_OUT, max_burst, &ring_out),
+                ep_in: ep_context(EP_TYPE_BULK_IN, max_burst, &ring_in),
+            },
+        );
+
+        let buf_out = kernel
+            .with_tag(MemTag::Transfer)
+            .array_zero_with_align(OUT_BUFFER_SIZE, 64, DmaDirection::ToDevice)
+            .map_err(|_| USBError::NoMemory)?;
+        let buf_in = kernel
+            .with_tag(MemTag::Transfer)
+            .array_zero_with_align(MAX_PACKET_SIZE, 64, DmaDirection::FromDevice)
+            .map_err(|_| USBError::NoMemory)?;
+
+        Ok(Self {
+            reg: dbc,
+            kernel,
+            event_ring,
+            ring_out,
+            ring_in,
+            ctx,
+            _strings: strings,
+            buf_out,
+            buf_in,
+            out_in_flight: false,
+            in_in_flight: false,
+            tx: VecDeque::with_capacity(config.tx_buffer_size),
+            rx: VecDeque::new(),
+            state: DbcState::Disabled,
+            config,
+        })
+    }
+
+    /// 启用 DbC，之后对端主机可以在被占用的端口上枚举到调试设备
+    pub fn enable(&mut self) -> Result {
+        if self.state != DbcState::Disabled {
+            return Ok(());
+        }
+        let erstba = self.event_ring.erstba();
+        let erdp = self.event_ring.erdp();
+        let erstsz = self.event_ring.len() as u16;
+        let ctx = self.ctx.dma_addr().as_u64();
+        let config = &self.config;
+
+        self.reg.dcerstsz.update_volatile(|r| r.set(erstsz));
+        self.reg.dcerstba.update_volatile(|r| r.set(erstba));
+        self.reg
+            .dcerdp
+            .update_volatile(|r| r.set_dequeue_pointer(erdp));
+        self.reg.dccp.update_volatile(|r| r.set(ctx));
+        self.reg.dcddi1.update_volatile(|r| {
+            r.set_dbc_protocol(config.protocol);
+            r.set_vendor_id(config.vendor_id);
+        });
+        self.reg.dcddi2.update_volatile(|r| {
+            r.set_product_id(config.product_id);
+            r.set_device_revision(config.device_revision);
+        });
+        mb();
+        self.reg.dcctrl.update_volatile(|r| {
+            r.set_link_status_event_enable();
+            r.set_debug_capability_enable();
+        });
+
+        // DCE 读回 1 后端口才会切换到 DbC
+        for _ in 0..1000 {
+            if self.reg.dcctrl.read_volatile().debug_capability_enable() {
+                self.state = DbcState::Enabled;
+                debug!(
+                    "DbC enabled on port {}",
+                    self.reg.dcst.read_volatile().debug_port_number()
+                );
+                return Ok(());
+            }
+            self.kernel.delay(Duration::from_micros(10));
+        }
+        Err(USBError::Timeout)
+    }
+
+    /// 关闭 DbC，端口交还给主机栈，缓冲区中未发送的数据被丢弃
+    pub fn disable(&mut self) {
+        self.reg.dcctrl.update_volatile(|r| {
+            r.clear_debug_capability_enable();
+        });
+        self.state = DbcState::Disabled;
+        self.tx.clear();
+        // 再次启用时按初始状态重新写入 ERSTBA/ERDP
+        self.event_ring.reset();
+        self.reset_endpoints();
+    }
+
+    /// 丢弃在途传输，把两个批量环与端点上下文恢复到初始状态
+    ///
+    /// 对端断开或 DbC 关闭后端点不再运行，下次进入 Configured 时控制器从 DbC 上下文
+    /// 重新读取出队指针，必须与软件的入队位置一致。
+    fn reset_endpoints(&mut self) {
+        self.ring_out.reset();
+        self.ring_in.reset();
+        self.out_in_flight = false;
+        self.in_in_flight = false;
+        let Some(mut ctx) = self.ctx.read_le(0) else {
+            return;
+        };
+        ctx.ep_out = reset_ep_context(&ctx.ep_out, &self.ring_out);
+        ctx.ep_in = reset_ep_context(&ctx.ep_in, &self.ring_in);
+        self.ctx.set_le(0, ctx);
+    }
+
+    pub fn state(&self) -> DbcState {
+        self.state
+    }
+
+    /// 处理端口变化与传输事件，并在已配置时提交缓冲区中的数据
+    pub fn poll(&mut self) -> DbcState {
+        if self.state == DbcState::Disabled {
+            return self.state;
+        }
+
+        let portsc = self.reg.dcportsc.read_volatile();
+        if portsc.connect_status_change()
+            || portsc.port_reset_change()
+            || portsc.port_link_status_change()
+            || portsc.port_config_error_change()
+        {
+            // 写回读到的值以清除变化位
+            self.reg.dcportsc.write_volatile(portsc);
+        }
+
+        let ctrl = self.reg.dcctrl.read_volatile();
+        let state = if !portsc.current_connect_status() {
+            DbcState::Enabled
+        } else if ctrl.dbc_run() {
+            DbcState::Configured
+        } else {
+            DbcState::Connected
+        };
+        if ctrl.dbc_run_change() {
+            self.reg.dcctrl.write_volatile(ctrl);
+        }
+        let disconnected = state == DbcState::Enabled
+            && matches!(self.state, DbcState::Connected | DbcState::Configured);
+        if state != self.state {
+            debug!("DbC state {:?} -> {state:?}", self.state);
+            self.state = state;
+        }
+
+        self.handle_events();
+        if disconnected {
+            self.reset_endpoints();
+        }
+
+        if self.state == DbcState::Configured {
+            self.submit_in();
+            self.submit_out();
+        }
+        self.state
+    }
+
+    /// 写入发送缓冲区，返回接受的字节数
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let n = data
+            .len()
+            .min(self.config.tx_buffer_size.saturating_sub(self.tx.len()));
+        self.tx.extend(&data[..n]);
+        n
+    }
+
+    /// 从接收缓冲区读取，返回读取的字节数
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.rx.len());
+        for (dst, src) in buf.iter_mut().zip(self.rx.drain(..n)) {
+            *dst = src;
+        }
+        n
+    }
+
+    /// 轮询直到发送缓冲区清空，未配置时返回 [`USBError::NotInitialized`]
+    pub fn flush(&mut self) -> Result {
+        while !self.tx.is_empty() || self.out_in_flight {
+            if self.poll() != DbcState::Configured {
+                return Err(USBError::NotInitialized);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_events(&mut self) {
+        let mut handled = false;
+        while let Some(event) = self.event_ring.next() {
+            handled = true;
+            match event {
+                EventAllowed::TransferEvent(ev) => self.handle_transfer(ev),
+                other => trace!("DbC event {other:?}"),
+            }
+        }
+        if handled {
+            let erdp = self.event_ring.erdp();
+            self.reg
+                .dcerdp
+                .update_volatile(|r| r.set_dequeue_pointer(erdp));
+        }
+    }
+
+    fn handle_transfer(&mut self, ev: TransferEvent) {
+        let ok = matches!(
+            ev.completion_code(),
+            Ok(CompletionCode::Success | CompletionCode::ShortPacket)
+        );
+        if !ok {
+            warn!(
+                "DbC transfer on ep {} failed: {:?}",
+                ev.endpoint_id(),
+                ev.completion_code()
+            );
+        }
+        let residual = ev.trb_transfer_length() as usize;
+        match ev.endpoint_id() {
+            EP_ID_OUT => {
+                self.out_in_flight = false;
+            }
+            EP_ID_IN => {
+                self.in_in_flight = false;
+                if ok {
+                    let len = MAX_PACKET_SIZE.saturating_sub(residual);
+                    self.buf_in
+                        .read_with(len, |data| self.rx.extend(data.iter().copied()));
+                }
+            }
+            id => warn!("DbC event for unknown endpoint {id}"),
+        }
+    }
+
+    fn submit_in(&mut self) {
+        if self.in_in_flight {
+            return;
+        }
+        let trb = normal_trb(self.buf_in.dma_addr().as_u64(), MAX_PACKET_SIZE);
+        self.ring_in.enque_transfer(trb);
+        self.in_in_flight = true;
+        mb();
+        self.ring_doorbell(DOORBELL_IN);
+    }
+
+    fn submit_out(&mut self) {
+        if self.out_in_flight || self.tx.is_empty() {
+            return;
+        }
+        let len = self.tx.len().min(OUT_BUFFER_SIZE);
+        let tx = &mut self.tx;
+        self.buf_out.write_with(len, |buf| {
+            for (dst, src) in buf.iter_mut().zip(tx.drain(..len)) {
+                *dst = src;
+            }
+        });
+        let trb = normal_trb(self.buf_out.dma_addr().as_u64(), len);
+        self.ring_out.enque_transfer(trb);
+        self.out_in_flight = true;
+        mb();
+        self.ring_doorbell(DOORBELL_OUT);
+    }
+
+    fn ring_doorbell(&mut self, target: u8) {
+        self.reg
+            .dcdb
+            .update_volatile(|r| r.set_doorbell_target(target));
+    }
+}
+
+impl Drop for XhciDbc {
+    fn drop(&mut self) {
+        // 关闭后控制器不再访问上下文与环
+        self.disable();
+    }
+}
+
+impl core::fmt::Write for XhciDbc {
+    /// 缓冲区满时丢弃多余的数据，日志输出不应因调试口阻塞
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write(s.as_bytes());
+        self.poll();
+        Ok(())
+    }
+}
+
+fn ep_context(ep_type: u32, max_burst: u32, ring: &Ring) -> [u32; 16] {
+    let mut ctx = [0; 16];
+    ctx[1] = (ep_type << 3) | (max_burst << 8) | ((MAX_PACKET_SIZE as u32) << 16);
+    let deq = ring.bus_addr().raw() | ring.cycle as u64;
+    ctx[2] = deq as u32;
+    ctx[3] = (deq >> 32) as u32;
+    ctx
+}
+
+/// 保留端点类型、突发与包长，清除控制器写回的状态，出队指针指向 `ring` 的入队位置
+fn reset_ep_context(ctx: &[u32; 16], ring: &Ring) -> [u32; 16] {
+    let mut reset = [0; 16];
+    reset[1] = ctx[1];
+    let deq = ring.trb_bus_addr(ring.i).raw() | ring.cycle as u64;
+    reset[2] = deq as u32;
+    reset[3] = (deq >> 32) as u32;
+    reset
+}
+
+fn normal_trb(addr: u64, len: usize) -> Allowed {
+    let mut trb = Normal::new();
+    trb.set_data_buffer_pointer(addr)
+        .set_trb_transfer_length(len as _)
+        .set_interrupt_on_short_packet()
+        .set_interrupt_on_completion();
+    Allowed::Normal(trb)
+}
+
+/// 把 `s` 编码为 UTF-16LE 字符串描述符写入 `slot`，返回描述符长度
+fn string_descriptor(slot: &mut [u8], s: &str) -> usize {
+    let mut len = 2;
+    for unit in s.encode_utf16() {
+        if len + 2 > slot.len() {
+            break;
+        }
+        slot[len..len + 2].copy_from_slice(&unit.to_le_bytes());
+        len += 2;
+    }
+    slot[0] = len as u8;
+    slot[1] = 3;
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::kmod::test_kernel::HeapKernel;
+
+    #[test]
+    fn context_layout() {
+        assert_eq!(size_of::<DbcInfoContext>(), 64);
+        assert_eq!(size_of::<DbcContext>(), 192);
+    }
+
+    #[test]
+    fn reset_points_context_at_ring_start() {
+        let kernel = HeapKernel::kernel();
+        let mut ring = Ring::new_with_len(8, true, DmaDirection::Bidirectional, &kernel).unwrap();
+        let ctx = ep_context(EP_TYPE_BULK_IN, 2, &ring);
+        // 跨过环尾后循环位翻转
+        for _ in 0..9 {
+            ring.enque_transfer(normal_trb(0, 1));
+        }
+        assert!(!ring.cycle);
+
+        let mut stale = ctx;
+        // 控制器写回的端点状态（Halted）
+        stale[0] = 2;
+        ring.reset();
+        assert_eq!(reset_ep_context(&stale, &ring), ctx);
+        assert!(
+            ring.trbs
+                .read_le(0)
+                .unwrap()
+                .to_raw()
+                .iter()
+                .all(|&w| w == 0)
+        );
+    }
+
+    #[test]
+    fn string_descriptor_truncates() {
+        let mut slot = [0u8; STRING_SLOT_SIZE];
+        assert_eq!(string_descriptor(&mut slot, "ab"), 6);
+        assert_eq!(slot[..6], [6, 3, b'a', 0, b'b', 0]);
+
+        let long = "x".repeat(40);
+        assert_eq!(string_descriptor(&mut slot, &long), 64);
+        assert_eq!(slot[0], 64);
+    }
+}
@@ -1,7 +1,3 @@
-use xhci::ring::trb::transfer;
-
-use usb_if::transfer::Direction;
-
 define_int_type!(SlotId, u8);
 
 impl SlotId {
@@ -27,24 +23,3 @@ impl Dci {
         self.0 as usize
     }
 }
-
-pub(crate) trait DirectionExt {
-    fn to_xhci_direction(&self) -> transfer::Direction;
-    fn to_xhci_transfer_type(&self) -> transfer::TransferType;
-}
-
-impl DirectionExt for Direction {
-    fn to_xhci_direction(&self) -> transfer::Direction {
-        match self {
-            Direction::Out => transfer::Direction::Out,
-            Direction::In => transfer::Direction::In,
-        }
-    }
-
-    fn to_xhci_transfer_type(&self) -> transfer::TransferType {
-        match self {
-            Direction::Out => transfer::TransferType::Out,
-            Direction::In => transfer::TransferType::In,
-        }
-    }
-}
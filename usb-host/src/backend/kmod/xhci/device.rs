@@ -1,4 +1,5 @@
 use alloc::collections::BTreeMap;
+use core::time::Duration;
 
 use alloc::{sync::Arc, vec::Vec};
 
@@ -10,7 +11,8 @@ use usb_if::endpoint::EndpointInfo;
 use usb_if::err::USBError;
 use usb_if::{
     descriptor::{
-        ConfigurationDescriptor, DescriptorType, DeviceDescriptor, EndpointDescriptor, EndpointType,
+        ConfigurationDescriptor, DescriptorType, DeviceDescriptor, DeviceQualifierDescriptor,
+        EndpointDescriptor, EndpointType, OtherSpeedConfigurationDescriptor,
     },
     host::{ControlSetup, hub::Speed},
     transfer::{Recipient, RequestType},
@@ -18,10 +20,11 @@ use usb_if::{
 use xhci::ring::trb::command;
 
 use super::{
-    SlotId, Xhci,
+    InterrupterMap, SlotId, TransferRingSize, Xhci,
     cmd::CommandRing,
-    context::ContextData,
+    context::{ContextData, StreamContextArray},
     endpoint::{Endpoint as XhciEndpoint, EndpointDescriptorExt},
+    isoch::{IsochCaps, IsochSchedule},
     parse_default_max_packet_size_from_port_speed,
     reg::SlotBell,
     transfer::TransferResultHandler,
@@ -29,11 +32,14 @@ use super::{
 use crate::DeviceAddressInfo;
 use crate::backend::ty::HubParams;
 
-use crate::osal::Kernel;
+use crate::osal::{DmaConfig, Kernel};
 use crate::{
     backend::{
         Dci,
-        ty::{DeviceOp, ep::Endpoint},
+        ty::{
+            DeviceOp,
+            ep::{Endpoint, declared_interval},
+        },
     },
     err::Result,
 };
@@ -46,11 +52,21 @@ pub struct Device {
     transfer_result_handler: TransferResultHandler,
     bell: Arc<Mutex<SlotBell>>,
     kernel: Kernel,
+    /// 传输缓冲区使用的 DMA 约束，由 `kernel` 按设备配置派生
+    transfer_dma: Kernel,
     current_config_value: Option<u8>,
     config_desc: Vec<ConfigurationDescriptor>,
+    qualifier: Option<DeviceQualifierDescriptor>,
+    other_speed_desc: Vec<ConfigurationDescriptor>,
     port_speed: Speed,
     eps: BTreeMap<u8, Endpoint>,
     cmd: CommandRing,
+    /// 控制器的 MaxPSASize，0 表示不支持流
+    max_psa_size: u8,
+    isoch_caps: IsochCaps,
+    transfer_ring: TransferRingSize,
+    interrupter_map: InterrupterMap,
+    interrupter_count: u16,
 }
 
 impl Device {
@@ -65,7 +81,6 @@ impl Device {
         let dma = host.kernel.clone();
         let ctx = host.dev_mut()?.new_ctx(slot_id, is_64, &dma)?;
         let bell = host.new_slot_bell(slot_id);
-        let bell = Arc::new(Mutex::new(bell));
         // let port_speed = host.port_speed(port);
         let desc = unsafe { core::mem::zeroed() };
 
@@ -75,20 +90,52 @@ impl Device {
             bell,
             ctrl_ep: None,
             desc,
+            transfer_dma: dma.clone(),
             kernel: dma,
             transfer_result_handler: host.transfer_result_handler.clone(),
             current_config_value: None,
             config_desc: vec![],
+            qualifier: None,
+            other_speed_desc: vec![],
             port_speed: Speed::Full,
             eps: BTreeMap::new(),
             cmd: host.cmd.clone(),
+            max_psa_size: host.max_psa_size(),
+            isoch_caps: host.isoch_caps(),
+            transfer_ring: host.transfer_ring(),
+            interrupter_map: host.interrupter_map(),
+            interrupter_count: host.interrupter_count(),
         })
     }
 
-    fn new_ep(&mut self, dci: Dci) -> Result<XhciEndpoint> {
-        let ep = XhciEndpoint::new(dci, &self.kernel, self.bell.clone())?;
-        self.transfer_result_handler
-            .register_queue(self.id.as_u8(), dci.as_u8(), ep.ring());
+    /// `address` 与 `ty` 为端点地址与类型，用于决定传输环大小
+    fn new_ep(&mut self, dci: Dci, address: u8, ty: EndpointType) -> Result<XhciEndpoint> {
+        let ring_bytes = self.transfer_ring.bytes(
+            self.id.as_u8(),
+            address,
+            ty,
+            self.port_speed,
+            self.kernel.page_size(),
+        );
+        let mut ep = XhciEndpoint::new(
+            dci,
+            ring_bytes,
+            &self.kernel,
+            self.bell.clone(),
+            self.cmd.clone(),
+        )?;
+        ep.set_transfer_dma(self.transfer_dma.clone());
+        ep.set_interrupter(self.interrupter_map.target(
+            self.id.as_u8(),
+            dci.as_u8(),
+            self.interrupter_count,
+        ));
+        self.transfer_result_handler.register_queue(
+            self.id.as_u8(),
+            dci.as_u8(),
+            ep.ring(),
+            ep.faults(),
+        );
 
         Ok(ep)
     }
@@ -106,7 +153,7 @@ impl Device {
         self.port_speed = info.port_speed;
         // let speed = info.port_speed.to_xhci_portsc_value();
 
-        let ep = self.new_ep(Dci::CTRL)?;
+        let ep = self.new_ep(Dci::CTRL, 0, EndpointType::Control)?;
         self.ctrl_ep = Some(Endpoint::new(EndpointInfo::control(), ep));
         self.address(host, info).await?;
         // self.dump_device_out();
@@ -130,6 +177,16 @@ impl Device {
             self.config_desc.push(config_desc);
         }
 
+        // Device Qualifier 只存在于支持高速的 USB 2.0 设备，这类设备运行在全速下时同样会返回。
+        // 仅支持全速的设备以 STALL 拒绝，控制端点随后自动复位，读取失败不影响枚举
+        if matches!(self.port_speed, Speed::High | Speed::Full)
+            && self.desc.usb_version >= 0x0200
+            && let Err(e) = self.read_other_speed_descriptors().await
+        {
+            debug!("No other-speed descriptors: {e:?}");
+            self.other_speed_desc.clear();
+        }
+
         // 设置配置为第一个配置（大多数设备只有一个配置）
         // 参考 USB 2.0 规范第 9.1.1 节和 u-boot 的 usb_set_configure_device
         if !self.config_desc.is_empty() {
@@ -343,17 +400,30 @@ impl Device {
         self.desc = self.control_endpoint_mut().get_device_descriptor().await?;
         Ok(())
     }
+    /// 读取 Device Qualifier 与全部 Other Speed Configuration 描述符
+    async fn read_other_speed_descriptors(&mut self) -> Result<()> {
+        let qualifier: DeviceQualifierDescriptor =
+            self.control_endpoint_mut().read_descriptor(0, 0).await?;
+        debug!("Device Qualifier: {qualifier:?}");
+
+        for i in 0..qualifier.num_configurations {
+            let desc: OtherSpeedConfigurationDescriptor =
+                self.control_endpoint_mut().read_descriptor(i, 0).await?;
+            self.other_speed_desc.push(desc.0);
+        }
+        self.qualifier = Some(qualifier);
+        Ok(())
+    }
+
     async fn get_device_descriptor_base(&mut self) -> Result<DeviceDescriptorBase> {
-        let mut data = vec![0u8; 8];
+        let mut data = vec![0u8; DeviceDescriptorBase::LEN];
 
         // DMA 传输
         self.control_endpoint_mut()
             .get_descriptor(DescriptorType::DEVICE, 0, 0, data.as_mut_slice())
             .await?;
 
-        let desc = unsafe { *(data.as_mut_slice().as_ptr() as *const DeviceDescriptorBase) };
-
-        Ok(desc)
+        DeviceDescriptorBase::parse(&data).ok_or_else(|| anyhow!("short device descriptor").into())
     }
 
     async fn get_configuration(&mut self) -> Result<u8> {
@@ -404,8 +474,57 @@ impl Device {
         Ok(())
     }
 
+    /// 通过 Configure Endpoint 命令丢弃接口的端点上下文，控制器随之停止这些端点
+    async fn _release_interface(&mut self, interface: u8, alternate: u8) -> Result {
+        let mut dcis = Vec::new();
+        for desc in self
+            .find_interface_endpoints(interface, alternate)?
+            .to_vec()
+        {
+            dcis.push(desc.dci());
+            self.eps.remove(&desc.address);
+        }
+
+        let mut bell = self.bell.lock();
+        let remaining: Vec<u8> = bell.active_dcis().collect();
+        let dropped: Vec<u8> = remaining
+            .iter()
+            .copied()
+            .filter(|dci| dcis.contains(dci))
+            .collect();
+        if dropped.is_empty() {
+            return Ok(());
+        }
+        let active_dcis = remaining
+            .iter()
+            .filter(|dci| !dropped.contains(dci))
+            .fold(0u32, |mask, dci| mask | 1 << dci);
+        bell.set_active_dcis(active_dcis);
+        drop(bell);
+
+        self.ctx.perper_change();
+        self.ctx.with_input(|input| {
+            let control_context = input.control_mut();
+            for &dci in &dropped {
+                control_context.set_drop_context_flag(dci as _);
+            }
+        });
+        mb();
+
+        self.cmd
+            .cmd_request(command::Allowed::ConfigureEndpoint(
+                *command::ConfigureEndpoint::default()
+                    .set_slot_id(self.id.into())
+                    .set_input_context_pointer(self.ctx.input_bus_addr()),
+            ))
+            .await?;
+        debug!("Interface {interface} released, dropped dcis {dropped:?}");
+        Ok(())
+    }
+
     async fn setup_all_endpoints(&mut self, interface: u8, alternate: u8) -> Result {
         let mut max_dci = 1;
+        let mut active_dcis = 1u32 << Dci::CTRL.as_u8();
         self.ctx.perper_change();
         self.eps.clear();
         self.ctx.with_input(|input| {
@@ -420,10 +539,11 @@ impl Device {
             .to_vec()
         {
             let dci = desc.dci();
+            active_dcis |= 1 << dci;
             if dci > max_dci {
                 max_dci = dci;
             }
-            let mut ep_raw = self.new_ep(dci.into())?;
+            let mut ep_raw = self.new_ep(dci.into(), desc.address, desc.transfer_type)?;
             let periodic_burst_size = match self.port_speed {
                 Speed::High
                     if matches!(
@@ -436,12 +556,49 @@ impl Device {
                 _ => 0,
             };
             ep_raw.configure_periodic(desc.max_packet_size as usize, periodic_burst_size);
-            let ring_addr = ep_raw.bus_addr();
-            self.eps
-                .insert(desc.address, Endpoint::new((&desc).into(), ep_raw));
-
             let xhci_interval =
                 self.calculate_xhci_interval(desc.interval, desc.transfer_type, desc.interval);
+            if desc.transfer_type == EndpointType::Isochronous {
+                ep_raw.configure_isoch(IsochSchedule::new(self.isoch_caps, xhci_interval));
+            }
+
+            let declared = declared_interval(self.port_speed, desc.transfer_type, desc.interval);
+            let encoded = Duration::from_micros(125 << xhci_interval);
+            let mut service_interval = declared.map(|_| encoded);
+
+            // 控制器只能以 2 的幂次编码周期，FS/LS 中断端点的 bInterval 会被向下取整，
+            // 此时由软件定时补足，保证按设备声明的周期轮询。
+            if desc.transfer_type == EndpointType::Interrupt
+                && let Some(declared) = declared
+                && declared > encoded
+            {
+                service_interval = Some(declared);
+                debug!(
+                    "ep {:#x}: declared interval {:?} > encoded {:?}, use software timer",
+                    desc.address, declared, encoded
+                );
+                ep_raw.set_soft_interval(declared);
+            }
+
+            let ring_addr = ep_raw.bus_addr();
+            let streams = self.alloc_streams(&desc);
+            if let Some(streams) = &streams {
+                self.transfer_result_handler.register_stream_queues(
+                    self.id.as_u8(),
+                    dci,
+                    core::iter::once(ep_raw.ring()).chain(streams.rings()),
+                );
+            }
+            let stream_ctx = streams
+                .as_ref()
+                .map(|s| (s.bus_addr(), s.max_primary_streams()));
+            if let Some(streams) = streams {
+                ep_raw.enable_streams(streams);
+            }
+            self.eps.insert(
+                desc.address,
+                Endpoint::new((&desc).into(), ep_raw).with_service_interval(service_interval),
+            );
 
             self.ctx.with_input(|input| {
                 let control_context = input.control_mut();
@@ -487,6 +644,14 @@ impl Device {
                 if let EndpointType::Isochronous = desc.transfer_type {
                     ep_mut.set_error_count(0);
                 }
+
+                // 启用流时 TR Dequeue Pointer 指向流上下文数组，DCS 不使用
+                if let Some((array_addr, max_pstreams)) = stream_ctx {
+                    ep_mut.set_tr_dequeue_pointer(array_addr);
+                    ep_mut.clear_dequeue_cycle_state();
+                    ep_mut.set_max_primary_streams(max_pstreams);
+                    ep_mut.set_linear_stream_array();
+                }
             });
         }
 
@@ -506,10 +671,40 @@ impl Device {
                     .set_input_context_pointer(self.ctx.input_bus_addr()),
             ))
             .await?;
+        self.bell.lock().set_active_dcis(active_dcis);
 
         Ok(())
     }
 
+    /// 为声明支持流的 SuperSpeed 批量端点分配流，控制器不支持或分配失败时退回单个环
+    fn alloc_streams(&self, desc: &EndpointDescriptor) -> Option<StreamContextArray> {
+        if desc.transfer_type != EndpointType::Bulk || desc.max_streams == 0 {
+            return None;
+        }
+        if self.max_psa_size == 0 {
+            debug!(
+                "ep {:#x}: controller does not support streams",
+                desc.address
+            );
+            return None;
+        }
+        match StreamContextArray::new(desc.max_streams as usize, self.max_psa_size, &self.kernel) {
+            Ok(streams) => {
+                debug!(
+                    "ep {:#x}: {} streams enabled (device supports {})",
+                    desc.address,
+                    streams.streams(),
+                    desc.max_streams
+                );
+                Some(streams)
+            }
+            Err(e) => {
+                warn!("ep {:#x}: allocate streams failed: {e:?}", desc.address);
+                None
+            }
+        }
+    }
+
     fn find_interface_endpoints(
         &self,
         interface: u8,
@@ -531,72 +726,51 @@ impl Device {
 
     /// 根据 XHCI 规范计算端点的 interval 值
     /// 参考 xHCI 规范第 6.2.3.6 节
+    ///
+    /// 返回值为指数形式，实际周期为 `2^interval * 125us`，取值范围 0..=15。
     fn calculate_xhci_interval(
         &self,
         binterval: u8,
         transfer_type: EndpointType,
         default: u8,
     ) -> u8 {
+        let hs_or_ss = matches!(
+            self.port_speed,
+            Speed::High | Speed::SuperSpeed | Speed::SuperSpeedPlus
+        );
         match transfer_type {
+            EndpointType::Isochronous | EndpointType::Interrupt if hs_or_ss => {
+                // HighSpeed, SuperSpeed, SuperSpeedPlus 周期端点
+                // bInterval 为指数：周期 = 2^(bInterval-1) * 125us
+                let interval = binterval.clamp(1, 16) - 1;
+                info!(
+                    "{:?} endpoint HS/SS: bInterval={} -> XHCI interval={}",
+                    transfer_type, binterval, interval
+                );
+                interval
+            }
             EndpointType::Isochronous => {
-                match self.port_speed {
-                    Speed::High | Speed::SuperSpeed | Speed::SuperSpeedPlus => {
-                        // HighSpeed, SuperSpeed, SuperSpeedPlus ISO 端点
-                        // Interval = max(1, min(16, bInterval))
-                        let interval = binterval.clamp(1, 16);
-                        info!(
-                            "ISO endpoint HS/SS: bInterval={} -> XHCI interval={}",
-                            binterval, interval
-                        );
-                        interval
-                    }
-                    _ => {
-                        // FullSpeed/LowSpeed ISO 端点
-                        // Interval = max(1, min(16, floor(log2(bInterval)) + 3))
-                        if binterval == 0 {
-                            1
-                        } else {
-                            // 计算 floor(log2(bInterval))
-                            let log2_binterval = 31 - (binterval as u32).leading_zeros() as u8 - 1;
-                            let interval = (log2_binterval + 3).clamp(1, 16);
-                            info!(
-                                "ISO endpoint FS/LS: bInterval={} -> log2={} -> XHCI interval={}",
-                                binterval, log2_binterval, interval
-                            );
-                            interval
-                        }
-                    }
-                }
+                // FullSpeed ISO 端点
+                // bInterval 为指数：周期 = 2^(bInterval-1) ms
+                let interval = binterval.clamp(1, 16) - 1 + 3;
+                let interval = interval.min(15);
+                info!(
+                    "ISO endpoint FS: bInterval={} -> XHCI interval={}",
+                    binterval, interval
+                );
+                interval
             }
             EndpointType::Interrupt => {
-                match self.port_speed {
-                    Speed::High | Speed::SuperSpeed | Speed::SuperSpeedPlus => {
-                        // HighSpeed, SuperSpeed, SuperSpeedPlus 中断端点
-                        // Interval = max(1, min(16, bInterval))
-                        let interval = binterval.clamp(1, 16);
-                        info!(
-                            "INT endpoint HS/SS: bInterval={} -> XHCI interval={}",
-                            binterval, interval
-                        );
-                        interval
-                    }
-                    _ => {
-                        // FullSpeed/LowSpeed 中断端点
-                        // Interval = max(1, min(16, floor(log2(bInterval)) + 3))
-                        if binterval == 0 {
-                            1
-                        } else {
-                            // 计算 floor(log2(bInterval))
-                            let log2_binterval = 31 - (binterval as u32).leading_zeros() as u8 - 1;
-                            let interval = (log2_binterval + 3).clamp(1, 16);
-                            info!(
-                                "INT endpoint FS/LS: bInterval={} -> log2={} -> XHCI interval={}",
-                                binterval, log2_binterval, interval
-                            );
-                            interval
-                        }
-                    }
-                }
+                // FullSpeed/LowSpeed 中断端点
+                // bInterval 单位为 ms (1..=255)
+                // Interval = floor(log2(bInterval * 8))，取值 3..=10
+                let frames = binterval.max(1) as u32;
+                let interval = (31 - (frames * 8).leading_zeros()) as u8;
+                info!(
+                    "INT endpoint FS/LS: bInterval={} -> XHCI interval={}",
+                    binterval, interval
+                );
+                interval
             }
             _ => {
                 // 控制和批量端点不使用 interval
@@ -672,6 +846,11 @@ impl DeviceOp for Device {
         &self.desc
     }
 
+    fn set_descriptors(&mut self, desc: DeviceDescriptor, configs: Vec<ConfigurationDescriptor>) {
+        self.desc = desc;
+        self.config_desc = configs;
+    }
+
     fn ctrl_ep_ref(&self) -> &Endpoint {
         self.control_endpoint()
     }
@@ -688,6 +867,14 @@ impl DeviceOp for Device {
         self._claim_interface(interface, alternate).boxed()
     }
 
+    fn release_interface<'a>(
+        &'a mut self,
+        interface: u8,
+        alternate: u8,
+    ) -> BoxFuture<'a, Result<()>> {
+        self._release_interface(interface, alternate).boxed()
+    }
+
     fn set_configuration<'a>(&'a mut self, configuration_value: u8) -> BoxFuture<'a, Result<()>> {
         self._set_configuration(configuration_value).boxed()
     }
@@ -696,6 +883,14 @@ impl DeviceOp for Device {
         &self.config_desc
     }
 
+    fn device_qualifier(&self) -> Option<&DeviceQualifierDescriptor> {
+        self.qualifier.as_ref()
+    }
+
+    fn other_speed_configurations(&self) -> &[ConfigurationDescriptor] {
+        &self.other_speed_desc
+    }
+
     fn endpoint(&mut self, desc: &usb_if::descriptor::EndpointDescriptor) -> Result<Endpoint> {
         let ep = self.eps.remove(&desc.address);
         ep.ok_or(USBError::NotFound)
@@ -704,4 +899,19 @@ impl DeviceOp for Device {
     fn update_hub(&mut self, params: HubParams) -> BoxFuture<'_, Result<()>> {
         self.update_hub_inner(params).boxed()
     }
+
+    fn set_dma_config(&mut self, config: DmaConfig) -> Result<()> {
+        self.transfer_dma = self.kernel.with_dma_config(config);
+        debug!(
+            "Slot {} transfer DMA mask {:#x}, bounce {:?}",
+            self.id.as_u8(),
+            self.transfer_dma.dma_mask(),
+            config.bounce
+        );
+        let dma = self.transfer_dma.clone();
+        for ep in self.ctrl_ep.iter_mut().chain(self.eps.values_mut()) {
+            ep.with_raw_mut(|ep: &mut XhciEndpoint| ep.set_transfer_dma(dma.clone()));
+        }
+        Ok(())
+    }
 }
@@ -1,29 +1,51 @@
-use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Poll, Waker},
+    time::Duration,
+};
 
 use dma_api::DmaDirection;
+use futures::{FutureExt, future::BoxFuture};
 use mbarrier::mb;
 use spin::Mutex;
 use usb_if::{
     descriptor::{self, EndpointDescriptor},
-    endpoint::{RequestId, TransferCompletion, TransferRequest},
+    endpoint::{RequestId, TransferCompletion, TransferRequest, TransferStatus},
     err::TransferError,
+    host::ControlSetup,
     transfer::{BmRequestType, Direction},
 };
 use xhci::{
     registers::doorbell,
     ring::trb::{
-        event::TransferEvent,
-        transfer::{self, Isoch, Normal},
+        command,
+        event::{CompletionCode, TransferEvent},
+        transfer::Allowed,
     },
 };
 
-use super::{DirectionExt, reg::SlotBell, ring::SendRing, transfer::TransferId};
+use super::{
+    cmd::CommandRing,
+    context::StreamContextArray,
+    isoch::{IsochSchedule, IsochStart},
+    reg::SlotBell,
+    ring::SendRing,
+    td_builder,
+    transfer::TransferId,
+};
 use crate::{
     BusAddr,
     backend::{
         Dci,
         ty::{
             ep::{EndpointOp, transfer_to_completion},
+            timer::{Timer, poll_until},
             transfer::{Transfer, TransferKind},
         },
     },
@@ -35,35 +57,71 @@ pub struct Endpoint {
     dci: Dci,
     pub ring: SendRing<TransferEvent>,
     bell: Arc<Mutex<SlotBell>>,
+    cmd: CommandRing,
     transfers: BTreeMap<TransferId, Transfer>,
-    cancelled: BTreeMap<TransferId, ()>,
-    iso_packet_ids: BTreeMap<TransferId, Vec<TransferId>>,
-    trb_counts: BTreeMap<TransferId, usize>,
-    outstanding_trbs: usize,
+    /// 每个请求占用的 TRB，键为最后一个 TRB
+    td_trbs: BTreeMap<TransferId, Vec<TransferId>>,
+    /// 各环上未完成请求占用的 TRB 数，键为流 ID，主环为 0
+    outstanding_trbs: BTreeMap<u16, usize>,
     kernel: Kernel,
     max_packet_size: usize,
     max_burst_size: usize,
+    /// 控制器无法编码设备声明的周期时，由软件保证的最小提交间隔
+    soft_interval: Option<Duration>,
+    next_due: Duration,
+    /// 已分配的流，启用后请求只提交到各流的环，`ring` 不再使用
+    streams: Option<StreamContextArray>,
+    /// 流上请求所属的流 ID，键为最后一个 TRB；主环上的请求不记录
+    stream_of: BTreeMap<TransferId, u16>,
+    /// 完成事件投递的中断器
+    interrupter: u16,
+    /// 等时端点的帧排程
+    isoch: Option<IsochSchedule>,
+    /// 事件处理器记录的等时环异常计数，见 [`super::transfer::TransferResultHandler`]
+    faults: Arc<AtomicU32>,
+    /// 已处理的环异常计数
+    seen_faults: u32,
+    /// 控制器报告了环异常，下一个事件之前没有事件的 TD 均已被跳过（Linux 的 `ep->skip`）
+    skip: bool,
+    /// 已判定被跳过、等待回收的等时 TD
+    missed: BTreeSet<TransferId>,
 }
 
 unsafe impl Send for Endpoint {}
 unsafe impl Sync for Endpoint {}
 
 impl Endpoint {
-    pub fn new(dci: Dci, kernel: &Kernel, bell: Arc<Mutex<SlotBell>>) -> crate::err::Result<Self> {
-        let ring = SendRing::new(DmaDirection::Bidirectional, kernel)?;
+    /// `ring_bytes` 为传输环占用的字节数
+    pub fn new(
+        dci: Dci,
+        ring_bytes: usize,
+        kernel: &Kernel,
+        bell: Arc<Mutex<SlotBell>>,
+        cmd: CommandRing,
+    ) -> crate::err::Result<Self> {
+        let ring = SendRing::new_with_bytes(ring_bytes, DmaDirection::Bidirectional, kernel)?;
 
         Ok(Self {
             dci,
             ring,
             bell,
+            cmd,
             transfers: BTreeMap::new(),
-            cancelled: BTreeMap::new(),
-            iso_packet_ids: BTreeMap::new(),
-            trb_counts: BTreeMap::new(),
-            outstanding_trbs: 0,
+            td_trbs: BTreeMap::new(),
+            outstanding_trbs: BTreeMap::new(),
             kernel: kernel.clone(),
             max_packet_size: 0,
             max_burst_size: 0,
+            soft_interval: None,
+            next_due: Duration::ZERO,
+            streams: None,
+            stream_of: BTreeMap::new(),
+            interrupter: 0,
+            isoch: None,
+            faults: Arc::new(AtomicU32::new(0)),
+            seen_faults: 0,
+            skip: false,
+            missed: BTreeSet::new(),
         })
     }
 
@@ -72,16 +130,77 @@ impl Endpoint {
         self.max_burst_size = max_burst_size;
     }
 
+    pub fn set_soft_interval(&mut self, interval: Duration) {
+        self.soft_interval = Some(interval);
+    }
+
+    /// 等时端点按 `schedule` 为 TD 指定 Frame ID
+    pub fn configure_isoch(&mut self, schedule: IsochSchedule) {
+        self.isoch = Some(schedule);
+    }
+
+    /// 事件处理器递增的环异常计数，注册传输环时一并登记
+    pub fn faults(&self) -> Arc<AtomicU32> {
+        self.faults.clone()
+    }
+
+    pub fn set_interrupter(&mut self, interrupter: u16) {
+        self.interrupter = interrupter;
+    }
+
+    /// 替换传输缓冲区使用的 DMA 约束，传输环仍使用创建时的内存
+    pub fn set_transfer_dma(&mut self, kernel: Kernel) {
+        self.kernel = kernel;
+    }
+
     pub fn bus_addr(&self) -> BusAddr {
         self.ring.bus_addr()
     }
 
-    fn doorbell(&mut self) {
+    /// 启用流，之后请求须通过 [`EndpointOp::submit_stream_request`] 提交
+    pub fn enable_streams(&mut self, streams: StreamContextArray) {
+        self.streams = Some(streams);
+    }
+
+    fn ring_for(&self, stream: u16) -> &SendRing<TransferEvent> {
+        match &self.streams {
+            Some(streams) if stream != 0 => streams.ring(stream).unwrap(),
+            _ => &self.ring,
+        }
+    }
+
+    fn ring_for_mut(&mut self, stream: u16) -> &mut SendRing<TransferEvent> {
+        match &mut self.streams {
+            Some(streams) if stream != 0 => streams.ring_mut(stream).unwrap(),
+            _ => &mut self.ring,
+        }
+    }
+
+    fn stream_of(&self, handle: TransferId) -> u16 {
+        self.stream_of.get(&handle).copied().unwrap_or(0)
+    }
+
+    fn doorbell(&mut self, stream: u16) {
         let mut bell = doorbell::Register::default();
         bell.set_doorbell_target(self.dci.into());
+        bell.set_doorbell_stream_id(stream);
         self.bell.lock().ring(bell);
     }
 
+    /// 停止端点后恢复运行：启用流时需要为每个仍有请求的流敲门铃
+    fn restart(&mut self) {
+        if self.streams.is_none() {
+            self.doorbell(0);
+            return;
+        }
+        let mut streams: Vec<u16> = self.stream_of.values().copied().collect();
+        streams.sort_unstable();
+        streams.dedup();
+        for stream in streams {
+            self.doorbell(stream);
+        }
+    }
+
     pub fn ring(&self) -> &SendRing<TransferEvent> {
         &self.ring
     }
@@ -92,24 +211,14 @@ impl Endpoint {
         handle: BusAddr,
     ) -> Result<Transfer, TransferError> {
         let handle = TransferId(handle);
-        if let Some(count) = self.trb_counts.remove(&handle) {
-            self.outstanding_trbs = self.outstanding_trbs.saturating_sub(count);
-        }
+        let trbs = self.forget(handle);
         let mut t = self.transfers.remove(&handle).unwrap();
-        match c.completion_code() {
-            Ok(code) => match code.to_result() {
-                Ok(_) => Ok(()),
-                Err(e) => Err(e),
-            },
-            Err(_e) => Err(TransferError::Other(anyhow!("Transfer failed"))),
-        }?;
 
         let transfer_len;
+        // 等时传输逐包检查完成码
         if let TransferKind::Isochronous { packet_lengths } = &t.kind {
-            let packet_ids = self
-                .iso_packet_ids
-                .remove(&handle)
-                .unwrap_or_else(|| vec![handle]);
+            // 等时传输的每个包都是独立的 TD，完成时需要逐个汇总
+            let packet_ids = trbs;
             if packet_ids.len() != packet_lengths.len() {
                 return Err(TransferError::Other(anyhow!(
                     "ISO completion count mismatch: ids={}, packets={}",
@@ -119,29 +228,41 @@ impl Endpoint {
             }
 
             let mut actual_lengths = Vec::with_capacity(packet_ids.len());
+            let mut status = Vec::with_capacity(packet_ids.len());
             for (index, packet_id) in packet_ids.iter().copied().enumerate() {
+                // TD 按顺序完成，最后一个包已完成而前面的包没有事件，说明控制器跳过了它
+                // （Missed Service Error 事件的 TRB 指针可能为空）
                 let event = if packet_id == handle {
                     c
+                } else if let Some(event) = self.ring.get_finished(packet_id.0) {
+                    event
                 } else {
-                    self.ring.get_finished(packet_id.0).ok_or_else(|| {
-                        TransferError::Other(anyhow!(
-                            "missing ISO packet completion for {:?}",
-                            packet_id
-                        ))
-                    })?
+                    trace!("ISO packet {index} skipped without completion event");
+                    actual_lengths.push(0);
+                    status.push(TransferStatus::Missed);
+                    continue;
+                };
+                // 单个包失败不影响同一请求中的其他包，由调用方按包状态处理
+                let packet_status = match event.completion_code() {
+                    Ok(code) => iso_packet_status(code),
+                    Err(_) => TransferStatus::Error,
                 };
-                match event.completion_code() {
-                    Ok(code) => code.to_result()?,
-                    Err(_e) => return Err(TransferError::Other(anyhow!("Transfer failed"))),
+                if packet_status != TransferStatus::Completed {
+                    trace!("ISO packet {index} failed: {packet_status:?}");
+                    actual_lengths.push(0);
+                    status.push(packet_status);
+                    continue;
                 }
 
                 let requested = packet_lengths[index];
                 let remaining = event.trb_transfer_length() as usize;
                 actual_lengths.push(requested.saturating_sub(remaining));
+                status.push(TransferStatus::Completed);
             }
 
             transfer_len = actual_lengths.iter().sum();
             t.iso_packet_actual_lengths = actual_lengths;
+            t.iso_packet_status = status;
             if transfer_len > 0 && matches!(t.direction, Direction::In) {
                 t.prepare_read_all();
             }
@@ -150,8 +271,33 @@ impl Endpoint {
             return Ok(t);
         }
 
-        let remaining = c.trb_transfer_length() as usize;
-        transfer_len = t.buffer_len().saturating_sub(remaining);
+        match c.completion_code() {
+            // 状态阶段是控制请求的最后一个 TRB，即句柄本身
+            Ok(CompletionCode::StallError)
+                if matches!(t.kind, TransferKind::Control(_))
+                    && BusAddr::from(c.trb_pointer()) == handle.0 =>
+            {
+                Err(TransferError::StatusStall)
+            }
+            Ok(code) => match code.to_result() {
+                Ok(_) => Ok(()),
+                Err(e) => Err(e),
+            },
+            Err(_e) => Err(TransferError::Other(anyhow!("Transfer failed"))),
+        }?;
+
+        transfer_len = if c.event_data() {
+            // Event Data TRB 的事件直接给出整个 TD 已传输的字节数（EDTLA）
+            (c.trb_transfer_length() as usize).min(t.buffer_len())
+        } else {
+            // 控制传输的 Data 阶段遇到短包时单独产生事件，Status 阶段的剩余长度总为 0
+            let short = match &t.kind {
+                TransferKind::Control(_) if trbs.len() == 3 => self.ring.get_finished(trbs[1].0),
+                _ => None,
+            };
+            let remaining = short.unwrap_or(c).trb_transfer_length() as usize;
+            t.buffer_len().saturating_sub(remaining)
+        };
 
         if transfer_len > 0 && matches!(t.direction, Direction::In) {
             // 刷新/失效缓存，确保从 DMA 缓冲读取到有效数据
@@ -163,96 +309,177 @@ impl Endpoint {
         Ok(t)
     }
 
-    fn enque_trb(&mut self, trb: transfer::Allowed) -> TransferId {
-        TransferId(self.ring.enque_transfer(trb))
+    /// TD 中间的 TRB 出错时的事件
+    ///
+    /// 出错时端点进入 Halted，事件落在出错的 TRB 上，句柄所在的最后一个 TRB 不会再有事件。
+    /// 成功与短包事件留在槽中供完成时计算长度；等时传输逐包处理，不在此检查。
+    fn td_error_event(&self, handle: TransferId) -> Option<TransferEvent> {
+        if matches!(
+            self.transfers.get(&handle)?.kind,
+            TransferKind::Isochronous { .. }
+        ) {
+            return None;
+        }
+        let ring = self.ring_for(self.stream_of(handle));
+        let (_, inner) = self.td_trbs.get(&handle)?.split_last()?;
+        inner.iter().find_map(|trb| {
+            ring.take_finished_if(trb.0, |event| {
+                !matches!(
+                    event.completion_code(),
+                    Ok(CompletionCode::Success | CompletionCode::ShortPacket)
+                )
+            })
+        })
     }
 
-    fn enque_iso(
-        &mut self,
-        bus_addr: u64,
-        packet_lengths: &[usize],
-        interrupt_on_short_packet: bool,
-    ) -> (TransferId, Vec<TransferId>) {
-        if packet_lengths.len() <= 1 {
-            let id = self.enque_iso_trb(
-                bus_addr,
-                packet_lengths.first().copied().unwrap_or(0),
-                false,
-                true,
-                interrupt_on_short_packet,
-            );
-            (id, vec![id])
-        } else {
-            self.enque_iso_multi(bus_addr, packet_lengths, interrupt_on_short_packet)
+    /// 移除请求的 TRB 记录并归还环空间，返回其占用的 TRB
+    fn forget(&mut self, handle: TransferId) -> Vec<TransferId> {
+        let trbs = self.td_trbs.remove(&handle).unwrap_or_else(|| vec![handle]);
+        let stream = self.stream_of.remove(&handle).unwrap_or(0);
+        if let Some(outstanding) = self.outstanding_trbs.get_mut(&stream) {
+            *outstanding = outstanding.saturating_sub(trbs.len());
         }
+        trbs
     }
 
-    fn enque_iso_trb(
-        &mut self,
-        bus_addr: u64,
-        buff_len: usize,
-        chain: bool,
-        ioc: bool,
-        interrupt_on_short_packet: bool,
-    ) -> TransferId {
-        let mut trb = Isoch::new();
-        trb.set_data_buffer_pointer(bus_addr as _)
-            .set_trb_transfer_length(buff_len as _)
-            .set_interrupter_target(0)
-            .set_start_isoch_asap();
-        if interrupt_on_short_packet {
-            trb.set_interrupt_on_short_packet();
-        }
-        let total_packets = if self.max_packet_size == 0 {
-            1
-        } else {
-            buff_len.div_ceil(self.max_packet_size).max(1)
-        };
-        let packets_per_burst = self.max_burst_size.saturating_add(1).max(1);
-        let burst_count = total_packets.div_ceil(packets_per_burst).saturating_sub(1);
-        let last_burst_packet_count = match total_packets % packets_per_burst {
-            0 => packets_per_burst.saturating_sub(1),
-            residue => residue.saturating_sub(1),
-        };
-        trb.set_td_size_or_tbc(burst_count.min(0x1f) as u8)
-            .set_transfer_last_burst_packet_count(last_burst_packet_count.min(0xf) as u8);
-        if chain {
-            trb.set_chain_bit();
+    /// 复位 Halted 的端点，从最早未完成的请求继续执行
+    ///
+    /// Reset Endpoint 使端点进入 Stopped 并复位数据切换位，出队指针仍停在出错的 TRB 上，
+    /// 需要用 Set TR Dequeue Pointer 移到下一个请求的第一个 TRB，没有请求时移到入队位置。
+    async fn reset_halted(&mut self) -> Result<(), TransferError> {
+        if self.streams.is_some() {
+            // 每个流的环需要分别设置出队指针
+            return Err(TransferError::NotSupported);
         }
-        if ioc {
-            trb.set_interrupt_on_completion();
+
+        let slot_id = self.bell.lock().slot_id();
+        let completion = self
+            .cmd
+            .cmd_completion(command::Allowed::ResetEndpoint(
+                *command::ResetEndpoint::default()
+                    .set_slot_id(slot_id.as_u8())
+                    .set_endpoint_id(self.dci.as_u8()),
+            ))
+            .await;
+        if !reset_endpoint_result(completion.completion_code())? {
+            debug!(
+                "Endpoint {} of slot {slot_id} is not halted",
+                self.dci.as_u8()
+            );
+            return Ok(());
         }
 
-        // 创建Isoch TRB
-        let trb = transfer::Allowed::Isoch(trb);
-        self.enque_trb(trb)
-    }
-    fn enque_iso_multi(
-        &mut self,
-        bus_addr: u64,
-        packet_lengths: &[usize],
-        interrupt_on_short_packet: bool,
-    ) -> (TransferId, Vec<TransferId>) {
-        let mut ids = Vec::with_capacity(packet_lengths.len());
-        let mut offset = 0u64;
+        // 出错的请求已随 STALL 事件完成，剩余请求中最早入队的一个即为下一个
+        let next = self
+            .transfers
+            .keys()
+            .map(|last| {
+                self.td_trbs
+                    .get(last)
+                    .and_then(|trbs| trbs.first())
+                    .copied()
+                    .unwrap_or(*last)
+            })
+            .max_by_key(|first| self.ring.age(first.0));
+        let (dequeue, cycle) = match next {
+            Some(first) => self.ring.dequeue_to(first.0),
+            None => self.ring.dequeue_to_enqueue(),
+        };
 
-        for packet_length in packet_lengths.iter().copied() {
-            let current_size = packet_length as u64;
-            let current_addr = bus_addr + offset;
+        let mut trb = command::SetTrDequeuePointer::default();
+        trb.set_slot_id(slot_id.as_u8())
+            .set_endpoint_id(self.dci.as_u8())
+            .set_new_tr_dequeue_pointer(dequeue.raw());
+        if cycle {
+            trb.set_dequeue_cycle_state();
+        }
+        self.cmd
+            .cmd_request(command::Allowed::SetTrDequeuePointer(trb))
+            .await?;
+        self.restart();
+        Ok(())
+    }
 
-            ids.push(self.enque_iso_trb(
-                current_addr,
-                current_size as _,
-                false,
-                true,
-                interrupt_on_short_packet,
-            ));
+    /// 停止端点后丢弃尚未完成的请求，返回时控制器已不再访问其缓冲区
+    async fn cancel_td(
+        &mut self,
+        id: RequestId,
+    ) -> Result<Option<TransferCompletion>, TransferError> {
+        let handle = TransferId(BusAddr(id.raw()));
+        if !self.transfers.contains_key(&handle) {
+            return Err(TransferError::InvalidEndpoint);
+        }
+        if let Some(res) = self.reclaim_request(id) {
+            return res.map(Some);
+        }
+        let stream = self.stream_of(handle);
+
+        let slot_id = self.bell.lock().slot_id();
+        // 端点已停止或处于 Halted 时返回 Context State Error，不影响后续处理
+        if let Err(e) = self
+            .cmd
+            .cmd_request(command::Allowed::StopEndpoint(
+                *command::StopEndpoint::default()
+                    .set_slot_id(slot_id.as_u8())
+                    .set_endpoint_id(self.dci.as_u8()),
+            ))
+            .await
+        {
+            debug!(
+                "Stop endpoint {} of slot {slot_id}: {e:?}",
+                self.dci.as_u8()
+            );
+        }
 
-            offset += current_size;
+        // Stopped 事件先于命令完成事件写入事件环，此时已可见
+        let mut inside = false;
+        if let Some(event) = self.ring_for(stream).get_finished(handle.0) {
+            let stopped = matches!(
+                event.completion_code(),
+                Ok(CompletionCode::Stopped
+                    | CompletionCode::StoppedLengthInvalid
+                    | CompletionCode::StoppedShortPacket)
+            );
+            if !stopped {
+                // 停止前请求已正常完成
+                let res = self.handle_transfer_completion(event, handle.0);
+                self.restart();
+                return res.map(|transfer| Some(transfer_to_completion(id, transfer)));
+            }
+            // 出队指针仍指向该 TD，门铃会让控制器重新执行它
+            inside = true;
         }
 
-        let id = ids.last().copied().unwrap_or(TransferId(BusAddr(0)));
-        (id, ids)
+        let trbs = self.forget(handle);
+        self.transfers.remove(&handle);
+        // 请求中已有 TRB 产生事件说明控制器停在该请求内部，需要把出队指针移到请求之后；
+        // 否则控制器尚未到达，改写为 No Op 即可
+        for &trb in &trbs {
+            inside |= self.ring_for(stream).get_finished(trb.0).is_some();
+            self.ring_for_mut(stream).noop_transfer(trb.0);
+        }
+        mb();
+        if inside {
+            let (dequeue, cycle) = self.ring_for(stream).next_dequeue(handle.0);
+            let mut trb = command::SetTrDequeuePointer::default();
+            trb.set_slot_id(slot_id.as_u8())
+                .set_endpoint_id(self.dci.as_u8())
+                .set_stream_id(stream)
+                .set_new_tr_dequeue_pointer(dequeue.raw());
+            if stream != 0 {
+                // 主流数组中的传输环
+                trb.set_stream_context_type(1);
+            }
+            if cycle {
+                trb.set_dequeue_cycle_state();
+            }
+            self.cmd
+                .cmd_request(command::Allowed::SetTrDequeuePointer(trb))
+                .await?;
+        }
+        // 门铃使端点从停止状态恢复运行
+        self.restart();
+        Ok(None)
     }
 
     fn required_trbs(transfer: &Transfer) -> usize {
@@ -264,19 +491,113 @@ impl Endpoint {
                     2
                 }
             }
-            TransferKind::Bulk | TransferKind::Interrupt => 1,
+            TransferKind::Bulk | TransferKind::Interrupt => {
+                td_builder::normal_trbs_upper_bound(transfer.buffer_len())
+            }
             TransferKind::Isochronous { packet_lengths } => packet_lengths.len().max(1),
         }
     }
 
-    fn ensure_ring_capacity(&self, required: usize) -> Result<(), TransferError> {
-        let usable = self.ring.usable_capacity().saturating_sub(1);
-        if self.outstanding_trbs.saturating_add(required) > usable {
+    fn ensure_ring_capacity(&self, stream: u16, required: usize) -> Result<(), TransferError> {
+        let outstanding = self.outstanding_trbs.get(&stream).copied().unwrap_or(0);
+        let usable = self.ring_for(stream).usable_capacity().saturating_sub(1);
+        if outstanding.saturating_add(required) > usable {
             return Err(TransferError::QueueFull);
         }
         Ok(())
     }
 
+    /// 等时请求中各 TD 的起始帧
+    ///
+    /// 环上没有未完成的请求时说明环已被取空（或控制器报告了 Ring Underrun/Overrun），
+    /// 排程从当前帧之后重新对齐；仍有请求时新 TD 紧接在已排程的 TD 之后。
+    ///
+    /// `packets` 为等时请求的包数，在请求的传输已构建好之后调用，失败时不占用排程。
+    fn isoch_start(&mut self, packets: Option<usize>) -> Result<IsochStart, TransferError> {
+        let (Some(packets), Some(_)) = (packets, &self.isoch) else {
+            return Ok(IsochStart::Asap);
+        };
+        let pending = !self.transfers.is_empty();
+        let faults = self.take_faults();
+        if faults != 0 {
+            debug!(
+                "ep dci {}: {faults} isoch ring fault(s) reported, pending={pending}",
+                self.dci.as_u8(),
+            );
+            if !pending && let Some(schedule) = &mut self.isoch {
+                schedule.lost();
+            }
+        }
+
+        let mfindex = self.bell.lock().mfindex();
+        let schedule = self.isoch.as_mut().unwrap();
+        let underruns = schedule.underruns();
+        let start = schedule.reserve(mfindex, packets, pending)?;
+        if schedule.underruns() != underruns {
+            debug!(
+                "ep dci {}: isoch underrun #{}, restart at frame {:?}",
+                self.dci.as_u8(),
+                schedule.underruns(),
+                start.frame_id(0)
+            );
+        }
+        Ok(start)
+    }
+
+    /// 取出事件处理器新记录的环异常数，有异常时开始跳过检查
+    fn take_faults(&mut self) -> u32 {
+        let faults = self.faults.load(Ordering::Acquire);
+        let new = faults.wrapping_sub(self.seen_faults);
+        if new != 0 {
+            self.seen_faults = faults;
+            self.skip = true;
+        }
+        new
+    }
+
+    /// 不带 TRB 指针的 Missed Service Error 之后，被跳过的 TD 不会再有事件
+    ///
+    /// 与 Linux 的 `ep->skip` 相同，以该端点的下一个事件为界：等时 TD 按入队顺序执行，
+    /// 比第一个有事件的 TD 更早入队的 TD 均已被控制器跳过，按 Missed 完成。
+    fn retire_skipped(&mut self) {
+        self.take_faults();
+        if !self.skip {
+            return;
+        }
+        let mut tds: Vec<TransferId> = self
+            .transfers
+            .keys()
+            .copied()
+            .filter(|handle| !self.missed.contains(handle))
+            .collect();
+        // 从最早入队的 TD 开始
+        tds.sort_by_key(|handle| core::cmp::Reverse(self.ring.age(handle.0)));
+        let Some(first) = tds.iter().position(|handle| {
+            self.td_trbs
+                .get(handle)
+                .is_some_and(|trbs| trbs.iter().any(|trb| self.ring.is_finished(trb.0)))
+        }) else {
+            return;
+        };
+        for handle in &tds[..first] {
+            trace!("ep dci {}: isoch TD {handle:?} skipped", self.dci.as_u8());
+            self.missed.insert(*handle);
+        }
+        self.skip = false;
+    }
+
+    /// 回收被跳过的等时 TD，所有包均为 Missed
+    fn missed_transfer(&mut self, handle: TransferId) -> Transfer {
+        self.forget(handle);
+        let mut t = self.transfers.remove(&handle).unwrap();
+        if let TransferKind::Isochronous { packet_lengths } = &t.kind {
+            t.iso_packet_actual_lengths = vec![0; packet_lengths.len()];
+            t.iso_packet_status = vec![TransferStatus::Missed; packet_lengths.len()];
+        }
+        t.transfer_len = 0;
+        t
+    }
+
     fn required_trbs_for_request(request: &TransferRequest) -> usize {
         match request {
             TransferRequest::Control { buffer, .. } => {
@@ -286,17 +607,40 @@ impl Endpoint {
                     2
                 }
             }
-            TransferRequest::Bulk { .. } | TransferRequest::Interrupt { .. } => 1,
+            TransferRequest::Bulk { buffer, .. } | TransferRequest::Interrupt { buffer, .. } => {
+                td_builder::normal_trbs_upper_bound(buffer.map_or(0, |buffer| buffer.len))
+            }
             TransferRequest::Isochronous { packets, .. } => packets.len().max(1),
         }
     }
 }
 
-impl EndpointOp for Endpoint {
-    fn submit_request(&mut self, request: TransferRequest) -> Result<RequestId, TransferError> {
+impl Endpoint {
+    fn submit_on(
+        &mut self,
+        stream: u16,
+        request: TransferRequest,
+    ) -> Result<RequestId, TransferError> {
+        if self.bell.lock().is_closed() {
+            return Err(TransferError::NoDevice);
+        }
+        if let TransferRequest::Bulk { buffer, .. } | TransferRequest::Interrupt { buffer, .. } =
+            &request
+            && buffer.is_some_and(|buffer| buffer.len > td_builder::MAX_EVENT_DATA_LEN)
+        {
+            return Err(TransferError::Other(anyhow!(
+                "transfer length exceeds {} bytes",
+                td_builder::MAX_EVENT_DATA_LEN
+            )));
+        }
         let required_trbs = Self::required_trbs_for_request(&request);
-        self.ensure_ring_capacity(required_trbs)?;
+        self.ensure_ring_capacity(stream, required_trbs)?;
+        let iso_packets = match &request {
+            TransferRequest::Isochronous { packets, .. } => Some(packets.len()),
+            _ => None,
+        };
         let transfer = Transfer::from_request(&self.kernel, request)?;
+        let start = self.isoch_start(iso_packets)?;
         debug_assert_eq!(required_trbs, Self::required_trbs(&transfer));
 
         let mut data_bus_addr = 0;
@@ -342,87 +686,99 @@ impl EndpointOp for Endpoint {
         let data_len = transfer.buffer_len();
         let dir = transfer.direction;
 
-        let mut handle = TransferId(BusAddr(0));
-        let mut iso_packet_ids = Vec::new();
-
-        match &transfer.kind {
-            TransferKind::Control(t) => {
-                let bm_request_type = BmRequestType {
-                    direction: transfer.direction,
-                    request_type: t.request_type,
-                    recipient: t.recipient,
-                };
-
-                let mut setup = transfer::SetupStage::default();
-                setup
-                    .set_request_type(bm_request_type.into())
-                    .set_request(t.request.into())
-                    .set_value(t.value)
-                    .set_index(t.index)
-                    .set_length(0)
-                    .set_transfer_type(transfer::TransferType::No);
+        let td = match &transfer.kind {
+            TransferKind::Control(t) => td_builder::control(
+                setup_stage(t, transfer.direction),
+                dir,
+                Some((data_bus_addr, data_len)),
+                self.interrupter,
+            ),
+            TransferKind::Interrupt | TransferKind::Bulk => td_builder::normal(
+                data_bus_addr,
+                data_len,
+                self.max_packet_size,
+                self.interrupter,
+            ),
+            TransferKind::Isochronous { packet_lengths } => td_builder::isoch(
+                data_bus_addr,
+                packet_lengths,
+                self.max_packet_size,
+                self.max_burst_size,
+                matches!(dir, Direction::In),
+                start,
+                self.interrupter,
+            ),
+        };
+        debug_assert!(td.len() <= required_trbs);
+
+        let ring = self.ring_for_mut(stream);
+        let ids: Vec<TransferId> = td
+            .into_iter()
+            .map(|mut trb| {
+                // Event Data 的值作为事件的 TRB 指针，填入自身地址使事件落在请求句柄上
+                if let Allowed::EventData(event) = &mut trb {
+                    event.set_event_data(ring.next_enqueue_addr().raw());
+                }
+                TransferId(ring.enque_transfer(trb))
+            })
+            .collect();
+        let handle = *ids.last().unwrap();
+        let ids_len = ids.len();
+        self.td_trbs.insert(handle, ids);
+        if let Some(interval) = self.soft_interval {
+            self.next_due = self.kernel.now() + interval;
+        }
+        *self.outstanding_trbs.entry(stream).or_default() += ids_len;
+        if stream != 0 {
+            self.stream_of.insert(handle, stream);
+        }
+        self.transfers.insert(handle, transfer);
+        mb();
+        self.doorbell(stream);
 
-                let mut data = None;
+        Ok(RequestId::new(handle.0.raw()))
+    }
+}
 
-                if transfer.buffer_len() > 0 {
-                    setup
-                        .set_transfer_type(dir.to_xhci_transfer_type())
-                        .set_length(data_len as _);
-
-                    let mut _data = transfer::DataStage::default();
-                    _data
-                        .set_data_buffer_pointer(data_bus_addr)
-                        .set_trb_transfer_length(data_len as _)
-                        .set_direction(transfer.direction.to_xhci_direction());
-                    data = Some(_data);
-                }
+impl Timer for Endpoint {
+    fn now(&self) -> Duration {
+        self.kernel.now()
+    }
 
-                let mut status = transfer::StatusStage::default();
-                status.set_interrupt_on_completion();
+    fn wake_at(&self, deadline: Duration, waker: &Waker) {
+        self.kernel.wake_at(deadline, waker)
+    }
+}
 
-                if matches!(transfer.direction, Direction::In) && transfer.buffer_len() > 0 {
-                    status.clear_direction();
-                } else {
-                    status.set_direction();
-                }
+impl EndpointOp for Endpoint {
+    fn submit_request(&mut self, request: TransferRequest) -> Result<RequestId, TransferError> {
+        if self.streams.is_some() {
+            return Err(TransferError::Other(anyhow!(
+                "endpoint has streams enabled, submit with a stream id"
+            )));
+        }
+        self.submit_on(0, request)
+    }
 
-                self.ring.enque_transfer(setup.into());
-                if let Some(data) = data {
-                    self.ring.enque_transfer(data.into());
-                }
-                handle.0 = self.ring.enque_transfer(status.into());
-            }
-            TransferKind::Interrupt | TransferKind::Bulk => {
-                let trb = transfer::Allowed::Normal(
-                    *Normal::new()
-                        .set_data_buffer_pointer(data_bus_addr as _)
-                        .set_trb_transfer_length(data_len as _)
-                        .set_interrupter_target(0)
-                        .set_interrupt_on_short_packet()
-                        .set_interrupt_on_completion(),
-                );
-                handle.0 = self.ring.enque_transfer(trb);
-            }
-            TransferKind::Isochronous { packet_lengths } => {
-                let ids = self.enque_iso(
-                    data_bus_addr,
-                    packet_lengths,
-                    matches!(transfer.direction, Direction::In),
-                );
-                handle = ids.0;
-                iso_packet_ids = ids.1;
-            }
+    fn submit_stream_request(
+        &mut self,
+        stream_id: u16,
+        request: TransferRequest,
+    ) -> Result<RequestId, TransferError> {
+        let streams = self.streams() as usize;
+        if streams == 0 {
+            return Err(TransferError::NotSupported);
         }
-        if !iso_packet_ids.is_empty() {
-            self.iso_packet_ids.insert(handle, iso_packet_ids);
+        if !(1..=streams).contains(&(stream_id as usize)) {
+            return Err(TransferError::Other(anyhow!(
+                "stream id {stream_id} out of range 1..={streams}"
+            )));
         }
-        self.trb_counts.insert(handle, required_trbs);
-        self.outstanding_trbs += required_trbs;
-        self.transfers.insert(handle, transfer);
-        mb();
-        self.doorbell();
+        self.submit_on(stream_id, request)
+    }
 
-        Ok(RequestId::new(handle.0.raw()))
+    fn streams(&self) -> u16 {
+        self.streams.as_ref().map_or(0, |s| s.streams())
     }
 
     fn reclaim_request(
@@ -430,28 +786,73 @@ impl EndpointOp for Endpoint {
         id: RequestId,
     ) -> Option<Result<TransferCompletion, TransferError>> {
         let raw_id = BusAddr(id.raw());
-        let c = self.ring.get_finished(raw_id)?;
-        let cancelled = self.cancelled.remove(&TransferId(raw_id)).is_some();
+        if self.isoch.is_some() {
+            self.retire_skipped();
+            let handle = TransferId(raw_id);
+            if self.missed.remove(&handle) && self.transfers.contains_key(&handle) {
+                let transfer = self.missed_transfer(handle);
+                return Some(Ok(transfer_to_completion(id, transfer)));
+            }
+        }
+        let ring = self.ring_for(self.stream_of(TransferId(raw_id)));
+        let c = match ring.get_finished(raw_id) {
+            Some(c) => c,
+            None => self.td_error_event(TransferId(raw_id))?,
+        };
         let res = self
             .handle_transfer_completion(c, raw_id)
             .map(|transfer| transfer_to_completion(id, transfer));
-        if cancelled {
-            return Some(Err(TransferError::Cancelled));
-        }
         Some(res)
     }
 
     fn register_waker(&self, id: RequestId, cx: &mut core::task::Context<'_>) {
-        self.ring.register_cx(BusAddr(id.raw()), cx);
+        let raw_id = BusAddr(id.raw());
+        let ring = self.ring_for(self.stream_of(TransferId(raw_id)));
+        // TD 中间的 TRB 出错时事件不会落在句柄上
+        match self.td_trbs.get(&TransferId(raw_id)) {
+            Some(trbs) => trbs.iter().for_each(|trb| ring.register_cx(trb.0, cx)),
+            None => ring.register_cx(raw_id, cx),
+        }
+        // 被跳过的等时 TD 没有事件，由之后入队的 TD 的事件唤醒
+        if self.isoch.is_some() && self.transfers.contains_key(&TransferId(raw_id)) {
+            let age = ring.age(raw_id);
+            self.transfers
+                .keys()
+                .filter(|handle| ring.age(handle.0) < age)
+                .for_each(|handle| ring.register_cx(handle.0, cx));
+        }
     }
 
-    fn cancel_request(&mut self, id: RequestId) -> Result<(), TransferError> {
-        let transfer_id = TransferId(BusAddr(id.raw()));
-        if !self.transfers.contains_key(&transfer_id) {
-            return Err(TransferError::InvalidEndpoint);
+    fn pending_requests(&self) -> Vec<RequestId> {
+        self.transfers
+            .keys()
+            .map(|id| RequestId::new(id.0.raw()))
+            .collect()
+    }
+
+    fn poll_ready(&mut self, cx: &mut core::task::Context<'_>) -> Poll<()> {
+        if self.soft_interval.is_none() {
+            return Poll::Ready(());
         }
-        self.cancelled.insert(transfer_id, ());
-        Ok(())
+        poll_until(self, self.next_due, cx)
+    }
+
+    fn cancel_request(
+        &mut self,
+        id: RequestId,
+    ) -> BoxFuture<'_, Result<Option<TransferCompletion>, TransferError>> {
+        self.cancel_td(id).boxed()
+    }
+
+    fn reset_halt(&mut self) -> BoxFuture<'_, Result<(), TransferError>> {
+        self.reset_halted().boxed()
+    }
+
+    #[cfg(feature = "dma-mapping")]
+    fn dma_mapping(&self, id: RequestId) -> Option<crate::backend::kmod::DmaMapping> {
+        self.transfers
+            .get(&TransferId(BusAddr(id.raw())))?
+            .dma_mapping()
     }
 }
 
@@ -478,3 +879,89 @@ impl EndpointDescriptorExt for EndpointDescriptor {
         }
     }
 }
+
+/// 等时包的完成码转换为包状态
+/// Reset Endpoint 的结果，端点需要继续恢复时返回 `true`
+///
+/// 端点未处于 Halted 时返回 Context State Error，主机侧无需恢复；其余错误说明命令本身失败。
+fn reset_endpoint_result(code: Result<CompletionCode, u8>) -> Result<bool, TransferError> {
+    match code {
+        Ok(CompletionCode::ContextStateError) => Ok(false),
+        Ok(code) => code.to_result().map(|()| true),
+        Err(code) => Err(TransferError::Other(anyhow!(
+            "Reset endpoint: unknown completion code {code}"
+        ))),
+    }
+}
+
+fn iso_packet_status(code: CompletionCode) -> TransferStatus {
+    match code {
+        CompletionCode::Success | CompletionCode::ShortPacket => TransferStatus::Completed,
+        // 错过服务周期，包未在总线上传输
+        CompletionCode::MissedServiceError
+        | CompletionCode::RingUnderrun
+        | CompletionCode::RingOverrun => TransferStatus::Missed,
+        CompletionCode::StallError => TransferStatus::Stalled,
+        CompletionCode::Stopped
+        | CompletionCode::StoppedLengthInvalid
+        | CompletionCode::StoppedShortPacket => TransferStatus::Cancelled,
+        _ => TransferStatus::Error,
+    }
+}
+
+/// Setup Stage TRB 的立即数据，类与厂商请求的编号原样写入 bRequest
+fn setup_stage(setup: &ControlSetup, direction: Direction) -> td_builder::ControlSetup {
+    let bm_request_type = BmRequestType {
+        direction,
+        request_type: setup.request_type,
+        recipient: setup.recipient,
+    };
+    td_builder::ControlSetup {
+        request_type: bm_request_type.into(),
+        request: setup.request.into(),
+        value: setup.value,
+        index: setup.index,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_endpoint_only_ignores_context_state_error() {
+        assert!(matches!(
+            reset_endpoint_result(Ok(CompletionCode::Success)),
+            Ok(true)
+        ));
+        assert!(matches!(
+            reset_endpoint_result(Ok(CompletionCode::ContextStateError)),
+            Ok(false)
+        ));
+        for code in [
+            CompletionCode::ParameterError,
+            CompletionCode::SlotNotEnabledError,
+            CompletionCode::EndpointNotEnabledError,
+        ] {
+            assert!(reset_endpoint_result(Ok(code)).is_err(), "{code:?}");
+        }
+        assert!(reset_endpoint_result(Err(0xff)).is_err());
+    }
+
+    #[test]
+    fn vendor_request_reaches_setup_stage() {
+        use usb_if::transfer::Recipient;
+
+        // 0x06 与标准 GET_DESCRIPTOR 重叠，厂商请求不能被当作标准请求映射
+        let setup = ControlSetup::vendor(Recipient::Device, 0x06, 0x1234, 0x0002);
+        let stage = setup_stage(&setup, Direction::In);
+        assert_eq!(
+            (stage.request_type, stage.request, stage.value, stage.index),
+            (0xc0, 0x06, 0x1234, 0x0002)
+        );
+
+        let setup = ControlSetup::vendor(Recipient::Interface, 0xa5, 0, 1);
+        let stage = setup_stage(&setup, Direction::Out);
+        assert_eq!((stage.request_type, stage.request), (0x41, 0xa5));
+    }
+}
@@ -1,9 +1,26 @@
+use alloc::vec::Vec;
+
 use dma_api::{DArray, DmaDirection};
 use mbarrier::mb;
 use xhci::ring::trb::event::Allowed;
 
-use super::ring::Ring;
-use crate::{err::*, osal::Kernel};
+use super::{
+    event_cursor::EventCursor,
+    ring::{Ring, TrbData},
+};
+use crate::{
+    backend::kmod::{
+        le::{DmaLe, LeWords},
+        mem::MemTag,
+    },
+    err::*,
+    osal::Kernel,
+};
+
+/// 每段占用的页数，与其他环一致
+const SEGMENT_PAGES: usize = 2;
+/// 单个 ERST 段允许的最大 TRB 数（xHCI 规范 6.5）
+const SEGMENT_MAX_TRBS: usize = 4096;
 
 #[repr(C)]
 pub struct EventRingSte {
@@ -12,8 +29,22 @@ pub struct EventRingSte {
     _reserved: [u8; 6],
 }
 
+impl LeWords for EventRingSte {
+    fn le(self) -> Self {
+        Self {
+            addr: self.addr.le(),
+            size: self.size.to_le(),
+            _reserved: self._reserved,
+        }
+    }
+}
+
+/// 由 ERST 描述的多段事件环
+///
+/// 各段等长，段之间没有 Link TRB，出队位置越过段尾时转到下一段。
 pub struct EventRing {
-    ring: Ring,
+    segments: Vec<Ring>,
+    cursor: EventCursor,
     pub ste: DArray<EventRingSte>,
 }
 
@@ -21,44 +52,73 @@ unsafe impl Send for EventRing {}
 unsafe impl Sync for EventRing {}
 
 impl EventRing {
+    /// 单段事件环
     pub fn new(dma: &Kernel) -> Result<Self> {
-        let ring = Ring::new(true, DmaDirection::Bidirectional, dma)?;
+        Self::new_with_segments(dma, 1)
+    }
 
-        // let mut ste = DVec::zeros(dma_mask as _, 1, 64, dma_api::Direction::Bidirectional)
-        //     .map_err(|_| USBError::NoMemory)?;
+    /// `segments` 段的事件环，调用方需保证不超过控制器的 ERST Max
+    pub fn new_with_segments(dma: &Kernel, segments: usize) -> Result<Self> {
+        let segment_len =
+            (dma.page_size() * SEGMENT_PAGES / size_of::<TrbData>()).min(SEGMENT_MAX_TRBS);
 
         let mut ste = dma
-            .array_zero_with_align(1, 64, DmaDirection::Bidirectional)
+            .with_tag(MemTag::Ring)
+            .array_zero_with_align(segments, 64, DmaDirection::Bidirectional)
             .map_err(|_| USBError::NoMemory)?;
 
-        let ste0 = EventRingSte {
-            addr: ring.trbs.dma_addr().as_u64(),
-            size: ring.len() as _,
-            _reserved: [0; 6],
-        };
-
-        ste.set(0, ste0);
+        let mut rings = Vec::with_capacity(segments);
+        for i in 0..segments {
+            let ring = Ring::new_with_len(segment_len, false, DmaDirection::Bidirectional, dma)?;
+            ste.set_le(
+                i,
+                EventRingSte {
+                    addr: ring.trbs.dma_addr().as_u64(),
+                    size: ring.len() as _,
+                    _reserved: [0; 6],
+                },
+            );
+            rings.push(ring);
+        }
 
-        Ok(Self { ring, ste })
+        Ok(Self {
+            segments: rings,
+            cursor: EventCursor::new(),
+            ste,
+        })
     }
 
-    /// 完成一次循环返回 true
+    /// 取出下一个事件，没有新事件时返回 None
     pub fn next(&mut self) -> Option<Allowed> {
-        let (data, flag) = self.ring.current_data();
+        let ring = &self.segments[self.cursor.segment];
+        let data = ring.trbs.read_le(self.cursor.index)?;
 
         let allowed = Allowed::try_from(data.to_raw()).ok()?;
 
-        if flag != allowed.cycle_bit() {
+        if self.cursor.cycle != allowed.cycle_bit() {
             return None;
         }
         mb();
-        self.ring.inc_deque();
+        self.cursor.advance(self.segments.len(), ring.len());
         Some(allowed)
     }
 
+    /// 清空全部段并把出队位置移回第一段开头，之后需重新写入 ERDP
+    pub fn reset(&mut self) {
+        self.segments.iter_mut().for_each(Ring::reset);
+        self.cursor = EventCursor::new();
+    }
+
     pub fn erdp(&self) -> u64 {
-        self.ring.current_trb_addr().raw() & 0xFFFF_FFFF_FFFF_FFF0
+        let ring = &self.segments[self.cursor.segment];
+        ring.trb_bus_addr(self.cursor.index).raw() & 0xFFFF_FFFF_FFFF_FFF0
+    }
+
+    /// 出队位置所在段的 ERST 索引，写入 ERDP 的 DESI 字段
+    pub fn desi(&self) -> u8 {
+        self.cursor.desi()
     }
+
     pub fn erstba(&self) -> u64 {
         self.ste.dma_addr().as_u64()
     }
@@ -67,6 +127,11 @@ impl EventRing {
         self.ste.len()
     }
 
+    /// 全部段的 TRB 总数
+    pub fn capacity(&self) -> usize {
+        self.segments.iter().map(Ring::len).sum()
+    }
+
     pub fn info(&self) -> EventRingInfo {
         EventRingInfo {
             erstz: self.len() as _,
@@ -0,0 +1,94 @@
+//! 事件环出队位置
+//!
+//! 事件环由 ERST 中的多个段首尾相接组成，段末尾没有 Link TRB：控制器写完一段后
+//! 转到下一段，写完最后一段后回到第一段并翻转 cycle 位。模块不接触 DMA 与寄存器，
+//! 主机上也会编译以运行单元测试。参考 xHCI 规范 4.9.4（Event Ring Management）。
+
+/// 实际使用的段数：请求为 0 时按 1 段，不超过控制器声明的 ERST Max
+pub(crate) fn segment_count(requested: usize, erst_max: u16) -> usize {
+    requested.clamp(1, (erst_max as usize).max(1))
+}
+
+/// 软件出队位置，`cycle` 为消费者 cycle 状态（CCS）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EventCursor {
+    pub segment: usize,
+    pub index: usize,
+    pub cycle: bool,
+}
+
+impl EventCursor {
+    pub fn new() -> Self {
+        Self {
+            segment: 0,
+            index: 0,
+            cycle: true,
+        }
+    }
+
+    /// 移到下一个 TRB，`segments` 与 `segment_len` 为段数与每段 TRB 数
+    pub fn advance(&mut self, segments: usize, segment_len: usize) {
+        self.index += 1;
+        if self.index < segment_len {
+            return;
+        }
+        self.index = 0;
+        self.segment += 1;
+        if self.segment >= segments {
+            self.segment = 0;
+            self.cycle = !self.cycle;
+        }
+    }
+
+    /// 写入 ERDP 的 DESI 字段，只保留低 3 位
+    pub fn desi(&self) -> u8 {
+        (self.segment & 0b111) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_count_is_clamped() {
+        assert_eq!(segment_count(0, 8), 1);
+        assert_eq!(segment_count(4, 8), 4);
+        assert_eq!(segment_count(16, 8), 8);
+        assert_eq!(segment_count(4, 0), 1);
+    }
+
+    #[test]
+    fn cycle_flips_after_last_segment() {
+        let mut cursor = EventCursor::new();
+        for _ in 0..3 {
+            cursor.advance(3, 4);
+        }
+        assert_eq!((cursor.segment, cursor.index, cursor.cycle), (0, 3, true));
+
+        cursor.advance(3, 4);
+        assert_eq!((cursor.segment, cursor.index, cursor.cycle), (1, 0, true));
+
+        for _ in 0..8 {
+            cursor.advance(3, 4);
+        }
+        assert_eq!(
+            cursor,
+            EventCursor {
+                segment: 0,
+                index: 0,
+                cycle: false,
+            }
+        );
+    }
+
+    #[test]
+    fn desi_wraps_to_three_bits() {
+        let mut cursor = EventCursor::new();
+        for _ in 0..9 {
+            cursor.advance(16, 1);
+        }
+        assert_eq!(cursor.segment, 9);
+        assert_eq!(cursor.desi(), 1);
+    }
+}
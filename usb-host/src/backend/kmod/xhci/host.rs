@@ -1,31 +1,41 @@
-use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
 use core::{cell::UnsafeCell, time::Duration};
 
 use ::xhci::{
     ExtendedCapability,
     extended_capabilities::{List, usb_legacy_support_capability::UsbLegacySupport},
     registers::doorbell,
-    ring::trb::{command, event::CommandCompletion},
+    ring::trb::{
+        command,
+        event::{CommandCompletion, CompletionCode},
+    },
 };
 use dma_api::DmaDirection;
 use futures::{FutureExt, future::BoxFuture};
 use mbarrier::mb;
-use spin::RwLock;
+use spin::{Mutex, RwLock};
 use usb_if::err::{TransferError, USBError};
 
 use super::{
-    Device, SlotId,
+    Device, EnumerationMode, ImodPolicy, InitProgress, InitStage, InterrupterMap, SlotId,
+    TransferRingSize, XhciConfig,
     cmd::CommandRing,
     context::{DeviceContextList, ScratchpadBufferArray},
     event::{EventRing, EventRingInfo},
+    event_cursor,
     hub::{PortChangeWaker, XhciRootHub},
+    imod::AdaptiveImod,
+    isoch::IsochCaps,
     reg::{MemMapper, XhciRegisters},
     transfer::TransferResultHandler,
 };
 use crate::{
     DeviceAddressInfo, KernelOp, Mmio,
     backend::{
-        kmod::{hub::HubOp, kcore::CoreOp, xhci::reg::SlotBell},
+        kmod::{
+            PerfCounters, SelfTestReport, hub::HubOp, iommu::IommuOp, kcore::CoreOp, le::DmaLe,
+            perf::PerfStats, xhci::reg::SlotBell,
+        },
         ty::{DeviceOp, Event, EventHandlerOp},
     },
     err::Result,
@@ -39,10 +49,14 @@ pub struct Xhci {
     pub(crate) cmd: CommandRing,
     dev_ctx: Option<DeviceContextList>,
     event_handler: Option<EventHandler>,
-    event_ring_info: EventRingInfo,
+    /// 各中断器的事件环，下标即中断器编号
+    event_ring_info: Vec<EventRingInfo>,
     scratchpad_buf_arr: Option<ScratchpadBufferArray>,
     pub(crate) transfer_result_handler: TransferResultHandler,
     root_hub: Option<XhciRootHub>,
+    stats: Arc<PerfStats>,
+    config: XhciConfig,
+    slots: BTreeMap<SlotId, Arc<Mutex<SlotBell>>>,
 }
 
 unsafe impl Send for Xhci {}
@@ -79,10 +93,74 @@ impl CoreOp for Xhci {
     fn kernel(&self) -> &Kernel {
         &self.kernel
     }
+
+    fn perf_counters(&self) -> PerfCounters {
+        PerfCounters {
+            imod_policy: self.config.imod,
+            ..self.stats.snapshot()
+        }
+    }
+
+    fn self_test<'a>(&'a mut self, count: u32) -> BoxFuture<'a, Result<SelfTestReport>> {
+        self._self_test(count).boxed()
+    }
+
+    fn eject_slot<'a>(
+        &'a mut self,
+        slot_id: u8,
+        power_off_port: Option<u8>,
+    ) -> BoxFuture<'a, Result> {
+        self._eject_slot(slot_id.into(), power_off_port).boxed()
+    }
+
+    fn enumeration_mode(&self) -> EnumerationMode {
+        self.config.enumeration
+    }
+
+    fn frame_index(&self) -> Option<u16> {
+        let reg = self.reg.read();
+        let sts = reg.operational.usbsts.read_volatile();
+        if sts.hc_halted() || sts.controller_not_ready() {
+            return None;
+        }
+        Some(reg.runtime.mfindex.read_volatile().microframe_index())
+    }
+
+    #[cfg(feature = "fault-injection")]
+    fn inject_port_disable(&mut self, port: u8) -> Result {
+        let idx = (port as usize)
+            .checked_sub(1)
+            .ok_or(USBError::InvalidParameter)?;
+        let mut reg = self.reg.write();
+        if idx >= reg.port_register_set.len() {
+            return Err(USBError::InvalidParameter);
+        }
+        warn!("Fault injection: disabling root port {port}");
+        reg.port_register_set.update_volatile_at(idx, |r| {
+            // 只写 PED，保留各变化位交给正常的端口变化处理
+            r.portsc.set_0_connect_status_change();
+            r.portsc.set_0_port_enabled_disabled_change();
+            r.portsc.set_0_warm_port_reset_change();
+            r.portsc.set_0_over_current_change();
+            r.portsc.set_0_port_reset_change();
+            r.portsc.set_0_port_link_state_change();
+            r.portsc.set_0_port_config_error_change();
+            r.portsc.clear_port_enabled_disabled();
+        });
+        Ok(())
+    }
 }
 
 impl Xhci {
     pub fn new(mmio: Mmio, kernel: &'static dyn KernelOp) -> Result<Self> {
+        Self::new_with_config(mmio, kernel, XhciConfig::default())
+    }
+
+    pub fn new_with_config(
+        mmio: Mmio,
+        kernel: &'static dyn KernelOp,
+        config: XhciConfig,
+    ) -> Result<Self> {
         let reg = XhciRegisters::new(mmio);
 
         // 检查 xHCI 控制器的寻址能力（HCCPARAMS1 寄存器）
@@ -103,75 +181,144 @@ impl Xhci {
             u32::MAX as usize
         };
 
-        let kernel = Kernel::new(dma_mask as _, kernel);
+        let kernel = match config.iommu {
+            Some(domain) => Kernel::new(dma_mask as _, IommuOp::leak(kernel, domain)),
+            None => Kernel::new(dma_mask as _, kernel),
+        };
 
         let reg_shared = Arc::new(RwLock::new(reg.clone()));
-
-        let cmd = CommandRing::new(DmaDirection::Bidirectional, &kernel, reg_shared.clone())?;
+        let stats = Arc::new(PerfStats::default());
+
+        let cmd = CommandRing::new(
+            DmaDirection::Bidirectional,
+            &kernel,
+            reg_shared.clone(),
+            stats.clone(),
+        )?;
         let cmd_finished = cmd.finished_handle();
-        let event_ring = EventRing::new(&kernel)?;
-        let event_ring_info = event_ring.info();
+        let hcsparams1 = reg.capability.hcsparams1.read_volatile();
+        let max_intrs = hcsparams1.number_of_interrupts().max(1);
+        let interrupters = config.interrupters.clamp(1, max_intrs);
+        if interrupters < config.interrupters {
+            warn!(
+                "xHCI: MaxIntrs is {max_intrs}, using {interrupters} interrupters (requested {})",
+                config.interrupters
+            );
+        }
 
-        let root_hub = XhciRootHub::new(reg.clone())?;
+        let erst_max = reg
+            .capability
+            .hcsparams2
+            .read_volatile()
+            .event_ring_segment_table_max();
+        let segments = event_cursor::segment_count(config.event_ring_segments, erst_max);
+        if segments < config.event_ring_segments {
+            warn!(
+                "xHCI: ERST Max is {erst_max}, event ring limited to {segments} segments (requested {})",
+                config.event_ring_segments
+            );
+        }
+        let mut event_rings = Vec::with_capacity(interrupters as usize);
+        for _ in 0..interrupters {
+            event_rings.push(EventRing::new_with_segments(&kernel, segments)?);
+        }
+        debug!(
+            "Event rings: {interrupters} interrupters, {segments} segments, {} TRBs each",
+            event_rings[0].capacity()
+        );
+        let event_ring_info = event_rings.iter().map(EventRing::info).collect();
+
+        let root_hub = XhciRootHub::new(reg.clone(), kernel.clone())?;
 
         let transfer_result_handler = TransferResultHandler::new(reg_shared.clone());
         let ports = root_hub.waker();
 
+        let interrupters = event_rings
+            .into_iter()
+            .enumerate()
+            .map(|(index, ring)| {
+                Interrupter::new(index, ring, AdaptiveImod::new(config.imod, &kernel))
+            })
+            .collect();
+        let event_handler = EventHandler::new(
+            reg,
+            cmd_finished,
+            interrupters,
+            transfer_result_handler.clone(),
+            ports,
+            stats.clone(),
+        );
+
         Ok(Xhci {
             reg: reg_shared,
             kernel,
             cmd,
             dev_ctx: None,
-            transfer_result_handler: transfer_result_handler.clone(),
-            event_handler: Some(EventHandler::new(
-                reg,
-                cmd_finished,
-                event_ring,
-                transfer_result_handler,
-                ports,
-            )),
+            transfer_result_handler,
+            event_handler: Some(event_handler),
             root_hub: Some(root_hub),
             event_ring_info,
             scratchpad_buf_arr: None,
+            stats,
+            config,
+            slots: BTreeMap::new(),
         })
     }
 
     async fn _init(&mut self) -> Result {
         self.disable_irq();
         // 4.2 Host Controller Initialization
+        let t = self.stage_begin(InitStage::ExtCaps);
         self.init_ext_caps().await?;
+        self.stage_end(InitStage::ExtCaps, t);
         // After Chip Hardware Reset6 wait until the Controller Not Ready (CNR) flag
         // in the USBSTS is ‘0’ before writing any xHC Operational or Runtime
         // registers.
+        let t = self.stage_begin(InitStage::Reset);
         self.chip_hardware_reset().await?;
+        self.stage_end(InitStage::Reset, t);
 
         self.disable_irq();
 
         // Program the Max Device Slots Enabled (MaxSlotsEn) field in the CONFIG
         // register (5.4.7) to enable the device slots that system software is going to
         // use.
+        let t = self.stage_begin(InitStage::Slots);
         let max_slots = self.setup_max_device_slots();
         self.dev_ctx = Some(DeviceContextList::new(max_slots as _, self.kernel())?);
+        self.stage_end(InitStage::Slots, t);
 
         // Program the Device Context Base Address Array Pointer (DCBAAP)
         // register (5.4.6) with a 64-bit address pointing to where the Device
         // Context Base Address Array is located.
+        let t = self.stage_begin(InitStage::Dcbaa);
         self.setup_dcbaap()?;
+        self.stage_end(InitStage::Dcbaa, t);
 
         // Define the Command Ring Dequeue Pointer by programming the
         // Command Ring Control Register (5.4.5) with a 64-bit address pointing to
         // the starting address of the first TRB of the Command Ring.
+        let t = self.stage_begin(InitStage::CommandRing);
         self.set_cmd_ring()?;
+        self.stage_end(InitStage::CommandRing, t);
+
+        let t = self.stage_begin(InitStage::Irq);
         self.init_irq()?;
+        self.stage_end(InitStage::Irq, t);
+
+        let t = self.stage_begin(InitStage::Scratchpads);
         self.setup_scratchpads()?;
+        self.stage_end(InitStage::Scratchpads, t);
         // At this point, the host controller is up and running and the Root Hub ports
         // (5.4.8) will begin reporting device connects, etc., and system software may begin
         // enumerating devices. System software may follow the procedures described in
         // section 4.3, to enumerate attached devices.
+        let t = self.stage_begin(InitStage::Start);
         self.start();
         mb();
 
         self.wait_for_running().await;
+        self.stage_end(InitStage::Start, t);
 
         self.enable_irq();
         // self.reset_ports().await;
@@ -179,6 +326,22 @@ impl Xhci {
         Ok(())
     }
 
+    /// 上报阶段开始，返回开始时间
+    fn stage_begin(&self, stage: InitStage) -> Duration {
+        if let Some(f) = self.config.on_init_progress {
+            f(InitProgress::Begin(stage));
+        }
+        self.kernel.now()
+    }
+
+    fn stage_end(&self, stage: InitStage, start: Duration) {
+        let elapsed = self.kernel.now().saturating_sub(start);
+        debug!("init stage {stage:?} took {elapsed:?}");
+        if let Some(f) = self.config.on_init_progress {
+            f(InitProgress::End { stage, elapsed });
+        }
+    }
+
     async fn new_device(&mut self, info: DeviceAddressInfo) -> Result<Box<dyn DeviceOp>> {
         let mut device = Device::new(self).await?;
         device.init(self, &info).await?;
@@ -374,47 +537,41 @@ impl Xhci {
     }
 
     fn init_irq(&mut self) -> Result {
-        let erstz = self.event_ring_info.erstz;
-        let erdp = self.event_ring_info.erdp;
-        let erstba = self.event_ring_info.erstba;
+        let interval = match self.config.imod {
+            ImodPolicy::Fixed(interval) => interval,
+            ImodPolicy::Adaptive { min, max, .. } => min.min(max),
+        };
+        debug!("IMOD: {:?}, initial interval {interval}", self.config.imod);
 
-        {
+        for (index, info) in self.event_ring_info.iter().enumerate() {
             let mut reg = self.reg.write();
-            let mut ir0 = reg.interrupter_register_set.interrupter_mut(0);
-
-            debug!("ERDP: {erdp:x}");
-
-            ir0.erdp.update_volatile(|r| {
-                r.set_event_ring_dequeue_pointer(erdp);
+            let mut ir = reg.interrupter_register_set.interrupter_mut(index);
+
+            debug!(
+                "Interrupter {index}: ERSTSZ {:x}, ERSTBA {:X}, ERDP {:x}",
+                info.erstz, info.erstba, info.erdp
+            );
+            ir.erstsz.update_volatile(|r| r.set(info.erstz as _));
+            ir.erdp.update_volatile(|r| {
+                r.set_event_ring_dequeue_pointer(info.erdp);
                 r.set_dequeue_erst_segment_index(0);
                 r.clear_event_handler_busy();
             });
-
-            debug!("ERSTZ: {erstz:x}");
-            ir0.erstsz.update_volatile(|r| r.set(erstz as _));
-            debug!("ERSTBA: {erstba:X}");
-            ir0.erstba.update_volatile(|r| {
-                r.set(erstba);
+            // 写 ERSTBA 后控制器开始使用事件环，须在 ERSTSZ 与 ERDP 之后
+            ir.erstba.update_volatile(|r| {
+                r.set(info.erstba);
             });
 
-            ir0.imod.update_volatile(|im| {
-                im.set_interrupt_moderation_interval(0x1F);
+            ir.imod.update_volatile(|im| {
+                im.set_interrupt_moderation_interval(interval);
                 im.set_interrupt_moderation_counter(0);
             });
+            ir.iman.update_volatile(|im| {
+                im.set_interrupt_enable();
+                im.clear_interrupt_pending();
+            });
         }
-
-        {
-            debug!("Enabling primary interrupter.");
-            self.reg
-                .write()
-                .interrupter_register_set
-                .interrupter_mut(0)
-                .iman
-                .update_volatile(|im| {
-                    im.set_interrupt_enable();
-                    im.clear_interrupt_pending();
-                });
-        }
+        self.stats.imod(interval, false);
 
         /* Set the HCD state before we enable the irqs */
         self.reg.write().operational.usbcmd.update_volatile(|r| {
@@ -440,11 +597,12 @@ impl Xhci {
             if buf_count == 0 {
                 return Ok(());
             }
-            let scratchpad_buf_arr = ScratchpadBufferArray::new(buf_count as _, &self.kernel)?;
+            let scratchpad_buf_arr =
+                ScratchpadBufferArray::new(buf_count as _, &self.config.scratchpad, &self.kernel)?;
 
             let bus_addr = scratchpad_buf_arr.bus_addr();
 
-            self.dev_mut()?.dcbaa.set(0, bus_addr);
+            self.dev_mut()?.dcbaa.set_le(0, bus_addr);
 
             debug!("Setting up {buf_count} scratchpads, at {bus_addr:#0x}");
             scratchpad_buf_arr
@@ -478,6 +636,79 @@ impl Xhci {
             .write()
             .doorbell
             .write_volatile_at(0, doorbell::Register::default());
+        self.stats.doorbell();
+    }
+
+    async fn _self_test(&mut self, count: u32) -> Result<SelfTestReport> {
+        if self.dev_ctx.is_none() {
+            return Err(USBError::NotInitialized);
+        }
+
+        let events_before = self.stats.snapshot().events;
+        let mut report = SelfTestReport {
+            min_latency: Duration::MAX,
+            ..Default::default()
+        };
+        let mut total = Duration::ZERO;
+
+        for _ in 0..count {
+            let start = self.kernel.now();
+            self.cmd_request(command::Allowed::Noop(command::Noop::new()))
+                .await?;
+            let latency = self.kernel.now().saturating_sub(start);
+
+            report.commands += 1;
+            report.min_latency = report.min_latency.min(latency);
+            report.max_latency = report.max_latency.max(latency);
+            total += latency;
+        }
+
+        report.events = self.stats.snapshot().events - events_before;
+        if report.commands == 0 {
+            report.min_latency = Duration::ZERO;
+        } else {
+            report.avg_latency = total / report.commands;
+        }
+
+        // 每条 NoOp 命令都应在主中断器的事件环上产生一个命令完成事件
+        if report.events < report.commands as u64 {
+            return Err(anyhow!(
+                "self test: {} commands completed but only {} events delivered",
+                report.commands,
+                report.events
+            )
+            .into());
+        }
+
+        // 命令完成事件只送往主中断器，其余中断器无法用命令触发，逐个回读事件环与中断使能
+        for (index, info) in self.event_ring_info.iter().enumerate() {
+            self.check_interrupter(index, info)?;
+            report.interrupters += 1;
+        }
+
+        debug!("Self test: {report:?}");
+        Ok(report)
+    }
+
+    /// 确认中断器 `index` 的寄存器仍是 [`Self::init_irq`] 写入的事件环配置
+    fn check_interrupter(&self, index: usize, info: &EventRingInfo) -> Result {
+        let reg = self.reg.read();
+        let ir = reg.interrupter_register_set.interrupter(index);
+        let erstsz = ir.erstsz.read_volatile().get();
+        let erstba = ir.erstba.read_volatile().get();
+        if erstsz != info.erstz || erstba != info.erstba {
+            return Err(anyhow!(
+                "self test: interrupter {index} event ring is ERSTSZ {erstsz}, ERSTBA {erstba:#x}, \
+                 expected ERSTSZ {}, ERSTBA {:#x}",
+                info.erstz,
+                info.erstba
+            )
+            .into());
+        }
+        if !ir.iman.read_volatile().interrupt_enable() {
+            return Err(anyhow!("self test: interrupter {index} is not enabled").into());
+        }
+        Ok(())
     }
 
     pub(crate) fn cmd_request(
@@ -496,8 +727,123 @@ impl Xhci {
             .context_size()
     }
 
-    pub(crate) fn new_slot_bell(&self, slot: SlotId) -> SlotBell {
-        SlotBell::new(slot, self.reg.read().clone())
+    /// HCCPARAMS1.MaxPSASize，0 表示控制器不支持流
+    pub(crate) fn interrupter_map(&self) -> InterrupterMap {
+        self.config.interrupter_map
+    }
+
+    pub(crate) fn interrupter_count(&self) -> u16 {
+        self.event_ring_info.len() as u16
+    }
+
+    pub(crate) fn max_psa_size(&self) -> u8 {
+        self.reg
+            .read()
+            .capability
+            .hccparams1
+            .read_volatile()
+            .maximum_primary_stream_array_size()
+    }
+
+    pub(crate) fn transfer_ring(&self) -> TransferRingSize {
+        self.config.transfer_ring
+    }
+
+    /// HCSPARAMS2.IST 与 HCCPARAMS1.CFC
+    pub(crate) fn isoch_caps(&self) -> IsochCaps {
+        let reg = self.reg.read();
+        IsochCaps::new(
+            reg.capability
+                .hcsparams2
+                .read_volatile()
+                .isochronous_scheduling_threshold(),
+            reg.capability
+                .hccparams1
+                .read_volatile()
+                .contiguous_frame_id_capability(),
+        )
+    }
+
+    pub(crate) fn new_slot_bell(&mut self, slot: SlotId) -> Arc<Mutex<SlotBell>> {
+        let bell = SlotBell::new(slot, self.reg.read().clone(), self.stats.clone());
+        let bell = Arc::new(Mutex::new(bell));
+        self.slots.insert(slot, bell.clone());
+        bell
+    }
+
+    /// 停止槽上全部端点、禁用槽并回收其在 DCBAA 中的表项，可选地关闭根端口电源
+    ///
+    /// 槽禁用后，仍由用户持有的端点提交传输会返回 [`TransferError::NoDevice`]。
+    async fn _eject_slot(&mut self, slot_id: SlotId, power_off_port: Option<u8>) -> Result {
+        let port_idx = match power_off_port {
+            Some(port) => {
+                let idx = (port as usize)
+                    .checked_sub(1)
+                    .ok_or(USBError::InvalidParameter)?;
+                let reg = self.reg.read();
+                if idx >= reg.port_register_set.len() {
+                    return Err(USBError::InvalidParameter);
+                }
+                // HCCPARAMS1.PPC 为 0 时 PORTSC.PP 只读，端口电源不可控
+                if !reg
+                    .capability
+                    .hccparams1
+                    .read_volatile()
+                    .port_power_control()
+                {
+                    return Err(USBError::NotSupported);
+                }
+                Some(idx)
+            }
+            None => None,
+        };
+
+        let bell = self.slots.remove(&slot_id).ok_or(USBError::NotFound)?;
+        let dcis: Vec<u8> = bell.lock().active_dcis().collect();
+
+        for dci in dcis {
+            // 未运行的端点会返回 Context State Error，不影响后续禁用槽
+            if let Err(e) = self
+                .cmd_request(command::Allowed::StopEndpoint(
+                    *command::StopEndpoint::default()
+                        .set_slot_id(slot_id.as_u8())
+                        .set_endpoint_id(dci),
+                ))
+                .await
+            {
+                debug!("Stop endpoint {dci} of slot {slot_id}: {e:?}");
+            }
+        }
+
+        bell.lock().close();
+
+        self.cmd_request(command::Allowed::DisableSlot(
+            *command::DisableSlot::default().set_slot_id(slot_id.as_u8()),
+        ))
+        .await?;
+        self.dev_mut()?.release_ctx(slot_id);
+        self.transfer_result_handler
+            .unregister_slot(slot_id.as_u8());
+        info!("Slot {slot_id} disabled");
+
+        if let Some(idx) = port_idx {
+            self.reg
+                .write()
+                .port_register_set
+                .update_volatile_at(idx, |r| {
+                    // 只清 PP，避免写 1 清除各变化位
+                    r.portsc.set_0_connect_status_change();
+                    r.portsc.set_0_port_enabled_disabled_change();
+                    r.portsc.set_0_warm_port_reset_change();
+                    r.portsc.set_0_over_current_change();
+                    r.portsc.set_0_port_reset_change();
+                    r.portsc.set_0_port_link_state_change();
+                    r.portsc.set_0_port_config_error_change();
+                    r.portsc.clear_port_power();
+                });
+            info!("Root port {} powered off", idx + 1);
+        }
+        Ok(())
     }
 
     pub(crate) async fn device_slot_assignment(
@@ -514,12 +860,43 @@ impl Xhci {
     }
 }
 
+/// 中断器及其事件环
+struct Interrupter {
+    index: usize,
+    event_ring: UnsafeCell<EventRing>,
+    /// 处理多少个事件后中途推进一次 ERDP
+    erdp_batch: u64,
+    imod: UnsafeCell<Option<AdaptiveImod>>,
+}
+
+impl Interrupter {
+    fn new(index: usize, event_ring: EventRing, imod: Option<AdaptiveImod>) -> Self {
+        Self {
+            index,
+            erdp_batch: (event_ring.capacity() / 4).max(1) as u64,
+            event_ring: UnsafeCell::new(event_ring),
+            imod: UnsafeCell::new(imod),
+        }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn event_ring(&self) -> &mut EventRing {
+        unsafe { &mut *self.event_ring.get() }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn imod(&self) -> &mut Option<AdaptiveImod> {
+        unsafe { &mut *self.imod.get() }
+    }
+}
+
 pub struct EventHandler {
     reg: UnsafeCell<XhciRegisters>,
     cmd_finished: Finished<CommandCompletion>,
-    event_ring: UnsafeCell<EventRing>,
+    interrupters: Vec<Interrupter>,
     transfer_result_handler: TransferResultHandler,
     ports: PortChangeWaker,
+    stats: Arc<PerfStats>,
 }
 
 unsafe impl Send for EventHandler {}
@@ -529,34 +906,35 @@ impl EventHandler {
     fn new(
         reg: XhciRegisters,
         cmd_finished: Finished<CommandCompletion>,
-        event_ring: EventRing,
+        interrupters: Vec<Interrupter>,
         transfer_result_handler: TransferResultHandler,
         ports: PortChangeWaker,
+        stats: Arc<PerfStats>,
     ) -> Self {
         Self {
             reg: UnsafeCell::new(reg),
             cmd_finished,
-            event_ring: UnsafeCell::new(event_ring),
+            interrupters,
             transfer_result_handler,
             ports,
+            stats,
         }
     }
 
-    #[allow(clippy::mut_from_ref)]
-    fn event_ring(&self) -> &mut EventRing {
-        unsafe { &mut *self.event_ring.get() }
-    }
-
     #[allow(clippy::mut_from_ref)]
     fn reg(&self) -> &mut XhciRegisters {
         unsafe { &mut *self.reg.get() }
     }
 
-    fn clean_event_ring(&self) -> Event {
+    /// 返回最后一个端口事件与本次处理的事件数
+    fn clean_event_ring(&self, ir: &Interrupter) -> (Event, u64) {
         use xhci::ring::trb::event::Allowed;
         let mut event = Event::Nothing;
+        let mut count: u64 = 0;
 
-        while let Some(allowed) = self.event_ring().next() {
+        while let Some(allowed) = ir.event_ring().next() {
+            self.stats.event();
+            count += 1;
             match allowed {
                 Allowed::CommandCompletion(c) => {
                     let addr = c.command_trb_pointer();
@@ -586,11 +964,73 @@ impl EventHandler {
                             .set_finished(slot_id, ep_id, ptr.into(), c)
                     };
                 }
+                Allowed::HostController(c)
+                    if c.completion_code() == Ok(CompletionCode::EventRingFullError) =>
+                {
+                    // 控制器在写满后暂停写入，推进 ERDP 后继续，期间的事件不会丢失；
+                    // 中断中不输出日志，通过性能计数器观察
+                    self.stats.event_ring_full();
+                }
                 _ => {
                     // debug!("unhandled event {allowed:?}");
                 }
             }
+
+            // 大量事件时中途推进 ERDP，让控制器尽早复用已处理的 TRB
+            if count.is_multiple_of(ir.erdp_batch) {
+                self.update_erdp(ir, false);
+            }
+        }
+        (event, count)
+    }
+
+    /// 把软件出队位置写入 ERDP，`clear_busy` 为真时同时清除 EHB
+    fn update_erdp(&self, ir: &Interrupter, clear_busy: bool) {
+        let erdp = ir.event_ring().erdp();
+        let desi = ir.event_ring().desi();
+        let mut regs = self
+            .reg()
+            .interrupter_register_set
+            .interrupter_mut(ir.index);
+        regs.erdp.update_volatile(|r| {
+            r.set_event_ring_dequeue_pointer(erdp);
+            r.set_dequeue_erst_segment_index(desi);
+            if clear_busy {
+                r.clear_event_handler_busy();
+            } else {
+                r.set_0_event_handler_busy();
+            }
+        });
+    }
+
+    /// 处理一个中断器：清除 IMAN.IP，取完事件环并按需调整节流间隔
+    fn service(&self, ir: &Interrupter) -> Event {
+        // 【关键】GIC 中断模式下，需要手动清除 IMAN.IP
+        // 参考: Linux xhci_irq() in xhci-ring.c:3054-3059
+        self.reg()
+            .interrupter_register_set
+            .interrupter_mut(ir.index)
+            .iman
+            .update_volatile(|r| {
+                r.clear_interrupt_pending();
+            });
+
+        let (event, events) = self.clean_event_ring(ir);
+        self.update_erdp(ir, true);
+
+        if let Some(imod) = ir.imod()
+            && let Some(interval) = imod.on_irq(events)
+        {
+            self.reg()
+                .interrupter_register_set
+                .interrupter_mut(ir.index)
+                .imod
+                .update_volatile(|im| {
+                    im.set_interrupt_moderation_interval(interval);
+                });
+            self.stats.imod(interval, true);
         }
+
         event
     }
 }
@@ -607,25 +1047,30 @@ impl EventHandlerOp for EventHandler {
         self.reg().operational.usbsts.update_volatile(|r| {
             r.clear_event_interrupt();
         });
+        self.stats.irq();
 
-        // 【关键】GIC 中断模式下，需要手动清除 IMAN.IP
-        // 参考: Linux xhci_irq() in xhci-ring.c:3054-3059
-        let mut irq = self.reg().interrupter_register_set.interrupter_mut(0);
-        irq.iman.update_volatile(|r| {
-            r.clear_interrupt_pending();
-        });
-
-        let erdp = {
-            res = self.clean_event_ring();
-            self.event_ring().erdp()
-        };
-        {
-            irq.erdp.update_volatile(|r| {
-                r.set_event_ring_dequeue_pointer(erdp);
-                r.clear_event_handler_busy();
-            });
+        for ir in &self.interrupters {
+            if let event @ Event::PortChange { .. } = self.service(ir) {
+                res = event;
+            }
         }
 
         res
     }
+
+    fn handle_interrupter(&self, index: u16) -> Event {
+        let Some(ir) = self.interrupters.get(index as usize) else {
+            return Event::Nothing;
+        };
+        // MSI-X 下 IMAN.IP 由控制器自动清除，不能据此判断是否有事件，直接取事件环
+        self.reg().operational.usbsts.update_volatile(|r| {
+            r.clear_event_interrupt();
+        });
+        self.stats.irq();
+        self.service(ir)
+    }
+
+    fn interrupter_count(&self) -> u16 {
+        self.interrupters.len() as u16
+    }
 }
@@ -6,15 +6,33 @@ use alloc::{sync::Arc, vec::Vec};
 use core::{
     cell::UnsafeCell,
     sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+    time::Duration,
 };
 
 use futures::{FutureExt, future::BoxFuture, task::AtomicWaker};
 use usb_if::{err::USBError, host::hub::Speed};
 
-use crate::backend::kmod::hub::{HubInfo, HubOp, PortChangeInfo, PortState};
+use crate::backend::kmod::{
+    hub::{HubInfo, HubOp, HubPortStatus, PortChangeInfo, PortState},
+    port_event::PortTransition,
+};
+use crate::osal::Kernel;
 
 use super::reg::XhciRegisters;
 
+/// PORTSC.PLS 取值，见 xHCI 5.4.8
+const PLS_U0: u8 = 0;
+const PLS_U3: u8 = 3;
+const PLS_RESUME: u8 = 15;
+
+/// USB 2.0 恢复信号持续时间 TDRSMDN
+const RESUME_SIGNAL: Duration = Duration::from_millis(20);
+/// USB 2.0 恢复后设备的恢复时间 TRSMRCY
+const RESUME_RECOVERY: Duration = Duration::from_millis(10);
+/// 等待链路状态切换完成的上限
+const LINK_STATE_TIMEOUT: Duration = Duration::from_millis(100);
+
 pub struct PortChangeWaker {
     ports: Arc<UnsafeCell<Vec<Port>>>,
 }
@@ -32,6 +50,7 @@ impl PortChangeWaker {
                 change_waker: AtomicWaker::new(),
                 changed: AtomicBool::new(false),
                 state: PortState::Uninit,
+                suspended: false,
             });
         }
         Self {
@@ -39,13 +58,30 @@ impl PortChangeWaker {
         }
     }
 
+    /// 标记端口状态变化并唤醒等待者
+    ///
+    /// 在中断中调用：只操作原子变量，不加锁、不输出日志。
     pub fn set_port_changed(&self, port_id: u8) {
         let ports = unsafe { &*self.ports.get() };
         let idx = (port_id - 1) as usize;
-        debug!("Setting port {} changed", port_id);
         ports[idx].changed.store(true, Ordering::Release);
         ports[idx].change_waker.wake();
     }
+
+    /// 有端口被标记变化时返回 `Ready` 并清除全部标记
+    fn poll_changed(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let ports = unsafe { &*self.ports.get() };
+        let mut changed = false;
+        for port in ports {
+            port.change_waker.register(cx.waker());
+            changed |= port.changed.swap(false, Ordering::AcqRel);
+        }
+        if changed {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
 }
 
 pub struct Port {
@@ -53,6 +89,8 @@ pub struct Port {
     change_waker: AtomicWaker,
     changed: AtomicBool,
     state: PortState,
+    /// 已由软件挂起，远程唤醒或 [`HubOp::resume_port`] 完成后清除
+    suspended: bool,
 }
 
 /// xHCI Root Hub
@@ -61,8 +99,11 @@ pub struct Port {
 pub struct XhciRootHub {
     /// 寄存器访问
     reg: XhciRegisters,
+    kernel: Kernel,
 
     ports: Arc<UnsafeCell<Vec<Port>>>,
+    /// 清除变化位时记录的端口状态变化，见 [`HubOp::take_port_events`]
+    events: Vec<(u8, PortTransition)>,
 }
 
 unsafe impl Send for XhciRootHub {}
@@ -75,6 +116,134 @@ impl XhciRootHub {
     fn ports_mut(&mut self) -> &mut [Port] {
         unsafe { &mut *self.ports.get() }
     }
+
+    /// 清除端口的全部变化位，此后的状态变化才会再次产生 Port Status Change 事件
+    fn ack_port_changes(&mut self, idx: usize) {
+        let portsc = self.reg.port_register_set.read_volatile_at(idx).portsc;
+        let port = (idx + 1) as u8;
+        if portsc.over_current_change() && portsc.over_current_active() {
+            warn!("Port {port} over-current");
+            self.events.push((port, PortTransition::OverCurrent));
+        }
+        if portsc.connect_status_change() && portsc.current_connect_status() {
+            self.events.push((port, PortTransition::Connected));
+        }
+        // 控制器因错误禁用端口时只置位 PEC，软件复位不会置位
+        if portsc.port_enabled_disabled_change()
+            && !portsc.port_enabled_disabled()
+            && portsc.current_connect_status()
+        {
+            self.events.push((port, PortTransition::Error));
+        }
+        self.reg.port_register_set.update_volatile_at(idx, |reg| {
+            // PED 同为写 1 清除，写回读到的 1 会禁用端口
+            reg.portsc.set_0_port_enabled_disabled();
+            reg.portsc.clear_connect_status_change();
+            reg.portsc.clear_port_enabled_disabled_change();
+            reg.portsc.clear_warm_port_reset_change();
+            reg.portsc.clear_over_current_change();
+            reg.portsc.clear_port_reset_change();
+            reg.portsc.clear_port_link_state_change();
+            reg.portsc.clear_port_config_error_change();
+        });
+    }
+
+    fn port_index(&self, port: u8) -> Result<usize, USBError> {
+        let idx = (port as usize)
+            .checked_sub(1)
+            .ok_or(USBError::InvalidParameter)?;
+        if idx >= self.reg.port_register_set.len() {
+            return Err(USBError::InvalidParameter);
+        }
+        Ok(idx)
+    }
+
+    fn is_superspeed(&self, idx: usize) -> bool {
+        let portsc = self.reg.port_register_set.read_volatile_at(idx).portsc;
+        matches!(
+            Speed::from_xhci_portsc(portsc.port_speed()),
+            Speed::SuperSpeed | Speed::SuperSpeedPlus
+        )
+    }
+
+    /// 写入 PLS 请求链路状态切换
+    fn set_link_state(&mut self, idx: usize, pls: u8) {
+        self.reg.port_register_set.update_volatile_at(idx, |reg| {
+            // 只写 PLS，避免写 1 禁用端口或清除各变化位
+            reg.portsc.set_0_port_enabled_disabled();
+            reg.portsc.set_0_connect_status_change();
+            reg.portsc.set_0_port_enabled_disabled_change();
+            reg.portsc.set_0_warm_port_reset_change();
+            reg.portsc.set_0_over_current_change();
+            reg.portsc.set_0_port_reset_change();
+            reg.portsc.set_0_port_link_state_change();
+            reg.portsc.set_0_port_config_error_change();
+            reg.portsc.set_port_link_state(pls);
+            reg.portsc.set_port_link_state_write_strobe();
+        });
+    }
+
+    fn wait_link_state(&self, idx: usize, pls: u8) -> Result<(), USBError> {
+        let deadline = self.kernel.now() + LINK_STATE_TIMEOUT;
+        loop {
+            let portsc = self.reg.port_register_set.read_volatile_at(idx).portsc;
+            if portsc.port_link_state() == pls {
+                return Ok(());
+            }
+            if !portsc.current_connect_status() {
+                return Err(USBError::NotFound);
+            }
+            if self.kernel.now() >= deadline {
+                return Err(USBError::Timeout);
+            }
+            self.kernel.delay(Duration::from_millis(1));
+        }
+    }
+
+    async fn _suspend_port(&mut self, port: u8) -> Result<(), USBError> {
+        let idx = self.port_index(port)?;
+        let portsc = self.reg.port_register_set.read_volatile_at(idx).portsc;
+        if !portsc.port_enabled_disabled() {
+            return Err(USBError::NotFound);
+        }
+        if portsc.port_link_state() != PLS_U3 {
+            self.set_link_state(idx, PLS_U3);
+            self.wait_link_state(idx, PLS_U3)?;
+        }
+        self.ports_mut()[idx].suspended = true;
+        debug!("Port {port} suspended");
+        Ok(())
+    }
+
+    async fn _resume_port(&mut self, port: u8) -> Result<(), USBError> {
+        let idx = self.port_index(port)?;
+        let superspeed = self.is_superspeed(idx);
+        let portsc = self.reg.port_register_set.read_volatile_at(idx).portsc;
+        match portsc.port_link_state() {
+            // SuperSpeed 链路由 U3 直接进入 U0，USB 2.0 需要主机先发出恢复信号
+            PLS_U3 if superspeed => self.set_link_state(idx, PLS_U0),
+            PLS_U3 => {
+                self.set_link_state(idx, PLS_RESUME);
+                self.kernel.delay(RESUME_SIGNAL);
+                self.set_link_state(idx, PLS_U0);
+            }
+            // 设备发起的远程唤醒：控制器已在发送恢复信号，SuperSpeed 链路自行回到 U0
+            PLS_RESUME => {
+                if !superspeed {
+                    self.kernel.delay(RESUME_SIGNAL);
+                    self.set_link_state(idx, PLS_U0);
+                }
+            }
+            _ => {}
+        }
+        self.wait_link_state(idx, PLS_U0)?;
+        if !superspeed {
+            self.kernel.delay(RESUME_RECOVERY);
+        }
+        self.ports_mut()[idx].suspended = false;
+        debug!("Port {port} resumed");
+        Ok(())
+    }
 }
 
 impl HubOp for XhciRootHub {
@@ -112,15 +281,105 @@ impl HubOp for XhciRootHub {
     fn slot_id(&self) -> u8 {
         0
     }
+
+    fn wait_port_change(&mut self) -> BoxFuture<'_, ()> {
+        let waker = self.waker();
+        core::future::poll_fn(move |cx| waker.poll_changed(cx)).boxed()
+    }
+
+    fn suspend_port(&mut self, port: u8) -> BoxFuture<'_, Result<(), USBError>> {
+        self._suspend_port(port).boxed()
+    }
+
+    fn resume_port(&mut self, port: u8) -> BoxFuture<'_, Result<(), USBError>> {
+        self._resume_port(port).boxed()
+    }
+
+    fn take_resumed_ports(&mut self) -> Vec<u8> {
+        let suspended = self
+            .ports()
+            .iter()
+            .filter(|port| port.suspended)
+            .map(|p| p.port_id)
+            .collect::<Vec<_>>();
+
+        let mut out = Vec::new();
+        for id in suspended {
+            let i = (id - 1) as usize;
+            let portsc = self.reg.port_register_set.read_volatile_at(i).portsc;
+            if !portsc.current_connect_status() {
+                // 挂起期间拔出，按断开处理
+                self.ports_mut()[i].suspended = false;
+                continue;
+            }
+            if portsc.port_link_state() != PLS_U3 {
+                debug!("Port {id} remote wakeup");
+                out.push(id);
+            }
+        }
+        out
+    }
+
+    fn take_port_events(&mut self) -> Vec<(u8, PortTransition)> {
+        core::mem::take(&mut self.events)
+    }
+
+    fn port_status(&self) -> Vec<HubPortStatus> {
+        self.ports()
+            .iter()
+            .enumerate()
+            .map(|(idx, port)| {
+                let portsc = self.reg.port_register_set.read_volatile_at(idx).portsc;
+                let connected = portsc.current_connect_status();
+                HubPortStatus {
+                    port: port.port_id,
+                    state: port.state,
+                    connected,
+                    speed: (connected && portsc.port_enabled_disabled())
+                        .then(|| Speed::from_xhci_portsc(portsc.port_speed())),
+                }
+            })
+            .collect()
+    }
+
+    fn take_disconnected_ports(&mut self) -> Vec<u8> {
+        let probed = self
+            .ports()
+            .iter()
+            .filter(|port| matches!(port.state, PortState::Probed))
+            .map(|p| p.port_id)
+            .collect::<Vec<_>>();
+
+        let mut out = Vec::new();
+        for id in probed {
+            let i = (id - 1) as usize;
+            let portsc = self.reg.port_register_set.read_volatile_at(i).portsc;
+            self.ack_port_changes(i);
+            // CSC 置位而 CCS 仍为 1 说明设备已被换下，按断开处理后重新枚举
+            if portsc.current_connect_status() && !portsc.connect_status_change() {
+                continue;
+            }
+            debug!("Port {id} device disconnected");
+            self.ports_mut()[i].state = PortState::Reseted;
+            self.ports_mut()[i].suspended = false;
+            out.push(id);
+        }
+        out
+    }
 }
 
 impl XhciRootHub {
     /// 创建新的 xHCI Root Hub
-    pub fn new(reg: XhciRegisters) -> Result<Self, USBError> {
+    pub fn new(reg: XhciRegisters, kernel: Kernel) -> Result<Self, USBError> {
         let port_num = reg.port_register_set.len();
         let ports = PortChangeWaker::new(port_num as _).ports.clone();
 
-        Ok(Self { reg, ports })
+        Ok(Self {
+            reg,
+            kernel,
+            ports,
+            events: Vec::new(),
+        })
     }
 
     pub fn waker(&self) -> PortChangeWaker {
@@ -145,6 +404,7 @@ impl XhciRootHub {
         for &id in &uninited {
             debug!("Waiting for port {id} reset ...");
             let i = (id - 1) as usize;
+            self.ack_port_changes(i);
 
             let port = self.reg.port_register_set.read_volatile_at(i).portsc;
 
@@ -177,9 +437,21 @@ impl XhciRootHub {
 
         for &id in &reseted {
             let i = (id - 1) as usize;
+            self.ack_port_changes(i);
             let port_reg = self.reg.port_register_set.read_volatile_at(i);
-            if !port_reg.portsc.current_connect_status() || !port_reg.portsc.port_enabled_disabled()
-            {
+            if !port_reg.portsc.current_connect_status() {
+                continue;
+            }
+            if !port_reg.portsc.port_enabled_disabled() {
+                // 初始化之后接入的 USB 2.0 设备需要先复位端口才会启用
+                if !port_reg.portsc.port_reset() {
+                    debug!("Port {id} connected but disabled, resetting");
+                    self.reg.port_register_set.update_volatile_at(i, |reg| {
+                        reg.portsc.set_0_port_enabled_disabled();
+                        reg.portsc.set_port_reset();
+                    });
+                    self.ports_mut()[i].state = PortState::Uninit;
+                }
                 continue;
             }
             let speed_raw = port_reg.portsc.port_speed();
@@ -0,0 +1,171 @@
+//! 中断节流（IMOD）自适应调整
+//!
+//! 按固定窗口统计事件与中断速率：中断速率超过目标时加倍节流间隔以防中断风暴，
+//! 事件速率低于目标一半时减半间隔以降低延迟，两者之间保持不变形成滞回。
+
+use core::time::Duration;
+
+use super::ImodPolicy;
+use crate::osal::Kernel;
+
+/// 统计窗口
+const WINDOW: Duration = Duration::from_millis(20);
+
+/// IMODI 寄存器单位为 250ns
+pub(crate) const IMOD_UNIT_NS: u64 = 250;
+
+pub(crate) struct AdaptiveImod {
+    kernel: Kernel,
+    min: u16,
+    max: u16,
+    target_irq_rate: u32,
+    current: u16,
+    window_start: Duration,
+    events: u64,
+    irqs: u64,
+}
+
+impl AdaptiveImod {
+    /// 固定策略返回 `None`
+    pub fn new(policy: ImodPolicy, kernel: &Kernel) -> Option<Self> {
+        match policy {
+            ImodPolicy::Fixed(_) => None,
+            ImodPolicy::Adaptive {
+                min,
+                max,
+                target_irq_rate,
+            } => Some(Self {
+                kernel: kernel.clone(),
+                min: min.min(max),
+                max,
+                target_irq_rate: target_irq_rate.max(1),
+                current: min.min(max),
+                window_start: kernel.now(),
+                events: 0,
+                irqs: 0,
+            }),
+        }
+    }
+
+    /// 记录一次中断及其处理的事件数，需要调整时返回新的间隔
+    ///
+    /// 在中断中调用，不输出日志；调整结果通过性能计数器观察。
+    pub fn on_irq(&mut self, events: u64) -> Option<u16> {
+        let now = self.kernel.now();
+        self.irqs += 1;
+        self.events += events;
+
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed < WINDOW {
+            return None;
+        }
+        let per_sec = |n: u64| (n as u128 * 1_000_000_000 / elapsed.as_nanos().max(1)) as u64;
+        let irq_rate = per_sec(self.irqs);
+        let event_rate = per_sec(self.events);
+        self.window_start = now;
+        self.events = 0;
+        self.irqs = 0;
+
+        let target = self.target_irq_rate as u64;
+        let next = if irq_rate > target {
+            self.current
+                .saturating_mul(2)
+                .max(self.current.saturating_add(1))
+        } else if event_rate < target / 2 {
+            self.current / 2
+        } else {
+            self.current
+        }
+        .clamp(self.min, self.max);
+
+        if next == self.current {
+            return None;
+        }
+        self.current = next;
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::kmod::test_kernel::ClockKernel;
+
+    const TARGET: u32 = 1000;
+
+    fn adaptive(min: u16, max: u16) -> (&'static ClockKernel, AdaptiveImod) {
+        let clock = ClockKernel::leak();
+        let policy = ImodPolicy::Adaptive {
+            min,
+            max,
+            target_irq_rate: TARGET,
+        };
+        let imod = AdaptiveImod::new(policy, &clock.kernel()).unwrap();
+        (clock, imod)
+    }
+
+    /// 在一个统计窗口内产生 `irqs` 次中断，每次处理 `events` 个事件，返回窗口结束时的调整
+    fn window(clock: &ClockKernel, imod: &mut AdaptiveImod, irqs: u64, events: u64) -> Option<u16> {
+        for _ in 1..irqs {
+            assert_eq!(imod.on_irq(events), None, "adjusted inside the window");
+        }
+        clock.advance(WINDOW);
+        imod.on_irq(events)
+    }
+
+    #[test]
+    fn fixed_policy_is_not_adaptive() {
+        let kernel = ClockKernel::leak().kernel();
+        assert!(AdaptiveImod::new(ImodPolicy::Fixed(0x1f), &kernel).is_none());
+    }
+
+    #[test]
+    fn irq_storm_doubles_up_to_max() {
+        let (clock, mut imod) = adaptive(4, 20);
+        // 20ms 内 40 次中断即 2000 次/秒，超过目标
+        assert_eq!(window(clock, &mut imod, 40, 1), Some(8));
+        assert_eq!(window(clock, &mut imod, 40, 1), Some(16));
+        assert_eq!(window(clock, &mut imod, 40, 1), Some(20));
+        assert_eq!(window(clock, &mut imod, 40, 1), None);
+    }
+
+    #[test]
+    fn quiet_period_halves_down_to_min() {
+        let (clock, mut imod) = adaptive(4, 64);
+        for _ in 0..4 {
+            window(clock, &mut imod, 40, 1);
+        }
+        assert_eq!(imod.current, 64);
+
+        // 1 个事件 / 20ms 即 50 次/秒，低于目标一半
+        assert_eq!(window(clock, &mut imod, 1, 1), Some(32));
+        assert_eq!(window(clock, &mut imod, 1, 1), Some(16));
+        assert_eq!(window(clock, &mut imod, 1, 1), Some(8));
+        assert_eq!(window(clock, &mut imod, 1, 1), Some(4));
+        assert_eq!(window(clock, &mut imod, 1, 1), None);
+    }
+
+    #[test]
+    fn hysteresis_keeps_interval() {
+        let (clock, mut imod) = adaptive(4, 64);
+        window(clock, &mut imod, 40, 1);
+        // 中断 500 次/秒不超过目标，事件 5000 次/秒不低于目标一半
+        assert_eq!(window(clock, &mut imod, 10, 10), None);
+        assert_eq!(imod.current, 8);
+    }
+
+    #[test]
+    fn zero_interval_can_grow() {
+        let (clock, mut imod) = adaptive(0, 8);
+        assert_eq!(window(clock, &mut imod, 40, 1), Some(1));
+        assert_eq!(window(clock, &mut imod, 40, 1), Some(2));
+    }
+
+    #[test]
+    fn min_above_max_is_clamped() {
+        let (clock, mut imod) = adaptive(32, 8);
+        assert_eq!(imod.current, 8);
+        assert_eq!(window(clock, &mut imod, 40, 1), None);
+        assert_eq!(window(clock, &mut imod, 1, 1), None);
+    }
+}
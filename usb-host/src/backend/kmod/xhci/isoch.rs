@@ -0,0 +1,274 @@
+//! 等时 TD 的帧调度
+//!
+//! 控制器支持 Contiguous Frame ID（HCCPARAMS1.CFC）时，每个 TD 的 Frame ID 按端点周期
+//! 连续排列，TD 在预定的帧上执行，不受提交时刻抖动的影响；不支持时所有 TD 置 SIA，
+//! 由控制器尽快调度。
+//!
+//! 连续排列的前提是环中始终有待执行的 TD。环被取空（Ring Underrun/Overrun）、
+//! 控制器错过服务周期（Missed Service Error）或预定的帧已经过去时，排程失效，
+//! 下一次提交从当前 MFINDEX 之后重新对齐。
+//!
+//! 参考 xHCI 规范 4.11.2.5（Isoch TRB 的 Frame ID 与 SIA）与 4.14.2.1（IST）。
+//! 模块不依赖 kmod 其他部分，主机上也会编译以运行单元测试。
+
+use usb_if::err::TransferError;
+
+/// MFINDEX 的微帧计数范围，Frame ID 为其高 11 位
+pub(crate) const MFINDEX_WRAP: u32 = 2048 * 8;
+
+/// Frame ID 最多可以比当前帧提前 895 ms
+const MAX_LEAD: u32 = 895 * 8;
+
+/// 控制器的等时调度能力
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct IsochCaps {
+    /// IST（Isochronous Scheduling Threshold）换算成的微帧数
+    pub threshold: u32,
+    /// 支持 Contiguous Frame ID
+    pub contiguous: bool,
+}
+
+impl IsochCaps {
+    /// `ist` 为 HCSPARAMS2.IST：bit 3 置位时低 3 位以帧为单位，否则以微帧为单位
+    pub fn new(ist: u8, contiguous: bool) -> Self {
+        let value = (ist & 0x7) as u32;
+        let threshold = if ist & 0x8 != 0 { value * 8 } else { value };
+        Self {
+            threshold,
+            contiguous,
+        }
+    }
+}
+
+/// 一次提交中各 TD 的起始方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IsochStart {
+    /// 置 SIA，由控制器尽快执行
+    Asap,
+    /// 第一个 TD 在该微帧所在的帧执行，之后每个 TD 顺延 `interval` 个微帧
+    Frame { start: u32, interval: u32 },
+}
+
+impl IsochStart {
+    /// 第 `index` 个 TD 的 Frame ID，`None` 表示置 SIA
+    pub fn frame_id(&self, index: usize) -> Option<u16> {
+        match *self {
+            IsochStart::Asap => None,
+            IsochStart::Frame { start, interval } => {
+                let uframe = (start as u64 + index as u64 * interval as u64) % MFINDEX_WRAP as u64;
+                Some((uframe >> 3) as u16)
+            }
+        }
+    }
+}
+
+/// 等时端点的排程
+#[derive(Debug, Clone)]
+pub(crate) struct IsochSchedule {
+    caps: IsochCaps,
+    /// 端点服务周期，单位为微帧
+    interval: u32,
+    /// 下一个 TD 的起始微帧，`None` 表示需要重新对齐
+    next: Option<u32>,
+    /// 排程失效的次数
+    underruns: u32,
+}
+
+impl IsochSchedule {
+    /// `interval` 为端点上下文中的 Interval，周期为 `2^interval` 个微帧
+    pub fn new(caps: IsochCaps, interval: u8) -> Self {
+        Self {
+            caps,
+            interval: 1 << interval.min(15),
+            next: None,
+            underruns: 0,
+        }
+    }
+
+    pub fn underruns(&self) -> u32 {
+        self.underruns
+    }
+
+    /// 为即将入队的 `tds` 个 TD 安排起始帧
+    ///
+    /// `mfindex` 为当前微帧计数，`pending` 表示环上是否还有未完成的等时 TD。
+    /// 会排到最远可调度帧之后的请求返回 [`TransferError::QueueFull`]，由调用者等已提交的
+    /// TD 完成后重试。
+    pub fn reserve(
+        &mut self,
+        mfindex: u16,
+        tds: usize,
+        pending: bool,
+    ) -> Result<IsochStart, TransferError> {
+        let now = mfindex as u32 % MFINDEX_WRAP;
+        let earliest = self.caps.threshold + 1;
+        let span = (tds.max(1) as u64 * self.interval as u64).min(MFINDEX_WRAP as u64) as u32;
+        // 一次提交就超出可调度范围时无法指定帧，交给控制器尽快执行
+        if !self.caps.contiguous || earliest + span + self.interval > MAX_LEAD {
+            self.lost();
+            return Ok(IsochStart::Asap);
+        }
+
+        let start = match self.next {
+            Some(next) if pending => {
+                let lead = (next + MFINDEX_WRAP - now) % MFINDEX_WRAP;
+                if lead >= earliest && lead < MAX_LEAD {
+                    Some(next)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        let start = match start {
+            Some(start) => start,
+            None => {
+                if self.next.is_some() {
+                    self.lost();
+                }
+                // 从下一个完整的帧开始，周期大于一帧时按周期对齐
+                let align = self.interval.max(8);
+                (now + earliest).next_multiple_of(align) % MFINDEX_WRAP
+            }
+        };
+
+        let lead = (start + MFINDEX_WRAP - now) % MFINDEX_WRAP;
+        if lead + span > MAX_LEAD {
+            return Err(TransferError::QueueFull);
+        }
+        self.next = Some((start + span) % MFINDEX_WRAP);
+        Ok(IsochStart::Frame {
+            start,
+            interval: self.interval,
+        })
+    }
+
+    /// 控制器报告错过服务周期或环被取空，下次提交重新对齐
+    pub fn lost(&mut self) {
+        if self.next.take().is_some() {
+            self.underruns = self.underruns.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps() -> IsochCaps {
+        IsochCaps::new(0x8 | 1, true)
+    }
+
+    #[test]
+    fn ist_units() {
+        assert_eq!(IsochCaps::new(4, true).threshold, 4);
+        assert_eq!(IsochCaps::new(0x8 | 2, true).threshold, 16);
+    }
+
+    #[test]
+    fn without_cfc_uses_sia() {
+        let mut sched = IsochSchedule::new(IsochCaps::new(1, false), 0);
+        assert_eq!(sched.reserve(100, 8, false).unwrap(), IsochStart::Asap);
+        assert_eq!(IsochStart::Asap.frame_id(3), None);
+    }
+
+    #[test]
+    fn frames_are_contiguous_while_pending() {
+        // 每微帧一次服务，每帧 8 个 TD
+        let mut sched = IsochSchedule::new(caps(), 0);
+        let first = sched.reserve(100, 16, false).unwrap();
+        // 100 + IST(8) + 1 之后的第一个帧边界
+        assert_eq!(
+            first,
+            IsochStart::Frame {
+                start: 112,
+                interval: 1
+            }
+        );
+        assert_eq!(first.frame_id(0), Some(14));
+        assert_eq!(first.frame_id(7), Some(14));
+        assert_eq!(first.frame_id(8), Some(15));
+
+        let second = sched.reserve(110, 8, true).unwrap();
+        assert_eq!(
+            second,
+            IsochStart::Frame {
+                start: 128,
+                interval: 1
+            }
+        );
+        assert_eq!(sched.underruns(), 0);
+    }
+
+    #[test]
+    fn long_interval_aligns_to_period() {
+        // 周期 4 ms
+        let mut sched = IsochSchedule::new(caps(), 5);
+        let start = sched.reserve(3, 2, false).unwrap();
+        assert_eq!(
+            start,
+            IsochStart::Frame {
+                start: 32,
+                interval: 32
+            }
+        );
+        assert_eq!(start.frame_id(1), Some(8));
+    }
+
+    #[test]
+    fn resync_after_underrun() {
+        let mut sched = IsochSchedule::new(caps(), 0);
+        sched.reserve(0, 8, false).unwrap();
+        // 环已取空：即便预定的帧仍在将来，也从当前位置重新对齐
+        let start = sched.reserve(40, 8, false).unwrap();
+        assert_eq!(
+            start,
+            IsochStart::Frame {
+                start: 56,
+                interval: 1
+            }
+        );
+        assert_eq!(sched.underruns(), 1);
+
+        // 预定的帧已经过去
+        let start = sched.reserve(200, 8, true).unwrap();
+        assert_eq!(
+            start,
+            IsochStart::Frame {
+                start: 216,
+                interval: 1
+            }
+        );
+        assert_eq!(sched.underruns(), 2);
+
+        sched.lost();
+        assert_eq!(sched.underruns(), 3);
+        sched.lost();
+        assert_eq!(sched.underruns(), 3);
+    }
+
+    #[test]
+    fn wraps_and_limits_lead() {
+        let mut sched = IsochSchedule::new(caps(), 3);
+        let start = sched.reserve((MFINDEX_WRAP - 4) as u16, 4, false).unwrap();
+        assert_eq!(
+            start,
+            IsochStart::Frame {
+                start: 8,
+                interval: 8
+            }
+        );
+        assert_eq!(start.frame_id(3), Some(4));
+
+        // 已排程的 TD 加上新请求超出 895 ms 时不能入队
+        let mut sched = IsochSchedule::new(caps(), 3);
+        sched.reserve(0, 800, false).unwrap();
+        assert!(matches!(
+            sched.reserve(0, 100, true),
+            Err(TransferError::QueueFull)
+        ));
+        // 单个请求就超出范围时不指定帧
+        let mut sched = IsochSchedule::new(caps(), 3);
+        assert_eq!(sched.reserve(0, 1000, false).unwrap(), IsochStart::Asap);
+    }
+}
@@ -1,18 +1,30 @@
 pub(crate) mod cmd;
+mod config;
 mod context;
+mod dbc;
 mod def;
 pub(crate) mod device;
 mod endpoint;
 mod event;
+mod event_cursor;
 pub(crate) mod host;
 pub(crate) mod hub;
+mod imod;
+mod isoch;
 mod reg;
 mod ring;
 mod sync;
+mod td_builder;
 mod transfer;
 
 pub(crate) use def::*;
+pub(crate) use imod::IMOD_UNIT_NS;
 
+pub use config::{
+    EnumerationMode, ImodPolicy, InitProgress, InitStage, InterrupterMap, ScratchpadPolicy,
+    TransferRingSize, XhciConfig,
+};
+pub use dbc::{DbcConfig, DbcState, XhciDbc};
 pub use device::Device;
 pub use host::Xhci;
 
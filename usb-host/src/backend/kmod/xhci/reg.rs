@@ -4,9 +4,11 @@ use core::{
     ptr::NonNull,
 };
 
+use alloc::sync::Arc;
 use xhci::accessor::Mapper;
 
 use super::SlotId;
+use crate::backend::kmod::perf::PerfStats;
 
 #[derive(Debug, Clone, Copy)]
 pub struct MemMapper;
@@ -93,16 +95,57 @@ impl Drop for DisableIrqGuard {
 pub struct SlotBell {
     slot_id: SlotId,
     reg: XhciRegisters,
+    stats: Arc<PerfStats>,
+    /// 已配置的端点，按 DCI 置位
+    active_dcis: u32,
+    /// 槽已被禁用，不再敲门铃
+    closed: bool,
 }
 
 impl SlotBell {
-    pub fn new(slot_id: SlotId, reg: XhciRegisters) -> Self {
-        Self { slot_id, reg }
+    pub fn new(slot_id: SlotId, reg: XhciRegisters, stats: Arc<PerfStats>) -> Self {
+        Self {
+            slot_id,
+            reg,
+            stats,
+            active_dcis: 1 << 1,
+            closed: false,
+        }
+    }
+
+    pub fn slot_id(&self) -> SlotId {
+        self.slot_id
+    }
+
+    pub fn set_active_dcis(&mut self, dcis: u32) {
+        self.active_dcis = dcis;
+    }
+
+    pub fn active_dcis(&self) -> impl Iterator<Item = u8> + use<> {
+        let dcis = self.active_dcis;
+        (1..32u8).filter(move |&i| dcis & (1 << i) != 0)
+    }
+
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// 当前微帧计数（MFINDEX）
+    pub fn mfindex(&self) -> u16 {
+        self.reg.runtime.mfindex.read_volatile().microframe_index()
     }
 
     pub fn ring(&mut self, bell: xhci::registers::doorbell::Register) {
+        if self.closed {
+            return;
+        }
         self.reg
             .doorbell
             .write_volatile_at(self.slot_id.as_usize(), bell);
+        self.stats.doorbell();
     }
 }
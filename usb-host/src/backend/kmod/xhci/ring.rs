@@ -3,6 +3,10 @@ use xhci::ring::trb::{Link, command, transfer};
 
 use crate::{
     BusAddr,
+    backend::kmod::{
+        le::{DmaLe, LeWords},
+        mem::MemTag,
+    },
     err::*,
     osal::Kernel,
     queue::{Finished, TWaiter},
@@ -22,6 +26,12 @@ impl TrbData {
     }
 }
 
+impl LeWords for TrbData {
+    fn le(self) -> Self {
+        Self(self.0.le())
+    }
+}
+
 impl From<command::Allowed> for TrbData {
     fn from(value: command::Allowed) -> Self {
         let raw = value.into_raw();
@@ -53,7 +63,11 @@ impl Ring {
         direction: DmaDirection,
         dma: &Kernel,
     ) -> core::result::Result<Self, HostError> {
-        let trbs = dma.array_zero_with_align(len, dma.page_size(), direction)?;
+        // 按环大小对齐，保证环不跨越 64KB 边界
+        let align = (len * TRB_SIZE).next_power_of_two().max(dma.page_size());
+        let trbs = dma
+            .with_tag(MemTag::Ring)
+            .array_zero_with_align(len, align, direction)?;
 
         Ok(Self {
             link,
@@ -72,8 +86,15 @@ impl Ring {
         self.trbs.len()
     }
 
-    fn get_trb(&self) -> Option<TrbData> {
-        self.trbs.read(self.i)
+    /// 清空全部 TRB，入队位置与循环位回到初始状态
+    ///
+    /// 调用方需保证控制器已不再访问该环，并在之后把出队指针重新指向环首。
+    pub fn reset(&mut self) {
+        for i in 0..self.len() {
+            self.trbs.set_le(i, TrbData([0; TRB_LEN]));
+        }
+        self.i = 0;
+        self.cycle = self.link;
     }
 
     pub fn bus_addr(&self) -> BusAddr {
@@ -103,17 +124,16 @@ impl Ring {
     }
 
     pub fn enque_trb(&mut self, trb: TrbData) -> BusAddr {
-        self.trbs.set(self.i, trb);
+        // 传输 TRB 的 CH 位均在 DW3 第 4 位，命令 TRB 中该位保留为 0
+        let chain = trb.0[3] & (1 << 4) != 0;
+        self.trbs.set_le(self.i, trb);
         let addr = self.trb_bus_addr(self.i);
-        self.next_index();
+        self.next_index(chain);
         addr
     }
 
-    pub fn current_data(&mut self) -> (TrbData, bool) {
-        (self.get_trb().unwrap(), self.cycle)
-    }
-
-    fn next_index(&mut self) -> usize {
+    /// `chain` 表示刚入队的 TRB 置了 CH 位，即 TD 尚未结束
+    fn next_index(&mut self, chain: bool) -> usize {
         self.i += 1;
         let len = self.len();
 
@@ -125,6 +145,10 @@ impl Ring {
             let mut link = Link::new();
             link.set_ring_segment_pointer(address.into())
                 .set_toggle_cycle();
+            // TD 跨越环尾时 Link TRB 也属于该 TD，必须置 CH 位（xHCI 规范 4.11.5.1）
+            if chain {
+                link.set_chain_bit();
+            }
 
             if self.cycle {
                 link.set_cycle_bit();
@@ -133,7 +157,7 @@ impl Ring {
             }
             let trb = command::Allowed::Link(link);
 
-            self.trbs.set(len - 1, trb.into());
+            self.trbs.set_le(len - 1, trb.into());
 
             self.cycle = !self.cycle;
         } else if self.i >= len {
@@ -143,22 +167,13 @@ impl Ring {
         self.i
     }
 
-    pub fn inc_deque(&mut self) {
-        self.i += 1;
-        let len = self.len();
-        if self.i >= len {
-            self.i = 0;
-            self.cycle = !self.cycle;
-        }
-    }
-
     pub fn trb_bus_addr(&self, i: usize) -> BusAddr {
         let base = self.bus_addr().raw();
         (base + (i * size_of::<TrbData>()) as u64).into()
     }
 
-    pub fn current_trb_addr(&self) -> BusAddr {
-        self.trb_bus_addr(self.i)
+    fn trb_index(&self, addr: BusAddr) -> usize {
+        (addr.raw() - self.bus_addr().raw()) as usize / TRB_SIZE
     }
 
     pub fn trb_bus_addr_list(&self) -> impl Iterator<Item = BusAddr> + '_ {
@@ -178,6 +193,17 @@ impl<R> SendRing<R> {
         Ok(Self { ring, finished })
     }
 
+    pub fn new_with_len(len: usize, direction: DmaDirection, dma: &Kernel) -> Result<Self> {
+        let ring = Ring::new_with_len(len, true, direction, dma)?;
+        let finished = Finished::new(ring.trb_bus_addr_list());
+        Ok(Self { ring, finished })
+    }
+
+    /// 占用 `bytes` 字节的环
+    pub fn new_with_bytes(bytes: usize, direction: DmaDirection, dma: &Kernel) -> Result<Self> {
+        Self::new_with_len(bytes / TRB_SIZE, direction, dma)
+    }
+
     pub fn enque_command(&mut self, trb: command::Allowed) -> BusAddr {
         let addr = self.ring.enque_command(trb);
         self.finished.clear_finished(addr);
@@ -202,6 +228,15 @@ impl<R> SendRing<R> {
         self.finished.get_finished(addr)
     }
 
+    pub fn take_finished_if(&self, addr: BusAddr, f: impl FnOnce(&R) -> bool) -> Option<R> {
+        self.finished.take_finished_if(addr, f)
+    }
+
+    /// TRB 已有结果，不取出
+    pub fn is_finished(&self, addr: BusAddr) -> bool {
+        self.finished.is_finished(addr)
+    }
+
     pub fn register_cx(&self, addr: BusAddr, cx: &mut core::task::Context<'_>) {
         self.finished.register_cx(addr, cx);
     }
@@ -210,6 +245,57 @@ impl<R> SendRing<R> {
         self.ring.bus_addr()
     }
 
+    /// 将已入队的传输 TRB 改写为 No Op（不置 IOC），只保留原 cycle 位
+    ///
+    /// 仅可在端点停止后调用，控制器重新运行时会跳过该 TRB 且不产生事件。
+    pub fn noop_transfer(&mut self, addr: BusAddr) {
+        let i = self.ring.trb_index(addr);
+        let cycle = self.ring.trbs.read_le(i).map_or(0, |trb| trb.0[3] & 1);
+        let ty = (xhci::ring::trb::Type::NoopTransfer as u32) << 10;
+        self.ring.trbs.set_le(i, TrbData([0, 0, 0, ty | cycle]));
+    }
+
+    /// `addr` 之后下一个 TRB 的地址及控制器到达该位置时应有的 cycle 状态
+    ///
+    /// 用于 Set TR Dequeue Pointer 跳过已取消的 TD。下一个位置可能是 Link TRB，
+    /// 控制器会照常跟随它回到环首。
+    pub fn next_dequeue(&self, addr: BusAddr) -> (BusAddr, bool) {
+        self.dequeue_at((self.ring.trb_index(addr) + 1) % self.ring.len())
+    }
+
+    /// 指向 `addr` 所在 TRB 的出队位置，用于端点 Halted 后从指定 TD 继续执行
+    pub fn dequeue_to(&self, addr: BusAddr) -> (BusAddr, bool) {
+        self.dequeue_at(self.ring.trb_index(addr))
+    }
+
+    /// 下一个入队 TRB 的地址
+    pub fn next_enqueue_addr(&self) -> BusAddr {
+        self.ring.trb_bus_addr(self.ring.i)
+    }
+
+    /// 指向入队位置的出队位置，即跳过环上全部 TD
+    pub fn dequeue_to_enqueue(&self) -> (BusAddr, bool) {
+        self.dequeue_at(self.ring.i)
+    }
+
+    /// 入队位置之前 `addr` 所在 TRB 已入队的 TRB 数，越大越早入队
+    pub fn age(&self, addr: BusAddr) -> usize {
+        let len = self.ring.len();
+        (self.ring.i + len - self.ring.trb_index(addr)) % len
+    }
+
+    fn dequeue_at(&self, i: usize) -> (BusAddr, bool) {
+        let cycle = if i == self.ring.i {
+            self.ring.cycle
+        } else {
+            self.ring
+                .trbs
+                .read_le(i)
+                .is_some_and(|trb| trb.0[3] & 1 != 0)
+        };
+        (self.ring.trb_bus_addr(i), cycle)
+    }
+
     pub fn usable_capacity(&self) -> usize {
         self.ring.len().saturating_sub(1)
     }
@@ -218,3 +304,81 @@ impl<R> SendRing<R> {
         self.ring.cycle
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use xhci::ring::trb::{
+        event::TransferEvent,
+        transfer::{Allowed, EventData, Normal},
+    };
+
+    use super::*;
+    use crate::backend::kmod::test_kernel::HeapKernel;
+
+    fn chained_normal() -> transfer::Allowed {
+        let mut normal = Normal::new();
+        normal.set_trb_transfer_length(512).set_chain_bit();
+        Allowed::Normal(normal)
+    }
+
+    #[test]
+    fn link_trb_chains_td_across_wrap() {
+        let mut ring = SendRing::<TransferEvent>::new_with_len(
+            8,
+            DmaDirection::Bidirectional,
+            &HeapKernel::kernel(),
+        )
+        .unwrap();
+        for _ in 0..7 {
+            ring.enque_transfer(chained_normal());
+        }
+
+        // TD 的最后一个 TRB 在环首，Event Data 的值是它自身的地址
+        let addr = ring.next_enqueue_addr();
+        let mut event = EventData::new();
+        event
+            .set_event_data(addr.raw())
+            .set_interrupt_on_completion();
+        assert_eq!(ring.enque_transfer(Allowed::EventData(event)), addr);
+        assert_eq!(addr, ring.bus_addr());
+
+        let link = ring.ring.trbs.read_le(7).unwrap().0;
+        assert_eq!((link[3] >> 10) & 0x3f, xhci::ring::trb::Type::Link as u32);
+        assert_ne!(
+            link[3] & (1 << 4),
+            0,
+            "Link TRB inside a TD must be chained"
+        );
+
+        // ED=1 的事件以 Event Data 的值为 TRB 指针，长度字段为整个 TD 的 EDTLA
+        let edtla = 7 * 512;
+        let raw = [
+            addr.raw() as u32,
+            (addr.raw() >> 32) as u32,
+            (1 << 24) | edtla,
+            (1 << 2) | ((xhci::ring::trb::Type::TransferEvent as u32) << 10),
+        ];
+        let completed = TransferEvent::try_from(raw).unwrap();
+        ring.finished_handle().set_finished(addr, completed);
+        let event = ring
+            .get_finished(addr)
+            .expect("event data TRB not completed");
+        assert!(event.event_data());
+        assert_eq!(event.trb_transfer_length(), edtla);
+    }
+
+    #[test]
+    fn link_trb_unchained_between_tds() {
+        let mut ring = SendRing::<TransferEvent>::new_with_len(
+            4,
+            DmaDirection::Bidirectional,
+            &HeapKernel::kernel(),
+        )
+        .unwrap();
+        for _ in 0..3 {
+            ring.enque_transfer(Allowed::Normal(Normal::new()));
+        }
+        let link = ring.ring.trbs.read_le(3).unwrap().0;
+        assert_eq!(link[3] & (1 << 4), 0);
+    }
+}
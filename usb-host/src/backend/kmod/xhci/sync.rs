@@ -5,6 +5,11 @@ use spin::{Mutex, RwLock};
 
 use super::reg::{DisableIrqGuard, XhciRegisters};
 
+/// 与中断路径共享的数据
+///
+/// 任务侧通过 [`IrqLock::lock`] 修改数据：先关闭控制器中断再获取自旋锁，
+/// 持锁期间同一核心上的中断处理不会进入。中断路径只通过 [`IrqLock::force_use`]
+/// 读取，从不获取该锁，因此不存在中断等待任务释放锁的死锁。
 pub(crate) struct IrqLock<T> {
     inner: Mutex<()>,
     reg: Arc<RwLock<XhciRegisters>>,
@@ -23,6 +28,7 @@ impl<T> IrqLock<T> {
         }
     }
 
+    /// 仅任务上下文
     pub fn lock(&self) -> IrqLockGuard<'_, T> {
         let _disable_guard = self.reg.write().disable_irq_guard();
         let guard = self.inner.lock();
@@ -33,6 +39,11 @@ impl<T> IrqLock<T> {
         }
     }
 
+    /// 中断路径使用，不加锁
+    ///
+    /// # Safety
+    ///
+    /// 只能在中断处理中只读访问；任务侧修改数据时控制器中断已关闭。
     #[allow(clippy::mut_from_ref)]
     pub unsafe fn force_use(&self) -> &mut T {
         unsafe { &mut *self.data.get() }
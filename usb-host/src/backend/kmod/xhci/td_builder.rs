@@ -0,0 +1,480 @@
+//! TD (Transfer Descriptor) 构建
+//!
+//! 只负责把一次传输翻译成 TRB 序列，不接触 DMA 与寄存器；cycle 位由 `Ring`
+//! 入队时写入。模块不依赖 kmod 其他部分，主机上也会编译以运行单元测试。
+//!
+//! 参考 xHCI 规范 4.11.2（Transfer TRBs）与 6.4.1（Transfer TRB 格式）。
+
+use alloc::vec::Vec;
+
+use usb_if::transfer::Direction;
+use xhci::ring::trb::transfer::{self, Allowed, EventData, Isoch, Normal};
+
+use super::isoch::IsochStart;
+
+/// Setup Stage 中的 8 字节 SETUP 包（wLength 由数据阶段决定）
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ControlSetup {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+}
+
+/// 控制传输 TD：Setup，可选 Data，Status
+///
+/// `data` 为 `(总线地址, 长度)`，长度为 0 时视为无数据阶段。只有 Status 阶段置 IOC，
+/// Data 阶段置 ISP，短包时由其事件报告实际长度。
+/// 以下各函数的 `interrupter` 为完成事件投递的中断器。
+pub(crate) fn control(
+    setup: ControlSetup,
+    direction: Direction,
+    data: Option<(u64, usize)>,
+    interrupter: u16,
+) -> Vec<Allowed> {
+    let data = data.filter(|&(_, len)| len > 0);
+    let mut trbs = Vec::with_capacity(3);
+
+    let mut stage = transfer::SetupStage::default();
+    stage
+        .set_request_type(setup.request_type)
+        .set_request(setup.request)
+        .set_value(setup.value)
+        .set_index(setup.index)
+        .set_length(0)
+        .set_interrupter_target(interrupter)
+        .set_transfer_type(transfer::TransferType::No);
+    if let Some((_, len)) = data {
+        let transfer_type = match direction {
+            Direction::Out => transfer::TransferType::Out,
+            Direction::In => transfer::TransferType::In,
+        };
+        stage.set_transfer_type(transfer_type).set_length(len as _);
+    }
+    trbs.push(stage.into());
+
+    if let Some((addr, len)) = data {
+        let mut stage = transfer::DataStage::default();
+        stage
+            .set_data_buffer_pointer(addr)
+            .set_trb_transfer_length(len as _)
+            .set_interrupter_target(interrupter)
+            .set_interrupt_on_short_packet()
+            .set_direction(match direction {
+                Direction::Out => transfer::Direction::Out,
+                Direction::In => transfer::Direction::In,
+            });
+        trbs.push(stage.into());
+    }
+
+    // Status 阶段方向与数据阶段相反；无数据阶段时固定为 IN
+    let mut status = transfer::StatusStage::default();
+    status
+        .set_interrupter_target(interrupter)
+        .set_interrupt_on_completion();
+    if matches!(direction, Direction::In) && data.is_some() {
+        status.clear_direction();
+    } else {
+        status.set_direction();
+    }
+    trbs.push(status.into());
+
+    trbs
+}
+
+/// 单个 TRB 的数据缓冲区不能跨越 64KB 边界（xHCI 规范 6.4.1）
+const TRB_BUFFER_BOUNDARY: u64 = 0x10000;
+
+/// Event Data TRB 报告的 EDTLA 字段宽度，TD 总长度不能超过它
+pub(crate) const MAX_EVENT_DATA_LEN: usize = 0xff_ffff;
+
+/// Bulk / Interrupt 传输 TD
+///
+/// 缓冲区不跨 64KB 边界时为单个 Normal TRB（ISP + IOC），完成事件的剩余长度即整个 TD 的
+/// 剩余长度。否则按边界拆成链接的 Normal TRB，末尾接一个置 IOC 的 Event Data TRB：短包时
+/// 控制器跳到 Event Data TRB，事件中 ED=1，长度字段为整个 TD 已传输的字节数（EDTLA）。
+/// Event Data 的值会出现在事件的 TRB 指针中，由入队方填入该 TRB 自身的地址。
+pub(crate) fn normal(
+    addr: u64,
+    len: usize,
+    max_packet_size: usize,
+    interrupter: u16,
+) -> Vec<Allowed> {
+    let chunks = buffer_chunks(addr, len);
+    if chunks.len() == 1 {
+        let mut trb = Normal::new();
+        trb.set_data_buffer_pointer(addr)
+            .set_trb_transfer_length(len as _)
+            .set_interrupter_target(interrupter)
+            .set_interrupt_on_short_packet()
+            .set_interrupt_on_completion();
+        return vec![Allowed::Normal(trb)];
+    }
+
+    debug_assert!(len <= MAX_EVENT_DATA_LEN);
+    let total_packets = if max_packet_size == 0 {
+        0
+    } else {
+        len.div_ceil(max_packet_size)
+    };
+    let mut trbs = Vec::with_capacity(chunks.len() + 1);
+    let mut done = 0;
+    for (i, &(chunk_addr, chunk_len)) in chunks.iter().enumerate() {
+        done += chunk_len;
+        // TD Size：本 TRB 之后剩余的包数，最后一个数据 TRB 为 0（4.11.2.4）
+        let td_size = if i + 1 == chunks.len() || max_packet_size == 0 {
+            0
+        } else {
+            (total_packets - done / max_packet_size).min(31)
+        };
+        let mut trb = Normal::new();
+        trb.set_data_buffer_pointer(chunk_addr)
+            .set_trb_transfer_length(chunk_len as _)
+            .set_td_size(td_size as _)
+            .set_interrupter_target(interrupter)
+            .set_chain_bit();
+        trbs.push(Allowed::Normal(trb));
+    }
+
+    let mut event = EventData::new();
+    event
+        .set_interrupter_target(interrupter)
+        .set_interrupt_on_completion();
+    trbs.push(Allowed::EventData(event));
+    trbs
+}
+
+/// `normal` 生成的 TRB 数上限，用于在映射缓冲区之前检查环空间
+///
+/// 实际数量取决于缓冲区起始地址，按最坏情况多算一个边界。
+pub(crate) fn normal_trbs_upper_bound(len: usize) -> usize {
+    if len <= 1 {
+        return 1;
+    }
+    // 数据 TRB 加一个 Event Data TRB
+    (len - 1) / TRB_BUFFER_BOUNDARY as usize + 2 + 1
+}
+
+/// 按 64KB 边界切分缓冲区，长度为 0 时返回一个空块
+fn buffer_chunks(addr: u64, len: usize) -> Vec<(u64, usize)> {
+    let mut chunks = Vec::new();
+    let mut offset = 0usize;
+    loop {
+        let chunk_addr = addr + offset as u64;
+        let to_boundary = TRB_BUFFER_BOUNDARY - chunk_addr % TRB_BUFFER_BOUNDARY;
+        let chunk_len = (len - offset).min(to_boundary as usize);
+        chunks.push((chunk_addr, chunk_len));
+        offset += chunk_len;
+        if offset >= len {
+            return chunks;
+        }
+    }
+}
+
+/// 等时传输：每个包一个独立的 TD（各自置 IOC），包按顺序紧密排列在缓冲区中
+///
+/// 没有包时仍生成一个长度为 0 的 TD。`start` 给出各 TD 的 Frame ID，为
+/// [`IsochStart::Asap`] 时置 SIA。
+pub(crate) fn isoch(
+    addr: u64,
+    packet_lengths: &[usize],
+    max_packet_size: usize,
+    max_burst_size: usize,
+    interrupt_on_short_packet: bool,
+    start: IsochStart,
+    interrupter: u16,
+) -> Vec<Allowed> {
+    let packets = if packet_lengths.is_empty() {
+        &[0][..]
+    } else {
+        packet_lengths
+    };
+
+    let mut trbs = Vec::with_capacity(packets.len());
+    let mut offset = 0u64;
+    for (index, &len) in packets.iter().enumerate() {
+        let (tbc, tlbpc) = iso_burst(len, max_packet_size, max_burst_size);
+        let mut trb = Isoch::new();
+        match start.frame_id(index) {
+            Some(frame) => trb.set_frame_id(frame),
+            None => trb.set_start_isoch_asap(),
+        };
+        trb.set_data_buffer_pointer(addr + offset)
+            .set_trb_transfer_length(len as _)
+            .set_interrupter_target(interrupter)
+            .set_td_size_or_tbc(tbc)
+            .set_transfer_last_burst_packet_count(tlbpc)
+            .set_interrupt_on_completion();
+        if interrupt_on_short_packet {
+            trb.set_interrupt_on_short_packet();
+        }
+        trbs.push(Allowed::Isoch(trb));
+        offset += len as u64;
+    }
+    trbs
+}
+
+/// 计算等时 TRB 的 TBC（Transfer Burst Count）与 TLBPC（Transfer Last Burst Packet Count）
+///
+/// 见 xHCI 规范 4.11.2.3。两者均为“数量减一”编码，`max_burst_size` 为端点上下文中的
+/// Max Burst Size（同样减一编码），`max_packet_size` 为 0 时按单包处理。
+pub(crate) fn iso_burst(len: usize, max_packet_size: usize, max_burst_size: usize) -> (u8, u8) {
+    let total_packets = if max_packet_size == 0 {
+        1
+    } else {
+        len.div_ceil(max_packet_size).max(1)
+    };
+    let packets_per_burst = max_burst_size.saturating_add(1).max(1);
+    let burst_count = total_packets.div_ceil(packets_per_burst).saturating_sub(1);
+    let last_burst_packet_count = match total_packets % packets_per_burst {
+        0 => packets_per_burst.saturating_sub(1),
+        residue => residue.saturating_sub(1),
+    };
+    (
+        burst_count.min(0x1f) as u8,
+        last_burst_packet_count.min(0xf) as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CYCLE: u32 = 1 << 0;
+    const CHAIN: u32 = 1 << 4;
+    const IOC: u32 = 1 << 5;
+    const ISP: u32 = 1 << 2;
+
+    fn raw(trb: &Allowed) -> [u32; 4] {
+        trb.into_raw()
+    }
+
+    fn trb_type(trb: &Allowed) -> u32 {
+        (raw(trb)[3] >> 10) & 0x3f
+    }
+
+    fn trb_len(trb: &Allowed) -> usize {
+        (raw(trb)[2] & 0x1ffff) as usize
+    }
+
+    fn trb_addr(trb: &Allowed) -> u64 {
+        let r = raw(trb);
+        r[0] as u64 | ((r[1] as u64) << 32)
+    }
+
+    /// 所有 TD 共有的约束：cycle 位留给 Ring、IOC 只在最后一个 TRB、TD 内不链接到 TD 外
+    fn check_td(td: &[Allowed]) {
+        assert!(!td.is_empty());
+        for (i, trb) in td.iter().enumerate() {
+            let ctrl = raw(trb)[3];
+            assert_eq!(ctrl & CYCLE, 0, "builder must not set cycle bit");
+            let last = i == td.len() - 1;
+            if last {
+                assert_ne!(ctrl & IOC, 0, "last TRB must interrupt on completion");
+                assert_eq!(ctrl & CHAIN, 0, "last TRB must not chain");
+            } else if ctrl & CHAIN == 0 {
+                // 控制传输各阶段不需要链接，但中间阶段不能产生完成事件
+                assert_eq!(ctrl & IOC, 0, "intermediate TRB must not set IOC");
+            }
+        }
+    }
+
+    fn setup() -> ControlSetup {
+        ControlSetup {
+            request_type: 0x80,
+            request: 6,
+            value: 0x0100,
+            index: 0,
+        }
+    }
+
+    #[test]
+    fn control_stages() {
+        for dir in [Direction::In, Direction::Out] {
+            for len in [0usize, 1, 8, 64, 4096, 0xffff] {
+                let td = control(setup(), dir, Some((0x1000, len)), 0);
+                check_td(&td);
+
+                let setup_len = (raw(&td[0])[1] >> 16) as usize;
+                let trt = (raw(&td[0])[3] >> 16) & 0x3;
+                let status_in = raw(td.last().unwrap())[3] & (1 << 16) != 0;
+                if len == 0 {
+                    assert_eq!(td.len(), 2);
+                    assert_eq!(setup_len, 0);
+                    assert_eq!(trt, 0, "no data stage");
+                    assert!(status_in);
+                } else {
+                    assert_eq!(td.len(), 3);
+                    assert_eq!(setup_len, len);
+                    assert_eq!(trb_len(&td[1]), len);
+                    assert_eq!(trb_addr(&td[1]), 0x1000);
+                    let data_in = raw(&td[1])[3] & (1 << 16) != 0;
+                    assert_eq!(data_in, matches!(dir, Direction::In));
+                    assert_eq!(trt, if data_in { 3 } else { 2 });
+                    assert_eq!(status_in, !data_in);
+                }
+                // Setup Stage 使用立即数据，长度固定为 8
+                assert_ne!(raw(&td[0])[3] & (1 << 6), 0);
+                assert_eq!(trb_len(&td[0]), 8);
+            }
+        }
+    }
+
+    #[test]
+    fn setup_trb_carries_raw_request() {
+        // 厂商请求编号不经任何映射写入 Setup Stage 的立即数据
+        let vendor = ControlSetup {
+            request_type: 0xc0,
+            request: 0xa5,
+            value: 0x1234,
+            index: 0x0002,
+        };
+        let td = control(vendor, Direction::In, Some((0x1000, 4)), 0);
+        assert_eq!(raw(&td[0])[0], 0x1234_a5c0);
+        assert_eq!(raw(&td[0])[1], 0x0004_0002);
+    }
+
+    #[test]
+    fn normal_single_trb() {
+        for len in [0usize, 1, 511, 512, 0x10000] {
+            let td = normal(0xdead_0000, len, 512, 0);
+            check_td(&td);
+            assert_eq!(td.len(), 1);
+            assert_eq!(trb_type(&td[0]), 1);
+            assert_eq!(trb_len(&td[0]), len);
+            assert_eq!(trb_addr(&td[0]), 0xdead_0000);
+            assert_ne!(raw(&td[0])[3] & ISP, 0);
+        }
+    }
+
+    #[test]
+    fn normal_splits_at_64k_and_ends_with_event_data() {
+        for (addr, len) in [
+            (0xdead_0000u64, 0x1ffffusize),
+            (0xdead_f000, 0x2000),
+            (0x1_0000_0200, 0x30000),
+            (0xfe00, 0x200 + 0x10000 + 1),
+        ] {
+            let td = normal(addr, len, 512, 0);
+            check_td(&td);
+            assert!(td.len() <= normal_trbs_upper_bound(len));
+
+            let (event, data) = td.split_last().unwrap();
+            assert!(data.len() > 1);
+            assert_eq!(trb_type(event), 7, "Event Data");
+            assert_eq!(raw(event)[0] | raw(event)[1], 0, "filled in on enqueue");
+
+            let mut expect_addr = addr;
+            for (i, trb) in data.iter().enumerate() {
+                let ctrl = raw(trb)[3];
+                assert_eq!(trb_type(trb), 1);
+                assert_eq!(trb_addr(trb), expect_addr);
+                assert_ne!(ctrl & CHAIN, 0);
+                assert_eq!(ctrl & (ISP | IOC), 0);
+                let end = expect_addr + trb_len(trb) as u64;
+                assert!(end - (expect_addr & !0xffff) <= 0x10000, "crosses 64K");
+                expect_addr = end;
+
+                let td_size = (raw(trb)[2] >> 17) & 0x1f;
+                if i + 1 == data.len() {
+                    assert_eq!(td_size, 0);
+                } else {
+                    let left = (addr + len as u64 - expect_addr) as usize;
+                    assert_eq!(td_size as usize, left.div_ceil(512).min(31));
+                }
+            }
+            assert_eq!(expect_addr, addr + len as u64);
+        }
+    }
+
+    #[test]
+    fn control_data_stage_interrupts_on_short_packet() {
+        let td = control(setup(), Direction::In, Some((0x1000, 255)), 0);
+        assert_ne!(raw(&td[1])[3] & ISP, 0);
+    }
+
+    #[test]
+    fn isoch_packets_are_contiguous_tds() {
+        let lengths = [0usize, 1, 188, 1024, 3072, 1023, 0, 2049];
+        for n in 0..=lengths.len() {
+            let packets = &lengths[..n];
+            for isp in [false, true] {
+                let trbs = isoch(0x8000, packets, 1024, 2, isp, IsochStart::Asap, 0);
+                assert_eq!(trbs.len(), n.max(1));
+
+                let mut expect_addr = 0x8000u64;
+                for (i, trb) in trbs.iter().enumerate() {
+                    check_td(core::slice::from_ref(trb));
+                    assert_eq!(trb_type(trb), 5);
+                    assert_eq!(trb_addr(trb), expect_addr);
+                    let len = packets.get(i).copied().unwrap_or(0);
+                    assert_eq!(trb_len(trb), len);
+                    assert_eq!(raw(trb)[3] & ISP != 0, isp);
+                    assert_ne!(raw(trb)[3] & (1 << 31), 0, "SIA");
+                    expect_addr += len as u64;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn isoch_frame_ids_follow_schedule() {
+        let start = IsochStart::Frame {
+            start: 8 * 2046,
+            interval: 8,
+        };
+        let trbs = isoch(0x8000, &[188; 4], 1024, 0, true, start, 0);
+        let frames: Vec<u32> = trbs.iter().map(|trb| (raw(trb)[3] >> 20) & 0x7ff).collect();
+        assert_eq!(frames, [2046, 2047, 0, 1]);
+        for trb in &trbs {
+            assert_eq!(raw(trb)[3] & (1 << 31), 0, "SIA");
+        }
+    }
+
+    #[test]
+    fn interrupter_target_on_every_trb() {
+        let mut tds = control(setup(), Direction::In, Some((0x1000, 18)), 3);
+        tds.extend(normal(0x2000, 512, 512, 3));
+        tds.extend(normal(0xf000, 0x2000, 512, 3));
+        tds.extend(isoch(
+            0x3000,
+            &[188, 188],
+            1024,
+            0,
+            true,
+            IsochStart::Asap,
+            3,
+        ));
+        for trb in &tds {
+            assert_eq!(raw(trb)[2] >> 22, 3);
+        }
+    }
+
+    #[test]
+    fn iso_burst_matches_packet_count() {
+        for mps in [0usize, 8, 188, 512, 1024] {
+            for burst in 0..=15usize {
+                for len in 0..=(3 * 16 * 1024usize) {
+                    if mps != 0 && len % 61 != 0 && len % mps > 1 {
+                        continue;
+                    }
+                    let (tbc, tlbpc) = iso_burst(len, mps, burst);
+                    assert!(tbc <= 0x1f && tlbpc <= 0xf);
+
+                    let packets = if mps == 0 {
+                        1
+                    } else {
+                        len.div_ceil(mps).max(1)
+                    };
+                    let per_burst = burst + 1;
+                    let bursts = tbc as usize + 1;
+                    if packets.div_ceil(per_burst) <= 0x20 {
+                        // 前 bursts-1 个突发满载，最后一个突发装下剩余的包
+                        assert_eq!((bursts - 1) * per_burst + tlbpc as usize + 1, packets);
+                        assert!(tlbpc as usize <= burst);
+                    }
+                }
+            }
+        }
+    }
+}
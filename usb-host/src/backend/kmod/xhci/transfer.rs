@@ -1,5 +1,6 @@
-use alloc::{collections::BTreeMap, sync::Arc};
-use xhci::ring::trb::event::TransferEvent;
+use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicU32, Ordering};
+use xhci::ring::trb::event::{CompletionCode, TransferEvent};
 
 use crate::{BusAddr, queue::Finished};
 
@@ -14,9 +15,16 @@ pub struct TransQueueId {
     ep_id: u8,
 }
 
+struct EpQueues {
+    /// 启用流的端点每个流一个环，事件按 TRB 地址落到所属的环
+    rings: Vec<Finished<TransferEvent>>,
+    /// 不指向任何 TRB 的等时异常事件计数，见 [`TransferResultHandler::set_finished`]
+    faults: Arc<AtomicU32>,
+}
+
 #[derive(Clone)]
 pub struct TransferResultHandler {
-    inner: Arc<IrqLock<BTreeMap<TransQueueId, Finished<TransferEvent>>>>,
+    inner: Arc<IrqLock<BTreeMap<TransQueueId, EpQueues>>>,
 }
 
 unsafe impl Send for TransferResultHandler {}
@@ -28,10 +36,42 @@ impl TransferResultHandler {
         }
     }
 
-    pub fn register_queue(&mut self, slot_id: u8, ep_id: u8, ring: &SendRing<TransferEvent>) {
+    /// `faults` 随每个不带 TRB 指针的 Ring Underrun/Overrun、Missed Service Error 事件递增，
+    /// 仅任务上下文
+    pub fn register_queue(
+        &mut self,
+        slot_id: u8,
+        ep_id: u8,
+        ring: &SendRing<TransferEvent>,
+        faults: Arc<AtomicU32>,
+    ) {
+        let id = TransQueueId { slot_id, ep_id };
+        let rings = vec![ring.finished_handle()];
+        self.inner.lock().insert(id, EpQueues { rings, faults });
+    }
+
+    /// 以端点的全部流环替换其注册，仅任务上下文
+    pub fn register_stream_queues<'a>(
+        &mut self,
+        slot_id: u8,
+        ep_id: u8,
+        rings: impl Iterator<Item = &'a SendRing<TransferEvent>>,
+    ) {
         let id = TransQueueId { slot_id, ep_id };
-        let handle = ring.finished_handle();
-        self.inner.lock().insert(id, handle);
+        let handles = rings.map(|ring| ring.finished_handle()).collect();
+        self.inner
+            .lock()
+            .entry(id)
+            .or_insert_with(|| EpQueues {
+                rings: Vec::new(),
+                faults: Arc::new(AtomicU32::new(0)),
+            })
+            .rings = handles;
+    }
+
+    /// 仅任务上下文
+    pub fn unregister_slot(&mut self, slot_id: u8) {
+        self.inner.lock().retain(|id, _| id.slot_id != slot_id);
     }
 
     /// Marks a queue completion from the xHCI interrupt path.
@@ -41,9 +81,32 @@ impl TransferResultHandler {
     /// by `IrqLock::lock`, which disables this interrupt source before mutating
     /// the map. The IRQ hot path uses `force_use` and only touches the
     /// pre-registered queue completion slot, then wakes queue-local waiters.
+    /// Delivery into the slot is lock-free, see [`Finished`].
+    ///
+    /// # Safety
+    ///
+    /// Must only be called from the event handler, never concurrently with
+    /// itself.
+    ///
+    /// Ring Underrun/Overrun 事件的 TRB 指针无效，Missed Service Error 事件也可能不给出
+    /// TRB 指针（xHCI 规范 4.10.3.1、4.10.3.2），这类事件只计数。端点在下次提交时重新对齐排程，
+    /// 并以之后的第一个事件为界回收被跳过的 TD。
     pub unsafe fn set_finished(&self, slot_id: u8, ep_id: u8, ptr: BusAddr, res: TransferEvent) {
         let queue_id = TransQueueId { slot_id, ep_id };
-        if let Some(q) = unsafe { self.inner.force_use().get(&queue_id) } {
+        let Some(queues) = (unsafe { self.inner.force_use().get(&queue_id) }) else {
+            return;
+        };
+        let fault = matches!(
+            res.completion_code(),
+            Ok(CompletionCode::RingUnderrun | CompletionCode::RingOverrun)
+        ) || (ptr.raw() == 0
+            && res.completion_code() == Ok(CompletionCode::MissedServiceError));
+        if fault {
+            queues.faults.fetch_add(1, Ordering::Release);
+            return;
+        }
+        // 未知地址被忽略，只有所属的环会收到结果
+        for q in &queues.rings {
             q.set_finished(ptr, res);
         }
     }
@@ -0,0 +1,408 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Debug,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::Waker,
+};
+
+use futures::{FutureExt, future::BoxFuture};
+use usb_if::{
+    descriptor::{ConfigurationDescriptor, DescriptorType, DeviceDescriptor, EndpointDescriptor},
+    endpoint::EndpointInfo,
+    err::{TransferError, USBError},
+    host::{ControlSetup, hub::Speed},
+    transfer::{Request, RequestType},
+};
+
+use super::endpoint::EndpointImpl;
+use crate::backend::ty::{
+    DeviceInfoOp, DeviceOp, HubParams,
+    ep::{Endpoint, declared_interval},
+};
+use crate::device::DeviceLocation;
+
+/// 字符串描述符 0：只支持英语（美国）
+const LANGIDS: [u8; 4] = [4, 0x03, 0x09, 0x04];
+
+/// 模拟设备的描述符与行为
+#[derive(Debug, Clone)]
+pub struct MockDevice {
+    device: [u8; DeviceDescriptor::LEN],
+    configurations: Vec<Vec<u8>>,
+    /// 回环的 (批量 IN, 批量 OUT) 端点地址
+    loopback: Option<(u8, u8)>,
+}
+
+impl MockDevice {
+    /// `device` 为设备描述符，`configurations` 为各配置的完整配置描述符
+    ///
+    /// # Panics
+    ///
+    /// 描述符无法解析时 panic。
+    pub fn new(device: [u8; DeviceDescriptor::LEN], configurations: Vec<Vec<u8>>) -> Self {
+        DeviceDescriptor::parse(&device).expect("mock device descriptor");
+        for raw in &configurations {
+            ConfigurationDescriptor::parse(raw).expect("mock configuration descriptor");
+        }
+        Self {
+            device,
+            configurations,
+            loopback: None,
+        }
+    }
+
+    /// 批量 OUT 端点 `output` 收到的每个传输依次从批量 IN 端点 `input` 返回
+    ///
+    /// 其他 IN 端点上的批量与中断请求一直挂起，直到取消或设备拔出。
+    pub fn with_loopback(mut self, input: u8, output: u8) -> Self {
+        self.loopback = Some((input, output));
+        self
+    }
+}
+
+struct SimState {
+    detached: bool,
+    configuration: u8,
+    /// 回环 OUT 收到、尚未被 IN 读取的数据
+    loopback: VecDeque<Vec<u8>>,
+    /// 等待回环数据或拔出的请求
+    wakers: Vec<Waker>,
+}
+
+/// 总线上的一个模拟设备，设备信息、打开的设备与端点共享
+pub(crate) struct Sim {
+    pub id: usize,
+    pub desc: DeviceDescriptor,
+    pub configs: Vec<ConfigurationDescriptor>,
+    spec: MockDevice,
+    in_flight: Arc<AtomicUsize>,
+    state: Mutex<SimState>,
+}
+
+impl Sim {
+    pub fn new(id: usize, spec: MockDevice, in_flight: Arc<AtomicUsize>) -> Self {
+        let desc = DeviceDescriptor::parse(&spec.device).unwrap();
+        let configs: Vec<_> = spec
+            .configurations
+            .iter()
+            .map(|raw| ConfigurationDescriptor::parse(raw).unwrap())
+            .collect();
+        // 与操作系统枚举后一样，默认选中第一个配置
+        let configuration = configs.first().map_or(0, |c| c.configuration_value);
+        Self {
+            id,
+            desc,
+            configs,
+            spec,
+            in_flight,
+            state: Mutex::new(SimState {
+                detached: false,
+                configuration,
+                loopback: VecDeque::new(),
+                wakers: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn detach(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.detached = true;
+        state.wakers.drain(..).for_each(Waker::wake);
+    }
+
+    pub fn is_detached(&self) -> bool {
+        self.state.lock().unwrap().detached
+    }
+
+    pub fn track(&self, submitted: bool) {
+        if submitted {
+            self.in_flight.fetch_add(1, Ordering::AcqRel);
+        } else {
+            self.in_flight.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+
+    pub fn register_waker(&self, waker: &Waker) {
+        self.state.lock().unwrap().wakers.push(waker.clone());
+    }
+
+    pub fn is_loopback_in(&self, address: u8) -> bool {
+        self.spec
+            .loopback
+            .is_some_and(|(input, _)| input == address)
+    }
+
+    pub fn is_loopback_out(&self, address: u8) -> bool {
+        self.spec
+            .loopback
+            .is_some_and(|(_, output)| output == address)
+    }
+
+    pub fn loopback_out(&self, data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state.loopback.push_back(data.to_vec());
+        state.wakers.drain(..).for_each(Waker::wake);
+    }
+
+    pub fn loopback_in(&self) -> Option<Vec<u8>> {
+        self.state.lock().unwrap().loopback.pop_front()
+    }
+
+    pub fn has_loopback_data(&self) -> bool {
+        !self.state.lock().unwrap().loopback.is_empty()
+    }
+
+    fn descriptor(&self, ty: DescriptorType, index: u8) -> Option<&[u8]> {
+        match ty {
+            DescriptorType::DEVICE => Some(&self.spec.device),
+            DescriptorType::CONFIGURATION => self
+                .spec
+                .configurations
+                .get(index as usize)
+                .map(Vec::as_slice),
+            DescriptorType::STRING if index == 0 => Some(&LANGIDS),
+            _ => None,
+        }
+    }
+
+    /// 应答标准 IN 请求，其他请求以 STALL 结束
+    pub fn control_in(&self, setup: &ControlSetup, buf: &mut [u8]) -> Result<usize, TransferError> {
+        let configuration = [self.state.lock().unwrap().configuration];
+        let reply: &[u8] = match (setup.request_type, setup.request) {
+            (RequestType::Standard, Request::GetDescriptor) => self
+                .descriptor(DescriptorType((setup.value >> 8) as u8), setup.value as u8)
+                .ok_or(TransferError::Stall)?,
+            (RequestType::Standard, Request::GetStatus) => &[0, 0],
+            (RequestType::Standard, Request::GetConfiguration) => &configuration,
+            (RequestType::Standard, Request::GetInterface) => &[0],
+            _ => return Err(TransferError::Stall),
+        };
+        let n = reply.len().min(buf.len());
+        buf[..n].copy_from_slice(&reply[..n]);
+        Ok(n)
+    }
+
+    /// 应答标准 OUT 请求，其他请求以 STALL 结束
+    pub fn control_out(&self, setup: &ControlSetup, data: &[u8]) -> Result<usize, TransferError> {
+        match (setup.request_type, setup.request) {
+            (RequestType::Standard, Request::SetConfiguration) => {
+                let value = setup.value as u8;
+                if value != 0 && !self.configs.iter().any(|c| c.configuration_value == value) {
+                    return Err(TransferError::Stall);
+                }
+                self.state.lock().unwrap().configuration = value;
+            }
+            (
+                RequestType::Standard,
+                Request::SetInterface | Request::ClearFeature | Request::SetFeature,
+            ) => {}
+            _ => return Err(TransferError::Stall),
+        }
+        Ok(data.len())
+    }
+
+    fn configuration(&self) -> Option<&ConfigurationDescriptor> {
+        let value = self.state.lock().unwrap().configuration;
+        self.configs.iter().find(|c| c.configuration_value == value)
+    }
+}
+
+pub struct DeviceInfo {
+    sim: Arc<Sim>,
+}
+
+impl DeviceInfo {
+    pub(crate) fn new(sim: Arc<Sim>) -> Self {
+        Self { sim }
+    }
+}
+
+impl Debug for DeviceInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DeviceInfo")
+            .field("id", &self.sim.id)
+            .finish()
+    }
+}
+
+impl DeviceInfoOp for DeviceInfo {
+    fn id(&self) -> usize {
+        self.sim.id
+    }
+
+    fn backend_name(&self) -> &str {
+        "mock"
+    }
+
+    fn descriptor(&self) -> &DeviceDescriptor {
+        &self.sim.desc
+    }
+
+    fn configuration_descriptors(&self) -> &[ConfigurationDescriptor] {
+        &self.sim.configs
+    }
+
+    fn location(&self) -> DeviceLocation {
+        DeviceLocation {
+            bus: 0,
+            port_path: vec![self.sim.id as u8],
+            address: self.sim.id,
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        self.sim.is_detached()
+    }
+}
+
+pub struct Device {
+    sim: Arc<Sim>,
+    desc: DeviceDescriptor,
+    configs: Vec<ConfigurationDescriptor>,
+    /// 已声明的接口及其备用设置
+    interfaces: BTreeMap<u8, u8>,
+    ctrl_ep: Endpoint,
+}
+
+impl Device {
+    pub(crate) fn open(info: &DeviceInfo) -> Result<Self, USBError> {
+        let sim = info.sim.clone();
+        if sim.is_detached() {
+            return Err(TransferError::NoDevice.into());
+        }
+        let ctrl_ep = Endpoint::new(EndpointInfo::control(), EndpointImpl::control(sim.clone()));
+        Ok(Self {
+            desc: sim.desc.clone(),
+            configs: sim.configs.clone(),
+            sim,
+            interfaces: BTreeMap::new(),
+            ctrl_ep,
+        })
+    }
+}
+
+#[cfg(test)]
+impl Device {
+    pub(super) fn is_claimed(&self, interface: u8) -> bool {
+        self.interfaces.contains_key(&interface)
+    }
+}
+
+impl DeviceOp for Device {
+    fn id(&self) -> usize {
+        self.sim.id
+    }
+
+    fn backend_name(&self) -> &str {
+        "mock"
+    }
+
+    fn descriptor(&self) -> &DeviceDescriptor {
+        &self.desc
+    }
+
+    fn configuration_descriptors(&self) -> &[ConfigurationDescriptor] {
+        &self.configs
+    }
+
+    fn set_descriptors(&mut self, desc: DeviceDescriptor, configs: Vec<ConfigurationDescriptor>) {
+        self.desc = desc;
+        self.configs = configs;
+    }
+
+    fn ctrl_ep_ref(&self) -> &Endpoint {
+        &self.ctrl_ep
+    }
+
+    fn ctrl_ep_mut(&mut self) -> &mut Endpoint {
+        &mut self.ctrl_ep
+    }
+
+    fn claim_interface<'a>(
+        &'a mut self,
+        interface: u8,
+        alternate: u8,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        let res = if self.sim.is_detached() {
+            Err(TransferError::NoDevice.into())
+        } else if self.sim.configuration().is_some_and(|config| {
+            config.interfaces.iter().any(|iface| {
+                iface.interface_number == interface
+                    && iface
+                        .alt_settings
+                        .iter()
+                        .any(|alt| alt.alternate_setting == alternate)
+            })
+        }) {
+            self.interfaces.insert(interface, alternate);
+            Ok(())
+        } else {
+            Err(USBError::NotFound)
+        };
+        async { res }.boxed()
+    }
+
+    fn release_interface<'a>(
+        &'a mut self,
+        interface: u8,
+        _alternate: u8,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        self.interfaces.remove(&interface);
+        async { Ok(()) }.boxed()
+    }
+
+    fn set_configuration<'a>(
+        &'a mut self,
+        configuration_value: u8,
+    ) -> BoxFuture<'a, Result<(), USBError>> {
+        let setup = ControlSetup {
+            request_type: RequestType::Standard,
+            recipient: usb_if::transfer::Recipient::Device,
+            request: Request::SetConfiguration,
+            value: configuration_value as u16,
+            index: 0,
+        };
+        let res = self.sim.control_out(&setup, &[]);
+        if res.is_ok() {
+            self.interfaces.clear();
+        }
+        async { res.map(|_| ()).map_err(USBError::from) }.boxed()
+    }
+
+    fn endpoint(&mut self, desc: &EndpointDescriptor) -> Result<Endpoint, USBError> {
+        let config = self
+            .sim
+            .configuration()
+            .ok_or(USBError::ConfigurationNotSet)?;
+        let claimed = config.interfaces.iter().any(|iface| {
+            self.interfaces
+                .get(&iface.interface_number)
+                .and_then(|&alt| {
+                    iface
+                        .alt_settings
+                        .iter()
+                        .find(|a| a.alternate_setting == alt)
+                })
+                .is_some_and(|alt| alt.endpoints.iter().any(|ep| ep.address == desc.address))
+        });
+        if !claimed {
+            return Err(USBError::NotFound);
+        }
+        let ep = EndpointImpl::new(self.sim.clone(), desc.address);
+        // 按 bcdUSB 推断速度，周期端点与真实设备一样按声明的间隔提交
+        let speed = match self.desc.usb_version {
+            0x0300.. => Speed::SuperSpeed,
+            0x0200.. => Speed::High,
+            _ => Speed::Full,
+        };
+        let interval = declared_interval(speed, desc.transfer_type, desc.interval);
+        Ok(Endpoint::new(EndpointInfo::from(desc), ep).with_service_interval(interval))
+    }
+
+    fn update_hub(&mut self, _params: HubParams) -> BoxFuture<'_, Result<(), USBError>> {
+        async { Ok(()) }.boxed()
+    }
+}
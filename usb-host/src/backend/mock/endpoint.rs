@@ -0,0 +1,207 @@
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    task::{Context, Waker},
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use usb_if::{
+    endpoint::{IsoPacketResult, RequestId, TransferCompletion, TransferRequest, TransferStatus},
+    err::TransferError,
+    transfer::Direction,
+};
+
+use super::device::Sim;
+use crate::backend::ty::{ep::EndpointOp, timer::Timer};
+
+struct Pending {
+    request: TransferRequest,
+    /// 已完成的结果，IN 请求在有回环数据之前为空
+    done: Option<Result<TransferCompletion, TransferError>>,
+}
+
+/// 模拟设备的端点
+///
+/// 控制、OUT 与等时请求在提交时完成；回环 IN 端点上的请求在有数据时完成，
+/// 其他 IN 端点上的批量与中断请求一直挂起。
+pub(crate) struct EndpointImpl {
+    sim: Arc<Sim>,
+    address: u8,
+    next_id: u64,
+    pending: BTreeMap<u64, Pending>,
+}
+
+// 请求缓冲区的裸指针只在提交与回收时访问
+unsafe impl Send for EndpointImpl {}
+
+impl EndpointImpl {
+    pub fn control(sim: Arc<Sim>) -> Self {
+        Self::new(sim, 0)
+    }
+
+    pub fn new(sim: Arc<Sim>, address: u8) -> Self {
+        Self {
+            sim,
+            address,
+            next_id: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// 按模拟设备的行为完成请求，需要等待时返回 `None`
+    fn complete(
+        &self,
+        id: RequestId,
+        request: &TransferRequest,
+    ) -> Option<Result<TransferCompletion, TransferError>> {
+        let buffer = request.buffer();
+        let data_in = || match buffer {
+            Some(b) => unsafe { core::slice::from_raw_parts_mut(b.ptr.as_ptr(), b.len) },
+            None => &mut [],
+        };
+        let data_out = || match buffer {
+            Some(b) => unsafe { core::slice::from_raw_parts(b.ptr.as_ptr(), b.len) },
+            None => &[],
+        };
+        let completion = |actual_length, iso_packets| TransferCompletion {
+            request_id: id,
+            status: TransferStatus::Completed,
+            actual_length,
+            iso_packets,
+        };
+
+        let actual_length = match request {
+            TransferRequest::Control {
+                setup, direction, ..
+            } => match direction {
+                Direction::In => self.sim.control_in(setup, data_in()),
+                Direction::Out => self.sim.control_out(setup, data_out()),
+            },
+            TransferRequest::Isochronous {
+                direction, packets, ..
+            } => {
+                // IN 包填满递增的计数，OUT 包全部发送
+                if *direction == Direction::In {
+                    for (i, byte) in data_in().iter_mut().enumerate() {
+                        *byte = i as u8;
+                    }
+                }
+                let results = packets
+                    .iter()
+                    .map(|p| IsoPacketResult {
+                        requested_length: p.length,
+                        actual_length: p.length,
+                        status: TransferStatus::Completed,
+                    })
+                    .collect();
+                let total = packets.iter().map(|p| p.length).sum();
+                return Some(Ok(completion(total, results)));
+            }
+            _ if request.direction() == Direction::Out => {
+                let data = data_out();
+                if self.sim.is_loopback_out(self.address) {
+                    self.sim.loopback_out(data);
+                }
+                Ok(data.len())
+            }
+            _ => {
+                if !self.sim.is_loopback_in(self.address) {
+                    return None;
+                }
+                let data = self.sim.loopback_in()?;
+                let buf = data_in();
+                if data.len() > buf.len() {
+                    Err(TransferError::Other(anyhow!(
+                        "Overflow: received {} bytes into a {} byte buffer",
+                        data.len(),
+                        buf.len()
+                    )))
+                } else {
+                    buf[..data.len()].copy_from_slice(&data);
+                    Ok(data.len())
+                }
+            }
+        };
+        Some(actual_length.map(|n| completion(n, Vec::new())))
+    }
+
+    fn retire(&mut self, id: RequestId) -> Option<Pending> {
+        let pending = self.pending.remove(&id.raw())?;
+        self.sim.track(false);
+        Some(pending)
+    }
+}
+
+impl Timer for EndpointImpl {
+    fn now(&self) -> Duration {
+        crate::backend::std_timer::now()
+    }
+
+    fn wake_at(&self, deadline: Duration, waker: &Waker) {
+        crate::backend::std_timer::wake_at(deadline, waker)
+    }
+}
+
+impl EndpointOp for EndpointImpl {
+    fn submit_request(&mut self, request: TransferRequest) -> Result<RequestId, TransferError> {
+        if self.sim.is_detached() {
+            return Err(TransferError::NoDevice);
+        }
+        self.next_id += 1;
+        let id = RequestId::new(self.next_id);
+        let done = self.complete(id, &request);
+        self.pending.insert(id.raw(), Pending { request, done });
+        self.sim.track(true);
+        Ok(id)
+    }
+
+    fn reclaim_request(
+        &mut self,
+        id: RequestId,
+    ) -> Option<Result<TransferCompletion, TransferError>> {
+        let pending = self.pending.get(&id.raw())?;
+        if pending.done.is_none() {
+            let done = if self.sim.is_detached() {
+                Some(Err(TransferError::NoDevice))
+            } else {
+                self.complete(id, &pending.request)
+            };
+            self.pending.get_mut(&id.raw())?.done = done;
+        }
+        self.pending.get(&id.raw())?.done.as_ref()?;
+        self.retire(id)?.done
+    }
+
+    fn register_waker(&self, id: RequestId, cx: &mut Context<'_>) {
+        self.sim.register_waker(cx.waker());
+        let ready = self.pending.get(&id.raw()).is_none_or(|p| p.done.is_some())
+            || self.sim.is_detached()
+            || (self.sim.is_loopback_in(self.address) && self.sim.has_loopback_data());
+        if ready {
+            cx.waker().wake_by_ref();
+        }
+    }
+
+    fn pending_requests(&self) -> Vec<RequestId> {
+        self.pending.keys().copied().map(RequestId::new).collect()
+    }
+
+    fn cancel_request(
+        &mut self,
+        id: RequestId,
+    ) -> BoxFuture<'_, Result<Option<TransferCompletion>, TransferError>> {
+        let res = match self.retire(id) {
+            Some(Pending {
+                done: Some(res), ..
+            }) => res.map(Some),
+            Some(Pending { done: None, .. }) => Ok(None),
+            None => Err(TransferError::InvalidEndpoint),
+        };
+        Box::pin(async { res })
+    }
+
+    fn reset_halt(&mut self) -> BoxFuture<'_, Result<(), TransferError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
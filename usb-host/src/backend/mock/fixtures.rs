@@ -0,0 +1,413 @@
+//! 测试用描述符构造器与常见设备的描述符集合
+//!
+//! 类驱动测试可以直接得到真实布局的描述符，或把设备插入模拟总线：
+//!
+//! ```
+//! use crab_usb::{USBHost, fixtures::DeviceFixture};
+//!
+//! let kbd = DeviceFixture::hid_keyboard();
+//! assert_eq!(kbd.configuration_descriptors()[0].interfaces[0].first_alt_setting().class, 3);
+//!
+//! let (_host, bus) = USBHost::new_mock();
+//! bus.attach(kbd);
+//! ```
+
+use usb_if::descriptor::{ConfigurationDescriptor, DeviceDescriptor, EndpointType};
+
+use super::MockDevice;
+
+/// 一个设备的全部描述符
+#[derive(Debug, Clone)]
+pub struct DeviceFixture {
+    /// 18 字节设备描述符
+    pub device: [u8; DeviceDescriptor::LEN],
+    /// 每个配置的完整配置描述符（含接口、端点与类特定描述符）
+    pub configurations: Vec<Vec<u8>>,
+}
+
+impl DeviceFixture {
+    pub fn device_descriptor(&self) -> DeviceDescriptor {
+        DeviceDescriptor::parse(&self.device).expect("fixture device descriptor")
+    }
+
+    pub fn configuration_descriptors(&self) -> Vec<ConfigurationDescriptor> {
+        self.configurations
+            .iter()
+            .map(|raw| ConfigurationDescriptor::parse(raw).expect("fixture configuration"))
+            .collect()
+    }
+
+    /// HID 启动协议键盘（QEMU `usb-kbd` 的 VID/PID），接口 0 带 8 字节中断 IN 端点 0x81
+    pub fn hid_keyboard() -> Self {
+        DeviceBuilder::new(0x0627, 0x0001)
+            .usb_version(0x0110)
+            .max_packet_size_0(8)
+            .configuration(
+                ConfigBuilder::new(1)
+                    .attributes(0xa0)
+                    .max_power(50)
+                    .interface(0, 0, 0x03, 0x01, 0x01)
+                    // HID 描述符：bcdHID 1.11，一个 63 字节的报告描述符
+                    .class_specific(&[9, 0x21, 0x11, 0x01, 0, 1, 0x22, 63, 0])
+                    .endpoint(0x81, EndpointType::Interrupt, 8, 10),
+            )
+            .build()
+    }
+
+    /// 大容量存储 U 盘（SCSI 透明命令集，仅批量传输），批量端点 0x81 / 0x02
+    pub fn msc_stick() -> Self {
+        DeviceBuilder::new(0x0781, 0x5567)
+            .configuration(
+                ConfigBuilder::new(1)
+                    .interface(0, 0, 0x08, 0x06, 0x50)
+                    .endpoint(0x81, EndpointType::Bulk, 512, 0)
+                    .endpoint(0x02, EndpointType::Bulk, 512, 0),
+            )
+            .build()
+    }
+
+    /// CDC-ACM 串口调制解调器：通信接口 0（中断端点 0x83）与数据接口 1（批量端点 0x81 / 0x02）
+    pub fn cdc_acm_modem() -> Self {
+        DeviceBuilder::new(0x0483, 0x5740)
+            .class(0xef, 0x02, 0x01)
+            .configuration(
+                ConfigBuilder::new(1)
+                    .iad(0, 2, 0x02, 0x02, 0x01)
+                    .interface(0, 0, 0x02, 0x02, 0x01)
+                    // Header 1.10、Call Management、ACM、Union（主接口 0，从接口 1）
+                    .class_specific(&[5, 0x24, 0x00, 0x10, 0x01])
+                    .class_specific(&[5, 0x24, 0x01, 0x00, 0x01])
+                    .class_specific(&[4, 0x24, 0x02, 0x02])
+                    .class_specific(&[5, 0x24, 0x06, 0x00, 0x01])
+                    .endpoint(0x83, EndpointType::Interrupt, 16, 9)
+                    .interface(1, 0, 0x0a, 0x00, 0x00)
+                    .endpoint(0x81, EndpointType::Bulk, 512, 0)
+                    .endpoint(0x02, EndpointType::Bulk, 512, 0),
+            )
+            .build()
+    }
+
+    /// UVC 1.0 摄像头：VC 接口 0，VS 接口 1 提供 MJPEG 640x480（30/15 fps）与 1280x720（30 fps）
+    ///
+    /// VS 接口的备用设置 1、2 分别带 512 字节与 3x1024 字节的等时 IN 端点 0x81。
+    pub fn uvc_camera() -> Self {
+        const VC_CLASS: u8 = 0x0e;
+        let vc_header = [
+            13, 0x24, 0x01, 0x00, 0x01, 40, 0, 0x80, 0x8d, 0x5b, 0x00, 1, 1,
+        ];
+        // Camera Terminal，无可选控制
+        let camera = [
+            18, 0x24, 0x02, 1, 0x01, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0,
+        ];
+        // USB Streaming Output Terminal，来源为 Camera Terminal
+        let output = [9, 0x24, 0x03, 2, 0x01, 0x01, 0, 1, 0];
+
+        let frames = [
+            mjpeg_frame(1, 640, 480, &[333_333, 666_666]),
+            mjpeg_frame(2, 1280, 720, &[333_333]),
+        ];
+        let format = [11, 0x24, 0x06, 1, frames.len() as u8, 1, 1, 0, 0, 0, 0];
+        let color = [6, 0x24, 0x0d, 1, 1, 4];
+        let vs_total = 14 + format.len() + frames.iter().map(Vec::len).sum::<usize>() + color.len();
+        let vs_header = [
+            14,
+            0x24,
+            0x01,
+            1,
+            vs_total as u8,
+            (vs_total >> 8) as u8,
+            0x81,
+            0,
+            2,
+            0,
+            0,
+            0,
+            1,
+            0,
+        ];
+
+        let mut config = ConfigBuilder::new(1)
+            .iad(0, 2, VC_CLASS, 0x03, 0x00)
+            .interface(0, 0, VC_CLASS, 0x01, 0x00)
+            .class_specific(&vc_header)
+            .class_specific(&camera)
+            .class_specific(&output)
+            .endpoint(0x87, EndpointType::Interrupt, 16, 8)
+            // CS_ENDPOINT EP_INTERRUPT
+            .class_specific(&[5, 0x25, 0x03, 16, 0])
+            .interface(1, 0, VC_CLASS, 0x02, 0x00)
+            .class_specific(&vs_header)
+            .class_specific(&format);
+        for frame in &frames {
+            config = config.class_specific(frame);
+        }
+        config = config
+            .class_specific(&color)
+            .interface(1, 1, VC_CLASS, 0x02, 0x00)
+            .endpoint(0x81, EndpointType::Isochronous, 512, 1)
+            .interface(1, 2, VC_CLASS, 0x02, 0x00)
+            .endpoint(0x81, EndpointType::Isochronous, 2 << 11 | 1024, 1);
+
+        DeviceBuilder::new(0x046d, 0x0825)
+            .class(0xef, 0x02, 0x01)
+            .configuration(config)
+            .build()
+    }
+}
+
+impl From<DeviceFixture> for MockDevice {
+    fn from(fixture: DeviceFixture) -> Self {
+        MockDevice::new(fixture.device, fixture.configurations)
+    }
+}
+
+/// 离散帧间隔的 MJPEG 帧描述符
+fn mjpeg_frame(index: u8, width: u16, height: u16, intervals: &[u32]) -> Vec<u8> {
+    let frame_size = width as u32 * height as u32 * 2;
+    let fps = |interval: u32| 10_000_000 / interval;
+    let bit_rate = |interval: u32| frame_size * 8 * fps(interval);
+
+    let mut data = vec![26 + 4 * intervals.len() as u8, 0x24, 0x07, index, 0];
+    data.extend(&width.to_le_bytes());
+    data.extend(&height.to_le_bytes());
+    data.extend(&bit_rate(*intervals.last().unwrap()).to_le_bytes());
+    data.extend(&bit_rate(intervals[0]).to_le_bytes());
+    data.extend(&frame_size.to_le_bytes());
+    data.extend(&intervals[0].to_le_bytes());
+    data.push(intervals.len() as u8);
+    for interval in intervals {
+        data.extend(&interval.to_le_bytes());
+    }
+    data
+}
+
+/// 设备描述符构造器，默认 USB 2.0、按接口定义类别、EP0 最大包长 64
+#[derive(Debug, Clone)]
+pub struct DeviceBuilder {
+    device: [u8; DeviceDescriptor::LEN],
+    configurations: Vec<Vec<u8>>,
+}
+
+impl DeviceBuilder {
+    pub fn new(vendor_id: u16, product_id: u16) -> Self {
+        let mut device = [0u8; DeviceDescriptor::LEN];
+        device[0] = DeviceDescriptor::LEN as u8;
+        device[1] = 0x01;
+        device[2..4].copy_from_slice(&0x0200u16.to_le_bytes());
+        device[7] = 64;
+        device[8..10].copy_from_slice(&vendor_id.to_le_bytes());
+        device[10..12].copy_from_slice(&product_id.to_le_bytes());
+        device[12..14].copy_from_slice(&0x0100u16.to_le_bytes());
+        Self {
+            device,
+            configurations: Vec::new(),
+        }
+    }
+
+    /// bcdUSB
+    pub fn usb_version(mut self, bcd: u16) -> Self {
+        self.device[2..4].copy_from_slice(&bcd.to_le_bytes());
+        self
+    }
+
+    pub fn class(mut self, class: u8, subclass: u8, protocol: u8) -> Self {
+        self.device[4..7].copy_from_slice(&[class, subclass, protocol]);
+        self
+    }
+
+    pub fn max_packet_size_0(mut self, size: u8) -> Self {
+        self.device[7] = size;
+        self
+    }
+
+    /// 依次追加配置，bNumConfigurations 在 [`DeviceBuilder::build`] 时填写
+    pub fn configuration(mut self, config: ConfigBuilder) -> Self {
+        self.configurations.push(config.build());
+        self
+    }
+
+    pub fn build(mut self) -> DeviceFixture {
+        self.device[17] = self.configurations.len() as u8;
+        DeviceFixture {
+            device: self.device,
+            configurations: self.configurations,
+        }
+    }
+}
+
+/// 配置描述符构造器
+///
+/// 按描述符在配置中出现的顺序调用。wTotalLength、bNumInterfaces 与各接口的
+/// bNumEndpoints 在构造时自动计算。
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    raw: Vec<u8>,
+    interfaces: Vec<u8>,
+    /// 最近一个接口描述符在 `raw` 中的偏移
+    current_interface: Option<usize>,
+}
+
+impl ConfigBuilder {
+    /// 总线供电、最大 500 mA
+    pub fn new(configuration_value: u8) -> Self {
+        Self {
+            raw: vec![9, 0x02, 0, 0, 0, configuration_value, 0, 0x80, 250],
+            interfaces: Vec::new(),
+            current_interface: None,
+        }
+    }
+
+    /// bmAttributes
+    pub fn attributes(mut self, attributes: u8) -> Self {
+        self.raw[7] = attributes;
+        self
+    }
+
+    /// bMaxPower，2 mA 为单位
+    pub fn max_power(mut self, max_power: u8) -> Self {
+        self.raw[8] = max_power;
+        self
+    }
+
+    /// 接口关联描述符
+    pub fn iad(
+        mut self,
+        first_interface: u8,
+        interface_count: u8,
+        class: u8,
+        subclass: u8,
+        protocol: u8,
+    ) -> Self {
+        self.raw.extend(&[
+            8,
+            0x0b,
+            first_interface,
+            interface_count,
+            class,
+            subclass,
+            protocol,
+            0,
+        ]);
+        self
+    }
+
+    pub fn interface(
+        mut self,
+        number: u8,
+        alternate: u8,
+        class: u8,
+        subclass: u8,
+        protocol: u8,
+    ) -> Self {
+        if !self.interfaces.contains(&number) {
+            self.interfaces.push(number);
+        }
+        self.current_interface = Some(self.raw.len());
+        self.raw
+            .extend(&[9, 0x04, number, alternate, 0, class, subclass, protocol, 0]);
+        self
+    }
+
+    /// 属于最近一个接口的端点，`max_packet_size` 为 wMaxPacketSize 原始值（含高带宽倍数位）
+    pub fn endpoint(
+        mut self,
+        address: u8,
+        transfer_type: EndpointType,
+        max_packet_size: u16,
+        interval: u8,
+    ) -> Self {
+        let interface = self
+            .current_interface
+            .expect("endpoint must follow an interface");
+        self.raw[interface + 4] += 1;
+        self.raw.push(7);
+        self.raw.push(0x05);
+        self.raw.push(address);
+        self.raw.push(transfer_type as u8);
+        self.raw.extend(&max_packet_size.to_le_bytes());
+        self.raw.push(interval);
+        self
+    }
+
+    /// 原样追加一个描述符，如类特定描述符或 SuperSpeed 端点伴随描述符
+    pub fn class_specific(mut self, descriptor: &[u8]) -> Self {
+        debug_assert_eq!(descriptor.first().copied(), Some(descriptor.len() as u8));
+        self.raw.extend(descriptor);
+        self
+    }
+
+    pub fn build(mut self) -> Vec<u8> {
+        let total = self.raw.len() as u16;
+        self.raw[2..4].copy_from_slice(&total.to_le_bytes());
+        self.raw[4] = self.interfaces.len() as u8;
+        self.raw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use usb_if::descriptor::validate_descriptors;
+
+    use super::*;
+
+    #[test]
+    fn fixtures_are_clean() {
+        for fixture in [
+            DeviceFixture::hid_keyboard(),
+            DeviceFixture::msc_stick(),
+            DeviceFixture::cdc_acm_modem(),
+            DeviceFixture::uvc_camera(),
+        ] {
+            let issues = validate_descriptors(
+                &fixture.device_descriptor(),
+                &fixture.configuration_descriptors(),
+                None,
+            );
+            assert_eq!(issues, Vec::new());
+        }
+    }
+
+    #[test]
+    fn fixtures_parse() {
+        let kbd = DeviceFixture::hid_keyboard();
+        assert_eq!(kbd.device_descriptor().vendor_id, 0x0627);
+        let config = &kbd.configuration_descriptors()[0];
+        let iface = config.interfaces[0].first_alt_setting();
+        assert_eq!((iface.class, iface.subclass, iface.protocol), (3, 1, 1));
+        assert_eq!(iface.endpoints[0].transfer_type, EndpointType::Interrupt);
+
+        let msc = DeviceFixture::msc_stick().configuration_descriptors();
+        assert_eq!(msc[0].interfaces[0].first_alt_setting().endpoints.len(), 2);
+
+        let acm = DeviceFixture::cdc_acm_modem().configuration_descriptors();
+        assert_eq!(acm[0].num_interfaces, 2);
+        assert_eq!(acm[0].interfaces[1].first_alt_setting().class, 0x0a);
+    }
+
+    #[test]
+    fn attaches_to_mock_bus() {
+        use futures::FutureExt;
+
+        let (mut host, bus) = crate::USBHost::new_mock();
+        let id = bus.attach(DeviceFixture::cdc_acm_modem());
+        let devices = host.probe_devices().now_or_never().unwrap().unwrap();
+        let info = devices[0].configurations();
+        assert_eq!(devices[0].id(), id);
+        assert_eq!(info[0].interfaces.len(), 2);
+    }
+
+    #[test]
+    fn uvc_camera_layout() {
+        let camera = DeviceFixture::uvc_camera();
+        let raw = &camera.configurations[0];
+        assert_eq!(u16::from_le_bytes([raw[2], raw[3]]) as usize, raw.len());
+
+        let config = &camera.configuration_descriptors()[0];
+        assert_eq!(config.num_interfaces, 2);
+        let vs = &config.interfaces[1];
+        assert_eq!(vs.alt_settings.len(), 3);
+        let ep = &vs.alt_settings[2].endpoints[0];
+        assert_eq!(ep.transfer_type, EndpointType::Isochronous);
+        assert_eq!((ep.max_packet_size, ep.packets_per_microframe), (1024, 3));
+    }
+}
@@ -0,0 +1,291 @@
+//! 不访问硬件的模拟后端
+//!
+//! 设备按 [`MockDevice`] 给出的描述符应答标准请求，经 [`MockBus`] 在任意时刻插入或
+//! 拔出。拔出后未完成的请求以 [`TransferError::NoDevice`](usb_if::err::TransferError::NoDevice)
+//! 结束，[`MockBus::in_flight`] 可用于检查请求是否都已回收。
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Poll, Waker},
+};
+
+use futures::FutureExt;
+use usb_if::err::USBError;
+
+use crate::{
+    USBHost,
+    backend::{
+        BackendOp,
+        ty::{DeviceInfoOp, HotplugEventOp, ProbedDeviceInfoOp},
+    },
+};
+
+mod device;
+mod endpoint;
+pub mod fixtures;
+
+pub use device::MockDevice;
+use device::Sim;
+
+impl USBHost {
+    /// 模拟后端，返回的 [`MockBus`] 用于插入与拔出模拟设备
+    pub fn new_mock() -> (USBHost, MockBus) {
+        let bus = MockBus::default();
+        let host = USBHost {
+            backend: Box::new(Mock { bus: bus.clone() }),
+            raw_probe_order: false,
+            spawner: None,
+        };
+        (host, bus)
+    }
+}
+
+#[derive(Default)]
+struct Bus {
+    next_id: usize,
+    devices: BTreeMap<usize, Arc<Sim>>,
+    /// 上次列举设备之后的插拔事件
+    events: VecDeque<HotplugEventOp>,
+    waker: Option<Waker>,
+}
+
+impl Bus {
+    fn push(&mut self, event: HotplugEventOp) {
+        self.events.push_back(event);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// 模拟总线，可克隆后在测试的任意位置插拔设备
+#[derive(Clone, Default)]
+pub struct MockBus {
+    bus: Arc<Mutex<Bus>>,
+    /// 全部设备上已提交、尚未回收或取消的请求
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl MockBus {
+    /// 插入设备，返回其编号，即 [`DeviceInfo::id`](crate::DeviceInfo::id)
+    ///
+    /// 也可直接插入 [`DeviceFixture`](fixtures::DeviceFixture)。
+    pub fn attach(&self, device: impl Into<MockDevice>) -> usize {
+        let device = device.into();
+        let mut bus = self.bus.lock().unwrap();
+        bus.next_id += 1;
+        let id = bus.next_id;
+        let sim = Arc::new(Sim::new(id, device, self.in_flight.clone()));
+        bus.devices.insert(id, sim.clone());
+        bus.push(HotplugEventOp::Attached(probed(sim)));
+        id
+    }
+
+    /// 拔出设备，已打开的设备上未完成的请求随之结束；设备不存在时返回 `false`
+    pub fn detach(&self, id: usize) -> bool {
+        let mut bus = self.bus.lock().unwrap();
+        let Some(sim) = bus.devices.remove(&id) else {
+            return false;
+        };
+        sim.detach();
+        bus.push(HotplugEventOp::Detached { id });
+        true
+    }
+
+    pub fn detach_all(&self) {
+        let ids: Vec<_> = self.bus.lock().unwrap().devices.keys().copied().collect();
+        for id in ids {
+            self.detach(id);
+        }
+    }
+
+    /// 已提交、尚未回收或取消的请求数，包括已拔出的设备上的请求
+    ///
+    /// 丢弃端点时未回收的请求不会归还，测试结束时不为 0 说明有请求泄漏。
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+}
+
+fn probed(sim: Arc<Sim>) -> ProbedDeviceInfoOp {
+    let is_hub = sim.desc.class == 0x09;
+    let info = Box::new(device::DeviceInfo::new(sim)) as Box<dyn DeviceInfoOp>;
+    if is_hub {
+        ProbedDeviceInfoOp::Hub(info)
+    } else {
+        ProbedDeviceInfoOp::Device(info)
+    }
+}
+
+struct Mock {
+    bus: MockBus,
+}
+
+impl BackendOp for Mock {
+    fn init<'a>(&'a mut self) -> futures::future::BoxFuture<'a, Result<(), USBError>> {
+        async { Ok(()) }.boxed()
+    }
+
+    fn device_list<'a>(
+        &'a mut self,
+    ) -> futures::future::BoxFuture<'a, Result<Vec<ProbedDeviceInfoOp>, USBError>> {
+        let mut bus = self.bus.bus.lock().unwrap();
+        // 列举结果已包含此前插入的设备
+        bus.events.clear();
+        let devices = bus.devices.values().cloned().map(probed).collect();
+        async { Ok(devices) }.boxed()
+    }
+
+    fn open_device<'a>(
+        &'a mut self,
+        dev: &'a dyn DeviceInfoOp,
+    ) -> futures::future::BoxFuture<'a, Result<Box<dyn crate::backend::ty::DeviceOp>, USBError>>
+    {
+        let info = (dev as &dyn core::any::Any)
+            .downcast_ref::<device::DeviceInfo>()
+            .unwrap();
+        let res = device::Device::open(info)
+            .map(|device| Box::new(device) as Box<dyn crate::backend::ty::DeviceOp>);
+        async { res }.boxed()
+    }
+
+    fn wait_hotplug<'a>(&'a mut self) -> futures::future::BoxFuture<'a, ()> {
+        let bus = self.bus.bus.clone();
+        core::future::poll_fn(move |cx| {
+            let mut bus = bus.lock().unwrap();
+            if bus.events.is_empty() {
+                bus.waker = Some(cx.waker().clone());
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .boxed()
+    }
+
+    fn poll_hotplug<'a>(
+        &'a mut self,
+    ) -> futures::future::BoxFuture<'a, Result<Option<HotplugEventOp>, USBError>> {
+        let event = self.bus.bus.lock().unwrap().events.pop_front();
+        async { Ok(event) }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use usb_if::{endpoint::TransferRequest, err::TransferError};
+
+    use super::*;
+    use crate::HotplugEvent;
+
+    /// 厂商接口 0 带批量 IN 0x81 与批量 OUT 0x02
+    fn loopback_device() -> MockDevice {
+        let device = [
+            18, 1, 0x00, 0x02, 0xff, 0, 0, 64, 0x0a, 0x1a, 0xdd, 0xba, 0, 1, 0, 0, 0, 1,
+        ];
+        let mut config = vec![9, 2, 32, 0, 1, 1, 0, 0x80, 50, 9, 4, 0, 0, 2, 0xff, 0, 0, 0];
+        config.extend_from_slice(&[7, 5, 0x81, 2, 0x00, 0x02, 0]);
+        config.extend_from_slice(&[7, 5, 0x02, 2, 0x00, 0x02, 0]);
+        MockDevice::new(device, vec![config]).with_loopback(0x81, 0x02)
+    }
+
+    #[test]
+    fn loopback_then_surprise_removal() {
+        let (mut host, bus) = USBHost::new_mock();
+        host.init().now_or_never().unwrap().unwrap();
+        let id = bus.attach(loopback_device());
+        let info = host
+            .probe_devices()
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .find_map(|d| d.into_device_info())
+            .unwrap();
+        assert_eq!(info.id(), id);
+
+        let mut device = host.open_device(&info).now_or_never().unwrap().unwrap();
+        device
+            .claim_interface(0, 0)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let mut input = device.endpoint(0x81).unwrap();
+        let mut output = device.endpoint(0x02).unwrap();
+        output
+            .wait(TransferRequest::bulk_out(&[1, 2, 3]))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let mut buf = [0u8; 512];
+        let n = input
+            .wait(TransferRequest::bulk_in(&mut buf))
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+            .actual_length;
+        assert_eq!(buf[..n], [1, 2, 3]);
+
+        // 没有数据时 IN 请求挂起，拔出后以 NoDevice 结束并归还
+        let request = input.submit(TransferRequest::bulk_in(&mut buf)).unwrap();
+        let mut cx = core::task::Context::from_waker(futures::task::noop_waker_ref());
+        assert!(input.poll_request(request, &mut cx).is_pending());
+        assert_eq!(bus.in_flight(), 1);
+        assert!(bus.detach(id));
+        assert!(matches!(
+            input.poll_request(request, &mut cx),
+            Poll::Ready(Err(TransferError::NoDevice))
+        ));
+        assert_eq!(bus.in_flight(), 0);
+        assert!(output.submit(TransferRequest::bulk_out(&[0])).is_err());
+
+        let event = host.next_hotplug_event().now_or_never().unwrap().unwrap();
+        assert!(matches!(event, HotplugEvent::Detached { id: gone } if gone == id));
+        assert!(!bus.detach(id));
+    }
+
+    #[test]
+    fn dropped_interface_is_released_before_next_claim() {
+        let (mut host, bus) = USBHost::new_mock();
+        bus.attach(fixtures::DeviceFixture::cdc_acm_modem());
+        let info = host
+            .probe_devices()
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+            .remove(0)
+            .into_device_info()
+            .unwrap();
+        let mut device = host.open_device(&info).now_or_never().unwrap().unwrap();
+        let claimed = |device: &crate::Device, interface| {
+            device.as_raw::<device::Device>().is_claimed(interface)
+        };
+
+        let comm = device.claim(0, 0).now_or_never().unwrap().unwrap();
+        drop(comm);
+        assert!(claimed(&device, 0));
+        let data = device.claim(1, 0).now_or_never().unwrap().unwrap();
+        assert!(!claimed(&device, 0));
+        assert!(claimed(&device, 1));
+
+        // 显式释放的接口不会在下次声明时再次释放
+        data.release(&mut device).now_or_never().unwrap().unwrap();
+        let mut comm = device.claim(0, 0).now_or_never().unwrap().unwrap();
+        assert!(comm.take_endpoint(0x83).is_some());
+        drop(comm);
+        device
+            .claim_interface(1, 0)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(
+            claimed(&device, 0),
+            "interface with taken endpoints was released"
+        );
+    }
+}
@@ -2,17 +2,48 @@ use core::any::Any;
 
 use alloc::{boxed::Box, vec::Vec};
 
-use futures::future::{BoxFuture, LocalBoxFuture};
+use futures::future::BoxFuture;
 use usb_if::err::USBError;
 
-use crate::backend::ty::{DeviceInfoOp, DeviceOp, ProbedDeviceInfoOp};
+use crate::backend::ty::{DeviceInfoOp, DeviceOp, HotplugEventOp, ProbedDeviceInfoOp};
 
 #[cfg(umod)]
 pub mod umod;
 
+#[cfg(nmod)]
+pub mod nusb;
+
+#[cfg(mmod)]
+pub mod mock;
+
 #[cfg(kmod)]
 pub mod kmod;
 
+// 用户空间后端、模拟后端与 VFIO 共用的定时线程
+#[cfg(any(umod, nmod, mmod, all(kmod, not(target_os = "none"))))]
+pub(crate) mod std_timer;
+
+// TD 构建与 qTD/TD 编码逻辑与硬件无关，在主机上单独编译以运行其单元测试
+#[cfg(all(test, not(kmod)))]
+#[path = "kmod/xhci/td_builder.rs"]
+mod td_builder;
+
+#[cfg(all(test, not(kmod)))]
+#[path = "kmod/xhci/isoch.rs"]
+mod isoch;
+
+#[cfg(all(test, not(kmod)))]
+#[path = "kmod/xhci/event_cursor.rs"]
+mod xhci_event_cursor;
+
+#[cfg(all(test, not(kmod)))]
+#[path = "kmod/ehci/qtd.rs"]
+mod ehci_qtd;
+
+#[cfg(all(test, not(kmod)))]
+#[path = "kmod/ohci/td.rs"]
+mod ohci_td;
+
 pub(crate) mod ty;
 
 define_int_type!(Dci, u8);
@@ -41,8 +72,89 @@ pub(crate) trait BackendOp: Send + Any + 'static {
     fn open_device<'a>(
         &'a mut self,
         dev: &'a dyn DeviceInfoOp,
-    ) -> LocalBoxFuture<'a, Result<Box<dyn DeviceOp>, USBError>>;
+    ) -> BoxFuture<'a, Result<Box<dyn DeviceOp>, USBError>>;
+
+    /// 等待到 [`BackendOp::poll_hotplug`] 有变化可处理，可随时取消而不丢失事件
+    fn wait_hotplug<'a>(&'a mut self) -> BoxFuture<'a, ()>;
+
+    /// 处理已观察到的变化并取出一个插入或拔出事件，不等待新的变化
+    ///
+    /// 变化处理后不一定产生事件，此时返回 `None`。
+    fn poll_hotplug<'a>(&'a mut self) -> BoxFuture<'a, Result<Option<HotplugEventOp>, USBError>>;
 
     #[cfg(kmod)]
     fn create_event_handler(&mut self) -> Box<dyn crate::backend::ty::EventHandlerOp>;
+
+    #[cfg(kmod)]
+    fn perf_counters(&self) -> crate::backend::kmod::PerfCounters;
+
+    #[cfg(kmod)]
+    fn extcon(&self) -> Option<crate::backend::kmod::Usb2PhyExtcon>;
+
+    #[cfg(kmod)]
+    fn self_test<'a>(
+        &'a mut self,
+        count: u32,
+    ) -> BoxFuture<'a, Result<crate::backend::kmod::SelfTestReport, USBError>>;
+
+    #[cfg(kmod)]
+    fn eject<'a>(
+        &'a mut self,
+        device_id: usize,
+        power_off_port: bool,
+    ) -> BoxFuture<'a, Result<(), USBError>>;
+
+    #[cfg(kmod)]
+    fn suspend_device<'a>(&'a mut self, device_id: usize) -> BoxFuture<'a, Result<(), USBError>>;
+
+    #[cfg(kmod)]
+    fn resume_device<'a>(&'a mut self, device_id: usize) -> BoxFuture<'a, Result<(), USBError>>;
+
+    /// 延迟枚举模式下已连接、尚未枚举的根端口
+    #[cfg(kmod)]
+    fn pending_ports(&self) -> Vec<crate::backend::kmod::PendingPort>;
+
+    #[cfg(kmod)]
+    fn enumerate_port<'a>(
+        &'a mut self,
+        port: u8,
+    ) -> BoxFuture<'a, Result<Vec<ProbedDeviceInfoOp>, USBError>>;
+
+    #[cfg(kmod)]
+    fn enable_watchdog(&mut self, config: crate::backend::kmod::WatchdogConfig);
+
+    #[cfg(kmod)]
+    fn poll_watchdog<'a>(
+        &'a mut self,
+    ) -> BoxFuture<'a, Result<Option<crate::backend::kmod::WatchdogEvent>, USBError>>;
+
+    /// 开启看门狗时返回一次检查周期的定时，不借用后端
+    #[cfg(kmod)]
+    fn watchdog_tick(&self) -> Option<BoxFuture<'static, ()>>;
+
+    #[cfg(kmod)]
+    fn set_port_event_callback(&mut self, callback: crate::backend::kmod::PortEventCallback);
+
+    #[cfg(kmod)]
+    fn subscribe_ports(&mut self) -> crate::backend::kmod::PortSubscription;
+
+    #[cfg(kmod)]
+    fn ports(&self) -> Vec<crate::backend::kmod::PortSnapshot>;
+
+    #[cfg(kmod)]
+    fn hubs(&self) -> Vec<(usize, &crate::backend::kmod::HubDevice)>;
+
+    #[cfg(kmod)]
+    fn set_hub_port_test_mode<'a>(
+        &'a mut self,
+        hub_device_id: usize,
+        port: u8,
+        selector: usb_if::host::hub::TestSelector,
+    ) -> BoxFuture<'a, Result<(), USBError>>;
+
+    #[cfg(all(kmod, feature = "fault-injection"))]
+    fn inject_port_disable(&mut self, port: u8) -> Result<(), USBError>;
+
+    #[cfg(all(kmod, feature = "mem-track"))]
+    fn memory_report(&self) -> crate::backend::kmod::MemoryReport;
 }
@@ -0,0 +1,395 @@
+use core::hash::{Hash, Hasher};
+use std::{collections::HashMap, fmt::Debug, hash::DefaultHasher};
+
+use futures::FutureExt;
+use usb_if::descriptor::{
+    ConfigurationDescriptor, DescriptorType, DeviceDescriptor, EndpointType, InterfaceDescriptor,
+    InterfaceDescriptors,
+};
+use usb_if::endpoint::EndpointInfo;
+use usb_if::host::hub::Speed;
+use usb_if::transfer::Direction;
+
+use super::{
+    endpoint::{EndpointImpl, Pipe},
+    err::usb_error,
+};
+use crate::backend::ty::ep::{Endpoint, declared_interval};
+use crate::backend::ty::{DeviceInfoOp, DeviceOp};
+use crate::device::DeviceLocation;
+use crate::err::*;
+
+pub struct DeviceInfo {
+    pub(crate) raw: ::nusb::DeviceInfo,
+    desc: DeviceDescriptor,
+    configs: Vec<ConfigurationDescriptor>,
+}
+
+impl Debug for DeviceInfo {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DeviceInfo").finish()
+    }
+}
+
+impl DeviceInfo {
+    pub(crate) fn new(raw: ::nusb::DeviceInfo) -> Self {
+        let (desc, configs) = cached_descriptors(&raw).unwrap_or_else(|| summary_descriptors(&raw));
+        Self { raw, desc, configs }
+    }
+}
+
+/// nusb 的设备编号不透明，以其哈希作为设备编号，设备拔出后仍可用于匹配热插拔事件
+pub(crate) fn device_id(id: ::nusb::DeviceId) -> usize {
+    let mut hasher = DefaultHasher::new();
+    id.hash(&mut hasher);
+    hasher.finish() as usize
+}
+
+impl DeviceInfoOp for DeviceInfo {
+    fn id(&self) -> usize {
+        device_id(self.raw.id())
+    }
+
+    fn backend_name(&self) -> &str {
+        "nusb"
+    }
+
+    fn descriptor(&self) -> &DeviceDescriptor {
+        &self.desc
+    }
+
+    fn configuration_descriptors(&self) -> &[ConfigurationDescriptor] {
+        &self.configs
+    }
+
+    fn location(&self) -> DeviceLocation {
+        #[cfg(target_os = "linux")]
+        let bus = self.raw.busnum();
+        #[cfg(not(target_os = "linux"))]
+        let bus = self.raw.bus_id().parse().unwrap_or(0);
+        DeviceLocation {
+            bus,
+            port_path: self.raw.port_chain().to_vec(),
+            address: self.raw.device_address() as usize,
+        }
+    }
+}
+
+/// 读取内核缓存的描述符，不打开设备
+#[cfg(target_os = "linux")]
+fn cached_descriptors(
+    raw: &::nusb::DeviceInfo,
+) -> Option<(DeviceDescriptor, Vec<ConfigurationDescriptor>)> {
+    let data = std::fs::read(raw.sysfs_path().join("descriptors")).ok()?;
+    parse_descriptors(&data)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cached_descriptors(
+    _raw: &::nusb::DeviceInfo,
+) -> Option<(DeviceDescriptor, Vec<ConfigurationDescriptor>)> {
+    None
+}
+
+/// 解析设备描述符及其后依次排列的各配置描述符（usbfs 与 sysfs `descriptors` 的格式）
+fn parse_descriptors(data: &[u8]) -> Option<(DeviceDescriptor, Vec<ConfigurationDescriptor>)> {
+    let desc = DeviceDescriptor::parse(data)?;
+    let mut rest = data.get(DeviceDescriptor::LEN..)?;
+    let mut configs = Vec::new();
+    while rest.len() >= ConfigurationDescriptor::LEN && rest[1] == DescriptorType::CONFIGURATION.0 {
+        let total = (u16::from_le_bytes([rest[2], rest[3]]) as usize).min(rest.len());
+        let (config, tail) = rest.split_at(total);
+        configs.push(ConfigurationDescriptor::parse(config)?);
+        rest = tail;
+    }
+    Some((desc, configs))
+}
+
+/// 无法读取缓存描述符时由枚举信息构造，只含活动配置的接口类别，不含端点
+///
+/// 打开设备后以完整的描述符替换。
+fn summary_descriptors(
+    raw: &::nusb::DeviceInfo,
+) -> (DeviceDescriptor, Vec<ConfigurationDescriptor>) {
+    let desc = DeviceDescriptor {
+        usb_version: raw.usb_version(),
+        class: raw.class(),
+        subclass: raw.subclass(),
+        protocol: raw.protocol(),
+        max_packet_size_0: 0,
+        vendor_id: raw.vendor_id(),
+        product_id: raw.product_id(),
+        device_version: raw.device_version(),
+        manufacturer_string_index: None,
+        product_string_index: None,
+        serial_number_string_index: None,
+        num_configurations: 1,
+    };
+    let interfaces: Vec<_> = raw
+        .interfaces()
+        .map(|iface| InterfaceDescriptors {
+            interface_number: iface.interface_number(),
+            alt_settings: vec![InterfaceDescriptor {
+                interface_number: iface.interface_number(),
+                alternate_setting: 0,
+                class: iface.class(),
+                subclass: iface.subclass(),
+                protocol: iface.protocol(),
+                string_index: None,
+                string: iface.interface_string().map(Into::into),
+                num_endpoints: 0,
+                endpoints: Vec::new(),
+                extra: Vec::new(),
+            }],
+        })
+        .collect();
+    let config = ConfigurationDescriptor {
+        num_interfaces: interfaces.len() as u8,
+        configuration_value: 1,
+        attributes: 0x80,
+        max_power: 0,
+        string_index: None,
+        string: None,
+        interfaces,
+        raw: Vec::new(),
+    };
+    (desc, vec![config])
+}
+
+pub struct Device {
+    id: usize,
+    raw: ::nusb::Device,
+    desc: DeviceDescriptor,
+    configs: Vec<ConfigurationDescriptor>,
+    speed: Speed,
+    /// 已声明的接口，端点从中打开
+    interfaces: HashMap<u8, ::nusb::Interface>,
+    /// 声明接口时卸载内核驱动，nusb 在释放接口时重新绑定
+    auto_detach: bool,
+    ctrl_ep: Endpoint,
+}
+
+impl Device {
+    pub(crate) async fn new(info: &DeviceInfo) -> Result<Self> {
+        let raw = info.raw.open().await.map_err(usb_error)?;
+
+        // 打开后 nusb 已缓存全部描述符，取代枚举时可能不完整的信息
+        let desc = DeviceDescriptor::parse(raw.device_descriptor().as_bytes())
+            .unwrap_or_else(|| info.desc.clone());
+        let configs = raw
+            .configurations()
+            .map(|config| ConfigurationDescriptor::parse(config.as_bytes()))
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_else(|| info.configs.clone());
+        let speed = match raw.speed() {
+            Some(::nusb::Speed::Low) => Speed::Low,
+            Some(::nusb::Speed::High) => Speed::High,
+            Some(::nusb::Speed::Super) => Speed::SuperSpeed,
+            Some(::nusb::Speed::SuperPlus) => Speed::SuperSpeedPlus,
+            _ => Speed::Full,
+        };
+
+        let ctrl_ep = Endpoint::new(EndpointInfo::control(), EndpointImpl::control(raw.clone()));
+
+        Ok(Self {
+            id: info.id(),
+            raw,
+            desc,
+            configs,
+            speed,
+            interfaces: HashMap::new(),
+            auto_detach: true,
+            ctrl_ep,
+        })
+    }
+
+    async fn _claim_interface(&mut self, interface: u8, alternate: u8) -> Result<()> {
+        // 仅 Linux 上会卸载内核驱动，其他系统等同于直接声明
+        let iface = if self.auto_detach {
+            self.raw.detach_and_claim_interface(interface).await
+        } else {
+            self.raw.claim_interface(interface).await
+        }
+        .map_err(usb_error)?;
+        debug!("Interface {interface} claimed successfully");
+
+        // 接口有备用设置时总是显式选择，从流设置切回 0 时才会释放带宽
+        let has_alternates = self
+            .configs
+            .iter()
+            .flat_map(|c| c.interfaces.iter())
+            .any(|i| i.interface_number == interface && i.alt_settings.len() > 1);
+        if alternate != 0 || has_alternates {
+            iface.set_alt_setting(alternate).await.map_err(usb_error)?;
+            debug!("Interface {interface} set to alternate setting {alternate} successfully");
+        }
+        self.interfaces.insert(interface, iface);
+        Ok(())
+    }
+
+    fn open_pipe(&self, desc: &usb_if::descriptor::EndpointDescriptor) -> Result<Box<dyn Pipe>> {
+        use ::nusb::transfer::{Bulk, In, Interrupt, Out};
+
+        let iface = self
+            .interfaces
+            .values()
+            .find(|iface| {
+                iface
+                    .descriptor()
+                    .is_some_and(|alt| alt.endpoints().any(|ep| ep.address() == desc.address))
+            })
+            .ok_or(USBError::NotFound)?;
+        let address = desc.address;
+        let pipe: Box<dyn Pipe> = match (desc.transfer_type, desc.direction) {
+            (EndpointType::Bulk, Direction::In) => {
+                Box::new(iface.endpoint::<Bulk, In>(address).map_err(usb_error)?)
+            }
+            (EndpointType::Bulk, Direction::Out) => {
+                Box::new(iface.endpoint::<Bulk, Out>(address).map_err(usb_error)?)
+            }
+            (EndpointType::Interrupt, Direction::In) => Box::new(
+                iface
+                    .endpoint::<Interrupt, In>(address)
+                    .map_err(usb_error)?,
+            ),
+            (EndpointType::Interrupt, Direction::Out) => Box::new(
+                iface
+                    .endpoint::<Interrupt, Out>(address)
+                    .map_err(usb_error)?,
+            ),
+            // nusb 尚不支持等时传输
+            _ => return Err(USBError::NotSupported),
+        };
+        Ok(pipe)
+    }
+}
+
+impl DeviceOp for Device {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn backend_name(&self) -> &str {
+        "nusb"
+    }
+
+    fn descriptor(&self) -> &DeviceDescriptor {
+        &self.desc
+    }
+
+    fn configuration_descriptors(&self) -> &[ConfigurationDescriptor] {
+        &self.configs
+    }
+
+    fn set_descriptors(&mut self, desc: DeviceDescriptor, configs: Vec<ConfigurationDescriptor>) {
+        self.desc = desc;
+        self.configs = configs;
+    }
+
+    fn ctrl_ep_ref(&self) -> &Endpoint {
+        &self.ctrl_ep
+    }
+
+    fn ctrl_ep_mut(&mut self) -> &mut Endpoint {
+        &mut self.ctrl_ep
+    }
+
+    fn claim_interface<'a>(
+        &'a mut self,
+        interface: u8,
+        alternate: u8,
+    ) -> futures::future::BoxFuture<'a, std::result::Result<(), USBError>> {
+        async move { self._claim_interface(interface, alternate).await }.boxed()
+    }
+
+    fn release_interface<'a>(
+        &'a mut self,
+        interface: u8,
+        _alternate: u8,
+    ) -> futures::future::BoxFuture<'a, std::result::Result<(), USBError>> {
+        async move {
+            // 最后一个引用（含端点）释放时 nusb 才真正释放接口
+            self.interfaces.remove(&interface);
+            debug!("Interface {interface} released");
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn set_configuration<'a>(
+        &'a mut self,
+        configuration_value: u8,
+    ) -> futures::future::BoxFuture<'a, std::result::Result<(), USBError>> {
+        async move {
+            self.raw
+                .set_configuration(configuration_value)
+                .await
+                .map_err(usb_error)
+        }
+        .boxed()
+    }
+
+    fn endpoint(
+        &mut self,
+        desc: &usb_if::descriptor::EndpointDescriptor,
+    ) -> std::result::Result<Endpoint, USBError> {
+        let ep = EndpointImpl::pipe(desc.address, self.open_pipe(desc)?);
+        // 由操作系统调度，按设备声明的周期估计
+        let interval = declared_interval(self.speed, desc.transfer_type, desc.interval);
+        Ok(Endpoint::new(EndpointInfo::from(desc), ep).with_service_interval(interval))
+    }
+
+    fn set_auto_detach_kernel_driver(&mut self, enable: bool) -> std::result::Result<(), USBError> {
+        self.auto_detach = enable;
+        Ok(())
+    }
+
+    fn detach_kernel_driver(&mut self, interface: u8) -> std::result::Result<(), USBError> {
+        self.raw
+            .detach_kernel_driver(interface)
+            .map_err(usb_error)?;
+        debug!("Kernel driver detached for interface {interface}");
+        Ok(())
+    }
+
+    fn attach_kernel_driver(&mut self, interface: u8) -> std::result::Result<(), USBError> {
+        self.raw
+            .attach_kernel_driver(interface)
+            .map_err(usb_error)?;
+        debug!("Kernel driver attached for interface {interface}");
+        Ok(())
+    }
+
+    fn update_hub(
+        &mut self,
+        _params: crate::backend::ty::HubParams,
+    ) -> futures::future::BoxFuture<'_, std::result::Result<(), USBError>> {
+        async {
+            debug!("nusb backend: Hub parameters managed by kernel");
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_device_and_configs() {
+        let mut data = vec![
+            18, 1, 0x00, 0x02, 0, 0, 0, 64, 0x34, 0x12, 0x78, 0x56, 0x00, 0x01, 0, 0, 0, 2,
+        ];
+        for value in [1u8, 2] {
+            data.extend_from_slice(&[9, 2, 18, 0, 1, value, 0, 0x80, 50]);
+            data.extend_from_slice(&[9, 4, 0, 0, 0, 0xff, 0, 0, 0]);
+        }
+        let (desc, configs) = parse_descriptors(&data).unwrap();
+        assert_eq!(desc.vendor_id, 0x1234);
+        assert_eq!(desc.product_id, 0x5678);
+        assert_eq!(configs.len(), 2);
+        assert_eq!(configs[1].configuration_value, 2);
+        assert_eq!(configs[1].interfaces[0].alt_settings[0].class, 0xff);
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::IntoFuture,
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use ::nusb::transfer::{Buffer, BulkOrInterrupt, Completion, EndpointDirection};
+use futures::{FutureExt, future::BoxFuture};
+use usb_if::{
+    endpoint::{RequestId, TransferCompletion, TransferRequest},
+    err::TransferError,
+    transfer::Direction,
+};
+
+use super::err::transfer_error;
+use crate::backend::ty::{
+    ep::{EndpointOp, transfer_to_completion},
+    timer::Timer,
+    transfer::{Transfer, TransferKind},
+};
+
+/// 未设置超时时控制传输的超时
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// nusb 的批量与中断端点，按类型与方向擦除泛型参数
+pub(crate) trait Pipe: Send {
+    fn max_packet_size(&self) -> usize;
+    fn submit(&mut self, buf: Buffer);
+    fn poll_next_complete(&mut self, cx: &mut Context<'_>) -> Poll<Completion>;
+    fn cancel_all(&mut self);
+    fn clear_halt(&mut self) -> BoxFuture<'static, Result<(), ::nusb::Error>>;
+}
+
+impl<T: BulkOrInterrupt + 'static, D: EndpointDirection + 'static> Pipe for ::nusb::Endpoint<T, D> {
+    fn max_packet_size(&self) -> usize {
+        ::nusb::Endpoint::max_packet_size(self)
+    }
+
+    fn submit(&mut self, buf: Buffer) {
+        ::nusb::Endpoint::submit(self, buf)
+    }
+
+    fn poll_next_complete(&mut self, cx: &mut Context<'_>) -> Poll<Completion> {
+        ::nusb::Endpoint::poll_next_complete(self, cx)
+    }
+
+    fn cancel_all(&mut self) {
+        ::nusb::Endpoint::cancel_all(self)
+    }
+
+    fn clear_halt(&mut self) -> BoxFuture<'static, Result<(), ::nusb::Error>> {
+        ::nusb::Endpoint::clear_halt(self).into_future().boxed()
+    }
+}
+
+enum Target {
+    /// 默认控制端点，nusb 的控制传输各自独立完成
+    Control(::nusb::Device),
+    /// 批量与中断端点，nusb 按提交顺序返回完成结果
+    Pipe(Box<dyn Pipe>),
+}
+
+struct Pending {
+    id: u64,
+    origin: Transfer,
+    /// 控制传输的 future，IN 传输输出读到的数据
+    control: Option<BoxFuture<'static, Result<Vec<u8>, ::nusb::transfer::TransferError>>>,
+}
+
+struct State {
+    target: Target,
+    /// 按提交顺序排列的未完成请求
+    pending: VecDeque<Pending>,
+    done: HashMap<u64, Result<Transfer, TransferError>>,
+    /// 最近注册的 waker，回收时以它轮询 nusb，避免覆盖已注册的唤醒
+    waker: Option<Waker>,
+}
+
+impl State {
+    fn poll(&mut self) {
+        let waker = self.waker.clone().unwrap_or_else(futures::task::noop_waker);
+        let mut cx = Context::from_waker(&waker);
+        match &mut self.target {
+            Target::Control(_) => {
+                let mut i = 0;
+                while i < self.pending.len() {
+                    let fut = self.pending[i].control.as_mut().unwrap();
+                    let Poll::Ready(res) = fut.poll_unpin(&mut cx) else {
+                        i += 1;
+                        continue;
+                    };
+                    let pending = self.pending.remove(i).unwrap();
+                    self.done
+                        .insert(pending.id, control_result(pending.origin, res));
+                }
+            }
+            Target::Pipe(pipe) => {
+                while !self.pending.is_empty() {
+                    let Poll::Ready(completion) = pipe.poll_next_complete(&mut cx) else {
+                        break;
+                    };
+                    let pending = self.pending.pop_front().unwrap();
+                    self.done
+                        .insert(pending.id, pipe_result(pending.origin, completion));
+                }
+            }
+        }
+    }
+}
+
+fn control_result(
+    mut origin: Transfer,
+    res: Result<Vec<u8>, ::nusb::transfer::TransferError>,
+) -> Result<Transfer, TransferError> {
+    let data = res.map_err(|e| match e {
+        // 控制传输只在超时时被 nusb 取消
+        ::nusb::transfer::TransferError::Cancelled => TransferError::Timeout,
+        e => transfer_error(e),
+    })?;
+    origin.transfer_len = match origin.direction {
+        Direction::In => copy_in(&origin, &data)?,
+        Direction::Out => origin.buffer.map_or(0, |(_, len)| len),
+    };
+    Ok(origin)
+}
+
+fn pipe_result(mut origin: Transfer, completion: Completion) -> Result<Transfer, TransferError> {
+    completion.status.map_err(transfer_error)?;
+    origin.transfer_len = match origin.direction {
+        Direction::In => copy_in(&origin, &completion.buffer)?,
+        Direction::Out => completion.actual_len,
+    };
+    Ok(origin)
+}
+
+/// 把 nusb 缓冲区中读到的数据复制到请求的缓冲区
+fn copy_in(origin: &Transfer, data: &[u8]) -> Result<usize, TransferError> {
+    let Some((ptr, len)) = origin.buffer else {
+        return Ok(0);
+    };
+    // IN 请求长度按最大包长向上取整，设备多发的数据视为溢出
+    if data.len() > len {
+        return Err(TransferError::Other(anyhow!(
+            "Overflow: received {} bytes into a {len} byte buffer",
+            data.len()
+        )));
+    }
+    unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), ptr.as_ptr(), data.len()) };
+    Ok(data.len())
+}
+
+pub struct EndpointImpl {
+    address: u8,
+    state: Mutex<State>,
+    next_id: u64,
+    timeout: Duration,
+}
+
+// 请求缓冲区的裸指针只在回收时访问
+unsafe impl Send for EndpointImpl {}
+
+impl EndpointImpl {
+    pub fn control(device: ::nusb::Device) -> Self {
+        Self::new(0, Target::Control(device))
+    }
+
+    pub(crate) fn pipe(address: u8, pipe: Box<dyn Pipe>) -> Self {
+        Self::new(address, Target::Pipe(pipe))
+    }
+
+    fn new(address: u8, target: Target) -> Self {
+        Self {
+            address,
+            state: Mutex::new(State {
+                target,
+                pending: VecDeque::new(),
+                done: HashMap::new(),
+                waker: None,
+            }),
+            next_id: 0,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    fn state(&mut self) -> &mut State {
+        self.state.get_mut().unwrap()
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn submit_control(
+    device: &::nusb::Device,
+    transfer: &Transfer,
+    timeout: Duration,
+) -> Result<BoxFuture<'static, Result<Vec<u8>, ::nusb::transfer::TransferError>>, TransferError> {
+    use ::nusb::transfer::{ControlIn, ControlOut, ControlType, Recipient};
+
+    let TransferKind::Control(setup) = &transfer.kind else {
+        return Err(TransferError::InvalidEndpoint);
+    };
+    let (ptr, len) = transfer
+        .buffer
+        .map_or((core::ptr::NonNull::dangling(), 0), |b| b);
+    let bytes = setup.to_bytes(transfer.direction, len as _);
+    let control_type = match (bytes[0] >> 5) & 0x03 {
+        0 => ControlType::Standard,
+        1 => ControlType::Class,
+        2 => ControlType::Vendor,
+        _ => return Err(TransferError::NotSupported),
+    };
+    let recipient = match bytes[0] & 0x1f {
+        0 => Recipient::Device,
+        1 => Recipient::Interface,
+        2 => Recipient::Endpoint,
+        _ => Recipient::Other,
+    };
+    let request = bytes[1];
+    let value = u16::from_le_bytes([bytes[2], bytes[3]]);
+    let index = u16::from_le_bytes([bytes[4], bytes[5]]);
+    let length = u16::try_from(len).map_err(|_| TransferError::NotSupported)?;
+
+    Ok(match transfer.direction {
+        Direction::In => {
+            let data = ControlIn {
+                control_type,
+                recipient,
+                request,
+                value,
+                index,
+                length,
+            };
+            device.control_in(data, timeout).into_future().boxed()
+        }
+        Direction::Out => {
+            // nusb 提交时复制数据，返回的 future 不借用请求缓冲区
+            let data = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), len) };
+            let data = ControlOut {
+                control_type,
+                recipient,
+                request,
+                value,
+                index,
+                data,
+            };
+            device
+                .control_out(data, timeout)
+                .into_future()
+                .map(|res| res.map(|()| Vec::new()))
+                .boxed()
+        }
+    })
+}
+
+/// Windows 只能经已声明的接口发起控制传输，暂不支持
+#[cfg(target_os = "windows")]
+fn submit_control(
+    _device: &::nusb::Device,
+    _transfer: &Transfer,
+    _timeout: Duration,
+) -> Result<BoxFuture<'static, Result<Vec<u8>, ::nusb::transfer::TransferError>>, TransferError> {
+    Err(TransferError::NotSupported)
+}
+
+impl Timer for EndpointImpl {
+    fn now(&self) -> Duration {
+        crate::backend::std_timer::now()
+    }
+
+    fn wake_at(&self, deadline: Duration, waker: &Waker) {
+        crate::backend::std_timer::wake_at(deadline, waker)
+    }
+}
+
+impl EndpointOp for EndpointImpl {
+    fn submit_request(&mut self, request: TransferRequest) -> Result<RequestId, TransferError> {
+        let (kind, direction, buffer) = request.into();
+        let origin = Transfer {
+            kind,
+            direction,
+            buffer: buffer.map(|buffer| (buffer.ptr, buffer.len)),
+            transfer_len: 0,
+            iso_packet_actual_lengths: Vec::new(),
+            iso_packet_status: Vec::new(),
+        };
+        let timeout = self.timeout;
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let state = self.state();
+        let control = match &mut state.target {
+            Target::Control(device) => Some(submit_control(device, &origin, timeout)?),
+            Target::Pipe(pipe) => {
+                if matches!(origin.kind, TransferKind::Isochronous { .. }) {
+                    return Err(TransferError::NotSupported);
+                }
+                let (ptr, len) = origin
+                    .buffer
+                    .map_or((core::ptr::NonNull::dangling(), 0), |b| b);
+                let buf = match origin.direction {
+                    Direction::Out => {
+                        Buffer::from(unsafe { core::slice::from_raw_parts(ptr.as_ptr(), len) })
+                    }
+                    // nusb 要求 IN 请求长度为最大包长的非零整数倍
+                    Direction::In => {
+                        let mps = pipe.max_packet_size().max(1);
+                        Buffer::new(len.max(1).next_multiple_of(mps))
+                    }
+                };
+                pipe.submit(buf);
+                None
+            }
+        };
+        state.pending.push_back(Pending {
+            id,
+            origin,
+            control,
+        });
+        trace!(
+            "Submitted nusb transfer id {id:#x} on endpoint {:#04x}",
+            self.address
+        );
+        Ok(RequestId::new(id))
+    }
+
+    fn reclaim_request(
+        &mut self,
+        id: RequestId,
+    ) -> Option<Result<TransferCompletion, TransferError>> {
+        let state = self.state();
+        if !state.done.contains_key(&id.raw()) {
+            state.poll();
+        }
+        let res = state.done.remove(&id.raw())?;
+        Some(res.map(|transfer| transfer_to_completion(id, transfer)))
+    }
+
+    fn register_waker(&self, _id: RequestId, cx: &mut Context<'_>) {
+        let mut state = self.state.lock().unwrap();
+        state.waker = Some(cx.waker().clone());
+        state.poll();
+        if !state.done.is_empty() {
+            cx.waker().wake_by_ref();
+        }
+    }
+
+    fn pending_requests(&self) -> Vec<RequestId> {
+        let state = self.state.lock().unwrap();
+        state
+            .pending
+            .iter()
+            .map(|p| p.id)
+            .chain(state.done.keys().copied())
+            .map(RequestId::new)
+            .collect()
+    }
+
+    fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout.unwrap_or(DEFAULT_TIMEOUT);
+    }
+
+    /// nusb 只能取消端点上的全部请求，同一端点上其他未完成的请求也会以
+    /// [`TransferError::Cancelled`] 完成
+    fn cancel_request(
+        &mut self,
+        id: RequestId,
+    ) -> BoxFuture<'_, Result<Option<TransferCompletion>, TransferError>> {
+        Box::pin(async move {
+            let state = self.state();
+            if !state.done.contains_key(&id.raw()) {
+                let index = state
+                    .pending
+                    .iter()
+                    .position(|p| p.id == id.raw())
+                    .ok_or(TransferError::InvalidEndpoint)?;
+                match &mut state.target {
+                    // 丢弃 future 即取消，nusb 使用自己的缓冲区，不再访问请求缓冲区
+                    Target::Control(_) => {
+                        state.pending.remove(index);
+                        return Ok(None);
+                    }
+                    Target::Pipe(pipe) => pipe.cancel_all(),
+                }
+            }
+
+            let res = std::future::poll_fn(|cx| {
+                if let Some(res) = self.reclaim_request(id) {
+                    return Poll::Ready(res);
+                }
+                self.register_waker(id, cx);
+                match self.reclaim_request(id) {
+                    Some(res) => Poll::Ready(res),
+                    None => Poll::Pending,
+                }
+            })
+            .await;
+            match res {
+                Ok(completion) => Ok(Some(completion)),
+                Err(TransferError::Cancelled) => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn reset_halt(&mut self) -> BoxFuture<'_, Result<(), TransferError>> {
+        // 端点 0 的协议 STALL 由操作系统恢复
+        let Target::Pipe(pipe) = &mut self.state().target else {
+            return Box::pin(async { Ok(()) });
+        };
+        // nusb 无法只复位主机侧，会再次向设备发送 CLEAR_FEATURE(ENDPOINT_HALT)
+        let fut = pipe.clear_halt();
+        Box::pin(async move {
+            fut.await
+                .map_err(|e| TransferError::Other(anyhow!("Failed to clear halt: {e}")))
+        })
+    }
+}
@@ -0,0 +1,20 @@
+use usb_if::err::{TransferError, USBError};
+
+pub(crate) fn usb_error(err: ::nusb::Error) -> USBError {
+    match err.kind() {
+        ::nusb::ErrorKind::NotFound => USBError::NotFound,
+        ::nusb::ErrorKind::Unsupported => USBError::NotSupported,
+        _ => USBError::Other(anyhow!("nusb error: {err}")),
+    }
+}
+
+pub(crate) fn transfer_error(err: ::nusb::transfer::TransferError) -> TransferError {
+    use ::nusb::transfer::TransferError as E;
+    match err {
+        E::Cancelled => TransferError::Cancelled,
+        E::Stall => TransferError::Stall,
+        E::Disconnected => TransferError::NoDevice,
+        E::InvalidArgument => TransferError::Other(anyhow!("nusb: invalid argument")),
+        E::Fault | E::Unknown(_) => TransferError::Other(anyhow!("nusb transfer error: {err}")),
+    }
+}
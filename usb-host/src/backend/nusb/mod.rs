@@ -0,0 +1,149 @@
+use ::nusb::hotplug::HotplugEvent;
+use futures::{FutureExt, StreamExt};
+use usb_if::err::USBError;
+
+use crate::{
+    USBHost,
+    backend::{
+        BackendOp,
+        ty::{DeviceInfoOp, HotplugEventOp, ProbedDeviceInfoOp},
+    },
+};
+
+mod device;
+mod endpoint;
+mod err;
+
+impl USBHost {
+    /// 基于 nusb 的用户空间后端，直接使用操作系统接口，不依赖 C libusb
+    pub fn new_nusb() -> Result<USBHost, USBError> {
+        let host = USBHost {
+            backend: Box::new(Nusb::new()),
+            raw_probe_order: false,
+            spawner: None,
+        };
+        Ok(host)
+    }
+}
+
+pub struct Nusb {
+    /// 在 `init` 中创建，之后插拔的设备都会进入事件队列；平台不支持热插拔时为空
+    hotplug: Option<::nusb::hotplug::HotplugWatch>,
+    /// `wait_hotplug` 已从监视中取出、尚未交给 `poll_hotplug` 的事件
+    ready: Option<Result<HotplugEventOp, USBError>>,
+}
+
+impl Nusb {
+    pub fn new() -> Self {
+        Self {
+            hotplug: None,
+            ready: None,
+        }
+    }
+
+    async fn device_list(&mut self) -> Result<Vec<ProbedDeviceInfoOp>, USBError> {
+        let devices = ::nusb::list_devices().await.map_err(err::usb_error)?;
+        Ok(devices.map(probed).collect())
+    }
+
+    /// 在首次列举设备之前创建监视，列举与等待事件之间插入的设备不会丢失
+    fn watch_devices(&mut self) -> Result<(), USBError> {
+        if self.hotplug.is_some() {
+            return Ok(());
+        }
+        match ::nusb::watch_devices().map_err(err::usb_error) {
+            Ok(watch) => self.hotplug = Some(watch),
+            Err(USBError::NotSupported) => debug!("nusb hotplug is not supported"),
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    async fn wait_hotplug(&mut self) {
+        // 不支持热插拔时立即返回，由 `poll_hotplug` 报告错误
+        let Some(hotplug) = self.hotplug.as_mut() else {
+            return;
+        };
+        if self.ready.is_some() {
+            return;
+        }
+        // `next` 在完成前取消不会丢失事件
+        let event = hotplug
+            .next()
+            .await
+            .ok_or(USBError::Other(anyhow!("nusb hotplug watch ended")));
+        self.ready = Some(event.map(|event| match event {
+            HotplugEvent::Connected(info) => HotplugEventOp::Attached(probed(info)),
+            HotplugEvent::Disconnected(id) => HotplugEventOp::Detached {
+                id: device::device_id(id),
+            },
+        }));
+    }
+
+    fn poll_hotplug(&mut self) -> Result<Option<HotplugEventOp>, USBError> {
+        if self.hotplug.is_none() {
+            return Err(USBError::NotSupported);
+        }
+        self.ready.take().transpose()
+    }
+
+    async fn _open_device(
+        &mut self,
+        dev: &dyn super::ty::DeviceInfoOp,
+    ) -> Result<Box<dyn super::ty::DeviceOp>, USBError> {
+        let dev_info = (dev as &dyn core::any::Any)
+            .downcast_ref::<device::DeviceInfo>()
+            .unwrap();
+
+        let device = device::Device::new(dev_info).await?;
+        Ok(Box::new(device) as Box<dyn super::ty::DeviceOp>)
+    }
+}
+
+fn probed(info: ::nusb::DeviceInfo) -> ProbedDeviceInfoOp {
+    let info = device::DeviceInfo::new(info);
+    let is_hub = info.descriptor().class == 0x09;
+    let info = Box::new(info) as Box<dyn super::ty::DeviceInfoOp>;
+    if is_hub {
+        ProbedDeviceInfoOp::Hub(info)
+    } else {
+        ProbedDeviceInfoOp::Device(info)
+    }
+}
+
+impl Default for Nusb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BackendOp for Nusb {
+    fn init<'a>(&'a mut self) -> futures::future::BoxFuture<'a, Result<(), USBError>> {
+        let res = self.watch_devices();
+        async move { res }.boxed()
+    }
+
+    fn device_list<'a>(
+        &'a mut self,
+    ) -> futures::future::BoxFuture<'a, Result<Vec<ProbedDeviceInfoOp>, USBError>> {
+        self.device_list().boxed()
+    }
+
+    fn open_device<'a>(
+        &'a mut self,
+        dev: &'a dyn super::ty::DeviceInfoOp,
+    ) -> futures::future::BoxFuture<'a, Result<Box<dyn super::ty::DeviceOp>, USBError>> {
+        async move { self._open_device(dev).await }.boxed()
+    }
+
+    fn wait_hotplug<'a>(&'a mut self) -> futures::future::BoxFuture<'a, ()> {
+        self.wait_hotplug().boxed()
+    }
+
+    fn poll_hotplug<'a>(
+        &'a mut self,
+    ) -> futures::future::BoxFuture<'a, Result<Option<HotplugEventOp>, USBError>> {
+        let res = self.poll_hotplug();
+        async move { res }.boxed()
+    }
+}
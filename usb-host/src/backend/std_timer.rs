@@ -0,0 +1,133 @@
+//! 用户空间后端共享的定时线程
+//!
+//! libusb、nusb 与 VFIO 后端没有内核定时器，到期唤醒由一个后台线程按截止时间顺序完成。
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{Condvar, Mutex, OnceLock},
+    task::Waker,
+    time::{Duration, Instant},
+};
+
+struct Entry {
+    at: Instant,
+    waker: Waker,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.at == other.at
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    /// 截止时间最早的在堆顶
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.at.cmp(&self.at)
+    }
+}
+
+struct TimerThread {
+    queue: Mutex<BinaryHeap<Entry>>,
+    cond: Condvar,
+}
+
+impl TimerThread {
+    fn run(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            while queue.peek().is_some_and(|e| e.at <= now) {
+                queue.pop().unwrap().waker.wake();
+            }
+            queue = match queue.peek().map(|e| e.at - now) {
+                Some(timeout) => self.cond.wait_timeout(queue, timeout).unwrap().0,
+                None => self.cond.wait(queue).unwrap(),
+            };
+        }
+    }
+}
+
+fn thread() -> &'static TimerThread {
+    static TIMER: OnceLock<&'static TimerThread> = OnceLock::new();
+    TIMER.get_or_init(|| {
+        let timer: &'static TimerThread = Box::leak(Box::new(TimerThread {
+            queue: Mutex::new(BinaryHeap::new()),
+            cond: Condvar::new(),
+        }));
+        std::thread::Builder::new()
+            .name("crab-usb-timer".into())
+            .spawn(|| timer.run())
+            .expect("failed to spawn timer thread");
+        timer
+    })
+}
+
+fn start() -> Instant {
+    static START: OnceLock<Instant> = OnceLock::new();
+    *START.get_or_init(Instant::now)
+}
+
+/// 进程内的单调时钟，用户空间后端与 [`crate::VfioKernel`] 的时钟均以它为基准
+pub(crate) fn now() -> Duration {
+    start().elapsed()
+}
+
+/// [`now`] 到达 `deadline` 后唤醒 `waker`，已过期时立即唤醒
+pub(crate) fn wake_at(deadline: Duration, waker: &Waker) {
+    let at = start() + deadline;
+    if at <= Instant::now() {
+        waker.wake_by_ref();
+        return;
+    }
+    let timer = thread();
+    timer.queue.lock().unwrap().push(Entry {
+        at,
+        waker: waker.clone(),
+    });
+    timer.cond.notify_one();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    };
+    use std::task::Wake;
+
+    use super::*;
+
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Release);
+        }
+    }
+
+    #[test]
+    fn wakes_after_deadline() {
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let deadline = now() + Duration::from_millis(20);
+        wake_at(deadline, &waker);
+        assert!(!flag.0.load(Ordering::Acquire));
+
+        let give_up = Instant::now() + Duration::from_secs(5);
+        while !flag.0.load(Ordering::Acquire) && Instant::now() < give_up {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(flag.0.load(Ordering::Acquire));
+        assert!(now() >= deadline);
+    }
+}
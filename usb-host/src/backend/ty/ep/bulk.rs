@@ -0,0 +1,99 @@
+use usb_if::descriptor::EndpointType;
+use usb_if::endpoint::{RequestId, TransferCompletion, TransferRequest};
+use usb_if::err::TransferError;
+use usb_if::transfer::Direction;
+
+use super::Endpoint;
+
+impl Endpoint {
+    /// 读满 `buff`，必要时发起多次传输
+    ///
+    /// 短包只结束当前传输，不结束读取；设备发送的数据多于剩余长度时由控制器报告错误。
+    /// 收到零长度包说明设备已结束发送，返回错误而不是继续等待。
+    pub async fn read_exact(&mut self, buff: &mut [u8]) -> Result<(), TransferError> {
+        self.check_bulk_in()?;
+
+        let mut offset = 0;
+        while offset < buff.len() {
+            let t = self
+                .wait(TransferRequest::bulk_in(&mut buff[offset..]))
+                .await?;
+            if t.actual_length == 0 {
+                return Err(TransferError::Other(anyhow!(
+                    "short read: {offset} of {} bytes",
+                    buff.len()
+                )));
+            }
+            offset += t.actual_length;
+        }
+        Ok(())
+    }
+
+    /// 持续读取直到收到短包（包括零长度包）或 `buff` 已满，返回读取的总字节数
+    ///
+    /// 每次传输请求的长度都是最大包长的整数倍，因此只有设备主动结束时才会出现短包。
+    /// `buff` 剩余空间不足一个最大包时停止读取。
+    pub async fn read_until_short(&mut self, buff: &mut [u8]) -> Result<usize, TransferError> {
+        self.check_bulk_in()?;
+
+        let mps = (self.info.max_packet_size as usize).max(1);
+        let mut offset = 0;
+        loop {
+            let request_len = (buff.len() - offset) / mps * mps;
+            if request_len == 0 {
+                return Ok(offset);
+            }
+
+            let t = self
+                .wait(TransferRequest::bulk_in(
+                    &mut buff[offset..offset + request_len],
+                ))
+                .await?;
+            offset += t.actual_length;
+            if t.actual_length < request_len {
+                return Ok(offset);
+            }
+        }
+    }
+
+    /// 端点已分配的流数量，可用的流 ID 为 `1..=streams()`，0 表示未启用流
+    ///
+    /// SuperSpeed 批量端点声明支持流（如 UAS）且控制器支持时，选择接口设置时自动分配。
+    pub fn streams(&self) -> u16 {
+        self.raw.streams()
+    }
+
+    /// 在流 `stream_id` 上提交批量请求
+    ///
+    /// 启用了流的端点只能通过该方法提交，各流上的请求独立完成。
+    pub fn submit_stream(
+        &mut self,
+        stream_id: u16,
+        request: TransferRequest,
+    ) -> Result<RequestId, TransferError> {
+        if self.info.transfer_type != EndpointType::Bulk
+            || !matches!(request, TransferRequest::Bulk { .. })
+        {
+            return Err(TransferError::InvalidEndpoint);
+        }
+        self.raw.submit_stream_request(stream_id, request)
+    }
+
+    /// 在流 `stream_id` 上发起请求并等待完成
+    pub async fn wait_stream(
+        &mut self,
+        stream_id: u16,
+        request: TransferRequest,
+    ) -> Result<TransferCompletion, TransferError> {
+        let id = self.submit_stream(stream_id, request)?;
+        self.wait_request(id, self.timeout).await
+    }
+
+    fn check_bulk_in(&self) -> Result<(), TransferError> {
+        if self.info.transfer_type == EndpointType::Bulk && self.info.direction == Direction::In {
+            Ok(())
+        } else {
+            Err(TransferError::InvalidEndpoint)
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use alloc::vec::Vec;
+use core::{
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use usb_if::{
+    descriptor::EndpointType,
+    endpoint::{RequestId, TransferRequest},
+    err::TransferError,
+    transfer::Direction,
+};
+
+use super::{Endpoint, EndpointOp};
+use crate::backend::ty::timer::poll_until;
+
+/// 批量 OUT 小包合并参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoalesceConfig {
+    /// 缓冲数据达到该长度时立即提交
+    pub max_size: usize,
+    /// 数据在缓冲中停留的最长时间
+    pub max_delay: Duration,
+}
+
+impl Default for CoalesceConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 4096,
+            max_delay: Duration::from_millis(1),
+        }
+    }
+}
+
+/// 批量 OUT 小包合并统计
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CoalesceStats {
+    /// 调用 [`Endpoint::write`] 的次数
+    pub writes: u64,
+    /// 实际提交的传输数
+    pub transfers: u64,
+    /// 已提交的字节数
+    pub bytes: u64,
+}
+
+/// 批量 OUT 端点的写合并状态
+///
+/// 同一时刻最多一个传输在途，在途期间的写入都追加到缓冲中，从而保证顺序。
+pub(crate) struct Coalesce {
+    config: CoalesceConfig,
+    stats: CoalesceStats,
+    pending: Vec<u8>,
+    /// 缓冲中最早一次写入的时间
+    since: Duration,
+    /// 在途的传输，缓冲区在完成前必须保持有效
+    in_flight: Option<(RequestId, Vec<u8>)>,
+}
+
+impl Coalesce {
+    fn new(config: CoalesceConfig) -> Self {
+        Self {
+            config,
+            stats: CoalesceStats::default(),
+            pending: Vec::new(),
+            since: Duration::ZERO,
+            in_flight: None,
+        }
+    }
+
+    /// 追加数据，返回是否应当立即提交
+    fn push(&mut self, data: &[u8], now: Duration) -> bool {
+        if self.pending.is_empty() {
+            self.since = now;
+        }
+        self.pending.extend_from_slice(data);
+        self.stats.writes += 1;
+        self.pending.len() >= self.config.max_size || (self.in_flight.is_none() && self.is_due(now))
+    }
+
+    /// 缓冲数据到期提交的时刻
+    fn due_at(&self) -> Duration {
+        self.since.saturating_add(self.config.max_delay)
+    }
+
+    fn is_due(&self, now: Duration) -> bool {
+        !self.pending.is_empty() && now.saturating_sub(self.since) >= self.config.max_delay
+    }
+
+    fn is_idle(&self) -> bool {
+        self.pending.is_empty() && self.in_flight.is_none()
+    }
+
+    /// 回收已完成的在途传输，传输失败时返回其错误
+    fn reap(&mut self, raw: &mut dyn EndpointOp) -> Result<(), TransferError> {
+        let Some((id, _)) = self.in_flight.as_ref() else {
+            return Ok(());
+        };
+        match raw.reclaim_request(*id) {
+            Some(res) => {
+                self.in_flight = None;
+                res.map(|_| ())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn poll_in_flight(
+        &mut self,
+        raw: &mut dyn EndpointOp,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), TransferError>> {
+        let Some((id, _)) = self.in_flight.as_ref() else {
+            return Poll::Ready(Ok(()));
+        };
+        raw.register_waker(*id, cx);
+        let res = self.reap(raw);
+        if res.is_ok() && self.in_flight.is_some() {
+            return Poll::Pending;
+        }
+        Poll::Ready(res)
+    }
+
+    /// 在途传输被取消后释放其缓冲区
+    pub(crate) fn on_cancel(&mut self, id: RequestId) {
+        if self
+            .in_flight
+            .as_ref()
+            .is_some_and(|(in_flight, _)| *in_flight == id)
+        {
+            self.in_flight = None;
+        }
+    }
+
+    /// 丢弃尚未提交的数据
+    pub(crate) fn discard(&mut self) {
+        self.pending.clear();
+    }
+
+    /// 把缓冲中的数据作为一个传输提交，调用前在途传输必须已完成
+    fn submit(&mut self, raw: &mut dyn EndpointOp) -> Result<(), TransferError> {
+        debug_assert!(self.in_flight.is_none());
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let buf = core::mem::take(&mut self.pending);
+        let id = raw.submit_request(TransferRequest::bulk_out(&buf))?;
+        self.stats.transfers += 1;
+        self.stats.bytes += buf.len() as u64;
+        self.in_flight = Some((id, buf));
+        Ok(())
+    }
+}
+
+impl Endpoint {
+    /// 设置批量 OUT 端点的写合并，传入 `None` 关闭
+    ///
+    /// 开启后通过 [`Endpoint::write`] 写入的小块数据会先进入缓冲，缓冲达到
+    /// `max_size`、停留超过 `max_delay` 或调用 [`Endpoint::flush`] 时才作为一个传输提交。
+    /// 同一时刻最多一个传输在途，在途期间的写入继续合并，数据顺序与写入顺序一致。
+    /// 开启期间不要再通过 [`Endpoint::submit`] 提交 OUT 请求。
+    ///
+    /// 关闭前必须先 [`Endpoint::flush`]，否则返回错误。
+    pub fn set_write_coalescing(
+        &mut self,
+        config: Option<CoalesceConfig>,
+    ) -> Result<(), TransferError> {
+        if self.info.transfer_type != EndpointType::Bulk || self.info.direction != Direction::Out {
+            return Err(TransferError::InvalidEndpoint);
+        }
+        match (self.coalesce.as_mut(), config) {
+            (Some(c), Some(config)) => c.config = config,
+            (None, Some(config)) => self.coalesce = Some(Coalesce::new(config)),
+            (Some(c), None) if !c.is_idle() => {
+                return Err(TransferError::Other(anyhow!(
+                    "flush before disabling write coalescing"
+                )));
+            }
+            (_, None) => self.coalesce = None,
+        }
+        Ok(())
+    }
+
+    /// 写合并统计，未开启时返回 `None`
+    pub fn write_coalesce_stats(&self) -> Option<CoalesceStats> {
+        self.coalesce.as_ref().map(|c| c.stats)
+    }
+
+    /// 写入数据，需先通过 [`Endpoint::set_write_coalescing`] 开启写合并
+    ///
+    /// 数据可能仍在缓冲中，返回不代表设备已收到。之前在途传输的错误在这里返回，
+    /// 出错的传输中的数据不会重发。
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), TransferError> {
+        let now = self.raw.now();
+        let c = self
+            .coalesce
+            .as_mut()
+            .ok_or(TransferError::InvalidEndpoint)?;
+        c.reap(self.raw.as_mut())?;
+        if c.push(data, now) {
+            self.submit_coalesced().await?;
+        }
+        Ok(())
+    }
+
+    /// 提交缓冲中的全部数据并等待传输完成
+    pub async fn flush(&mut self) -> Result<(), TransferError> {
+        self.submit_coalesced().await?;
+        self.wait_coalesced().await
+    }
+
+    /// 等到缓冲数据超过 `max_delay` 后提交，缓冲为空时立即返回
+    ///
+    /// 写入停止后缓冲中剩余的数据不会自动发出，可以与数据源一起 `select` 该方法。
+    pub async fn flush_due(&mut self) -> Result<(), TransferError> {
+        core::future::poll_fn(|cx| {
+            let Some(c) = self.coalesce.as_ref() else {
+                return Poll::Ready(Err(TransferError::InvalidEndpoint));
+            };
+            if c.pending.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            poll_until(self.raw.as_ref(), c.due_at(), cx).map(Ok)
+        })
+        .await?;
+        self.submit_coalesced().await
+    }
+
+    async fn wait_coalesced(&mut self) -> Result<(), TransferError> {
+        core::future::poll_fn(|cx| match self.coalesce.as_mut() {
+            Some(c) => c.poll_in_flight(self.raw.as_mut(), cx),
+            None => Poll::Ready(Err(TransferError::InvalidEndpoint)),
+        })
+        .await
+    }
+
+    async fn submit_coalesced(&mut self) -> Result<(), TransferError> {
+        self.wait_coalesced().await?;
+        match self.coalesce.as_mut() {
+            Some(c) => c.submit(self.raw.as_mut()),
+            None => Err(TransferError::InvalidEndpoint),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn coalesce() -> Coalesce {
+        Coalesce::new(CoalesceConfig {
+            max_size: 64,
+            max_delay: 2 * MS,
+        })
+    }
+
+    #[test]
+    fn flush_on_size() {
+        let mut c = coalesce();
+        c.in_flight = Some((RequestId::new(1), Vec::new()));
+        assert!(!c.push(&[0; 40], Duration::ZERO));
+        assert!(c.push(&[0; 24], Duration::ZERO));
+        assert_eq!(c.stats.writes, 2);
+    }
+
+    #[test]
+    fn flush_on_delay_only_when_idle() {
+        let mut c = coalesce();
+        c.in_flight = Some((RequestId::new(1), Vec::new()));
+        assert!(!c.push(&[1], Duration::ZERO));
+        // 在途期间即使超时也继续合并
+        assert!(!c.push(&[2], 3 * MS));
+        assert!(c.is_due(3 * MS));
+
+        c.in_flight = None;
+        assert!(c.push(&[3], 3 * MS));
+        assert_eq!(c.pending, [1, 2, 3]);
+    }
+
+    #[test]
+    fn delay_starts_at_first_buffered_write() {
+        let mut c = coalesce();
+        c.in_flight = Some((RequestId::new(1), Vec::new()));
+        c.push(&[1], 10 * MS);
+        assert!(!c.is_due(11 * MS));
+        assert!(c.is_due(12 * MS));
+        assert_eq!(c.due_at(), 12 * MS);
+    }
+}
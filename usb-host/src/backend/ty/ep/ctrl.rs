@@ -1,4 +1,4 @@
-use usb_if::descriptor::{ConfigurationDescriptor, DescriptorType, DeviceDescriptor};
+use usb_if::descriptor::{ConfigurationDescriptor, Descriptor, DescriptorType, DeviceDescriptor};
 use usb_if::endpoint::TransferRequest;
 use usb_if::err::{TransferError, USBError};
 use usb_if::host::ControlSetup;
@@ -7,22 +7,103 @@ use usb_if::transfer::{Recipient, Request, RequestType};
 use super::Endpoint;
 
 impl Endpoint {
+    /// 控制 IN 传输，STALL 时区分数据阶段（[`TransferError::Stall`]）与状态阶段
+    /// （[`TransferError::StatusStall`]），返回前已恢复端点 0
     pub async fn control_in(
         &mut self,
         param: usb_if::host::ControlSetup,
         buff: &mut [u8],
     ) -> Result<usize, TransferError> {
-        let t = self.wait(TransferRequest::control_in(param, buff)).await?;
-        Ok(t.actual_length)
+        let res = self.wait(TransferRequest::control_in(param, buff)).await;
+        Ok(self.recover_protocol_stall(res).await?.actual_length)
     }
 
+    /// 控制 OUT 传输，错误处理同 [`Endpoint::control_in`]
     pub async fn control_out(
         &mut self,
         param: usb_if::host::ControlSetup,
         buff: &[u8],
     ) -> Result<usize, TransferError> {
-        let t = self.wait(TransferRequest::control_out(param, buff)).await?;
-        Ok(t.actual_length)
+        let res = self.wait(TransferRequest::control_out(param, buff)).await;
+        Ok(self.recover_protocol_stall(res).await?.actual_length)
+    }
+
+    /// 控制端点的协议 STALL 由设备在下一个 SETUP 时清除，主机侧端点却停在 Halted，
+    /// 不复位的话之后的控制传输都无法执行
+    async fn recover_protocol_stall<T>(
+        &mut self,
+        res: Result<T, TransferError>,
+    ) -> Result<T, TransferError> {
+        if let Err(e) = &res
+            && e.is_stall()
+            && let Err(reset) = self.raw.reset_halt().await
+        {
+            warn!("recover control endpoint after {e}: {reset:?}");
+        }
+        res
+    }
+
+    /// GET_STATUS，`index` 为接口号或端点地址，接收者为设备时取 0
+    pub async fn get_status(
+        &mut self,
+        recipient: Recipient,
+        index: u16,
+    ) -> Result<u16, TransferError> {
+        let mut buff = [0u8; 2];
+        self.control_in(
+            ControlSetup {
+                request_type: RequestType::Standard,
+                recipient,
+                request: Request::GetStatus,
+                value: 0,
+                index,
+            },
+            &mut buff,
+        )
+        .await?;
+        Ok(u16::from_le_bytes(buff))
+    }
+
+    /// SET_FEATURE，`feature` 为特性选择子，如设备的 DEVICE_REMOTE_WAKEUP(1)、端点的 ENDPOINT_HALT(0)
+    pub async fn set_feature(
+        &mut self,
+        recipient: Recipient,
+        feature: u16,
+        index: u16,
+    ) -> Result<(), TransferError> {
+        self.control_out(
+            ControlSetup {
+                request_type: RequestType::Standard,
+                recipient,
+                request: Request::SetFeature,
+                value: feature,
+                index,
+            },
+            &[],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// CLEAR_FEATURE，参数同 [`Endpoint::set_feature`]
+    pub async fn clear_feature(
+        &mut self,
+        recipient: Recipient,
+        feature: u16,
+        index: u16,
+    ) -> Result<(), TransferError> {
+        self.control_out(
+            ControlSetup {
+                request_type: RequestType::Standard,
+                recipient,
+                request: Request::ClearFeature,
+                value: feature,
+                index,
+            },
+            &[],
+        )
+        .await?;
+        Ok(())
     }
 
     pub async fn set_configuration(
@@ -51,27 +132,55 @@ impl Endpoint {
         buff: &mut [u8],
     ) -> Result<(), TransferError> {
         self.control_in(
-            ControlSetup {
-                request_type: RequestType::Standard,
-                recipient: Recipient::Device,
-                request: Request::GetDescriptor,
-                value: ((desc_type.0 as u16) << 8) | desc_index as u16,
-                index: language_id,
-            },
+            get_descriptor_setup(desc_type, desc_index, language_id),
             buff,
         )
         .await?;
         Ok(())
     }
 
-    pub async fn get_device_descriptor(&mut self) -> Result<DeviceDescriptor, USBError> {
-        let mut buff = alloc::vec![0u8; DeviceDescriptor::LEN];
-        self.get_descriptor(DescriptorType::DEVICE, 0, 0, &mut buff)
+    /// 读取描述符：先读头部得到完整长度，头部不足以容纳完整描述符时再读取一次
+    pub async fn read_descriptor<T: Descriptor>(
+        &mut self,
+        index: u8,
+        language_id: u16,
+    ) -> Result<T, USBError> {
+        let mut header = alloc::vec![0u8; T::HEADER_LEN];
+        let n = self
+            .control_in(
+                get_descriptor_setup(T::DESCRIPTOR_TYPE, index, language_id),
+                &mut header,
+            )
             .await?;
-        trace!("data: {buff:?}");
-        let desc = DeviceDescriptor::parse(&buff).ok_or(anyhow!("device descriptor parse err"))?;
 
-        Ok(desc)
+        let total_length = T::total_length(&header);
+        if total_length < 2 {
+            Err(anyhow!(
+                "descriptor {:#04x} reports invalid length {total_length}",
+                T::DESCRIPTOR_TYPE.0
+            ))?;
+        }
+        trace!(
+            "Reading descriptor {:#04x} index {index}, total length: {total_length}",
+            T::DESCRIPTOR_TYPE.0
+        );
+
+        let full_data = if total_length <= n {
+            header.truncate(total_length);
+            header
+        } else {
+            let mut full_data = alloc::vec![0u8; total_length];
+            self.get_descriptor(T::DESCRIPTOR_TYPE, index, language_id, &mut full_data)
+                .await?;
+            full_data
+        };
+
+        T::parse(&full_data)
+            .ok_or_else(|| anyhow!("descriptor {:#04x} parse err", T::DESCRIPTOR_TYPE.0).into())
+    }
+
+    pub async fn get_device_descriptor(&mut self) -> Result<DeviceDescriptor, USBError> {
+        self.read_descriptor(0, 0).await
     }
 
     pub async fn get_configuration(&mut self) -> Result<u8, TransferError> {
@@ -94,17 +203,20 @@ impl Endpoint {
         &mut self,
         index: u8,
     ) -> Result<ConfigurationDescriptor, USBError> {
-        let mut header = alloc::vec![0u8; ConfigurationDescriptor::LEN];
-        self.get_descriptor(DescriptorType::CONFIGURATION, index, 0, &mut header)
-            .await?;
-
-        let total_length = u16::from_le_bytes(header[2..4].try_into().unwrap()) as usize;
-        let mut full_data = alloc::vec![0u8; total_length];
-        debug!("Reading configuration descriptor for index {index}, total length: {total_length}");
-        self.get_descriptor(DescriptorType::CONFIGURATION, index, 0, &mut full_data)
-            .await?;
+        self.read_descriptor(index, 0).await
+    }
+}
 
-        ConfigurationDescriptor::parse(&full_data)
-            .ok_or_else(|| anyhow!("config descriptor parse err").into())
+fn get_descriptor_setup(
+    desc_type: DescriptorType,
+    desc_index: u8,
+    language_id: u16,
+) -> ControlSetup {
+    ControlSetup {
+        request_type: RequestType::Standard,
+        recipient: Recipient::Device,
+        request: Request::GetDescriptor,
+        value: ((desc_type.0 as u16) << 8) | desc_index as u16,
+        index: language_id,
     }
 }
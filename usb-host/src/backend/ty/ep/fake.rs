@@ -0,0 +1,88 @@
+//! 端点单元测试共用的假端点
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{
+    task::{Context, Waker},
+    time::Duration,
+};
+
+use usb_if::{
+    endpoint::{IsoPacketResult, RequestId, TransferCompletion, TransferRequest, TransferStatus},
+    err::TransferError,
+};
+
+use super::EndpointOp;
+use crate::backend::ty::timer::Timer;
+
+/// 按提交顺序立即完成请求
+///
+/// 非等时请求传输 `actual_length` 字节；等时请求每包填满一半。
+#[derive(Default)]
+pub(crate) struct Completing {
+    next: u64,
+    actual_length: usize,
+    submitted: BTreeMap<RequestId, Vec<usize>>,
+}
+
+impl Completing {
+    pub fn new(actual_length: usize) -> Self {
+        Self {
+            actual_length,
+            ..Default::default()
+        }
+    }
+}
+
+impl Timer for Completing {
+    fn now(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn wake_at(&self, _: Duration, waker: &Waker) {
+        waker.wake_by_ref();
+    }
+}
+
+impl EndpointOp for Completing {
+    fn submit_request(&mut self, request: TransferRequest) -> Result<RequestId, TransferError> {
+        self.next += 1;
+        let id = RequestId::new(self.next);
+        let lengths = request.iso_packets().iter().map(|p| p.length).collect();
+        self.submitted.insert(id, lengths);
+        Ok(id)
+    }
+
+    fn reclaim_request(
+        &mut self,
+        id: RequestId,
+    ) -> Option<Result<TransferCompletion, TransferError>> {
+        let lengths = self.submitted.remove(&id)?;
+        if lengths.is_empty() {
+            return Some(Ok(TransferCompletion {
+                request_id: id,
+                status: TransferStatus::Completed,
+                actual_length: self.actual_length,
+                iso_packets: Vec::new(),
+            }));
+        }
+        Some(Ok(TransferCompletion {
+            request_id: id,
+            status: TransferStatus::Completed,
+            actual_length: lengths.iter().map(|l| l / 2).sum(),
+            iso_packets: lengths
+                .iter()
+                .map(|&l| IsoPacketResult {
+                    requested_length: l,
+                    actual_length: l / 2,
+                    status: TransferStatus::Completed,
+                })
+                .collect(),
+        }))
+    }
+
+    fn register_waker(&self, _: RequestId, _: &mut Context<'_>) {}
+
+    fn pending_requests(&self) -> Vec<RequestId> {
+        self.submitted.keys().copied().collect()
+    }
+}
@@ -0,0 +1,350 @@
+use alloc::{boxed::Box, collections::BTreeSet, vec, vec::Vec};
+
+use usb_if::{
+    endpoint::{RequestId, TransferCompletion, TransferRequest, TransferStatus},
+    err::TransferError,
+};
+
+use super::{Endpoint, EndpointOp};
+
+/// 等时 OUT 端点的欠载统计
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IsoOutStats {
+    /// 已完成的服务周期（包）数，包括填充包
+    pub intervals: u64,
+    /// 控制器报告未能按时传输的包数
+    pub missed: u64,
+    /// 请求完成时调用方没有排队后续请求的次数
+    pub underruns: u64,
+    /// 由填充回调补上的包数
+    pub filled: u64,
+}
+
+/// 填充回调，参数为一个包的缓冲区（已清零），可写入静音或黑帧数据
+pub type IsoFiller = Box<dyn FnMut(&mut [u8]) + Send>;
+
+/// 等时 OUT 端点的欠载检测状态
+#[derive(Default)]
+pub(crate) struct IsoOut {
+    stats: IsoOutStats,
+    filler: Option<IsoFiller>,
+    /// 调用方提交、尚未回收的请求
+    pending: BTreeSet<RequestId>,
+    /// 已提交的填充请求，缓冲区在完成前必须保持有效
+    fillers: Vec<(RequestId, Vec<u8>)>,
+}
+
+impl IsoOut {
+    fn account(&mut self, completion: &TransferCompletion) {
+        self.stats.intervals += completion.iso_packets.len() as u64;
+        self.stats.missed += completion
+            .iso_packets
+            .iter()
+            .filter(|packet| packet.status != TransferStatus::Completed)
+            .count() as u64;
+    }
+
+    /// 调用方请求完成后记账，队列已空时插入一个填充请求
+    pub(crate) fn on_complete(
+        &mut self,
+        raw: &mut dyn EndpointOp,
+        id: RequestId,
+        res: &Result<TransferCompletion, TransferError>,
+    ) {
+        if !self.pending.remove(&id) {
+            return;
+        }
+        let Ok(completion) = res else {
+            return;
+        };
+        self.account(completion);
+        if self.pending.is_empty() {
+            self.stats.underruns += 1;
+            trace!("ISO OUT underrun after request {id:?}");
+            let lengths: Vec<usize> = completion
+                .iso_packets
+                .iter()
+                .map(|packet| packet.requested_length)
+                .collect();
+            self.fill(raw, &lengths);
+        }
+    }
+
+    pub(crate) fn on_submit(&mut self, id: RequestId) {
+        self.pending.insert(id);
+    }
+
+    /// 被取消的请求不计入统计，也不触发填充；被取消的填充请求随即释放缓冲区
+    pub(crate) fn on_cancel(&mut self, id: RequestId) {
+        self.pending.remove(&id);
+        self.fillers.retain(|(filler, _)| *filler != id);
+    }
+
+    /// 回收已完成的填充请求，调用方仍未提交数据时继续填充
+    pub(crate) fn reap(&mut self, raw: &mut dyn EndpointOp) {
+        let mut refill = None;
+        let mut i = 0;
+        while i < self.fillers.len() {
+            let id = self.fillers[i].0;
+            match raw.reclaim_request(id) {
+                Some(res) => {
+                    // 传输已结束，可以释放缓冲区
+                    self.fillers.swap_remove(i);
+                    if let Ok(completion) = res {
+                        self.account(&completion);
+                        let lengths = completion
+                            .iso_packets
+                            .iter()
+                            .map(|packet| packet.requested_length)
+                            .collect::<Vec<_>>();
+                        refill = Some(lengths);
+                    }
+                }
+                None => i += 1,
+            }
+        }
+        if let Some(lengths) = refill
+            && self.pending.is_empty()
+            && self.fillers.is_empty()
+        {
+            self.fill(raw, &lengths);
+        }
+    }
+
+    fn fill(&mut self, raw: &mut dyn EndpointOp, lengths: &[usize]) {
+        let Some(filler) = self.filler.as_mut() else {
+            return;
+        };
+        if lengths.is_empty() {
+            return;
+        }
+
+        let mut buf = vec![0u8; lengths.iter().sum()];
+        let mut offset = 0;
+        for &len in lengths {
+            filler(&mut buf[offset..offset + len]);
+            offset += len;
+        }
+        match raw.submit_request(TransferRequest::iso_out(&buf, lengths)) {
+            Ok(id) => {
+                self.stats.filled += lengths.len() as u64;
+                self.fillers.push((id, buf));
+            }
+            Err(e) => debug!("ISO OUT filler submit failed: {e:?}"),
+        }
+    }
+}
+
+impl Endpoint {
+    /// 等时 OUT 端点的欠载统计，其他端点返回 `None`
+    pub fn iso_out_stats(&self) -> Option<IsoOutStats> {
+        self.iso_out.as_ref().map(|iso| iso.stats)
+    }
+
+    /// 设置等时 OUT 欠载时的填充回调，传入 `None` 关闭填充
+    ///
+    /// 调用方的请求完成时若队列中已没有后续请求，端点会按刚完成请求的包布局提交一个
+    /// 填充请求，回调逐包写入填充数据（音频静音、视频黑帧等），避免设备侧出现断流。
+    /// 填充请求在调用方下一次提交或回收时被回收；调用方仍未提交数据时继续填充。
+    /// 为避免填充数据插入正常数据流，调用方应保持至少两个请求在队列中。
+    pub fn set_iso_filler(&mut self, filler: Option<IsoFiller>) -> Result<(), TransferError> {
+        let iso = self
+            .iso_out
+            .as_mut()
+            .ok_or(TransferError::InvalidEndpoint)?;
+        iso.filler = filler;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{collections::BTreeSet, sync::Arc};
+    use core::{
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Waker},
+        time::Duration,
+    };
+
+    use usb_if::{
+        descriptor::EndpointType,
+        endpoint::{EndpointAddress, EndpointInfo, IsoPacketResult},
+    };
+
+    use super::super::fake::Completing;
+    use super::*;
+    use crate::backend::ty::timer::Timer;
+
+    const PACKETS: [usize; 4] = [100; 4];
+
+    /// 请求在测试调用 [`Held::release`] 之后才完成，模拟仍在控制器上排队的传输
+    #[derive(Default)]
+    struct Held {
+        inner: Completing,
+        released: BTreeSet<RequestId>,
+    }
+
+    impl Held {
+        fn release(&mut self, id: RequestId) {
+            self.released.insert(id);
+        }
+    }
+
+    impl Timer for Held {
+        fn now(&self) -> Duration {
+            self.inner.now()
+        }
+
+        fn wake_at(&self, deadline: Duration, waker: &Waker) {
+            self.inner.wake_at(deadline, waker)
+        }
+    }
+
+    impl EndpointOp for Held {
+        fn submit_request(&mut self, request: TransferRequest) -> Result<RequestId, TransferError> {
+            self.inner.submit_request(request)
+        }
+
+        fn reclaim_request(
+            &mut self,
+            id: RequestId,
+        ) -> Option<Result<TransferCompletion, TransferError>> {
+            if !self.released.remove(&id) {
+                return None;
+            }
+            self.inner.reclaim_request(id)
+        }
+
+        fn register_waker(&self, id: RequestId, cx: &mut Context<'_>) {
+            self.inner.register_waker(id, cx)
+        }
+
+        fn pending_requests(&self) -> Vec<RequestId> {
+            self.inner.pending_requests()
+        }
+    }
+
+    fn endpoint(address: u8) -> Endpoint {
+        let address = EndpointAddress::new(address);
+        Endpoint::new(
+            EndpointInfo {
+                address,
+                transfer_type: EndpointType::Isochronous,
+                direction: address.direction(),
+                max_packet_size: 1024,
+                packets_per_microframe: 1,
+                interval: 1,
+            },
+            Held::default(),
+        )
+    }
+
+    fn submit(ep: &mut Endpoint) -> RequestId {
+        ep.submit(TransferRequest::iso_out(&[0x55; 400], &PACKETS))
+            .unwrap()
+    }
+
+    fn release(ep: &mut Endpoint, id: RequestId) {
+        ep.with_raw_mut(|raw: &mut Held| raw.release(id));
+    }
+
+    /// 设置逐包检查缓冲区的填充回调，返回回调次数
+    fn counting_filler(ep: &mut Endpoint) -> Arc<AtomicUsize> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        ep.set_iso_filler(Some(Box::new(move |packet: &mut [u8]| {
+            assert!(packet.iter().all(|&b| b == 0));
+            assert_eq!(packet.len(), 100);
+            counter.fetch_add(1, Ordering::Relaxed);
+        })))
+        .unwrap();
+        calls
+    }
+
+    #[test]
+    fn queued_requests_do_not_underrun() {
+        let mut ep = endpoint(0x01);
+        let calls = counting_filler(&mut ep);
+        let first = submit(&mut ep);
+        submit(&mut ep);
+
+        release(&mut ep, first);
+        assert!(ep.reclaim(first).unwrap().is_some());
+        let stats = ep.iso_out_stats().unwrap();
+        assert_eq!(stats.intervals, 4);
+        assert_eq!(stats.underruns, 0);
+        assert_eq!(stats.filled, 0);
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn underrun_submits_filler_until_caller_resumes() {
+        let mut ep = endpoint(0x01);
+        let calls = counting_filler(&mut ep);
+        let id = submit(&mut ep);
+
+        // 队列已空，按刚完成请求的包布局插入一个填充请求
+        release(&mut ep, id);
+        assert!(ep.reclaim(id).unwrap().is_some());
+        let stats = ep.iso_out_stats().unwrap();
+        assert_eq!(stats.underruns, 1);
+        assert_eq!(stats.filled, 4);
+        assert_eq!(calls.load(Ordering::Relaxed), 4);
+        let fillers = ep.raw.pending_requests();
+        assert_eq!(fillers.len(), 1);
+
+        // 调用方恢复提交后只回收填充请求，不再在新数据前补充
+        release(&mut ep, fillers[0]);
+        let id = submit(&mut ep);
+        assert_eq!(ep.raw.pending_requests(), [id]);
+        let stats = ep.iso_out_stats().unwrap();
+        assert_eq!(stats.intervals, 8);
+        assert_eq!(stats.filled, 4);
+        assert_eq!(calls.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn underrun_without_filler_is_only_counted() {
+        let mut ep = endpoint(0x01);
+        let id = submit(&mut ep);
+        release(&mut ep, id);
+        assert!(ep.reclaim(id).unwrap().is_some());
+
+        let stats = ep.iso_out_stats().unwrap();
+        assert_eq!(stats.underruns, 1);
+        assert_eq!(stats.filled, 0);
+        assert!(ep.raw.pending_requests().is_empty());
+    }
+
+    #[test]
+    fn missed_packets_are_counted() {
+        let mut iso = IsoOut::default();
+        let packet = |status| IsoPacketResult {
+            requested_length: 100,
+            actual_length: 0,
+            status,
+        };
+        iso.account(&TransferCompletion {
+            request_id: RequestId::new(1),
+            status: TransferStatus::Completed,
+            actual_length: 0,
+            iso_packets: vec![
+                packet(TransferStatus::Completed),
+                packet(TransferStatus::Missed),
+                packet(TransferStatus::Missed),
+            ],
+        });
+        assert_eq!(iso.stats.intervals, 3);
+        assert_eq!(iso.stats.missed, 2);
+    }
+
+    #[test]
+    fn iso_in_has_no_underrun_tracking() {
+        let mut ep = endpoint(0x81);
+        assert!(ep.iso_out_stats().is_none());
+        assert!(matches!(
+            ep.set_iso_filler(None),
+            Err(TransferError::InvalidEndpoint)
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::task::Poll;
+
+use usb_if::{
+    descriptor::EndpointType,
+    endpoint::{IsoPacketResult, RequestId, TransferCompletion, TransferRequest},
+    err::TransferError,
+    transfer::Direction,
+};
+
+use super::{Endpoint, EndpointOp};
+
+/// 等时 IN 队列中的一个缓冲区
+///
+/// 由 [`Endpoint::next_iso_in`] 取出，处理完后通过 [`Endpoint::requeue_iso_in`] 交还队列。
+#[derive(Debug)]
+pub struct IsoBuffer {
+    data: Vec<u8>,
+    packet_lengths: Vec<usize>,
+    completion: Option<TransferCompletion>,
+}
+
+impl IsoBuffer {
+    fn new(packets: usize, packet_size: usize) -> Self {
+        Self {
+            data: vec![0; packets * packet_size],
+            packet_lengths: vec![packet_size; packets],
+            completion: None,
+        }
+    }
+
+    /// 最近一次传输的完成结果，尚未完成过时为 `None`
+    pub fn completion(&self) -> Option<&TransferCompletion> {
+        self.completion.as_ref()
+    }
+
+    /// 逐包的结果与数据，见 [`TransferCompletion::iso_packet_data`]
+    pub fn packets(&self) -> impl Iterator<Item = (&IsoPacketResult, &[u8])> {
+        self.completion
+            .iter()
+            .flat_map(|c| c.iso_packet_data(&self.data))
+    }
+
+    /// 整个缓冲区，各包位于按请求长度划分的偏移处
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn packet_count(&self) -> usize {
+        self.packet_lengths.len()
+    }
+}
+
+/// 等时 IN 请求环
+///
+/// 保持多个请求同时在途，控制器在调用方处理上一个缓冲区时继续接收数据。
+#[derive(Default)]
+pub(crate) struct IsoInQueue {
+    /// 按提交顺序排列的在途请求，缓冲区在完成前必须保持有效
+    in_flight: VecDeque<(RequestId, IsoBuffer)>,
+}
+
+impl IsoInQueue {
+    fn submit(
+        &mut self,
+        raw: &mut dyn EndpointOp,
+        mut buf: IsoBuffer,
+    ) -> Result<(), TransferError> {
+        buf.completion = None;
+        let id = raw.submit_request(TransferRequest::iso_in(&mut buf.data, &buf.packet_lengths))?;
+        // 缓冲区数据位于堆上，移动 `IsoBuffer` 不改变其地址
+        self.in_flight.push_back((id, buf));
+        Ok(())
+    }
+
+    /// 回收最早提交的请求，请求整体失败时缓冲区随错误一起丢弃
+    fn poll_front(
+        &mut self,
+        raw: &mut dyn EndpointOp,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<IsoBuffer, TransferError>> {
+        let Some((id, _)) = self.in_flight.front() else {
+            return Poll::Ready(Err(TransferError::Other(anyhow!("iso in queue is empty"))));
+        };
+        let id = *id;
+        let res = match raw.reclaim_request(id) {
+            Some(res) => res,
+            None => {
+                raw.register_waker(id, cx);
+                match raw.reclaim_request(id) {
+                    Some(res) => res,
+                    None => return Poll::Pending,
+                }
+            }
+        };
+        let (_, mut buf) = self.in_flight.pop_front().unwrap();
+        Poll::Ready(res.map(|completion| {
+            buf.completion = Some(completion);
+            buf
+        }))
+    }
+
+    pub(crate) fn on_cancel(&mut self, id: RequestId) {
+        self.in_flight.retain(|(in_flight, _)| *in_flight != id);
+    }
+}
+
+impl Endpoint {
+    /// 在等时 IN 端点上建立 `buffers` 个请求的环，每个请求 `packets_per_buffer` 个包
+    ///
+    /// 包长为 `wMaxPacketSize * 每微帧包数`。全部请求立即提交，之后用
+    /// [`Endpoint::next_iso_in`] 按提交顺序取出完成的缓冲区，处理后用
+    /// [`Endpoint::requeue_iso_in`] 重新提交。环中的请求由端点持有，端点释放前缓冲区一直有效。
+    pub fn submit_iso_in_queue(
+        &mut self,
+        buffers: usize,
+        packets_per_buffer: usize,
+    ) -> Result<(), TransferError> {
+        if self.info.transfer_type != EndpointType::Isochronous
+            || self.info.direction != Direction::In
+            || buffers == 0
+            || packets_per_buffer == 0
+        {
+            return Err(TransferError::InvalidEndpoint);
+        }
+        let packet_size = self.iso_packet_size();
+        let queue = self.iso_in.get_or_insert_with(IsoInQueue::default);
+        for _ in 0..buffers {
+            queue.submit(
+                self.raw.as_mut(),
+                IsoBuffer::new(packets_per_buffer, packet_size),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 等待环中最早提交的请求完成并取出其缓冲区
+    ///
+    /// 逐包的长度与状态见 [`IsoBuffer::packets`]。请求整体失败时返回错误，缓冲区不再回到环中。
+    pub async fn next_iso_in(&mut self) -> Result<IsoBuffer, TransferError> {
+        core::future::poll_fn(|cx| self.poll_next_iso_in(cx)).await
+    }
+
+    /// [`Endpoint::next_iso_in`] 的轮询形式，供手写 `Future`/`Stream` 使用
+    pub fn poll_next_iso_in(
+        &mut self,
+        cx: &mut core::task::Context<'_>,
+    ) -> Poll<Result<IsoBuffer, TransferError>> {
+        match self.iso_in.as_mut() {
+            Some(queue) => queue.poll_front(self.raw.as_mut(), cx),
+            None => Poll::Ready(Err(TransferError::InvalidEndpoint)),
+        }
+    }
+
+    /// 把缓冲区重新提交到环尾
+    pub fn requeue_iso_in(&mut self, buf: IsoBuffer) -> Result<(), TransferError> {
+        let queue = self.iso_in.as_mut().ok_or(TransferError::InvalidEndpoint)?;
+        queue.submit(self.raw.as_mut(), buf)
+    }
+
+    /// 环中在途的请求数
+    pub fn iso_in_queued(&self) -> usize {
+        self.iso_in.as_ref().map_or(0, |q| q.in_flight.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use usb_if::endpoint::{EndpointAddress, EndpointInfo};
+
+    use super::super::fake::Completing;
+    use super::*;
+
+    fn endpoint(address: u8) -> Endpoint {
+        let address = EndpointAddress::new(address);
+        Endpoint::new(
+            EndpointInfo {
+                address,
+                transfer_type: EndpointType::Isochronous,
+                direction: address.direction(),
+                max_packet_size: 1024,
+                packets_per_microframe: 3,
+                interval: 1,
+            },
+            Completing::default(),
+        )
+    }
+
+    #[test]
+    fn ring_completes_in_order_and_requeues() {
+        let mut ep = endpoint(0x81);
+        ep.submit_iso_in_queue(3, 8).unwrap();
+        assert_eq!(ep.iso_in_queued(), 3);
+
+        let buf = ep.next_iso_in().now_or_never().unwrap().unwrap();
+        assert_eq!(buf.completion().unwrap().request_id, RequestId::new(1));
+        assert_eq!(buf.packet_count(), 8);
+        assert_eq!(buf.as_slice().len(), 8 * 3072);
+        assert!(buf.packets().all(|(p, d)| p.is_ok() && d.len() == 1536));
+        assert_eq!(ep.iso_in_queued(), 2);
+
+        ep.requeue_iso_in(buf).unwrap();
+        assert_eq!(ep.iso_in_queued(), 3);
+        let buf = ep.next_iso_in().now_or_never().unwrap().unwrap();
+        assert_eq!(buf.completion().unwrap().request_id, RequestId::new(2));
+    }
+
+    #[test]
+    fn rejects_non_iso_in() {
+        let mut ep = endpoint(0x01);
+        assert!(matches!(
+            ep.submit_iso_in_queue(2, 8),
+            Err(TransferError::InvalidEndpoint)
+        ));
+    }
+}
@@ -4,22 +4,49 @@ use core::{
     future::Future,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
+use futures::future::BoxFuture;
 
+#[cfg(any(kmod, umod, nmod))]
+use usb_if::endpoint::{IsoPacketResult, TransferStatus};
+#[cfg(any(kmod, umod, nmod, mmod, test))]
+use usb_if::transfer::Direction;
 use usb_if::{
     descriptor::EndpointType,
-    endpoint::{
-        EndpointInfo, IsoPacketResult, RequestId, TransferCompletion, TransferRequest,
-        TransferStatus,
-    },
+    endpoint::{EndpointInfo, RequestId, TransferCompletion, TransferRequest},
     err::TransferError,
+    transfer::{Recipient, StandardFeature},
 };
 
+use super::timer::{Timer, poll_until};
+#[cfg(any(kmod, umod, nmod))]
 use super::transfer::Transfer;
+use crate::device::Device;
 
+mod bulk;
+mod coalesce;
 mod ctrl;
+#[cfg(test)]
+pub(crate) mod fake;
+mod iso;
+mod iso_in;
+mod pacing;
+mod resubmit;
+mod retry;
+mod typed;
 
-pub(crate) trait EndpointOp: Send + Any + 'static {
+pub use coalesce::{CoalesceConfig, CoalesceStats};
+pub use iso::{IsoFiller, IsoOutStats};
+pub use iso_in::IsoBuffer;
+#[cfg(any(kmod, umod, nmod, mmod))]
+pub(crate) use pacing::declared_interval;
+pub use retry::RetryPolicy;
+pub(crate) use retry::retry;
+pub use typed::*;
+
+/// 端点操作，时钟与到期唤醒见 [`Timer`]
+pub(crate) trait EndpointOp: Timer + Send + Any + 'static {
     fn submit_request(&mut self, request: TransferRequest) -> Result<RequestId, TransferError>;
 
     fn reclaim_request(
@@ -29,35 +56,194 @@ pub(crate) trait EndpointOp: Send + Any + 'static {
 
     fn register_waker(&self, id: RequestId, cx: &mut Context<'_>);
 
-    fn cancel_request(&mut self, _id: RequestId) -> Result<(), TransferError> {
+    /// 已提交、尚未回收的请求
+    fn pending_requests(&self) -> Vec<RequestId>;
+
+    /// 取消请求，返回的 future 在控制器不再访问请求缓冲区后完成
+    ///
+    /// 请求在取消生效前已完成时返回其完成结果，否则返回 `None`。
+    fn cancel_request(
+        &mut self,
+        _id: RequestId,
+    ) -> BoxFuture<'_, Result<Option<TransferCompletion>, TransferError>> {
+        Box::pin(async { Err(TransferError::NotSupported) })
+    }
+
+    /// 在流 `stream_id` 上提交请求，仅启用了流的批量端点支持
+    fn submit_stream_request(
+        &mut self,
+        _stream_id: u16,
+        _request: TransferRequest,
+    ) -> Result<RequestId, TransferError> {
         Err(TransferError::NotSupported)
     }
+
+    /// 已分配的流数量，流 ID 为 `1..=streams`
+    fn streams(&self) -> u16 {
+        0
+    }
+
+    /// 端点是否可以提交下一个请求，软件定时的周期端点在服务周期到达前返回 `Pending`
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+
+    /// 恢复 Halted 的端点在主机侧的状态，使其可以继续处理请求
+    ///
+    /// 不包含向设备发送 CLEAR_FEATURE(ENDPOINT_HALT)，由 [`Endpoint::clear_halt`] 负责。
+    fn reset_halt(&mut self) -> BoxFuture<'_, Result<(), TransferError>> {
+        Box::pin(async { Err(TransferError::NotSupported) })
+    }
+
+    /// 设置之后提交的请求的超时，后端能由控制器或驱动自行超时时实现，`None` 恢复默认
+    fn set_timeout(&mut self, _timeout: Option<Duration>) {}
+
+    /// 未完成请求的缓冲区映射，请求没有数据阶段或已回收时返回 `None`
+    #[cfg(all(kmod, feature = "dma-mapping"))]
+    fn dma_mapping(&self, _id: RequestId) -> Option<crate::backend::kmod::DmaMapping> {
+        None
+    }
 }
 
 pub struct Endpoint {
     info: EndpointInfo,
     raw: Box<dyn EndpointOp>,
+    /// 等时 OUT 端点的欠载检测，需在 `raw` 之后释放以保证填充缓冲区比传输活得久
+    iso_out: Option<iso::IsoOut>,
+    /// 批量 OUT 写合并，同样需在 `raw` 之后释放
+    coalesce: Option<coalesce::Coalesce>,
+    /// 等时 IN 请求环，同样需在 `raw` 之后释放
+    iso_in: Option<iso_in::IsoInQueue>,
+    /// 等待请求完成的超时
+    timeout: Option<Duration>,
+    /// 周期端点的实际服务间隔，由后端按设备速度确定
+    service_interval: Option<Duration>,
+    /// 周期 OUT 端点的提交节奏，见 [`Endpoint::next_service`]
+    pacer: Option<pacing::Pacer>,
 }
 
 impl Endpoint {
+    #[cfg(any(kmod, umod, nmod, mmod, test))]
     pub(crate) fn new(info: EndpointInfo, raw: impl EndpointOp) -> Self {
+        let iso_out = (info.transfer_type == EndpointType::Isochronous
+            && info.direction == Direction::Out)
+            .then(iso::IsoOut::default);
         Self {
             info,
             raw: Box::new(raw),
+            iso_out,
+            coalesce: None,
+            iso_in: None,
+            timeout: None,
+            service_interval: None,
+            pacer: None,
         }
     }
 
+    /// 设置周期端点的服务间隔，OUT 端点同时启用提交节奏估计
+    #[cfg(any(kmod, umod, nmod, mmod))]
+    pub(crate) fn with_service_interval(mut self, interval: Option<Duration>) -> Self {
+        self.service_interval = interval;
+        self.pacer = interval
+            .filter(|_| {
+                self.info.direction == Direction::Out
+                    && matches!(
+                        self.info.transfer_type,
+                        EndpointType::Interrupt | EndpointType::Isochronous
+                    )
+            })
+            .map(pacing::Pacer::new);
+        self
+    }
+
+    /// 控制器服务该端点的间隔，非周期端点或后端无法确定时为 `None`
+    ///
+    /// 与设备声明的 `bInterval` 不同时以控制器实际采用的为准。
+    pub fn service_interval(&self) -> Option<Duration> {
+        self.service_interval
+    }
+
+    /// 周期 OUT 端点上现在提交的请求最早被服务的时刻，以后端的单调时钟表示
+    ///
+    /// 按已提交请求占用的服务间隔推算（中断请求一个，等时请求每包一个），
+    /// 其他端点为 `None`。
+    pub fn next_service(&self) -> Option<Duration> {
+        let pacer = self.pacer.as_ref()?;
+        Some(pacer.next_service(self.now()))
+    }
+
     pub fn info(&self) -> EndpointInfo {
         self.info
     }
 
+    /// 等时端点每个服务间隔可传输的字节数，即 `wMaxPacketSize * 每微帧包数`
+    ///
+    /// 等时请求中的每一“包”对应一个服务间隔，高带宽端点的一包包含多个事务。
+    pub fn iso_packet_size(&self) -> usize {
+        self.info.max_packet_size as usize * self.info.packets_per_microframe.max(1)
+    }
+
+    /// 设置等待请求完成的超时，默认不超时
+    ///
+    /// 对 [`Endpoint::wait`] 以及基于它的控制、批量、中断、等时传输方法生效。
+    /// 超时后请求被取消，返回 [`TransferError::Timeout`]；取消前请求恰好完成时
+    /// 仍返回完成结果。直接 [`Endpoint::submit`] 的请求不受影响。
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+        self.raw.set_timeout(timeout);
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// 后端的单调时钟
+    pub fn now(&self) -> Duration {
+        self.raw.now()
+    }
+
+    /// 按 `policy` 重试 `op`，遇到暂时性错误时在端点时钟上退避等待
+    ///
+    /// ```ignore
+    /// let n = ep
+    ///     .retry(&RetryPolicy::default(), async |ep| {
+    ///         Ok(ep.wait(TransferRequest::bulk_in(&mut buf)).await?.actual_length)
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn retry<T>(
+        &mut self,
+        policy: &RetryPolicy,
+        op: impl AsyncFnMut(&mut Self) -> Result<T, TransferError>,
+    ) -> Result<T, TransferError> {
+        retry::retry(self, |ep| ep, policy, op).await
+    }
+
     pub fn submit(&mut self, request: TransferRequest) -> Result<RequestId, TransferError> {
         self.validate_request(&request)?;
-        self.raw.submit_request(request)
+        self.submit_unchecked(request)
+    }
+
+    /// 跳过类型检查提交，仅供请求类型已由调用方静态保证的场景使用
+    fn submit_unchecked(&mut self, request: TransferRequest) -> Result<RequestId, TransferError> {
+        let intervals = match &request {
+            TransferRequest::Isochronous { packets, .. } => packets.len(),
+            _ => 1,
+        };
+        let id = self.raw.submit_request(request)?;
+        // 先登记新请求再回收填充请求，避免队列看似为空而在新数据前再插入填充
+        if let Some(iso) = self.iso_out.as_mut() {
+            iso.on_submit(id);
+            iso.reap(self.raw.as_mut());
+        }
+        if let Some(pacer) = self.pacer.as_mut() {
+            pacer.on_submit(self.raw.now(), intervals);
+        }
+        Ok(id)
     }
 
     pub fn reclaim(&mut self, id: RequestId) -> Result<Option<TransferCompletion>, TransferError> {
-        match self.raw.reclaim_request(id) {
+        match self.reclaim_raw(id) {
             Some(result) => result.map(Some),
             None => Ok(None),
         }
@@ -68,25 +254,193 @@ impl Endpoint {
         id: RequestId,
         cx: &mut Context<'_>,
     ) -> Poll<Result<TransferCompletion, TransferError>> {
-        match self.raw.reclaim_request(id) {
+        // 被唤醒后请求通常已完成，先直接回收，省去一次唤醒器注册
+        if let Some(res) = self.reclaim_raw(id) {
+            return Poll::Ready(res);
+        }
+        // 再注册后回收一次：完成事件可能在中断中随时到达，只注册不复查会丢失唤醒
+        self.raw.register_waker(id, cx);
+        match self.reclaim_raw(id) {
             Some(res) => Poll::Ready(res),
-            None => {
-                self.raw.register_waker(id, cx);
-                Poll::Pending
+            None => Poll::Pending,
+        }
+    }
+
+    /// 取消已提交的请求，等待控制器交还缓冲区
+    ///
+    /// 返回后请求缓冲区可以立即复用。请求在取消生效前已经完成时返回 `Some`，
+    /// 其中包含正常的完成结果；否则返回 `None`。之后不应再回收该请求。
+    pub async fn cancel(
+        &mut self,
+        id: RequestId,
+    ) -> Result<Option<TransferCompletion>, TransferError> {
+        let res = self.raw.cancel_request(id).await;
+        if let Some(iso) = self.iso_out.as_mut() {
+            iso.on_cancel(id);
+        }
+        if let Some(c) = self.coalesce.as_mut() {
+            c.on_cancel(id);
+        }
+        if let Some(q) = self.iso_in.as_mut() {
+            q.on_cancel(id);
+        }
+        res
+    }
+
+    /// 已提交请求的缓冲区在总线上的地址，用于把同一块内存交给其他 DMA 引擎
+    ///
+    /// # Safety
+    ///
+    /// 映射只在请求回收或取消之前有效，之后地址可能被解除映射或释放。请求未完成时
+    /// 控制器仍在访问该内存，调用方需自行与其他 DMA 引擎协调访问顺序与缓存维护；
+    /// 反弹缓冲区中的 IN 数据在回收时才复制回调用方缓冲区。
+    #[cfg(all(kmod, feature = "dma-mapping"))]
+    pub unsafe fn dma_mapping(&self, id: RequestId) -> Option<crate::backend::kmod::DmaMapping> {
+        self.raw.dma_mapping(id)
+    }
+
+    /// 是否有已提交、尚未回收的请求，包括端点内部提交的填充与合并请求
+    pub fn has_pending(&self) -> bool {
+        !self.raw.pending_requests().is_empty()
+    }
+
+    /// 取消端点上全部未完成的请求，返回被取消的请求数
+    ///
+    /// 返回后控制器不再访问任何请求缓冲区。写合并缓冲中尚未提交的数据被丢弃。
+    /// 单个请求取消失败时继续取消其余请求，最后返回遇到的第一个错误。
+    pub async fn cancel_all(&mut self) -> Result<usize, TransferError> {
+        let mut cancelled = 0;
+        let mut first_err = None;
+        for id in self.raw.pending_requests() {
+            match self.cancel(id).await {
+                Ok(None) => cancelled += 1,
+                Ok(Some(_)) => {}
+                Err(e) => {
+                    debug!("cancel request {id:?}: {e:?}");
+                    first_err.get_or_insert(e);
+                }
             }
         }
+        if let Some(c) = self.coalesce.as_mut() {
+            c.discard();
+        }
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(cancelled),
+        }
+    }
+
+    /// 清除批量或中断端点的 STALL
+    ///
+    /// 向设备发送 CLEAR_FEATURE(ENDPOINT_HALT)，再复位主机侧的端点，两侧的数据切换位
+    /// 一起归零。端点 Halted 时仍未完成的请求随后继续执行。
+    pub async fn clear_halt(&mut self, device: &mut Device) -> Result<(), TransferError> {
+        if !matches!(
+            self.info.transfer_type,
+            EndpointType::Bulk | EndpointType::Interrupt
+        ) {
+            return Err(TransferError::InvalidEndpoint);
+        }
+        device
+            .clear_feature(
+                Recipient::Endpoint,
+                StandardFeature::EndpointHalt as u16,
+                self.info.address.raw() as u16,
+            )
+            .await?;
+        self.raw.reset_halt().await
     }
 
-    pub fn cancel(&mut self, id: RequestId) -> Result<(), TransferError> {
-        self.raw.cancel_request(id)
+    /// 等待端点进入可提交状态
+    ///
+    /// 对于控制器无法精确编码 `bInterval` 的中断端点，后端会用软件定时保证
+    /// 相邻两次提交的间隔不小于设备声明的周期。直接使用 [`Endpoint::submit`]
+    /// 的调用者应先等待该方法。
+    ///
+    /// 周期 OUT 端点上已有请求排队时，等到 [`Endpoint::next_service`] 之前一个服务间隔
+    /// 才返回，此时生成并提交的数据恰好赶上下一次服务，不会在队列中积压。
+    pub async fn ready(&mut self) {
+        core::future::poll_fn(|cx| {
+            if let Some(pacer) = &self.pacer
+                && poll_until(self.raw.as_ref(), pacer.ready_at(), cx).is_pending()
+            {
+                return Poll::Pending;
+            }
+            self.raw.poll_ready(cx)
+        })
+        .await
     }
 
     pub async fn wait(
         &mut self,
         request: TransferRequest,
     ) -> Result<TransferCompletion, TransferError> {
-        let id = self.submit(request)?;
-        EndpointRequestFuture { id, endpoint: self }.await
+        self.validate_request(&request)?;
+        self.wait_unchecked(request).await
+    }
+
+    /// 发起请求并等待完成，本次使用 `timeout` 代替 [`Endpoint::set_timeout`] 设置的超时
+    pub async fn wait_timeout(
+        &mut self,
+        request: TransferRequest,
+        timeout: Duration,
+    ) -> Result<TransferCompletion, TransferError> {
+        self.validate_request(&request)?;
+        self.ready().await;
+        let id = self.submit_unchecked(request)?;
+        self.wait_request(id, Some(timeout)).await
+    }
+
+    async fn wait_unchecked(
+        &mut self,
+        request: TransferRequest,
+    ) -> Result<TransferCompletion, TransferError> {
+        self.ready().await;
+        let id = self.submit_unchecked(request)?;
+        self.wait_request(id, self.timeout).await
+    }
+
+    /// 等待已提交的请求，超时后取消
+    async fn wait_request(
+        &mut self,
+        id: RequestId,
+        timeout: Option<Duration>,
+    ) -> Result<TransferCompletion, TransferError> {
+        let Some(timeout) = timeout else {
+            return EndpointRequestFuture { id, endpoint: self }.await;
+        };
+
+        let deadline = self.raw.now() + timeout;
+        let res = core::future::poll_fn(|cx| match self.poll_request(id, cx) {
+            Poll::Ready(res) => Poll::Ready(Some(res)),
+            Poll::Pending => poll_until(self.raw.as_ref(), deadline, cx).map(|_| None),
+        })
+        .await;
+        if let Some(res) = res {
+            return res;
+        }
+
+        debug!("request {id:?} timed out after {timeout:?}");
+        match self.cancel(id).await {
+            Ok(Some(completion)) => Ok(completion),
+            Ok(None) => Err(TransferError::Timeout),
+            // 无法取消时控制器可能仍在访问缓冲区，只能继续等待
+            Err(e) => {
+                warn!("cancel timed out request {id:?}: {e:?}");
+                EndpointRequestFuture { id, endpoint: self }.await
+            }
+        }
+    }
+
+    fn reclaim_raw(&mut self, id: RequestId) -> Option<Result<TransferCompletion, TransferError>> {
+        let res = self.raw.reclaim_request(id);
+        if let Some(iso) = self.iso_out.as_mut() {
+            if let Some(res) = &res {
+                iso.on_complete(self.raw.as_mut(), id, res);
+            }
+            iso.reap(self.raw.as_mut());
+        }
+        res
     }
 
     #[allow(unused)]
@@ -110,6 +464,16 @@ impl Endpoint {
     }
 }
 
+impl Timer for Endpoint {
+    fn now(&self) -> Duration {
+        self.raw.now()
+    }
+
+    fn wake_at(&self, deadline: Duration, waker: &core::task::Waker) {
+        self.raw.wake_at(deadline, waker);
+    }
+}
+
 struct EndpointRequestFuture<'a> {
     id: RequestId,
     endpoint: &'a mut Endpoint,
@@ -124,16 +488,22 @@ impl Future for EndpointRequestFuture<'_> {
     }
 }
 
+#[cfg(any(kmod, umod, nmod))]
 pub(crate) fn transfer_to_completion(id: RequestId, transfer: Transfer) -> TransferCompletion {
     let iso_packets = match &transfer.kind {
         usb_if::endpoint::TransferKind::Isochronous { packet_lengths } => packet_lengths
             .iter()
             .copied()
             .zip(transfer.iso_packet_actual_lengths.iter().copied())
-            .map(|(requested_length, actual_length)| IsoPacketResult {
+            .enumerate()
+            .map(|(i, (requested_length, actual_length))| IsoPacketResult {
                 requested_length,
                 actual_length,
-                status: TransferStatus::Completed,
+                status: transfer
+                    .iso_packet_status
+                    .get(i)
+                    .copied()
+                    .unwrap_or(TransferStatus::Completed),
             })
             .collect(),
         _ => Vec::new(),
@@ -146,3 +516,158 @@ pub(crate) fn transfer_to_completion(id: RequestId, transfer: Transfer) -> Trans
         iso_packets,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use core::{
+        ops::ControlFlow,
+        pin::pin,
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    use futures::task::noop_waker_ref;
+    use usb_if::endpoint::EndpointAddress;
+
+    use super::fake::Completing;
+    use super::*;
+    use crate::spawn::TaskQueue;
+
+    /// 请求永不完成，每次读取时钟前进 1ms
+    #[derive(Default)]
+    struct Stuck {
+        clock: AtomicU64,
+        pending: Option<RequestId>,
+    }
+
+    impl Timer for Stuck {
+        fn now(&self) -> Duration {
+            Duration::from_millis(self.clock.fetch_add(1, Ordering::Relaxed))
+        }
+
+        fn wake_at(&self, _: Duration, waker: &core::task::Waker) {
+            waker.wake_by_ref();
+        }
+    }
+
+    impl EndpointOp for Stuck {
+        fn submit_request(&mut self, _: TransferRequest) -> Result<RequestId, TransferError> {
+            let id = RequestId::new(1);
+            self.pending = Some(id);
+            Ok(id)
+        }
+
+        fn reclaim_request(
+            &mut self,
+            _: RequestId,
+        ) -> Option<Result<TransferCompletion, TransferError>> {
+            None
+        }
+
+        fn register_waker(&self, _: RequestId, _: &mut Context<'_>) {}
+
+        fn pending_requests(&self) -> Vec<RequestId> {
+            self.pending.into_iter().collect()
+        }
+
+        fn cancel_request(
+            &mut self,
+            id: RequestId,
+        ) -> BoxFuture<'_, Result<Option<TransferCompletion>, TransferError>> {
+            assert_eq!(self.pending.take(), Some(id));
+            Box::pin(async { Ok(None) })
+        }
+    }
+
+    #[test]
+    fn wait_times_out_and_cancels() {
+        let address = EndpointAddress::new(0x81);
+        let mut ep = Endpoint::new(
+            EndpointInfo {
+                address,
+                transfer_type: EndpointType::Bulk,
+                direction: address.direction(),
+                max_packet_size: 512,
+                packets_per_microframe: 1,
+                interval: 0,
+            },
+            Stuck::default(),
+        );
+        ep.set_timeout(Some(Duration::from_millis(10)));
+
+        let mut buff = [0u8; 64];
+        let res = {
+            let mut fut = pin!(ep.wait(TransferRequest::bulk_in(&mut buff)));
+            let mut cx = Context::from_waker(noop_waker_ref());
+            (0..100)
+                .find_map(|_| match fut.as_mut().poll(&mut cx) {
+                    Poll::Ready(res) => Some(res),
+                    Poll::Pending => None,
+                })
+                .expect("wait did not time out")
+        };
+        assert!(matches!(res, Err(TransferError::Timeout)));
+        assert!(!ep.has_pending());
+    }
+
+    #[test]
+    fn resubmit_until_break() {
+        let info = |addr: u8| {
+            let address = EndpointAddress::new(addr);
+            EndpointInfo {
+                address,
+                transfer_type: EndpointType::Interrupt,
+                direction: address.direction(),
+                max_packet_size: 8,
+                packets_per_microframe: 1,
+                interval: 10,
+            }
+        };
+        let queue = TaskQueue::default();
+        let out = Endpoint::new(info(0x02), Completing::new(4));
+        assert!(matches!(
+            out.spawn_resubmit(&queue, 8, |_| ControlFlow::Continue(())),
+            Err(TransferError::InvalidEndpoint)
+        ));
+
+        let received = Arc::new(AtomicU64::new(0));
+        let counter = received.clone();
+        Endpoint::new(info(0x81), Completing::new(4))
+            .spawn_resubmit(&queue, 8, move |data| {
+                assert_eq!(data.unwrap().len(), 4);
+                match counter.fetch_add(1, Ordering::Relaxed) {
+                    2 => ControlFlow::Break(()),
+                    _ => ControlFlow::Continue(()),
+                }
+            })
+            .unwrap();
+
+        let mut task = queue.pop().unwrap();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(task.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(received.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn read_exact_fails_on_zero_length_packet() {
+        let address = EndpointAddress::new(0x81);
+        let info = EndpointInfo {
+            address,
+            transfer_type: EndpointType::Bulk,
+            direction: address.direction(),
+            max_packet_size: 512,
+            packets_per_microframe: 1,
+            interval: 0,
+        };
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut buff = [0u8; 8];
+
+        let mut ep = Endpoint::new(info, Completing::new(4));
+        let res = pin!(ep.read_exact(&mut buff)).poll(&mut cx);
+        assert!(matches!(res, Poll::Ready(Ok(()))));
+
+        let mut ep = Endpoint::new(info, Completing::new(0));
+        let res = pin!(ep.read_exact(&mut buff)).poll(&mut cx);
+        assert!(matches!(res, Poll::Ready(Err(TransferError::Other(_)))));
+    }
+}
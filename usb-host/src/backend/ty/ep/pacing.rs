@@ -0,0 +1,105 @@
+//! 周期 OUT 端点的提交节奏
+//!
+//! 中断 OUT 请求每次占用一个服务间隔，等时 OUT 请求每包占用一个。按已提交的请求
+//! 推算控制器下一次服务新请求的时刻，应用在此之前一个间隔生成数据即可按时送达，
+//! 既不欠载也不在队列中积压。
+
+use core::time::Duration;
+
+#[cfg(any(kmod, umod, nmod, mmod))]
+use usb_if::{descriptor::EndpointType, host::hub::Speed};
+
+/// 设备在端点描述符中声明的服务周期，非周期端点为 `None`
+///
+/// HS/SS 周期端点与 FS 等时端点的 `bInterval` 为指数，FS/LS 中断端点以毫秒为单位。
+#[cfg(any(kmod, umod, nmod, mmod))]
+pub(crate) fn declared_interval(
+    speed: Speed,
+    transfer_type: EndpointType,
+    binterval: u8,
+) -> Option<Duration> {
+    let hs_or_ss = matches!(
+        speed,
+        Speed::High | Speed::SuperSpeed | Speed::SuperSpeedPlus
+    );
+    match transfer_type {
+        EndpointType::Interrupt if !hs_or_ss => {
+            Some(Duration::from_millis(binterval.max(1) as u64))
+        }
+        EndpointType::Isochronous if !hs_or_ss => {
+            Some(Duration::from_millis(1 << (binterval.clamp(1, 16) - 1)))
+        }
+        EndpointType::Isochronous | EndpointType::Interrupt => {
+            Some(Duration::from_micros(125 << (binterval.clamp(1, 16) - 1)))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Pacer {
+    interval: Duration,
+    /// 已提交的请求全部服务完毕的时刻
+    next: Duration,
+}
+
+impl Pacer {
+    #[cfg(any(kmod, umod, nmod, mmod, test))]
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: Duration::ZERO,
+        }
+    }
+
+    /// 记录在 `now` 提交的、占用 `intervals` 个服务间隔的请求
+    pub fn on_submit(&mut self, now: Duration, intervals: usize) {
+        self.next = self.next.max(now) + self.interval * intervals.max(1) as u32;
+    }
+
+    /// 现在提交的请求最早被服务的时刻
+    pub fn next_service(&self, now: Duration) -> Duration {
+        self.next.max(now)
+    }
+
+    /// 应提交下一个请求的时刻：下一次服务之前一个间隔
+    pub fn ready_at(&self) -> Duration {
+        self.next.saturating_sub(self.interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    #[cfg(any(kmod, umod, nmod, mmod))]
+    fn declared_interval_by_speed() {
+        let fs_int = declared_interval(Speed::Full, EndpointType::Interrupt, 10);
+        assert_eq!(fs_int, Some(10 * MS));
+        let fs_iso = declared_interval(Speed::Full, EndpointType::Isochronous, 4);
+        assert_eq!(fs_iso, Some(8 * MS));
+        let hs_int = declared_interval(Speed::High, EndpointType::Interrupt, 4);
+        assert_eq!(hs_int, Some(Duration::from_micros(1000)));
+        assert_eq!(declared_interval(Speed::High, EndpointType::Bulk, 4), None);
+    }
+
+    #[test]
+    fn requests_queue_back_to_back() {
+        let mut pacer = Pacer::new(MS);
+        assert_eq!(pacer.next_service(5 * MS), 5 * MS);
+        assert_eq!(pacer.ready_at(), Duration::ZERO);
+
+        // 8 包的等时请求占用 8 个间隔，后续请求排在其后
+        pacer.on_submit(5 * MS, 8);
+        pacer.on_submit(6 * MS, 1);
+        assert_eq!(pacer.next_service(6 * MS), 14 * MS);
+        assert_eq!(pacer.ready_at(), 13 * MS);
+
+        // 队列排空后从当前时刻重新计算
+        pacer.on_submit(30 * MS, 1);
+        assert_eq!(pacer.next_service(30 * MS), 31 * MS);
+    }
+}
@@ -0,0 +1,50 @@
+use alloc::boxed::Box;
+use core::ops::ControlFlow;
+
+use usb_if::{
+    descriptor::EndpointType, endpoint::TransferRequest, err::TransferError, transfer::Direction,
+};
+
+use super::Endpoint;
+use crate::spawn::Spawner;
+
+impl Endpoint {
+    /// 在后台任务中反复提交 IN 请求，适用于批量与中断 IN 端点
+    ///
+    /// 每个请求最多读取 `len` 字节，完成后把数据交给 `on_data`。`on_data` 返回
+    /// [`ControlFlow::Break`] 或传输出错时任务结束，错误会先交给 `on_data`；端点随任务释放。
+    pub fn spawn_resubmit(
+        mut self,
+        spawner: &dyn Spawner,
+        len: usize,
+        mut on_data: impl FnMut(Result<&[u8], TransferError>) -> ControlFlow<()> + Send + 'static,
+    ) -> Result<(), TransferError> {
+        let transfer_type = self.info.transfer_type;
+        if self.info.direction != Direction::In
+            || !matches!(transfer_type, EndpointType::Bulk | EndpointType::Interrupt)
+        {
+            return Err(TransferError::InvalidEndpoint);
+        }
+
+        spawner.spawn(Box::pin(async move {
+            let mut buf = vec![0u8; len];
+            loop {
+                let request = match transfer_type {
+                    EndpointType::Bulk => TransferRequest::bulk_in(&mut buf),
+                    _ => TransferRequest::interrupt_in(&mut buf),
+                };
+                let res = match self.wait(request).await {
+                    Ok(t) => on_data(Ok(&buf[..t.actual_length])),
+                    Err(e) => {
+                        let _ = on_data(Err(e));
+                        break;
+                    }
+                };
+                if res.is_break() {
+                    break;
+                }
+            }
+        }));
+        Ok(())
+    }
+}
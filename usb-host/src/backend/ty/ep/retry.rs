@@ -0,0 +1,170 @@
+use core::time::Duration;
+
+use usb_if::err::TransferError;
+
+use crate::backend::ty::timer::{Timer, sleep_until};
+
+/// 传输重试策略
+///
+/// 超时与队列满视为暂时性错误；STALL 仅在 `retry_stall` 时重试，适用于控制端点的
+/// 协议 STALL（下一个 SETUP 自动清除）或由调用方在重试闭包中清除了 halt 的端点。
+/// 两次尝试之间按指数退避等待。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// 总尝试次数，包括第一次
+    pub max_attempts: u32,
+    /// 第一次重试前的等待时间，之后每次翻倍
+    pub initial_backoff: Duration,
+    /// 单次等待的上限
+    pub max_backoff: Duration,
+    pub retry_stall: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(50),
+            retry_stall: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 是否值得重试
+    pub fn is_transient(&self, err: &TransferError) -> bool {
+        match err {
+            TransferError::Timeout | TransferError::QueueFull => true,
+            TransferError::Stall | TransferError::StatusStall => self.retry_stall,
+            _ => false,
+        }
+    }
+
+    /// 第 `retry` 次重试（从 0 开始）前的等待时间
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .checked_mul(1 << retry.min(31))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// 按 `policy` 重复执行 `op`，`timer` 取出退避使用的定时器
+pub(crate) async fn retry<C, D: Timer + ?Sized, T>(
+    target: &mut C,
+    timer: fn(&C) -> &D,
+    policy: &RetryPolicy,
+    mut op: impl AsyncFnMut(&mut C) -> Result<T, TransferError>,
+) -> Result<T, TransferError> {
+    let mut retry = 0;
+    loop {
+        match op(target).await {
+            Err(e) if retry + 1 < policy.max_attempts && policy.is_transient(&e) => {
+                let backoff = policy.backoff(retry);
+                debug!("transfer failed: {e:?}, retry in {backoff:?}");
+                let timer = timer(target);
+                sleep_until(timer, timer.now() + backoff).await;
+                retry += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::task::Waker;
+
+    use futures::FutureExt;
+
+    use super::*;
+
+    /// 每读取一次前进 1ms 的时钟，记录尝试次数
+    #[derive(Default)]
+    struct Counter {
+        clock: core::cell::Cell<u64>,
+        attempts: u32,
+    }
+
+    impl Timer for Counter {
+        fn now(&self) -> Duration {
+            let t = self.clock.get();
+            self.clock.set(t + 1);
+            Duration::from_millis(t)
+        }
+
+        fn wake_at(&self, _deadline: Duration, waker: &Waker) {
+            waker.wake_by_ref();
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(2),
+            max_backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(2));
+        assert_eq!(policy.backoff(2), Duration::from_millis(8));
+        assert_eq!(policy.backoff(3), Duration::from_millis(10));
+        assert_eq!(policy.backoff(100), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn retries_only_transient_errors() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::ZERO,
+            ..Default::default()
+        };
+
+        let mut c = Counter::default();
+        let res = retry(
+            &mut c,
+            |c| c,
+            &policy,
+            async |c: &mut Counter| {
+                c.attempts += 1;
+                if c.attempts < 3 {
+                    Err(TransferError::Timeout)
+                } else {
+                    Ok(c.attempts)
+                }
+            },
+        )
+        .now_or_never()
+        .unwrap();
+        assert_eq!(res.unwrap(), 3);
+
+        let mut c = Counter::default();
+        let res = retry(
+            &mut c,
+            |c| c,
+            &policy,
+            async |c: &mut Counter| {
+                c.attempts += 1;
+                Err::<(), _>(TransferError::Stall)
+            },
+        )
+        .now_or_never()
+        .unwrap();
+        assert!(matches!(res, Err(TransferError::Stall)));
+        assert_eq!(c.attempts, 1);
+
+        let mut c = Counter::default();
+        let res = retry(
+            &mut c,
+            |c| c,
+            &policy,
+            async |c: &mut Counter| {
+                c.attempts += 1;
+                Err::<(), _>(TransferError::Timeout)
+            },
+        )
+        .now_or_never()
+        .unwrap();
+        assert!(matches!(res, Err(TransferError::Timeout)));
+        assert_eq!(c.attempts, policy.max_attempts);
+    }
+}
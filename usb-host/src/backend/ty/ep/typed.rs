@@ -0,0 +1,549 @@
+use core::{
+    marker::PhantomData,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use alloc::vec::Vec;
+
+use usb_if::{
+    descriptor::EndpointType,
+    endpoint::{EndpointInfo, RequestId, TransferCompletion, TransferRequest},
+    err::TransferError,
+    transfer::Direction,
+};
+
+use super::{
+    CoalesceConfig, CoalesceStats, Endpoint, IsoBuffer, IsoFiller, IsoOutStats, RetryPolicy,
+};
+use crate::device::Device;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// 端点传输类型标记，由 [`Bulk`]、[`Interrupt`]、[`Isochronous`] 实现
+pub trait EndpointKind: sealed::Sealed + 'static {
+    const TYPE: EndpointType;
+}
+
+/// 端点方向标记，由 [`In`]、[`Out`] 实现
+pub trait EndpointDirection: sealed::Sealed + 'static {
+    const DIRECTION: Direction;
+}
+
+/// 批量传输
+#[derive(Debug)]
+pub enum Bulk {}
+
+/// 中断传输
+#[derive(Debug)]
+pub enum Interrupt {}
+
+/// 等时传输
+#[derive(Debug)]
+pub enum Isochronous {}
+
+/// 设备到主机
+#[derive(Debug)]
+pub enum In {}
+
+/// 主机到设备
+#[derive(Debug)]
+pub enum Out {}
+
+impl sealed::Sealed for Bulk {}
+impl sealed::Sealed for Interrupt {}
+impl sealed::Sealed for Isochronous {}
+impl sealed::Sealed for In {}
+impl sealed::Sealed for Out {}
+
+impl EndpointKind for Bulk {
+    const TYPE: EndpointType = EndpointType::Bulk;
+}
+
+impl EndpointKind for Interrupt {
+    const TYPE: EndpointType = EndpointType::Interrupt;
+}
+
+impl EndpointKind for Isochronous {
+    const TYPE: EndpointType = EndpointType::Isochronous;
+}
+
+impl EndpointDirection for In {
+    const DIRECTION: Direction = Direction::In;
+}
+
+impl EndpointDirection for Out {
+    const DIRECTION: Direction = Direction::Out;
+}
+
+/// 传输类型与方向在编译期确定的端点
+///
+/// 由 [`Endpoint::into_typed`] 在运行时校验一次端点描述后得到，之后只提供与类型、方向
+/// 匹配的方法，提交时不再逐个请求校验类型。需要动态接口时用 [`TypedEndpoint::into_inner`]
+/// 取回 [`Endpoint`]。
+pub struct TypedEndpoint<T: EndpointKind, D: EndpointDirection> {
+    inner: Endpoint,
+    _marker: PhantomData<fn() -> (T, D)>,
+}
+
+pub type BulkIn = TypedEndpoint<Bulk, In>;
+pub type BulkOut = TypedEndpoint<Bulk, Out>;
+pub type InterruptIn = TypedEndpoint<Interrupt, In>;
+pub type InterruptOut = TypedEndpoint<Interrupt, Out>;
+pub type IsochronousIn = TypedEndpoint<Isochronous, In>;
+pub type IsochronousOut = TypedEndpoint<Isochronous, Out>;
+
+impl Endpoint {
+    /// 是否为给定传输类型与方向的端点
+    pub fn is<T: EndpointKind, D: EndpointDirection>(&self) -> bool {
+        self.info.transfer_type == T::TYPE && self.info.direction == D::DIRECTION
+    }
+
+    /// 转换为类型化端点，类型或方向不符时原样返回
+    #[allow(clippy::result_large_err)]
+    pub fn into_typed<T: EndpointKind, D: EndpointDirection>(
+        self,
+    ) -> Result<TypedEndpoint<T, D>, Endpoint> {
+        if self.is::<T, D>() {
+            Ok(TypedEndpoint {
+                inner: self,
+                _marker: PhantomData,
+            })
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<T: EndpointKind, D: EndpointDirection> TypedEndpoint<T, D> {
+    pub fn info(&self) -> EndpointInfo {
+        self.inner.info
+    }
+
+    pub fn into_inner(self) -> Endpoint {
+        self.inner
+    }
+
+    pub fn as_endpoint(&self) -> &Endpoint {
+        &self.inner
+    }
+
+    pub fn reclaim(&mut self, id: RequestId) -> Result<Option<TransferCompletion>, TransferError> {
+        self.inner.reclaim(id)
+    }
+
+    pub fn poll_request(
+        &mut self,
+        id: RequestId,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<TransferCompletion, TransferError>> {
+        self.inner.poll_request(id, cx)
+    }
+
+    /// 见 [`Endpoint::cancel`]
+    pub async fn cancel(
+        &mut self,
+        id: RequestId,
+    ) -> Result<Option<TransferCompletion>, TransferError> {
+        self.inner.cancel(id).await
+    }
+
+    /// 见 [`Endpoint::cancel_all`]
+    pub async fn cancel_all(&mut self) -> Result<usize, TransferError> {
+        self.inner.cancel_all().await
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.inner.has_pending()
+    }
+
+    /// 见 [`Endpoint::ready`]
+    pub async fn ready(&mut self) {
+        self.inner.ready().await
+    }
+
+    /// 见 [`Endpoint::clear_halt`]
+    pub async fn clear_halt(&mut self, device: &mut Device) -> Result<(), TransferError> {
+        self.inner.clear_halt(device).await
+    }
+
+    /// 见 [`Endpoint::set_timeout`]
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.inner.set_timeout(timeout)
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+
+    /// 见 [`Endpoint::retry`]
+    pub async fn retry<R>(
+        &mut self,
+        policy: &RetryPolicy,
+        op: impl AsyncFnMut(&mut Self) -> Result<R, TransferError>,
+    ) -> Result<R, TransferError> {
+        super::retry::retry(self, |ep| &ep.inner, policy, op).await
+    }
+}
+
+impl<T: EndpointKind, D: EndpointDirection> core::fmt::Debug for TypedEndpoint<T, D> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TypedEndpoint")
+            .field("transfer_type", &T::TYPE)
+            .field("direction", &D::DIRECTION)
+            .field("address", &self.inner.info.address)
+            .finish()
+    }
+}
+
+impl<D: EndpointDirection> TypedEndpoint<Bulk, D> {
+    /// 见 [`Endpoint::streams`]
+    pub fn streams(&self) -> u16 {
+        self.inner.streams()
+    }
+}
+
+impl TypedEndpoint<Bulk, In> {
+    pub fn submit(&mut self, buff: &mut [u8]) -> Result<RequestId, TransferError> {
+        self.inner.submit_unchecked(TransferRequest::bulk_in(buff))
+    }
+
+    /// 发起一次传输，返回实际读取的字节数
+    pub async fn transfer(&mut self, buff: &mut [u8]) -> Result<usize, TransferError> {
+        let t = self
+            .inner
+            .wait_unchecked(TransferRequest::bulk_in(buff))
+            .await?;
+        Ok(t.actual_length)
+    }
+
+    /// 在流 `stream_id` 上提交，见 [`Endpoint::submit_stream`]
+    pub fn submit_stream(
+        &mut self,
+        stream_id: u16,
+        buff: &mut [u8],
+    ) -> Result<RequestId, TransferError> {
+        self.inner
+            .submit_stream(stream_id, TransferRequest::bulk_in(buff))
+    }
+
+    /// 在流 `stream_id` 上发起一次传输，返回实际读取的字节数
+    pub async fn transfer_stream(
+        &mut self,
+        stream_id: u16,
+        buff: &mut [u8],
+    ) -> Result<usize, TransferError> {
+        let t = self
+            .inner
+            .wait_stream(stream_id, TransferRequest::bulk_in(buff))
+            .await?;
+        Ok(t.actual_length)
+    }
+
+    /// 见 [`Endpoint::read_exact`]
+    pub async fn read_exact(&mut self, buff: &mut [u8]) -> Result<(), TransferError> {
+        self.inner.read_exact(buff).await
+    }
+
+    /// 见 [`Endpoint::read_until_short`]
+    pub async fn read_until_short(&mut self, buff: &mut [u8]) -> Result<usize, TransferError> {
+        self.inner.read_until_short(buff).await
+    }
+}
+
+impl TypedEndpoint<Bulk, Out> {
+    pub fn submit(&mut self, buff: &[u8]) -> Result<RequestId, TransferError> {
+        self.inner.submit_unchecked(TransferRequest::bulk_out(buff))
+    }
+
+    /// 发起一次传输，返回实际写出的字节数
+    pub async fn transfer(&mut self, buff: &[u8]) -> Result<usize, TransferError> {
+        let t = self
+            .inner
+            .wait_unchecked(TransferRequest::bulk_out(buff))
+            .await?;
+        Ok(t.actual_length)
+    }
+
+    /// 在流 `stream_id` 上提交，见 [`Endpoint::submit_stream`]
+    pub fn submit_stream(
+        &mut self,
+        stream_id: u16,
+        buff: &[u8],
+    ) -> Result<RequestId, TransferError> {
+        self.inner
+            .submit_stream(stream_id, TransferRequest::bulk_out(buff))
+    }
+
+    /// 在流 `stream_id` 上发起一次传输，返回实际写出的字节数
+    pub async fn transfer_stream(
+        &mut self,
+        stream_id: u16,
+        buff: &[u8],
+    ) -> Result<usize, TransferError> {
+        let t = self
+            .inner
+            .wait_stream(stream_id, TransferRequest::bulk_out(buff))
+            .await?;
+        Ok(t.actual_length)
+    }
+
+    /// 见 [`Endpoint::set_write_coalescing`]
+    pub fn set_write_coalescing(
+        &mut self,
+        config: Option<CoalesceConfig>,
+    ) -> Result<(), TransferError> {
+        self.inner.set_write_coalescing(config)
+    }
+
+    pub fn write_coalesce_stats(&self) -> Option<CoalesceStats> {
+        self.inner.write_coalesce_stats()
+    }
+
+    /// 见 [`Endpoint::write`]
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), TransferError> {
+        self.inner.write(data).await
+    }
+
+    /// 见 [`Endpoint::flush`]
+    pub async fn flush(&mut self) -> Result<(), TransferError> {
+        self.inner.flush().await
+    }
+
+    /// 见 [`Endpoint::flush_due`]
+    pub async fn flush_due(&mut self) -> Result<(), TransferError> {
+        self.inner.flush_due().await
+    }
+}
+
+impl TypedEndpoint<Interrupt, In> {
+    pub fn submit(&mut self, buff: &mut [u8]) -> Result<RequestId, TransferError> {
+        self.inner
+            .submit_unchecked(TransferRequest::interrupt_in(buff))
+    }
+
+    /// 等待端点周期到达后发起一次传输，返回实际读取的字节数
+    pub async fn transfer(&mut self, buff: &mut [u8]) -> Result<usize, TransferError> {
+        let t = self
+            .inner
+            .wait_unchecked(TransferRequest::interrupt_in(buff))
+            .await?;
+        Ok(t.actual_length)
+    }
+}
+
+impl TypedEndpoint<Interrupt, Out> {
+    /// 见 [`Endpoint::service_interval`]
+    pub fn service_interval(&self) -> Option<Duration> {
+        self.inner.service_interval()
+    }
+
+    /// 见 [`Endpoint::next_service`]
+    pub fn next_service(&self) -> Option<Duration> {
+        self.inner.next_service()
+    }
+
+    pub fn submit(&mut self, buff: &[u8]) -> Result<RequestId, TransferError> {
+        self.inner
+            .submit_unchecked(TransferRequest::interrupt_out(buff))
+    }
+
+    /// 等待端点周期到达后发起一次传输，返回实际写出的字节数
+    pub async fn transfer(&mut self, buff: &[u8]) -> Result<usize, TransferError> {
+        let t = self
+            .inner
+            .wait_unchecked(TransferRequest::interrupt_out(buff))
+            .await?;
+        Ok(t.actual_length)
+    }
+}
+
+impl TypedEndpoint<Isochronous, In> {
+    pub fn submit(
+        &mut self,
+        buff: &mut [u8],
+        packet_lengths: &[usize],
+    ) -> Result<RequestId, TransferError> {
+        self.inner
+            .submit_unchecked(TransferRequest::iso_in(buff, packet_lengths))
+    }
+
+    /// 发起一次传输，逐包结果见 [`TransferCompletion::iso_packets`]
+    pub async fn transfer(
+        &mut self,
+        buff: &mut [u8],
+        packet_lengths: &[usize],
+    ) -> Result<TransferCompletion, TransferError> {
+        self.inner
+            .wait_unchecked(TransferRequest::iso_in(buff, packet_lengths))
+            .await
+    }
+
+    /// 按端点的 [`Endpoint::iso_packet_size`] 把 `buff` 划分为若干包发起一次传输
+    ///
+    /// 最后一包可以不满一个服务间隔。逐包的实际长度与状态见
+    /// [`TransferCompletion::iso_packet_data`]，各包数据位于按请求长度划分的偏移处。
+    pub async fn transfer_packets(
+        &mut self,
+        buff: &mut [u8],
+    ) -> Result<TransferCompletion, TransferError> {
+        let packet_lengths = iso_packet_layout(buff.len(), self.inner.iso_packet_size());
+        self.transfer(buff, &packet_lengths).await
+    }
+
+    /// 见 [`Endpoint::submit_iso_in_queue`]
+    pub fn submit_queue(
+        &mut self,
+        buffers: usize,
+        packets_per_buffer: usize,
+    ) -> Result<(), TransferError> {
+        self.inner.submit_iso_in_queue(buffers, packets_per_buffer)
+    }
+
+    /// 见 [`Endpoint::next_iso_in`]
+    pub async fn next_completed(&mut self) -> Result<IsoBuffer, TransferError> {
+        self.inner.next_iso_in().await
+    }
+
+    /// 见 [`Endpoint::requeue_iso_in`]
+    pub fn requeue(&mut self, buf: IsoBuffer) -> Result<(), TransferError> {
+        self.inner.requeue_iso_in(buf)
+    }
+
+    pub fn queued(&self) -> usize {
+        self.inner.iso_in_queued()
+    }
+}
+
+/// 把 `len` 字节按 `packet_size` 划分，最后一包取余下的长度
+fn iso_packet_layout(len: usize, packet_size: usize) -> Vec<usize> {
+    let packet_size = packet_size.max(1);
+    (0..len.div_ceil(packet_size))
+        .map(|i| packet_size.min(len - i * packet_size))
+        .collect()
+}
+
+impl TypedEndpoint<Isochronous, Out> {
+    /// 见 [`Endpoint::service_interval`]
+    pub fn service_interval(&self) -> Option<Duration> {
+        self.inner.service_interval()
+    }
+
+    /// 见 [`Endpoint::next_service`]
+    pub fn next_service(&self) -> Option<Duration> {
+        self.inner.next_service()
+    }
+
+    pub fn submit(
+        &mut self,
+        buff: &[u8],
+        packet_lengths: &[usize],
+    ) -> Result<RequestId, TransferError> {
+        self.inner
+            .submit_unchecked(TransferRequest::iso_out(buff, packet_lengths))
+    }
+
+    /// 发起一次传输，逐包结果见 [`TransferCompletion::iso_packets`]
+    pub async fn transfer(
+        &mut self,
+        buff: &[u8],
+        packet_lengths: &[usize],
+    ) -> Result<TransferCompletion, TransferError> {
+        self.inner
+            .wait_unchecked(TransferRequest::iso_out(buff, packet_lengths))
+            .await
+    }
+
+    pub fn iso_out_stats(&self) -> Option<IsoOutStats> {
+        self.inner.iso_out_stats()
+    }
+
+    /// 见 [`Endpoint::set_iso_filler`]
+    pub fn set_iso_filler(&mut self, filler: Option<IsoFiller>) -> Result<(), TransferError> {
+        self.inner.set_iso_filler(filler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use usb_if::endpoint::EndpointAddress;
+
+    use core::task::Waker;
+
+    use super::super::EndpointOp;
+    use super::*;
+    use crate::backend::ty::timer::Timer;
+
+    struct Idle;
+
+    impl Timer for Idle {
+        fn now(&self) -> Duration {
+            Duration::ZERO
+        }
+
+        fn wake_at(&self, deadline: Duration, waker: &Waker) {
+            let _ = deadline;
+            waker.wake_by_ref();
+        }
+    }
+
+    impl EndpointOp for Idle {
+        fn submit_request(&mut self, _: TransferRequest) -> Result<RequestId, TransferError> {
+            Err(TransferError::NotSupported)
+        }
+
+        fn reclaim_request(
+            &mut self,
+            _: RequestId,
+        ) -> Option<Result<TransferCompletion, TransferError>> {
+            None
+        }
+
+        fn register_waker(&self, _: RequestId, _: &mut Context<'_>) {}
+
+        fn pending_requests(&self) -> Vec<RequestId> {
+            Vec::new()
+        }
+    }
+
+    fn endpoint(address: u8, transfer_type: EndpointType) -> Endpoint {
+        let address = EndpointAddress::new(address);
+        Endpoint::new(
+            EndpointInfo {
+                address,
+                transfer_type,
+                direction: address.direction(),
+                max_packet_size: 512,
+                packets_per_microframe: 1,
+                interval: 0,
+            },
+            Idle,
+        )
+    }
+
+    #[test]
+    fn iso_layout_follows_packet_size() {
+        assert_eq!(iso_packet_layout(3072 * 2 + 100, 3072), [3072, 3072, 100]);
+        assert_eq!(iso_packet_layout(1024, 1024), [1024]);
+        assert!(iso_packet_layout(0, 1024).is_empty());
+    }
+
+    #[test]
+    fn into_typed_checks_type_and_direction() {
+        let ep = endpoint(0x81, EndpointType::Bulk);
+        let ep = ep.into_typed::<Bulk, Out>().unwrap_err();
+        let ep = ep.into_typed::<Interrupt, In>().unwrap_err();
+        let Ok(ep) = ep.into_typed::<Bulk, In>() else {
+            panic!("bulk in endpoint rejected");
+        };
+        assert_eq!(ep.info().address, EndpointAddress::new(0x81));
+
+        let ep = endpoint(0x02, EndpointType::Isochronous);
+        assert!(ep.is::<Isochronous, Out>());
+        assert!(ep.into_typed::<Isochronous, In>().is_err());
+    }
+}
@@ -1,13 +1,19 @@
+#[cfg(any(kmod, umod, nmod, mmod))]
 use alloc::boxed::Box;
+use alloc::vec::Vec;
 use core::any::Any;
 use core::fmt::Debug;
 
 use futures::future::BoxFuture;
-use usb_if::descriptor::{ConfigurationDescriptor, DeviceDescriptor, EndpointDescriptor};
+use usb_if::descriptor::{
+    ConfigurationDescriptor, DeviceDescriptor, DeviceQualifierDescriptor, EndpointDescriptor,
+};
 
-use crate::{backend::ty::ep::Endpoint, err::USBError};
+use crate::{backend::ty::ep::Endpoint, device::DeviceLocation, err::USBError};
 
 pub mod ep;
+pub(crate) mod timer;
+#[cfg(any(kmod, umod, nmod))]
 pub mod transfer;
 
 #[derive(Debug, Clone)]
@@ -19,6 +25,19 @@ pub enum Event {
 
 pub(crate) trait EventHandlerOp: Send + Any + Sync + 'static {
     fn handle_event(&self) -> Event;
+
+    /// 只处理第 `index` 个中断器，只有一个中断器的控制器等同于 `handle_event`
+    fn handle_interrupter(&self, index: u16) -> Event {
+        if index == 0 {
+            self.handle_event()
+        } else {
+            Event::Nothing
+        }
+    }
+
+    fn interrupter_count(&self) -> u16 {
+        1
+    }
 }
 
 #[allow(dead_code)]
@@ -27,13 +46,39 @@ pub(crate) trait DeviceInfoOp: Send + Sync + Any + Debug + 'static {
     fn backend_name(&self) -> &str;
     fn descriptor(&self) -> &DeviceDescriptor;
     fn configuration_descriptors(&self) -> &[ConfigurationDescriptor];
+
+    fn location(&self) -> DeviceLocation;
+
+    fn device_qualifier(&self) -> Option<&DeviceQualifierDescriptor> {
+        None
+    }
+
+    fn other_speed_configurations(&self) -> &[ConfigurationDescriptor] {
+        &[]
+    }
+
+    /// 设备已被弹出，信息不再有效
+    fn is_stale(&self) -> bool {
+        false
+    }
 }
 
 pub(crate) enum ProbedDeviceInfoOp {
+    #[cfg(any(kmod, umod, nmod, mmod))]
     Device(Box<dyn DeviceInfoOp>),
+    #[cfg(any(kmod, umod, nmod, mmod))]
     Hub(Box<dyn DeviceInfoOp>),
 }
 
+pub(crate) enum HotplugEventOp {
+    #[cfg(any(kmod, umod, nmod, mmod))]
+    Attached(ProbedDeviceInfoOp),
+    #[cfg(any(kmod, umod, nmod, mmod))]
+    Detached { id: usize },
+    #[cfg(kmod)]
+    RemoteWakeup { id: usize },
+}
+
 /// USB 设备特征（高层抽象）
 pub(crate) trait DeviceOp: Send + Any + 'static {
     fn id(&self) -> usize;
@@ -41,6 +86,17 @@ pub(crate) trait DeviceOp: Send + Any + 'static {
     fn descriptor(&self) -> &DeviceDescriptor;
     fn configuration_descriptors(&self) -> &[ConfigurationDescriptor];
 
+    fn device_qualifier(&self) -> Option<&DeviceQualifierDescriptor> {
+        None
+    }
+
+    fn other_speed_configurations(&self) -> &[ConfigurationDescriptor] {
+        &[]
+    }
+
+    /// 替换缓存的设备描述符与配置描述符，用于设备原地切换模式之后
+    fn set_descriptors(&mut self, desc: DeviceDescriptor, configs: Vec<ConfigurationDescriptor>);
+
     fn ctrl_ep_ref(&self) -> &Endpoint;
 
     fn ctrl_ep_mut(&mut self) -> &mut Endpoint;
@@ -51,6 +107,13 @@ pub(crate) trait DeviceOp: Send + Any + 'static {
         alternate: u8,
     ) -> BoxFuture<'a, Result<(), USBError>>;
 
+    /// 释放接口并停用其当前备用设置的端点，调用前端点上的请求应已全部取消
+    fn release_interface<'a>(
+        &'a mut self,
+        interface: u8,
+        alternate: u8,
+    ) -> BoxFuture<'a, Result<(), USBError>>;
+
     fn set_configuration<'a>(
         &'a mut self,
         configuration_value: u8,
@@ -59,6 +122,33 @@ pub(crate) trait DeviceOp: Send + Any + 'static {
     fn endpoint(&mut self, desc: &EndpointDescriptor) -> Result<ep::Endpoint, USBError>;
 
     fn update_hub(&mut self, params: HubParams) -> BoxFuture<'_, Result<(), USBError>>;
+
+    /// 设置此后提交的传输使用的 DMA 约束
+    #[cfg(kmod)]
+    fn set_dma_config(&mut self, _config: crate::backend::kmod::DmaConfig) -> Result<(), USBError> {
+        Err(USBError::NotSupported)
+    }
+
+    /// 声明接口时卸载已绑定的内核驱动，释放接口时重新绑定
+    #[cfg(any(umod, nmod))]
+    fn set_auto_detach_kernel_driver(&mut self, _enable: bool) -> Result<(), USBError> {
+        Err(USBError::NotSupported)
+    }
+
+    #[cfg(any(umod, nmod))]
+    fn kernel_driver_active(&self, _interface: u8) -> Result<bool, USBError> {
+        Err(USBError::NotSupported)
+    }
+
+    #[cfg(any(umod, nmod))]
+    fn detach_kernel_driver(&mut self, _interface: u8) -> Result<(), USBError> {
+        Err(USBError::NotSupported)
+    }
+
+    #[cfg(any(umod, nmod))]
+    fn attach_kernel_driver(&mut self, _interface: u8) -> Result<(), USBError> {
+        Err(USBError::NotSupported)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,35 @@
+//! 软件定时的等待
+//!
+//! 周期端点的提交间隔、请求超时与重试退避都要等到某个时刻。时钟与到期唤醒由后端提供：
+//! 内核后端使用 [`crate::KernelOp::wake_at`]，用户空间后端使用共享的定时线程。
+
+use core::{
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+pub(crate) trait Timer {
+    /// 单调时钟，用于软件定时
+    fn now(&self) -> Duration;
+
+    /// 时钟到达 `deadline` 后唤醒 `waker`，`deadline` 已过时立即唤醒
+    fn wake_at(&self, deadline: Duration, waker: &Waker);
+}
+
+/// 时钟到达 `deadline` 时返回 `Ready`，否则登记到期唤醒后返回 `Pending`
+pub(crate) fn poll_until<T: Timer + ?Sized>(
+    timer: &T,
+    deadline: Duration,
+    cx: &mut Context<'_>,
+) -> Poll<()> {
+    if timer.now() >= deadline {
+        return Poll::Ready(());
+    }
+    timer.wake_at(deadline, cx.waker());
+    Poll::Pending
+}
+
+/// 等待时钟到达 `deadline`
+pub(crate) async fn sleep_until<T: Timer + ?Sized>(timer: &T, deadline: Duration) {
+    core::future::poll_fn(|cx| poll_until(timer, deadline, cx)).await
+}
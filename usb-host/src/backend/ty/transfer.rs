@@ -1,15 +1,18 @@
 use alloc::vec::Vec;
 
 pub use usb_if::endpoint::TransferKind;
+use usb_if::endpoint::TransferStatus;
 
-#[cfg_attr(umod, derive(Clone))]
+#[cfg_attr(any(umod, nmod), derive(Clone))]
 pub struct Transfer {
     pub kind: TransferKind,
     pub direction: usb_if::transfer::Direction,
     #[cfg(kmod)]
-    pub mapping: Option<dma_api::SArrayPtr<u8>>,
-    #[cfg(umod)]
+    pub mapping: Option<crate::backend::kmod::transfer::TransferDma>,
+    #[cfg(any(umod, nmod))]
     pub buffer: Option<(std::ptr::NonNull<u8>, usize)>,
     pub transfer_len: usize,
     pub iso_packet_actual_lengths: Vec<usize>,
+    /// 每个等时包的完成状态，为空表示全部成功
+    pub iso_packet_status: Vec<TransferStatus>,
 }
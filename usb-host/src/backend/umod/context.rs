@@ -18,12 +18,20 @@ impl Context {
     pub fn device_list(&self) -> crate::err::Result<DeviceList> {
         let mut list: *const *mut libusb_device = std::ptr::null_mut();
         let count = unsafe { libusb1_sys::libusb_get_device_list(self.0, &mut list) };
+        // 失败时返回负的错误码
+        if count < 0 {
+            super::err::libusb_error_to_usb_error(count as i32)?;
+        }
         Ok(DeviceList {
             list,
             len: count as usize,
         })
     }
 
+    pub fn raw(&self) -> *mut libusb_context {
+        self.0
+    }
+
     pub fn handle_events(&self) -> Result<()> {
         usb!(libusb1_sys::libusb_handle_events(self.0))?;
         Ok(())
@@ -2,15 +2,17 @@ use core::{mem::MaybeUninit, num::NonZero};
 use std::{fmt::Debug, sync::Arc};
 
 use futures::FutureExt;
-use libusb1_sys::*;
+use libusb1_sys::{constants::*, *};
 use usb_if::descriptor::{
     ConfigurationDescriptor, DeviceDescriptor, InterfaceDescriptor, InterfaceDescriptors,
 };
 use usb_if::endpoint::EndpointInfo;
+use usb_if::host::hub::Speed;
 
 use super::{context::Context, endpoint::EndpointImpl};
-use crate::backend::ty::ep::Endpoint;
+use crate::backend::ty::ep::{Endpoint, declared_interval};
 use crate::backend::ty::{DeviceInfoOp, DeviceOp};
+use crate::device::DeviceLocation;
 use crate::err::*;
 
 pub struct DeviceInfo {
@@ -44,6 +46,13 @@ impl DeviceInfo {
     }
 }
 
+/// 以总线号与设备地址组成设备编号，设备拔出后仍可用于匹配热插拔事件
+pub(crate) fn device_id(raw: *mut libusb_device) -> usize {
+    let bus = unsafe { libusb_get_bus_number(raw) } as usize;
+    let address = unsafe { libusb_get_device_address(raw) } as usize;
+    (bus << 8) | address
+}
+
 impl Drop for DeviceInfo {
     fn drop(&mut self) {
         unsafe {
@@ -54,7 +63,7 @@ impl Drop for DeviceInfo {
 
 impl DeviceInfoOp for DeviceInfo {
     fn id(&self) -> usize {
-        0
+        device_id(self.raw)
     }
 
     fn backend_name(&self) -> &str {
@@ -68,6 +77,17 @@ impl DeviceInfoOp for DeviceInfo {
     fn configuration_descriptors(&self) -> &[ConfigurationDescriptor] {
         &self.configs
     }
+
+    fn location(&self) -> DeviceLocation {
+        // USB 3.0 规定拓扑最多 7 级
+        let mut path = [0u8; 7];
+        let len = unsafe { libusb_get_port_numbers(self.raw, path.as_mut_ptr(), path.len() as _) };
+        DeviceLocation {
+            bus: unsafe { libusb_get_bus_number(self.raw) },
+            port_path: path[..len.max(0) as usize].to_vec(),
+            address: unsafe { libusb_get_device_address(self.raw) } as usize,
+        }
+    }
 }
 
 fn libusb_get_configuration_descriptors(
@@ -113,10 +133,17 @@ fn libusb_get_configuration_descriptors(
                     _ => unreachable!(),
                 };
 
+                let extra = extra_bytes(ep_desc.extra, ep_desc.extra_length);
                 let packets_per_microframe = match transfer_type {
                     usb_if::descriptor::EndpointType::Isochronous
                     | usb_if::descriptor::EndpointType::Interrupt => {
-                        (((ep_desc.wMaxPacketSize >> 11) & 0x03) + 1) as usize
+                        // SuperSpeed 端点的 wMaxPacketSize 高位保留，每个服务间隔的包数由配套描述符给出
+                        ss_packets_per_interval(
+                            transfer_type,
+                            ep_desc.wMaxPacketSize & 0x7FF,
+                            &extra,
+                        )
+                        .unwrap_or((((ep_desc.wMaxPacketSize >> 11) & 0x03) + 1) as usize)
                     }
                     _ => 1,
                 };
@@ -128,6 +155,8 @@ fn libusb_get_configuration_descriptors(
                     direction,
                     packets_per_microframe,
                     interval: ep_desc.bInterval,
+                    max_streams: 0,
+                    extra,
                 });
             }
 
@@ -141,6 +170,7 @@ fn libusb_get_configuration_descriptors(
                 string: None,
                 num_endpoints: alt_desc.bNumEndpoints,
                 endpoints,
+                extra: extra_bytes(alt_desc.extra, alt_desc.extra_length),
             });
         }
 
@@ -170,10 +200,59 @@ fn libusb_get_configuration_descriptors(
     Ok(out)
 }
 
+/// SuperSpeed 周期端点每个服务间隔的包数：(bMaxBurst + 1) × 等时端点的 (Mult + 1)
+///
+/// 带 SuperSpeedPlus 等时配套描述符的端点按其 dwBytesPerInterval 与 `max_packet_size` 计算。
+fn ss_packets_per_interval(
+    transfer_type: usb_if::descriptor::EndpointType,
+    max_packet_size: u16,
+    extra: &[u8],
+) -> Option<usize> {
+    use usb_if::descriptor::DescriptorType;
+
+    // 逐个取出描述符，遇到长度非法的描述符时停止
+    let mut rest = extra;
+    let mut descriptors = core::iter::from_fn(|| {
+        let len = *rest.first()? as usize;
+        if len < 2 || len > rest.len() {
+            return None;
+        }
+        let (desc, tail) = rest.split_at(len);
+        rest = tail;
+        Some(desc)
+    })
+    .skip_while(|d| d.len() < 4 || d[1] != DescriptorType::SUPERSPEED_USB_ENDPOINT_COMPANION.0);
+
+    let companion = descriptors.next()?;
+    let burst = companion[2] as usize + 1;
+    if transfer_type != usb_if::descriptor::EndpointType::Isochronous {
+        return Some(burst);
+    }
+    // bmAttributes 第 7 位表示紧随其后有 SuperSpeedPlus 等时配套描述符，此时 Mult 无效
+    if companion[3] & 0x80 != 0
+        && let Some(ssp) = descriptors.next()
+        && ssp.len() >= 8
+        && ssp[1] == DescriptorType::SUPERSPEEDPLUS_ISOCHRONOUS_ENDPOINT_COMPANION.0
+    {
+        let bytes = u32::from_le_bytes([ssp[4], ssp[5], ssp[6], ssp[7]]) as usize;
+        return Some(bytes.div_ceil(max_packet_size.max(1) as usize).max(1));
+    }
+    Some(burst * ((companion[3] & 0x03) as usize + 1))
+}
+
+/// libusb 保存在 `extra` 中的、标准描述符之后的其他描述符
+fn extra_bytes(ptr: *const u8, len: i32) -> Vec<u8> {
+    if ptr.is_null() || len <= 0 {
+        return Vec::new();
+    }
+    unsafe { std::slice::from_raw_parts(ptr, len as usize) }.to_vec()
+}
+
 pub struct Device {
     handle: Arc<DeviceHandle>,
     desc: DeviceDescriptor,
     configs: Vec<ConfigurationDescriptor>,
+    speed: Speed,
     ctrl_ep: Endpoint,
 }
 
@@ -187,12 +266,27 @@ impl Device {
 
         let desc = info.desc.clone();
         let configs = info.configs.clone();
+        let speed = match unsafe { libusb_get_device_speed(raw) } {
+            LIBUSB_SPEED_LOW => Speed::Low,
+            LIBUSB_SPEED_HIGH => Speed::High,
+            LIBUSB_SPEED_SUPER => Speed::SuperSpeed,
+            LIBUSB_SPEED_SUPER_PLUS => Speed::SuperSpeedPlus,
+            _ => Speed::Full,
+        };
 
         let handle = Arc::new(DeviceHandle {
             raw: handle,
             _ctx: ctx,
         });
 
+        // 默认自动卸载内核驱动，usbhid、uvcvideo 等已绑定的接口也能直接声明；
+        // Windows 与 macOS 不支持，接口由 WinUSB 等驱动提供时可直接声明
+        if let Err(e) = usb!(libusb_set_auto_detach_kernel_driver(handle.raw(), 1))
+            && !e.is_not_supported()
+        {
+            return Err(e.into());
+        }
+
         // 创建控制端点（endpoint address 0）
         let ctrl_ep_impl = EndpointImpl::new(handle.clone(), 0);
         let ctrl_ep = Endpoint::new(EndpointInfo::control(), ctrl_ep_impl);
@@ -201,28 +295,23 @@ impl Device {
             handle,
             desc,
             configs,
+            speed,
             ctrl_ep,
         })
     }
 
     async fn _claim_interface(&mut self, interface: u8, alternate: u8) -> Result<()> {
-        let res = usb!(libusb_kernel_driver_active(
-            self.handle.raw(),
-            interface as _
-        ))?;
-
-        if res == 1 {
-            usb!(libusb_detach_kernel_driver(
-                self.handle.raw(),
-                interface as _
-            ))?;
-            debug!("Kernel driver detached for interface {interface}");
-        }
-
+        // 启用自动卸载时由 libusb 在声明前卸载内核驱动、释放后重新绑定
         usb!(libusb_claim_interface(self.handle.raw(), interface as _))?;
 
         debug!("Interface {interface} claimed successfully");
-        if alternate != 0 {
+        // 接口有备用设置时总是显式选择，从流设置切回 0 时才会释放带宽
+        let has_alternates = self
+            .configs
+            .iter()
+            .flat_map(|c| c.interfaces.iter())
+            .any(|i| i.interface_number == interface && i.alt_settings.len() > 1);
+        if alternate != 0 || has_alternates {
             usb!(libusb_set_interface_alt_setting(
                 self.handle.raw(),
                 interface as _,
@@ -251,6 +340,11 @@ impl DeviceOp for Device {
         &self.configs
     }
 
+    fn set_descriptors(&mut self, desc: DeviceDescriptor, configs: Vec<ConfigurationDescriptor>) {
+        self.desc = desc;
+        self.configs = configs;
+    }
+
     fn ctrl_ep_ref(&self) -> &Endpoint {
         &self.ctrl_ep
     }
@@ -267,16 +361,42 @@ impl DeviceOp for Device {
         async move { self._claim_interface(interface, alternate).await }.boxed()
     }
 
+    fn release_interface<'a>(
+        &'a mut self,
+        interface: u8,
+        _alternate: u8,
+    ) -> futures::future::BoxFuture<'a, std::result::Result<(), USBError>> {
+        async move {
+            usb!(libusb_release_interface(self.handle.raw(), interface as _))?;
+            debug!("Interface {interface} released");
+            Ok(())
+        }
+        .boxed()
+    }
+
     fn set_configuration<'a>(
         &'a mut self,
         configuration_value: u8,
     ) -> futures::future::BoxFuture<'a, std::result::Result<(), USBError>> {
         async move {
-            usb!(libusb_set_configuration(
+            let res = usb!(libusb_set_configuration(
                 self.handle.raw(),
                 configuration_value as _
-            ))?;
-            Ok(())
+            ));
+            match res {
+                Ok(_) => Ok(()),
+                // Windows 不支持切换配置，已处于请求的配置时视为成功
+                Err(e) if e.is_not_supported() => {
+                    let mut active = 0;
+                    usb!(libusb_get_configuration(self.handle.raw(), &mut active))?;
+                    if active == configuration_value as i32 {
+                        Ok(())
+                    } else {
+                        Err(USBError::NotSupported)
+                    }
+                }
+                Err(e) => Err(e.into()),
+            }
         }
         .boxed()
     }
@@ -286,7 +406,47 @@ impl DeviceOp for Device {
         desc: &usb_if::descriptor::EndpointDescriptor,
     ) -> std::result::Result<Endpoint, USBError> {
         let ep = EndpointImpl::new(self.handle.clone(), desc.address);
-        Ok(Endpoint::new(EndpointInfo::from(desc), ep))
+        // 由内核调度，按设备声明的周期估计
+        let interval = declared_interval(self.speed, desc.transfer_type, desc.interval);
+        Ok(Endpoint::new(EndpointInfo::from(desc), ep).with_service_interval(interval))
+    }
+
+    fn set_auto_detach_kernel_driver(&mut self, enable: bool) -> std::result::Result<(), USBError> {
+        usb!(libusb_set_auto_detach_kernel_driver(
+            self.handle.raw(),
+            enable as _
+        ))
+        .map_err(kernel_driver_error)?;
+        Ok(())
+    }
+
+    fn kernel_driver_active(&self, interface: u8) -> std::result::Result<bool, USBError> {
+        let res = usb!(libusb_kernel_driver_active(
+            self.handle.raw(),
+            interface as _
+        ))
+        .map_err(kernel_driver_error)?;
+        Ok(res == 1)
+    }
+
+    fn detach_kernel_driver(&mut self, interface: u8) -> std::result::Result<(), USBError> {
+        usb!(libusb_detach_kernel_driver(
+            self.handle.raw(),
+            interface as _
+        ))
+        .map_err(kernel_driver_error)?;
+        debug!("Kernel driver detached for interface {interface}");
+        Ok(())
+    }
+
+    fn attach_kernel_driver(&mut self, interface: u8) -> std::result::Result<(), USBError> {
+        usb!(libusb_attach_kernel_driver(
+            self.handle.raw(),
+            interface as _
+        ))
+        .map_err(kernel_driver_error)?;
+        debug!("Kernel driver attached for interface {interface}");
+        Ok(())
     }
 
     fn update_hub(
@@ -341,3 +501,107 @@ impl DeviceHandle {
         self.raw
     }
 }
+
+/// 内核驱动操作只在 Linux 上可用，其他平台返回 NotSupported
+fn kernel_driver_error(err: super::err::LibUsbErr) -> USBError {
+    if err.is_not_supported() {
+        USBError::NotSupported
+    } else {
+        err.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use usb_if::descriptor::EndpointType;
+
+    use super::*;
+
+    /// SuperSpeed 配套描述符：bMaxBurst、bmAttributes、wBytesPerInterval
+    fn companion(max_burst: u8, attributes: u8, bytes: u16) -> Vec<u8> {
+        let [lo, hi] = bytes.to_le_bytes();
+        vec![6, 0x30, max_burst, attributes, lo, hi]
+    }
+
+    #[test]
+    fn superspeed_isoch_burst_and_mult() {
+        // 16 个突发 × 3 次，每个服务间隔 48 包
+        let extra = companion(15, 0x02, 48 * 1024);
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Isochronous, 1024, &extra),
+            Some(48)
+        );
+        // 中断端点没有 Mult
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Interrupt, 1024, &extra),
+            Some(16)
+        );
+    }
+
+    #[test]
+    fn companion_after_class_descriptor() {
+        let mut extra = vec![7, 0x25, 0x01, 0x00, 0x00, 0x00, 0x00];
+        extra.extend(companion(1, 0x01, 4096));
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Isochronous, 1024, &extra),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn superspeedplus_isoch_companion() {
+        // 96 KiB 每服务间隔，超出 SuperSpeed 最多 48 包的限制
+        let mut extra = companion(15, 0x80, 0);
+        extra.extend([8, 0x31, 0, 0]);
+        extra.extend((96 * 1024u32).to_le_bytes());
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Isochronous, 1024, &extra),
+            Some(96)
+        );
+
+        // 不足一整包时向上取整
+        let len = extra.len();
+        extra[len - 4..].copy_from_slice(&1500u32.to_le_bytes());
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Isochronous, 1024, &extra),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn missing_superspeedplus_companion_falls_back_to_burst() {
+        let extra = companion(3, 0x80, 0);
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Isochronous, 1024, &extra),
+            Some(4)
+        );
+        // 截断的 SuperSpeedPlus 配套描述符同样忽略
+        let mut truncated = extra.clone();
+        truncated.extend([8, 0x31, 0, 0]);
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Isochronous, 1024, &truncated),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn malformed_or_missing_companion() {
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Isochronous, 1024, &[]),
+            None
+        );
+        // bLength 为 0 或超出剩余字节时停止解析
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Isochronous, 1024, &[0, 0x30, 1, 0]),
+            None
+        );
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Isochronous, 1024, &[9, 0x30, 1, 0]),
+            None
+        );
+        assert_eq!(
+            ss_packets_per_interval(EndpointType::Interrupt, 1024, &[4, 0x24, 0, 0]),
+            None
+        );
+    }
+}
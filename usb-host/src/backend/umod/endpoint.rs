@@ -2,24 +2,31 @@ use std::{
     collections::HashMap,
     ptr::null_mut,
     sync::{Arc, Weak, atomic::AtomicBool},
+    task::{Poll, Waker},
+    time::Duration,
 };
 
-use futures::task::AtomicWaker;
+use futures::{future::BoxFuture, task::AtomicWaker};
 use libusb1_sys::{
-    libusb_cancel_transfer, libusb_control_transfer_get_data, libusb_fill_bulk_transfer,
-    libusb_fill_control_setup, libusb_fill_control_transfer, libusb_fill_iso_transfer,
-    libusb_submit_transfer, libusb_transfer,
+    libusb_cancel_transfer, libusb_clear_halt, libusb_control_transfer_get_data,
+    libusb_fill_bulk_transfer, libusb_fill_control_transfer, libusb_fill_interrupt_transfer,
+    libusb_fill_iso_transfer, libusb_submit_transfer, libusb_transfer,
 };
 use log::trace;
 use usb_if::{
     endpoint::{RequestId, TransferCompletion, TransferRequest},
     err::TransferError,
-    transfer::{BmRequestType, Direction},
+    host::ControlSetup,
+    transfer::Direction,
 };
 
-use super::{device::DeviceHandle, err::transfer_status_to_result};
+use super::{
+    device::DeviceHandle,
+    err::{iso_packet_status, transfer_status_to_result},
+};
 use crate::backend::ty::{
     ep::{EndpointOp, transfer_to_completion},
+    timer::Timer,
     transfer::{Transfer, TransferKind},
 };
 
@@ -27,14 +34,20 @@ pub struct EndpointImpl {
     dev: Arc<DeviceHandle>,
     address: u8,
     transfers: HashMap<u64, Arc<TransferHandleRaw>>,
+    /// libusb 传输超时，毫秒
+    timeout: u32,
 }
 
+/// 未设置超时时 libusb 传输的超时，毫秒
+const DEFAULT_TIMEOUT_MS: u32 = 1000;
+
 impl EndpointImpl {
     pub fn new(dev: Arc<DeviceHandle>, address: u8) -> Self {
         Self {
             dev,
             address,
             transfers: HashMap::new(),
+            timeout: DEFAULT_TIMEOUT_MS,
         }
     }
 
@@ -59,7 +72,7 @@ impl EndpointImpl {
         let direction = transfer.direction;
         let mut buffer = null_mut();
         let data_len;
-        let timeout = 1000; // TODO: make it configurable
+        let timeout = self.timeout;
 
         if let Some((buff_ptr, buff_len)) = transfer.buffer {
             buffer = buff_ptr.as_ptr();
@@ -68,12 +81,10 @@ impl EndpointImpl {
             data_len = 0;
         }
 
-        // 判断是否为控制传输
-        let temp_buff = if matches!(transfer.kind, TransferKind::Control(_)) {
-            let total_len = 8 + data_len;
-            vec![0u8; total_len]
-        } else {
-            vec![]
+        // 控制传输的 setup 包与数据共用一块缓冲区
+        let temp_buff = match &transfer.kind {
+            TransferKind::Control(setup) => control_buffer(setup, direction, data_len),
+            _ => vec![],
         };
 
         let temp_buff_ptr = temp_buff.as_ptr() as *mut u8;
@@ -91,7 +102,7 @@ impl EndpointImpl {
         let user_data = Weak::into_raw(weak) as *mut core::ffi::c_void;
 
         match &trans_handle.origin.kind {
-            TransferKind::Control(setup) => {
+            TransferKind::Control(_) => {
                 unsafe {
                     buffer = temp_buff_ptr;
 
@@ -103,16 +114,6 @@ impl EndpointImpl {
                         core::ptr::copy_nonoverlapping(ptr.as_ptr(), buffer.add(8), data_len);
                     }
 
-                    // 填充 setup 包到 buffer[0..8]
-                    libusb_fill_control_setup(
-                        buffer,
-                        BmRequestType::new(direction, setup.request_type, setup.recipient).into(),
-                        setup.request.into(),
-                        setup.value,
-                        setup.index,
-                        data_len as _, // wLength = 数据长度
-                    );
-
                     libusb_fill_control_transfer(
                         trans_ptr,
                         dev_handle,
@@ -139,7 +140,7 @@ impl EndpointImpl {
             }
             TransferKind::Interrupt => {
                 unsafe {
-                    libusb_fill_bulk_transfer(
+                    libusb_fill_interrupt_transfer(
                         trans_ptr,
                         dev_handle,
                         self.address,
@@ -184,6 +185,16 @@ impl EndpointImpl {
 
 unsafe impl Send for EndpointImpl {}
 
+impl Timer for EndpointImpl {
+    fn now(&self) -> Duration {
+        crate::backend::std_timer::now()
+    }
+
+    fn wake_at(&self, deadline: Duration, waker: &Waker) {
+        crate::backend::std_timer::wake_at(deadline, waker)
+    }
+}
+
 impl EndpointOp for EndpointImpl {
     fn submit_request(
         &mut self,
@@ -196,6 +207,7 @@ impl EndpointOp for EndpointImpl {
             buffer: buffer.map(|buffer| (buffer.ptr, buffer.len)),
             transfer_len: 0,
             iso_packet_actual_lengths: Vec::new(),
+            iso_packet_status: Vec::new(),
         };
         let trans = self.make_transfer(transfer)?;
         let id = trans.id();
@@ -234,19 +246,66 @@ impl EndpointOp for EndpointImpl {
         }
     }
 
-    fn cancel_request(&mut self, id: RequestId) -> Result<(), usb_if::err::TransferError> {
-        let trans = self
-            .transfers
-            .get(&id.raw())
-            .ok_or(TransferError::InvalidEndpoint)?;
-        let res = unsafe { libusb_cancel_transfer(trans.transfer) };
-        if res == libusb1_sys::constants::LIBUSB_SUCCESS as i32 {
-            Ok(())
-        } else {
-            Err(TransferError::Other(anyhow!(
-                "Failed to cancel transfer: libusb error {res}"
-            )))
+    fn pending_requests(&self) -> Vec<RequestId> {
+        self.transfers.keys().copied().map(RequestId::new).collect()
+    }
+
+    fn set_timeout(&mut self, timeout: Option<Duration>) {
+        // libusb 中 0 表示不超时，向上取整到至少 1ms
+        self.timeout = timeout.map_or(DEFAULT_TIMEOUT_MS, |t| {
+            t.as_millis().clamp(1, u32::MAX as u128) as u32
+        });
+    }
+
+    fn cancel_request(
+        &mut self,
+        id: RequestId,
+    ) -> BoxFuture<'_, Result<Option<TransferCompletion>, TransferError>> {
+        Box::pin(async move {
+            let trans = self
+                .transfers
+                .get(&id.raw())
+                .ok_or(TransferError::InvalidEndpoint)?;
+            let res = unsafe { libusb_cancel_transfer(trans.transfer) };
+            // 传输已经完成时返回 NOT_FOUND，照常回收
+            if res != libusb1_sys::constants::LIBUSB_SUCCESS
+                && res != libusb1_sys::constants::LIBUSB_ERROR_NOT_FOUND
+            {
+                return Err(TransferError::Other(anyhow!(
+                    "Failed to cancel transfer: libusb error {res}"
+                )));
+            }
+
+            // 回调触发后 libusb 不再访问缓冲区
+            let res = std::future::poll_fn(|cx| {
+                if let Some(res) = self.reclaim_request(id) {
+                    return Poll::Ready(res);
+                }
+                self.register_waker(id, cx);
+                match self.reclaim_request(id) {
+                    Some(res) => Poll::Ready(res),
+                    None => Poll::Pending,
+                }
+            })
+            .await;
+            match res {
+                Ok(completion) => Ok(Some(completion)),
+                Err(TransferError::Cancelled) => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn reset_halt(&mut self) -> BoxFuture<'_, Result<(), TransferError>> {
+        // 端点 0 的协议 STALL 由操作系统恢复
+        if self.address & 0x0f == 0 {
+            return Box::pin(async { Ok(()) });
         }
+        // libusb 无法只复位主机侧，会再次向设备发送 CLEAR_FEATURE(ENDPOINT_HALT)
+        let res = usb!(libusb_clear_halt(self.dev.raw(), self.address))
+            .map(|_| ())
+            .map_err(|e| TransferError::Other(anyhow!("Failed to clear halt: {e:?}")));
+        Box::pin(async move { res })
     }
 }
 
@@ -287,11 +346,15 @@ impl TransferHandleRaw {
         out.transfer_len = trans_raw.actual_length as usize;
         if let TransferKind::Isochronous { packet_lengths } = &self.origin.kind {
             out.iso_packet_actual_lengths = Vec::with_capacity(packet_lengths.len());
+            out.iso_packet_status = Vec::with_capacity(packet_lengths.len());
             for i in 0..trans_raw.num_iso_packets as usize {
                 let packet = unsafe { &*trans_raw.iso_packet_desc.as_ptr().add(i) };
                 out.iso_packet_actual_lengths
                     .push(packet.actual_length as usize);
+                out.iso_packet_status.push(iso_packet_status(packet.status));
             }
+            // libusb 不填写等时传输的 actual_length，以各包实际长度之和代替
+            out.transfer_len = out.iso_packet_actual_lengths.iter().sum();
         }
         Ok(out)
     }
@@ -310,6 +373,13 @@ impl Drop for TransferHandleRaw {
     }
 }
 
+/// libusb 控制传输的缓冲区：前 8 字节为 setup 包（wLength 为数据长度），之后是数据阶段
+fn control_buffer(setup: &ControlSetup, direction: Direction, data_len: usize) -> Vec<u8> {
+    let mut buff = vec![0u8; 8 + data_len];
+    buff[..8].copy_from_slice(&setup.to_bytes(direction, data_len as _));
+    buff
+}
+
 extern "system" fn transfer_callback(transfer: *mut libusb_transfer) {
     let user_data = unsafe { (*transfer).user_data };
     if user_data.is_null() {
@@ -327,3 +397,22 @@ extern "system" fn transfer_callback(transfer: *mut libusb_transfer) {
         trans_handle.waker.wake();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use usb_if::transfer::Recipient;
+
+    use super::*;
+
+    #[test]
+    fn vendor_request_reaches_setup_packet() {
+        // 0x06 与标准 GET_DESCRIPTOR 重叠，厂商请求不能被当作标准请求映射
+        let setup = ControlSetup::vendor(Recipient::Device, 0x06, 0x1234, 0x0002);
+        let buff = control_buffer(&setup, Direction::In, 4);
+        assert_eq!(buff, [0xc0, 0x06, 0x34, 0x12, 0x02, 0x00, 4, 0, 0, 0, 0, 0]);
+
+        let setup = ControlSetup::vendor(Recipient::Interface, 0xa5, 0, 1);
+        let buff = control_buffer(&setup, Direction::Out, 0);
+        assert_eq!(buff, [0x41, 0xa5, 0, 0, 0x01, 0x00, 0, 0]);
+    }
+}
@@ -1,7 +1,10 @@
 use core::fmt::Display;
 
 use libusb1_sys::constants::*;
-use usb_if::err::{TransferError, USBError};
+use usb_if::{
+    endpoint::TransferStatus,
+    err::{TransferError, USBError},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct LibUsbErr {
@@ -32,6 +35,13 @@ pub(crate) fn libusb_error_to_usb_error(code: i32) -> Result<i32, LibUsbErr> {
     Err(LibUsbErr { code, msg })
 }
 
+impl LibUsbErr {
+    /// Windows 与 macOS 上查询、卸载内核驱动等操作返回此错误
+    pub fn is_not_supported(&self) -> bool {
+        self.code == LIBUSB_ERROR_NOT_SUPPORTED
+    }
+}
+
 impl Display for LibUsbErr {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "LibUSB error {}: {}", self.code, self.msg)
@@ -46,6 +56,13 @@ impl From<LibUsbErr> for USBError {
             LIBUSB_ERROR_NOT_FOUND => USBError::NotFound,
             LIBUSB_ERROR_TIMEOUT => USBError::Timeout,
             LIBUSB_ERROR_NO_MEM => USBError::NoMemory,
+            // Windows 上设备未绑定 WinUSB 等 libusb 可用的驱动时打开或声明接口返回此错误
+            #[cfg(windows)]
+            LIBUSB_ERROR_NOT_SUPPORTED => USBError::Other(anyhow!(
+                "LibUSB error {}: {} (bind WinUSB to the device or interface, e.g. with Zadig)",
+                err.code,
+                err.msg
+            )),
             _ => USBError::Other(anyhow!("LibUSB error {}: {}", err.code, err.msg)),
         }
     }
@@ -65,6 +82,18 @@ pub(crate) fn transfer_status_to_result(status: i32) -> Result<(), TransferError
     }
 }
 
+/// 等时包的完成状态转换为包状态
+pub(crate) fn iso_packet_status(status: i32) -> TransferStatus {
+    match status {
+        LIBUSB_TRANSFER_COMPLETED => TransferStatus::Completed,
+        // usbfs 将错过服务周期（-EXDEV）报告为 LIBUSB_TRANSFER_ERROR
+        LIBUSB_TRANSFER_ERROR => TransferStatus::Missed,
+        LIBUSB_TRANSFER_STALL => TransferStatus::Stalled,
+        LIBUSB_TRANSFER_CANCELLED => TransferStatus::Cancelled,
+        _ => TransferStatus::Error,
+    }
+}
+
 macro_rules! usb {
     ($e:expr) => {
         unsafe { crate::backend::umod::err::libusb_error_to_usb_error($e) }
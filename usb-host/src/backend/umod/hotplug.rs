@@ -0,0 +1,108 @@
+use core::{
+    ffi::{c_int, c_void},
+    task::{Context, Poll},
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use futures::task::AtomicWaker;
+use libusb1_sys::{constants::*, *};
+
+use super::{context, device};
+use crate::backend::ty::{DeviceInfoOp, HotplugEventOp, ProbedDeviceInfoOp};
+use crate::err::*;
+
+/// libusb 热插拔回调写入、异步任务读取的事件队列
+#[derive(Default)]
+struct Queue {
+    events: Mutex<VecDeque<HotplugEventOp>>,
+    waker: AtomicWaker,
+}
+
+/// 已注册的 libusb 热插拔回调，释放时注销
+pub struct Hotplug {
+    ctx: Arc<context::Context>,
+    handle: libusb_hotplug_callback_handle,
+    /// 回调的 user_data 指向此队列，注销前必须保持有效
+    queue: Arc<Queue>,
+}
+
+impl Hotplug {
+    /// 注册插入与拔出回调，不报告注册前已连接的设备
+    pub fn register(ctx: Arc<context::Context>) -> Result<Self> {
+        if unsafe { libusb_has_capability(LIBUSB_CAP_HAS_HOTPLUG) } == 0 {
+            return Err(USBError::NotSupported);
+        }
+        let queue = Arc::new(Queue::default());
+        let mut handle = 0;
+        usb!(libusb_hotplug_register_callback(
+            ctx.raw(),
+            LIBUSB_HOTPLUG_EVENT_DEVICE_ARRIVED | LIBUSB_HOTPLUG_EVENT_DEVICE_LEFT,
+            LIBUSB_HOTPLUG_NO_FLAGS,
+            LIBUSB_HOTPLUG_MATCH_ANY,
+            LIBUSB_HOTPLUG_MATCH_ANY,
+            LIBUSB_HOTPLUG_MATCH_ANY,
+            hotplug_callback,
+            Arc::as_ptr(&queue) as *mut c_void,
+            &mut handle,
+        ))?;
+        Ok(Self { ctx, handle, queue })
+    }
+
+    /// 队列中有事件时就绪，不取出事件
+    pub fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.queue.waker.register(cx.waker());
+        if self.queue.events.lock().unwrap().is_empty() {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+
+    pub fn try_next(&self) -> Option<HotplugEventOp> {
+        self.queue.events.lock().unwrap().pop_front()
+    }
+}
+
+impl Drop for Hotplug {
+    fn drop(&mut self) {
+        unsafe { libusb_hotplug_deregister_callback(self.ctx.raw(), self.handle) };
+    }
+}
+
+/// 在 libusb 事件线程中调用，只读取缓存的描述符，不打开设备
+extern "system" fn hotplug_callback(
+    _ctx: *mut libusb_context,
+    dev: *mut libusb_device,
+    event: c_int,
+    user_data: *mut c_void,
+) -> c_int {
+    let queue = unsafe { &*(user_data as *const Queue) };
+    let event = match event {
+        LIBUSB_HOTPLUG_EVENT_DEVICE_ARRIVED => match device::DeviceInfo::new(dev) {
+            Ok(info) => {
+                let is_hub = info.descriptor().class == 0x09;
+                let info = Box::new(info) as Box<dyn DeviceInfoOp>;
+                HotplugEventOp::Attached(if is_hub {
+                    ProbedDeviceInfoOp::Hub(info)
+                } else {
+                    ProbedDeviceInfoOp::Device(info)
+                })
+            }
+            Err(e) => {
+                warn!("Failed to read descriptors of attached device: {e:?}");
+                return 0;
+            }
+        },
+        LIBUSB_HOTPLUG_EVENT_DEVICE_LEFT => HotplugEventOp::Detached {
+            id: device::device_id(dev),
+        },
+        _ => return 0,
+    };
+    queue.events.lock().unwrap().push_back(event);
+    queue.waker.wake();
+    // 返回 0 保持回调注册
+    0
+}
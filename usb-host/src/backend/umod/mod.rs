@@ -7,7 +7,7 @@ use crate::{
     USBHost,
     backend::{
         BackendOp,
-        ty::{DeviceInfoOp, ProbedDeviceInfoOp},
+        ty::{DeviceInfoOp, HotplugEventOp, ProbedDeviceInfoOp},
     },
 };
 
@@ -17,11 +17,14 @@ mod err;
 mod context;
 mod device;
 mod endpoint;
+mod hotplug;
 
 impl USBHost {
     pub fn new_libusb() -> Result<USBHost, USBError> {
         let host = USBHost {
-            backend: Box::new(Libusb::new()),
+            backend: Box::new(Libusb::try_new()?),
+            raw_probe_order: false,
+            spawner: None,
         };
         Ok(host)
     }
@@ -29,11 +32,18 @@ impl USBHost {
 
 pub struct Libusb {
     ctx: Arc<context::Context>,
+    /// 在 `init` 中注册，之后插拔的设备都会进入事件队列；平台不支持热插拔时为空
+    hotplug: Option<hotplug::Hotplug>,
 }
 
 impl Libusb {
     pub fn new() -> Self {
-        let ctx = context::Context::new().expect("Failed to create libusb context");
+        Self::try_new().expect("Failed to create libusb context")
+    }
+
+    /// 系统没有可用的 USB 子系统（如容器中没有 usbfs）时返回错误
+    pub fn try_new() -> Result<Self, USBError> {
+        let ctx = context::Context::new()?;
         let handle = Arc::downgrade(&ctx);
 
         thread::spawn(move || {
@@ -47,7 +57,7 @@ impl Libusb {
             }
         });
 
-        Self { ctx }
+        Ok(Self { ctx, hotplug: None })
     }
 
     async fn device_list(&mut self) -> Result<Vec<ProbedDeviceInfoOp>, USBError> {
@@ -55,7 +65,17 @@ impl Libusb {
         let devices = ctx.device_list()?;
         let mut infos = Vec::new();
         for dev in devices {
-            let info = device::DeviceInfo::new(dev)?;
+            // Windows 上部分设备（如未安装驱动的复合设备）无法读取描述符，跳过而不中断枚举
+            let info = match device::DeviceInfo::new(dev) {
+                Ok(info) => info,
+                Err(e) => {
+                    warn!(
+                        "Skipping device {:#x}: failed to read descriptors: {e:?}",
+                        device::device_id(dev)
+                    );
+                    continue;
+                }
+            };
             let is_hub = info.descriptor().class == 0x09;
             let info = Box::new(info) as Box<dyn super::ty::DeviceInfoOp>;
             let info = if is_hub {
@@ -68,6 +88,31 @@ impl Libusb {
         Ok(infos)
     }
 
+    /// 在首次列举设备之前注册回调，列举与等待事件之间插入的设备不会丢失
+    fn register_hotplug(&mut self) -> Result<(), USBError> {
+        if self.hotplug.is_some() {
+            return Ok(());
+        }
+        match hotplug::Hotplug::register(self.ctx.clone()) {
+            Ok(hotplug) => self.hotplug = Some(hotplug),
+            Err(USBError::NotSupported) => debug!("libusb hotplug is not supported"),
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    async fn wait_hotplug(&mut self) {
+        // 不支持热插拔时立即返回，由 `poll_hotplug` 报告错误
+        if let Some(hotplug) = &self.hotplug {
+            core::future::poll_fn(|cx| hotplug.poll_ready(cx)).await;
+        }
+    }
+
+    fn poll_hotplug(&mut self) -> Result<Option<HotplugEventOp>, USBError> {
+        let hotplug = self.hotplug.as_ref().ok_or(USBError::NotSupported)?;
+        Ok(hotplug.try_next())
+    }
+
     async fn _open_device(
         &mut self,
         dev: &dyn super::ty::DeviceInfoOp,
@@ -89,7 +134,8 @@ impl Default for Libusb {
 
 impl BackendOp for Libusb {
     fn init<'a>(&'a mut self) -> futures::future::BoxFuture<'a, Result<(), USBError>> {
-        async { Ok(()) }.boxed()
+        let res = self.register_hotplug();
+        async move { res }.boxed()
     }
 
     fn device_list<'a>(
@@ -101,7 +147,18 @@ impl BackendOp for Libusb {
     fn open_device<'a>(
         &'a mut self,
         dev: &'a dyn super::ty::DeviceInfoOp,
-    ) -> futures::future::LocalBoxFuture<'a, Result<Box<dyn super::ty::DeviceOp>, USBError>> {
-        async move { self._open_device(dev).await }.boxed_local()
+    ) -> futures::future::BoxFuture<'a, Result<Box<dyn super::ty::DeviceOp>, USBError>> {
+        async move { self._open_device(dev).await }.boxed()
+    }
+
+    fn wait_hotplug<'a>(&'a mut self) -> futures::future::BoxFuture<'a, ()> {
+        self.wait_hotplug().boxed()
+    }
+
+    fn poll_hotplug<'a>(
+        &'a mut self,
+    ) -> futures::future::BoxFuture<'a, Result<Option<HotplugEventOp>, USBError>> {
+        let res = self.poll_hotplug();
+        async move { res }.boxed()
     }
 }
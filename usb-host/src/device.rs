@@ -1,21 +1,58 @@
-use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 use anyhow::anyhow;
 use core::{
     any::Any,
     fmt::{Debug, Display},
+    sync::atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering},
 };
 
 use usb_if::{
     descriptor::{
-        ConfigurationDescriptor, DescriptorType, DeviceDescriptor, InterfaceDescriptor, LanguageId,
-        decode_string_descriptor,
+        ConfigurationDescriptor, Descriptor, DescriptorIssue, DescriptorType, DeviceDescriptor,
+        DeviceQualifierDescriptor, InterfaceDescriptor, LanguageId, decode_string_descriptor,
+        validate_descriptors,
     },
     err::{TransferError, USBError},
     host::ControlSetup,
+    transfer::Recipient,
 };
 
-use crate::backend::ty::ep::Endpoint;
+use crate::backend::ty::ep::{
+    Endpoint, EndpointDirection, EndpointKind, RetryPolicy, TypedEndpoint,
+};
 use crate::backend::ty::{DeviceInfoOp, DeviceOp};
+use crate::spawn::Spawner;
+use crate::string_cache::StringCache;
+
+/// 设备在总线拓扑中的位置，[`USBHost::probe_devices`](crate::USBHost::probe_devices)
+/// 按此排序
+///
+/// 字段按排序优先级排列：先比较总线号，再按字典序比较端口路径，最后比较地址。
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeviceLocation {
+    /// 总线号；内核后端每个控制器只有一条总线，固定为 0
+    pub bus: u8,
+    /// 从根端口开始逐级的端口号，根端口从 1 开始
+    pub port_path: Vec<u8>,
+    /// 设备地址；内核与模拟后端为控制器分配的设备编号，即 [`DeviceInfo::id`]
+    pub address: usize,
+}
+
+impl DeviceLocation {
+    /// xHCI 规范 8.9 的 Route String：根端口之后每级 Hub 的端口号占 4 位，第一级在最低位
+    ///
+    /// 直接连接在根端口上的设备为 0。端口号大于 15 时记为 15，超过 5 级的部分被忽略。
+    pub fn route_string(&self) -> u32 {
+        self.port_path
+            .iter()
+            .skip(1)
+            .take(5)
+            .enumerate()
+            .fold(0, |route, (tier, &port)| {
+                route | (port.min(15) as u32) << (tier * 4)
+            })
+    }
+}
 
 pub struct DeviceInfo {
     pub(crate) inner: Box<dyn DeviceInfoOp>,
@@ -45,6 +82,13 @@ impl ProbedDevice {
         }
     }
 
+    pub fn location(&self) -> DeviceLocation {
+        match self {
+            Self::Device(info) => info.location(),
+            Self::Hub(info) => info.location(),
+        }
+    }
+
     pub fn configurations(&self) -> &[ConfigurationDescriptor] {
         match self {
             Self::Device(info) => info.configurations(),
@@ -98,6 +142,10 @@ impl DeviceInfo {
         self.inner.id()
     }
 
+    pub fn location(&self) -> DeviceLocation {
+        self.inner.location()
+    }
+
     pub fn descriptor(&self) -> &DeviceDescriptor {
         self.inner.descriptor()
     }
@@ -106,6 +154,26 @@ impl DeviceInfo {
         self.inner.configuration_descriptors()
     }
 
+    /// 设备工作在另一速度下时的设备信息，仅支持单一速度或未读取时为 `None`
+    pub fn device_qualifier(&self) -> Option<&DeviceQualifierDescriptor> {
+        self.inner.device_qualifier()
+    }
+
+    /// 设备工作在另一速度下时提供的配置
+    pub fn other_speed_configurations(&self) -> &[ConfigurationDescriptor] {
+        self.inner.other_speed_configurations()
+    }
+
+    /// 设备已被 [`USBHost::eject`](crate::USBHost::eject) 弹出，不能再打开
+    pub fn is_stale(&self) -> bool {
+        self.inner.is_stale()
+    }
+
+    /// 检查描述符是否违反规范，每个问题输出一条警告，见 [`validate_descriptors`]
+    pub fn validate_descriptors(&self) -> Vec<DescriptorIssue> {
+        report_descriptor_issues(self.descriptor(), self.configurations())
+    }
+
     pub fn interface_descriptors<'a>(
         &'a self,
     ) -> impl Iterator<Item = &'a InterfaceDescriptor> + 'a {
@@ -131,6 +199,10 @@ impl HubDeviceInfo {
         self.inner.id()
     }
 
+    pub fn location(&self) -> DeviceLocation {
+        self.inner.location()
+    }
+
     pub fn descriptor(&self) -> &DeviceDescriptor {
         self.inner.descriptor()
     }
@@ -192,9 +264,13 @@ impl Display for HubDeviceInfo {
 
 pub struct Device {
     pub(crate) inner: Box<dyn DeviceOp>,
-    lang_id: LanguageId,
+    strings: StringCache,
     manufacturer: Option<String>,
     current_interface: Option<(u8, u8)>,
+    shared: Arc<DeviceShared>,
+    /// 丢弃 [`Interface`] 时留下的端点与接口，下次重新配置前统一处理
+    orphans: Arc<spin::Mutex<Orphans>>,
+    pub(crate) spawner: Option<Arc<dyn Spawner>>,
 }
 
 impl Debug for Device {
@@ -209,22 +285,33 @@ impl Debug for Device {
 
 impl<T: DeviceOp> From<T> for Device {
     fn from(inner: T) -> Self {
-        Self {
-            inner: Box::new(inner),
-            current_interface: None,
-            lang_id: LanguageId::default(),
-            manufacturer: None,
-        }
+        Self::from(Box::new(inner) as Box<dyn DeviceOp>)
     }
 }
 
 impl From<Box<dyn DeviceOp>> for Device {
     fn from(inner: Box<dyn DeviceOp>) -> Self {
+        let shared = Arc::new(DeviceShared {
+            id: inner.id(),
+            backend: String::from(inner.backend_name()),
+            descriptor: inner.descriptor().clone(),
+            configurations: inner.configuration_descriptors().to_vec(),
+            manufacturer: spin::Once::new(),
+            state: AtomicU8::new(DeviceState::Active as u8),
+            configuration: AtomicU8::new(0),
+            interface: AtomicU32::new(0),
+            control_transfers: AtomicU64::new(0),
+            control_errors: AtomicU64::new(0),
+            stalls: AtomicU64::new(0),
+        });
         Self {
             inner,
             current_interface: None,
-            lang_id: LanguageId::default(),
+            strings: StringCache::default(),
             manufacturer: None,
+            spawner: None,
+            shared,
+            orphans: Arc::new(spin::Mutex::new(Orphans::default())),
         }
     }
 }
@@ -232,9 +319,27 @@ impl From<Box<dyn DeviceOp>> for Device {
 impl Device {
     pub(crate) async fn init(&mut self) -> Result<(), USBError> {
         self.manufacturer = self.read_manufacturer().await;
+        if let Some(name) = &self.manufacturer {
+            self.shared.manufacturer.call_once(|| name.clone());
+        }
         Ok(())
     }
 
+    /// 创建只读的监视句柄
+    ///
+    /// 监视句柄可以克隆并交给监控任务，读取描述符、状态与统计时不需要访问 `Device`，
+    /// 传输仍由持有 `Device` 的任务独占执行。
+    pub fn monitor(&self) -> DeviceMonitor {
+        DeviceMonitor {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// 打开设备时 [`USBHost`](crate::USBHost) 设置的后台任务执行器
+    pub fn spawner(&self) -> Option<&Arc<dyn Spawner>> {
+        self.spawner.as_ref()
+    }
+
     pub fn product_id(&self) -> u16 {
         self.descriptor().product_id
     }
@@ -249,8 +354,13 @@ impl Device {
 
     pub async fn claim_interface(&mut self, interface: u8, alternate: u8) -> Result<(), USBError> {
         trace!("Claiming interface {interface}, alternate {alternate}");
+        self.reap_orphans().await;
         self.inner.claim_interface(interface, alternate).await?;
         self.current_interface = Some((interface, alternate));
+        self.shared.interface.store(
+            INTERFACE_VALID | (interface as u32) << 8 | alternate as u32,
+            Ordering::Relaxed,
+        );
         Ok(())
     }
 
@@ -262,18 +372,186 @@ impl Device {
         self.inner.configuration_descriptors()
     }
 
+    pub fn device_qualifier(&self) -> Option<&DeviceQualifierDescriptor> {
+        self.inner.device_qualifier()
+    }
+
+    pub fn other_speed_configurations(&self) -> &[ConfigurationDescriptor] {
+        self.inner.other_speed_configurations()
+    }
+
+    /// 检查描述符是否违反规范，见 [`DeviceInfo::validate_descriptors`]
+    pub fn validate_descriptors(&self) -> Vec<DescriptorIssue> {
+        report_descriptor_issues(self.descriptor(), self.configurations())
+    }
+
     pub fn manufacturer(&self) -> Option<&str> {
         self.manufacturer.as_deref()
     }
 
+    /// 重新读取设备描述符与全部配置描述符，内容有变化时替换缓存并返回 `true`
+    ///
+    /// 用于设备不重新枚举、原地切换模式（配置数量或接口改变）的情况。已取出的端点与
+    /// 已创建的 [`DeviceMonitor`] 仍基于旧的描述符。
+    pub async fn reload_descriptors(&mut self) -> Result<bool, USBError> {
+        let desc: DeviceDescriptor = self.read_descriptor(0, 0).await?;
+        let mut configs = Vec::with_capacity(desc.num_configurations as usize);
+        for index in 0..desc.num_configurations {
+            configs.push(
+                self.read_descriptor::<ConfigurationDescriptor>(index, 0)
+                    .await?,
+            );
+        }
+
+        let old = self.descriptor();
+        let changed = (old.vendor_id, old.product_id, old.device_version, old.class)
+            != (
+                desc.vendor_id,
+                desc.product_id,
+                desc.device_version,
+                desc.class,
+            )
+            || configs.len() != self.configurations().len()
+            || configs
+                .iter()
+                .zip(self.configurations())
+                .any(|(new, old)| new.raw != old.raw);
+        if changed {
+            info!(
+                "Device descriptors changed: {:04x}:{:04x}, {} configurations",
+                desc.vendor_id,
+                desc.product_id,
+                configs.len()
+            );
+            self.inner.set_descriptors(desc, configs);
+            self.strings.clear();
+        }
+        Ok(changed)
+    }
+
     pub async fn set_configuration(&mut self, configuration_value: u8) -> crate::err::Result {
+        self.reap_orphans().await;
         let result = self.inner.set_configuration(configuration_value).await;
         if result.is_ok() {
             self.current_interface = None;
+            self.shared
+                .configuration
+                .store(configuration_value, Ordering::Relaxed);
+            self.shared.interface.store(0, Ordering::Relaxed);
         }
         result
     }
 
+    /// 声明接口并取出其全部端点
+    ///
+    /// 通过 [`Interface::release`] 或 [`Device::release_interface`] 释放时会先取消端点上
+    /// 未完成的请求再停用端点；直接丢弃 `Interface` 时，在下一次声明接口或设置配置前
+    /// 取消仍有请求的端点并停用接口。
+    pub async fn claim(&mut self, interface: u8, alternate: u8) -> Result<Interface, USBError> {
+        self.claim_interface(interface, alternate).await?;
+        let endpoints = self.take_endpoints()?;
+        Ok(Interface {
+            number: interface,
+            alternate,
+            endpoints,
+            release_on_drop: true,
+            orphans: self.orphans.clone(),
+        })
+    }
+
+    /// 取消接口端点上的全部请求，等待控制器停止访问缓冲区后停用这些端点
+    ///
+    /// 适用于切换备用设置（如 UVC 调整带宽）前释放旧端点，保证环上不残留 TD。
+    /// 取消失败时仍会继续释放，最后返回遇到的第一个错误。
+    pub async fn release_interface(&mut self, mut interface: Interface) -> Result<(), USBError> {
+        let mut first_err: Option<USBError> = None;
+        interface.release_on_drop = false;
+        for (address, mut ep) in core::mem::take(&mut interface.endpoints) {
+            if let Err(e) = ep.cancel_all().await {
+                debug!("Cancel transfers on endpoint {address:#x}: {e:?}");
+                first_err.get_or_insert(e.into());
+            }
+        }
+        self.reap_orphans().await;
+
+        if let Err(e) = self
+            .inner
+            .release_interface(interface.number, interface.alternate)
+            .await
+        {
+            first_err.get_or_insert(e);
+        }
+        if self.current_interface == Some((interface.number, interface.alternate)) {
+            self.current_interface = None;
+            self.shared.interface.store(0, Ordering::Relaxed);
+        }
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    async fn reap_orphans(&mut self) {
+        let Orphans {
+            endpoints,
+            interfaces,
+        } = core::mem::take(&mut *self.orphans.lock());
+        for mut ep in endpoints {
+            if let Err(e) = ep.cancel_all().await {
+                debug!("Cancel transfers on orphaned endpoint: {e:?}");
+            }
+        }
+        // 通过后端释放，kmod 下由 Configure Endpoint 命令丢弃端点上下文并归还带宽
+        for (interface, alternate) in interfaces {
+            if let Err(e) = self.inner.release_interface(interface, alternate).await {
+                debug!("Release dropped interface {interface}: {e:?}");
+            }
+            if self.current_interface == Some((interface, alternate)) {
+                self.current_interface = None;
+                self.shared.interface.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// 覆盖此设备传输缓冲区的 DMA 掩码与反弹策略，默认沿用控制器的掩码
+    ///
+    /// 掩码与控制器掩码取交集。只影响控制端点与之后取出的端点，
+    /// 已经取出的端点保持原有约束，应在声明接口前调用。
+    #[cfg(kmod)]
+    pub fn set_dma_config(
+        &mut self,
+        config: crate::backend::kmod::DmaConfig,
+    ) -> Result<(), USBError> {
+        self.inner.set_dma_config(config)
+    }
+
+    /// 声明接口时是否自动卸载已绑定的内核驱动（如 usbhid、uvcvideo），释放接口时重新绑定
+    ///
+    /// Linux 上默认启用；关闭后内核驱动仍绑定时声明接口失败，需先
+    /// [`Device::detach_kernel_driver`]。不支持的平台返回 [`USBError::NotSupported`]。
+    #[cfg(any(umod, nmod))]
+    pub fn set_auto_detach_kernel_driver(&mut self, enable: bool) -> Result<(), USBError> {
+        self.inner.set_auto_detach_kernel_driver(enable)
+    }
+
+    /// 接口是否绑定了内核驱动
+    #[cfg(any(umod, nmod))]
+    pub fn kernel_driver_active(&self, interface: u8) -> Result<bool, USBError> {
+        self.inner.kernel_driver_active(interface)
+    }
+
+    /// 卸载接口上的内核驱动
+    #[cfg(any(umod, nmod))]
+    pub fn detach_kernel_driver(&mut self, interface: u8) -> Result<(), USBError> {
+        self.inner.detach_kernel_driver(interface)
+    }
+
+    /// 重新绑定接口的内核驱动，接口须已释放
+    #[cfg(any(umod, nmod))]
+    pub fn attach_kernel_driver(&mut self, interface: u8) -> Result<(), USBError> {
+        self.inner.attach_kernel_driver(interface)
+    }
+
     pub fn ctrl_ep_ref(&self) -> &Endpoint {
         self.inner.ctrl_ep_ref()
     }
@@ -287,38 +565,177 @@ impl Device {
         self.string_descriptor(idx.get()).await.ok()
     }
 
+    /// 读取字符串描述符使用的语言，见 [`StringCache::language`]
     pub fn lang_id(&self) -> LanguageId {
-        self.lang_id
+        self.strings.language()
     }
 
+    /// 选择读取字符串描述符使用的语言，已缓存的其他语言的字符串保留
     pub fn set_lang_id(&mut self, lang_id: LanguageId) {
-        self.lang_id = lang_id;
+        self.strings.select(lang_id);
+    }
+
+    /// 已读取的字符串描述符缓存
+    pub fn strings(&self) -> &StringCache {
+        &self.strings
+    }
+
+    /// 设备支持的语言，首次调用时读取字符串描述符 0 的 LANGID 表
+    pub async fn languages(&mut self) -> Result<Vec<LanguageId>, USBError> {
+        if let Some(langs) = self.strings.languages() {
+            return Ok(langs.to_vec());
+        }
+        let mut data = alloc::vec![0u8; 256];
+        let res = self
+            .ctrl_ep_mut()
+            .get_descriptor(DescriptorType::STRING, 0, 0, &mut data)
+            .await;
+        self.shared.record_control(res.as_ref().map(|_| ()));
+        res?;
+        self.strings.set_languages(&data);
+        Ok(self.strings.languages().unwrap_or_default().to_vec())
+    }
+
+    /// 产品名称字符串
+    pub async fn product(&mut self) -> Option<String> {
+        let idx = self.descriptor().product_string_index?;
+        self.string_descriptor(idx.get()).await.ok()
+    }
+
+    /// 序列号字符串
+    pub async fn serial_number(&mut self) -> Option<String> {
+        let idx = self.descriptor().serial_number_string_index?;
+        self.string_descriptor(idx.get()).await.ok()
+    }
+
+    /// 接口备用设置的名称字符串
+    pub async fn interface_string(&mut self, interface: u8, alternate: u8) -> Option<String> {
+        let idx = self
+            .configurations()
+            .iter()
+            .flat_map(|c| &c.interfaces)
+            .filter(|i| i.interface_number == interface)
+            .flat_map(|i| &i.alt_settings)
+            .find(|alt| alt.alternate_setting == alternate)?
+            .string_index?;
+        self.string_descriptor(idx.get()).await.ok()
     }
 
+    /// 读取类型为 `T` 的标准描述符，自动完成“先读头部、再读完整长度”的两阶段读取
+    pub async fn read_descriptor<T: Descriptor>(
+        &mut self,
+        index: u8,
+        language_id: u16,
+    ) -> Result<T, USBError> {
+        let res = self.ctrl_ep_mut().read_descriptor(index, language_id).await;
+        // 描述符解析失败不算传输错误
+        self.shared.record_control(match &res {
+            Err(USBError::TransferError(e)) => Err(e),
+            _ => Ok(()),
+        });
+        res
+    }
+
+    /// 读取并解码字符串描述符，结果按当前语言缓存
+    ///
+    /// 首次读取前先获取 LANGID 表，未选择语言时使用设备的第一种语言。
     pub async fn string_descriptor(&mut self, index: u8) -> Result<String, USBError> {
+        if self.strings.languages().is_none()
+            && let Err(e) = self.languages().await
+        {
+            // 部分设备不提供 LANGID 表，此后按默认语言读取
+            debug!("Read LANGID table: {e:?}");
+            self.strings.set_languages(&[]);
+        }
+        if let Some(s) = self.strings.get(index) {
+            return Ok(s.into());
+        }
+
         let mut data = alloc::vec![0u8; 256];
         let lang_id = self.lang_id();
-        self.ctrl_ep_mut()
+        let res = self
+            .ctrl_ep_mut()
             .get_descriptor(DescriptorType::STRING, index, lang_id.into(), &mut data)
-            .await?;
+            .await;
+        self.shared.record_control(res.as_ref().map(|_| ()));
+        res?;
         let res = decode_string_descriptor(&data)?;
+        self.strings.insert(index, res.clone());
         Ok(res)
     }
 
+    /// GET_STATUS，见 [`Endpoint::get_status`]
+    pub async fn get_status(
+        &mut self,
+        recipient: Recipient,
+        index: u16,
+    ) -> Result<u16, TransferError> {
+        let res = self.ctrl_ep_mut().get_status(recipient, index).await;
+        self.shared.record_control(res.as_ref().map(|_| ()));
+        res
+    }
+
+    /// SET_FEATURE，见 [`Endpoint::set_feature`]
+    pub async fn set_feature(
+        &mut self,
+        recipient: Recipient,
+        feature: u16,
+        index: u16,
+    ) -> Result<(), TransferError> {
+        let res = self
+            .ctrl_ep_mut()
+            .set_feature(recipient, feature, index)
+            .await;
+        self.shared.record_control(res.as_ref().map(|_| ()));
+        res
+    }
+
+    /// CLEAR_FEATURE，见 [`Endpoint::clear_feature`]
+    pub async fn clear_feature(
+        &mut self,
+        recipient: Recipient,
+        feature: u16,
+        index: u16,
+    ) -> Result<(), TransferError> {
+        let res = self
+            .ctrl_ep_mut()
+            .clear_feature(recipient, feature, index)
+            .await;
+        self.shared.record_control(res.as_ref().map(|_| ()));
+        res
+    }
+
+    /// 通过默认控制端点发起 IN 控制传输
+    ///
+    /// 不需要先 claim 接口，可用于接收者为设备的标准请求或厂商请求。
     pub async fn control_in(
         &mut self,
         param: ControlSetup,
         buff: &mut [u8],
     ) -> Result<usize, TransferError> {
-        self.ctrl_ep_mut().control_in(param, buff).await
+        let res = self.ctrl_ep_mut().control_in(param, buff).await;
+        self.shared.record_control(res.as_ref().map(|_| ()));
+        res
     }
 
+    /// 通过默认控制端点发起 OUT 控制传输，同 [`Device::control_in`]
     pub async fn control_out(
         &mut self,
         param: ControlSetup,
         buff: &[u8],
     ) -> Result<usize, TransferError> {
-        self.ctrl_ep_mut().control_out(param, buff).await
+        let res = self.ctrl_ep_mut().control_out(param, buff).await;
+        self.shared.record_control(res.as_ref().map(|_| ()));
+        res
+    }
+
+    /// 按 `policy` 重试 `op`，用于设备级控制请求，见 [`Endpoint::retry`]
+    pub async fn retry<T>(
+        &mut self,
+        policy: &RetryPolicy,
+        op: impl AsyncFnMut(&mut Self) -> Result<T, TransferError>,
+    ) -> Result<T, TransferError> {
+        crate::backend::ty::ep::retry(self, Self::ctrl_ep_ref, policy, op).await
     }
 
     pub async fn update_hub(
@@ -423,3 +840,289 @@ impl Display for Device {
         )
     }
 }
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        self.shared
+            .state
+            .store(DeviceState::Closed as u8, Ordering::Release);
+    }
+}
+
+/// 丢弃 [`Interface`] 时留下、等待 [`Device`] 处理的资源
+#[derive(Default)]
+struct Orphans {
+    /// 仍有未完成请求的端点
+    endpoints: Vec<Endpoint>,
+    /// 未经释放就丢弃的接口及其备用设置
+    interfaces: Vec<(u8, u8)>,
+}
+
+/// 通过 [`Device::claim`] 声明的接口，持有其当前备用设置的全部端点
+pub struct Interface {
+    number: u8,
+    alternate: u8,
+    endpoints: BTreeMap<u8, Endpoint>,
+    /// 丢弃时停用接口；已显式释放或取出过端点时为 `false`
+    release_on_drop: bool,
+    orphans: Arc<spin::Mutex<Orphans>>,
+}
+
+impl Interface {
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    pub fn alternate(&self) -> u8 {
+        self.alternate
+    }
+
+    pub fn endpoint(&mut self, address: u8) -> Option<&mut Endpoint> {
+        self.endpoints.get_mut(&address)
+    }
+
+    /// 取出端点，取出后的端点不再随接口释放
+    ///
+    /// 取出过端点的接口在丢弃时不会停用，需要时用 [`Interface::release`] 显式释放。
+    pub fn take_endpoint(&mut self, address: u8) -> Option<Endpoint> {
+        let ep = self.endpoints.remove(&address)?;
+        self.release_on_drop = false;
+        Some(ep)
+    }
+
+    /// 取出类型化端点，端点不存在时返回 `NotFound`，类型或方向不符时端点保留在接口中
+    pub fn take_typed<T: EndpointKind, D: EndpointDirection>(
+        &mut self,
+        address: u8,
+    ) -> Result<TypedEndpoint<T, D>, USBError> {
+        let ep = self.endpoints.remove(&address).ok_or(USBError::NotFound)?;
+        let typed = ep.into_typed().map_err(|ep| {
+            self.endpoints.insert(address, ep);
+            TransferError::InvalidEndpoint
+        })?;
+        self.release_on_drop = false;
+        Ok(typed)
+    }
+
+    pub fn endpoints_mut(&mut self) -> impl Iterator<Item = (u8, &mut Endpoint)> {
+        self.endpoints
+            .iter_mut()
+            .map(|(&address, ep)| (address, ep))
+    }
+
+    /// 取消全部端点上未完成的请求，返回被取消的请求数
+    pub async fn cancel_all(&mut self) -> Result<usize, TransferError> {
+        let mut cancelled = 0;
+        for ep in self.endpoints.values_mut() {
+            cancelled += ep.cancel_all().await?;
+        }
+        Ok(cancelled)
+    }
+
+    /// 等同于 [`Device::release_interface`]
+    pub async fn release(self, device: &mut Device) -> Result<(), USBError> {
+        device.release_interface(self).await
+    }
+}
+
+impl Debug for Interface {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Interface")
+            .field("number", &self.number)
+            .field("alternate", &self.alternate)
+            .field("endpoints", &self.endpoints.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Drop for Interface {
+    /// 无法在析构中等待控制器，仍有请求的端点与接口的停用交给 `Device` 延后处理
+    fn drop(&mut self) {
+        let busy = core::mem::take(&mut self.endpoints)
+            .into_values()
+            .filter(Endpoint::has_pending);
+        let mut orphans = self.orphans.lock();
+        orphans.endpoints.extend(busy);
+        if self.release_on_drop {
+            orphans.interfaces.push((self.number, self.alternate));
+        }
+    }
+}
+
+/// 设备句柄的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DeviceState {
+    /// `Device` 仍被持有且未发现异常
+    Active,
+    /// 传输返回 [`TransferError::NoDevice`]，设备已断开或被弹出
+    Disconnected,
+    /// `Device` 已释放
+    Closed,
+}
+
+/// 控制传输统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceStats {
+    pub control_transfers: u64,
+    pub control_errors: u64,
+    pub stalls: u64,
+}
+
+const INTERFACE_VALID: u32 = 1 << 16;
+
+fn report_descriptor_issues(
+    device: &DeviceDescriptor,
+    configurations: &[ConfigurationDescriptor],
+) -> Vec<DescriptorIssue> {
+    let issues = validate_descriptors(device, configurations, None);
+    for issue in &issues {
+        warn!(
+            "{:04x}:{:04x}: {issue}",
+            device.vendor_id, device.product_id
+        );
+    }
+    issues
+}
+
+struct DeviceShared {
+    id: usize,
+    backend: String,
+    descriptor: DeviceDescriptor,
+    configurations: Vec<ConfigurationDescriptor>,
+    manufacturer: spin::Once<String>,
+    state: AtomicU8,
+    configuration: AtomicU8,
+    /// bit16 有效位，bit8..16 接口号，bit0..8 备用设置
+    interface: AtomicU32,
+    control_transfers: AtomicU64,
+    control_errors: AtomicU64,
+    stalls: AtomicU64,
+}
+
+impl DeviceShared {
+    fn record_control(&self, res: Result<(), &TransferError>) {
+        self.control_transfers.fetch_add(1, Ordering::Relaxed);
+        let Err(e) = res else {
+            return;
+        };
+        self.control_errors.fetch_add(1, Ordering::Relaxed);
+        match e {
+            TransferError::Stall | TransferError::StatusStall => {
+                self.stalls.fetch_add(1, Ordering::Relaxed);
+            }
+            TransferError::NoDevice => {
+                let _ = self.state.compare_exchange(
+                    DeviceState::Active as u8,
+                    DeviceState::Disconnected as u8,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// [`Device`] 的只读监视句柄
+///
+/// 由 [`Device::monitor`] 创建，可克隆、可跨任务共享。`Device` 释放后句柄仍然有效，
+/// 状态变为 [`DeviceState::Closed`]。
+#[derive(Clone)]
+pub struct DeviceMonitor {
+    shared: Arc<DeviceShared>,
+}
+
+impl DeviceMonitor {
+    pub fn id(&self) -> usize {
+        self.shared.id
+    }
+
+    pub fn backend_name(&self) -> &str {
+        &self.shared.backend
+    }
+
+    pub fn descriptor(&self) -> &DeviceDescriptor {
+        &self.shared.descriptor
+    }
+
+    pub fn configurations(&self) -> &[ConfigurationDescriptor] {
+        &self.shared.configurations
+    }
+
+    pub fn product_id(&self) -> u16 {
+        self.shared.descriptor.product_id
+    }
+
+    pub fn vendor_id(&self) -> u16 {
+        self.shared.descriptor.vendor_id
+    }
+
+    pub fn manufacturer(&self) -> Option<&str> {
+        self.shared.manufacturer.get().map(String::as_str)
+    }
+
+    pub fn state(&self) -> DeviceState {
+        match self.shared.state.load(Ordering::Acquire) {
+            0 => DeviceState::Active,
+            1 => DeviceState::Disconnected,
+            _ => DeviceState::Closed,
+        }
+    }
+
+    /// 通过 [`Device::set_configuration`] 设置的配置值
+    pub fn configuration(&self) -> Option<u8> {
+        match self.shared.configuration.load(Ordering::Relaxed) {
+            0 => None,
+            value => Some(value),
+        }
+    }
+
+    /// 当前声明的接口与备用设置
+    pub fn interface(&self) -> Option<(u8, u8)> {
+        let value = self.shared.interface.load(Ordering::Relaxed);
+        (value & INTERFACE_VALID != 0).then_some(((value >> 8) as u8, value as u8))
+    }
+
+    pub fn stats(&self) -> DeviceStats {
+        DeviceStats {
+            control_transfers: self.shared.control_transfers.load(Ordering::Relaxed),
+            control_errors: self.shared.control_errors.load(Ordering::Relaxed),
+            stalls: self.shared.stalls.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Debug for DeviceMonitor {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DeviceMonitor")
+            .field("backend", &self.backend_name())
+            .field("vender_id", &self.vendor_id())
+            .field("product_id", &self.product_id())
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(port_path: &[u8]) -> DeviceLocation {
+        DeviceLocation {
+            bus: 0,
+            port_path: port_path.to_vec(),
+            address: 1,
+        }
+    }
+
+    #[test]
+    fn route_string_skips_root_port() {
+        assert_eq!(location(&[]).route_string(), 0);
+        assert_eq!(location(&[3]).route_string(), 0);
+        assert_eq!(location(&[3, 2]).route_string(), 0x2);
+        assert_eq!(location(&[3, 2, 4, 1]).route_string(), 0x142);
+        assert_eq!(location(&[1, 20, 7]).route_string(), 0x7f);
+        assert_eq!(location(&[1, 1, 2, 3, 4, 5, 6]).route_string(), 0x54321);
+    }
+}
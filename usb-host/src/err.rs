@@ -15,6 +15,9 @@ impl ConvertXhciError for CompletionCode {
             CompletionCode::Success => Ok(()),
             CompletionCode::ShortPacket => Ok(()),
             CompletionCode::StallError => Err(TransferError::Stall),
+            CompletionCode::BandwidthError | CompletionCode::SecondaryBandwidthError => {
+                Err(TransferError::NoBandwidth)
+            }
             CompletionCode::MissedServiceError => {
                 // MissedServiceError 通常是暂时性的，可以重试
                 Err(TransferError::Other(anyhow!(
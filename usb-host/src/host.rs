@@ -1,9 +1,15 @@
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::ops::ControlFlow;
+
+use futures::Stream;
+use futures::future::BoxFuture;
 
 use crate::backend::BackendOp;
 use crate::backend::ty::*;
 use crate::err::Result;
+use crate::spawn::Spawner;
 
 #[cfg(kmod)]
 pub use super::backend::kmod::*;
@@ -11,11 +17,49 @@ pub use super::backend::kmod::*;
 #[cfg(umod)]
 pub use super::backend::umod::*;
 
-pub use crate::device::{Device, DeviceInfo, HubDeviceInfo, ProbedDevice};
+#[cfg(nmod)]
+pub use super::backend::nusb::*;
+
+#[cfg(mmod)]
+pub use super::backend::mock::{MockBus, MockDevice, fixtures};
+
+pub use crate::device::{Device, DeviceInfo, DeviceLocation, HubDeviceInfo, ProbedDevice};
+
+/// 设备热插拔事件，见 [`USBHost::watch`]
+///
+/// 不同后端支持的事件不同，以后也可能增加新的事件，匹配时需保留通配分支。
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HotplugEvent {
+    /// 新设备插入，已完成枚举，可直接打开
+    Attached(ProbedDevice),
+    /// 设备拔出，`id` 为此前 [`ProbedDevice::id`] 的值，对应的设备信息已失效
+    Detached { id: usize },
+    /// 经 [`USBHost::suspend_device`] 挂起的设备发出远程唤醒，端口已恢复，设备可继续传输
+    #[cfg(kmod)]
+    RemoteWakeup { id: usize },
+}
+
+/// 后台监视任务报告的事件，见 [`USBHost::spawn_monitor`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HostEvent {
+    Hotplug(HotplugEvent),
+    /// 开启看门狗时由监视任务周期性检查得到
+    #[cfg(kmod)]
+    Watchdog(WatchdogEvent),
+}
 
 /// USB 主机控制器
 pub struct USBHost {
     pub(crate) backend: Box<dyn BackendOp>,
+    /// 为真时 [`USBHost::probe_devices`] 保留后端返回的原始顺序
+    pub(crate) raw_probe_order: bool,
+    /// 后台任务执行器，打开的设备会继承
+    pub(crate) spawner: Option<Arc<dyn Spawner>>,
+    /// 必须位于最后，在后端释放之后检查 DMA 内存是否全部归还
+    #[cfg(all(kmod, feature = "mem-track"))]
+    pub(crate) _mem_guard: crate::backend::kmod::mem::LeakGuard,
 }
 
 impl USBHost {
@@ -25,40 +69,335 @@ impl USBHost {
         Ok(())
     }
 
+    /// 探测新连接的设备
+    ///
+    /// 结果按 [`DeviceLocation`] 排序，与枚举完成的先后及后端无关，同一拓扑下每次
+    /// 调用的顺序相同；需要后端原始顺序时见 [`USBHost::set_raw_probe_order`]。
+    ///
+    /// xHCI 后端配置为 `EnumerationMode::Lazy` 时，根端口上的设备只记录为待枚举端口，
+    /// 不出现在返回值中，见 `USBHost::pending_ports`。
     pub async fn probe_devices(&mut self) -> Result<Vec<ProbedDevice>> {
         let device_infos = self.backend.device_list().await?;
-        let mut devices = Vec::new();
-        for dev in device_infos {
-            let dev_info = match dev {
-                ProbedDeviceInfoOp::Device(inner) => ProbedDevice::Device(DeviceInfo { inner }),
-                ProbedDeviceInfoOp::Hub(inner) => ProbedDevice::Hub(HubDeviceInfo { inner }),
-            };
-            devices.push(dev_info);
+        let mut devices: Vec<_> = device_infos.into_iter().map(probed_device).collect();
+        if !self.raw_probe_order {
+            devices.sort_by_cached_key(ProbedDevice::location);
         }
         Ok(devices)
     }
 
+    /// 设置后台任务执行器，之后打开的 [`Device`] 经 [`Device::spawner`] 取得同一个执行器
+    pub fn set_spawner(&mut self, spawner: Arc<dyn Spawner>) {
+        self.spawner = Some(spawner);
+    }
+
+    pub fn spawner(&self) -> Option<&Arc<dyn Spawner>> {
+        self.spawner.as_ref()
+    }
+
+    /// 为真时 [`USBHost::probe_devices`] 不再排序，按后端完成枚举的顺序返回
+    pub fn set_raw_probe_order(&mut self, raw: bool) {
+        self.raw_probe_order = raw;
+    }
+
+    /// 等待下一个热插拔事件
+    ///
+    /// 只报告上次 [`USBHost::probe_devices`] 或本方法返回之后的变化。xHCI 后端由根端口的
+    /// 状态变化事件驱动，需要事件处理器已接入；外部 Hub 下游端口的变化在下一次根端口变化时
+    /// 一并检查。libusb 后端使用 libusb 的热插拔回调，平台不支持时返回
+    /// [`USBError::NotSupported`](crate::err::USBError::NotSupported)。
+    pub async fn next_hotplug_event(&mut self) -> Result<HotplugEvent> {
+        loop {
+            self.wait_hotplug().await;
+            if let Some(event) = self.poll_hotplug().await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// 等待到 [`USBHost::poll_hotplug`] 有变化可处理，可随时取消
+    pub(crate) async fn wait_hotplug(&mut self) {
+        self.backend.wait_hotplug().await
+    }
+
+    /// 处理已观察到的变化，变化未产生事件时返回 `None`
+    pub(crate) async fn poll_hotplug(&mut self) -> Result<Option<HotplugEvent>> {
+        Ok(self.backend.poll_hotplug().await?.map(|event| match event {
+            #[cfg(any(kmod, umod, nmod, mmod))]
+            HotplugEventOp::Attached(dev) => HotplugEvent::Attached(probed_device(dev)),
+            #[cfg(any(kmod, umod, nmod, mmod))]
+            HotplugEventOp::Detached { id } => HotplugEvent::Detached { id },
+            #[cfg(kmod)]
+            HotplugEventOp::RemoteWakeup { id } => HotplugEvent::RemoteWakeup { id },
+        }))
+    }
+
+    /// 热插拔事件流，逐个产生 [`USBHost::next_hotplug_event`] 的结果
+    ///
+    /// 后端出错后产生该错误并结束，之后不再等待事件。流借用主机，处理事件时如需打开
+    /// 设备，可改为循环调用 [`USBHost::next_hotplug_event`]。
+    pub fn watch(&mut self) -> impl Stream<Item = Result<HotplugEvent>> + '_ {
+        futures::stream::unfold(Some(self), |host| async move {
+            let host = host?;
+            match host.next_hotplug_event().await {
+                Ok(event) => Some((Ok(event), Some(host))),
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// 把主机交给 [`USBHost::set_spawner`] 设置的执行器，在后台处理热插拔、外部 Hub
+    /// 端口变化与看门狗检查，事件依次交给 `on_event`
+    ///
+    /// `on_event` 可借用主机打开新插入的设备，返回 [`ControlFlow::Break`] 或后端出错时
+    /// 任务结束，错误会先交给 `on_event`；主机随任务释放。未设置执行器时原样返回主机。
+    pub fn spawn_monitor<F>(self, mut on_event: F) -> core::result::Result<(), Self>
+    where
+        F: for<'a> FnMut(&'a mut USBHost, Result<HostEvent>) -> BoxFuture<'a, ControlFlow<()>>
+            + Send
+            + 'static,
+    {
+        let Some(spawner) = self.spawner.clone() else {
+            return Err(self);
+        };
+        spawner.spawn(Box::pin(async move {
+            let mut host = self;
+            loop {
+                let event = host.next_event().await;
+                let failed = event.is_err();
+                if on_event(&mut host, event).await.is_break() || failed {
+                    break;
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    /// 等待下一个热插拔事件，开启看门狗时在等待期间周期性检查
+    async fn next_event(&mut self) -> Result<HostEvent> {
+        #[cfg(kmod)]
+        while let Some(tick) = self.backend.watchdog_tick() {
+            let changed = self.backend.wait_hotplug();
+            let woke = matches!(
+                futures::future::select(changed, tick).await,
+                futures::future::Either::Left(_)
+            );
+            if woke {
+                if let Some(event) = self.poll_hotplug().await? {
+                    return Ok(HostEvent::Hotplug(event));
+                }
+            } else if let Some(event) = self.poll_watchdog().await? {
+                return Ok(HostEvent::Watchdog(event));
+            }
+        }
+        Ok(HostEvent::Hotplug(self.next_hotplug_event().await?))
+    }
+
     #[cfg(kmod)]
     pub fn create_event_handler(&mut self) -> EventHandler {
         let handler = self.backend.create_event_handler();
         EventHandler { handler }
     }
 
+    /// 获取控制器运行时统计快照
+    #[cfg(kmod)]
+    pub fn perf_counters(&self) -> PerfCounters {
+        self.backend.perf_counters()
+    }
+
+    /// 控制器自检：发送 `count` 条 NoOp 命令，确认命令完成事件能经主中断器送达，
+    /// 并测量命令往返耗时；其余中断器逐个检查事件环与中断使能。需在 [`USBHost::init`]
+    /// 之后、事件处理已接入时调用。
+    #[cfg(kmod)]
+    pub async fn self_test(&mut self, count: u32) -> Result<SelfTestReport> {
+        self.backend.self_test(count).await
+    }
+
+    /// 禁用根端口 `port`（从 1 开始），模拟设备被意外拔出，用于测试断开处理路径
+    ///
+    /// 端口号超出范围时返回 [`USBError::InvalidParameter`](crate::err::USBError::InvalidParameter)。
+    #[cfg(all(kmod, feature = "fault-injection"))]
+    pub fn inject_port_disable(&mut self, port: u8) -> Result<()> {
+        self.backend.inject_port_disable(port)
+    }
+
+    /// 按用途统计控制器当前占用的 DMA 内存
+    ///
+    /// `USBHost` 释放时会断言所有 DMA 内存均已归还，因此设备、端点与事件处理器
+    /// 都应先于 `USBHost` 释放。
+    #[cfg(all(kmod, feature = "mem-track"))]
+    pub fn memory_report(&self) -> MemoryReport {
+        self.backend.memory_report()
+    }
+
+    /// 安全移除设备：停止全部端点、禁用设备槽并回收控制器资源，
+    /// `power_off_port` 为真时同时关闭所在根端口的电源
+    ///
+    /// 大容量存储设备应先完成 SYNCHRONIZE CACHE 等收尾操作；设备上下文由 [`Device`]
+    /// 持有，已打开的设备需在释放前弹出。弹出后对应的
+    /// [`DeviceInfo`] 标记为失效，仍被持有的 [`Device`] 上的传输返回
+    /// [`TransferError::NoDevice`](crate::err::TransferError::NoDevice)。
+    /// Hub 以及外部 Hub 下游端口的断电暂不支持。
+    #[cfg(kmod)]
+    pub async fn eject(&mut self, device_id: usize, power_off_port: bool) -> Result<()> {
+        self.backend.eject(device_id, power_off_port).await
+    }
+
+    /// 选择性挂起设备：所在端口的链路进入 U3，设备停止收到 SOF 后进入低功耗状态
+    ///
+    /// 挂起前端点上不应有进行中的传输。需要设备远程唤醒时，先通过
+    /// [`Device::set_feature`] 设置 `DEVICE_REMOTE_WAKEUP`；唤醒经
+    /// [`USBHost::next_hotplug_event`] 以 [`HotplugEvent::RemoteWakeup`] 报告。
+    /// 支持根端口与 USB 2.0 外部 Hub 端口上的非 Hub 设备，Hub 与 SuperSpeed Hub
+    /// 下游的设备返回 [`USBError::NotSupported`](crate::err::USBError::NotSupported)。
+    #[cfg(kmod)]
+    pub async fn suspend_device(&mut self, device_id: usize) -> Result<()> {
+        self.backend.suspend_device(device_id).await
+    }
+
+    /// 恢复挂起的设备，返回时端口链路已回到 U0，可继续传输
+    #[cfg(kmod)]
+    pub async fn resume_device(&mut self, device_id: usize) -> Result<()> {
+        self.backend.resume_device(device_id).await
+    }
+
+    /// 已连接但尚未枚举的根端口，见 [`EnumerationMode::Lazy`]
+    #[cfg(kmod)]
+    pub fn pending_ports(&self) -> Vec<PendingPort> {
+        self.backend.pending_ports()
+    }
+
+    /// 枚举延迟的根端口 `port`，返回其设备；设备为 Hub 时还包括其下游设备
+    ///
+    /// 端口不在 [`USBHost::pending_ports`] 中时返回
+    /// [`USBError::NotFound`](crate::err::USBError::NotFound)。
+    #[cfg(kmod)]
+    pub async fn enumerate_port(&mut self, port: u8) -> Result<Vec<ProbedDevice>> {
+        let devices = self.backend.enumerate_port(port).await?;
+        Ok(devices.into_iter().map(probed_device).collect())
+    }
+
+    /// 开启控制器时钟看门狗，之后需周期性调用 [`USBHost::poll_watchdog`]，或由
+    /// [`USBHost::spawn_monitor`] 的后台任务检查
+    #[cfg(kmod)]
+    pub fn enable_watchdog(&mut self, config: WatchdogConfig) {
+        self.backend.enable_watchdog(config)
+    }
+
+    /// 检查 MFINDEX 是否仍在增长，检查周期应小于 [`WatchdogConfig::timeout`]
+    ///
+    /// 时钟丢失时所有传输都会停滞，调用方不应在等待传输的任务中调用本方法。
+    /// 未开启看门狗时返回 [`USBError::NotInitialized`](crate::err::USBError::NotInitialized)。
+    #[cfg(kmod)]
+    pub async fn poll_watchdog(&mut self) -> Result<Option<WatchdogEvent>> {
+        self.backend.poll_watchdog().await
+    }
+
+    /// 注册端口状态回调，用于驱动前面板 LED 或板级诊断，重复调用时替换之前的回调
+    ///
+    /// 回调在 [`USBHost::probe_devices`] 与热插拔处理中同步调用。外部 Hub 的端口
+    /// 变化在下次探测设备时报告。
+    #[cfg(kmod)]
+    pub fn set_port_event_callback(
+        &mut self,
+        callback: impl Fn(&PortEvent) + Send + Sync + 'static,
+    ) {
+        self.backend.set_port_event_callback(Box::new(callback))
+    }
+
+    /// 订阅端口状态变化，内容与 [`USBHost::set_port_event_callback`] 的回调相同
+    ///
+    /// 可以同时存在多个订阅，各自独立排队。配合 [`USBHost::ports`] 的初始快照
+    /// 即可维护实时的拓扑视图。
+    #[cfg(kmod)]
+    pub fn subscribe_ports(&mut self) -> PortSubscription {
+        self.backend.subscribe_ports()
+    }
+
+    /// 根 Hub 与各外部 Hub 下游端口的状态快照，按端口路径排序
+    ///
+    /// 根端口直接读取寄存器；外部 Hub 端口为最近一次处理其端口变化时的状态，
+    /// 不发起控制传输。
+    #[cfg(kmod)]
+    pub fn ports(&self) -> impl Iterator<Item = PortSnapshot> + use<> {
+        self.backend.ports().into_iter()
+    }
+
+    /// 已枚举的外部 Hub，与其 [`HubDeviceInfo::id`] 成对返回，按编号排序
+    ///
+    /// 根 Hub 的端口见 [`USBHost::ports`]。
+    #[cfg(kmod)]
+    pub fn hubs(&self) -> impl Iterator<Item = (usize, &HubDevice)> {
+        self.backend.hubs().into_iter()
+    }
+
+    /// 使外部 Hub 的下游端口 `port`（从 1 开始）进入 USB 2.0 电气测试模式，用于合规测试
+    ///
+    /// `hub_device_id` 为 [`HubDeviceInfo::id`]。Hub 上其余已启用的端口会先被挂起，
+    /// 测试结束后需要复位 Hub 或重新上电。SuperSpeed Hub 返回
+    /// [`USBError::NotSupported`](crate::err::USBError::NotSupported)。
+    #[cfg(kmod)]
+    pub async fn set_hub_port_test_mode(
+        &mut self,
+        hub_device_id: usize,
+        port: u8,
+        selector: usb_if::host::hub::TestSelector,
+    ) -> Result<()> {
+        self.backend
+            .set_hub_port_test_mode(hub_device_id, port, selector)
+            .await
+    }
+
+    /// 获取 OTG 端口 VBUS/ID 监视器，用于检测数据角色变化
+    #[cfg(kmod)]
+    pub fn extcon(&self) -> Option<Usb2PhyExtcon> {
+        self.backend.extcon()
+    }
+
     pub async fn open_device(&mut self, dev: &DeviceInfo) -> Result<Device> {
         let device = self.backend.open_device(dev.inner.as_ref()).await?;
         let mut device: Device = device.into();
+        device.spawner = self.spawner.clone();
         device.init().await?;
         Ok(device)
     }
 }
 
+fn probed_device(dev: ProbedDeviceInfoOp) -> ProbedDevice {
+    match dev {
+        #[cfg(any(kmod, umod, nmod, mmod))]
+        ProbedDeviceInfoOp::Device(inner) => ProbedDevice::Device(DeviceInfo { inner }),
+        #[cfg(any(kmod, umod, nmod, mmod))]
+        ProbedDeviceInfoOp::Hub(inner) => ProbedDevice::Hub(HubDeviceInfo { inner }),
+    }
+}
+
 pub struct EventHandler {
     handler: Box<dyn EventHandlerOp>,
 }
 
 impl EventHandler {
     /// 处理事件
+    ///
+    /// 中断安全：可在中断中调用，也可在任务中轮询，但同一控制器不能并发调用。
+    /// 内部只操作寄存器与无锁完成槽，不获取任务侧持有的锁，也不输出日志，
+    /// 因此打断正在提交传输的任务时不会死锁。
     pub fn handle_event(&self) -> Event {
         self.handler.handle_event()
     }
+
+    /// 只处理第 `index` 个中断器的事件，用于 MSI-X 下每个向量各自的中断处理函数
+    ///
+    /// 不同中断器可在不同 CPU 上并发调用，同一中断器不能并发调用。端口事件与命令完成
+    /// 总是由 0 号中断器报告。
+    pub fn handle_interrupter(&self, index: u16) -> Event {
+        self.handler.handle_interrupter(index)
+    }
+
+    /// 已启用的中断器数，即 MSI-X 下需要分配的向量数
+    ///
+    /// 向量 `i` 的中断处理函数调用 [`EventHandler::handle_interrupter`]`(i)`；
+    /// 只有一个中断源（INTx 或单个 MSI）时调用 [`EventHandler::handle_event`] 即可处理全部中断器。
+    pub fn interrupter_count(&self) -> u16 {
+        self.handler.interrupter_count()
+    }
 }
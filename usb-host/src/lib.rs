@@ -1,4 +1,27 @@
 #![cfg_attr(target_os = "none", no_std)]
+
+#[cfg(all(feature = "libusb", feature = "vfio", target_os = "linux"))]
+compile_error!("features `libusb` and `vfio` are mutually exclusive");
+
+#[cfg(all(
+    feature = "nusb",
+    any(feature = "libusb", all(feature = "vfio", target_os = "linux"))
+))]
+compile_error!("feature `nusb` is mutually exclusive with `libusb` and `vfio`");
+
+#[cfg(all(feature = "mock", feature = "vfio", target_os = "linux"))]
+compile_error!("features `mock` and `vfio` are mutually exclusive");
+
+#[cfg(all(feature = "tokio", target_os = "none"))]
+compile_error!(
+    "feature `tokio` needs a hosted target; implement `Spawner` on the kernel executor instead"
+);
+
+#[cfg(all(kmod, target_endian = "big"))]
+compile_error!(
+    "the kmod backend accesses controller registers in native byte order and does not support big-endian targets"
+);
+
 #[macro_use]
 extern crate alloc;
 #[macro_use]
@@ -17,10 +40,24 @@ pub(crate) mod backend;
 pub mod device;
 pub mod err;
 mod host;
+mod modeswitch;
+mod raw;
+mod spawn;
+mod string_cache;
+mod system;
 
 pub use crate::backend::ty::Event;
-pub use crate::backend::ty::ep::Endpoint;
+pub use crate::backend::ty::ep::{
+    Bulk, BulkIn, BulkOut, CoalesceConfig, CoalesceStats, Endpoint, EndpointDirection,
+    EndpointKind, In, Interrupt, InterruptIn, InterruptOut, IsoBuffer, IsoFiller, IsoOutStats,
+    Isochronous, IsochronousIn, IsochronousOut, Out, RetryPolicy, TypedEndpoint,
+};
 pub use host::*;
+pub use modeswitch::*;
+pub use raw::*;
+pub use spawn::*;
+pub use string_cache::StringCache;
+pub use system::*;
 
 #[allow(unused_imports)]
 #[cfg(kmod)]
@@ -0,0 +1,172 @@
+use alloc::vec::Vec;
+
+use usb_if::{
+    descriptor::EndpointType,
+    endpoint::TransferRequest,
+    err::{TransferError, USBError},
+    host::ControlSetup,
+    transfer::Direction,
+};
+
+use crate::device::Device;
+
+/// 模式切换消息，参考 usb_modeswitch
+#[derive(Debug, Clone)]
+pub enum ModeSwitchMessage {
+    /// 通过接口 0 的批量 OUT 端点发送的原始消息，通常是 CBW（usb_modeswitch 的 MessageContent）
+    ///
+    /// `read_response` 为真时随后从批量 IN 端点读取 13 字节的 CSW。
+    Bulk { data: Vec<u8>, read_response: bool },
+    /// 厂商控制请求，如部分华为设备使用的 SET_FEATURE
+    Control { setup: ControlSetup, data: Vec<u8> },
+}
+
+impl ModeSwitchMessage {
+    const CBW_SIGNATURE: u32 = 0x4342_5355;
+
+    /// 以 SCSI 命令构造无数据阶段的 CBW，随后读取 CSW
+    pub fn scsi(tag: u32, lun: u8, cdb: &[u8]) -> Self {
+        let cdb_len = cdb.len().min(16);
+        let mut data = Vec::with_capacity(31);
+        data.extend(&Self::CBW_SIGNATURE.to_le_bytes());
+        data.extend(&tag.to_le_bytes());
+        // dCBWDataTransferLength 与 bmCBWFlags
+        data.extend(&0u32.to_le_bytes());
+        data.push(0);
+        data.push(lun);
+        data.push(cdb_len as u8);
+        data.extend(&cdb[..cdb_len]);
+        data.resize(31, 0);
+        Self::Bulk {
+            data,
+            read_response: true,
+        }
+    }
+
+    /// 标准大容量存储弹出序列，与 usb_modeswitch 的 StandardEject 相同
+    ///
+    /// 对 LUN 0、1 依次发送 PREVENT ALLOW MEDIUM REMOVAL（允许移除）与
+    /// START STOP UNIT（LoEj），适用于多数以虚拟光驱形式出现的 4G 网卡。
+    pub fn msc_eject() -> Vec<Self> {
+        const ALLOW_MEDIUM_REMOVAL: [u8; 6] = [0x1e, 0, 0, 0, 0, 0];
+        const EJECT: [u8; 6] = [0x1b, 0, 0, 0, 0x02, 0];
+        (0..2)
+            .flat_map(|lun| {
+                [
+                    Self::scsi(0x2143_6587, lun, &ALLOW_MEDIUM_REMOVAL),
+                    Self::scsi(0x2143_6597, lun, &EJECT),
+                ]
+            })
+            .collect()
+    }
+}
+
+/// 模式切换之后设备的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeSwitchOutcome {
+    /// 描述符没有变化
+    Unchanged,
+    /// 设备原地切换，描述符已重新读取
+    Reconfigured,
+    /// 设备已断开，将以新的身份重新枚举，见 [`USBHost::watch`](crate::USBHost::watch)
+    Disconnected,
+}
+
+impl Device {
+    /// 依次发送模式切换消息，然后重新读取描述符
+    ///
+    /// 第一条消息失败时返回错误；之后的失败视为设备已开始切换，不再发送剩余消息。
+    /// 重新读取描述符失败时认为设备已断开并将重新枚举。
+    pub async fn mode_switch(
+        &mut self,
+        messages: &[ModeSwitchMessage],
+    ) -> Result<ModeSwitchOutcome, USBError> {
+        for (i, message) in messages.iter().enumerate() {
+            if let Err(e) = self.send_mode_switch_message(message).await {
+                if i == 0 {
+                    return Err(e);
+                }
+                debug!("Mode switch message {i} failed, device may be switching: {e:?}");
+                break;
+            }
+        }
+
+        match self.reload_descriptors().await {
+            Ok(true) => Ok(ModeSwitchOutcome::Reconfigured),
+            Ok(false) => Ok(ModeSwitchOutcome::Unchanged),
+            Err(e) => {
+                debug!("Reload descriptors after mode switch: {e:?}");
+                Ok(ModeSwitchOutcome::Disconnected)
+            }
+        }
+    }
+
+    async fn send_mode_switch_message(
+        &mut self,
+        message: &ModeSwitchMessage,
+    ) -> Result<(), USBError> {
+        let (data, read_response) = match message {
+            ModeSwitchMessage::Control { setup, data } => {
+                self.control_out(setup.clone(), data).await?;
+                return Ok(());
+            }
+            ModeSwitchMessage::Bulk {
+                data,
+                read_response,
+            } => (data, *read_response),
+        };
+
+        let mut interface = self.claim(0, 0).await?;
+        let mut result = Ok(());
+        let mut response = [0u8; 13];
+        for direction in [Direction::Out, Direction::In] {
+            if direction == Direction::In && !read_response {
+                break;
+            }
+            let Some((_, ep)) = interface.endpoints_mut().find(|(_, ep)| {
+                let info = ep.info();
+                info.transfer_type == EndpointType::Bulk && info.direction == direction
+            }) else {
+                result = Err(TransferError::InvalidEndpoint);
+                break;
+            };
+            let request = match direction {
+                Direction::Out => TransferRequest::bulk_out(data),
+                Direction::In => TransferRequest::bulk_in(&mut response),
+            };
+            if let Err(e) = ep.wait(request).await {
+                result = Err(e);
+                break;
+            }
+        }
+        if let Err(e) = self.release_interface(interface).await {
+            debug!("Release interface after mode switch message: {e:?}");
+        }
+        Ok(result?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eject_sequence_matches_usb_modeswitch() {
+        let messages = ModeSwitchMessage::msc_eject();
+        assert_eq!(messages.len(), 4);
+        let ModeSwitchMessage::Bulk {
+            data,
+            read_response,
+        } = &messages[3]
+        else {
+            panic!("expected bulk message");
+        };
+        assert!(read_response);
+        // usb_modeswitch: 5553424397654321000000000001061b000000020000000000000000000000
+        let mut expected = vec![
+            0x55, 0x53, 0x42, 0x43, 0x97, 0x65, 0x43, 0x21, 0, 0, 0, 0, 0, 1, 6, 0x1b, 0, 0, 0, 2,
+        ];
+        expected.resize(31, 0);
+        assert_eq!(data, &expected);
+    }
+}
@@ -0,0 +1,305 @@
+use core::time::Duration;
+
+use alloc::vec::Vec;
+
+use usb_if::{
+    descriptor::EndpointType,
+    endpoint::TransferRequest,
+    err::{TransferError, USBError},
+    host::ControlSetup,
+    transfer::{Direction, Recipient},
+};
+
+use crate::{
+    Endpoint,
+    device::{Device, Interface},
+};
+
+/// 厂商自定义接口类
+const CLASS_VENDOR_SPECIFIC: u8 = 0xff;
+
+/// 简化的厂商设备句柄，便于移植基于 libusb 的工具
+///
+/// 声明一个接口后按传输类型与方向选用其第一个端点，提供与 libusb 同步 API 对应的
+/// `read_bulk` / `write_bulk` / `read_interrupt` / 控制请求方法。读取长度不是最大包长
+/// 整数倍时经内部缓冲区接收，多出的数据留给下一次读取，不会因设备发满一个包而溢出。
+///
+/// ```ignore
+/// let mut dev = RawDevice::open(device).await?;
+/// dev.set_timeout(Some(Duration::from_secs(1)));
+/// dev.write_vendor(0x01, 0, 0, &[]).await?;
+/// dev.write_bulk(b"ping").await?;
+/// let n = dev.read_bulk(&mut buf).await?;
+/// ```
+pub struct RawDevice {
+    device: Device,
+    interface: Interface,
+    bulk_in: Option<u8>,
+    bulk_out: Option<u8>,
+    interrupt_in: Option<u8>,
+    interrupt_out: Option<u8>,
+    zero_length_packet: bool,
+    bulk_rx: RxBuffer,
+    interrupt_rx: RxBuffer,
+}
+
+impl RawDevice {
+    /// 声明第一个厂商自定义（bInterfaceClass 0xFF）接口的默认设置
+    pub async fn open(device: Device) -> Result<Self, USBError> {
+        let interface = device
+            .configurations()
+            .first()
+            .ok_or(USBError::NotFound)?
+            .interfaces
+            .iter()
+            .map(|iface| iface.first_alt_setting())
+            .find(|alt| alt.class == CLASS_VENDOR_SPECIFIC)
+            .ok_or(USBError::NotFound)?
+            .interface_number;
+        Self::open_interface(device, interface, 0).await
+    }
+
+    /// 声明指定的接口与备用设置，接口类不限
+    pub async fn open_interface(
+        mut device: Device,
+        interface: u8,
+        alternate: u8,
+    ) -> Result<Self, USBError> {
+        let mut interface = device.claim(interface, alternate).await?;
+        let mut find = |ty: EndpointType, dir: Direction| {
+            interface
+                .endpoints_mut()
+                .find(|(_, ep)| {
+                    let info = ep.info();
+                    info.transfer_type == ty && info.direction == dir
+                })
+                .map(|(address, _)| address)
+        };
+        let bulk_in = find(EndpointType::Bulk, Direction::In);
+        let bulk_out = find(EndpointType::Bulk, Direction::Out);
+        let interrupt_in = find(EndpointType::Interrupt, Direction::In);
+        let interrupt_out = find(EndpointType::Interrupt, Direction::Out);
+        debug!(
+            "Raw device interface {}: bulk in {bulk_in:x?}, bulk out {bulk_out:x?}, \
+             interrupt in {interrupt_in:x?}, interrupt out {interrupt_out:x?}",
+            interface.number()
+        );
+
+        Ok(Self {
+            device,
+            interface,
+            bulk_in,
+            bulk_out,
+            interrupt_in,
+            interrupt_out,
+            zero_length_packet: false,
+            bulk_rx: RxBuffer::default(),
+            interrupt_rx: RxBuffer::default(),
+        })
+    }
+
+    /// 释放接口，取回设备
+    pub async fn close(self) -> Result<Device, USBError> {
+        let Self {
+            mut device,
+            interface,
+            ..
+        } = self;
+        device.release_interface(interface).await?;
+        Ok(device)
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn device_mut(&mut self) -> &mut Device {
+        &mut self.device
+    }
+
+    /// 已声明的接口，可直接访问其全部端点
+    pub fn interface_mut(&mut self) -> &mut Interface {
+        &mut self.interface
+    }
+
+    /// 设置接口全部端点的传输超时，默认不超时，见 [`Endpoint::set_timeout`]
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        for (_, ep) in self.interface.endpoints_mut() {
+            ep.set_timeout(timeout);
+        }
+    }
+
+    /// 批量写入长度为最大包长整数倍时，是否追加零长度包结束传输，默认不追加
+    pub fn set_zero_length_packet(&mut self, enable: bool) {
+        self.zero_length_packet = enable;
+    }
+
+    /// 从批量 IN 端点读取一次，返回读取的字节数
+    ///
+    /// 先返回上一次读取多出的数据；设备发送短包时返回的长度小于 `buf`。
+    pub async fn read_bulk(&mut self, buf: &mut [u8]) -> Result<usize, USBError> {
+        let ep = endpoint(&mut self.interface, self.bulk_in)?;
+        Ok(self.bulk_rx.read(ep, buf, TransferRequest::bulk_in).await?)
+    }
+
+    /// 向批量 OUT 端点写入 `data`，返回写入的字节数
+    pub async fn write_bulk(&mut self, data: &[u8]) -> Result<usize, USBError> {
+        let zlp = self.zero_length_packet;
+        let ep = endpoint(&mut self.interface, self.bulk_out)?;
+        let t = ep.wait(TransferRequest::bulk_out(data)).await?;
+        let mps = ep.info().max_packet_size as usize;
+        if zlp && !data.is_empty() && data.len().is_multiple_of(mps.max(1)) {
+            ep.wait(TransferRequest::bulk_out(&[])).await?;
+        }
+        Ok(t.actual_length)
+    }
+
+    /// 从中断 IN 端点读取一次，同 [`RawDevice::read_bulk`]
+    pub async fn read_interrupt(&mut self, buf: &mut [u8]) -> Result<usize, USBError> {
+        let ep = endpoint(&mut self.interface, self.interrupt_in)?;
+        Ok(self
+            .interrupt_rx
+            .read(ep, buf, TransferRequest::interrupt_in)
+            .await?)
+    }
+
+    /// 向中断 OUT 端点写入 `data`，返回写入的字节数
+    pub async fn write_interrupt(&mut self, data: &[u8]) -> Result<usize, USBError> {
+        let ep = endpoint(&mut self.interface, self.interrupt_out)?;
+        let t = ep.wait(TransferRequest::interrupt_out(data)).await?;
+        Ok(t.actual_length)
+    }
+
+    /// 厂商 IN 请求，接收者为设备，返回读取的字节数
+    pub async fn read_vendor(
+        &mut self,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+    ) -> Result<usize, USBError> {
+        let setup = ControlSetup::vendor(Recipient::Device, request, value, index);
+        Ok(self.device.control_in(setup, buf).await?)
+    }
+
+    /// 厂商 OUT 请求，接收者为设备，返回写入的字节数
+    pub async fn write_vendor(
+        &mut self,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+    ) -> Result<usize, USBError> {
+        let setup = ControlSetup::vendor(Recipient::Device, request, value, index);
+        Ok(self.device.control_out(setup, data).await?)
+    }
+
+    /// 任意 IN 控制请求，对应 libusb_control_transfer
+    pub async fn control_in(
+        &mut self,
+        setup: ControlSetup,
+        buf: &mut [u8],
+    ) -> Result<usize, USBError> {
+        Ok(self.device.control_in(setup, buf).await?)
+    }
+
+    /// 任意 OUT 控制请求
+    pub async fn control_out(
+        &mut self,
+        setup: ControlSetup,
+        data: &[u8],
+    ) -> Result<usize, USBError> {
+        Ok(self.device.control_out(setup, data).await?)
+    }
+
+    /// 丢弃读取缓冲区中剩余的数据
+    pub fn discard_buffered(&mut self) {
+        self.bulk_rx.clear();
+        self.interrupt_rx.clear();
+    }
+}
+
+fn endpoint(interface: &mut Interface, address: Option<u8>) -> Result<&mut Endpoint, USBError> {
+    address
+        .and_then(|address| interface.endpoint(address))
+        .ok_or(TransferError::InvalidEndpoint.into())
+}
+
+/// IN 端点的接收缓冲区
+#[derive(Default)]
+struct RxBuffer {
+    data: Vec<u8>,
+    offset: usize,
+}
+
+impl RxBuffer {
+    fn clear(&mut self) {
+        self.data.clear();
+        self.offset = 0;
+    }
+
+    /// 取出缓冲的数据，返回复制的字节数
+    fn take(&mut self, buf: &mut [u8]) -> usize {
+        let pending = &self.data[self.offset..];
+        let n = pending.len().min(buf.len());
+        buf[..n].copy_from_slice(&pending[..n]);
+        self.offset += n;
+        if self.offset == self.data.len() {
+            self.clear();
+        }
+        n
+    }
+
+    async fn read(
+        &mut self,
+        ep: &mut Endpoint,
+        buf: &mut [u8],
+        request: impl Fn(&mut [u8]) -> TransferRequest,
+    ) -> Result<usize, TransferError> {
+        if self.offset < self.data.len() || buf.is_empty() {
+            return Ok(self.take(buf));
+        }
+
+        let mps = (ep.info().max_packet_size as usize).max(1);
+        if buf.len().is_multiple_of(mps) {
+            let t = ep.wait(request(buf)).await?;
+            return Ok(t.actual_length);
+        }
+
+        // 长度向上取整到最大包长，设备发满最后一个包时不会溢出
+        self.data.resize(buf.len().next_multiple_of(mps), 0);
+        let t = ep.wait(request(&mut self.data)).await;
+        match t {
+            Ok(t) => {
+                self.data.truncate(t.actual_length);
+                Ok(self.take(buf))
+            }
+            Err(e) => {
+                self.clear();
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rx_buffer_keeps_leftover() {
+        let mut rx = RxBuffer {
+            data: (0..10).collect(),
+            offset: 0,
+        };
+        let mut buf = [0u8; 4];
+        assert_eq!(rx.take(&mut buf), 4);
+        assert_eq!(buf, [0, 1, 2, 3]);
+        assert_eq!(rx.take(&mut buf), 4);
+        assert_eq!(buf, [4, 5, 6, 7]);
+        assert_eq!(rx.take(&mut buf), 2);
+        assert_eq!(&buf[..2], &[8, 9]);
+        assert!(rx.data.is_empty());
+        assert_eq!(rx.take(&mut buf), 0);
+    }
+}
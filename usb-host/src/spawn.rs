@@ -0,0 +1,52 @@
+//! 后台任务执行器
+//!
+//! [`Spawner`] 定义在 `crab-usb-hal` 中，由内核或应用提供。启用 `tokio` 特性后可直接
+//! 使用 [`TokioSpawner`]。
+
+pub use crab_usb_hal::{Spawner, Task};
+
+/// 在 tokio 运行时上运行后台任务
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone)]
+pub struct TokioSpawner {
+    handle: tokio::runtime::Handle,
+}
+
+#[cfg(feature = "tokio")]
+impl TokioSpawner {
+    pub fn new(handle: tokio::runtime::Handle) -> Self {
+        Self { handle }
+    }
+
+    /// 使用当前所在的 tokio 运行时，不在运行时中调用时 panic
+    pub fn current() -> Self {
+        Self::new(tokio::runtime::Handle::current())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Spawner for TokioSpawner {
+    fn spawn(&self, task: Task) {
+        // 任务自行结束，不需要 JoinHandle
+        drop(self.handle.spawn(task));
+    }
+}
+
+/// 单元测试使用的执行器，只记录任务，由测试自行轮询
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct TaskQueue(spin::Mutex<alloc::vec::Vec<Task>>);
+
+#[cfg(test)]
+impl TaskQueue {
+    pub fn pop(&self) -> Option<Task> {
+        self.0.lock().pop()
+    }
+}
+
+#[cfg(test)]
+impl Spawner for TaskQueue {
+    fn spawn(&self, task: Task) {
+        self.0.lock().push(task);
+    }
+}
@@ -0,0 +1,107 @@
+//! 设备字符串描述符缓存
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use usb_if::descriptor::{DescriptorType, LanguageId};
+
+/// 设备字符串描述符缓存
+///
+/// 字符串描述符 0 的 LANGID 表只读取一次；解码后的字符串按（LANGID，索引）缓存，
+/// 切换语言后首次读取时重新向设备请求。
+#[derive(Debug, Default, Clone)]
+pub struct StringCache {
+    languages: Option<Vec<LanguageId>>,
+    selected: Option<LanguageId>,
+    strings: BTreeMap<(u16, u8), String>,
+}
+
+impl StringCache {
+    /// 设备支持的语言，尚未读取 LANGID 表时为 `None`
+    pub fn languages(&self) -> Option<&[LanguageId]> {
+        self.languages.as_deref()
+    }
+
+    /// 读取字符串使用的语言
+    ///
+    /// 优先使用调用者选择的语言，其次是 LANGID 表中的第一项，都没有时为美式英语。
+    pub fn language(&self) -> LanguageId {
+        self.selected
+            .or_else(|| self.languages.as_ref()?.first().copied())
+            .unwrap_or_default()
+    }
+
+    /// 当前语言下已缓存的字符串
+    pub fn get(&self, index: u8) -> Option<&str> {
+        let lang: u16 = self.language().into();
+        self.strings.get(&(lang, index)).map(String::as_str)
+    }
+
+    /// 清空 LANGID 表与已缓存的字符串，保留调用者选择的语言
+    pub fn clear(&mut self) {
+        self.languages = None;
+        self.strings.clear();
+    }
+
+    pub(crate) fn select(&mut self, lang: LanguageId) {
+        self.selected = Some(lang);
+    }
+
+    /// 记录字符串描述符 0 的内容，格式不对时视为设备不提供 LANGID 表
+    pub(crate) fn set_languages(&mut self, data: &[u8]) {
+        self.languages = Some(parse_languages(data));
+    }
+
+    pub(crate) fn insert(&mut self, index: u8, s: String) {
+        let lang: u16 = self.language().into();
+        self.strings.insert((lang, index), s);
+    }
+}
+
+fn parse_languages(data: &[u8]) -> Vec<LanguageId> {
+    if data.len() < 2 || data[1] != DescriptorType::STRING.0 {
+        return Vec::new();
+    }
+    let len = (data[0] as usize).min(data.len());
+    data.get(2..len)
+        .unwrap_or_default()
+        .chunks_exact(2)
+        .map(|c| LanguageId::from(u16::from_le_bytes([c[0], c[1]])))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages_follow_table_until_selected() {
+        let mut cache = StringCache::default();
+        assert_eq!(cache.language(), LanguageId::EnglishUnitedStates);
+
+        // bLength 为 6，其后的 0x0409 不属于描述符
+        cache.set_languages(&[6, 3, 0x07, 0x04, 0x04, 0x08, 0x09, 0x04]);
+        assert_eq!(
+            cache.languages().unwrap(),
+            [LanguageId::GermanStandard, LanguageId::ChinesePRC]
+        );
+        assert_eq!(cache.language(), LanguageId::GermanStandard);
+
+        cache.insert(1, "Hersteller".into());
+        cache.select(LanguageId::ChinesePRC);
+        assert_eq!(cache.get(1), None);
+        cache.insert(1, "制造商".into());
+        assert_eq!(cache.get(1), Some("制造商"));
+
+        cache.clear();
+        assert_eq!(cache.languages(), None);
+        assert_eq!(cache.language(), LanguageId::ChinesePRC);
+    }
+
+    #[test]
+    fn malformed_table_is_empty() {
+        let mut cache = StringCache::default();
+        cache.set_languages(&[4, 2, 0x09, 0x04]);
+        assert_eq!(cache.languages(), Some(&[][..]));
+        assert_eq!(cache.language(), LanguageId::EnglishUnitedStates);
+    }
+}
@@ -0,0 +1,256 @@
+use alloc::{collections::BTreeSet, vec::Vec};
+
+use futures::{FutureExt, Stream};
+
+use crate::err::{Result, USBError};
+use crate::host::{Device, DeviceInfo, HotplugEvent, ProbedDevice, USBHost};
+
+#[cfg(kmod)]
+use crate::{Event, host::EventHandler};
+
+/// 控制器在 [`UsbSystem`] 中的编号，按加入顺序从 0 开始
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ControllerId(pub usize);
+
+/// 带控制器编号的设备
+pub struct SystemDevice {
+    pub controller: ControllerId,
+    pub device: ProbedDevice,
+}
+
+/// 多个主机控制器的集合
+///
+/// 例如 RK3588 上的两个 DWC3 加上 PCIe xHCI 卡。设备列表与事件都带有
+/// [`ControllerId`]，打开设备时按编号转发到对应控制器。
+#[derive(Default)]
+pub struct UsbSystem {
+    hosts: Vec<USBHost>,
+    /// 热插拔出错后不再等待的控制器
+    hotplug_stopped: BTreeSet<ControllerId>,
+}
+
+impl UsbSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 加入一个控制器，返回其编号
+    pub fn add(&mut self, host: USBHost) -> ControllerId {
+        self.hosts.push(host);
+        ControllerId(self.hosts.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    pub fn host(&self, id: ControllerId) -> Option<&USBHost> {
+        self.hosts.get(id.0)
+    }
+
+    pub fn host_mut(&mut self, id: ControllerId) -> Option<&mut USBHost> {
+        self.hosts.get_mut(id.0)
+    }
+
+    fn host_or_err(&mut self, id: ControllerId) -> Result<&mut USBHost> {
+        self.hosts.get_mut(id.0).ok_or(USBError::InvalidParameter)
+    }
+
+    /// 依次初始化全部控制器
+    ///
+    /// 单个控制器初始化失败不影响其余控制器，返回初始化失败的控制器及其错误。
+    pub async fn init(&mut self) -> Vec<(ControllerId, USBError)> {
+        let mut failed = Vec::new();
+        for (i, host) in self.hosts.iter_mut().enumerate() {
+            if let Err(e) = host.init().await {
+                warn!("controller {i} init failed: {e:?}");
+                failed.push((ControllerId(i), e));
+            }
+        }
+        failed
+    }
+
+    /// 枚举全部控制器下的设备
+    ///
+    /// 结果按控制器编号排列，同一控制器内的顺序见 [`USBHost::probe_devices`]。
+    /// 某个控制器枚举失败时跳过该控制器并记录日志。
+    pub async fn probe_devices(&mut self) -> Vec<SystemDevice> {
+        let mut out = Vec::new();
+        for (i, host) in self.hosts.iter_mut().enumerate() {
+            match host.probe_devices().await {
+                Ok(devices) => out.extend(devices.into_iter().map(|device| SystemDevice {
+                    controller: ControllerId(i),
+                    device,
+                })),
+                Err(e) => warn!("controller {i} probe failed: {e:?}"),
+            }
+        }
+        out
+    }
+
+    /// 在 `controller` 上打开设备，`dev` 须来自同一控制器的枚举结果
+    pub async fn open_device(
+        &mut self,
+        controller: ControllerId,
+        dev: &DeviceInfo,
+    ) -> Result<Device> {
+        self.host_or_err(controller)?.open_device(dev).await
+    }
+
+    /// 同时等待全部控制器，返回最先产生的热插拔事件
+    ///
+    /// 某个控制器出错（如未初始化或平台不支持热插拔）时返回该错误，之后不再等待它，
+    /// 其余控制器不受影响；全部控制器都已停止时返回 `None`。
+    pub async fn next_hotplug_event(&mut self) -> Option<(ControllerId, Result<HotplugEvent>)> {
+        loop {
+            let stopped = &self.hotplug_stopped;
+            let waits: Vec<_> = self
+                .hosts
+                .iter_mut()
+                .enumerate()
+                .filter(|(i, _)| !stopped.contains(&ControllerId(*i)))
+                .map(|(i, host)| host.wait_hotplug().map(move |_| ControllerId(i)).boxed())
+                .collect();
+            if waits.is_empty() {
+                return None;
+            }
+            // 各控制器的等待可取消，未完成的变化留给下一次调用
+            let (id, _, _) = futures::future::select_all(waits).await;
+            match self.hosts[id.0].poll_hotplug().await {
+                Ok(Some(event)) => return Some((id, Ok(event))),
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("controller {} hotplug stopped: {e:?}", id.0);
+                    self.hotplug_stopped.insert(id);
+                    return Some((id, Err(e)));
+                }
+            }
+        }
+    }
+
+    /// 热插拔事件流，逐个产生 [`UsbSystem::next_hotplug_event`] 的结果
+    pub fn watch(&mut self) -> impl Stream<Item = (ControllerId, Result<HotplugEvent>)> + '_ {
+        futures::stream::unfold(self, |system| async move {
+            let event = system.next_hotplug_event().await?;
+            Some((event, system))
+        })
+    }
+
+    /// 为全部控制器创建事件处理器
+    #[cfg(kmod)]
+    pub fn create_event_handler(&mut self) -> SystemEventHandler {
+        let handlers = self
+            .hosts
+            .iter_mut()
+            .enumerate()
+            .map(|(i, host)| (ControllerId(i), host.create_event_handler()))
+            .collect();
+        SystemEventHandler { handlers }
+    }
+}
+
+/// 多个控制器的事件处理器
+///
+/// 各控制器有独立的中断时，也可以通过 [`SystemEventHandler::get`] 取出单个处理器在
+/// 对应中断中调用。
+#[cfg(kmod)]
+pub struct SystemEventHandler {
+    handlers: Vec<(ControllerId, EventHandler)>,
+}
+
+#[cfg(kmod)]
+impl SystemEventHandler {
+    pub fn get(&self, id: ControllerId) -> Option<&EventHandler> {
+        self.handlers
+            .iter()
+            .find(|(handler_id, _)| *handler_id == id)
+            .map(|(_, handler)| handler)
+    }
+
+    /// 依次处理各控制器的事件，对每个非 [`Event::Nothing`] 事件调用 `f`
+    ///
+    /// 中断安全性与 [`EventHandler::handle_event`] 相同。
+    pub fn handle_events(&self, mut f: impl FnMut(ControllerId, Event)) {
+        for (id, handler) in &self.handlers {
+            match handler.handle_event() {
+                Event::Nothing => {}
+                event => f(*id, event),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, kmod))]
+mod tests {
+    use alloc::vec;
+
+    use futures::{FutureExt, StreamExt};
+
+    use super::*;
+    use crate::backend::kmod::{
+        Core,
+        test_core::{MockCore, MockHub, PortLog},
+    };
+
+    fn mock_host(slot: u8) -> (USBHost, alloc::sync::Arc<std::sync::Mutex<PortLog>>) {
+        let (root, log) = MockHub::new(slot);
+        (USBHost::new(MockCore::new(root)), log)
+    }
+
+    fn core(host: &mut USBHost) -> &mut Core {
+        (host.backend.as_mut() as &mut dyn core::any::Any)
+            .downcast_mut()
+            .unwrap()
+    }
+
+    #[test]
+    fn hotplug_events_carry_their_controller() {
+        let mut system = UsbSystem::new();
+        let (first, first_log) = mock_host(0);
+        let (second, second_log) = mock_host(0);
+        system.add(first);
+        let second = system.add(second);
+        assert!(system.init().now_or_never().unwrap().is_empty());
+        core(system.host_mut(second).unwrap()).insert_device(7, vec![2], false);
+
+        assert!(system.next_hotplug_event().now_or_never().is_none());
+        // 第一个控制器的端口变化没有产生事件，不影响等待第二个控制器
+        first_log.lock().unwrap().wakeups.push(1);
+        second_log.lock().unwrap().wakeups.push(2);
+        let (id, event) = system.next_hotplug_event().now_or_never().unwrap().unwrap();
+        assert_eq!(id, second);
+        assert!(matches!(event, Ok(HotplugEvent::RemoteWakeup { id: 7 })));
+        assert_eq!(first_log.lock().unwrap().resumed, [1]);
+        assert!(system.next_hotplug_event().now_or_never().is_none());
+    }
+
+    #[test]
+    fn failed_controllers_stop_the_stream() {
+        let mut system = UsbSystem::new();
+        let (first, _) = mock_host(0);
+        let (second, _) = mock_host(0);
+        let first = system.add(first);
+        let second = system.add(second);
+
+        // 未初始化的控制器报告错误后不再等待
+        let events: Vec<_> = system.watch().collect().now_or_never().unwrap();
+        let ids: Vec<_> = events.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [first, second]);
+        assert!(
+            events
+                .iter()
+                .all(|(_, e)| matches!(e, Err(USBError::NotInitialized)))
+        );
+        assert!(
+            system
+                .next_hotplug_event()
+                .now_or_never()
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -7,13 +7,15 @@ use crate::transfer::Direction;
 mod class_code;
 mod lang_id;
 mod parser;
+mod validate;
 
 pub use class_code::*;
 pub use lang_id::*;
 pub use parser::decode_string_descriptor;
+pub use validate::{DescriptorIssue, validate_descriptors};
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct DescriptorType(pub u8);
 
 impl DescriptorType {
@@ -22,8 +24,8 @@ impl DescriptorType {
     pub const STRING: Self = Self(0x03);
     pub const INTERFACE: Self = Self(0x04);
     pub const ENDPOINT: Self = Self(0x05);
-    // Reserved 6
-    // Reserved 7
+    pub const DEVICE_QUALIFIER: Self = Self(0x06);
+    pub const OTHER_SPEED_CONFIGURATION: Self = Self(0x07);
     pub const INTERFACE_POWER: Self = Self(0x08);
     pub const OTG: Self = Self(0x09);
     pub const DEBUG: Self = Self(0x0A);
@@ -33,6 +35,8 @@ impl DescriptorType {
     pub const SUPERSPEED_USB_ENDPOINT_COMPANION: Self = Self(0x30);
     pub const SUPERSPEEDPLUS_ISOCHRONOUS_ENDPOINT_COMPANION: Self = Self(0x31);
     pub const HUB: Self = Self(0x29);
+    pub const CS_INTERFACE: Self = Self(0x24);
+    pub const CS_ENDPOINT: Self = Self(0x25);
 }
 
 impl From<u8> for DescriptorType {
@@ -47,6 +51,21 @@ impl From<DescriptorType> for u8 {
     }
 }
 
+/// 可通过标准 GET_DESCRIPTOR 请求读取的描述符
+pub trait Descriptor: Sized {
+    const DESCRIPTOR_TYPE: DescriptorType;
+
+    /// 首次读取的长度，需足以从中得到描述符的完整长度
+    ///
+    /// 定长描述符取完整长度，首次读取已包含整个描述符时不再发起第二次请求。
+    const HEADER_LEN: usize;
+
+    /// 从头部取得完整长度
+    fn total_length(header: &[u8]) -> usize;
+
+    fn parse(data: &[u8]) -> Option<Self>;
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct DeviceDescriptorBase {
@@ -60,6 +79,23 @@ pub struct DeviceDescriptorBase {
 }
 
 impl DeviceDescriptorBase {
+    /// 设备描述符的前 8 字节，足以得到 bMaxPacketSize0
+    pub const LEN: usize = 8;
+
+    /// 从设备描述符的前 8 字节解析，多字节字段按小端序读取
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let data: &[u8; Self::LEN] = data.get(..Self::LEN)?.try_into().ok()?;
+        Some(Self {
+            length: data[0],
+            descriptor_type: data[1],
+            usb_version: u16::from_le_bytes([data[2], data[3]]),
+            class: data[4],
+            subclass: data[5],
+            protocol: data[6],
+            max_packet_size_0: data[7],
+        })
+    }
+
     pub fn class(&self) -> Class {
         Class::from_class_and_subclass(self.class, self.subclass, self.protocol)
     }
@@ -93,6 +129,44 @@ impl DeviceDescriptor {
     }
 }
 
+/// Device Qualifier 描述符（USB 2.0 9.6.2）
+///
+/// 支持高速的设备用它描述工作在另一速度下时的设备信息，仅支持全速的设备会 STALL。
+#[derive(Debug, Clone)]
+pub struct DeviceQualifierDescriptor {
+    pub usb_version: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+    pub max_packet_size_0: u8,
+    pub num_configurations: u8,
+}
+
+impl DeviceQualifierDescriptor {
+    pub const LEN: usize = 10;
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < Self::LEN
+            || (data[0] as usize) < Self::LEN
+            || data[1] != DescriptorType::DEVICE_QUALIFIER.0
+        {
+            return None;
+        }
+        Some(Self {
+            usb_version: u16::from_le_bytes([data[2], data[3]]),
+            class: data[4],
+            subclass: data[5],
+            protocol: data[6],
+            max_packet_size_0: data[7],
+            num_configurations: data[8],
+        })
+    }
+
+    pub fn class(&self) -> Class {
+        Class::from_class_and_subclass(self.class, self.subclass, self.protocol)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct InterfaceDescriptor {
     pub interface_number: u8,
@@ -104,12 +178,56 @@ pub struct InterfaceDescriptor {
     pub string: Option<String>,
     pub num_endpoints: u8,
     pub endpoints: Vec<EndpointDescriptor>,
+    /// 接口描述符之后、第一个端点描述符之前的其他描述符，按原样拼接
+    pub extra: Vec<u8>,
 }
 
 impl InterfaceDescriptor {
     pub fn class(&self) -> Class {
         Class::from_class_and_subclass(self.class, self.subclass, self.protocol)
     }
+
+    /// 逐个遍历 [`extra`](Self::extra) 中的描述符
+    pub fn extra_descriptors(&self) -> impl Iterator<Item = &[u8]> {
+        split_descriptors(&self.extra)
+    }
+
+    /// 接口的类特定描述符（CS_INTERFACE），如 UVC/UAC/CDC 的功能描述符
+    pub fn class_descriptors(&self) -> impl Iterator<Item = ClassDescriptor<'_>> {
+        class_descriptors(&self.extra, DescriptorType::CS_INTERFACE)
+    }
+}
+
+/// 类特定描述符，`data` 从 bLength 开始，包含完整的描述符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassDescriptor<'a> {
+    pub descriptor_type: DescriptorType,
+    /// bDescriptorSubtype
+    pub subtype: u8,
+    pub data: &'a [u8],
+}
+
+/// 按 bLength 切分拼接的描述符，遇到长度非法的描述符时停止
+fn split_descriptors(mut buf: &[u8]) -> impl Iterator<Item = &[u8]> {
+    core::iter::from_fn(move || {
+        let len = *buf.first()? as usize;
+        if len < 2 || len > buf.len() {
+            return None;
+        }
+        let (desc, rest) = buf.split_at(len);
+        buf = rest;
+        Some(desc)
+    })
+}
+
+fn class_descriptors(buf: &[u8], ty: DescriptorType) -> impl Iterator<Item = ClassDescriptor<'_>> {
+    split_descriptors(buf)
+        .filter(move |d| d.len() >= 3 && d[1] == ty.0)
+        .map(|data| ClassDescriptor {
+            descriptor_type: DescriptorType(data[1]),
+            subtype: data[2],
+            data,
+        })
 }
 
 /// Endpoint type.
@@ -136,6 +254,10 @@ pub struct EndpointDescriptor {
     pub direction: Direction,
     pub packets_per_microframe: usize,
     pub interval: u8,
+    /// SuperSpeed 批量端点支持的流数量，0 表示不支持流
+    pub max_streams: u32,
+    /// 端点描述符之后、下一个端点或接口描述符之前的其他描述符，按原样拼接
+    pub extra: Vec<u8>,
 }
 
 impl EndpointDescriptor {
@@ -155,6 +277,16 @@ impl EndpointDescriptor {
                 }
             }
     }
+
+    /// 逐个遍历 [`extra`](Self::extra) 中的描述符，如 SuperSpeed 端点伴随描述符
+    pub fn extra_descriptors(&self) -> impl Iterator<Item = &[u8]> {
+        split_descriptors(&self.extra)
+    }
+
+    /// 端点的类特定描述符（CS_ENDPOINT）
+    pub fn class_descriptors(&self) -> impl Iterator<Item = ClassDescriptor<'_>> {
+        class_descriptors(&self.extra, DescriptorType::CS_ENDPOINT)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -189,6 +321,77 @@ impl ConfigurationDescriptor {
     pub const LEN: usize = 9;
 }
 
+/// Other Speed Configuration 描述符（USB 2.0 9.6.4）
+///
+/// 布局与配置描述符相同，只有 bDescriptorType 不同；`raw` 中保留原始类型。
+#[derive(Debug, Clone)]
+pub struct OtherSpeedConfigurationDescriptor(pub ConfigurationDescriptor);
+
+impl OtherSpeedConfigurationDescriptor {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 2 || data[1] != DescriptorType::OTHER_SPEED_CONFIGURATION.0 {
+            return None;
+        }
+        let mut buf = data.to_vec();
+        buf[1] = DescriptorType::CONFIGURATION.0;
+        let mut desc = ConfigurationDescriptor::parse(&buf)?;
+        desc.raw[1] = DescriptorType::OTHER_SPEED_CONFIGURATION.0;
+        Some(Self(desc))
+    }
+}
+
+impl Descriptor for DeviceDescriptor {
+    const DESCRIPTOR_TYPE: DescriptorType = DescriptorType::DEVICE;
+    const HEADER_LEN: usize = Self::LEN;
+
+    fn total_length(header: &[u8]) -> usize {
+        header[0] as usize
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        Self::parse(data)
+    }
+}
+
+impl Descriptor for ConfigurationDescriptor {
+    const DESCRIPTOR_TYPE: DescriptorType = DescriptorType::CONFIGURATION;
+    const HEADER_LEN: usize = Self::LEN;
+
+    fn total_length(header: &[u8]) -> usize {
+        u16::from_le_bytes([header[2], header[3]]) as usize
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        Self::parse(data)
+    }
+}
+
+impl Descriptor for DeviceQualifierDescriptor {
+    const DESCRIPTOR_TYPE: DescriptorType = DescriptorType::DEVICE_QUALIFIER;
+    const HEADER_LEN: usize = Self::LEN;
+
+    fn total_length(header: &[u8]) -> usize {
+        header[0] as usize
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        Self::parse(data)
+    }
+}
+
+impl Descriptor for OtherSpeedConfigurationDescriptor {
+    const DESCRIPTOR_TYPE: DescriptorType = DescriptorType::OTHER_SPEED_CONFIGURATION;
+    const HEADER_LEN: usize = ConfigurationDescriptor::LEN;
+
+    fn total_length(header: &[u8]) -> usize {
+        u16::from_le_bytes([header[2], header[3]]) as usize
+    }
+
+    fn parse(data: &[u8]) -> Option<Self> {
+        Self::parse(data)
+    }
+}
+
 impl From<parser::DeviceDescriptor> for DeviceDescriptor {
     fn from(desc: parser::DeviceDescriptor) -> Self {
         DeviceDescriptor {
@@ -217,6 +420,8 @@ impl From<parser::EndpointDescriptor<'_>> for EndpointDescriptor {
             transfer_type: desc.transfer_type(),
             packets_per_microframe: desc.packets_per_microframe() as usize,
             interval: desc.interval(),
+            max_streams: desc.max_streams(),
+            extra: desc.descriptors().as_bytes().to_vec(),
         }
     }
 }
@@ -248,6 +453,7 @@ impl From<parser::InterfaceDescriptor<'_>> for InterfaceDescriptor {
             num_endpoints: desc.num_endpoints(),
             endpoints: desc.endpoints().map(EndpointDescriptor::from).collect(),
             string: None,
+            extra: desc.extra().to_vec(),
         }
     }
 }
@@ -260,3 +466,99 @@ impl From<parser::InterfaceDescriptors<'_>> for InterfaceDescriptors {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test]
+    fn test_device_qualifier() {
+        let q = DeviceQualifierDescriptor::parse(&[10, 6, 0x00, 0x02, 0, 0, 0, 64, 1, 0]).unwrap();
+        assert_eq!(q.usb_version, 0x0200);
+        assert_eq!(q.max_packet_size_0, 64);
+        assert_eq!(q.num_configurations, 1);
+
+        assert!(
+            DeviceQualifierDescriptor::parse(&[18, 1, 0x00, 0x02, 0, 0, 0, 64, 1, 0]).is_none()
+        );
+        assert!(DeviceQualifierDescriptor::parse(&[10, 6, 0x00, 0x02]).is_none());
+    }
+
+    #[test]
+    fn test_other_speed_configuration() {
+        let raw = [
+            9, 7, 25, 0, 1, 1, 0, 0x80, 50, // configuration
+            9, 4, 0, 0, 1, 3, 1, 1, 0, // interface
+            7, 5, 0x81, 3, 8, 0, 10, // endpoint
+        ];
+        let desc = OtherSpeedConfigurationDescriptor::parse(&raw).unwrap().0;
+        assert_eq!(desc.configuration_value, 1);
+        assert_eq!(desc.interfaces.len(), 1);
+        assert_eq!(
+            desc.interfaces[0].alt_settings[0].endpoints[0].max_packet_size,
+            8
+        );
+        assert_eq!(desc.raw, raw);
+
+        let mut config = raw;
+        config[1] = DescriptorType::CONFIGURATION.0;
+        assert!(OtherSpeedConfigurationDescriptor::parse(&config).is_none());
+    }
+
+    #[test]
+    fn test_device_descriptor_base() {
+        let base = DeviceDescriptorBase::parse(&[18, 1, 0x10, 0x02, 0xef, 2, 1, 64]).unwrap();
+        assert_eq!(base.usb_version, 0x0210);
+        assert_eq!(base.max_packet_size_0, 64);
+        assert!(DeviceDescriptorBase::parse(&[18, 1, 0x10, 0x02]).is_none());
+    }
+
+    #[test]
+    fn test_class_specific_descriptors() {
+        // UVC 摄像头：VC 接口带三个类特定描述符与中断端点，VS 接口带两个帧描述符
+        let frame = |index: u8| {
+            let mut frame = vec![0u8; 30];
+            frame[..4].copy_from_slice(&[30, 0x24, 0x07, index]);
+            frame
+        };
+        let mut raw = vec![9, 0x02, 0, 0, 2, 1, 0, 0x80, 250];
+        raw.extend([9, 0x04, 0, 0, 1, 0x0e, 0x01, 0, 0]);
+        raw.extend([
+            13, 0x24, 0x01, 0x00, 0x01, 40, 0, 0x80, 0x8d, 0x5b, 0x00, 1, 1,
+        ]);
+        raw.extend([
+            18, 0x24, 0x02, 1, 0x01, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0,
+        ]);
+        raw.extend([9, 0x24, 0x03, 2, 0x01, 0x01, 0, 1, 0]);
+        raw.extend([7, 0x05, 0x87, 0x03, 16, 0, 8]);
+        raw.extend([5, 0x25, 0x03, 16, 0]);
+        raw.extend([9, 0x04, 1, 0, 0, 0x0e, 0x02, 0, 0]);
+        raw.extend([14, 0x24, 0x01, 1, 95, 0, 0x81, 0, 2, 0, 0, 0, 1, 0]);
+        raw.extend([11, 0x24, 0x06, 1, 2, 1, 1, 0, 0, 0, 0]);
+        raw.extend(frame(1));
+        raw.extend(frame(2));
+        raw.extend([6, 0x24, 0x0d, 1, 1, 4]);
+        raw.extend([9, 0x04, 1, 1, 1, 0x0e, 0x02, 0, 0]);
+        raw.extend([7, 0x05, 0x81, 0x05, 0x00, 0x02, 1]);
+        let total = raw.len() as u16;
+        raw[2..4].copy_from_slice(&total.to_le_bytes());
+        let config = ConfigurationDescriptor::parse(&raw).unwrap();
+        let vc = &config.interfaces[0].alt_settings[0];
+        let subtypes: Vec<u8> = vc.class_descriptors().map(|d| d.subtype).collect();
+        assert_eq!(subtypes, [0x01, 0x02, 0x03]);
+
+        let ep = &vc.endpoints[0];
+        let cs: Vec<_> = ep.class_descriptors().collect();
+        assert_eq!(cs.len(), 1);
+        assert_eq!(cs[0].descriptor_type, DescriptorType::CS_ENDPOINT);
+        assert_eq!(cs[0].data, [5, 0x25, 0x03, 16, 0]);
+
+        // VS 接口：输入头、格式、2 个帧、色彩匹配，其后的接口不计入
+        let vs = &config.interfaces[1].alt_settings[0];
+        assert_eq!(vs.class_descriptors().count(), 5);
+        assert_eq!(vs.extra_descriptors().count(), 5);
+        assert_eq!(config.interfaces[1].alt_settings[1].extra, []);
+    }
+}
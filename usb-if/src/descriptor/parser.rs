@@ -8,7 +8,10 @@ use core::{fmt::Debug, iter, num::NonZeroU8, ops::Deref};
 use alloc::{collections::btree_map::BTreeMap, string::String, vec::Vec};
 use log::warn;
 
-use crate::{descriptor::EndpointType, transfer::Direction};
+use crate::{
+    descriptor::{DescriptorType, EndpointType},
+    transfer::Direction,
+};
 
 pub(crate) const DESCRIPTOR_TYPE_DEVICE: u8 = 0x01;
 pub(crate) const DESCRIPTOR_LEN_DEVICE: u8 = 18;
@@ -551,6 +554,21 @@ impl<'a> InterfaceDescriptor<'a> {
         DescriptorIter(&self.0[self.0[0] as usize..])
     }
 
+    /// The bytes of the descriptors between this interface descriptor and its first
+    /// endpoint descriptor, usually class-specific descriptors.
+    pub fn extra(&self) -> &'a [u8] {
+        let mut iter = self.descriptors();
+        let all = iter.as_bytes();
+        let mut len = 0;
+        for desc in iter.by_ref() {
+            if desc.descriptor_type() == DESCRIPTOR_TYPE_ENDPOINT {
+                break;
+            }
+            len += desc.descriptor_len();
+        }
+        &all[..len]
+    }
+
     /// Get the endpoints of this interface.
     pub fn endpoints(&self) -> impl Iterator<Item = EndpointDescriptor<'a>> {
         self.descriptors()
@@ -655,6 +673,25 @@ impl<'a> EndpointDescriptor<'a> {
     pub fn packets_per_microframe(&self) -> u8 {
         ((self.max_packet_size_raw() >> 11) & 0b11) as u8 + 1
     }
+
+    /// For SuperSpeed bulk endpoints, get the number of streams supported (`2^MaxStreams`).
+    ///
+    /// Taken from `bmAttributes` of the SuperSpeed Endpoint Companion descriptor. Returns 0 if the
+    /// endpoint has no companion descriptor, is not a bulk endpoint, or does not support streams.
+    pub fn max_streams(&self) -> u32 {
+        if self.transfer_type() != EndpointType::Bulk {
+            return 0;
+        }
+        self.descriptors()
+            .find(|d| {
+                d.descriptor_type() == DescriptorType::SUPERSPEED_USB_ENDPOINT_COMPANION.0
+                    && d.descriptor_len() >= 4
+            })
+            .map_or(0, |d| match d[3] & 0x1f {
+                0 => 0,
+                n => 1 << n.min(16),
+            })
+    }
 }
 
 descriptor_fields! {
@@ -691,6 +728,7 @@ impl<'a> Debug for EndpointDescriptor<'a> {
             .field("max_packet_size", &self.max_packet_size())
             .field("packets_per_microframe", &self.packets_per_microframe())
             .field("interval", &self.interval())
+            .field("max_streams", &self.max_streams())
             .finish()
     }
 }
@@ -726,8 +764,10 @@ pub(crate) fn validate_string_descriptor(data: &[u8]) -> Result<(), &'static str
 pub fn decode_string_descriptor(data: &[u8]) -> Result<String, &'static str> {
     validate_string_descriptor(data)?;
 
+    // 只解码 bLength 范围内的 UTF-16LE 码元，缓冲区剩余部分可能是残留数据
+    let len = (data[0] as usize).max(2);
     Ok(char::decode_utf16(
-        data[2..]
+        data[2..len]
             .chunks_exact(2)
             .map(|c| u16::from_le_bytes(c.try_into().unwrap())),
     )
@@ -821,6 +861,34 @@ fn test_empty_config() {
     assert_eq!(c.interfaces().count(), 0);
 }
 
+#[test]
+#[rustfmt::skip]
+fn test_ss_bulk_streams() {
+    let c = ConfigurationDescriptor(&[
+        0x09, 0x02, 0x2c, 0x00, 0x01, 0x01, 0x00, 0x80, 0x00,
+        0x09, 0x04, 0x00, 0x01, 0x02, 0x08, 0x06, 0x62, 0x00,
+        // bulk IN 0x81, companion MaxStreams = 5
+        0x07, 0x05, 0x81, 0x02, 0x00, 0x04, 0x00,
+        0x06, 0x30, 0x0f, 0x05, 0x00, 0x00,
+        0x04, 0x24, 0x03, 0x00,
+        // bulk OUT 0x02, no streams
+        0x07, 0x05, 0x02, 0x02, 0x00, 0x04, 0x00,
+        0x06, 0x30, 0x0f, 0x00, 0x00, 0x00,
+    ]);
+    let alt = c.interfaces().next().unwrap().alt_settings().next().unwrap();
+    let mut endpoints = alt.endpoints();
+    assert_eq!(endpoints.next().unwrap().max_streams(), 32);
+    assert_eq!(endpoints.next().unwrap().max_streams(), 0);
+}
+
+#[test]
+fn test_decode_string_within_length() {
+    // "Ab" 加一个代理对（U+1F600），其后是不属于该描述符的残留数据
+    let data = [10, 3, b'A', 0, b'b', 0, 0x3d, 0xd8, 0x00, 0xde, b'x', 0];
+    assert_eq!(decode_string_descriptor(&data).unwrap(), "Ab\u{1f600}");
+    assert!(decode_string_descriptor(&[4, 2, b'A', 0]).is_err());
+}
+
 #[test]
 fn test_malformed() {
     let c = ConfigurationDescriptor(&[9, 2, 0, 0, 0, 1, 0, 0, 2, 5, 250, 0, 0, 0]);
@@ -0,0 +1,356 @@
+//! 描述符合规性检查
+//!
+//! 检查常见的规范违例：描述符长度、bNumInterfaces / bNumEndpoints 与实际数量、
+//! 同一备用设置中重复的端点地址、与速度不符的 wMaxPacketSize。结果只用于诊断，
+//! 不影响枚举，便于在排查驱动问题前先确认设备本身的描述符是否有问题。
+
+use core::fmt::Display;
+
+use alloc::vec::Vec;
+
+use super::{ConfigurationDescriptor, DescriptorType, DeviceDescriptor, EndpointType};
+use crate::Speed;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DescriptorIssue {
+    /// bLength 小于该类型的最小长度，或越过配置描述符末尾
+    BadLength {
+        configuration: u8,
+        offset: usize,
+        descriptor_type: u8,
+        length: u8,
+    },
+    /// bNumInterfaces 与实际接口数不符
+    InterfaceCount {
+        configuration: u8,
+        declared: u8,
+        actual: usize,
+    },
+    /// bNumEndpoints 与实际端点描述符数不符
+    EndpointCount {
+        configuration: u8,
+        interface: u8,
+        alternate: u8,
+        declared: u8,
+        actual: usize,
+    },
+    /// 同一备用设置中端点地址重复
+    DuplicateEndpoint {
+        configuration: u8,
+        interface: u8,
+        alternate: u8,
+        address: u8,
+    },
+    /// bMaxPacketSize0 不符合速度要求
+    MaxPacketSize0 { speed: Option<Speed>, size: u8 },
+    /// wMaxPacketSize 不符合端点类型与速度要求，`mult` 为每微帧事务数
+    MaxPacketSize {
+        configuration: u8,
+        interface: u8,
+        alternate: u8,
+        address: u8,
+        transfer_type: EndpointType,
+        speed: Option<Speed>,
+        size: u16,
+        mult: usize,
+    },
+}
+
+impl Display for DescriptorIssue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::BadLength {
+                configuration,
+                offset,
+                descriptor_type,
+                length,
+            } => write!(
+                f,
+                "config {configuration}: descriptor {descriptor_type:#04x} at offset {offset} has invalid bLength {length}"
+            ),
+            Self::InterfaceCount {
+                configuration,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "config {configuration}: bNumInterfaces is {declared}, found {actual} interfaces"
+            ),
+            Self::EndpointCount {
+                configuration,
+                interface,
+                alternate,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "config {configuration} interface {interface}.{alternate}: bNumEndpoints is {declared}, found {actual} endpoints"
+            ),
+            Self::DuplicateEndpoint {
+                configuration,
+                interface,
+                alternate,
+                address,
+            } => write!(
+                f,
+                "config {configuration} interface {interface}.{alternate}: duplicate endpoint {address:#04x}"
+            ),
+            Self::MaxPacketSize0 { speed, size } => {
+                write!(f, "bMaxPacketSize0 {size} is invalid at {speed:?} speed")
+            }
+            Self::MaxPacketSize {
+                configuration,
+                interface,
+                alternate,
+                address,
+                transfer_type,
+                speed,
+                size,
+                mult,
+            } => write!(
+                f,
+                "config {configuration} interface {interface}.{alternate}: {transfer_type:?} endpoint {address:#04x} wMaxPacketSize {size} x{mult} is invalid at {speed:?} speed"
+            ),
+        }
+    }
+}
+
+/// 检查设备描述符与全部配置描述符
+///
+/// `speed` 为设备实际连接速度；未知时传 `None`，bcdUSB 为 3.x 的设备按 SuperSpeed 检查，
+/// 其余只检查与具体 USB 2.0 速度无关的范围。
+pub fn validate_descriptors(
+    device: &DeviceDescriptor,
+    configurations: &[ConfigurationDescriptor],
+    speed: Option<Speed>,
+) -> Vec<DescriptorIssue> {
+    let speed = speed.or((device.usb_version >= 0x0300).then_some(Speed::SuperSpeed));
+    let mut issues = Vec::new();
+
+    if !max_packet_size_0_valid(speed, device.max_packet_size_0) {
+        issues.push(DescriptorIssue::MaxPacketSize0 {
+            speed,
+            size: device.max_packet_size_0,
+        });
+    }
+
+    for config in configurations {
+        validate_configuration(config, speed, &mut issues);
+    }
+    issues
+}
+
+fn validate_configuration(
+    config: &ConfigurationDescriptor,
+    speed: Option<Speed>,
+    issues: &mut Vec<DescriptorIssue>,
+) {
+    let configuration = config.configuration_value;
+
+    let mut offset = 0;
+    while offset + 2 <= config.raw.len() {
+        let length = config.raw[offset];
+        let descriptor_type = config.raw[offset + 1];
+        if length < min_length(descriptor_type) || offset + length as usize > config.raw.len() {
+            issues.push(DescriptorIssue::BadLength {
+                configuration,
+                offset,
+                descriptor_type,
+                length,
+            });
+            // 长度不可信，后续描述符无法定位
+            break;
+        }
+        offset += length as usize;
+    }
+
+    if config.num_interfaces as usize != config.interfaces.len() {
+        issues.push(DescriptorIssue::InterfaceCount {
+            configuration,
+            declared: config.num_interfaces,
+            actual: config.interfaces.len(),
+        });
+    }
+
+    for iface in config.interfaces.iter().flat_map(|i| &i.alt_settings) {
+        let interface = iface.interface_number;
+        let alternate = iface.alternate_setting;
+        if iface.num_endpoints as usize != iface.endpoints.len() {
+            issues.push(DescriptorIssue::EndpointCount {
+                configuration,
+                interface,
+                alternate,
+                declared: iface.num_endpoints,
+                actual: iface.endpoints.len(),
+            });
+        }
+
+        for (i, ep) in iface.endpoints.iter().enumerate() {
+            if iface.endpoints[..i].iter().any(|e| e.address == ep.address) {
+                issues.push(DescriptorIssue::DuplicateEndpoint {
+                    configuration,
+                    interface,
+                    alternate,
+                    address: ep.address,
+                });
+            }
+
+            if !max_packet_size_valid(
+                speed,
+                ep.transfer_type,
+                ep.max_packet_size,
+                ep.packets_per_microframe,
+            ) {
+                issues.push(DescriptorIssue::MaxPacketSize {
+                    configuration,
+                    interface,
+                    alternate,
+                    address: ep.address,
+                    transfer_type: ep.transfer_type,
+                    speed,
+                    size: ep.max_packet_size,
+                    mult: ep.packets_per_microframe,
+                });
+            }
+        }
+    }
+}
+
+/// 标准描述符的最小 bLength，类特定描述符只要求能容纳头部
+const MIN_LENGTHS: [(DescriptorType, u8); 5] = [
+    (DescriptorType::CONFIGURATION, 9),
+    (DescriptorType::INTERFACE, 9),
+    (DescriptorType::INTERFACE_ASSOCIATION, 8),
+    (DescriptorType::ENDPOINT, 7),
+    (DescriptorType::SUPERSPEED_USB_ENDPOINT_COMPANION, 6),
+];
+
+fn min_length(descriptor_type: u8) -> u8 {
+    MIN_LENGTHS
+        .iter()
+        .find(|(ty, _)| ty.0 == descriptor_type)
+        .map_or(2, |(_, len)| *len)
+}
+
+fn max_packet_size_0_valid(speed: Option<Speed>, size: u8) -> bool {
+    match speed {
+        Some(Speed::Low) => size == 8,
+        Some(Speed::High) => size == 64,
+        // SuperSpeed 下为 2 的指数，固定 512 字节
+        Some(Speed::SuperSpeed | Speed::SuperSpeedPlus) => size == 9,
+        Some(Speed::Wireless) => true,
+        Some(Speed::Full) | None => matches!(size, 8 | 16 | 32 | 64),
+    }
+}
+
+/// USB 2.0 5.5.3 ~ 5.8.3 与 USB 3.2 9.6.6 中各端点类型的最大包长
+fn max_packet_size_valid(speed: Option<Speed>, ty: EndpointType, size: u16, mult: usize) -> bool {
+    // 11..12 位为 3 是保留编码；只有高速周期端点允许每微帧多个事务
+    let periodic = matches!(ty, EndpointType::Interrupt | EndpointType::Isochronous);
+    let mult_ok = match speed {
+        Some(Speed::High) | None => mult <= 3 && (mult == 1 || periodic),
+        _ => mult == 1,
+    };
+    if !mult_ok {
+        return false;
+    }
+
+    match (speed, ty) {
+        (Some(Speed::Wireless), _) => true,
+        (Some(Speed::Low), EndpointType::Control) => size == 8,
+        (Some(Speed::Low), EndpointType::Interrupt) => size <= 8,
+        (Some(Speed::Low), _) => false,
+        (Some(Speed::Full), EndpointType::Control | EndpointType::Bulk) => {
+            matches!(size, 8 | 16 | 32 | 64)
+        }
+        (Some(Speed::Full), EndpointType::Interrupt) => size <= 64,
+        (Some(Speed::Full), EndpointType::Isochronous) => size <= 1023,
+        (Some(Speed::High), EndpointType::Control) => size == 64,
+        (Some(Speed::High), EndpointType::Bulk) => size == 512,
+        (Some(Speed::SuperSpeed | Speed::SuperSpeedPlus), EndpointType::Control) => size == 512,
+        (Some(Speed::SuperSpeed | Speed::SuperSpeedPlus), EndpointType::Bulk) => size == 1024,
+        (None, EndpointType::Control) => matches!(size, 8 | 16 | 32 | 64),
+        (None, EndpointType::Bulk) => matches!(size, 8 | 16 | 32 | 64 | 512),
+        // 高速、SuperSpeed 以及未知速度下的周期端点
+        (_, EndpointType::Interrupt | EndpointType::Isochronous) => size <= 1024,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 厂商类 USB 2.0 设备，只有一个配置
+    fn device() -> DeviceDescriptor {
+        DeviceDescriptor::parse(&[
+            18, 0x01, 0x00, 0x02, 0, 0, 0, 64, 0x34, 0x12, 0x78, 0x56, 0x00, 0x01, 0, 0, 0, 1,
+        ])
+        .unwrap()
+    }
+
+    fn config(raw: &[u8]) -> ConfigurationDescriptor {
+        ConfigurationDescriptor::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn reports_violations() {
+        // bNumEndpoints 声明为 2，实际有两个 0x81 批量端点和一个中断端点
+        let raw = [
+            9, 0x02, 39, 0, 1, 1, 0, 0x80, 250, //
+            9, 0x04, 0, 0, 2, 0xff, 0, 0, 0, //
+            7, 0x05, 0x81, 0x02, 0x00, 0x02, 0, //
+            7, 0x05, 0x81, 0x02, 0x00, 0x02, 0, //
+            7, 0x05, 0x02, 0x03, 64, 0, 1,
+        ];
+        let device = device();
+        let configs = [config(&raw)];
+
+        let issues = validate_descriptors(&device, &configs, Some(Speed::Full));
+        assert!(issues.contains(&DescriptorIssue::EndpointCount {
+            configuration: 1,
+            interface: 0,
+            alternate: 0,
+            declared: 2,
+            actual: 3,
+        }));
+        assert!(issues.contains(&DescriptorIssue::DuplicateEndpoint {
+            configuration: 1,
+            interface: 0,
+            alternate: 0,
+            address: 0x81,
+        }));
+        // 全速批量端点最大 64 字节
+        let bad_size = issues
+            .iter()
+            .filter(|i| matches!(i, DescriptorIssue::MaxPacketSize { size: 512, .. }))
+            .count();
+        assert_eq!(bad_size, 2);
+
+        // 高速下批量 512、中断 64 都合法，只剩数量与地址问题
+        let issues = validate_descriptors(&device, &configs, Some(Speed::High));
+        assert_eq!(issues.len(), 2);
+    }
+
+    #[test]
+    fn reports_bad_length() {
+        // 大容量存储接口，批量端点 0x81 / 0x02
+        let mut config = config(&[
+            9, 0x02, 32, 0, 1, 1, 0, 0x80, 250, //
+            9, 0x04, 0, 0, 2, 0x08, 0x06, 0x50, 0, //
+            7, 0x05, 0x81, 0x02, 0x00, 0x02, 0, //
+            7, 0x05, 0x02, 0x02, 0x00, 0x02, 0,
+        ]);
+        // 第一个端点描述符 bLength 改为 3
+        config.raw[18] = 3;
+        let issues = validate_descriptors(&device(), &[config], None);
+        assert!(matches!(
+            issues[..],
+            [DescriptorIssue::BadLength {
+                offset: 18,
+                descriptor_type: 0x05,
+                length: 3,
+                ..
+            }]
+        ));
+    }
+}
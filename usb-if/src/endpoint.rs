@@ -311,6 +311,8 @@ pub enum TransferStatus {
     Stalled,
     Cancelled,
     Error,
+    /// 等时包错过了服务周期，未在总线上传输
+    Missed,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -320,6 +322,16 @@ pub struct IsoPacketResult {
     pub status: TransferStatus,
 }
 
+impl IsoPacketResult {
+    pub fn is_ok(&self) -> bool {
+        self.status == TransferStatus::Completed
+    }
+}
+
+/// 传输完成结果
+///
+/// 等时传输中单个包失败不会使整个请求返回错误：请求整体成功，
+/// 各包的结果记录在 `iso_packets` 中，由调用方决定如何处理失败的包。
 #[derive(Clone, Debug)]
 pub struct TransferCompletion {
     pub request_id: RequestId,
@@ -327,3 +339,79 @@ pub struct TransferCompletion {
     pub actual_length: usize,
     pub iso_packets: Vec<IsoPacketResult>,
 }
+
+impl TransferCompletion {
+    /// 等时传输中部分包失败、部分包成功
+    pub fn is_partial(&self) -> bool {
+        self.iso_packets.iter().any(|p| p.is_ok()) && self.iso_packets.iter().any(|p| !p.is_ok())
+    }
+
+    /// 失败的等时包数量
+    pub fn iso_failed_count(&self) -> usize {
+        self.iso_packets.iter().filter(|p| !p.is_ok()).count()
+    }
+
+    /// 按包切分等时 IN 传输的缓冲区
+    ///
+    /// 第 i 个包的数据位于之前各包请求长度之和的偏移处，返回的切片长度为实际长度。
+    pub fn iso_packet_data<'a>(
+        &'a self,
+        buffer: &'a [u8],
+    ) -> impl Iterator<Item = (&'a IsoPacketResult, &'a [u8])> + 'a {
+        self.iso_packets.iter().scan(0usize, move |offset, packet| {
+            let start = (*offset).min(buffer.len());
+            let end = (start + packet.actual_length.min(packet.requested_length)).min(buffer.len());
+            *offset += packet.requested_length;
+            Some((packet, &buffer[start..end]))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    fn packet(
+        requested_length: usize,
+        actual_length: usize,
+        status: TransferStatus,
+    ) -> IsoPacketResult {
+        IsoPacketResult {
+            requested_length,
+            actual_length,
+            status,
+        }
+    }
+
+    #[test]
+    fn partial_iso_completion() {
+        let completion = TransferCompletion {
+            request_id: RequestId::new(1),
+            status: TransferStatus::Completed,
+            actual_length: 5,
+            iso_packets: vec![
+                packet(4, 3, TransferStatus::Completed),
+                packet(4, 0, TransferStatus::Missed),
+                packet(4, 2, TransferStatus::Completed),
+            ],
+        };
+        assert!(completion.is_partial());
+        assert_eq!(completion.iso_failed_count(), 1);
+
+        let buffer = [1, 2, 3, 0, 9, 9, 9, 9, 5, 6, 0, 0];
+        let data: Vec<_> = completion
+            .iso_packet_data(&buffer)
+            .map(|(p, d)| (p.status, d))
+            .collect();
+        assert_eq!(
+            data,
+            vec![
+                (TransferStatus::Completed, &[1, 2, 3][..]),
+                (TransferStatus::Missed, &[][..]),
+                (TransferStatus::Completed, &[5, 6][..]),
+            ]
+        );
+    }
+}
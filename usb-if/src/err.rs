@@ -2,8 +2,14 @@ use alloc::{boxed::Box, string::String};
 
 #[derive(thiserror::Error, Debug)]
 pub enum TransferError {
+    /// 端点 STALL；控制传输中为 SETUP 或数据阶段被 STALL
     #[error("Stall")]
     Stall,
+    /// 控制传输在状态阶段被 STALL：设备理解了请求但拒绝执行
+    ///
+    /// 这是协议 STALL，主机侧的端点 0 已恢复，下一个控制传输可以直接发起。
+    #[error("Stall in status stage")]
+    StatusStall,
     #[error("Queue full")]
     QueueFull,
     #[error("Invalid endpoint")]
@@ -16,10 +22,20 @@ pub enum TransferError {
     Timeout,
     #[error("Cancelled")]
     Cancelled,
+    /// 主机没有足够的周期带宽容纳端点，可换用更小的 alternate setting 后重试
+    #[error("No bandwidth")]
+    NoBandwidth,
     #[error("Other error: {0}")]
     Other(#[from] anyhow::Error),
 }
 
+impl TransferError {
+    /// 是否为任一阶段的 STALL
+    pub fn is_stall(&self) -> bool {
+        matches!(self, Self::Stall | Self::StatusStall)
+    }
+}
+
 impl From<Box<dyn core::error::Error>> for TransferError {
     fn from(err: Box<dyn core::error::Error>) -> Self {
         TransferError::Other(anyhow::anyhow!("{}", err))
@@ -47,6 +47,22 @@ pub enum PortFeature {
     CSuspend = 18,     // 清除挂起变化
     COverCurrent = 19, // 清除过流变化
     CReset = 20,       // 清除复位完成
+    Test = 21,
+    Indicator = 22,
+}
+
+/// PORT_TEST 的测试选择子，置于 SetPortFeature 请求 wIndex 的高字节
+///
+/// 参照 USB 2.0 规范表 9-7 与 11.24.2.13。端口进入测试模式后只能通过
+/// 复位 Hub 或重新上电退出。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TestSelector {
+    TestJ = 1,
+    TestK = 2,
+    TestSe0Nak = 3,
+    TestPacket = 4,
+    TestForceEnable = 5,
 }
 
 const USB_MAXCHILDREN: usize = 8;
@@ -1,4 +1,4 @@
-use crate::transfer::{Recipient, Request, RequestType};
+use crate::transfer::{BmRequestType, Direction, Recipient, Request, RequestType};
 
 pub mod hub;
 
@@ -10,3 +10,66 @@ pub struct ControlSetup {
     pub value: u16,
     pub index: u16,
 }
+
+impl ControlSetup {
+    /// 类请求，`request` 为类规范定义的 bRequest
+    pub fn class(recipient: Recipient, request: u8, value: u16, index: u16) -> Self {
+        Self {
+            request_type: RequestType::Class,
+            recipient,
+            request: Request::Class(request),
+            value,
+            index,
+        }
+    }
+
+    /// 厂商请求，`request` 原样作为 bRequest 发送
+    pub fn vendor(recipient: Recipient, request: u8, value: u16, index: u16) -> Self {
+        Self {
+            request_type: RequestType::Vendor,
+            recipient,
+            request: Request::Vendor(request),
+            value,
+            index,
+        }
+    }
+
+    /// 8 字节 SETUP 包，`length` 为数据阶段长度（wLength）
+    pub fn to_bytes(&self, direction: Direction, length: u16) -> [u8; 8] {
+        let request_type: u8 =
+            BmRequestType::new(direction, self.request_type, self.recipient).into();
+        let [value_lo, value_hi] = self.value.to_le_bytes();
+        let [index_lo, index_hi] = self.index.to_le_bytes();
+        let [len_lo, len_hi] = length.to_le_bytes();
+        [
+            request_type,
+            self.request.into(),
+            value_lo,
+            value_hi,
+            index_lo,
+            index_hi,
+            len_lo,
+            len_hi,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vendor_setup_bytes() {
+        let setup = ControlSetup::vendor(Recipient::Device, 0x06, 0x1234, 0x0002);
+        assert_eq!(
+            setup.to_bytes(Direction::In, 64),
+            [0xc0, 0x06, 0x34, 0x12, 0x02, 0x00, 64, 0]
+        );
+
+        let setup = ControlSetup::class(Recipient::Interface, 0x0a, 0x7d00, 1);
+        assert_eq!(
+            setup.to_bytes(Direction::Out, 0),
+            [0x21, 0x0a, 0x00, 0x7d, 0x01, 0x00, 0, 0]
+        );
+    }
+}
@@ -1,5 +1,3 @@
-use num_enum::{FromPrimitive, IntoPrimitive};
-
 #[repr(u8)]
 /// The direction of the data transfer.
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
@@ -55,7 +53,7 @@ impl From<BmRequestType> for u8 {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum RequestType {
     Standard = 0,
@@ -73,7 +71,12 @@ pub enum Recipient {
     Other = 3,
 }
 
-#[derive(Debug, Clone, FromPrimitive, IntoPrimitive, Copy)]
+/// 控制请求的 bRequest
+///
+/// 具名变体为 USB 规范定义的标准请求。类与厂商请求的编号由各自的规范或厂商决定，
+/// 可能与标准请求重叠，使用 [`Request::Class`] / [`Request::Vendor`] 原样传递，
+/// 并与对应的 [`RequestType`] 搭配。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Request {
     GetStatus = 0,
@@ -102,6 +105,122 @@ pub enum Request {
     SetFwStatus = 27,
     SetSel = 48,
     SetIsochDelay = 49,
-    #[num_enum(catch_all)]
+    /// 类请求，如 HID SET_IDLE、UVC GET_CUR
+    Class(u8),
+    /// 厂商请求
+    Vendor(u8),
+    /// 未识别的标准请求编号
     Other(u8),
 }
+
+impl From<u8> for Request {
+    /// 按标准请求解码，未定义的编号为 [`Request::Other`]
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::GetStatus,
+            1 => Self::ClearFeature,
+            3 => Self::SetFeature,
+            5 => Self::SetAddress,
+            6 => Self::GetDescriptor,
+            7 => Self::SetDescriptor,
+            8 => Self::GetConfiguration,
+            9 => Self::SetConfiguration,
+            10 => Self::GetInterface,
+            11 => Self::SetInterface,
+            12 => Self::SynchFrame,
+            13 => Self::SetEncryption,
+            14 => Self::GetEncryption,
+            15 => Self::SetHandshake,
+            16 => Self::GetHandshake,
+            17 => Self::SetConnection,
+            18 => Self::SetSecurityData,
+            19 => Self::GetSecurityData,
+            20 => Self::SetWusbData,
+            21 => Self::LoopbackDataWrite,
+            22 => Self::LoopbackDataRead,
+            23 => Self::SetInterfaceDs,
+            26 => Self::GetFwStatus,
+            27 => Self::SetFwStatus,
+            48 => Self::SetSel,
+            49 => Self::SetIsochDelay,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl From<Request> for u8 {
+    fn from(value: Request) -> Self {
+        match value {
+            Request::GetStatus => 0,
+            Request::ClearFeature => 1,
+            Request::SetFeature => 3,
+            Request::SetAddress => 5,
+            Request::GetDescriptor => 6,
+            Request::SetDescriptor => 7,
+            Request::GetConfiguration => 8,
+            Request::SetConfiguration => 9,
+            Request::GetInterface => 10,
+            Request::SetInterface => 11,
+            Request::SynchFrame => 12,
+            Request::SetEncryption => 13,
+            Request::GetEncryption => 14,
+            Request::SetHandshake => 15,
+            Request::GetHandshake => 16,
+            Request::SetConnection => 17,
+            Request::SetSecurityData => 18,
+            Request::GetSecurityData => 19,
+            Request::SetWusbData => 20,
+            Request::LoopbackDataWrite => 21,
+            Request::LoopbackDataRead => 22,
+            Request::SetInterfaceDs => 23,
+            Request::GetFwStatus => 26,
+            Request::SetFwStatus => 27,
+            Request::SetSel => 48,
+            Request::SetIsochDelay => 49,
+            Request::Class(raw) | Request::Vendor(raw) | Request::Other(raw) => raw,
+        }
+    }
+}
+
+impl Request {
+    /// 与请求搭配的请求类型，标准请求与未识别的编号均为 [`RequestType::Standard`]
+    pub fn request_type(&self) -> RequestType {
+        match self {
+            Self::Class(_) => RequestType::Class,
+            Self::Vendor(_) => RequestType::Vendor,
+            _ => RequestType::Standard,
+        }
+    }
+}
+
+/// 标准特性选择子，用于 SET_FEATURE / CLEAR_FEATURE
+///
+/// 参照 USB 2.0 规范表 9-6。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum StandardFeature {
+    /// 接收者为端点
+    EndpointHalt = 0,
+    /// 接收者为设备
+    DeviceRemoteWakeup = 1,
+    /// 接收者为设备
+    TestMode = 2,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_byte_round_trip() {
+        for raw in 0..=u8::MAX {
+            assert_eq!(u8::from(Request::from(raw)), raw);
+            assert_eq!(u8::from(Request::Class(raw)), raw);
+            assert_eq!(u8::from(Request::Vendor(raw)), raw);
+        }
+        assert_eq!(u8::from(Request::SetIsochDelay), 49);
+        assert_eq!(Request::from(0x0a), Request::GetInterface);
+        assert_eq!(Request::from(0x81), Request::Other(0x81));
+        assert_eq!(Request::Vendor(6).request_type(), RequestType::Vendor);
+    }
+}
@@ -0,0 +1,82 @@
+//! 断开/拔出模拟测试辅助
+//!
+//! 提供 DMA 占用统计与带事件泵的轮询执行，配合 crab-usb 的 `fault-injection`
+//! 特性在任意时刻模拟设备被拔出，检查 Future 不会悬挂、内存不会延迟释放。
+
+use core::{
+    future::Future,
+    pin::pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use bare_test::time::since_boot;
+use crab_usb::EventHandler;
+
+static COHERENT: AtomicUsize = AtomicUsize::new(0);
+static MAPPED: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn track_coherent(alloc: bool) {
+    track(&COHERENT, alloc);
+}
+
+pub(crate) fn track_mapped(map: bool) {
+    track(&MAPPED, map);
+}
+
+fn track(counter: &AtomicUsize, inc: bool) {
+    if inc {
+        counter.fetch_add(1, Ordering::Relaxed);
+    } else {
+        counter.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// [`KernelImpl`](crate::KernelImpl) 当前未释放的 DMA 资源数
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DmaUsage {
+    /// 一致性内存分配（环、上下文等）
+    pub coherent: usize,
+    /// 流式映射（传输缓冲区）
+    pub mapped: usize,
+}
+
+pub fn dma_usage() -> DmaUsage {
+    DmaUsage {
+        coherent: COHERENT.load(Ordering::Relaxed),
+        mapped: MAPPED.load(Ordering::Relaxed),
+    }
+}
+
+/// 模拟拔出的时间点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalPoint {
+    /// 枚举过程中
+    Enumeration,
+    /// 控制传输已提交、尚未完成
+    ControlTransfer,
+    /// 等时流传输中
+    IsoStream,
+}
+
+/// 在不依赖中断的情况下执行 `fut`：每次轮询前处理一次事件环
+///
+/// 超过 `timeout` 仍未完成时返回 `None`，表示 Future 悬挂。
+pub fn poll_with_events<F: Future>(
+    handler: &EventHandler,
+    fut: F,
+    timeout: Duration,
+) -> Option<F::Output> {
+    let mut fut = pin!(fut);
+    let mut cx = Context::from_waker(Waker::noop());
+    let deadline = since_boot() + timeout;
+
+    while since_boot() < deadline {
+        handler.handle_event();
+        if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return Some(out);
+        }
+    }
+    None
+}
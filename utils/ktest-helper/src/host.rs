@@ -0,0 +1,214 @@
+//! QEMU 与开发板上的主机控制器查找
+
+use alloc::vec::Vec;
+
+use bare_test::{
+    GetIrqConfig,
+    fdt_parser::{PciSpace, Status},
+    globals::{PlatformInfoKind, global_val},
+    irq::IrqInfo,
+    mem::iomap,
+    platform::fdt::GetPciIrqConfig,
+    println,
+};
+use crab_usb::USBHost;
+#[cfg(feature = "fault-injection")]
+use crab_usb::err::USBError;
+use log::*;
+use pcie::*;
+
+use crate::KernelImpl;
+
+/// 设备树中找到的 xHCI 控制器
+pub struct XhciInfo {
+    pub usb: USBHost,
+    pub irq: Option<IrqInfo>,
+}
+
+fn get_usb_host_pcie() -> Option<XhciInfo> {
+    let PlatformInfoKind::DeviceTree(fdt) = &global_val().platform_info;
+
+    let fdt = fdt.get();
+
+    let pcie = fdt
+        .find_compatible(&["pci-host-ecam-generic", "brcm,bcm2711-pcie"])
+        .next()?
+        .into_pci()
+        .unwrap();
+
+    let mut pcie_regs = alloc::vec![];
+
+    println!("pcie: {}", pcie.node.name);
+
+    for reg in pcie.node.reg().unwrap() {
+        println!(
+            "pcie reg: {:#x}, bus: {:#x}",
+            reg.address, reg.child_bus_address
+        );
+        let size = reg.size.unwrap_or_default().align_up(0x1000);
+
+        pcie_regs.push(iomap((reg.address as usize).into(), size));
+    }
+
+    let mut bar_alloc = SimpleBarAllocator::default();
+
+    for range in pcie.ranges().unwrap() {
+        info!("pcie range: {range:?}");
+
+        match range.space {
+            PciSpace::Memory32 => bar_alloc.set_mem32(range.cpu_address as _, range.size as _),
+            PciSpace::Memory64 => bar_alloc.set_mem64(range.cpu_address, range.size),
+            _ => {}
+        }
+    }
+
+    let base_vaddr = pcie_regs[0];
+
+    info!("Init PCIE @{base_vaddr:?}");
+
+    let mut root = RootComplexGeneric::new(base_vaddr);
+
+    // for elem in root.enumerate_keep_bar(None) {
+    for elem in root.enumerate(None, Some(bar_alloc)) {
+        debug!("PCI {elem}");
+
+        if let Header::Endpoint(mut ep) = elem.header {
+            ep.update_command(elem.root, |mut cmd| {
+                cmd.remove(CommandRegister::INTERRUPT_DISABLE);
+                cmd | CommandRegister::IO_ENABLE
+                    | CommandRegister::MEMORY_ENABLE
+                    | CommandRegister::BUS_MASTER_ENABLE
+            });
+
+            for cap in &mut ep.capabilities {
+                match cap {
+                    PciCapability::Msi(msi_capability) => {
+                        msi_capability.set_enabled(false, &mut *elem.root);
+                    }
+                    PciCapability::MsiX(msix_capability) => {
+                        msix_capability.set_enabled(false, &mut *elem.root);
+                    }
+                    _ => {}
+                }
+            }
+
+            println!("irq_pin {:?}, {:?}", ep.interrupt_pin, ep.interrupt_line);
+
+            if matches!(ep.device_type(), DeviceType::UsbController) {
+                let bar_addr;
+                let mut bar_size;
+                match ep.bar {
+                    pcie::BarVec::Memory32(bar_vec_t) => {
+                        let bar0 = bar_vec_t[0].as_ref().unwrap();
+                        bar_addr = bar0.address as usize;
+                        bar_size = bar0.size as usize;
+                    }
+                    pcie::BarVec::Memory64(bar_vec_t) => {
+                        let bar0 = bar_vec_t[0].as_ref().unwrap();
+                        bar_addr = bar0.address as usize;
+                        bar_size = bar0.size as usize;
+                    }
+                    pcie::BarVec::Io(_bar_vec_t) => todo!(),
+                };
+
+                println!("bar0: {:#x}", bar_addr);
+                println!("bar0 size: {:#x}", bar_size);
+                bar_size = bar_size.align_up(0x1000);
+                println!("bar0 size algin: {:#x}", bar_size);
+
+                let addr = iomap(bar_addr.into(), bar_size);
+                trace!("pin {:?}", ep.interrupt_pin);
+
+                let irq = pcie.child_irq_info(
+                    ep.address.bus(),
+                    ep.address.device(),
+                    ep.address.function(),
+                    ep.interrupt_pin,
+                );
+
+                println!("irq: {irq:?}");
+
+                return Some(XhciInfo {
+                    usb: USBHost::new_xhci(addr, &KernelImpl).unwrap(),
+                    irq,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// 优先使用 PCIe 上的 xHCI，否则使用设备树中 host 模式的 xHCI/DWC3 节点
+pub fn get_usb_host() -> XhciInfo {
+    if let Some(info) = get_usb_host_pcie() {
+        return info;
+    }
+
+    let PlatformInfoKind::DeviceTree(fdt) = &global_val().platform_info;
+
+    let fdt = fdt.get();
+    for node in fdt.all_nodes() {
+        if matches!(node.status(), Some(Status::Disabled)) {
+            continue;
+        }
+
+        if node
+            .compatibles()
+            .any(|c| c.contains("xhci") | c.contains("snps,dwc3"))
+        {
+            // 只选择明确为 host 模式的控制器，避免误用 OTG 端口
+            if let Some(prop) = node.find_property("dr_mode") {
+                let mode = prop.str();
+                if mode != "host" {
+                    debug!("skip {} because dr_mode={}", node.name(), mode);
+                    continue;
+                }
+            }
+
+            println!("usb node: {}", node.name);
+            let regs = node.reg().unwrap().collect::<Vec<_>>();
+            println!("usb regs: {:?}", regs);
+
+            let addr = iomap(
+                (regs[0].address as usize).into(),
+                regs[0].size.unwrap_or(0x1000),
+            );
+
+            let irq = node.irq_info();
+
+            return XhciInfo {
+                usb: USBHost::new_xhci(addr, &KernelImpl).unwrap(),
+                irq,
+            };
+        }
+    }
+
+    panic!("no xhci found");
+}
+
+/// 禁用全部根端口，模拟所有设备被拔出
+#[cfg(feature = "fault-injection")]
+pub fn disable_all_ports(host: &mut USBHost) -> Result<(), USBError> {
+    for port in 1..=u8::MAX {
+        match host.inject_port_disable(port) {
+            Ok(()) => {}
+            Err(USBError::InvalidParameter) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+trait Align {
+    fn align_up(&self, align: usize) -> usize;
+}
+
+impl Align for usize {
+    fn align_up(&self, align: usize) -> usize {
+        if (*self).is_multiple_of(align) {
+            *self
+        } else {
+            *self + align - *self % align
+        }
+    }
+}
@@ -7,10 +7,16 @@ use core::{alloc::Layout, num::NonZeroUsize, ptr::NonNull, time::Duration};
 
 use bare_test::{
     mem::{PhysAddr, VirtAddr, alloc_with_mask, page_size},
-    time::spin_delay,
+    time::{since_boot, spin_delay},
 };
 use crab_usb::*;
 
+mod fault;
+mod host;
+
+pub use fault::*;
+pub use host::*;
+
 pub struct KernelImpl;
 
 impl DmaOp for KernelImpl {
@@ -59,14 +65,17 @@ impl DmaOp for KernelImpl {
                 self.flush_invalidate(new_virt, size);
             }
 
+            fault::track_mapped(true);
             Ok(unsafe { DmaMapHandle::new(addr, new_phys.into(), layout, Some(new_virt)) })
         } else {
             // ✅ 原始地址可以使用，直接返回
+            fault::track_mapped(true);
             Ok(unsafe { DmaMapHandle::new(addr, orig_phys.into(), layout, None) })
         }
     }
 
     unsafe fn unmap_single(&self, handle: DmaMapHandle) {
+        fault::track_mapped(false);
         if let Some(virt) = handle.alloc_virt() {
             // 重新分配过，需要释放新分配的内存
             unsafe {
@@ -86,10 +95,12 @@ impl DmaOp for KernelImpl {
         let virt = VirtAddr::from(ptr);
         let phys = PhysAddr::from(virt).raw() as u64;
 
+        fault::track_coherent(true);
         Some(unsafe { DmaHandle::new(ptr, phys.into(), layout) })
     }
 
     unsafe fn dealloc_coherent(&self, handle: DmaHandle) {
+        fault::track_coherent(false);
         unsafe { alloc::alloc::dealloc(handle.as_ptr().as_ptr(), handle.layout()) }
     }
 
@@ -179,4 +190,8 @@ impl KernelOp for KernelImpl {
     fn delay(&self, duration: Duration) {
         spin_delay(duration);
     }
+
+    fn now(&self) -> Duration {
+        since_boot()
+    }
 }
@@ -9,6 +9,9 @@ use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 
+mod sink;
+pub use sink::{DiskSink, StreamSink};
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct VideoInfo {
     pub width: usize,
@@ -23,6 +26,7 @@ pub enum Pixel {
     Nv12,
     Rgb24,
     Rgb32,
+    Gray,
     Mjpeg,
     H264,
 }
@@ -84,6 +88,7 @@ impl Parser {
                     UncompressedFormat::Nv12 => Pixel::Nv12,
                     UncompressedFormat::Rgb24 => Pixel::Rgb24,
                     UncompressedFormat::Rgb32 => Pixel::Rgb32,
+                    UncompressedFormat::Y800 => Pixel::Gray,
                 };
                 (*width as usize, *height as usize, pixel)
             }
@@ -163,6 +168,7 @@ impl Parser {
                     UncompressedFormat::Nv12 => "nv12",
                     UncompressedFormat::Rgb24 => "rgb24",
                     UncompressedFormat::Rgb32 => "rgba",
+                    UncompressedFormat::Y800 => "gray",
                 };
                 (*width, *height, ffmpeg_format)
             }
@@ -324,7 +330,8 @@ impl Parser {
                             if buffer.len() >= 2 && buffer[0] == 0xFF && buffer[1] == 0xD8 {
                                 // 这是JPEG数据，我们需要解码它
                                 // 创建临时文件来解码JPEG
-                                let temp_jpeg_path = format!("/tmp/temp_frame_{}.jpg", i);
+                                let temp_jpeg_path =
+                                    std::env::temp_dir().join(format!("temp_frame_{}.jpg", i));
                                 std::fs::write(&temp_jpeg_path, &buffer)?;
 
                                 // 使用ffmpeg解码JPEG
@@ -193,6 +193,7 @@ fn parse_video_format_from_log(line: &str) -> Result<VideoFormat, Box<dyn std::e
             height: height as u16,
             frame_rate,
             format_type: VideoFormatType::Mjpeg,
+            h264: None,
         })
     } else if line.contains("Uncompressed") {
         let width = extract_field_value(line, "width")?;
@@ -208,6 +209,8 @@ fn parse_video_format_from_log(line: &str) -> Result<VideoFormat, Box<dyn std::e
             UncompressedFormat::Rgb24
         } else if line.contains("Rgb32") {
             UncompressedFormat::Rgb32
+        } else if line.contains("Y800") {
+            UncompressedFormat::Y800
         } else {
             UncompressedFormat::Yuy2 // 默认
         };
@@ -217,6 +220,7 @@ fn parse_video_format_from_log(line: &str) -> Result<VideoFormat, Box<dyn std::e
             height: height as u16,
             frame_rate,
             format_type: VideoFormatType::Uncompressed(format_type),
+            h264: None,
         })
     } else if line.contains("H264") {
         let width = extract_field_value(line, "width")?;
@@ -228,6 +232,7 @@ fn parse_video_format_from_log(line: &str) -> Result<VideoFormat, Box<dyn std::e
             height: height as u16,
             frame_rate,
             format_type: VideoFormatType::H264,
+            h264: None,
         })
     } else {
         Err("Unsupported video format in log".into())
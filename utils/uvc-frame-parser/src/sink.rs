@@ -0,0 +1,94 @@
+//! 主机侧的 [`FrameSink`] 实现
+
+use std::sync::Arc;
+
+use crab_uvc::{
+    frame::FrameEvent,
+    sink::{FrameSink, SinkError},
+};
+use futures::future::BoxFuture;
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::Parser;
+
+/// 将每帧保存为 `frame_XXXXXX.raw`，供 [`Parser`] 后续合成视频或图片
+pub struct DiskSink {
+    parser: Arc<Parser>,
+    saved: Vec<u32>,
+}
+
+impl DiskSink {
+    pub fn new(parser: Arc<Parser>) -> Self {
+        Self {
+            parser,
+            saved: Vec::new(),
+        }
+    }
+
+    /// 已保存的帧序号
+    pub fn saved_frames(&self) -> &[u32] {
+        &self.saved
+    }
+}
+
+impl FrameSink for DiskSink {
+    fn write<'a>(&'a mut self, frame: FrameEvent) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move {
+            let frame_number = self.saved.len() as u32;
+            self.parser
+                .save_frame_to_file(&frame, frame_number)
+                .await
+                .map_err(|e| anyhow::anyhow!("save frame {frame_number}: {e}"))?;
+            self.saved.push(frame_number);
+            Ok(())
+        })
+    }
+}
+
+/// 以 `长度(u32 LE) + 帧数据` 的格式逐帧写入字节流
+///
+/// 写入在对端读取过慢时阻塞，由此向采集循环施加背压。
+pub struct StreamSink<W> {
+    writer: W,
+}
+
+impl StreamSink<TcpStream> {
+    pub async fn connect(addr: &str) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream))
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> StreamSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    async fn send(&mut self, frame: &FrameEvent) -> std::io::Result<()> {
+        self.writer
+            .write_all(&(frame.data.len() as u32).to_le_bytes())
+            .await?;
+        self.writer.write_all(&frame.data).await
+    }
+}
+
+fn io_err(e: std::io::Error) -> SinkError {
+    match e.kind() {
+        std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::ConnectionReset => SinkError::Closed,
+        _ => SinkError::Other(anyhow::anyhow!("{e}")),
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> FrameSink for StreamSink<W> {
+    fn write<'a>(&'a mut self, frame: FrameEvent) -> BoxFuture<'a, Result<(), SinkError>> {
+        Box::pin(async move { self.send(&frame).await.map_err(io_err) })
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), SinkError>> {
+        Box::pin(async move { self.writer.flush().await.map_err(io_err) })
+    }
+}